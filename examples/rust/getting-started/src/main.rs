@@ -22,7 +22,7 @@ async fn upgrade_device_certs_to_creds(
 ) -> Result<Device> {
     Device {
         cert: device_cert,
-        key: device_key,
+        key: device_key.into(),
         ..Default::default()
     }
     .upgrade(scheduler, signer)
@@ -67,7 +67,7 @@ async fn register_node(seed: Vec<u8>, developer_cert_path: String, developer_key
     let developer_key = std::fs::read(developer_key_path).unwrap_or_default();
     let developer_creds = Nobody {
         cert: developer_cert,
-        key: developer_key,
+        key: developer_key.into(),
         ..Nobody::default()
     };
     // ---8<--- [end: dev_creds]
@@ -153,7 +153,7 @@ async fn recover_node(
     let network = gl_client::bitcoin::Network::Bitcoin;
     let creds = Nobody {
         cert: nobody_cert,
-        key: nobody_key,
+        key: nobody_key.into(),
         ..Nobody::default()
     };
 
@@ -0,0 +1,321 @@
+//! `glcli`: poke at Greenlight nodes from the command line.
+//!
+//! Most commands need the device credentials returned on
+//! registration, by default read from `./creds`. Commands that need
+//! signatures, such as `invoice` and `pay`, also need the seed, so
+//! they can run a signer for the duration of the command.
+//!
+//! The settings are read from the `--config` file, see
+//! [`gl_client::config`], the `GL_*` environment variables, and the
+//! command line, the latter taking precedence.
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use clap::{Parser, Subcommand};
+use gl_client::bitcoin::Network;
+use gl_client::config::Config;
+use gl_client::credentials::{Device, Nobody};
+use gl_client::node::ClnClient;
+use gl_client::pb::cln;
+use gl_client::runes;
+use gl_client::scheduler::Scheduler;
+use gl_client::signer::{CancellationToken, Signer};
+use serde::Serialize;
+use std::path::PathBuf;
+use tokio_util::sync::DropGuard;
+
+#[derive(Parser, Debug)]
+#[command(name = "glcli", version, about)]
+struct Cli {
+    /// The configuration file.
+    #[arg(long, env = "GL_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// The network the node runs on.
+    #[arg(long)]
+    network: Option<Network>,
+
+    /// The device credentials file.
+    #[arg(long)]
+    creds: Option<PathBuf>,
+
+    /// The file holding the 32 byte seed of the node.
+    #[arg(long)]
+    seed: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Register a new node for the seed, and store its credentials.
+    Register {
+        #[arg(long)]
+        invite_code: Option<String>,
+    },
+    /// Recover the credentials of the node of the seed.
+    Recover,
+    /// Start the node if necessary, and print its address.
+    Schedule,
+    Getinfo,
+    /// Create an invoice, `amount` is in millisatoshi or `any`.
+    Invoice {
+        amount: String,
+        label: String,
+        #[arg(default_value = "")]
+        description: String,
+    },
+    Pay {
+        bolt11: String,
+        /// The amount for invoices without one.
+        #[arg(long)]
+        amount_msat: Option<u64>,
+    },
+    Listfunds,
+    /// Carve a restricted rune from the device's rune. Each
+    /// restriction is a `|`-separated list of alternatives, e.g.,
+    /// `method^list|method^get`. Escape `|`, `&` and `\` in values
+    /// with a backslash.
+    Rune {
+        #[arg(required = true)]
+        restrictions: Vec<String>,
+    },
+    /// Manage the credentials file.
+    #[command(subcommand)]
+    Creds(CredsCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum CredsCommand {
+    /// Print the credentials base64-encoded, to copy them to another
+    /// device.
+    Export,
+    /// Store base64-encoded credentials, e.g., from `creds export`.
+    Import { encoded: String },
+}
+
+impl Cli {
+    /// The configuration, with the command line overrides applied.
+    fn config(&self) -> Result<Config> {
+        let mut config = Config::load(self.config.as_deref())?;
+        if let Some(network) = self.network {
+            config.network = network;
+        }
+        if let Some(creds) = &self.creds {
+            config.creds = creds.clone();
+        }
+        if let Some(seed) = &self.seed {
+            config.seed = Some(seed.clone());
+        }
+        Ok(config)
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let cli = Cli::parse();
+    if let Err(e) = run(cli).await {
+        eprintln!("error: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let config = cli.config()?;
+    match cli.command {
+        Command::Register { invite_code } => {
+            let signer = signer(&config, Nobody::new())?;
+            let scheduler = Scheduler::with_config(&config, Nobody::new()).await?;
+            let res = scheduler.register(&signer, invite_code).await?;
+            store_creds(&config, &res.creds)?;
+            println!("Registered node {}", hex::encode(signer.node_id()));
+        }
+        Command::Recover => {
+            let signer = signer(&config, Nobody::new())?;
+            let scheduler = Scheduler::with_config(&config, Nobody::new()).await?;
+            let res = scheduler.recover(&signer).await?;
+            store_creds(&config, &res.creds)?;
+            println!("Recovered node {}", hex::encode(signer.node_id()));
+        }
+        Command::Schedule => {
+            let res = scheduler(&config).await?.schedule().await?;
+            println!("{}", res.grpc_uri);
+        }
+        Command::Getinfo => {
+            let mut node = node(&config).await?;
+            print(
+                node.getinfo(cln::GetinfoRequest::default())
+                    .await?
+                    .get_ref(),
+            )?;
+        }
+        Command::Invoice {
+            amount,
+            label,
+            description,
+        } => {
+            let value = match amount.as_str() {
+                "any" => cln::amount_or_any::Value::Any(true),
+                a => cln::amount_or_any::Value::Amount(cln::Amount {
+                    msat: a.parse().context("amount must be in msat or `any`")?,
+                }),
+            };
+            let _signer = run_signer(&config)?;
+            let mut node = node(&config).await?;
+            let res = node
+                .invoice(cln::InvoiceRequest {
+                    amount_msat: Some(cln::AmountOrAny { value: Some(value) }),
+                    label,
+                    description,
+                    ..Default::default()
+                })
+                .await?;
+            print(res.get_ref())?;
+        }
+        Command::Pay {
+            bolt11,
+            amount_msat,
+        } => {
+            let _signer = run_signer(&config)?;
+            let mut node = node(&config).await?;
+            let res = node
+                .pay(cln::PayRequest {
+                    bolt11,
+                    amount_msat: amount_msat.map(|msat| cln::Amount { msat }),
+                    ..Default::default()
+                })
+                .await?;
+            print(res.get_ref())?;
+        }
+        Command::Listfunds => {
+            let mut node = node(&config).await?;
+            print(
+                node.list_funds(cln::ListfundsRequest::default())
+                    .await?
+                    .get_ref(),
+            )?;
+        }
+        Command::Rune { restrictions } => {
+            let creds = device(&config)?;
+            let signer = signer(&config, creds.clone())?;
+            let restrictions = restrictions
+                .iter()
+                .map(|r| runes::split_alternatives(r))
+                .collect();
+            println!("{}", signer.create_rune(Some(&creds.rune), restrictions)?);
+        }
+        Command::Creds(CredsCommand::Export) => {
+            let creds = device(&config)?;
+            println!(
+                "{}",
+                base64::engine::general_purpose::STANDARD.encode(creds.to_bytes())
+            );
+        }
+        Command::Creds(CredsCommand::Import { encoded }) => {
+            let data = base64::engine::general_purpose::STANDARD
+                .decode(encoded.trim())
+                .context("credentials must be base64-encoded")?;
+            let creds = Device::from_bytes(&data);
+            if creds.rune.is_empty() {
+                return Err(anyhow!("not a complete set of device credentials"));
+            }
+            store_creds(&config, &data)?;
+        }
+    }
+    Ok(())
+}
+
+fn print<T: Serialize>(v: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(v)?);
+    Ok(())
+}
+
+fn store_creds(config: &Config, data: &[u8]) -> Result<()> {
+    let path = &config.creds;
+    std::fs::write(path, data)
+        .with_context(|| format!("writing credentials to {}", path.display()))?;
+    eprintln!("Stored credentials in {}", path.display());
+    Ok(())
+}
+
+fn device(config: &Config) -> Result<Device> {
+    let path = &config.creds;
+    let data = std::fs::read(path).with_context(|| {
+        format!(
+            "reading credentials from {}, register or recover first",
+            path.display()
+        )
+    })?;
+    Ok(Device::from_bytes(data))
+}
+
+fn signer<T>(config: &Config, creds: T) -> Result<Signer>
+where
+    T: gl_client::credentials::TlsConfigProvider + 'static,
+{
+    let path = config
+        .seed
+        .as_deref()
+        .ok_or_else(|| anyhow!("this command needs the seed, pass it with --seed"))?;
+    let seed =
+        std::fs::read(path).with_context(|| format!("reading seed from {}", path.display()))?;
+    if seed.len() != 32 {
+        return Err(anyhow!("the seed must be 32 bytes, got {}", seed.len()));
+    }
+    let signer = Signer::new(seed, config.network, creds)?;
+    if let Some(policy) = config.signer_policy()? {
+        signer.update_policy(policy)?;
+    }
+    Ok(signer)
+}
+
+async fn scheduler(config: &Config) -> Result<Scheduler<Device>> {
+    Scheduler::with_config(config, device(config)?).await
+}
+
+async fn node(config: &Config) -> Result<ClnClient> {
+    scheduler(config).await?.node().await
+}
+
+/// Run a signer in the background, until the returned guard is
+/// dropped.
+fn run_signer(config: &Config) -> Result<DropGuard> {
+    let signer = signer(config, device(config)?)?;
+    let shutdown = CancellationToken::new();
+    let handle = signer.start(shutdown.clone());
+    tokio::spawn(async move {
+        if let Err(e) = handle.await_stopped().await {
+            log::error!("Signer exited: {}", e);
+        }
+    });
+    Ok(shutdown.drop_guard())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let cli = Cli::try_parse_from([
+            "glcli",
+            "--network",
+            "regtest",
+            "--seed",
+            "/tmp/seed",
+            "invoice",
+            "1000",
+            "label",
+        ])
+        .unwrap();
+        let config = cli.config().unwrap();
+        assert_eq!(config.network, Network::Regtest);
+        assert_eq!(config.seed, Some(PathBuf::from("/tmp/seed")));
+        assert!(matches!(
+            cli.command,
+            Command::Invoice { ref amount, .. } if amount == "1000"
+        ));
+        assert!(Cli::try_parse_from(["glcli", "rune"]).is_err());
+    }
+}
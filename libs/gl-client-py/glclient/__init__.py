@@ -4,16 +4,28 @@ from pyln import grpc as clnpb  # type: ignore
 from pyln.grpc import Amount, AmountOrAll, AmountOrAny  # noqa: F401
 from . import glclient as native
 from .glclient import backup_decrypt_with_seed  # noqa: F401
+from .glclient import (  # noqa: F401
+    GLError,
+    GLConnectionError,
+    GLRateLimited,
+    GLAuthError,
+    GLSignerRejected,
+    GLRpcError,
+    GLVersionNotOffered,
+)
 from .tls import TlsConfig
 from google.protobuf.message import Message as PbMessage
 from binascii import hexlify, unhexlify
-from typing import Optional, List, Iterable, Any, Type, TypeVar
+from typing import Optional, List, Iterable, Any, Type, TypeVar, Callable, Union, Dict, Generic
+import asyncio
+import json
 import logging
 from glclient.lsps import LspClient
 from glclient.glclient import Credentials
 
 
 backup_decrypt_with_seed = native.backup_decrypt_with_seed
+configure_runtime = native.configure_runtime
 
 
 # Keep in sync with the libhsmd version, this is tested in unit tests.
@@ -25,6 +37,71 @@ def _convert(cls: Type[E], res: Iterable[Any]) -> E:
     return cls.FromString(bytes(res))
 
 
+T = TypeVar('T')
+
+
+class Stream(Generic[T]):
+    """A stream of messages, iterated with `for` or `async for`.
+
+    Messages are read in the background, a few ahead of the consumer,
+    after which the server waits until they are consumed. Closing the
+    stream, leaving a `with` block, cancelling the task iterating it,
+    or dropping it cancels the stream.
+    """
+
+    # How long a blocking iteration waits before checking for signals,
+    # e.g., a `KeyboardInterrupt`.
+    _POLL_SECS = 0.5
+
+    def __init__(self, inner: "native.Stream", convert: Callable[[bytes], T]):
+        self.inner = inner
+        self.convert = convert
+
+    def next(self, timeout: Optional[float] = None) -> Optional[T]:
+        """The next message, or `None` once the stream ended. Raises
+        `TimeoutError` if none arrived within `timeout` seconds.
+        """
+        n = self.inner.next(timeout)
+        return None if n is None else self.convert(bytes(n))
+
+    def close(self) -> None:
+        self.inner.close()
+
+    def __iter__(self) -> "Stream[T]":
+        return self
+
+    def __next__(self) -> T:
+        while True:
+            try:
+                n = self.next(self._POLL_SECS)
+            except TimeoutError:
+                continue
+            if n is None:
+                raise StopIteration
+            return n
+
+    def __aiter__(self) -> "Stream[T]":
+        return self
+
+    async def __anext__(self) -> T:
+        loop = asyncio.get_running_loop()
+        try:
+            n = await loop.run_in_executor(None, self.next)
+        except asyncio.CancelledError:
+            # Unblocks the executor thread waiting for the next message.
+            self.close()
+            raise
+        if n is None:
+            raise StopAsyncIteration
+        return n
+
+    def __enter__(self) -> "Stream[T]":
+        return self
+
+    def __exit__(self, *exc: Any) -> None:
+        self.close()
+
+
 class Signer(object):
     def __init__(self, secret: bytes, network: str, creds: Credentials):
         self.inner = native.Signer(secret, network, creds)
@@ -40,6 +117,16 @@ class Signer(object):
     def run_in_foreground(self) -> None:
         return self.inner.run_in_foreground()
 
+    def enable_telemetry(self, endpoint: str, interval: Optional[float] = None) -> None:
+        """Report the reasons the signer rejected requests to `endpoint`.
+
+        The signer counts its rejections, and `POST`s the counts every
+        `interval` seconds, or every hour. Reports do not identify the
+        node, and only contain the names of the failed checks. Call
+        before starting the signer.
+        """
+        self.inner.enable_telemetry(endpoint, interval)
+
     def node_id(self) -> bytes:
         return bytes(self.inner.node_id())
 
@@ -49,6 +136,9 @@ class Signer(object):
     def sign_challenge(self, message: bytes) -> bytes:
         return bytes(self.inner.sign_challenge(message))
 
+    def sign_ownership_proof(self, challenge: bytes) -> str:
+        return self.inner.sign_ownership_proof(challenge)
+
     def shutdown(self) -> None:
         if self.handle is None:
             raise ValueError("Attempted to shut down a signer that is not running")
@@ -61,6 +151,45 @@ class Signer(object):
     def is_running(self) -> bool:
         return self.handle is not None
 
+    def status(self) -> str:
+        """The state of the connection to the node, one of
+        `disconnected`, `scheduler_connected`, `node_starting`,
+        `node_ready` or `signer_attached`.
+        """
+        return self.inner.status()
+
+    def on_status(self, callback: Callable[[str], None]) -> None:
+        """Call `callback` with the connection status whenever it
+        changes. The callback runs on a background thread.
+        """
+        self.inner.on_status(callback)
+
+    def state(self) -> str:
+        """The state of the signer loop, one of `stopped`, `running`,
+        `reconnecting` or `failed`. A signer that failed, e.g.,
+        because it could not connect for too long, does not restart
+        on its own.
+        """
+        return self.inner.state()
+
+    def last_error(self) -> Optional[str]:
+        """The error the signer failed with, or the one it is
+        reconnecting after.
+        """
+        return self.inner.last_error()
+
+    def on_state_change(self, callback: Callable[[str, Optional[str]], None]) -> None:
+        """Call `callback` with the state and last error whenever the
+        state changes. The callback runs on a background thread.
+        """
+        self.inner.on_state_change(callback)
+
+    def events(self) -> Stream[Dict[str, Any]]:
+        """The events the signer publishes from now on, e.g.,
+        `{"SignerOffline": {"offline_secs": 300}}`.
+        """
+        return Stream(self.inner.events(), json.loads)
+
 
 class Scheduler(object):
 
@@ -69,6 +198,14 @@ class Scheduler(object):
         self.creds = creds if creds is not None else native.Credentials()
         self.inner = native.Scheduler(network, self.creds)
 
+    def __getstate__(self) -> Dict[str, Any]:
+        # The connection can not be pickled, it is opened again from the
+        # network and credentials when unpickling.
+        return {"network": self.network, "creds": self.creds}
+
+    def __setstate__(self, state: Dict[str, Any]) -> None:
+        self.__init__(state["network"], state["creds"])  # type: ignore[misc]
+
     def schedule(self) -> schedpb.NodeInfoResponse:
         res = self.inner.schedule()
         return schedpb.NodeInfoResponse.FromString(bytes(res))
@@ -125,6 +262,152 @@ class Scheduler(object):
         res = self.inner.rotate_outgoing_webhook_secret(webhook_id)
         return schedpb.WebhookSecretResponse.FromString(bytes(res))
 
+    def add_lifecycle_webhook(
+            self,
+            uri: str,
+            events: Optional[List[str]] = None,
+    ) -> schedpb.AddOutgoingWebhookResponse:
+        """Have the scheduler call `uri` when the node is `scheduled`,
+        `stopped`, or runs without a signer (`signer_missing`).
+
+        Subscribes to all events if `events` is `None`. The response
+        holds the id of the webhook, and the secret its calls are
+        signed with.
+        """
+        res = self.inner.add_lifecycle_webhook(uri, events)
+        return schedpb.AddOutgoingWebhookResponse.FromString(bytes(res))
+
+    def list_lifecycle_webhooks(self) -> schedpb.ListLifecycleWebhooksResponse:
+        res = self.inner.list_lifecycle_webhooks()
+        return schedpb.ListLifecycleWebhooksResponse.FromString(bytes(res))
+
+    def delete_lifecycle_webhook(self, id: int) -> None:
+        self.inner.delete_lifecycle_webhook(id)
+
+    def register_push_token(
+            self,
+            platform: str,
+            token: str,
+    ) -> schedpb.RegisterPushTokenResponse:
+        """Register an `apns` or `fcm` device token, or a `webhook` URI.
+
+        The response holds the id of the registration, and the secret
+        used to sign the notifications, see `decode_push_notification`.
+        """
+        res = self.inner.register_push_token(platform, token)
+        return schedpb.RegisterPushTokenResponse.FromString(bytes(res))
+
+    def unregister_push_token(self, id: int) -> None:
+        self.inner.unregister_push_token(id)
+
+    def get_node_metadata(self) -> schedpb.NodeMetadata:
+        """The versions and platform tags reported for the node, and
+        its current `deployment`, i.e., the `cln_version`, the
+        `last_start_time` in seconds since the epoch, and whether it
+        is `running`.
+        """
+        res = self.inner.get_node_metadata()
+        return schedpb.NodeMetadata.FromString(bytes(res))
+
+    def update_node_metadata(
+            self,
+            signer_version: Optional[str] = None,
+            client_version: Optional[str] = None,
+            platform_tags: Optional[List[str]] = None,
+    ) -> schedpb.NodeMetadata:
+        """Update the metadata of the node, fields left as `None` are
+        unchanged. Returns the updated metadata.
+        """
+        res = self.inner.update_node_metadata(
+            signer_version, client_version, platform_tags
+        )
+        return schedpb.NodeMetadata.FromString(bytes(res))
+
+    def list_cln_versions(self) -> schedpb.ListClnVersionsResponse:
+        """The CLN `versions` offered for the node, and its version
+        `policy`, i.e., the `current` version it runs the next time it
+        starts, the version it is `pinned` to, empty if none, and
+        whether it opted in to upgrades.
+        """
+        res = self.inner.list_cln_versions()
+        return schedpb.ListClnVersionsResponse.FromString(bytes(res))
+
+    def pin_cln_version(self, version: Optional[str]) -> schedpb.ClnVersionPolicy:
+        """Pin the node to `version`, or unpin it with `None`.
+
+        Raises `GLVersionNotOffered` if the version is not offered.
+        Returns the version policy of the node.
+        """
+        res = self.inner.pin_cln_version(version)
+        return schedpb.ClnVersionPolicy.FromString(bytes(res))
+
+    def set_cln_upgrade_opt_in(self, opt_in: bool) -> schedpb.ClnVersionPolicy:
+        """Opt the node in to upgrades before they become the default,
+        or out of them again. Returns the version policy of the node.
+        """
+        res = self.inner.set_cln_upgrade_opt_in(opt_in)
+        return schedpb.ClnVersionPolicy.FromString(bytes(res))
+
+
+def decode_push_notification(
+        payload: bytes,
+        signature: Optional[str] = None,
+        secret: Optional[str] = None,
+) -> Dict[str, Any]:
+    """Decode the payload of a push notification for an offline node.
+
+    Pass the `signature` delivered with the notification and the
+    `secret` returned by `Scheduler.register_push_token` to reject
+    notifications that were not sent by Greenlight.
+    """
+    return json.loads(native.decode_push_notification(payload, signature, secret))
+
+
+def qr_code(data: str, format: str = "svg", size: Optional[int] = None) -> bytes:
+    """Render an invoice, offer or LNURL as a QR code.
+
+    Returns an `svg` image at least `size` pixels wide, 256 by
+    default, or a `png` image with each module `size` pixels wide, 8
+    by default. Invoices and offers are encoded in uppercase, which
+    makes for smaller codes.
+    """
+    return bytes(native.qr_code(data, format, size))
+
+
+def carve_rune(rune: str, rules: List[str], commando: bool = False) -> str:
+    """Carve a narrower rune from `rune` to hand to a sub-component.
+
+    Each of the `rules` has to hold, and is one of `readonly`, `pay`,
+    `weekdays` and `hours(<start>-<end>)` (UTC), or alternatives of
+    them such as `pay|readonly`. With `commando` the restrictions use
+    core-lightning's lowercase method names.
+    """
+    return native.carve_rune(rune, rules, commando)
+
+
+def decode_rune(rune: str) -> Dict[str, Any]:
+    """The `authcode`, `unique_id` and `restrictions` of `rune`."""
+    return json.loads(native.decode_rune(rune))
+
+
+class Rates(object):
+    """Fiat exchange rates for bitcoin.
+
+    Queries the `providers` in order, any of `mempool`, `coinbase`
+    and `kraken`, and caches the prices they return.
+
+    """
+    def __init__(self, providers: Optional[List[str]] = None):
+        self.inner = native.Rates(providers)
+
+    def rate(self, currency: str, at: int) -> float:
+        """The price of one bitcoin in `currency` at the UNIX time `at`."""
+        return self.inner.rate(currency, at)
+
+    def convert(self, msat: int, currency: str, at_time: Optional[int] = None) -> float:
+        """Convert `msat` to `currency`, at `at_time` or now."""
+        return self.inner.convert(msat, currency, at_time)
+
 
 class Node(object):
 
@@ -430,6 +713,38 @@ class Node(object):
             bytes(self.inner.call(uri, bytes(req)))
         )
 
+    def sign_invoice(self, invstring: str) -> clnpb.SigninvoiceResponse:
+        uri = "/cln.Node/SignInvoice"
+        res = clnpb.SigninvoiceResponse
+        req = clnpb.SigninvoiceRequest(
+            invstring=invstring,
+        ).SerializeToString()
+
+        return res.FromString(
+            bytes(self.inner.call(uri, bytes(req)))
+        )
+
+    def create_invoice(
+            self,
+            invstring: str,
+            label: str,
+            preimage: bytes,
+    ) -> clnpb.CreateinvoiceResponse:
+        if len(preimage) != 32:
+            raise ValueError("Preimage must be 32 bytes in length")
+
+        uri = "/cln.Node/CreateInvoice"
+        res = clnpb.CreateinvoiceResponse
+        req = clnpb.CreateinvoiceRequest(
+            invstring=invstring,
+            label=label,
+            preimage=preimage,
+        ).SerializeToString()
+
+        return res.FromString(
+            bytes(self.inner.call(uri, bytes(req)))
+        )
+
     def pay(
             self,
             bolt11: str,
@@ -474,31 +789,19 @@ class Node(object):
             bytes(self.inner.call(uri, bytes(req)))
         )
 
-    def stream_log(self):
+    def stream_log(self) -> Stream[nodepb.LogEntry]:
         """Stream logs as they get generated on the server side.
         """
         stream = self.inner.stream_log(b"")
-        while True:
-            n = stream.next()
-            if n is None:
-                break
-            yield nodepb.LogEntry.FromString(bytes(n))
+        return Stream(stream, nodepb.LogEntry.FromString)
 
-    def stream_incoming(self):
+    def stream_incoming(self) -> Stream[nodepb.IncomingPayment]:
         stream = self.inner.stream_incoming(b"")
-        while True:
-            n = stream.next()
-            if n is None:
-                break
-            yield nodepb.IncomingPayment.FromString(bytes(n))
+        return Stream(stream, nodepb.IncomingPayment.FromString)
 
-    def stream_custommsg(self):
+    def stream_custommsg(self) -> Stream[nodepb.Custommsg]:
         stream = self.inner.stream_custommsg(b"")
-        while True:
-            n = stream.next()
-            if n is None:
-                break
-            yield nodepb.Custommsg.FromString(bytes(n))
+        return Stream(stream, nodepb.Custommsg.FromString)
 
     def send_custommsg(
             self,
@@ -651,6 +954,28 @@ class Node(object):
             bytes(self.inner.call(uri, bytes(req)))
         )
 
+    def export_ledger(
+            self,
+            format: str = "csv",
+            start: Optional[int] = None,
+            end: Optional[int] = None,
+            currency: Optional[str] = None,
+            rate: Optional[Union[Rates, Callable[[str, int], float]]] = None,
+    ) -> str:
+        """Export the node's ledger as `csv` or `json`.
+
+        Only entries with a timestamp between `start` and `end` are
+        included. If `currency` and `rate` are given, entries include
+        their fiat value and cost basis. `rate` is either a `Rates`
+        instance, or a callable that is called with the currency and
+        the timestamp of each entry, and returns the price of one
+        bitcoin at that time.
+
+        """
+        if isinstance(rate, Rates):
+            rate = rate.inner
+        return self.inner.export_ledger(format, start, end, currency, rate)
+
 
 def normalize_node_id(node_id, string=False):
     if len(node_id) == 66:
@@ -5,88 +5,336 @@ These refer to the API exposed by the Rust library, not the main
 level API that shuffles bytes back and forth. The `glclient` python
 package adds a pythonic facade on top of this to improve usability.
 
+This file is generated by `stubgen.py`, do not edit it by hand.
 """
 
-from typing import Optional, List
-import glclient.glclient as native;
+from typing import Any, Callable, List, Optional, Tuple, Union
 
 
-class TlsConfig:
-    def __init__(self) -> None: ...
-    def with_ca_certificate(self, ca: bytes) -> "TlsConfig": ...
-    def identity(self, cert_pem: bytes, key_pem: bytes) -> "TlsConfig": ...
-    def identity_from_path(self, path : str) -> "TlsConfig": ...
+class GLError(ValueError):
+    """An error of a Greenlight call."""
+
+
+class GLConnectionError(GLError):
+    """The node could not be reached, the call may be retried."""
+
+
+class GLRateLimited(GLError):
+    """The service rate limited the call, retry after `retry_after` seconds."""
+    retry_after: Optional[float]
+
+
+class GLAuthError(GLError):
+    """The credentials were not accepted for the call."""
+
+
+class GLSignerRejected(GLError):
+    """The signer refused to sign for the call."""
+
+
+class GLRpcError(GLError):
+    """lightningd returned an error, with its `method`, `code` and `data`."""
+    method: str
+    code: Optional[int]
+    data: Optional[Any]
+
+
+class GLVersionNotOffered(GLError):
+    """The scheduler does not offer the requested CLN version."""
+
 
 class Credentials:
     def __init__(self) -> None: ...
     @staticmethod
     def nobody_with(cert: bytes, key: bytes) -> Credentials: ...
     @staticmethod
-    def from_bytes(data: bytes) -> Credentials: ...
-    @staticmethod
     def from_path(path: str) -> Credentials: ...
     @staticmethod
+    def from_bytes(data: bytes) -> Credentials: ...
+    @staticmethod
     def from_parts(cert: bytes, key: bytes, rune: str) -> Credentials: ...
-    def node_id(self) -> bytes: ...
     def upgrade(self, scheduler: Scheduler, signer: Signer) -> Credentials: ...
     def to_bytes(self) -> bytes: ...
-    def with_ca(self) -> Credentials: ...
+    def ensure_device(self) -> None: ...
+    def ensure_nobody(self) -> None: ...
+    def node_id(self) -> bytes: ...
+    def with_rune(self, rune: str) -> Credentials:
+        """The same device identity with a different, usually narrower,
+        `rune`.
+        """
+        ...
+    def derive_session(self, ttl: int, rules: List[str]) -> Credentials:
+        """Short-lived credentials with the same device identity, and a
+        rune carved with `rules` that expires after `ttl` seconds. The
+        `rules` are the ones accepted by `carve_rune`.
+        """
+        ...
+    def with_ca(self, ca: bytes) -> Credentials: ...
 
-class SignerHandle:
-    def shutdown(self) -> None: ...
 
+class LspClient:
+    def rpc_call(
+        self,
+        peer_id: bytes,
+        method_name: str,
+        value: bytes,
+    ) -> bytes: ...
+    def rpc_call_with_json_rpc_id(
+        self,
+        peer_id: bytes,
+        method_name: str,
+        value: bytes,
+        json_rpc_id: str,
+    ) -> bytes: ...
+    def list_lsp_servers(self) -> List[str]: ...
 
-class Signer:
-    def __init__(self, secret: bytes, network: str, creds: Credentials): ...
-    def sign_challenge(self, challenge: bytes) -> bytes: ...
-    def run_in_thread(self) -> SignerHandle: ...
-    def run_in_foreground(self) -> None: ...
-    def node_id(self) -> bytes: ...
-    def version(self) -> str: ...
-    def is_running(self) -> bool: ...
-    def shutdown(self) -> None: ...
-    def create_rune(self, restrictions: List[List[str]], rune: Optional[str] = None) -> str: ...
+
+class Node:
+    def __init__(
+        self,
+        node_id: bytes,
+        grpc_uri: str,
+        creds: Credentials,
+    ) -> None: ...
+    def call(self, method: str, payload: bytes) -> bytes: ...
+    def stream_log(self, args: bytes) -> Stream: ...
+    def stream_incoming(self, args: bytes) -> Stream: ...
+    def stream_custommsg(self, args: bytes) -> Stream: ...
+    def get_lsp_client(self) -> LspClient: ...
+    def configure(self, payload: bytes) -> None: ...
+    def export_ledger(
+        self,
+        format: str,
+        start: Optional[int] = None,
+        end: Optional[int] = None,
+        currency: Optional[str] = None,
+        rate: Optional[Union[Rates, Callable[[str, int], float]]] = None,
+    ) -> str:
+        """Export the ledger as `csv` or `json`. `rate` values the
+        entries in `currency`, and is either a `Rates` instance or a
+        callable `rate(currency, timestamp)` returning the price of
+        one bitcoin.
+        """
+        ...
+
+
+class Rates:
+    def __init__(self, providers: Optional[List[str]] = None) -> None:
+        """Query `providers` in order, any of `mempool`, `coinbase` and
+        `kraken`. Defaults to all of them.
+        """
+        ...
+    def rate(self, currency: str, at: int) -> float: ...
+    def convert(
+        self,
+        msat: int,
+        currency: str,
+        at_time: Optional[int] = None,
+    ) -> float:
+        """Convert `msat` to `currency`, at `at_time` or now."""
+        ...
 
 
 class Scheduler:
-    def __init__(self, network: str, creds: Optional[Credentials]): ...
-    def register(self, signer: Signer, invite_code: Optional[str]) -> bytes: ...
+    def __init__(self, network: str, creds: Credentials) -> None: ...
+    def register(
+        self,
+        signer: Signer,
+        invite_code: Optional[str] = None,
+    ) -> bytes: ...
     def recover(self, signer: Signer) -> bytes: ...
-    def authenticate(self, creds: Credentials): ...
+    def authenticate(self, creds: Credentials) -> Scheduler: ...
+    def export_node(self) -> bytes: ...
     def schedule(self) -> bytes: ...
     def node(self) -> bytes: ...
-    def get_node_info(self, wait: bool) -> bytes: ...
-    def export_node(self) -> bytes: ...
     def get_invite_codes(self) -> bytes: ...
+    def get_node_info(self, wait: bool) -> bytes: ...
     def add_outgoing_webhook(self, uri: str) -> bytes: ...
     def list_outgoing_webhooks(self) -> bytes: ...
-    def delete_outgoing_webhook(self, webhook_id: int) -> bytes: ...
     def delete_outgoing_webhooks(self, webhook_ids: List[int]) -> bytes: ...
-    def rotate_outgoing_webhook_secret(self, webhook_id: int)  -> bytes: ...
+    def rotate_outgoing_webhook_secret(self, webhook_id: int) -> bytes: ...
+    def add_lifecycle_webhook(
+        self,
+        uri: str,
+        events: Optional[List[str]] = None,
+    ) -> bytes:
+        """Register a webhook for the `scheduled`, `stopped` and
+        `signer_missing` events of the node, or for all of them if
+        `events` is `None`.
+        """
+        ...
+    def list_lifecycle_webhooks(self) -> bytes: ...
+    def delete_lifecycle_webhook(self, id: int) -> bytes: ...
+    def register_push_token(self, platform: str, token: str) -> bytes:
+        """Register a push token for `platform`, one of `apns`, `fcm` or
+        `webhook`.
+        """
+        ...
+    def unregister_push_token(self, id: int) -> bytes: ...
+    def get_node_metadata(self) -> bytes: ...
+    def update_node_metadata(
+        self,
+        signer_version: Optional[str] = None,
+        client_version: Optional[str] = None,
+        platform_tags: Optional[List[str]] = None,
+    ) -> bytes:
+        """Update the versions and platform tags reported for the node,
+        leaving the ones that are `None` unchanged. Returns the updated
+        metadata.
+        """
+        ...
+    def list_cln_versions(self) -> bytes:
+        """The offered CLN versions and the version policy of the node."""
+        ...
+    def pin_cln_version(self, version: Optional[str] = None) -> bytes:
+        """Pin the node to `version`, or unpin it with `None`. Raises
+        `GLVersionNotOffered` if the version is not offered. Returns
+        the version policy.
+        """
+        ...
+    def set_cln_upgrade_opt_in(self, opt_in: bool) -> bytes:
+        """Opt the node in to upgrades before they become the default.
+        Returns the version policy.
+        """
+        ...
 
 
-class Node:
+class Signer:
     def __init__(
         self,
-        node_id: bytes,
-        grpc_uri: str,
+        secret: bytes,
+        network: str,
         creds: Credentials,
     ) -> None: ...
-    def stop(self) -> None: ...
-    def call(self, method: str, request: bytes) -> bytes: ...
-    def get_lsp_client(self) -> LspClient: ...
-    def configure(self, payload: bytes) -> None: ...
-
-class LspClient:
-    def rpc_call(self, peer_id: bytes, method: str, params: bytes) -> bytes: ...
-    def rpc_call_with_json_rpc_id(
+    def run_in_thread(self) -> SignerHandle: ...
+    def run_in_foreground(self) -> None: ...
+    def state(self) -> str:
+        """The state of the signer loop, one of `stopped`, `running`,
+        `reconnecting` or `failed`.
+        """
+        ...
+    def last_error(self) -> Optional[str]:
+        """The error the signer loop failed with, or the one it is
+        reconnecting after.
+        """
+        ...
+    def on_state_change(
         self,
-        peer_id: bytes,
-        method: str,
-        params: bytes,
-        json_rpc_id: Optional[str] = None,
-    ) -> bytes: ...
-    def list_lsp_servers(self) -> List[str]: ...
+        callback: Callable[[str, Optional[str]], None],
+    ) -> None:
+        """Call `callback` with the current state and last error, and
+        again whenever the state changes.
+        """
+        ...
+    def enable_telemetry(
+        self,
+        endpoint: str,
+        interval: Optional[float] = None,
+    ) -> None:
+        """Count the requests the signer rejects, and report the counts
+        to `endpoint` every `interval` seconds, or every hour. Takes
+        effect the next time the signer is started.
+        """
+        ...
+    def node_id(self) -> bytes: ...
+    def init(self) -> bytes: ...
+    def bip32_key(self) -> bytes: ...
+    def sign_challenge(self, challenge: bytes) -> bytes: ...
+    def sign_ownership_proof(self, challenge: bytes) -> str:
+        """Sign `challenge` with the node key, in the zbase32 format of
+        `lightningd`'s `signmessage`.
+        """
+        ...
+    def version(self) -> str: ...
+    def create_rune(
+        self,
+        restrictions: List[List[str]],
+        rune: Optional[str] = None,
+    ) -> str: ...
+    def status(self) -> str: ...
+    def events(self) -> Stream:
+        """The events the signer publishes from now on, as JSON."""
+        ...
+    def on_status(self, callback: Callable[[str], None]) -> None:
+        """Call `callback` with the current connection status, and again
+        whenever it changes.
+        """
+        ...
+
+
+class SignerHandle:
+    def shutdown(self) -> None: ...
+
+
+class Stream:
+    """A stream of messages from the node or the signer, read in the
+    background. Dropping or closing the stream cancels it.
+    """
+    def next(self, timeout: Optional[float] = None) -> Optional[bytes]:
+        """The next message, or `None` once the stream ended or was
+        closed. Raises `TimeoutError` if no message arrived within
+        `timeout` seconds.
+        """
+        ...
+    def close(self) -> None:
+        """Cancel the stream. Calls to `next` return the messages that
+        were already read, and then `None`.
+        """
+        ...
+
+
+class TlsConfig:
+    def __init__(self) -> None: ...
+    def identity(self, cert_pem: bytes, key_pem: bytes) -> TlsConfig: ...
+    def identity_from_path(self, path: str) -> TlsConfig: ...
+    def with_ca_certificate(self, ca: bytes) -> TlsConfig: ...
+    def ca_certificate(self) -> bytes: ...
 
 
 def backup_decrypt_with_seed(encrypted: bytes, seed: bytes) -> bytes: ...
+
+def decode_push_notification(
+    payload: bytes,
+    signature: Optional[str] = None,
+    secret: Optional[str] = None,
+) -> str:
+    """Decode a push notification payload, returning it as JSON. If
+    `signature` and `secret` are given, the payload is only accepted if
+    it was signed with the secret.
+    """
+    ...
+
+def qr_code(
+    data: str,
+    format: str = "svg",
+    size: Optional[int] = None,
+) -> bytes:
+    """Render `data`, e.g., an invoice, as a QR code, either as an SVG
+    image at least `size` pixels wide, or as a PNG image with modules
+    `size` pixels wide, depending on `format`.
+    """
+    ...
+
+def carve_rune(rune: str, rules: List[str], commando: bool = False) -> str:
+    """Carve a narrower rune from the base64 encoded `rune`. Each entry of
+    `rules` is a restriction that has to hold, e.g., `readonly`, and
+    may combine rules as alternatives, e.g., `pay|readonly`. With
+    `commando` the method names follow core-lightning's naming.
+    """
+    ...
+
+def decode_rune(rune: str) -> str:
+    """Decode the base64 encoded `rune`, returning its contents as JSON."""
+    ...
+
+def configure_runtime(
+    flavor: str = "multi_thread",
+    worker_threads: Optional[int] = None,
+) -> None:
+    """Configure the runtime the library runs on. Must be called before
+    the first call into the library. `flavor` is either
+    `multi_thread`, the default, or `current_thread`, which runs
+    everything on the calling thread, at the cost of background tasks
+    only progressing during calls.
+    """
+    ...
@@ -4,7 +4,8 @@ use crate::signer::Signer;
 use gl_client::credentials::{self, NodeIdProvider, RuneProvider, TlsConfigProvider};
 use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
-use pyo3::types::PyBytes;
+use pyo3::types::{PyBytes, PyType};
+use std::time::Duration;
 
 pub type PyCredentials = UnifiedCredentials<credentials::Nobody, credentials::Device>;
 
@@ -55,6 +56,13 @@ where
             UnifiedCredentials::Device(d) => d.tls_config(),
         }
     }
+
+    fn try_tls_config(&self) -> credentials::Result<gl_client::tls::TlsConfig> {
+        match self {
+            UnifiedCredentials::Nobody(n) => n.try_tls_config(),
+            UnifiedCredentials::Device(d) => d.try_tls_config(),
+        }
+    }
 }
 
 impl<T, R> RuneProvider for UnifiedCredentials<T, R>
@@ -70,6 +78,15 @@ where
             UnifiedCredentials::Device(d) => d.rune(),
         }
     }
+
+    fn try_rune(&self) -> credentials::Result<String> {
+        match self {
+            UnifiedCredentials::Nobody(_) => Err(credentials::Error::IsIdentityError(
+                "can not provide rune from nobody credentials".to_string(),
+            )),
+            UnifiedCredentials::Device(d) => d.try_rune(),
+        }
+    }
 }
 
 impl<T, R> NodeIdProvider for UnifiedCredentials<T, R>
@@ -159,6 +176,31 @@ impl Credentials {
         }
     }
 
+    /// Pickle the credentials as their kind and bytes, so they can be
+    /// passed to other processes.
+    fn __reduce__<'a>(&self, py: Python<'a>) -> (&'a PyType, (), (&'static str, &'a PyBytes)) {
+        let state = match &self.inner {
+            UnifiedCredentials::Nobody(n) => ("nobody", PyBytes::new(py, &n.to_bytes()[..])),
+            UnifiedCredentials::Device(d) => ("device", PyBytes::new(py, &d.to_bytes()[..])),
+        };
+        (py.get_type::<Credentials>(), (), state)
+    }
+
+    fn __setstate__(&mut self, state: (&str, &[u8])) -> Result<()> {
+        self.inner = match state {
+            ("nobody", data) => {
+                UnifiedCredentials::Nobody(gl_client::credentials::Nobody::from_bytes(data)?)
+            }
+            ("device", data) => {
+                UnifiedCredentials::Device(gl_client::credentials::Device::from_bytes(data))
+            }
+            (kind, _) => Err(credentials::Error::TransformDataIntoCredentialsError(
+                format!("unknown kind of credentials {}", kind),
+            ))?,
+        };
+        Ok(())
+    }
+
     pub fn ensure_device(&self) -> Result<()> {
         self.inner.ensure_device()
     }
@@ -171,6 +213,42 @@ impl Credentials {
         Ok(self.inner.node_id()?)
     }
 
+    /// The same device identity with a different, usually narrower,
+    /// `rune`.
+    pub fn with_rune(&self, rune: &str) -> Result<Self> {
+        match &self.inner {
+            UnifiedCredentials::Nobody(_) => Err(credentials::Error::IsIdentityError(
+                "can not set a rune on nobody credentials".to_string(),
+            ))?,
+            UnifiedCredentials::Device(creds) => {
+                let d = creds.clone().with_rune(rune);
+                let inner = UnifiedCredentials::Device(d);
+                Ok(Self { inner })
+            }
+        }
+    }
+
+    /// Short-lived credentials with the same device identity, and a
+    /// rune carved with `rules` that expires after `ttl` seconds. The
+    /// `rules` are the ones accepted by `carve_rune`.
+    pub fn derive_session(&self, ttl: u64, rules: Vec<String>) -> PyResult<Self> {
+        match &self.inner {
+            UnifiedCredentials::Nobody(_) => {
+                Err(ErrorWrapper::from(credentials::Error::IsIdentityError(
+                    "can not derive a session from nobody credentials".to_string(),
+                )))?
+            }
+            UnifiedCredentials::Device(creds) => {
+                let d = crate::runes::with_rules(&rules, |rules| {
+                    creds.derive_session(Duration::from_secs(ttl), rules)
+                })?
+                .map_err(ErrorWrapper::from)?;
+                let inner = UnifiedCredentials::Device(d);
+                Ok(Self { inner })
+            }
+        }
+    }
+
     pub fn with_ca(&self, ca: &[u8]) -> Self {
         match &self.inner {
             UnifiedCredentials::Nobody(creds) => {
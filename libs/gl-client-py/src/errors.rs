@@ -0,0 +1,120 @@
+use gl_client::node::NodeError;
+use gl_client::versions::VersionNotOffered;
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use tonic::Status;
+
+// The exceptions derive from `ValueError`, which the bindings raised
+// for all errors before.
+create_exception!(
+    glclient,
+    GLError,
+    PyValueError,
+    "An error of a Greenlight call."
+);
+create_exception!(
+    glclient,
+    GLConnectionError,
+    GLError,
+    "The node could not be reached, the call may be retried."
+);
+create_exception!(
+    glclient,
+    GLRateLimited,
+    GLError,
+    "The service rate limited the call, retry after `retry_after` seconds."
+);
+create_exception!(
+    glclient,
+    GLAuthError,
+    GLError,
+    "The credentials were not accepted for the call."
+);
+create_exception!(
+    glclient,
+    GLSignerRejected,
+    GLError,
+    "The signer refused to sign for the call."
+);
+create_exception!(
+    glclient,
+    GLRpcError,
+    GLError,
+    "lightningd returned an error, with its `method`, `code` and `data`."
+);
+create_exception!(
+    glclient,
+    GLVersionNotOffered,
+    GLError,
+    "The scheduler does not offer the requested CLN version."
+);
+
+pub(crate) fn register(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add("GLError", py.get_type::<GLError>())?;
+    m.add("GLConnectionError", py.get_type::<GLConnectionError>())?;
+    m.add("GLRateLimited", py.get_type::<GLRateLimited>())?;
+    m.add("GLAuthError", py.get_type::<GLAuthError>())?;
+    m.add("GLSignerRejected", py.get_type::<GLSignerRejected>())?;
+    m.add("GLRpcError", py.get_type::<GLRpcError>())?;
+    m.add("GLVersionNotOffered", py.get_type::<GLVersionNotOffered>())?;
+    Ok(())
+}
+
+/// The exception for a failed node or scheduler call.
+pub(crate) fn status_error(status: &Status) -> PyErr {
+    node_error(NodeError::from(status))
+}
+
+pub(crate) fn node_error(e: NodeError) -> PyErr {
+    let msg = e.to_string();
+    match e {
+        NodeError::Connection(_) => GLConnectionError::new_err(msg),
+        NodeError::RateLimited(limited) => Python::with_gil(|py| {
+            let err = GLRateLimited::new_err(msg);
+            let retry_after = limited.retry_after.map(|d| d.as_secs_f64());
+            match err.value(py).setattr("retry_after", retry_after) {
+                Ok(()) => err,
+                Err(e) => e,
+            }
+        }),
+        NodeError::Auth(_) => GLAuthError::new_err(msg),
+        NodeError::SignerRejected(_) => GLSignerRejected::new_err(msg),
+        NodeError::Other(_) => GLError::new_err(msg),
+        NodeError::Rpc {
+            method, code, data, ..
+        } => Python::with_gil(|py| {
+            let err = GLRpcError::new_err(msg);
+            let data = data.map(|d| {
+                py.import("json")
+                    .and_then(|json| json.call_method1("loads", (d.to_string(),)))
+                    .map(|d| d.to_object(py))
+                    .unwrap_or_else(|_| d.to_string().to_object(py))
+            });
+            let value = err.value(py);
+            let attrs = value
+                .setattr("method", method)
+                .and_then(|_| value.setattr("code", code))
+                .and_then(|_| value.setattr("data", data));
+            match attrs {
+                Ok(()) => err,
+                Err(e) => e,
+            }
+        }),
+    }
+}
+
+/// The exception for a call that failed with `e`, which may be a
+/// [`Status`] or a transport error.
+pub(crate) fn anyhow_error(e: anyhow::Error) -> PyErr {
+    if e.downcast_ref::<VersionNotOffered>().is_some() {
+        return GLVersionNotOffered::new_err(e.to_string());
+    }
+    if let Some(status) = e.downcast_ref::<Status>() {
+        return status_error(status);
+    }
+    if e.downcast_ref::<tonic::transport::Error>().is_some() {
+        return GLConnectionError::new_err(e.to_string());
+    }
+    GLError::new_err(e.to_string())
+}
@@ -5,17 +5,23 @@ use pyo3::prelude::*;
 extern crate log;
 
 mod credentials;
+mod errors;
 mod lsps;
 mod node;
+mod rates;
+mod runes;
 mod runtime;
 mod scheduler;
 mod signer;
+mod stream;
 mod tls;
 
 pub use lsps::LspClient;
 pub use node::Node;
+pub use rates::Rates;
 pub use scheduler::Scheduler;
 pub use signer::{Signer, SignerHandle};
+pub use stream::Stream;
 pub use tls::TlsConfig;
 
 #[pyfunction]
@@ -31,19 +37,67 @@ pub fn backup_decrypt_with_seed(encrypted: Vec<u8>, seed: Vec<u8>) -> PyResult<V
     Ok(res[..].into())
 }
 
+/// Decode a push notification payload, returning it as JSON. If
+/// `signature` and `secret` are given, the payload is only accepted if
+/// it was signed with the secret.
+#[pyfunction]
+pub fn decode_push_notification(
+    payload: Vec<u8>,
+    signature: Option<String>,
+    secret: Option<String>,
+) -> PyResult<String> {
+    use gl_client::push::PushNotification;
+    use pyo3::exceptions::PyValueError;
+    let n = match (signature, secret) {
+        (Some(sig), Some(secret)) => PushNotification::verify(&payload, &sig, &secret),
+        _ => PushNotification::decode(&payload),
+    }
+    .map_err(|e| PyValueError::new_err(format!("error decoding push notification: {}", e)))?;
+    serde_json::to_string(&n).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// Render `data`, e.g., an invoice, as a QR code, either as an SVG
+/// image at least `size` pixels wide, or as a PNG image with modules
+/// `size` pixels wide, depending on `format`.
+#[pyfunction]
+#[pyo3(signature = (data, format = "svg", size = None))]
+pub fn qr_code(data: &str, format: &str, size: Option<u32>) -> PyResult<Vec<u8>> {
+    use gl_client::qr;
+    use pyo3::exceptions::PyValueError;
+    let res = match format {
+        "svg" => qr::svg(data, size.unwrap_or(256)).map(String::into_bytes),
+        "png" => qr::png(data, size.unwrap_or(8)),
+        o => {
+            return Err(PyValueError::new_err(format!(
+                "unknown QR code format {}",
+                o
+            )))
+        }
+    };
+    res.map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
 /// A Python module implemented in Rust.
 #[pymodule]
-fn glclient(_py: Python, m: &PyModule) -> PyResult<()> {
+fn glclient(py: Python, m: &PyModule) -> PyResult<()> {
     env_logger::init();
+    errors::register(py, m)?;
     m.add_class::<Signer>()?;
     m.add_class::<SignerHandle>()?;
     m.add_class::<Node>()?;
     m.add_class::<Scheduler>()?;
     m.add_class::<TlsConfig>()?;
     m.add_class::<LspClient>()?;
+    m.add_class::<Rates>()?;
+    m.add_class::<Stream>()?;
     m.add_class::<credentials::Credentials>()?;
 
     m.add_function(wrap_pyfunction!(backup_decrypt_with_seed, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_push_notification, m)?)?;
+    m.add_function(wrap_pyfunction!(qr_code, m)?)?;
+    m.add_function(wrap_pyfunction!(runtime::configure_runtime, m)?)?;
+    m.add_function(wrap_pyfunction!(runes::carve_rune, m)?)?;
+    m.add_function(wrap_pyfunction!(runes::decode_rune, m)?)?;
 
     Ok(())
 }
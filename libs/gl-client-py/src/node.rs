@@ -1,11 +1,13 @@
+use crate::errors::{anyhow_error, status_error, GLConnectionError};
 use crate::runtime::exec;
-use crate::{credentials::Credentials, lsps::LspClient};
+use crate::stream::Stream;
+use crate::{credentials::Credentials, lsps::LspClient, rates::Rates};
 use gl_client as gl;
+use gl_client::ledger::{DateRange, Format, Ledger, RateProvider};
 use gl_client::pb;
 use prost::Message;
 use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
-use tonic::{Code, Status};
 
 #[pyclass]
 pub struct Node {
@@ -31,33 +33,33 @@ impl Node {
     fn call(&self, method: &str, payload: Vec<u8>) -> PyResult<Vec<u8>> {
         exec(self.gclient.clone().call(method, payload))
             .map(|x| x.into_inner().to_vec())
-            .map_err(|s| PyValueError::new_err(format!("Error calling {}: {}", method, s)))
+            .map_err(|s| status_error(&s))
     }
 
-    fn stream_log(&self, args: &[u8]) -> PyResult<LogStream> {
+    fn stream_log(&self, args: &[u8]) -> PyResult<Stream> {
         let req = pb::StreamLogRequest::decode(args).map_err(error_decoding_request)?;
 
         let stream = exec(self.client.clone().stream_log(req))
             .map(|x| x.into_inner())
-            .map_err(error_starting_stream)?;
-        Ok(LogStream { inner: stream })
+            .map_err(|s| status_error(&s))?;
+        Ok(Stream::messages(stream))
     }
 
-    fn stream_incoming(&self, args: &[u8]) -> PyResult<IncomingStream> {
+    fn stream_incoming(&self, args: &[u8]) -> PyResult<Stream> {
         let req = pb::StreamIncomingFilter::decode(args).map_err(error_decoding_request)?;
 
         let stream = exec(self.client.clone().stream_incoming(req))
             .map(|x| x.into_inner())
-            .map_err(error_starting_stream)?;
-        Ok(IncomingStream { inner: stream })
+            .map_err(|s| status_error(&s))?;
+        Ok(Stream::messages(stream))
     }
 
-    fn stream_custommsg(&self, args: &[u8]) -> PyResult<CustommsgStream> {
+    fn stream_custommsg(&self, args: &[u8]) -> PyResult<Stream> {
         let req = pb::StreamCustommsgRequest::decode(args).map_err(error_decoding_request)?;
         let stream = exec(self.client.clone().stream_custommsg(req))
             .map(|x| x.into_inner())
-            .map_err(error_starting_stream)?;
-        Ok(CustommsgStream { inner: stream })
+            .map_err(|s| status_error(&s))?;
+        Ok(Stream::messages(stream))
     }
 
     fn get_lsp_client(&self) -> LspClient {
@@ -69,80 +71,69 @@ impl Node {
 
         exec(self.client.clone().configure(req))
             .map(|x| x.into_inner())
-            .map_err(error_calling_remote_method)?;
+            .map_err(|s| status_error(&s))?;
 
         return Ok(());
     }
-}
-
-fn error_decoding_request<D: core::fmt::Display>(e: D) -> PyErr {
-    PyValueError::new_err(format!("error decoding request: {}", e))
-}
-
-pub fn error_calling_remote_method<D: core::fmt::Display>(e: D) -> PyErr {
-    PyValueError::new_err(format!("error calling remote method: {}", e))
-}
-
-fn error_starting_stream<D: core::fmt::Display>(e: D) -> PyErr {
-    PyValueError::new_err(format!("Error starting stream: {}", e))
-}
-
-#[pyclass]
-struct LogStream {
-    inner: tonic::codec::Streaming<pb::LogEntry>,
-}
 
-#[pymethods]
-impl LogStream {
-    fn next(&mut self) -> PyResult<Option<Vec<u8>>> {
-        convert_stream_entry(exec(async { self.inner.message().await }))
+    /// Export the ledger as `csv` or `json`. `rate` values the
+    /// entries in `currency`, and is either a `Rates` instance or a
+    /// callable `rate(currency, timestamp)` returning the price of
+    /// one bitcoin.
+    fn export_ledger(
+        &self,
+        format: &str,
+        start: Option<u64>,
+        end: Option<u64>,
+        currency: Option<String>,
+        rate: Option<PyObject>,
+    ) -> PyResult<String> {
+        let format: Format = format
+            .parse()
+            .map_err(|e: anyhow::Error| PyValueError::new_err(e.to_string()))?;
+        let provider: Option<Box<dyn RateProvider>> = rate.map(|r| {
+            Python::with_gil(|py| match r.extract::<Rates>(py) {
+                Ok(rates) => Box::new(rates.inner) as Box<dyn RateProvider>,
+                Err(_) => Box::new(PyRateProvider(r)),
+            })
+        });
+        let mut client = self.client.clone();
+
+        exec(async move {
+            let ledger = Ledger::fetch(&mut client).await?;
+            let valuation = match (&provider, &currency) {
+                (Some(p), Some(c)) => Some((p.as_ref(), c.as_str())),
+                _ => None,
+            };
+            ledger
+                .export(format, DateRange::new(start, end), valuation)
+                .await
+        })
+        .map_err(anyhow_error)
     }
 }
 
-#[pyclass]
-struct IncomingStream {
-    inner: tonic::codec::Streaming<pb::IncomingPayment>,
-}
-
-#[pymethods]
-impl IncomingStream {
-    fn next(&mut self) -> PyResult<Option<Vec<u8>>> {
-        convert_stream_entry(exec(async { self.inner.message().await }))
+/// A rate provider backed by a Python callable.
+struct PyRateProvider(PyObject);
+
+#[async_trait::async_trait]
+impl RateProvider for PyRateProvider {
+    async fn rate(&self, currency: &str, at: u64) -> anyhow::Result<f64> {
+        Python::with_gil(|py| {
+            self.0
+                .call1(py, (currency, at))
+                .and_then(|r| r.extract::<f64>(py))
+                .map_err(|e| anyhow::anyhow!("error calling rate provider: {}", e))
+        })
     }
 }
 
-#[pyclass]
-struct CustommsgStream {
-    inner: tonic::codec::Streaming<pb::Custommsg>,
-}
-
-#[pymethods]
-impl CustommsgStream {
-    fn next(&mut self) -> PyResult<Option<Vec<u8>>> {
-        convert_stream_entry(exec(async { self.inner.message().await }))
-    }
+fn error_decoding_request<D: core::fmt::Display>(e: D) -> PyErr {
+    PyValueError::new_err(format!("error decoding request: {}", e))
 }
 
-fn convert_stream_entry<T: Message>(r: Result<Option<T>, Status>) -> PyResult<Option<Vec<u8>>> {
-    let res = match r {
-        Ok(Some(v)) => v,
-        Ok(None) => return Ok(None),
-        Err(e) => match e.code() {
-            Code::Unknown => {
-                // Unknown most likely just means we lost the
-                // connection. This is due to a shutdown and shouldn't
-                // be as noisy as other errors.
-                return Ok(None);
-            }
-            _ => {
-                log::warn!("ERROR {:?}", e);
-                return Err(error_calling_remote_method(e));
-            }
-        },
-    };
-    let mut buf = Vec::with_capacity(res.encoded_len());
-    res.encode(&mut buf).unwrap();
-    Ok(Some(buf))
+pub fn error_calling_remote_method<D: core::fmt::Display>(e: D) -> PyErr {
+    PyValueError::new_err(format!("error calling remote method: {}", e))
 }
 
 fn node_from_inner(inner: gl::node::Node, grpc_uri: String) -> PyResult<Node> {
@@ -166,9 +157,7 @@ fn node_from_inner(inner: gl::node::Node, grpc_uri: String) -> PyResult<Node> {
             h3.await??,
         ))
     })
-    .map_err(|e| {
-        pyo3::exceptions::PyValueError::new_err(format!("could not connect to node: {}", e))
-    })?;
+    .map_err(|e| GLConnectionError::new_err(format!("could not connect to node: {}", e)))?;
 
     Ok(Node {
         client,
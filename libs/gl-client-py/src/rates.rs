@@ -0,0 +1,60 @@
+use crate::node::error_calling_remote_method;
+use crate::runtime::exec;
+use gl_client::rates;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+#[pyclass]
+#[derive(Clone)]
+pub struct Rates {
+    pub(crate) inner: Arc<rates::Rates>,
+}
+
+#[pymethods]
+impl Rates {
+    /// Query `providers` in order, any of `mempool`, `coinbase` and
+    /// `kraken`. Defaults to all of them.
+    #[new]
+    fn new(providers: Option<Vec<String>>) -> PyResult<Self> {
+        let inner = match providers {
+            None => rates::Rates::default(),
+            Some(names) => rates::Rates::new(
+                names
+                    .iter()
+                    .map(|n| -> PyResult<Box<dyn rates::RateProvider>> {
+                        match n.as_str() {
+                            "mempool" => Ok(Box::<rates::MempoolSpace>::default()),
+                            "coinbase" => Ok(Box::<rates::Coinbase>::default()),
+                            "kraken" => Ok(Box::<rates::Kraken>::default()),
+                            o => Err(PyValueError::new_err(format!(
+                                "unknown rate provider {}",
+                                o
+                            ))),
+                        }
+                    })
+                    .collect::<PyResult<Vec<_>>>()?,
+            ),
+        };
+        Ok(Rates {
+            inner: Arc::new(inner),
+        })
+    }
+
+    fn rate(&self, currency: &str, at: u64) -> PyResult<f64> {
+        use rates::RateProvider;
+        exec(self.inner.rate(currency, at)).map_err(error_calling_remote_method)
+    }
+
+    /// Convert `msat` to `currency`, at `at_time` or now.
+    fn convert(&self, msat: u64, currency: &str, at_time: Option<u64>) -> PyResult<f64> {
+        let at_time = at_time.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+        });
+        exec(self.inner.convert(msat, currency, at_time)).map_err(error_calling_remote_method)
+    }
+}
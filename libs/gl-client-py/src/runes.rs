@@ -0,0 +1,50 @@
+use gl_client::runes::{self, DefRules, RuneFactory, RuneInfo};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+/// Carve a narrower rune from the base64 encoded `rune`. Each entry of
+/// `rules` is a restriction that has to hold, e.g., `readonly`, and
+/// may combine rules as alternatives, e.g., `pay|readonly`. With
+/// `commando` the method names follow core-lightning's naming.
+#[pyfunction]
+#[pyo3(signature = (rune, rules, commando = false))]
+pub fn carve_rune(rune: &str, rules: Vec<String>, commando: bool) -> PyResult<String> {
+    let origin = runes::decode_rune(rune).map_err(value_error)?;
+    with_rules(&rules, |rules| match commando {
+        true => RuneFactory::carve_commando(&origin, rules),
+        false => RuneFactory::carve(&origin, rules),
+    })?
+    .map_err(value_error)
+}
+
+/// Parse `rules` as accepted by `carve_rune`, and pass them to `f`.
+pub(crate) fn with_rules<T>(rules: &[String], f: impl FnOnce(&[DefRules]) -> T) -> PyResult<T> {
+    let groups = rules
+        .iter()
+        .map(|r| {
+            r.split('|')
+                .map(|r| r.trim().parse::<DefRules>())
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(value_error)?;
+    let rules: Vec<DefRules> = groups
+        .iter()
+        .map(|g| match g.as_slice() {
+            [r] => *r,
+            g => DefRules::Add(g),
+        })
+        .collect();
+    Ok(f(&rules))
+}
+
+/// Decode the base64 encoded `rune`, returning its contents as JSON.
+#[pyfunction]
+pub fn decode_rune(rune: &str) -> PyResult<String> {
+    let info = RuneInfo::decode(rune).map_err(value_error)?;
+    serde_json::to_string(&info).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+fn value_error<E: std::fmt::Display>(e: E) -> PyErr {
+    PyValueError::new_err(format!("invalid rune: {}", e))
+}
@@ -1,15 +1,40 @@
 use ::tokio::runtime::{Builder, Runtime};
 use once_cell::sync::OnceCell;
-use pyo3::prelude::Python;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
 use std::future::Future;
+use std::sync::Mutex;
 
 static TOKIO_RUNTIME: OnceCell<Runtime> = OnceCell::new();
 
+/// How to build the runtime, set by `configure_runtime`.
+static CONFIG: Mutex<Option<RuntimeConfig>> = Mutex::new(None);
+
+#[derive(Clone, Copy)]
+struct RuntimeConfig {
+    current_thread: bool,
+    worker_threads: Option<usize>,
+}
+
 pub(crate) fn get_runtime<'a>() -> &'a Runtime {
     TOKIO_RUNTIME.get_or_init(|| {
-        let mut builder = Builder::new_multi_thread();
+        let config = *CONFIG.lock().unwrap();
+        let mut builder = match config {
+            Some(RuntimeConfig {
+                current_thread: true,
+                ..
+            }) => Builder::new_current_thread(),
+            _ => Builder::new_multi_thread(),
+        };
+        if let Some(n) = config.and_then(|c| c.worker_threads) {
+            builder.worker_threads(n);
+        }
         builder.enable_all();
-        builder.build().expect("Unable to build Tokio runtime")
+        let runtime = builder.build().expect("Unable to build Tokio runtime");
+        if gl_client::runtime::set_handle(runtime.handle().clone()).is_err() {
+            warn!("gl-client runtime handle was already set elsewhere");
+        }
+        runtime
     })
 }
 
@@ -20,3 +45,28 @@ where
 {
     Python::with_gil(|py| py.allow_threads(move || get_runtime().block_on(f)))
 }
+
+/// Configure the runtime the library runs on. Must be called before
+/// the first call into the library. `flavor` is either
+/// `multi_thread`, the default, or `current_thread`, which runs
+/// everything on the calling thread, at the cost of background tasks
+/// only progressing during calls.
+#[pyfunction]
+#[pyo3(signature = (flavor = "multi_thread", worker_threads = None))]
+pub fn configure_runtime(flavor: &str, worker_threads: Option<usize>) -> PyResult<()> {
+    let current_thread = match flavor {
+        "multi_thread" => false,
+        "current_thread" => true,
+        f => return Err(PyValueError::new_err(format!("unknown runtime flavor {}", f))),
+    };
+    if TOKIO_RUNTIME.get().is_some() {
+        return Err(PyValueError::new_err(
+            "the runtime is already running, configure it before using the library",
+        ));
+    }
+    *CONFIG.lock().unwrap() = Some(RuntimeConfig {
+        current_thread,
+        worker_threads,
+    });
+    Ok(())
+}
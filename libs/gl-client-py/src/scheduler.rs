@@ -125,6 +125,75 @@ where
         s.rotate_outgoing_webhook_secret(webhook_id).await
     }
 
+    async fn add_lifecycle_webhook(
+        &self,
+        uri: String,
+        events: &[pb::scheduler::NodeLifecycleEvent],
+    ) -> Result<pb::scheduler::AddOutgoingWebhookResponse> {
+        let s = self.authenticated_scheduler()?;
+        s.add_lifecycle_webhook(uri, events).await
+    }
+
+    async fn list_lifecycle_webhooks(
+        &self,
+    ) -> Result<pb::scheduler::ListLifecycleWebhooksResponse> {
+        let s = self.authenticated_scheduler()?;
+        s.list_lifecycle_webhooks().await
+    }
+
+    async fn delete_lifecycle_webhook(&self, id: i64) -> Result<pb::Empty> {
+        let s = self.authenticated_scheduler()?;
+        s.delete_lifecycle_webhook(id).await
+    }
+
+    async fn register_push_token(
+        &self,
+        platform: pb::scheduler::PushPlatform,
+        token: String,
+    ) -> Result<pb::scheduler::RegisterPushTokenResponse> {
+        let s = self.authenticated_scheduler()?;
+        s.register_push_token(platform, token).await
+    }
+
+    async fn unregister_push_token(&self, id: i64) -> Result<pb::Empty> {
+        let s = self.authenticated_scheduler()?;
+        s.unregister_push_token(id).await
+    }
+
+    async fn get_node_metadata(&self) -> Result<pb::scheduler::NodeMetadata> {
+        let s = self.authenticated_scheduler()?;
+        s.get_node_metadata().await
+    }
+
+    async fn update_node_metadata(
+        &self,
+        update: scheduler::NodeMetadataUpdate,
+    ) -> Result<pb::scheduler::NodeMetadata> {
+        let s = self.authenticated_scheduler()?;
+        s.update_node_metadata(update).await
+    }
+
+    async fn list_cln_versions(&self) -> Result<pb::scheduler::ListClnVersionsResponse> {
+        let s = self.authenticated_scheduler()?;
+        s.list_cln_versions().await
+    }
+
+    async fn pin_cln_version(
+        &self,
+        version: Option<String>,
+    ) -> Result<pb::scheduler::ClnVersionPolicy> {
+        let s = self.authenticated_scheduler()?;
+        s.pin_cln_version(version).await
+    }
+
+    async fn set_cln_upgrade_opt_in(
+        &self,
+        opt_in: bool,
+    ) -> Result<pb::scheduler::ClnVersionPolicy> {
+        let s = self.authenticated_scheduler()?;
+        s.set_cln_upgrade_opt_in(opt_in).await
+    }
+
     fn authenticated_scheduler(&self) -> Result<&scheduler::Scheduler<R>> {
         match self {
             UnifiedScheduler::Unauthenticated(_) => {
@@ -239,10 +308,113 @@ impl Scheduler {
             self.inner.rotate_outgoing_webhook_secret(webhook_id).await
         }))
     }
+
+    /// Register a webhook for the `scheduled`, `stopped` and
+    /// `signer_missing` events of the node, or for all of them if
+    /// `events` is `None`.
+    fn add_lifecycle_webhook(&self, uri: String, events: Option<Vec<String>>) -> PyResult<Vec<u8>> {
+        let events = events
+            .unwrap_or_default()
+            .iter()
+            .map(|e| lifecycle_event(e))
+            .collect::<PyResult<Vec<_>>>()?;
+        convert(exec(async {
+            self.inner.add_lifecycle_webhook(uri, &events).await
+        }))
+    }
+
+    fn list_lifecycle_webhooks(&self) -> PyResult<Vec<u8>> {
+        convert(exec(async { self.inner.list_lifecycle_webhooks().await }))
+    }
+
+    fn delete_lifecycle_webhook(&self, id: i64) -> PyResult<Vec<u8>> {
+        convert(exec(async {
+            self.inner.delete_lifecycle_webhook(id).await
+        }))
+    }
+
+    /// Register a push token for `platform`, one of `apns`, `fcm` or
+    /// `webhook`.
+    fn register_push_token(&self, platform: &str, token: String) -> PyResult<Vec<u8>> {
+        let platform = match platform {
+            "apns" => pb::scheduler::PushPlatform::Apns,
+            "fcm" => pb::scheduler::PushPlatform::Fcm,
+            "webhook" => pb::scheduler::PushPlatform::Webhook,
+            o => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown push platform {}",
+                    o
+                )))
+            }
+        };
+        convert(exec(async {
+            self.inner.register_push_token(platform, token).await
+        }))
+    }
+
+    fn unregister_push_token(&self, id: i64) -> PyResult<Vec<u8>> {
+        convert(exec(async { self.inner.unregister_push_token(id).await }))
+    }
+
+    fn get_node_metadata(&self) -> PyResult<Vec<u8>> {
+        convert(exec(async { self.inner.get_node_metadata().await }))
+    }
+
+    /// Update the versions and platform tags reported for the node,
+    /// leaving the ones that are `None` unchanged. Returns the updated
+    /// metadata.
+    fn update_node_metadata(
+        &self,
+        signer_version: Option<String>,
+        client_version: Option<String>,
+        platform_tags: Option<Vec<String>>,
+    ) -> PyResult<Vec<u8>> {
+        let update = scheduler::NodeMetadataUpdate {
+            signer_version,
+            client_version,
+            platform_tags,
+        };
+        convert(exec(async {
+            self.inner.update_node_metadata(update).await
+        }))
+    }
+
+    /// The offered CLN versions and the version policy of the node.
+    fn list_cln_versions(&self) -> PyResult<Vec<u8>> {
+        convert(exec(async { self.inner.list_cln_versions().await }))
+    }
+
+    /// Pin the node to `version`, or unpin it with `None`. Raises
+    /// `GLVersionNotOffered` if the version is not offered. Returns
+    /// the version policy.
+    fn pin_cln_version(&self, version: Option<String>) -> PyResult<Vec<u8>> {
+        convert(exec(async { self.inner.pin_cln_version(version).await }))
+    }
+
+    /// Opt the node in to upgrades before they become the default.
+    /// Returns the version policy.
+    fn set_cln_upgrade_opt_in(&self, opt_in: bool) -> PyResult<Vec<u8>> {
+        convert(exec(async {
+            self.inner.set_cln_upgrade_opt_in(opt_in).await
+        }))
+    }
+}
+
+fn lifecycle_event(name: &str) -> PyResult<pb::scheduler::NodeLifecycleEvent> {
+    use pb::scheduler::NodeLifecycleEvent::*;
+    match name {
+        "scheduled" => Ok(NodeScheduled),
+        "stopped" => Ok(NodeStopped),
+        "signer_missing" => Ok(SignerMissing),
+        o => Err(PyValueError::new_err(format!(
+            "unknown lifecycle event {}",
+            o
+        ))),
+    }
 }
 
 pub fn convert<T: Message>(r: Result<T>) -> PyResult<Vec<u8>> {
-    let res = r.map_err(crate::node::error_calling_remote_method)?;
+    let res = r.map_err(crate::errors::anyhow_error)?;
     let mut buf = Vec::with_capacity(res.encoded_len());
     res.encode(&mut buf).unwrap();
     Ok(buf)
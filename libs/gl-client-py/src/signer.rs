@@ -1,13 +1,72 @@
 use crate::credentials::Credentials;
+use crate::stream::Stream;
 use gl_client::bitcoin::Network;
+use gl_client::signer::{SupervisorStatus, Task, TaskState, TelemetryConfig};
 use log::warn;
 use pyo3::{exceptions::PyValueError, prelude::*};
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
 
 #[pyclass]
 #[derive(Clone)]
 pub struct Signer {
     pub(crate) inner: gl_client::signer::Signer,
+    run: Arc<watch::Sender<Run>>,
+}
+
+/// Whether the signer loop is running, and the error it stopped with.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Run {
+    running: bool,
+    error: Option<String>,
+}
+
+impl Signer {
+    fn started(&self) {
+        self.run.send_replace(Run {
+            running: true,
+            error: None,
+        });
+    }
+
+    /// Run the signer loop on `runtime`, recording the error it
+    /// stopped with.
+    fn run(&self, runtime: tokio::runtime::Runtime, rx: mpsc::Receiver<()>) -> Result<(), String> {
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            runtime.block_on(self.inner.run_forever(rx))
+        }));
+        let error = match res {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(_) => Some("the signer loop panicked".to_string()),
+        };
+        self.run.send_replace(Run {
+            running: false,
+            error: error.clone(),
+        });
+        error.map_or(Ok(()), Err)
+    }
+}
+
+/// The state of the signer: `stopped`, `running`, `reconnecting`
+/// after losing the connection, or `failed` if it gave up.
+fn state(run: &Run, supervisor: &SupervisorStatus) -> (&'static str, Option<String>) {
+    if !run.running {
+        let state = if run.error.is_some() {
+            "failed"
+        } else {
+            "stopped"
+        };
+        return (state, run.error.clone());
+    }
+    let restarting = supervisor.tasks.iter().find(|t| {
+        matches!(t.task, Task::Connection | Task::Stream) && t.state == TaskState::Restarting
+    });
+    match restarting {
+        Some(t) => ("reconnecting", t.last_error.clone()),
+        None => ("running", None),
+    }
 }
 
 #[pymethods]
@@ -34,44 +93,94 @@ impl Signer {
             }
         };
 
-        Ok(Signer { inner })
+        let (run, _) = watch::channel(Run::default());
+        Ok(Signer {
+            inner,
+            run: Arc::new(run),
+        })
     }
 
     fn run_in_thread(&mut self) -> PyResult<SignerHandle> {
         trace!("Starting a new thread for signer");
-        let inner = self.inner.clone();
+        let signer = self.clone();
         let runtime = tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .build()?;
 
         let (tx, rx) = mpsc::channel(1);
 
+        self.started();
         std::thread::spawn(move || {
-            runtime.block_on(async move {
-                if let Err(e) = inner.run_forever(rx).await {
-                    log::error!("Error running signer in thread: {e}")
-                }
-            })
+            if let Err(e) = signer.run(runtime, rx) {
+                log::error!("Error running signer in thread: {e}")
+            }
         });
         Ok(SignerHandle { signal: tx })
     }
 
-    fn run_in_foreground(&self) -> PyResult<()> {
+    fn run_in_foreground(&self, py: Python) -> PyResult<()> {
         trace!("Running signer in foreground thread");
         let (_tx, rx) = mpsc::channel(1);
-        let res = tokio::runtime::Builder::new_current_thread()
+        let runtime = tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .build()
-            .unwrap()
-            .block_on(async { self.inner.run_forever(rx).await });
-
-        match res {
-            Ok(_) => Ok(()),
-            Err(e) => Err(pyo3::exceptions::PyValueError::new_err(format!(
-                "Error running Signer: {}",
-                e
-            ))),
+            .unwrap();
+
+        self.started();
+        py.allow_threads(|| self.run(runtime, rx)).map_err(|e| {
+            pyo3::exceptions::PyValueError::new_err(format!("Error running Signer: {}", e))
+        })
+    }
+
+    /// The state of the signer loop, one of `stopped`, `running`,
+    /// `reconnecting` or `failed`.
+    fn state(&self) -> &'static str {
+        state(&self.run.borrow(), &self.inner.supervisor_status()).0
+    }
+
+    /// The error the signer loop failed with, or the one it is
+    /// reconnecting after.
+    fn last_error(&self) -> Option<String> {
+        state(&self.run.borrow(), &self.inner.supervisor_status()).1
+    }
+
+    /// Call `callback` with the current state and last error, and
+    /// again whenever the state changes.
+    fn on_state_change(&self, callback: PyObject) {
+        let mut run = self.run.subscribe();
+        let mut supervisor = self.inner.subscribe_supervisor();
+        crate::runtime::get_runtime().spawn(async move {
+            let mut last = None;
+            loop {
+                let current = state(&run.borrow_and_update(), &supervisor.borrow_and_update());
+                if last.as_ref() != Some(&current) {
+                    Python::with_gil(|py| {
+                        if let Err(e) = callback.call1(py, current.clone()) {
+                            warn!("Signer state callback failed: {}", e);
+                        }
+                    });
+                    last = Some(current);
+                }
+                tokio::select! {
+                    r = run.changed() => if r.is_err() { break },
+                    r = supervisor.changed() => if r.is_err() { break },
+                }
+            }
+        });
+    }
+
+    /// Count the requests the signer rejects, and report the counts
+    /// to `endpoint` every `interval` seconds, or every hour. Takes
+    /// effect the next time the signer is started.
+    fn enable_telemetry(&mut self, endpoint: &str, interval: Option<f64>) -> PyResult<()> {
+        let mut config = TelemetryConfig::new(endpoint);
+        if let Some(interval) = interval {
+            let interval = Duration::try_from_secs_f64(interval)
+                .map_err(|e| PyValueError::new_err(e.to_string()))?;
+            config = config.with_interval(interval);
         }
+        self.inner = self.inner.clone().with_telemetry(config);
+        Ok(())
     }
 
     fn node_id(&self) -> Vec<u8> {
@@ -93,6 +202,14 @@ impl Signer {
         }
     }
 
+    /// Sign `challenge` with the node key, in the zbase32 format of
+    /// `lightningd`'s `signmessage`.
+    fn sign_ownership_proof(&self, challenge: Vec<u8>) -> PyResult<String> {
+        self.inner
+            .sign_ownership_proof(&challenge)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
     fn version(&self) -> PyResult<&'static str> {
         Ok(self.inner.version())
     }
@@ -102,6 +219,34 @@ impl Signer {
             .create_rune(rune, restrictions)
             .map_err(|e| PyValueError::new_err(e.to_string()))
     }
+
+    fn status(&self) -> &'static str {
+        self.inner.status().current().as_str()
+    }
+
+    /// The events the signer publishes from now on, as JSON.
+    fn events(&self) -> Stream {
+        Stream::events(self.inner.subscribe_events())
+    }
+
+    /// Call `callback` with the current connection status, and again
+    /// whenever it changes.
+    fn on_status(&self, callback: PyObject) {
+        let mut rx = self.inner.status().subscribe();
+        crate::runtime::get_runtime().spawn(async move {
+            loop {
+                let status = rx.borrow_and_update().as_str();
+                Python::with_gil(|py| {
+                    if let Err(e) = callback.call1(py, (status,)) {
+                        warn!("Connection status callback failed: {}", e);
+                    }
+                });
+                if rx.changed().await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
 }
 
 #[pyclass]
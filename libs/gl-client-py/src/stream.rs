@@ -0,0 +1,128 @@
+use crate::errors::status_error;
+use crate::runtime::{exec, get_runtime};
+use gl_client::events::Event;
+use prost::Message;
+use pyo3::exceptions::{PyTimeoutError, PyValueError};
+use pyo3::prelude::*;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tonic::{codec::Streaming, Code, Status};
+
+/// The number of messages read ahead of the consumer. Once the buffer
+/// is full the stream is no longer read, and the server has to wait.
+const BUFFER: usize = 16;
+
+type Item = PyResult<Vec<u8>>;
+
+/// A stream of messages from the node or the signer, read in the
+/// background. Dropping or closing the stream cancels it.
+#[pyclass]
+pub struct Stream {
+    rx: Mutex<mpsc::Receiver<Item>>,
+    pump: JoinHandle<()>,
+}
+
+impl Stream {
+    fn spawn<F, Fut>(pump: F) -> Self
+    where
+        F: FnOnce(mpsc::Sender<Item>) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel(BUFFER);
+        Stream {
+            rx: Mutex::new(rx),
+            pump: get_runtime().spawn(pump(tx)),
+        }
+    }
+
+    /// The encoded messages of a server stream. The stream ends after
+    /// the first error.
+    pub(crate) fn messages<T: Message + 'static>(mut inner: Streaming<T>) -> Self {
+        Stream::spawn(|tx| async move {
+            while let Some(item) = convert_stream_entry(inner.message().await).transpose() {
+                let failed = item.is_err();
+                if tx.send(item).await.is_err() || failed {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// The events published from now on, as JSON. Events are skipped
+    /// if the consumer falls too far behind.
+    pub(crate) fn events(mut rx: broadcast::Receiver<Event>) -> Self {
+        Stream::spawn(|tx| async move {
+            loop {
+                let event = match rx.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        log::warn!("Event stream fell behind, skipped {} events", n);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let item =
+                    serde_json::to_vec(&event).map_err(|e| PyValueError::new_err(e.to_string()));
+                if tx.send(item).await.is_err() {
+                    break;
+                }
+            }
+        })
+    }
+}
+
+#[pymethods]
+impl Stream {
+    /// The next message, or `None` once the stream ended or was
+    /// closed. Raises `TimeoutError` if no message arrived within
+    /// `timeout` seconds.
+    #[pyo3(signature = (timeout = None))]
+    fn next(&self, timeout: Option<f64>) -> PyResult<Option<Vec<u8>>> {
+        exec(async {
+            let mut rx = self.rx.lock().await;
+            let item = match timeout {
+                None => rx.recv().await,
+                Some(t) => tokio::time::timeout(Duration::from_secs_f64(t.max(0.0)), rx.recv())
+                    .await
+                    .map_err(|_| PyTimeoutError::new_err("no message within the timeout"))?,
+            };
+            item.transpose()
+        })
+    }
+
+    /// Cancel the stream. Calls to `next` return the messages that
+    /// were already read, and then `None`.
+    fn close(&self) {
+        self.pump.abort();
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.pump.abort();
+    }
+}
+
+fn convert_stream_entry<T: Message>(r: Result<Option<T>, Status>) -> PyResult<Option<Vec<u8>>> {
+    let res = match r {
+        Ok(Some(v)) => v,
+        Ok(None) => return Ok(None),
+        Err(e) => match e.code() {
+            Code::Unknown => {
+                // Unknown most likely just means we lost the
+                // connection. This is due to a shutdown and shouldn't
+                // be as noisy as other errors.
+                return Ok(None);
+            }
+            _ => {
+                log::warn!("ERROR {:?}", e);
+                return Err(status_error(&e));
+            }
+        },
+    };
+    let mut buf = Vec::with_capacity(res.encoded_len());
+    res.encode(&mut buf).unwrap();
+    Ok(Some(buf))
+}
@@ -0,0 +1,312 @@
+#!/usr/bin/env python3
+"""Generate `glclient/glclient.pyi` from the pyo3 bindings in `src/`.
+
+The stubs are derived from the `#[pyclass]`, `#[pymethods]`,
+`#[pyfunction]` and `create_exception!` items, mapping the Rust
+argument and return types to their Python counterparts. Types that
+can not be derived from Rust, e.g., callbacks taken as `PyObject`,
+are listed in `OVERRIDES`.
+
+Run with `--check` to fail if the checked in stubs are out of date.
+"""
+
+import re
+import sys
+from pathlib import Path
+from typing import Dict, List, Optional, Tuple
+
+ROOT = Path(__file__).parent
+SRC = ROOT / "src"
+STUBS = ROOT / "glclient" / "glclient.pyi"
+
+HEADER = '''"""Stubs for the API exposed by the Rust `gl-client` library.
+
+These refer to the API exposed by the Rust library, not the main
+`glclient` python package. As such these mostly just concern the lower
+level API that shuffles bytes back and forth. The `glclient` python
+package adds a pythonic facade on top of this to improve usability.
+
+This file is generated by `stubgen.py`, do not edit it by hand.
+"""
+
+from typing import Any, Callable, List, Optional, Tuple, Union
+'''
+
+# Python types for arguments (`Class.method.arg`), return values
+# (`Class.method`) and attributes (`Class`) that are untyped in Rust.
+OVERRIDES: Dict[str, str] = {
+    "Signer.on_status.callback": "Callable[[str], None]",
+    "Signer.on_state_change.callback": "Callable[[str, Optional[str]], None]",
+    "Node.export_ledger.rate": "Optional[Union[Rates, Callable[[str, int], float]]]",
+    "LspClient.rpc_call": "bytes",
+    "LspClient.rpc_call_with_json_rpc_id": "bytes",
+}
+
+# Attributes set on the exceptions when they are raised.
+ATTRIBUTES: Dict[str, List[str]] = {
+    "GLRateLimited": ["retry_after: Optional[float]"],
+    "GLRpcError": ["method: str", "code: Optional[int]", "data: Optional[Any]"],
+}
+
+SIMPLE_TYPES = {
+    "str": "str",
+    "String": "str",
+    "bool": "bool",
+    "f32": "float",
+    "f64": "float",
+    "PyBytes": "bytes",
+    "PyObject": "Any",
+    "PyAny": "Any",
+    "PyType": "type",
+    "()": "None",
+}
+SIMPLE_TYPES.update(
+    {t: "int" for t in ["u8", "u16", "u32", "u64", "usize", "i8", "i16", "i32", "i64", "isize"]}
+)
+
+EXCEPTION_BASES = {
+    "PyException": "Exception",
+    "PyValueError": "ValueError",
+    "PyRuntimeError": "RuntimeError",
+}
+
+
+def blank_literals(src: str) -> str:
+    """Blank out strings and comments, keeping offsets, so braces in
+    them do not confuse the matching below.
+    """
+    pattern = re.compile(r'"(?:\\.|[^"\\])*"|//[^\n]*|\'(?:\\.|[^\'\\])\'')
+    return pattern.sub(lambda m: m.group(0)[0] + " " * (len(m.group(0)) - 1), src)
+
+
+def closing(src: str, start: int, open_: str, close: str) -> int:
+    """The index of the bracket closing the one at `start`."""
+    depth = 0
+    for i in range(start, len(src)):
+        if src[i] == open_:
+            depth += 1
+        elif src[i] == close:
+            depth -= 1
+            if depth == 0:
+                return i
+    raise ValueError(f"unbalanced {open_!r} at {start}")
+
+
+def split_top(s: str) -> List[str]:
+    """Split `s` at the commas that are not nested in brackets."""
+    parts, depth, cur = [], 0, ""
+    for c in s:
+        if c in "<([":
+            depth += 1
+        elif c in ">)]":
+            depth -= 1
+        if c == "," and depth == 0:
+            parts.append(cur)
+            cur = ""
+        else:
+            cur += c
+    parts.append(cur)
+    return [p.strip() for p in parts if p.strip()]
+
+
+def py_type(rust: str, self_name: str) -> str:
+    t = re.sub(r"'\w+\s*", "", rust)
+    t = re.sub(r"&\s*(mut\s+)?", "", t).strip()
+    generic = re.fullmatch(r"([\w:]+)<(.*)>", t, re.S)
+    if generic:
+        name, args = generic.group(1).split("::")[-1], split_top(generic.group(2))
+        if name in ("PyResult", "Result"):
+            return py_type(args[0], self_name)
+        if name == "Option":
+            return f"Optional[{py_type(args[0], self_name)}]"
+        if name == "Vec" and args[0] == "u8":
+            return "bytes"
+        if name == "Vec":
+            return f"List[{py_type(args[0], self_name)}]"
+        if name in ("Py", "PyRef", "PyRefMut"):
+            return py_type(args[0], self_name)
+        return "Any"
+    if t == "[u8]":
+        return "bytes"
+    if t.startswith("(") and t != "()":
+        items = ", ".join(py_type(i, self_name) for i in split_top(t[1:-1]))
+        return f"Tuple[{items}]"
+    if t == "Self":
+        return self_name
+    if t in SIMPLE_TYPES:
+        return SIMPLE_TYPES[t]
+    # Other pyclasses are referred to by name, anything else is opaque.
+    return t.split("::")[-1] if t.split("::")[-1] in CLASSES else "Any"
+
+
+def py_default(rust: str) -> str:
+    return {"None": "None", "true": "True", "false": "False"}.get(rust, rust)
+
+
+def attributes_and_docs(src: str, start: int) -> Tuple[List[str], List[str]]:
+    """The attributes and doc comment lines preceding `start`."""
+    attrs: List[str] = []
+    docs: List[str] = []
+    lines = src[:start].splitlines()
+    # The last line is the one containing the item itself.
+    for line in reversed(lines[:-1]):
+        line = line.strip()
+        if line.startswith("#["):
+            attrs.insert(0, line)
+        elif line.startswith("///"):
+            docs.insert(0, line[3:].strip())
+        else:
+            break
+    return attrs, docs
+
+
+def docstring(docs: List[str], indent: str) -> List[str]:
+    if not docs:
+        return []
+    if len(docs) == 1:
+        return [f'{indent}"""{docs[0]}"""']
+    return [f'{indent}"""{docs[0]}'] + [f"{indent}{d}" if d else "" for d in docs[1:]] + [f'{indent}"""']
+
+
+def render_fn(
+    src: str, blank: str, at: int, name: str, owner: Optional[str], indent: str
+) -> Optional[List[str]]:
+    attrs, docs = attributes_and_docs(src, at)
+    key = f"{owner}.{name}" if owner else name
+    is_new = "#[new]" in attrs
+    is_static = "#[staticmethod]" in attrs
+    if name.startswith("_") and not is_new:
+        return None
+
+    i = blank.index("(", at)
+    # Skip generic parameters, e.g., `fn to_bytes<'a>`.
+    if blank[at:i].count("<"):
+        i = blank.index("(", closing(blank, blank.index("<", at), "<", ">"))
+    end = closing(blank, i, "(", ")")
+    body = blank.index("{", end)
+    ret = re.sub(r"\bwhere\b.*", "", blank[end + 1 : body], flags=re.S).strip()
+    ret = ret[2:].strip() if ret.startswith("->") else "()"
+
+    defaults: Dict[str, str] = {}
+    for a in attrs:
+        sig = re.search(r"signature\s*=\s*\(", a)
+        if sig:
+            params_end = closing(a, sig.end() - 1, "(", ")")
+            for p in split_top(a[sig.end() : params_end]):
+                if "=" in p:
+                    k, v = p.split("=", 1)
+                    defaults[k.strip()] = py_default(v.strip())
+    has_signature = any("signature" in a for a in attrs)
+
+    params: List[Tuple[str, str]] = []
+    for p in split_top(src[i + 1 : end]):
+        if ":" not in p:
+            continue  # `self`, `&self` or `&mut self`
+        pname, ptype = (s.strip() for s in p.split(":", 1))
+        pname = pname.replace("mut ", "")
+        if re.match(r"Python\b", ptype) or pname in ("slf", "py"):
+            continue
+        params.append((pname, ptype))
+
+    # Without a signature pyo3 makes trailing `Option` arguments optional.
+    if not has_signature:
+        for pname, ptype in reversed(params):
+            if not ptype.startswith("Option<"):
+                break
+            defaults[pname] = "None"
+
+    args = [] if is_static or owner is None else ["self"]
+    for pname, ptype in params:
+        t = OVERRIDES.get(f"{key}.{pname}", py_type(ptype, owner or ""))
+        args.append(f"{pname}: {t}" + (f" = {defaults[pname]}" if pname in defaults else ""))
+
+    out = [f"{indent}@staticmethod"] if is_static else []
+    if is_new:
+        sig = f"{indent}def __init__({', '.join(args)}) -> None:"
+    else:
+        rtype = OVERRIDES.get(key, py_type(ret, owner or ""))
+        sig = f"{indent}def {name}({', '.join(args)}) -> {rtype}:"
+    if len(sig) > 79:
+        inner = "".join(f"{indent}    {a},\n" for a in args)
+        sig = sig.split("(", 1)[0] + "(\n" + inner + indent + ")" + sig.rsplit(")", 1)[1]
+    doc = docstring(docs, indent + "    ")
+    return out + ([sig] + doc + [f"{indent}    ..."] if doc else [sig + " ..."])
+
+
+CLASSES: Dict[str, List[str]] = {}
+
+
+def generate() -> str:
+    CLASSES.clear()
+    sources = [(p, p.read_text()) for p in sorted(SRC.glob("*.rs"))]
+    exceptions: List[str] = []
+    functions: List[List[str]] = []
+    methods: Dict[str, List[List[str]]] = {}
+    docs: Dict[str, List[str]] = {}
+
+    for _, src in sources:
+        for m in re.finditer(r"#\[pyclass[^\]]*\]", src):
+            decl = re.search(r"struct\s+(\w+)", src[m.end():])
+            assert decl is not None
+            named = re.search(r'name\s*=\s*"(\w+)"', m.group(0))
+            name = named.group(1) if named else decl.group(1)
+            CLASSES[name] = []
+            docs[name] = attributes_and_docs(src, m.start() + 1)[1]
+        for m in re.finditer(
+            r'create_exception!\(\s*\w+,\s*(\w+),\s*(\w+),\s*"((?:\\.|[^"\\])*)"\s*\)', src
+        ):
+            name, base, doc = m.groups()
+            base = EXCEPTION_BASES.get(base, base)
+            lines = [f"class {name}({base}):", f'    """{doc}"""']
+            lines += [f"    {a}" for a in ATTRIBUTES.get(name, [])]
+            exceptions.append("\n".join(lines))
+
+    for _, src in sources:
+        blank = blank_literals(src)
+        for m in re.finditer(r"#\[pymethods\]\s*impl\s+(\w+)\s*\{", blank):
+            owner = m.group(1)
+            end = closing(blank, m.end() - 1, "{", "}")
+            depth = 0
+            for fn in re.finditer(r"\bfn\s+(\w+)|[{}]", blank[m.end() : end]):
+                if fn.group(0) in "{}":
+                    depth += 1 if fn.group(0) == "{" else -1
+                    continue
+                if depth == 0:
+                    at = m.end() + fn.start()
+                    r = render_fn(src, blank, at, fn.group(1), owner, "    ")
+                    if r:
+                        methods.setdefault(owner, []).append(r)
+        for m in re.finditer(r"#\[pyfunction\]", blank):
+            fn = re.compile(r"\bfn\s+(\w+)").search(blank, m.end())
+            assert fn is not None
+            r = render_fn(src, blank, fn.start(), fn.group(1), None, "")
+            if r:
+                functions.append(r)
+
+    out = [HEADER, "\n\n"]
+    out += [e + "\n\n\n" for e in exceptions]
+    for name in CLASSES:
+        lines = [f"class {name}:"] + docstring(docs[name], "    ")
+        for method in methods.get(name, []):
+            lines += method
+        if len(lines) == 1:
+            lines.append("    ...")
+        out.append("\n".join(lines) + "\n\n\n")
+    for f in functions:
+        out.append("\n".join(f) + "\n\n")
+    return "".join(out).rstrip() + "\n"
+
+
+def main(argv: List[str]) -> int:
+    stubs = generate()
+    if "--check" in argv:
+        if STUBS.read_text() != stubs:
+            print(f"{STUBS} is out of date, run `python stubgen.py`", file=sys.stderr)
+            return 1
+        return 0
+    STUBS.write_text(stubs)
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(main(sys.argv[1:]))
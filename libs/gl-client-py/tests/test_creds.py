@@ -28,3 +28,37 @@ def test_upgrade_credentials(scheduler, sclient, signer):
 
     assert c
     assert type(c.to_bytes()) is bytes
+
+
+def test_with_rune():
+    creds = Credentials.from_parts(b"cert", b"key", "rune")
+    narrowed = creds.with_rune("narrow")
+    assert b"narrow" in narrowed.to_bytes()
+    assert b"cert" in narrowed.to_bytes()
+
+    with pytest.raises(ValueError):
+        Credentials().with_rune("narrow")
+
+
+def test_derive_session():
+    from test_runes import RUNE
+
+    creds = Credentials.from_parts(b"cert", b"key", RUNE)
+    session = creds.derive_session(600, ["readonly"])
+    assert b"cert" in session.to_bytes()
+    assert session.to_bytes() != creds.to_bytes()
+
+    with pytest.raises(ValueError):
+        creds.derive_session(600, ["unknown"])
+
+
+def test_pickle(creds):
+    import pickle
+
+    device = Credentials.from_parts(b"cert", b"key", "rune")
+    assert pickle.loads(pickle.dumps(device)).to_bytes() == device.to_bytes()
+
+    nobody = pickle.loads(pickle.dumps(creds))
+    with pytest.raises(ValueError):
+        nobody.to_bytes()
+    nobody.ensure_nobody()
@@ -0,0 +1,23 @@
+from glclient import (
+    GLError,
+    GLConnectionError,
+    GLRateLimited,
+    GLAuthError,
+    GLSignerRejected,
+    GLRpcError,
+    GLVersionNotOffered,
+)
+
+
+def test_hierarchy():
+    for e in [
+        GLConnectionError,
+        GLRateLimited,
+        GLAuthError,
+        GLSignerRejected,
+        GLRpcError,
+        GLVersionNotOffered,
+    ]:
+        assert issubclass(e, GLError)
+    # Code catching `ValueError`, which was raised before, still works.
+    assert issubclass(GLError, ValueError)
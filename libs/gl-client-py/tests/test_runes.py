@@ -0,0 +1,16 @@
+from glclient import carve_rune, decode_rune
+
+# A master rune with unique id 0, restricted to a pubkey.
+RUNE = "q3gAOP3JVkuen3qOh2G3LMU9TbHpXQS2VXfecJmlZY89MC1nbDAmcHVia2V5PTA0OGI0ZWZhNDZkNTZmMmUxM2RmOTdjOGFmNzJiZjYzZWEwNDgzODFlMTdkMTRhOGVkMThlNDVhMzFkNDIzMmNlMzE3OWE4NjE2ZTU2ODUxOTc5MjcxOTZlMTI2YjU0YjhhMmU5NzAwNWJiNzY2YTYzM2M1ODc0M2RjMGU3ZDZhZGY="
+
+
+def test_carve_rune():
+    carved = carve_rune(RUNE, ["pay|readonly", "hours(9-17)"], commando=True)
+    info = decode_rune(carved)
+    assert info["unique_id"] == "0"
+    assert info["restrictions"][-3:] == [
+        ["method=pay", "method^get", "method^list"],
+        ["hour>8"],
+        ["hour<17"],
+    ]
+    assert decode_rune(RUNE)["authcode"] != info["authcode"]
@@ -75,3 +75,13 @@ def test_register_with_invite_code(scheduler, sclient, signer):
     sclient.register(signer, "some-invite-code")
     assert scheduler.received_invite_code == "some-invite-code"
 
+
+
+def test_pickle(sclient, signer):
+    """A scheduler client can be passed to a worker process."""
+    import pickle
+
+    s = pickle.loads(pickle.dumps(sclient))
+    assert s.network == sclient.network
+    res = s.register(signer)
+    assert res.creds
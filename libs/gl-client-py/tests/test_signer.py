@@ -0,0 +1,29 @@
+import time
+from fixtures import *
+
+
+def wait_for(f, timeout=30):
+    start = time.time()
+    while not f():
+        assert time.time() - start < timeout, "timed out"
+        time.sleep(0.1)
+
+
+def test_signer_state(signer):
+    assert signer.state() == "stopped"
+    assert signer.last_error() is None
+
+    states = []
+    signer.on_state_change(lambda state, error: states.append(state))
+    wait_for(lambda: states == ["stopped"])
+
+    # The node is not registered, so the signer keeps reconnecting.
+    signer.run_in_thread()
+    wait_for(lambda: signer.state() == "reconnecting")
+    assert signer.last_error() is not None
+
+    signer.shutdown()
+    wait_for(lambda: signer.state() == "stopped")
+    assert signer.last_error() is None
+    wait_for(lambda: states[-1] == "stopped")
+    assert "reconnecting" in states
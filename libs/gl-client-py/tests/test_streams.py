@@ -0,0 +1,31 @@
+import asyncio
+from fixtures import *
+
+
+def test_event_stream_close(signer):
+    events = signer.events()
+    with pytest.raises(TimeoutError):
+        events.next(timeout=0.1)
+
+    events.close()
+    assert events.next() is None
+    assert list(events) == []
+
+    with signer.events() as events:
+        pass
+    assert events.next(timeout=1) is None
+
+
+def test_event_stream_cancel(signer):
+    async def consume():
+        async for _ in signer.events():
+            pass
+
+    async def main():
+        task = asyncio.ensure_future(consume())
+        await asyncio.sleep(0.1)
+        task.cancel()
+        with pytest.raises(asyncio.CancelledError):
+            await task
+
+    asyncio.run(asyncio.wait_for(main(), timeout=5))
@@ -0,0 +1,38 @@
+import ast
+import sys
+from pathlib import Path
+
+import glclient.glclient as native
+
+ROOT = Path(__file__).parent.parent
+sys.path.insert(0, str(ROOT))
+import stubgen  # noqa: E402
+
+
+def test_stubs_up_to_date():
+    """The checked in stubs match the bindings, see `stubgen.py`."""
+    assert stubgen.STUBS.read_text() == stubgen.generate()
+
+
+def test_stubs_complete():
+    """Every class and method of the native module has a stub."""
+    tree = ast.parse(stubgen.STUBS.read_text())
+    stubs = {
+        n.name: {m.name for m in n.body if isinstance(m, ast.FunctionDef)}
+        for n in tree.body
+        if isinstance(n, ast.ClassDef)
+    }
+    functions = {n.name for n in tree.body if isinstance(n, ast.FunctionDef)}
+
+    for name in dir(native):
+        if name.startswith("_"):
+            continue
+        obj = getattr(native, name)
+        if not isinstance(obj, type):
+            assert name in functions
+            continue
+        assert name in stubs
+        if issubclass(obj, BaseException):
+            continue
+        methods = {m for m in vars(obj) if not m.startswith("_")}
+        assert methods <= stubs[name], f"missing stubs for {name}"
@@ -1,5 +1,6 @@
+use std::collections::HashSet;
 use std::env::var;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 fn main() {
@@ -61,18 +62,159 @@ fn main() {
     println!("cargo:rerun-if-env-changed=GL_CUSTOM_NOBODY_CERT");
     println!("cargo:rerun-if-env-changed=GL_CUSTOM_NOBODY_KEY");
 
-    let builder = tonic_build::configure();
+    // Mobile apps often only call a handful of `cln.Node` methods, but
+    // the full model adds megabytes to the binary. Setting
+    // `GL_CLN_METHODS` to a comma separated list of methods,
+    // e.g. "Invoice,Pay,ListInvoices", generates `pb::cln` with only
+    // those methods and the messages they use, instead of using the
+    // model of `cln-grpc`. The trimmed model is generated from the
+    // same `.proto` files as `cln-grpc`'s, so the messages match.
+    println!("cargo:rerun-if-env-changed=GL_CLN_METHODS");
+    println!("cargo:rustc-check-cfg=cfg(cln_trimmed)");
+    // Set by `cargo fuzz`, see `fuzz/`.
+    println!("cargo:rustc-check-cfg=cfg(fuzzing)");
+    let mut includes = vec![PathBuf::from(".resources/proto")];
+    let mut protos = vec![
+        PathBuf::from(".resources/proto/glclient/greenlight.proto"),
+        PathBuf::from(".resources/proto/glclient/scheduler.proto"),
+    ];
+    if let Ok(methods) = var("GL_CLN_METHODS") {
+        let cln_protos = cln_grpc_protos(&manifest_dir);
+        let source = std::fs::read_to_string(cln_protos.join("node.proto")).unwrap();
+        let dir = Path::new(&var("OUT_DIR").unwrap()).join("cln-trimmed");
+        std::fs::create_dir_all(&dir).unwrap();
+        let node_proto = dir.join("node.proto");
+        std::fs::write(&node_proto, trim_cln_proto(&source, &methods)).unwrap();
+        // The trimmed file must come first, or protoc complains that
+        // it is shadowed by the full one.
+        includes.insert(0, cln_protos);
+        includes.insert(0, dir);
+        protos.push(node_proto);
+        println!("cargo:rustc-cfg=cln_trimmed");
+    }
+
+    let mut builder = tonic_build::configure();
+    // The serde impls are large, and most applications never
+    // serialize the messages.
+    if var("CARGO_FEATURE_MODEL_SERDE").is_ok() {
+        builder = builder.type_attribute(".", "#[derive(serde::Serialize,serde::Deserialize)]");
+    }
 
     builder
-        .type_attribute(".", "#[derive(serde::Serialize,serde::Deserialize)]")
         .protoc_arg("--experimental_allow_proto3_optional")
-        .compile(
-            &[
-                ".resources/proto/glclient/greenlight.proto",
-                ".resources/proto/glclient/scheduler.proto",
-                ".resources/proto/node.proto",
-            ],
-            &[".resources/proto"],
-        )
+        .compile(&protos, &includes)
+        .unwrap();
+}
+
+/// The directory of the `.proto` files the `cln-grpc` dependency
+/// generates its model from.
+fn cln_grpc_protos(manifest_dir: &str) -> PathBuf {
+    let target = var("TARGET").unwrap();
+    let output = Command::new(var("CARGO").unwrap())
+        .args(["metadata", "--format-version", "1", "--offline"])
+        .args(["--filter-platform", &target])
+        .arg("--manifest-path")
+        .arg(Path::new(manifest_dir).join("Cargo.toml"))
+        .output()
         .unwrap();
+    if !output.status.success() {
+        panic!(
+            "Could not locate cln-grpc: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    let metadata: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let manifest = metadata["packages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|p| p["name"] == "cln-grpc")
+        .and_then(|p| p["manifest_path"].as_str())
+        .expect("cln-grpc is a dependency");
+    let dir = Path::new(manifest).parent().unwrap().join("proto");
+    println!("cargo:rerun-if-changed={}", dir.display());
+    dir
+}
+
+/// Remove the methods of the `cln.Node` service that are not listed
+/// in `methods`, and the messages no remaining method uses. `Getinfo`
+/// is always kept, the scheduler uses it to check that the node is
+/// up.
+fn trim_cln_proto(source: &str, methods: &str) -> String {
+    let mut methods: HashSet<&str> = methods
+        .split(',')
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .collect();
+    methods.insert("Getinfo");
+
+    // The file is generated, with the service and the messages at the
+    // top level, each closed by a `}` at the start of a line.
+    let mut header = vec![];
+    let mut rpcs = vec![];
+    let mut messages: Vec<(&str, Vec<&str>)> = vec![];
+    let mut lines = source.lines();
+    while let Some(line) = lines.next() {
+        if line.starts_with("service Node") {
+            for line in lines.by_ref().take_while(|l| *l != "}") {
+                rpcs.push(line);
+            }
+        } else if let Some(decl) = line.strip_prefix("message ") {
+            let name = decl.trim_end_matches('{').trim();
+            let mut body = vec![line];
+            body.extend(lines.by_ref().take_while(|l| *l != "}"));
+            messages.push((name, body));
+        } else if rpcs.is_empty() {
+            header.push(line);
+        }
+    }
+
+    let idents = |line: &str| -> Vec<String> {
+        line.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+
+    let mut kept = vec![];
+    let mut used = vec![];
+    for rpc in rpcs {
+        let name = idents(rpc).into_iter().nth(1).unwrap_or_default();
+        if methods.remove(name.as_str()) {
+            kept.push(rpc);
+            used.extend(idents(rpc));
+        }
+    }
+    if !methods.is_empty() {
+        panic!("Unknown methods in GL_CLN_METHODS: {:?}", methods);
+    }
+
+    // Keep the messages the kept methods use, directly or through the
+    // fields of other kept messages.
+    let mut keep: HashSet<&str> = HashSet::new();
+    while let Some(ident) = used.pop() {
+        if let Some((name, body)) = messages.iter().find(|(n, _)| *n == ident) {
+            if keep.insert(*name) {
+                for line in &body[1..] {
+                    used.extend(idents(line));
+                }
+            }
+        }
+    }
+
+    let mut out = header.join("\n");
+    out.push_str("\nservice Node {\n");
+    for rpc in kept {
+        out.push_str(rpc);
+        out.push('\n');
+    }
+    out.push_str("}\n");
+    for (name, body) in &messages {
+        if keep.contains(name) {
+            out.push('\n');
+            out.push_str(&body.join("\n"));
+            out.push_str("\n}\n");
+        }
+    }
+    out
 }
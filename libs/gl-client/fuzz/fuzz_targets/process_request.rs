@@ -0,0 +1,12 @@
+#![no_main]
+use gl_client::signer::{fuzz, Signer};
+use libfuzzer_sys::fuzz_target;
+use once_cell::sync::Lazy;
+use tokio::runtime::{Builder, Runtime};
+
+static RUNTIME: Lazy<Runtime> = Lazy::new(|| Builder::new_current_thread().build().unwrap());
+static SIGNER: Lazy<Signer> = Lazy::new(fuzz::signer);
+
+fuzz_target!(|data: &[u8]| {
+    RUNTIME.block_on(fuzz::process_request(&SIGNER, data));
+});
@@ -0,0 +1,308 @@
+//! Conversions between the amount types of the generated models.
+//!
+//! The `cln` models carry amounts as [`Amount`], [`AmountOrAll`] and
+//! [`AmountOrAny`] messages, and some fields as plain `u64`s, in
+//! millisatoshis or satoshis depending on the field. [`Msat`] is an
+//! amount in millisatoshis that converts from and to all of them,
+//! checks its arithmetic, and serializes as a number. [`AmountExt`]
+//! adds constructors to the generated types directly:
+//!
+//! ```
+//! use gl_client::amount::{AmountExt, AmountOrAllExt, Msat};
+//! use gl_client::pb::cln::{Amount, AmountOrAll};
+//!
+//! let fee = Amount::from_sat(1)?;
+//! let total = Msat::from(fee) + Msat::from_sat(2)?;
+//! assert_eq!(total.to_sat(), 3);
+//! assert_eq!(AmountOrAll::all().msat(), None);
+//! # Ok::<(), gl_client::amount::AmountOverflow>(())
+//! ```
+use crate::pb::cln::{amount_or_all, amount_or_any, Amount, AmountOrAll, AmountOrAny};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, AddAssign, Sub};
+
+/// An amount in millisatoshis.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(transparent)]
+pub struct Msat(pub u64);
+
+impl Msat {
+    pub const ZERO: Msat = Msat(0);
+
+    /// Fails if the amount does not fit into millisatoshis.
+    pub fn from_sat(sat: u64) -> Result<Msat, AmountOverflow> {
+        sat.checked_mul(1000).map(Msat).ok_or(AmountOverflow)
+    }
+
+    pub fn to_msat(self) -> u64 {
+        self.0
+    }
+
+    /// The amount in satoshis, rounded down.
+    pub fn to_sat(self) -> u64 {
+        self.0 / 1000
+    }
+
+    pub fn checked_add(self, other: Msat) -> Option<Msat> {
+        self.0.checked_add(other.0).map(Msat)
+    }
+
+    pub fn checked_sub(self, other: Msat) -> Option<Msat> {
+        self.0.checked_sub(other.0).map(Msat)
+    }
+
+    pub fn saturating_sub(self, other: Msat) -> Msat {
+        Msat(self.0.saturating_sub(other.0))
+    }
+}
+
+/// Panics on overflow, use [`Msat::checked_add`] for untrusted
+/// amounts.
+impl Add for Msat {
+    type Output = Msat;
+    fn add(self, other: Msat) -> Msat {
+        self.checked_add(other).expect("amount overflow")
+    }
+}
+
+impl AddAssign for Msat {
+    fn add_assign(&mut self, other: Msat) {
+        *self = *self + other;
+    }
+}
+
+/// Panics on underflow, use [`Msat::checked_sub`] or
+/// [`Msat::saturating_sub`] for untrusted amounts.
+impl Sub for Msat {
+    type Output = Msat;
+    fn sub(self, other: Msat) -> Msat {
+        self.checked_sub(other).expect("amount underflow")
+    }
+}
+
+impl std::iter::Sum for Msat {
+    fn sum<I: Iterator<Item = Msat>>(iter: I) -> Msat {
+        iter.fold(Msat::ZERO, Add::add)
+    }
+}
+
+impl fmt::Display for Msat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}msat", self.0)
+    }
+}
+
+/// Accepts a number, or a string with an `msat` suffix as
+/// `lightningd` used to return.
+impl<'de> Deserialize<'de> for Msat {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Msat, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Num(u64),
+            Str(String),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Num(n) => Ok(Msat(n)),
+            Repr::Str(s) => s
+                .strip_suffix("msat")
+                .unwrap_or(&s)
+                .parse()
+                .map(Msat)
+                .map_err(|_| de::Error::custom(format!("invalid msat amount: {}", s))),
+        }
+    }
+}
+
+impl From<u64> for Msat {
+    fn from(msat: u64) -> Msat {
+        Msat(msat)
+    }
+}
+
+impl From<Msat> for u64 {
+    fn from(m: Msat) -> u64 {
+        m.0
+    }
+}
+
+impl From<Amount> for Msat {
+    fn from(a: Amount) -> Msat {
+        Msat(a.msat)
+    }
+}
+
+impl From<&Amount> for Msat {
+    fn from(a: &Amount) -> Msat {
+        Msat(a.msat)
+    }
+}
+
+/// Unset amounts, as in optional fields of the models, are zero.
+impl From<&Option<Amount>> for Msat {
+    fn from(a: &Option<Amount>) -> Msat {
+        a.as_ref().map(Msat::from).unwrap_or_default()
+    }
+}
+
+impl From<Msat> for Amount {
+    fn from(m: Msat) -> Amount {
+        Amount { msat: m.0 }
+    }
+}
+
+impl From<Msat> for AmountOrAll {
+    fn from(m: Msat) -> AmountOrAll {
+        AmountOrAll::from_msat(m.0)
+    }
+}
+
+impl From<Msat> for AmountOrAny {
+    fn from(m: Msat) -> AmountOrAny {
+        AmountOrAny::from_msat(m.0)
+    }
+}
+
+/// An amount in satoshis that is too large to be expressed in
+/// millisatoshis.
+#[derive(thiserror::Error, Clone, Copy, Debug, PartialEq, Eq)]
+#[error("amount overflow")]
+pub struct AmountOverflow;
+
+/// An [`AmountOrAll`] or [`AmountOrAny`] that is not a specific
+/// amount.
+#[derive(thiserror::Error, Clone, Copy, Debug, PartialEq, Eq)]
+#[error("not a specific amount")]
+pub struct NotAnAmount;
+
+impl TryFrom<&AmountOrAll> for Msat {
+    type Error = NotAnAmount;
+    fn try_from(a: &AmountOrAll) -> Result<Msat, NotAnAmount> {
+        a.msat().map(Msat).ok_or(NotAnAmount)
+    }
+}
+
+impl TryFrom<&AmountOrAny> for Msat {
+    type Error = NotAnAmount;
+    fn try_from(a: &AmountOrAny) -> Result<Msat, NotAnAmount> {
+        a.msat().map(Msat).ok_or(NotAnAmount)
+    }
+}
+
+/// Constructors and accessors for the generated amount types.
+pub trait AmountExt: Sized {
+    fn from_msat(msat: u64) -> Self;
+
+    /// Fails if the amount does not fit into millisatoshis.
+    fn from_sat(sat: u64) -> Result<Self, AmountOverflow> {
+        Msat::from_sat(sat).map(|m| Self::from_msat(m.0))
+    }
+
+    /// The amount in millisatoshis, `None` for `all` and `any`.
+    fn msat(&self) -> Option<u64>;
+}
+
+impl AmountExt for Amount {
+    fn from_msat(msat: u64) -> Self {
+        Amount { msat }
+    }
+
+    fn msat(&self) -> Option<u64> {
+        Some(self.msat)
+    }
+}
+
+impl AmountExt for AmountOrAll {
+    fn from_msat(msat: u64) -> Self {
+        AmountOrAll {
+            value: Some(amount_or_all::Value::Amount(Amount { msat })),
+        }
+    }
+
+    fn msat(&self) -> Option<u64> {
+        match &self.value {
+            Some(amount_or_all::Value::Amount(a)) => Some(a.msat),
+            _ => None,
+        }
+    }
+}
+
+impl AmountExt for AmountOrAny {
+    fn from_msat(msat: u64) -> Self {
+        AmountOrAny {
+            value: Some(amount_or_any::Value::Amount(Amount { msat })),
+        }
+    }
+
+    fn msat(&self) -> Option<u64> {
+        match &self.value {
+            Some(amount_or_any::Value::Amount(a)) => Some(a.msat),
+            _ => None,
+        }
+    }
+}
+
+/// Constructors for the non-amount values of [`AmountOrAll`] and
+/// [`AmountOrAny`].
+pub trait AmountOrAllExt {
+    /// All available funds.
+    fn all() -> Self;
+}
+
+impl AmountOrAllExt for AmountOrAll {
+    fn all() -> Self {
+        AmountOrAll {
+            value: Some(amount_or_all::Value::All(true)),
+        }
+    }
+}
+
+pub trait AmountOrAnyExt {
+    /// Any amount, chosen by the payer.
+    fn any() -> Self;
+}
+
+impl AmountOrAnyExt for AmountOrAny {
+    fn any() -> Self {
+        AmountOrAny {
+            value: Some(amount_or_any::Value::Any(true)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let a = Amount::from_sat(2).unwrap();
+        assert_eq!(a.msat, 2000);
+        assert_eq!(Msat::from_sat(u64::MAX), Err(AmountOverflow));
+        assert_eq!(AmountOrAll::from_sat(u64::MAX / 999), Err(AmountOverflow));
+        assert_eq!(Msat::from(&Some(a.clone())), Msat(2000));
+        assert_eq!(Msat::from(&None), Msat::ZERO);
+
+        let all = AmountOrAll::all();
+        assert_eq!(Msat::try_from(&all), Err(NotAnAmount));
+        let any = AmountOrAny::from(Msat(1500));
+        assert_eq!(Msat::try_from(&any), Ok(Msat(1500)));
+        assert_eq!(AmountOrAny::any().msat(), None);
+
+        assert_eq!(Msat(1500).to_sat(), 1);
+        assert_eq!(Msat(1).checked_sub(Msat(2)), None);
+        assert_eq!(vec![Msat(1), Msat(2)].into_iter().sum::<Msat>(), Msat(3));
+    }
+
+    #[test]
+    fn test_serde() {
+        let m: Msat = serde_json::from_str("1000").unwrap();
+        assert_eq!(m, Msat(1000));
+        let m: Msat = serde_json::from_str("\"1000msat\"").unwrap();
+        assert_eq!(m, Msat(1000));
+        assert!(serde_json::from_str::<Msat>("\"1btc\"").is_err());
+        assert_eq!(serde_json::to_string(&Msat(5)).unwrap(), "5");
+    }
+}
@@ -0,0 +1,131 @@
+//! Experimental: custom routing policies with the `askrene` plugin.
+//!
+//! `askrene` computes routes over the node's view of the gossip,
+//! modified by layers. A [`Layer`] can exclude nodes and channels,
+//! e.g., peers the user does not want to route through, and is
+//! applied by naming it in [`get_routes`]. The plugin is still under
+//! development upstream, so this API may change or break with new
+//! `lightningd` versions.
+use crate::node::Client;
+use crate::pb::{
+    AskreneDisableChannelRequest, AskreneDisableNodeRequest, AskreneLayerRequest, AskreneRoute,
+    GetRoutesRequest,
+};
+use anyhow::Result;
+
+/// The default CLTV delta of the last hop.
+pub const DEFAULT_FINAL_CLTV: u32 = 18;
+
+/// Layers `askrene` maintains itself: the balances of the node's
+/// own channels, and no fees on channels from the source.
+pub const AUTO_LAYERS: [&str; 2] = ["auto.localchans", "auto.sourcefree"];
+
+/// A named set of changes to the gossip.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layer {
+    pub name: String,
+}
+
+impl Layer {
+    /// Create the layer `name` on the node.
+    pub async fn create(node: &mut Client, name: &str) -> Result<Layer> {
+        node.askrene_create_layer(AskreneLayerRequest {
+            layer: name.to_string(),
+        })
+        .await?;
+        Ok(Layer {
+            name: name.to_string(),
+        })
+    }
+
+    /// Refer to an existing layer.
+    pub fn existing(name: &str) -> Layer {
+        Layer {
+            name: name.to_string(),
+        }
+    }
+
+    /// Do not route through `node_id`.
+    pub async fn disable_node(&self, node: &mut Client, node_id: &[u8]) -> Result<()> {
+        node.askrene_disable_node(AskreneDisableNodeRequest {
+            layer: self.name.clone(),
+            node: node_id.to_vec(),
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Do not route through the channel `short_channel_id` in
+    /// `direction`, 0 from the node with the lower id, 1 otherwise.
+    pub async fn disable_channel(
+        &self,
+        node: &mut Client,
+        short_channel_id: &str,
+        direction: u8,
+    ) -> Result<()> {
+        node.askrene_disable_channel(AskreneDisableChannelRequest {
+            layer: self.name.clone(),
+            short_channel_id_dir: scid_dir(short_channel_id, direction),
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Delete the layer from the node.
+    pub async fn remove(self, node: &mut Client) -> Result<()> {
+        node.askrene_remove_layer(AskreneLayerRequest { layer: self.name })
+            .await?;
+        Ok(())
+    }
+}
+
+/// Routes found by [`get_routes`], which together deliver the amount.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Routes {
+    /// The probability that all routes succeed, in parts per million.
+    pub probability_ppm: u64,
+    pub routes: Vec<AskreneRoute>,
+}
+
+/// Find routes delivering `amount_msat` from `source` to
+/// `destination` for at most `maxfee_msat` in fees, applying `layers`
+/// in order.
+pub async fn get_routes(
+    node: &mut Client,
+    source: &[u8],
+    destination: &[u8],
+    amount_msat: u64,
+    maxfee_msat: u64,
+    layers: &[&str],
+) -> Result<Routes> {
+    let res = node
+        .get_routes(GetRoutesRequest {
+            source: source.to_vec(),
+            destination: destination.to_vec(),
+            amount_msat,
+            layers: layers.iter().map(|l| l.to_string()).collect(),
+            maxfee_msat,
+            final_cltv: DEFAULT_FINAL_CLTV,
+        })
+        .await?
+        .into_inner();
+    Ok(Routes {
+        probability_ppm: res.probability_ppm,
+        routes: res.routes,
+    })
+}
+
+fn scid_dir(short_channel_id: &str, direction: u8) -> String {
+    format!("{}/{}", short_channel_id, direction & 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scid_dir() {
+        assert_eq!(scid_dir("1x2x3", 0), "1x2x3/0");
+        assert_eq!(scid_dir("1x2x3", 1), "1x2x3/1");
+    }
+}
@@ -0,0 +1,109 @@
+//! Run the same client code against Greenlight or a local node.
+//!
+//! Applications talk to their node with a [`ClnClient`], whether the
+//! node runs on Greenlight or not. During development it is often
+//! quicker to point the client at a Core Lightning node running
+//! locally, e.g., on regtest, with the `cln-grpc` plugin enabled, and
+//! only switch to Greenlight once things work. The [`Backend`] in the
+//! [`Config`] decides where [`Backend::connect`] goes:
+//!
+//! ```toml
+//! [backend]
+//! type = "local"
+//! grpc_uri = "https://localhost:9736"
+//! certs = "/home/dev/.lightning/regtest"
+//! ```
+//!
+//! Local nodes do not need a scheduler, credentials or a signer, the
+//! node holds its own keys. Greenlight specific calls, such as
+//! streaming the logs or the signer requests, are not available.
+use crate::config::Config;
+use crate::credentials::{Device, RuneProvider, TlsConfigProvider};
+use crate::node::{ClnClient, Node};
+use crate::scheduler::Scheduler;
+use crate::tls::TlsConfig;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// The port the `cln-grpc` plugin listens on by default.
+pub const DEFAULT_LOCAL_GRPC_URI: &str = "https://localhost:9736";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Backend {
+    /// Schedule the node on Greenlight, with the scheduler and the
+    /// credentials of the [`Config`].
+    #[default]
+    Greenlight,
+    /// Connect to the `cln-grpc` plugin of a locally running node.
+    Local {
+        #[serde(default = "default_local_grpc_uri")]
+        grpc_uri: String,
+        /// The directory holding the `ca.pem`, `client.pem` and
+        /// `client-key.pem` the plugin generated, i.e., the network
+        /// directory of the node.
+        certs: PathBuf,
+    },
+}
+
+fn default_local_grpc_uri() -> String {
+    DEFAULT_LOCAL_GRPC_URI.to_string()
+}
+
+impl Backend {
+    pub fn is_local(&self) -> bool {
+        matches!(self, Backend::Local { .. })
+    }
+
+    /// Connect to the node of this backend, scheduling it first if it
+    /// runs on Greenlight.
+    pub async fn connect(&self, config: &Config) -> Result<ClnClient> {
+        match self {
+            Backend::Greenlight => {
+                let creds = Device::from_path(&config.creds);
+                Scheduler::with_config(config, creds).await?.node().await
+            }
+            Backend::Local { grpc_uri, certs } => {
+                let creds = LocalCreds::load(certs)?;
+                Node::new(vec![], creds)?
+                    .with_compression(config.compression)
+                    .connect(grpc_uri.clone())
+                    .await
+            }
+        }
+    }
+}
+
+/// The client identity generated by the `cln-grpc` plugin.
+struct LocalCreds {
+    tls: TlsConfig,
+}
+
+impl LocalCreds {
+    fn load(dir: &Path) -> Result<Self> {
+        let read = |name: &str| {
+            let path = dir.join(name);
+            std::fs::read(&path).with_context(|| format!("reading {}", path.display()))
+        };
+        let tls = TlsConfig::with(
+            read("client.pem")?,
+            read("client-key.pem")?,
+            read("ca.pem")?,
+        );
+        Ok(LocalCreds { tls })
+    }
+}
+
+impl TlsConfigProvider for LocalCreds {
+    fn tls_config(&self) -> TlsConfig {
+        self.tls.clone()
+    }
+}
+
+impl RuneProvider for LocalCreds {
+    /// The plugin authenticates clients by their certificate alone.
+    fn rune(&self) -> String {
+        String::new()
+    }
+}
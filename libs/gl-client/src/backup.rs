@@ -0,0 +1,180 @@
+//! Verify that a locally stored static channel backup (SCB) still
+//! covers all of the node's channels.
+//!
+//! The SCB returned by `staticbackup` only contains the channels that
+//! existed at the time it was taken. A backup that is not refreshed
+//! after opening a new channel silently fails to recover that
+//! channel. [`verify_backup`] detects this before the backup is
+//! needed.
+//!
+//! Once the node lost its channel state, [`recover_channels`] and
+//! [`emergency_recover`] have it ask the peers of the lost channels
+//! to close them, returning the funds onchain.
+use crate::channels::ChannelState;
+use crate::events::{Event, EventBus};
+use crate::node::{Client, ClnClient};
+use crate::pb::cln::{ListpeerchannelsRequest, ListpeerchannelsResponse};
+use crate::pb::{EmergencyRecoverRequest, RecoverChannelRequest};
+use std::collections::HashSet;
+use thiserror::Error;
+
+/// Each SCB entry starts with a `u64` id, followed by the 32 byte
+/// channel id.
+const SCB_CHANNEL_ID_RANGE: std::ops::Range<usize> = 8..40;
+
+#[derive(Error, Debug)]
+pub enum BackupAlert {
+    #[error("static channel backup is missing {} channel(s)", missing_channels.len())]
+    Stale { missing_channels: Vec<Vec<u8>> },
+
+    #[error("static channel backup entry {0} is malformed")]
+    Malformed(usize),
+
+    #[error("could not fetch the channels from the node: {0}")]
+    Node(#[from] Box<tonic::Status>),
+}
+
+/// Extract the channel ids from the entries of a static channel
+/// backup, as returned by `staticbackup`.
+pub fn backup_channel_ids(scb: &[Vec<u8>]) -> Result<HashSet<Vec<u8>>, BackupAlert> {
+    scb.iter()
+        .enumerate()
+        .map(|(i, e)| {
+            e.get(SCB_CHANNEL_ID_RANGE)
+                .map(|id| id.to_vec())
+                .ok_or(BackupAlert::Malformed(i))
+        })
+        .collect()
+}
+
+/// Check the backup `scb` against the channels in `channels`.
+///
+/// Only channels that still hold funds off-chain need to be backed
+/// up. Channels in the backup that no longer exist are ignored.
+pub fn check_backup(
+    scb: &[Vec<u8>],
+    channels: &ListpeerchannelsResponse,
+) -> Result<(), BackupAlert> {
+    let backed_up = backup_channel_ids(scb)?;
+    let missing_channels: Vec<Vec<u8>> = channels
+        .channels
+        .iter()
+        .filter(|c| ChannelState::of(c).is_some_and(|s| !s.is_closed()))
+        .filter_map(|c| c.channel_id.clone())
+        .filter(|id| !backed_up.contains(id))
+        .collect();
+
+    if missing_channels.is_empty() {
+        Ok(())
+    } else {
+        Err(BackupAlert::Stale { missing_channels })
+    }
+}
+
+/// Check the backup `scb` against the node's current channels.
+///
+/// If `events` is given, a stale backup is also published as
+/// [`Event::BackupStale`].
+pub async fn verify_backup(
+    node: &mut ClnClient,
+    scb: &[Vec<u8>],
+    events: Option<&EventBus>,
+) -> Result<(), BackupAlert> {
+    let channels = node
+        .list_peer_channels(ListpeerchannelsRequest::default())
+        .await
+        .map_err(Box::new)?
+        .into_inner();
+
+    let res = check_backup(scb, &channels);
+    if let (Err(BackupAlert::Stale { missing_channels }), Some(events)) = (&res, events) {
+        events.publish(Event::BackupStale {
+            missing_channels: missing_channels.clone(),
+        });
+    }
+    res
+}
+
+/// Recover the channels in the backup `scb`, as returned by
+/// `staticbackup`. Returns the ids of the channels being recovered,
+/// channels the node still knows are skipped.
+pub async fn recover_channels(
+    node: &mut Client,
+    scb: &[Vec<u8>],
+) -> Result<Vec<Vec<u8>>, BackupAlert> {
+    backup_channel_ids(scb)?;
+    Ok(node
+        .recover_channel(RecoverChannelRequest { scb: scb.to_vec() })
+        .await
+        .map_err(Box::new)?
+        .into_inner()
+        .stubs)
+}
+
+/// Recover the channels in the backup the node keeps itself, in its
+/// `emergency.recover` file. Returns the ids of the channels being
+/// recovered.
+pub async fn emergency_recover(node: &mut Client) -> Result<Vec<Vec<u8>>, BackupAlert> {
+    Ok(node
+        .emergency_recover(EmergencyRecoverRequest {})
+        .await
+        .map_err(Box::new)?
+        .into_inner()
+        .stubs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::cln::{
+        listpeerchannels_channels::ListpeerchannelsChannelsState as ChannelState,
+        ListpeerchannelsChannels,
+    };
+
+    fn entry(id: u8) -> Vec<u8> {
+        let mut e = vec![0; 8];
+        e.extend([id; 32]);
+        e.extend([2; 33]);
+        e
+    }
+
+    fn channels(ids: &[(u8, ChannelState)]) -> ListpeerchannelsResponse {
+        ListpeerchannelsResponse {
+            channels: ids
+                .iter()
+                .map(|(id, state)| ListpeerchannelsChannels {
+                    channel_id: Some(vec![*id; 32]),
+                    state: Some(*state as i32),
+                    ..Default::default()
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_check_backup() {
+        let scb = vec![entry(1), entry(2)];
+
+        let chans = channels(&[
+            (1, ChannelState::ChanneldNormal),
+            (3, ChannelState::Onchain),
+        ]);
+        assert!(check_backup(&scb, &chans).is_ok());
+
+        let chans = channels(&[
+            (1, ChannelState::ChanneldNormal),
+            (3, ChannelState::ChanneldNormal),
+        ]);
+        match check_backup(&scb, &chans) {
+            Err(BackupAlert::Stale { missing_channels }) => {
+                assert_eq!(missing_channels, vec![vec![3; 32]])
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        assert!(matches!(
+            check_backup(&[vec![0; 12]], &chans),
+            Err(BackupAlert::Malformed(0))
+        ));
+    }
+}
@@ -0,0 +1,352 @@
+//! Summarize the funds of a node, and explain which parts of it can
+//! not be spent right away.
+//!
+//! The sum of onchain outputs and channel balances is rarely the
+//! amount a user can actually send. Channel reserves, fee buffers
+//! and the emergency funds `lightningd` keeps around to bump
+//! anchor-channel closes all reduce the spendable amount. The
+//! [`BalanceSummary`] breaks these down, so apps can tell the user
+//! why the full balance is not available.
+use crate::amount::Msat;
+use crate::channels::ChannelState;
+use crate::node::ClnClient;
+use crate::pb::cln::{
+    listfunds_outputs::ListfundsOutputsStatus, ListfundsRequest, ListfundsResponse,
+    ListpeerchannelsRequest, ListpeerchannelsResponse, ListpeersRequest, ListpeersResponse,
+};
+use crate::util::is_feature_bit_enabled;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+/// The default value of `lightningd`'s `min-emergency-msat` option
+/// (25'000 sat). It is kept onchain as long as there are channels
+/// using anchor outputs, so we can pay for the fees of a unilateral
+/// close.
+pub const DEFAULT_MIN_EMERGENCY_MSAT: u64 = 25_000_000;
+
+/// `option_anchor_outputs` (even and odd bit).
+const FEATURE_ANCHOR_OUTPUTS: usize = 20;
+/// `option_anchors_zero_fee_htlc_tx` (even and odd bit).
+const FEATURE_ANCHORS_ZERO_FEE_HTLC_TX: usize = 22;
+
+/// Funds that are part of the balance, but can not be spent.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Reserves {
+    /// The channel reserve we must keep on our side of each active
+    /// channel, as required by our peers.
+    pub channel_reserve_msat: u64,
+    /// Confirmed onchain funds held back to pay the fees for closing
+    /// anchor channels. Zero if there are no anchor channels.
+    pub emergency_msat: u64,
+    /// The configured `min-emergency-msat` used to compute
+    /// `emergency_msat`.
+    pub min_emergency_msat: u64,
+    /// Our channel balance that is neither spendable nor part of the
+    /// channel reserve, e.g., fee buffers and in-flight HTLCs.
+    pub unspendable_msat: u64,
+}
+
+impl Reserves {
+    /// The total amount that can not be spent.
+    pub fn total_msat(&self) -> u64 {
+        self.channel_reserve_msat + self.emergency_msat + self.unspendable_msat
+    }
+}
+
+/// A breakdown of the funds of a node.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct BalanceSummary {
+    /// Confirmed onchain outputs that are not reserved.
+    pub onchain_confirmed_msat: u64,
+    /// Onchain outputs that are not yet confirmed, or still immature.
+    pub onchain_unconfirmed_msat: u64,
+    /// Onchain outputs reserved for a pending transaction.
+    pub onchain_reserved_msat: u64,
+    /// Our balance in channels that are active.
+    pub channels_msat: u64,
+    /// How much we can send over active channels.
+    pub channels_spendable_msat: u64,
+    /// How much we can receive over active channels.
+    pub channels_receivable_msat: u64,
+    /// Our balance in channels that are still being opened.
+    pub channels_pending_open_msat: u64,
+    /// Our balance in channels that are being closed, and is not
+    /// yet returned to our onchain wallet.
+    pub channels_pending_close_msat: u64,
+    /// Whether any of the active channels uses anchor outputs.
+    pub has_anchor_channels: bool,
+    pub reserves: Reserves,
+}
+
+impl BalanceSummary {
+    /// Compute the summary from the results of `listfunds` and
+    /// `listpeerchannels`.
+    ///
+    /// `has_anchor_channels` controls whether onchain funds are held
+    /// back as emergency funds, see [`has_anchor_channels`] to
+    /// determine it from `listpeers`.
+    pub fn from_responses(
+        funds: &ListfundsResponse,
+        channels: &ListpeerchannelsResponse,
+        has_anchor_channels: bool,
+        min_emergency_msat: u64,
+    ) -> BalanceSummary {
+        let mut summary = BalanceSummary {
+            has_anchor_channels,
+            ..Default::default()
+        };
+
+        for o in funds.outputs.iter() {
+            let amount = Msat::from(&o.amount_msat).to_msat();
+            if o.reserved {
+                summary.onchain_reserved_msat += amount;
+                continue;
+            }
+            match ListfundsOutputsStatus::from_i32(o.status) {
+                Some(ListfundsOutputsStatus::Confirmed) => summary.onchain_confirmed_msat += amount,
+                Some(ListfundsOutputsStatus::Unconfirmed)
+                | Some(ListfundsOutputsStatus::Immature) => {
+                    summary.onchain_unconfirmed_msat += amount
+                }
+                Some(ListfundsOutputsStatus::Spent) | None => {}
+            }
+        }
+
+        for c in channels.channels.iter() {
+            let to_us = Msat::from(&c.to_us_msat).to_msat();
+            match ChannelState::of(c) {
+                Some(s) if s.is_active() => {
+                    let spendable = Msat::from(&c.spendable_msat).to_msat();
+                    let reserve = Msat::from(&c.our_reserve_msat).to_msat().min(to_us);
+                    summary.channels_msat += to_us;
+                    summary.channels_spendable_msat += spendable;
+                    summary.channels_receivable_msat += Msat::from(&c.receivable_msat).to_msat();
+                    summary.reserves.channel_reserve_msat += reserve;
+                    summary.reserves.unspendable_msat +=
+                        to_us.saturating_sub(spendable).saturating_sub(reserve);
+                }
+                Some(s) if s.is_opening() => summary.channels_pending_open_msat += to_us,
+                Some(_) => summary.channels_pending_close_msat += to_us,
+                None => {}
+            }
+        }
+
+        summary.reserves.min_emergency_msat = min_emergency_msat;
+        if has_anchor_channels {
+            summary.reserves.emergency_msat =
+                min_emergency_msat.min(summary.onchain_confirmed_msat);
+        }
+
+        summary
+    }
+
+    /// Onchain funds that can be withdrawn or used to open channels.
+    pub fn onchain_spendable_msat(&self) -> u64 {
+        self.onchain_confirmed_msat
+            .saturating_sub(self.reserves.emergency_msat)
+    }
+
+    /// The sum of all funds, spendable or not.
+    pub fn total_msat(&self) -> u64 {
+        self.onchain_confirmed_msat
+            + self.onchain_unconfirmed_msat
+            + self.onchain_reserved_msat
+            + self.channels_msat
+            + self.channels_pending_open_msat
+            + self.channels_pending_close_msat
+    }
+}
+
+/// Check whether any of the active channels was negotiated with a
+/// peer supporting anchor outputs.
+///
+/// `listpeerchannels` does not report the channel type, so we fall
+/// back to the features the peers advertised.
+pub fn has_anchor_channels(peers: &ListpeersResponse, channels: &ListpeerchannelsResponse) -> bool {
+    channels
+        .channels
+        .iter()
+        .filter(|c| {
+            matches!(
+                ChannelState::of(c),
+                Some(ChannelState::ChanneldNormal)
+                    | Some(ChannelState::ChanneldAwaitingSplice)
+                    | Some(ChannelState::ChanneldAwaitingLockin)
+                    | Some(ChannelState::DualopendAwaitingLockin)
+            )
+        })
+        .filter_map(|c| c.peer_id.as_ref())
+        .filter_map(|id| peers.peers.iter().find(|p| &p.id == id))
+        .filter_map(|p| p.features.as_ref())
+        .any(|f| supports_anchors(f))
+}
+
+/// How much we can receive over the active channels listed in
+/// `channels`.
+pub fn receivable_msat(channels: &ListpeerchannelsResponse) -> u64 {
+    channels
+        .channels
+        .iter()
+        .filter(|c| {
+            matches!(
+                ChannelState::of(c),
+                Some(ChannelState::ChanneldNormal) | Some(ChannelState::ChanneldAwaitingSplice)
+            )
+        })
+        .map(|c| Msat::from(&c.receivable_msat).to_msat())
+        .sum()
+}
+
+/// Fetch `listfunds`, `listpeerchannels` and `listpeers` from the
+/// node and summarize the balance.
+pub async fn balance_summary(
+    node: &mut ClnClient,
+    min_emergency_msat: u64,
+) -> Result<BalanceSummary> {
+    let funds = node
+        .list_funds(ListfundsRequest::default())
+        .await
+        .map_err(|e| anyhow!(e))?
+        .into_inner();
+    let channels = node
+        .list_peer_channels(ListpeerchannelsRequest::default())
+        .await
+        .map_err(|e| anyhow!(e))?
+        .into_inner();
+    let peers = node
+        .list_peers(ListpeersRequest::default())
+        .await
+        .map_err(|e| anyhow!(e))?
+        .into_inner();
+
+    let anchors = has_anchor_channels(&peers, &channels);
+    Ok(BalanceSummary::from_responses(
+        &funds,
+        &channels,
+        anchors,
+        min_emergency_msat,
+    ))
+}
+
+fn supports_anchors(features: &[u8]) -> bool {
+    [FEATURE_ANCHOR_OUTPUTS, FEATURE_ANCHORS_ZERO_FEE_HTLC_TX]
+        .iter()
+        .any(|b| is_feature_bit_enabled(features, *b) || is_feature_bit_enabled(features, b + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::cln::{
+        listpeerchannels_channels::ListpeerchannelsChannelsState as ChannelState, Amount,
+        ListfundsOutputs, ListpeerchannelsChannels, ListpeersPeers,
+    };
+
+    fn amount(msat: u64) -> Option<Amount> {
+        Some(Amount { msat })
+    }
+
+    fn output(msat: u64, status: ListfundsOutputsStatus, reserved: bool) -> ListfundsOutputs {
+        ListfundsOutputs {
+            amount_msat: amount(msat),
+            status: status as i32,
+            reserved,
+            ..Default::default()
+        }
+    }
+
+    fn channel(
+        state: ChannelState,
+        to_us: u64,
+        spendable: u64,
+        reserve: u64,
+    ) -> ListpeerchannelsChannels {
+        ListpeerchannelsChannels {
+            peer_id: Some(vec![2; 33]),
+            state: Some(state as i32),
+            to_us_msat: amount(to_us),
+            spendable_msat: amount(spendable),
+            receivable_msat: amount(0),
+            our_reserve_msat: amount(reserve),
+            ..Default::default()
+        }
+    }
+
+    fn responses() -> (ListfundsResponse, ListpeerchannelsResponse) {
+        let funds = ListfundsResponse {
+            outputs: vec![
+                output(10_000_000, ListfundsOutputsStatus::Confirmed, false),
+                output(3_000_000, ListfundsOutputsStatus::Unconfirmed, false),
+                output(5_000_000, ListfundsOutputsStatus::Confirmed, true),
+                output(7_000_000, ListfundsOutputsStatus::Spent, false),
+            ],
+            channels: vec![],
+        };
+        let channels = ListpeerchannelsResponse {
+            channels: vec![
+                channel(
+                    ChannelState::ChanneldNormal,
+                    100_000_000,
+                    95_000_000,
+                    1_000_000,
+                ),
+                channel(ChannelState::ChanneldAwaitingLockin, 20_000_000, 0, 0),
+                channel(ChannelState::Onchain, 30_000_000, 0, 0),
+            ],
+        };
+        (funds, channels)
+    }
+
+    #[test]
+    fn test_summary_without_anchors() {
+        let (funds, channels) = responses();
+        let s =
+            BalanceSummary::from_responses(&funds, &channels, false, DEFAULT_MIN_EMERGENCY_MSAT);
+
+        assert_eq!(s.onchain_confirmed_msat, 10_000_000);
+        assert_eq!(s.onchain_unconfirmed_msat, 3_000_000);
+        assert_eq!(s.onchain_reserved_msat, 5_000_000);
+        assert_eq!(s.channels_msat, 100_000_000);
+        assert_eq!(s.channels_spendable_msat, 95_000_000);
+        assert_eq!(s.channels_pending_open_msat, 20_000_000);
+        assert_eq!(s.channels_pending_close_msat, 30_000_000);
+        assert_eq!(s.reserves.channel_reserve_msat, 1_000_000);
+        assert_eq!(s.reserves.unspendable_msat, 4_000_000);
+        assert_eq!(s.reserves.emergency_msat, 0);
+        assert_eq!(s.onchain_spendable_msat(), 10_000_000);
+        assert_eq!(s.total_msat(), 168_000_000);
+    }
+
+    #[test]
+    fn test_summary_with_anchors() {
+        let (funds, channels) = responses();
+
+        // Less onchain funds than the emergency reserve: all of it is held back.
+        let s = BalanceSummary::from_responses(&funds, &channels, true, DEFAULT_MIN_EMERGENCY_MSAT);
+        assert_eq!(s.reserves.emergency_msat, 10_000_000);
+        assert_eq!(s.onchain_spendable_msat(), 0);
+
+        let s = BalanceSummary::from_responses(&funds, &channels, true, 4_000_000);
+        assert_eq!(s.reserves.emergency_msat, 4_000_000);
+        assert_eq!(s.onchain_spendable_msat(), 6_000_000);
+        assert_eq!(s.reserves.total_msat(), 9_000_000);
+    }
+
+    #[test]
+    fn test_has_anchor_channels() {
+        let (_, channels) = responses();
+        let mut peers = ListpeersResponse {
+            peers: vec![ListpeersPeers {
+                id: vec![2; 33],
+                // Bit 14 only (`payment_secret`)
+                features: Some(vec![0x40, 0x00]),
+                ..Default::default()
+            }],
+        };
+        assert!(!has_anchor_channels(&peers, &channels));
+
+        // Bit 23 (`option_anchors_zero_fee_htlc_tx`, odd)
+        peers.peers[0].features = Some(vec![0x80, 0x00, 0x00]);
+        assert!(has_anchor_channels(&peers, &channels));
+    }
+}
@@ -0,0 +1,375 @@
+//! A typed view of the node's channels.
+//!
+//! The channel details in `listpeers` are deprecated upstream in
+//! favor of `listpeerchannels`, which reports the state of each
+//! channel as a raw enum value, and most amounts as optional
+//! fields. [`list_channels`] turns the response into [`Channel`]s,
+//! with a [`ChannelState`] that groups the many internal states of
+//! `lightningd` into the ones applications care about.
+//!
+//! [`open_channels`] opens channels to several peers in a single
+//! transaction, and reports the outcome for each of them.
+use crate::amount::Msat;
+use crate::node::{Client, ClnClient};
+use crate::pb::cln::{
+    listpeerchannels_channels::ListpeerchannelsChannelsState as PbState,
+    listpeerchannels_channels_htlcs::ListpeerchannelsChannelsHtlcsDirection, HtlcState,
+    ListpeerchannelsChannels, ListpeerchannelsChannelsHtlcs, ListpeerchannelsRequest,
+};
+use crate::pb::{MultiFundChannelDestination, MultiFundChannelRequest, MultiFundChannelResponse};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// The state of a channel, as reported by `listpeerchannels`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ChannelState {
+    Openingd,
+    ChanneldAwaitingLockin,
+    ChanneldNormal,
+    ChanneldShuttingDown,
+    ClosingdSigexchange,
+    ClosingdComplete,
+    AwaitingUnilateral,
+    FundingSpendSeen,
+    Onchain,
+    DualopendOpenInit,
+    DualopendAwaitingLockin,
+    ChanneldAwaitingSplice,
+    DualopendOpenCommitted,
+    DualopendOpenCommitReady,
+}
+
+impl ChannelState {
+    /// The state of `channel`, if it is set and known.
+    pub fn of(channel: &ListpeerchannelsChannels) -> Option<ChannelState> {
+        channel.state.and_then(PbState::from_i32).map(Into::into)
+    }
+
+    /// The channel can send and receive payments.
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self,
+            ChannelState::ChanneldNormal | ChannelState::ChanneldAwaitingSplice
+        )
+    }
+
+    /// The channel is being opened, and waits for the funding
+    /// transaction to confirm.
+    pub fn is_opening(&self) -> bool {
+        matches!(
+            self,
+            ChannelState::Openingd
+                | ChannelState::ChanneldAwaitingLockin
+                | ChannelState::DualopendOpenInit
+                | ChannelState::DualopendOpenCommitted
+                | ChannelState::DualopendOpenCommitReady
+                | ChannelState::DualopendAwaitingLockin
+        )
+    }
+
+    /// The channel is being closed, cooperatively or not. Funds are
+    /// returned onchain once the closing transaction confirms.
+    pub fn is_closing(&self) -> bool {
+        !self.is_active() && !self.is_opening()
+    }
+
+    /// The closing transaction was negotiated or broadcast, so the
+    /// peer no longer needs the channel state to recover funds.
+    pub fn is_closed(&self) -> bool {
+        matches!(
+            self,
+            ChannelState::ClosingdComplete
+                | ChannelState::AwaitingUnilateral
+                | ChannelState::FundingSpendSeen
+                | ChannelState::Onchain
+        )
+    }
+}
+
+impl From<PbState> for ChannelState {
+    fn from(s: PbState) -> ChannelState {
+        match s {
+            PbState::Openingd => ChannelState::Openingd,
+            PbState::ChanneldAwaitingLockin => ChannelState::ChanneldAwaitingLockin,
+            PbState::ChanneldNormal => ChannelState::ChanneldNormal,
+            PbState::ChanneldShuttingDown => ChannelState::ChanneldShuttingDown,
+            PbState::ClosingdSigexchange => ChannelState::ClosingdSigexchange,
+            PbState::ClosingdComplete => ChannelState::ClosingdComplete,
+            PbState::AwaitingUnilateral => ChannelState::AwaitingUnilateral,
+            PbState::FundingSpendSeen => ChannelState::FundingSpendSeen,
+            PbState::Onchain => ChannelState::Onchain,
+            PbState::DualopendOpenInit => ChannelState::DualopendOpenInit,
+            PbState::DualopendAwaitingLockin => ChannelState::DualopendAwaitingLockin,
+            PbState::ChanneldAwaitingSplice => ChannelState::ChanneldAwaitingSplice,
+            PbState::DualopendOpenCommitted => ChannelState::DualopendOpenCommitted,
+            PbState::DualopendOpenCommitReady => ChannelState::DualopendOpenCommitReady,
+        }
+    }
+}
+
+/// The direction of an HTLC, from the point of view of the node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HtlcDirection {
+    In,
+    Out,
+}
+
+/// An HTLC in flight on a channel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Htlc {
+    pub id: u64,
+    pub direction: HtlcDirection,
+    pub amount_msat: u64,
+    /// The block height at which the HTLC times out.
+    pub expiry: u32,
+    pub payment_hash: Vec<u8>,
+    /// The state of the HTLC in the commitment dance, if reported.
+    pub state: Option<HtlcState>,
+}
+
+impl Htlc {
+    fn from_pb(h: &ListpeerchannelsChannelsHtlcs) -> Option<Htlc> {
+        let direction = match h
+            .direction
+            .and_then(ListpeerchannelsChannelsHtlcsDirection::from_i32)?
+        {
+            ListpeerchannelsChannelsHtlcsDirection::In => HtlcDirection::In,
+            ListpeerchannelsChannelsHtlcsDirection::Out => HtlcDirection::Out,
+        };
+        Some(Htlc {
+            id: h.id?,
+            direction,
+            amount_msat: Msat::from(&h.amount_msat).to_msat(),
+            expiry: h.expiry.unwrap_or(0),
+            payment_hash: h.payment_hash.clone().unwrap_or_default(),
+            state: h.state.and_then(HtlcState::from_i32),
+        })
+    }
+}
+
+/// A channel of the node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Channel {
+    pub peer_id: Vec<u8>,
+    pub peer_connected: bool,
+    pub state: ChannelState,
+    pub channel_id: Option<Vec<u8>>,
+    /// Only set once the funding transaction confirmed.
+    pub short_channel_id: Option<String>,
+    pub private: bool,
+    pub to_us_msat: u64,
+    pub total_msat: u64,
+    pub spendable_msat: u64,
+    pub receivable_msat: u64,
+    pub our_reserve_msat: u64,
+    pub htlcs: Vec<Htlc>,
+}
+
+impl Channel {
+    /// Convert a channel from `listpeerchannels`. Returns `None` if
+    /// the peer or the state of the channel is missing.
+    pub fn from_pb(c: &ListpeerchannelsChannels) -> Option<Channel> {
+        Some(Channel {
+            peer_id: c.peer_id.clone()?,
+            peer_connected: c.peer_connected.unwrap_or(false),
+            state: ChannelState::of(c)?,
+            channel_id: c.channel_id.clone(),
+            short_channel_id: c.short_channel_id.clone(),
+            private: c.private.unwrap_or(false),
+            to_us_msat: Msat::from(&c.to_us_msat).to_msat(),
+            total_msat: Msat::from(&c.total_msat).to_msat(),
+            spendable_msat: Msat::from(&c.spendable_msat).to_msat(),
+            receivable_msat: Msat::from(&c.receivable_msat).to_msat(),
+            our_reserve_msat: Msat::from(&c.our_reserve_msat).to_msat(),
+            htlcs: c.htlcs.iter().filter_map(Htlc::from_pb).collect(),
+        })
+    }
+
+    /// The sum of the HTLCs in `direction`.
+    pub fn htlcs_msat(&self, direction: HtlcDirection) -> u64 {
+        self.htlcs
+            .iter()
+            .filter(|h| h.direction == direction)
+            .map(|h| h.amount_msat)
+            .sum()
+    }
+}
+
+/// List the node's channels.
+pub async fn list_channels(node: &mut ClnClient) -> Result<Vec<Channel>> {
+    let res = node
+        .list_peer_channels(ListpeerchannelsRequest::default())
+        .await?
+        .into_inner();
+    Ok(res.channels.iter().filter_map(Channel::from_pb).collect())
+}
+
+/// A channel opened by [`open_channels`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpenedChannel {
+    pub channel_id: Vec<u8>,
+    /// The output of the funding transaction funding the channel.
+    pub outnum: u32,
+}
+
+/// Why [`open_channels`] could not open a channel with a peer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpenFailure {
+    /// The step that failed, e.g., `connect` or `openchannel_init`.
+    pub method: String,
+    pub code: i32,
+    pub message: String,
+}
+
+/// The outcome of [`open_channels`], by peer.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MultiFundResult {
+    /// The funding transaction of the opened channels.
+    pub txid: Vec<u8>,
+    pub opened: BTreeMap<Vec<u8>, OpenedChannel>,
+    pub failed: BTreeMap<Vec<u8>, OpenFailure>,
+}
+
+impl MultiFundResult {
+    /// The outcome for the peer `id`, `None` if it was not a
+    /// destination.
+    pub fn get(&self, id: &[u8]) -> Option<std::result::Result<&OpenedChannel, &OpenFailure>> {
+        self.opened
+            .get(id)
+            .map(Ok)
+            .or_else(|| self.failed.get(id).map(Err))
+    }
+}
+
+impl From<MultiFundChannelResponse> for MultiFundResult {
+    fn from(r: MultiFundChannelResponse) -> Self {
+        MultiFundResult {
+            txid: r.txid,
+            opened: r
+                .channel_ids
+                .into_iter()
+                .map(|c| {
+                    let channel = OpenedChannel {
+                        channel_id: c.channel_id,
+                        outnum: c.outnum,
+                    };
+                    (c.id, channel)
+                })
+                .collect(),
+            failed: r
+                .failed
+                .into_iter()
+                .map(|f| {
+                    let failure = OpenFailure {
+                        method: f.method,
+                        code: f.code,
+                        message: f.message,
+                    };
+                    (f.id, failure)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Open channels to all `destinations` in a single transaction.
+///
+/// With `minchannels` set, destinations that fail are dropped as
+/// long as at least that many channels can be opened, and reported
+/// in [`MultiFundResult::failed`]. Otherwise, any failure aborts
+/// the call and no channel is opened.
+pub async fn open_channels(
+    node: &mut Client,
+    destinations: Vec<MultiFundChannelDestination>,
+    minchannels: Option<u32>,
+) -> Result<MultiFundResult> {
+    let res = node
+        .multi_fund_channel(MultiFundChannelRequest {
+            destinations,
+            minchannels,
+            ..Default::default()
+        })
+        .await?
+        .into_inner();
+    Ok(res.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::cln::Amount;
+
+    #[test]
+    fn test_channel_from_pb() {
+        let htlc = |id, direction: ListpeerchannelsChannelsHtlcsDirection, msat| {
+            ListpeerchannelsChannelsHtlcs {
+                id: Some(id),
+                direction: Some(direction as i32),
+                amount_msat: Some(Amount { msat }),
+                expiry: Some(800_000),
+                state: Some(HtlcState::SentAddAckRevocation as i32),
+                ..Default::default()
+            }
+        };
+        let mut c = ListpeerchannelsChannels {
+            peer_id: Some(vec![2; 33]),
+            state: Some(PbState::ChanneldNormal as i32),
+            to_us_msat: Some(Amount { msat: 5_000 }),
+            htlcs: vec![
+                htlc(0, ListpeerchannelsChannelsHtlcsDirection::In, 100),
+                htlc(1, ListpeerchannelsChannelsHtlcsDirection::Out, 200),
+                htlc(2, ListpeerchannelsChannelsHtlcsDirection::Out, 300),
+            ],
+            ..Default::default()
+        };
+
+        let channel = Channel::from_pb(&c).unwrap();
+        assert!(channel.state.is_active());
+        assert_eq!(channel.to_us_msat, 5_000);
+        assert_eq!(channel.htlcs_msat(HtlcDirection::In), 100);
+        assert_eq!(channel.htlcs_msat(HtlcDirection::Out), 500);
+        assert_eq!(
+            channel.htlcs[0].state,
+            Some(HtlcState::SentAddAckRevocation)
+        );
+
+        c.state = Some(PbState::FundingSpendSeen as i32);
+        let state = Channel::from_pb(&c).unwrap().state;
+        assert!(state.is_closing() && state.is_closed());
+
+        c.state = Some(PbState::ChanneldShuttingDown as i32);
+        let state = Channel::from_pb(&c).unwrap().state;
+        assert!(state.is_closing() && !state.is_closed());
+
+        c.state = None;
+        assert!(Channel::from_pb(&c).is_none());
+    }
+
+    #[test]
+    fn test_multifund_result() {
+        use crate::pb::{MultiFundChannelChannel, MultiFundChannelFailure};
+        let res: MultiFundResult = MultiFundChannelResponse {
+            txid: vec![1; 32],
+            channel_ids: vec![MultiFundChannelChannel {
+                id: vec![2; 33],
+                outnum: 1,
+                channel_id: vec![4; 32],
+                ..Default::default()
+            }],
+            failed: vec![MultiFundChannelFailure {
+                id: vec![3; 33],
+                method: "connect".to_string(),
+                code: 401,
+                message: "Unable to connect".to_string(),
+            }],
+            ..Default::default()
+        }
+        .into();
+        assert_eq!(res.get(&[2; 33]).unwrap().unwrap().outnum, 1);
+        assert_eq!(res.get(&[3; 33]).unwrap().unwrap_err().method, "connect");
+        assert!(res.get(&[5; 33]).is_none());
+    }
+}
@@ -0,0 +1,325 @@
+//! Track channels that are being closed, until the funds are back in
+//! the onchain wallet.
+//!
+//! Closing a channel, especially unilaterally, can take a long time:
+//! our output is locked by the `to_self_delay` our peer asked for,
+//! and has to be swept once the timelock expires. The
+//! [`ClosureTracker`] reports which stage each closing channel is in,
+//! and estimates when the funds become spendable.
+use crate::amount::Msat;
+use crate::node::ClnClient;
+use crate::pb::cln::{
+    listpeerchannels_channels::ListpeerchannelsChannelsState as ChannelState, ChannelSide,
+    GetinfoRequest, ListfundsRequest, ListfundsResponse, ListpeerchannelsChannels,
+    ListpeerchannelsRequest, ListpeerchannelsResponse,
+};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// The average time between two blocks, used for estimates.
+const BLOCK_INTERVAL: Duration = Duration::from_secs(600);
+
+/// The stage a closing channel is in.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum ClosureStage {
+    /// A mutual close is being negotiated with the peer.
+    Negotiating,
+    /// The closing transaction was broadcast, and we wait for it to
+    /// confirm. For unilateral closes this includes waiting for the
+    /// peer to come back online.
+    AwaitingConfirmation,
+    /// Our output is locked by `to_self_delay`.
+    Timelocked { blocks_remaining: u32 },
+    /// Onchain outputs are being resolved, e.g., the sweep was
+    /// broadcast and awaits confirmation.
+    Sweeping,
+    /// All outputs are resolved, and the funds are in the onchain
+    /// wallet.
+    Swept,
+}
+
+/// A channel that is being closed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Closure {
+    pub channel_id: Vec<u8>,
+    pub peer_id: Option<Vec<u8>>,
+    pub short_channel_id: Option<String>,
+    pub funding_txid: Option<Vec<u8>>,
+    /// Our balance at the time of the close.
+    pub amount_msat: u64,
+    /// Whether we initiated the close, if known.
+    pub closer: Option<ChannelSide>,
+    pub stage: ClosureStage,
+    /// Estimated number of blocks until the funds can be spent.
+    pub blocks_until_spendable: Option<u32>,
+    /// Estimated blockheight at which the funds can be spent.
+    pub spendable_at_height: Option<u32>,
+}
+
+impl Closure {
+    /// Estimated time until the funds can be spent.
+    pub fn time_until_spendable(&self) -> Option<Duration> {
+        self.blocks_until_spendable.map(|b| BLOCK_INTERVAL * b)
+    }
+}
+
+/// Extract the closures from the results of `listpeerchannels` and
+/// `listfunds`, given the current `blockheight`.
+pub fn closures(
+    channels: &ListpeerchannelsResponse,
+    funds: &ListfundsResponse,
+    blockheight: u32,
+) -> Vec<Closure> {
+    channels
+        .channels
+        .iter()
+        .filter_map(|c| closure(c, funds, blockheight))
+        .collect()
+}
+
+fn closure(
+    c: &ListpeerchannelsChannels,
+    funds: &ListfundsResponse,
+    blockheight: u32,
+) -> Option<Closure> {
+    let channel_id = c.channel_id.clone()?;
+    let closer = c.closer.and_then(ChannelSide::from_i32);
+    let state = c.state.and_then(ChannelState::from_i32)?;
+    let status = c.status.last().map(|s| s.as_str()).unwrap_or("");
+
+    let stage = match state {
+        ChannelState::ChanneldShuttingDown | ChannelState::ClosingdSigexchange => {
+            ClosureStage::Negotiating
+        }
+        ChannelState::ClosingdComplete
+        | ChannelState::AwaitingUnilateral
+        | ChannelState::FundingSpendSeen => ClosureStage::AwaitingConfirmation,
+        ChannelState::Onchain => onchain_stage(status),
+        _ => return None,
+    };
+
+    let blocks_until_spendable = match (&stage, closer) {
+        (ClosureStage::Negotiating, _) => None,
+        // Once confirmed, our own unilateral close still has to wait
+        // out the timelock, and be swept.
+        (ClosureStage::AwaitingConfirmation, Some(ChannelSide::Local))
+            if state != ChannelState::ClosingdComplete =>
+        {
+            c.our_to_self_delay.map(|d| d + 2)
+        }
+        (ClosureStage::AwaitingConfirmation, _) => Some(1),
+        (ClosureStage::Timelocked { blocks_remaining }, _) => Some(blocks_remaining + 1),
+        (ClosureStage::Sweeping, _) => Some(1),
+        (ClosureStage::Swept, _) => Some(0),
+    };
+
+    let amount_msat = c
+        .to_us_msat
+        .as_ref()
+        .map(Msat::from)
+        .or_else(|| {
+            funds
+                .channels
+                .iter()
+                .find(|f| f.channel_id.as_ref() == Some(&channel_id))
+                .and_then(|f| f.our_amount_msat.as_ref())
+                .map(Msat::from)
+        })
+        .unwrap_or_default()
+        .to_msat();
+
+    Some(Closure {
+        peer_id: c.peer_id.clone(),
+        short_channel_id: c.short_channel_id.clone(),
+        funding_txid: c.funding_txid.clone(),
+        amount_msat,
+        closer,
+        stage,
+        spendable_at_height: blocks_until_spendable.map(|b| blockheight + b),
+        blocks_until_spendable,
+        channel_id,
+    })
+}
+
+/// Map the status `onchaind` reports to a stage, e.g.,
+/// `ONCHAIN:1 outputs unresolved: in 143 blocks will spend
+/// DELAYED_OUTPUT_TO_US (...) using OUR_DELAYED_RETURN_TO_WALLET`.
+fn onchain_stage(status: &str) -> ClosureStage {
+    if status.contains("All outputs resolved") {
+        return ClosureStage::Swept;
+    }
+
+    let mut words = status.split_whitespace();
+    while let Some(w) = words.next() {
+        if w != "in" {
+            continue;
+        }
+        if let (Some(Ok(n)), Some("blocks")) = (words.next().map(|n| n.parse()), words.next()) {
+            return ClosureStage::Timelocked {
+                blocks_remaining: n,
+            };
+        }
+    }
+
+    ClosureStage::Sweeping
+}
+
+/// Remembers the stage of each closing channel, and reports the ones
+/// that changed since the last update.
+#[derive(Debug, Default)]
+pub struct ClosureTracker {
+    stages: HashMap<Vec<u8>, ClosureStage>,
+}
+
+impl ClosureTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the current closures, returning the ones that are new
+    /// or moved to a different stage. Channels that are no longer
+    /// reported have been forgotten by the node, and are dropped.
+    pub fn update(&mut self, closures: &[Closure]) -> Vec<Closure> {
+        let mut changed = vec![];
+        let mut stages = HashMap::new();
+        for c in closures.iter() {
+            if self.stages.get(&c.channel_id) != Some(&c.stage) {
+                changed.push(c.clone());
+            }
+            stages.insert(c.channel_id.clone(), c.stage.clone());
+        }
+        self.stages = stages;
+        changed
+    }
+
+    /// Fetch the closures from the node, and update the tracker.
+    ///
+    /// Returns all current closures, and the ones that changed.
+    pub async fn poll(&mut self, node: &mut ClnClient) -> Result<(Vec<Closure>, Vec<Closure>)> {
+        let closures = fetch(node).await?;
+        let changed = self.update(&closures);
+        Ok((closures, changed))
+    }
+}
+
+/// Fetch the closures from the node.
+pub async fn fetch(node: &mut ClnClient) -> Result<Vec<Closure>> {
+    let height = node
+        .getinfo(GetinfoRequest::default())
+        .await
+        .map_err(|e| anyhow!(e))?
+        .into_inner()
+        .blockheight;
+    let channels = node
+        .list_peer_channels(ListpeerchannelsRequest::default())
+        .await
+        .map_err(|e| anyhow!(e))?
+        .into_inner();
+    let funds = node
+        .list_funds(ListfundsRequest::default())
+        .await
+        .map_err(|e| anyhow!(e))?
+        .into_inner();
+
+    Ok(closures(&channels, &funds, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::cln::Amount;
+
+    fn channel(
+        id: u8,
+        state: ChannelState,
+        closer: ChannelSide,
+        status: &str,
+    ) -> ListpeerchannelsChannels {
+        ListpeerchannelsChannels {
+            channel_id: Some(vec![id; 32]),
+            state: Some(state as i32),
+            closer: Some(closer as i32),
+            status: vec![status.to_string()],
+            to_us_msat: Some(Amount { msat: 1_000_000 }),
+            our_to_self_delay: Some(144),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_onchain_stage() {
+        assert_eq!(
+            onchain_stage("ONCHAIN:1 outputs unresolved: in 143 blocks will spend DELAYED_OUTPUT_TO_US (abc:0) using OUR_DELAYED_RETURN_TO_WALLET"),
+            ClosureStage::Timelocked { blocks_remaining: 143 }
+        );
+        assert_eq!(
+            onchain_stage(
+                "ONCHAIN:All outputs resolved: waiting 90 more blocks before forgetting channel"
+            ),
+            ClosureStage::Swept
+        );
+        assert_eq!(
+            onchain_stage("ONCHAIN:Tracking our own unilateral close"),
+            ClosureStage::Sweeping
+        );
+    }
+
+    #[test]
+    fn test_closures() {
+        let channels = ListpeerchannelsResponse {
+            channels: vec![
+                channel(1, ChannelState::ChanneldNormal, ChannelSide::Local, ""),
+                channel(2, ChannelState::AwaitingUnilateral, ChannelSide::Local, ""),
+                channel(
+                    3,
+                    ChannelState::Onchain,
+                    ChannelSide::Local,
+                    "ONCHAIN:1 outputs unresolved: in 10 blocks will spend DELAYED_OUTPUT_TO_US",
+                ),
+                channel(4, ChannelState::ClosingdComplete, ChannelSide::Remote, ""),
+            ],
+        };
+        let c = closures(&channels, &ListfundsResponse::default(), 800_000);
+
+        assert_eq!(c.len(), 3);
+        assert_eq!(c[0].stage, ClosureStage::AwaitingConfirmation);
+        assert_eq!(c[0].blocks_until_spendable, Some(146));
+        assert_eq!(
+            c[1].stage,
+            ClosureStage::Timelocked {
+                blocks_remaining: 10
+            }
+        );
+        assert_eq!(c[1].spendable_at_height, Some(800_011));
+        assert_eq!(
+            c[1].time_until_spendable(),
+            Some(Duration::from_secs(11 * 600))
+        );
+        assert_eq!(c[2].blocks_until_spendable, Some(1));
+        assert_eq!(c[2].amount_msat, 1_000_000);
+    }
+
+    #[test]
+    fn test_tracker() {
+        let mut channels = ListpeerchannelsResponse {
+            channels: vec![channel(
+                1,
+                ChannelState::ClosingdSigexchange,
+                ChannelSide::Local,
+                "",
+            )],
+        };
+        let funds = ListfundsResponse::default();
+        let mut tracker = ClosureTracker::new();
+
+        assert_eq!(tracker.update(&closures(&channels, &funds, 1)).len(), 1);
+        assert_eq!(tracker.update(&closures(&channels, &funds, 2)).len(), 0);
+
+        channels.channels[0].state = Some(ChannelState::ClosingdComplete as i32);
+        let changed = tracker.update(&closures(&channels, &funds, 3));
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].stage, ClosureStage::AwaitingConfirmation);
+    }
+}
@@ -0,0 +1,349 @@
+//! Load the client setup from a configuration file.
+//!
+//! Every application needs the same handful of settings: the network,
+//! where the scheduler is, where the credentials and the seed are
+//! stored, and so on. [`Config`] collects them, read from a TOML file
+//! and overridden by environment variables, or constructed directly:
+//!
+//! ```toml
+//! network = "regtest"
+//! scheduler_uri = "https://scheduler.example.com"
+//! creds = "/var/lib/gl/creds"
+//! seed = "/var/lib/gl/seed"
+//! proxy = "http://proxy.example.com:3128"
+//! policy = "/etc/gl/policy.toml"
+//! compression = true
+//!
+//! [timeouts]
+//! connect_secs = 10
+//! request_secs = 30
+//! ```
+//!
+//! The environment variables are `GL_NETWORK`,
+//! `GL_SCHEDULER_GRPC_URI`, `GL_CREDS`, `GL_SEED`, `GL_PROXY`,
+//! `GL_POLICY`, `GL_COMPRESSION`, `GL_CONNECT_TIMEOUT` and
+//! `GL_REQUEST_TIMEOUT`, the latter two in seconds. `GL_BACKEND`
+//! switches between `greenlight` and a `local` node, see
+//! [`Backend`], whose plugin certificates are in `GL_LOCAL_CERTS` and
+//! which listens on `GL_LOCAL_GRPC_URI`.
+use crate::backend::{Backend, DEFAULT_LOCAL_GRPC_URI};
+#[cfg(feature = "signer")]
+use crate::signer::SignerPolicy;
+use crate::utils::scheduler_uri;
+use anyhow::{anyhow, Context, Result};
+use lightning_signer::bitcoin::Network;
+use serde::{Deserialize, Deserializer};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[serde(deserialize_with = "deserialize_network")]
+    pub network: Network,
+    /// The scheduler to talk to, `None` for the production scheduler.
+    pub scheduler_uri: Option<String>,
+    /// The device credentials file.
+    pub creds: PathBuf,
+    /// The file holding the seed, only needed to run a signer.
+    pub seed: Option<PathBuf>,
+    /// Proxy for the HTTP requests of the library, e.g., to LNURL
+    /// services or rate providers, see [`Config::http_client`]. gRPC
+    /// connections to the scheduler and the node do not go through
+    /// the proxy.
+    pub proxy: Option<String>,
+    pub timeouts: Timeouts,
+    /// Accept gzip compressed responses from the scheduler and the
+    /// node, see [`Scheduler::with_compression`].
+    ///
+    /// [`Scheduler::with_compression`]: crate::scheduler::Scheduler::with_compression
+    pub compression: bool,
+    /// A TOML file with the [`SignerPolicy`] to run the signer with.
+    pub policy: Option<PathBuf>,
+    /// Whether clients talk to Greenlight or a local node.
+    pub backend: Backend,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// How long to wait for a connection to be established.
+    pub connect_secs: Option<u64>,
+    /// How long to wait for the response to a request.
+    pub request_secs: Option<u64>,
+}
+
+impl Timeouts {
+    pub fn connect(&self) -> Option<Duration> {
+        self.connect_secs.map(Duration::from_secs)
+    }
+
+    pub fn request(&self) -> Option<Duration> {
+        self.request_secs.map(Duration::from_secs)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            network: Network::Bitcoin,
+            scheduler_uri: None,
+            creds: PathBuf::from("creds"),
+            seed: None,
+            proxy: None,
+            timeouts: Timeouts::default(),
+            compression: false,
+            policy: None,
+            backend: Backend::Greenlight,
+        }
+    }
+}
+
+fn deserialize_network<'de, D>(deserializer: D) -> Result<Network, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    Network::from_str(&s).map_err(serde::de::Error::custom)
+}
+
+impl Config {
+    /// Load the configuration from `path`, if any, and apply the
+    /// overrides from the environment.
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        let config = match path {
+            Some(path) => {
+                let s = std::fs::read_to_string(path)
+                    .with_context(|| format!("reading configuration {}", path.display()))?;
+                Config::from_toml(&s)
+                    .with_context(|| format!("parsing configuration {}", path.display()))?
+            }
+            None => Config::default(),
+        };
+        config.with_env(|k| std::env::var(k).ok())
+    }
+
+    pub fn from_toml(s: &str) -> Result<Config> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// Apply the overrides returned by `var` for the environment
+    /// variables.
+    pub fn with_env<F>(mut self, var: F) -> Result<Config>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let secs = |k: &str| -> Result<Option<u64>> {
+            var(k)
+                .map(|v| {
+                    v.parse()
+                        .with_context(|| format!("{} must be in seconds", k))
+                })
+                .transpose()
+        };
+        if let Some(n) = var("GL_NETWORK") {
+            self.network = Network::from_str(&n)
+                .map_err(|_| anyhow!("unknown network {} in GL_NETWORK", n))?;
+        }
+        if let Some(uri) = var("GL_SCHEDULER_GRPC_URI") {
+            self.scheduler_uri = Some(uri);
+        }
+        if let Some(p) = var("GL_CREDS") {
+            self.creds = p.into();
+        }
+        if let Some(p) = var("GL_SEED") {
+            self.seed = Some(p.into());
+        }
+        if let Some(p) = var("GL_PROXY") {
+            self.proxy = Some(p);
+        }
+        if let Some(p) = var("GL_POLICY") {
+            self.policy = Some(p.into());
+        }
+        if let Some(c) = var("GL_COMPRESSION") {
+            self.compression = c.parse().context("GL_COMPRESSION must be true or false")?;
+        }
+        if let Some(s) = secs("GL_CONNECT_TIMEOUT")? {
+            self.timeouts.connect_secs = Some(s);
+        }
+        if let Some(s) = secs("GL_REQUEST_TIMEOUT")? {
+            self.timeouts.request_secs = Some(s);
+        }
+        if let Some(b) = var("GL_BACKEND") {
+            self.backend = match b.as_str() {
+                "greenlight" => Backend::Greenlight,
+                "local" => {
+                    let (grpc_uri, certs) = match self.backend {
+                        Backend::Local { grpc_uri, certs } => (Some(grpc_uri), Some(certs)),
+                        Backend::Greenlight => (None, None),
+                    };
+                    Backend::Local {
+                        grpc_uri: var("GL_LOCAL_GRPC_URI")
+                            .or(grpc_uri)
+                            .unwrap_or_else(|| DEFAULT_LOCAL_GRPC_URI.to_string()),
+                        certs: var("GL_LOCAL_CERTS")
+                            .map(PathBuf::from)
+                            .or(certs)
+                            .context("GL_BACKEND=local requires GL_LOCAL_CERTS")?,
+                    }
+                }
+                _ => return Err(anyhow!("unknown backend {} in GL_BACKEND", b)),
+            };
+        }
+        Ok(self)
+    }
+
+    /// The scheduler URI, falling back to the production scheduler.
+    pub fn scheduler_uri(&self) -> String {
+        self.scheduler_uri.clone().unwrap_or_else(scheduler_uri)
+    }
+
+    /// Read the signer policy from the policy file, if one is
+    /// configured. Fields missing in the file keep their defaults,
+    /// unknown fields are an error.
+    #[cfg(feature = "signer")]
+    pub fn signer_policy(&self) -> Result<Option<SignerPolicy>> {
+        let path = match &self.policy {
+            Some(p) => p,
+            None => return Ok(None),
+        };
+        let s = std::fs::read_to_string(path)
+            .with_context(|| format!("reading signer policy {}", path.display()))?;
+        let policy: PolicyFile = toml::from_str(&s)
+            .with_context(|| format!("parsing signer policy {}", path.display()))?;
+        Ok(Some(policy.0))
+    }
+
+    /// An HTTP client honoring the proxy and timeouts.
+    pub fn http_client(&self) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        if let Some(t) = self.timeouts.connect() {
+            builder = builder.connect_timeout(t);
+        }
+        if let Some(t) = self.timeouts.request() {
+            builder = builder.timeout(t);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// A [`SignerPolicy`] with defaults for the missing fields.
+#[cfg(feature = "signer")]
+struct PolicyFile(SignerPolicy);
+
+#[cfg(feature = "signer")]
+impl<'de> Deserialize<'de> for PolicyFile {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut value =
+            serde_json::to_value(SignerPolicy::default()).map_err(serde::de::Error::custom)?;
+        let overrides = toml::Table::deserialize(deserializer)?;
+        for (k, v) in overrides {
+            let v = serde_json::to_value(v).map_err(serde::de::Error::custom)?;
+            value[k] = v;
+        }
+        serde_json::from_value(value)
+            .map(PolicyFile)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_config() {
+        let config = Config::from_toml(
+            r#"
+            network = "regtest"
+            creds = "/tmp/creds"
+            [timeouts]
+            connect_secs = 5
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.network, Network::Regtest);
+        assert_eq!(config.creds, PathBuf::from("/tmp/creds"));
+        assert_eq!(config.timeouts.connect(), Some(Duration::from_secs(5)));
+        assert!(Config::from_toml("netwrok = \"regtest\"").is_err());
+
+        let env: HashMap<&str, &str> = [
+            ("GL_NETWORK", "testnet"),
+            ("GL_SCHEDULER_GRPC_URI", "https://localhost:1234"),
+            ("GL_REQUEST_TIMEOUT", "30"),
+            ("GL_COMPRESSION", "true"),
+        ]
+        .iter()
+        .copied()
+        .collect();
+        let config = config
+            .with_env(|k| env.get(k).map(|v| v.to_string()))
+            .unwrap();
+        assert_eq!(config.network, Network::Testnet);
+        assert_eq!(config.scheduler_uri(), "https://localhost:1234");
+        assert_eq!(config.timeouts.request_secs, Some(30));
+        assert_eq!(config.timeouts.connect_secs, Some(5));
+        assert!(config.compression);
+        assert_eq!(config.backend, Backend::Greenlight);
+        assert!(Config::default()
+            .with_env(|k| (k == "GL_CONNECT_TIMEOUT").then(|| "soon".to_string()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_backend() {
+        let config = Config::from_toml(
+            r#"
+            [backend]
+            type = "local"
+            certs = "/tmp/regtest"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.backend,
+            Backend::Local {
+                grpc_uri: DEFAULT_LOCAL_GRPC_URI.to_string(),
+                certs: PathBuf::from("/tmp/regtest"),
+            }
+        );
+
+        let config = config
+            .with_env(|k| (k == "GL_BACKEND").then(|| "greenlight".to_string()))
+            .unwrap();
+        assert!(!config.backend.is_local());
+        assert!(Config::default()
+            .with_env(|k| (k == "GL_BACKEND").then(|| "local".to_string()))
+            .is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "signer")]
+    fn test_signer_policy() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"max_invoices = 5\n").unwrap();
+        let config = Config {
+            policy: Some(file.path().to_path_buf()),
+            ..Default::default()
+        };
+        let policy = config.signer_policy().unwrap().unwrap();
+        assert_eq!(policy.max_invoices, 5);
+        assert_eq!(
+            policy.max_routing_fee_msat,
+            SignerPolicy::default().max_routing_fee_msat
+        );
+
+        // A misspelled field is an error, rather than silently
+        // keeping the default.
+        std::io::Write::write_all(&mut file, b"max_invoice = 5\n").unwrap();
+        assert!(config.signer_policy().is_err());
+    }
+}
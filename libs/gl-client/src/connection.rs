@@ -0,0 +1,131 @@
+//! Track how far along the connection to the node is.
+//!
+//! The scheduler and the signer both learn about the state of the
+//! connection: the scheduler when it schedules the node, and the
+//! signer when it attaches to it. Sharing a [`StatusWatch`] between
+//! them, via [`Scheduler::with_status`] and [`Signer::with_status`],
+//! gives applications a single [`ConnectionStatus`] to show.
+//!
+//! [`Scheduler::with_status`]: crate::scheduler::Scheduler::with_status
+//! [`Signer::with_status`]: crate::signer::Signer::with_status
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// The states are ordered by how far along the connection is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionStatus {
+    Disconnected,
+    /// The scheduler is reachable, but the node is not running.
+    SchedulerConnected,
+    /// The scheduler is starting the node.
+    NodeStarting,
+    /// The node is running and can be reached.
+    NodeReady,
+    /// The signer is attached to the running node, so it can sign
+    /// off on payments.
+    SignerAttached,
+}
+
+impl ConnectionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionStatus::Disconnected => "disconnected",
+            ConnectionStatus::SchedulerConnected => "scheduler_connected",
+            ConnectionStatus::NodeStarting => "node_starting",
+            ConnectionStatus::NodeReady => "node_ready",
+            ConnectionStatus::SignerAttached => "signer_attached",
+        }
+    }
+}
+
+/// A shared, observable [`ConnectionStatus`].
+///
+/// Cloning the watch is cheap, and all clones update the same status.
+#[derive(Clone, Debug)]
+pub struct StatusWatch {
+    sender: Arc<watch::Sender<ConnectionStatus>>,
+}
+
+impl StatusWatch {
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(ConnectionStatus::Disconnected);
+        StatusWatch {
+            sender: Arc::new(sender),
+        }
+    }
+
+    pub fn current(&self) -> ConnectionStatus {
+        *self.sender.borrow()
+    }
+
+    /// Receive the status whenever it changes.
+    pub fn subscribe(&self) -> watch::Receiver<ConnectionStatus> {
+        self.sender.subscribe()
+    }
+
+    pub(crate) fn set(&self, status: ConnectionStatus) {
+        self.sender.send_if_modified(|s| {
+            let modified = *s != status;
+            *s = status;
+            modified
+        });
+    }
+
+    /// Move to `status` unless the connection is already further
+    /// along, e.g., when scheduling a node the signer is attached to.
+    pub(crate) fn raise(&self, status: ConnectionStatus) {
+        self.sender.send_if_modified(|s| {
+            let modified = *s < status;
+            if modified {
+                *s = status;
+            }
+            modified
+        });
+    }
+
+    /// Move back to `status` if the connection is currently in
+    /// `from`, leaving updates by other components untouched.
+    pub(crate) fn revert(&self, from: ConnectionStatus, status: ConnectionStatus) {
+        self.sender.send_if_modified(|s| {
+            let modified = *s == from;
+            if modified {
+                *s = status;
+            }
+            modified
+        });
+    }
+}
+
+impl Default for StatusWatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_watch() {
+        let status = StatusWatch::new();
+        let mut rx = status.clone().subscribe();
+
+        status.raise(ConnectionStatus::NodeStarting);
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(*rx.borrow_and_update(), ConnectionStatus::NodeStarting);
+
+        status.set(ConnectionStatus::SignerAttached);
+        status.raise(ConnectionStatus::NodeReady);
+        status.revert(
+            ConnectionStatus::NodeStarting,
+            ConnectionStatus::Disconnected,
+        );
+        assert_eq!(*rx.borrow_and_update(), ConnectionStatus::SignerAttached);
+
+        status.set(ConnectionStatus::SignerAttached);
+        assert!(!rx.has_changed().unwrap());
+    }
+}
@@ -1,6 +1,11 @@
+#[cfg(feature = "signer")]
 use crate::{
+    runes::{self, DefRules, RuneFactory},
     scheduler::Scheduler,
     signer::Signer,
+};
+use crate::{
+    secret::SecretBytes,
     tls::{self, TlsConfig},
     utils::get_node_id_from_tls_config,
 };
@@ -9,7 +14,9 @@ use crate::{
 /// They represent the identity of a device and can be encoded into a byte
 /// format for easy storage.
 use log::debug;
-use std::{convert::TryFrom, path::Path};
+#[cfg(feature = "signer")]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{convert::TryFrom, future::Future, path::Path, sync::Arc};
 use thiserror;
 
 const CRED_VERSION: u32 = 1u32;
@@ -39,16 +46,37 @@ pub enum Error {
     ReadFromFileError(#[from] std::io::Error),
     #[error("could not fetch default nobody credentials: {}", .0)]
     FetchDefaultNobodyCredentials(#[source] anyhow::Error),
+    #[error("could not derive session credentials: {}", .0)]
+    DeriveSessionError(String),
+    #[error("invalid signature on the fetched nobody credentials")]
+    NobodySignatureError,
+    #[error("fetched nobody credentials are stale: {}", .0)]
+    NobodyStaleError(String),
+    #[error("could not load credentials on demand: {}", .0)]
+    LoadOnDemandError(#[source] anyhow::Error),
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 pub trait TlsConfigProvider: Send + Sync {
     fn tls_config(&self) -> TlsConfig;
+
+    /// Like [`TlsConfigProvider::tls_config`], but for providers that
+    /// load the identity on demand and may fail to do so. Clients use
+    /// this whenever they connect.
+    fn try_tls_config(&self) -> Result<TlsConfig> {
+        Ok(self.tls_config())
+    }
 }
 
 pub trait RuneProvider {
     fn rune(&self) -> String;
+
+    /// Like [`RuneProvider::rune`], but for providers that may fail to
+    /// load the rune.
+    fn try_rune(&self) -> Result<String> {
+        Ok(self.rune())
+    }
 }
 
 pub trait NodeIdProvider {
@@ -60,25 +88,30 @@ pub trait NodeIdProvider {
 #[derive(Clone, Debug)]
 struct Identity {
     cert: Vec<u8>,
-    key: Vec<u8>,
+    key: SecretBytes,
 }
 
 impl Default for Identity {
     fn default() -> Self {
         let key = load_file_or_default("GL_NOBODY_KEY", NOBODY_KEY)
-            .expect("Could not load file from GL_NOBODY_KEY");
+            .expect("Could not load file from GL_NOBODY_KEY")
+            .into();
         let cert = load_file_or_default("GL_NOBODY_CRT", NOBODY_CRT)
             .expect("Could not load file from GL_NOBODY_CRT");
         Self { cert, key }
     }
 }
 
+/// The path on the scheduler at which the current `Nobody`
+/// credentials are published, see [`Nobody::fetch`].
+pub const NOBODY_BUNDLE_PATH: &str = "/.well-known/greenlight/nobody.json";
+
 /// The `Nobody` credentials struct. This is an unauthenticated set of
 /// credentials and can only be used for registration and recovery.
 #[derive(Clone, Debug)]
 pub struct Nobody {
     pub cert: Vec<u8>,
-    pub key: Vec<u8>,
+    pub key: SecretBytes,
     pub ca: Vec<u8>,
 }
 
@@ -98,7 +131,7 @@ impl Nobody {
 
         Self {
             cert: cert.into(),
-            key: key.into(),
+            key: SecretBytes::new(key.into()),
             ca,
         }
     }
@@ -112,6 +145,143 @@ impl Nobody {
             ..self
         }
     }
+
+    /// Returns a byte encoded representation of the credentials, in the
+    /// format of [`Device::to_bytes`] without a rune.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        model::Data {
+            version: CRED_VERSION,
+            cert: Some(self.cert.clone()),
+            key: Some(self.key.to_vec()),
+            ca: Some(self.ca.clone()),
+            rune: None,
+        }
+        .into()
+    }
+
+    /// Creates a set of `Nobody` credentials from the blob returned by
+    /// [`Nobody::to_bytes`]. Unlike [`Device::from_bytes`] this fails
+    /// if the certificate or the key are missing.
+    pub fn from_bytes(data: impl AsRef<[u8]>) -> Result<Self> {
+        let data = model::Data::try_from(data.as_ref())?;
+        let (cert, key) = match (data.cert, data.key) {
+            (Some(cert), Some(key)) => (cert, key),
+            _ => {
+                return Err(Error::TransformDataIntoCredentialsError(
+                    "missing certificate or key".to_string(),
+                ))
+            }
+        };
+        let creds = Nobody::with(cert, key);
+        Ok(match data.ca {
+            Some(ca) => creds.with_ca(ca),
+            None => creds,
+        })
+    }
+
+    /// Fetches the current `Nobody` credentials and CA from the
+    /// scheduler at `scheduler_uri`, instead of using the ones compiled
+    /// into this crate, so they can be rotated without a new
+    /// release. The bundle is fetched over the web PKI, and is only
+    /// accepted if it is signed by `verifying_key`, an uncompressed
+    /// P-256 public key.
+    ///
+    /// To keep a compromised or stale server from replaying an old
+    /// bundle, it must not be expired, and its version must be at
+    /// least `min_version`. The version of the accepted bundle is
+    /// returned, store it and pass it as `min_version` next time.
+    ///
+    /// The request goes through `http`, use
+    /// [`Config::http_client`](crate::config::Config::http_client)
+    /// to honor the configured proxy and timeouts.
+    pub async fn fetch(
+        http: &reqwest::Client,
+        scheduler_uri: &str,
+        verifying_key: &[u8],
+        min_version: u64,
+    ) -> Result<(Self, u64)> {
+        let url = format!(
+            "{}{}",
+            scheduler_uri.trim_end_matches('/'),
+            NOBODY_BUNDLE_PATH
+        );
+        debug!("Fetching nobody credentials from {}", url);
+        let bundle = async {
+            http.get(&url)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<NobodyBundle>()
+                .await
+        }
+        .await
+        .map_err(|e| Error::FetchDefaultNobodyCredentials(e.into()))?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let version = bundle.version;
+        Ok((bundle.verify(verifying_key, min_version, now)?, version))
+    }
+}
+
+/// The `Nobody` credentials as published by the scheduler.
+#[derive(serde::Deserialize)]
+struct NobodyBundle {
+    /// Increases with every published bundle.
+    version: u64,
+    /// Seconds since the UNIX epoch after which the bundle is no
+    /// longer accepted.
+    expires_at: u64,
+    cert: String,
+    key: String,
+    ca: String,
+    /// Hex encoded fixed-size ECDSA signature over
+    /// [`NobodyBundle::message`].
+    signature: String,
+}
+
+impl NobodyBundle {
+    /// The signed message: the big-endian `u64` version and expiry,
+    /// followed by the PEM encoded certificate, key and CA, each
+    /// prefixed with its big-endian `u32` length.
+    fn message(&self) -> Vec<u8> {
+        let mut msg = vec![];
+        msg.extend(self.version.to_be_bytes());
+        msg.extend(self.expires_at.to_be_bytes());
+        for field in [&self.cert, &self.key, &self.ca] {
+            msg.extend((field.len() as u32).to_be_bytes());
+            msg.extend(field.as_bytes());
+        }
+        msg
+    }
+
+    /// Check the signature, and that the bundle is neither older than
+    /// `min_version` nor expired at `now`.
+    fn verify(self, verifying_key: &[u8], min_version: u64, now: u64) -> Result<Nobody> {
+        use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED};
+        let signature = hex::decode(&self.signature).map_err(|_| Error::NobodySignatureError)?;
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, verifying_key)
+            .verify(&self.message(), &signature)
+            .map_err(|_| Error::NobodySignatureError)?;
+        if self.version < min_version {
+            return Err(Error::NobodyStaleError(format!(
+                "version {} is older than {}",
+                self.version, min_version
+            )));
+        }
+        if self.expires_at <= now {
+            return Err(Error::NobodyStaleError(format!(
+                "expired at {}",
+                self.expires_at
+            )));
+        }
+        Ok(Nobody {
+            cert: self.cert.into_bytes(),
+            key: self.key.into_bytes().into(),
+            ca: self.ca.into_bytes(),
+        })
+    }
 }
 
 impl TlsConfigProvider for Nobody {
@@ -140,7 +310,7 @@ impl Default for Nobody {
 pub struct Device {
     pub version: u32,
     pub cert: Vec<u8>,
-    pub key: Vec<u8>,
+    pub key: SecretBytes,
     pub ca: Vec<u8>,
     pub rune: String,
 }
@@ -157,7 +327,7 @@ impl Device {
                 creds.cert = cert
             }
             if let Some(key) = data.key {
-                creds.key = key
+                creds.key = key.into()
             }
             if let Some(ca) = data.ca {
                 creds.ca = ca
@@ -191,7 +361,7 @@ impl Device {
         Self {
             version: CRED_VERSION,
             cert: cert.into(),
-            key: key.into(),
+            key: SecretBytes::new(key.into()),
             rune: rune.into(),
             ca
         }
@@ -207,8 +377,41 @@ impl Device {
         }
     }
 
+    /// Returns the same device identity with `rune` in place of the
+    /// current rune, e.g., a read-only rune carved for a dashboard. The
+    /// node still checks the rune, so this can only narrow access.
+    pub fn with_rune<S>(self, rune: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Device {
+            rune: rune.into(),
+            ..self
+        }
+    }
+
+    /// Derives short-lived credentials for a semi-trusted component,
+    /// such as a web view or support tooling. They share the TLS
+    /// identity, but carry a rune carved from this one with `rules`,
+    /// that expires after `ttl`.
+    #[cfg(feature = "signer")]
+    pub fn derive_session(&self, ttl: Duration, rules: &[DefRules]) -> Result<Self> {
+        let err = |e: &dyn std::fmt::Display| Error::DeriveSessionError(e.to_string());
+        let origin = runes::decode_rune(&self.rune).map_err(|e| err(&e))?;
+        let expiry = (SystemTime::now() + ttl)
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| err(&e))?
+            .as_secs();
+
+        let mut rules = rules.to_vec();
+        rules.push(DefRules::Until(expiry));
+        let rune = RuneFactory::carve(&origin, &rules).map_err(|e| err(&e))?;
+        Ok(self.clone().with_rune(rune))
+    }
+
     /// Asynchronously upgrades the credentials using the provided scheduler and
     /// signer, potentially involving network operations or other async tasks.
+    #[cfg(feature = "signer")]
     pub async fn upgrade<T>(mut self, _scheduler: &Scheduler<T>, signer: &Signer) -> Result<Self>
     where
         T: TlsConfigProvider,
@@ -253,7 +456,7 @@ impl TlsConfigProvider for Device {
 
 impl RuneProvider for Device {
     fn rune(&self) -> String {
-        self.to_owned().rune
+        self.rune.clone()
     }
 }
 
@@ -267,6 +470,151 @@ impl NodeIdProvider for Device {
     }
 }
 
+type Source<T> = Arc<dyn Fn() -> anyhow::Result<T> + Send + Sync>;
+
+/// Credentials whose certificate, key and rune are loaded on demand,
+/// e.g., from Vault or a KMS, rather than being held in memory for the
+/// lifetime of the process. They are loaded whenever a client
+/// connects, which then holds the identity while it is connected.
+#[derive(Clone)]
+pub struct OnDemand {
+    cert: Source<Vec<u8>>,
+    key: Source<SecretBytes>,
+    rune: Source<String>,
+    ca: Vec<u8>,
+}
+
+impl OnDemand {
+    /// Creates credentials from closures returning the device
+    /// certificate, its private key, and the rune respectively. Their
+    /// errors are returned by the clients that fail to connect.
+    pub fn new<C, K, R>(cert: C, key: K, rune: R) -> Result<Self>
+    where
+        C: Fn() -> anyhow::Result<Vec<u8>> + Send + Sync + 'static,
+        K: Fn() -> anyhow::Result<SecretBytes> + Send + Sync + 'static,
+        R: Fn() -> anyhow::Result<String> + Send + Sync + 'static,
+    {
+        let ca = load_file_or_default("GL_CA_CRT", CA_RAW)?;
+        Ok(OnDemand {
+            cert: Arc::new(cert),
+            key: Arc::new(key),
+            rune: Arc::new(rune),
+            ca,
+        })
+    }
+
+    /// Like [`OnDemand::new`], but with async closures, e.g., for
+    /// clients of a secrets manager. They are run to completion on a
+    /// runtime of their own, so the credentials can be used from sync
+    /// code as well as from any tokio runtime.
+    pub fn from_async<C, K, R, FC, FK, FR>(cert: C, key: K, rune: R) -> Result<Self>
+    where
+        C: Fn() -> FC + Send + Sync + 'static,
+        K: Fn() -> FK + Send + Sync + 'static,
+        R: Fn() -> FR + Send + Sync + 'static,
+        FC: Future<Output = anyhow::Result<Vec<u8>>> + Send + 'static,
+        FK: Future<Output = anyhow::Result<SecretBytes>> + Send + 'static,
+        FR: Future<Output = anyhow::Result<String>> + Send + 'static,
+    {
+        let rt = Arc::new(SourceRuntime::new()?);
+        let (rt_key, rt_rune) = (rt.clone(), rt.clone());
+        Self::new(
+            move || rt.block_on(cert()),
+            move || rt_key.block_on(key()),
+            move || rt_rune.block_on(rune()),
+        )
+    }
+
+    pub fn with_ca<V>(self, ca: V) -> Self
+    where
+        V: Into<Vec<u8>>,
+    {
+        OnDemand {
+            ca: ca.into(),
+            ..self
+        }
+    }
+}
+
+/// The runtime the sources of [`OnDemand::from_async`] run on. It is
+/// separate from the caller's, since blocking on the caller's runtime
+/// panics on a current-thread one, and outside of any.
+struct SourceRuntime(Option<tokio::runtime::Runtime>);
+
+impl SourceRuntime {
+    fn new() -> Result<Self> {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("gl-credentials")
+            .enable_all()
+            .build()
+            .map_err(|e| Error::LoadOnDemandError(e.into()))?;
+        Ok(SourceRuntime(Some(rt)))
+    }
+
+    /// Run `f` to completion, blocking the calling thread.
+    fn block_on<T, F>(&self, f: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: Future<Output = anyhow::Result<T>> + Send + 'static,
+    {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let rt = self.0.as_ref().expect("the runtime is only taken on drop");
+        rt.spawn(async move {
+            let _ = tx.send(f.await);
+        });
+        rx.recv()
+            .map_err(|_| anyhow::anyhow!("the credentials source panicked"))?
+    }
+}
+
+impl Drop for SourceRuntime {
+    /// Dropping a runtime blocks, which panics if the credentials are
+    /// dropped within an async context.
+    fn drop(&mut self) {
+        if let Some(rt) = self.0.take() {
+            rt.shutdown_background();
+        }
+    }
+}
+
+impl TlsConfigProvider for OnDemand {
+    /// Panics if the identity cannot be loaded, clients use
+    /// [`TlsConfigProvider::try_tls_config`] instead.
+    fn tls_config(&self) -> TlsConfig {
+        self.try_tls_config()
+            .expect("loading the TLS identity on demand")
+    }
+
+    fn try_tls_config(&self) -> Result<TlsConfig> {
+        let cert = (self.cert)().map_err(Error::LoadOnDemandError)?;
+        let key = (self.key)().map_err(Error::LoadOnDemandError)?;
+        Ok(tls::TlsConfig::with(cert, key, self.ca.clone()))
+    }
+}
+
+impl RuneProvider for OnDemand {
+    /// Panics if the rune cannot be loaded, clients use
+    /// [`RuneProvider::try_rune`] instead.
+    fn rune(&self) -> String {
+        self.try_rune().expect("loading the rune on demand")
+    }
+
+    fn try_rune(&self) -> Result<String> {
+        (self.rune)().map_err(Error::LoadOnDemandError)
+    }
+}
+
+impl NodeIdProvider for OnDemand {
+    fn node_id(&self) -> Result<Vec<u8>> {
+        get_node_id_from_tls_config(&self.try_tls_config()?).map_err(|_e| {
+            Error::GetFromIdentityError(
+                "node_id could not be retrieved from the certificate".to_string(),
+            )
+        })
+    }
+}
+
 impl From<Device> for Vec<u8> {
     fn from(value: Device) -> Self {
         let data: model::Data = value.into();
@@ -279,7 +627,7 @@ impl From<Device> for model::Data {
         model::Data {
             version: CRED_VERSION,
             cert: Some(device.cert),
-            key: Some(device.key),
+            key: Some(device.key.to_vec()),
             ca: Some(device.ca),
             rune: Some(device.rune),
         }
@@ -355,6 +703,96 @@ fn load_file_or_default(varname: &str, default: &[u8]) -> Result<Vec<u8>> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_verify_nobody_bundle() {
+        use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let keypair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref()).unwrap();
+
+        let verifying_key = keypair.public_key().as_ref().to_vec();
+        let sign = |version: u64, ca: &str| {
+            let mut bundle = NobodyBundle {
+                version,
+                expires_at: 1000,
+                cert: "cert".to_string(),
+                key: "key".to_string(),
+                ca: ca.to_string(),
+                signature: String::new(),
+            };
+            bundle.signature = hex::encode(keypair.sign(&rng, &bundle.message()).unwrap());
+            bundle
+        };
+
+        let nobody = sign(2, "ca").verify(&verifying_key, 2, 999).unwrap();
+        assert_eq!(nobody.cert, b"cert");
+        assert_eq!(&nobody.key[..], b"key");
+        assert_eq!(nobody.ca, b"ca");
+
+        let mut tampered = sign(2, "ca");
+        tampered.ca = "other ca".to_string();
+        assert!(matches!(
+            tampered.verify(&verifying_key, 2, 999),
+            Err(Error::NobodySignatureError)
+        ));
+        // The version and expiry are signed too.
+        let mut tampered = sign(1, "ca");
+        tampered.version = 2;
+        assert!(matches!(
+            tampered.verify(&verifying_key, 2, 999),
+            Err(Error::NobodySignatureError)
+        ));
+
+        assert!(matches!(
+            sign(1, "ca").verify(&verifying_key, 2, 999),
+            Err(Error::NobodyStaleError(_))
+        ));
+        assert!(matches!(
+            sign(2, "ca").verify(&verifying_key, 2, 1000),
+            Err(Error::NobodyStaleError(_))
+        ));
+    }
+
+    #[test]
+    fn test_on_demand() {
+        let device = Device::default();
+        let (cert, key) = (device.cert.clone(), device.key.clone());
+        let creds = OnDemand::new(
+            move || Ok(cert.clone()),
+            move || Ok(key.clone()),
+            || Err(anyhow::anyhow!("vault is sealed")),
+        )
+        .unwrap();
+        assert!(matches!(creds.try_rune(), Err(Error::LoadOnDemandError(_))));
+        assert_eq!(
+            creds.tls_config().private_key,
+            device.tls_config().private_key
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_on_demand_async() {
+        let device = Device::default();
+        let (cert, key) = (device.cert.clone(), device.key.clone());
+        let creds = OnDemand::from_async(
+            move || futures::future::ready(Ok(cert.clone())),
+            move || futures::future::ready(Ok(key.clone())),
+            || async { Ok("rune".to_string()) },
+        )
+        .unwrap();
+        // Neither from within a current-thread runtime, nor from
+        // outside of any.
+        assert_eq!(creds.try_rune().unwrap(), "rune");
+        assert_eq!(creds.try_rune().unwrap(), "rune");
+        let other = creds.clone();
+        let tls = std::thread::spawn(move || other.try_tls_config().unwrap());
+        assert!(tls.join().unwrap().x509_cert.is_some());
+
+        // Nor when the runtime is dropped.
+        drop(creds);
+    }
+
     #[test]
     fn test_encode() {
         let cert: Vec<u8> = vec![99, 98];
@@ -384,6 +822,51 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_with_rune() {
+        let device = Device::with(vec![99, 98], vec![97, 96], "rune");
+        let narrowed = device.clone().with_rune("narrow");
+        assert_eq!(narrowed.rune(), "narrow");
+        assert_eq!(narrowed.cert, device.cert);
+        assert_eq!(narrowed.key, device.key);
+
+        let decoded = Device::from_bytes(narrowed.to_bytes());
+        assert_eq!(decoded.rune, "narrow");
+        assert_eq!(decoded.cert, device.cert);
+    }
+
+    #[test]
+    fn test_nobody_bytes() {
+        let nobody = Nobody::with(vec![99, 98], vec![97, 96]).with_ca(vec![95, 94]);
+        let decoded = Nobody::from_bytes(nobody.to_bytes()).unwrap();
+        assert_eq!(decoded.cert, nobody.cert);
+        assert_eq!(decoded.key, nobody.key);
+        assert_eq!(decoded.ca, nobody.ca);
+
+        let device = Device::with(vec![99, 98], vec![97, 96], "rune");
+        assert!(Nobody::from_bytes(device.to_bytes()).is_ok());
+        assert!(Nobody::from_bytes(Vec::new()).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "signer")]
+    fn test_derive_session() {
+        let mr = runeauth::Rune::new_master_rune(&[0; 32], vec![], None, None).unwrap();
+        let device = Device::with(vec![99, 98], vec![97, 96], mr.to_base64());
+
+        let session = device
+            .derive_session(Duration::from_secs(60), &[DefRules::ReadOnly])
+            .unwrap();
+        assert_eq!(session.cert, device.cert);
+        let rune = runeauth::Rune::from_base64(&session.rune).unwrap();
+        assert!(mr.is_authorized(&rune));
+        assert!(rune.to_string().contains("&time<"));
+
+        assert!(Device::default()
+            .derive_session(Duration::from_secs(60), &[])
+            .is_err());
+    }
+
     #[test]
     fn test_decode() {
         let data: Vec<u8> = vec![
@@ -0,0 +1,139 @@
+//! Abandon slow operations consistently on the client and the servers.
+//!
+//! Operations such as paying an invoice take several calls: the node
+//! is scheduled, then the payment is sent. A timeout on each call
+//! leaves the total open, and once the client gives up, the scheduler
+//! and the node keep working on calls nobody waits for anymore. A
+//! deadline applies to all calls made within [`with_deadline`]:
+//!
+//! ```no_run
+//! # use gl_client::deadline;
+//! # use gl_client::scheduler::Scheduler;
+//! # use gl_client::credentials::Device;
+//! # use gl_client::node::ClnClient;
+//! # use gl_client::pb::cln::GetinfoRequest;
+//! # use std::time::Duration;
+//! # async fn example(scheduler: Scheduler<Device>) -> anyhow::Result<()> {
+//! let info = deadline::with_timeout(Duration::from_secs(10), async {
+//!     let mut node: ClnClient = scheduler.node().await?;
+//!     Ok::<_, anyhow::Error>(node.getinfo(GetinfoRequest::default()).await?)
+//! })
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each call carries the time remaining in the standard
+//! `grpc-timeout` header, so the scheduler and the node drop the call
+//! when the client gives up on it, and fails with
+//! `DEADLINE_EXCEEDED` on the client once the deadline passes.
+use http::{HeaderMap, HeaderValue};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tonic::Status;
+
+const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
+
+tokio::task_local! {
+    static DEADLINE: Instant;
+}
+
+/// Run `fut`, with all scheduler and node calls it makes abandoned at
+/// `deadline`. Nested deadlines can only shorten the outer one.
+pub async fn with_deadline<F: Future>(deadline: Instant, fut: F) -> F::Output {
+    let deadline = current().map_or(deadline, |outer| outer.min(deadline));
+    DEADLINE.scope(deadline, fut).await
+}
+
+/// Like [`with_deadline`], with the deadline `timeout` from now.
+pub async fn with_timeout<F: Future>(timeout: Duration, fut: F) -> F::Output {
+    with_deadline(Instant::now() + timeout, fut).await
+}
+
+/// The deadline of the calls made by the current task, if any.
+pub fn current() -> Option<Instant> {
+    DEADLINE.try_with(|d| *d).ok()
+}
+
+/// Put the time remaining until `deadline` on the headers of a call,
+/// failing if there is none left.
+#[allow(clippy::result_large_err)]
+pub(crate) fn stamp(deadline: Option<Instant>, headers: &mut HeaderMap) -> Result<(), Status> {
+    let deadline = match deadline {
+        Some(d) => d,
+        None => return Ok(()),
+    };
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return Err(Status::deadline_exceeded("deadline passed before the call"));
+    }
+    // The value is ASCII digits and a unit, which is always valid.
+    headers.insert(
+        GRPC_TIMEOUT_HEADER,
+        HeaderValue::from_str(&encode(remaining)).unwrap(),
+    );
+    Ok(())
+}
+
+/// Resolves once `deadline` passed, never if there is none.
+pub(crate) async fn expired(deadline: Option<Instant>) -> Status {
+    match deadline {
+        Some(d) => tokio::time::sleep_until(d.into()).await,
+        None => futures::future::pending().await,
+    }
+    Status::deadline_exceeded("deadline passed while waiting for the response")
+}
+
+/// Encode `timeout` as the value of the `grpc-timeout` header, which
+/// allows at most 8 digits. Rounds up, so servers do not give up
+/// before the client.
+fn encode(timeout: Duration) -> String {
+    let units: [(&str, u128); 4] = [
+        ("m", 1_000_000),
+        ("S", 1_000_000_000),
+        ("M", 60 * 1_000_000_000),
+        ("H", 60 * 60 * 1_000_000_000),
+    ];
+    let nanos = timeout.as_nanos();
+    for (unit, scale) in units.iter() {
+        let value = nanos.div_ceil(*scale);
+        if value < 100_000_000 {
+            return format!("{}{}", value, unit);
+        }
+    }
+    "99999999H".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(Duration::from_millis(1500)), "1500m");
+        assert_eq!(encode(Duration::from_micros(1)), "1m");
+        assert_eq!(encode(Duration::from_secs(200_000)), "200000S");
+    }
+
+    #[tokio::test]
+    async fn test_nested_deadlines() {
+        assert_eq!(current(), None);
+        let outer = Instant::now() + Duration::from_secs(5);
+        with_deadline(outer, async {
+            with_timeout(Duration::from_secs(60), async {
+                assert_eq!(current(), Some(outer));
+            })
+            .await;
+
+            let mut headers = HeaderMap::new();
+            stamp(current(), &mut headers).unwrap();
+            assert!(headers.contains_key(GRPC_TIMEOUT_HEADER));
+        })
+        .await;
+
+        let passed = Instant::now() - Duration::from_millis(1);
+        let mut headers = HeaderMap::new();
+        let err = stamp(Some(passed), &mut headers).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::DeadlineExceeded);
+    }
+}
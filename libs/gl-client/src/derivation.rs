@@ -0,0 +1,189 @@
+//! The keys greenlight derives from the node seed.
+//!
+//! Alternative signer implementations must derive the same keys from
+//! the 32 byte seed to be interchangeable with [`crate::signer`]. The
+//! node key and the onchain wallet follow `lightningd`'s `hsmd`, the
+//! other keys are specific to greenlight:
+//!
+//!  - node key: `HKDF-SHA256(seed, info="nodeid")`.
+//!  - BIP32 master key: from the seed `HKDF-SHA256(seed, info="bip32
+//!    seed")`. The onchain wallet uses the account key at `m/0/0`,
+//!    addresses are its children `m/0/0/i`.
+//!  - rune secret: `HKDF-SHA256(seed, info="gl-commando")`.
+//!  - swap keys: `HKDF-SHA256(seed, info="gl-swaps", salt=index)`,
+//!    the index being big-endian `u32`.
+//!  - LNURL-auth linking keys: LUD-05 from the BIP32 master key.
+//!  - decoy seed for duress mode: `HKDF-SHA256(seed,
+//!    info="greenlight/duress/decoy")`.
+//!
+//! TLS device keys are not derived: every device generates a fresh
+//! key pair when registering or recovering. What binds the device to
+//! the node is a signature of the scheduler's challenge with the node
+//! key, over [`challenge_hash`].
+use crate::bitcoin::hashes::{hmac, sha256, sha256d, Hash, HashEngine};
+use crate::bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use crate::bitcoin::util::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey};
+use crate::bitcoin::Network;
+use anyhow::Result;
+use lightning_signer::util::crypto_utils::hkdf_sha256;
+use zeroize::Zeroizing;
+
+pub const NODE_KEY_INFO: &str = "nodeid";
+pub const BIP32_SEED_INFO: &str = "bip32 seed";
+// This is the same derivation key that is used by core lightning itself.
+pub const RUNE_SECRET_INFO: &str = "gl-commando";
+pub const SWAP_KEY_INFO: &str = "gl-swaps";
+pub const DECOY_SEED_INFO: &str = "greenlight/duress/decoy";
+
+/// Path of the onchain wallet's account key.
+pub const WALLET_ACCOUNT_PATH: [ChildNumber; 2] = [
+    ChildNumber::Normal { index: 0 },
+    ChildNumber::Normal { index: 0 },
+];
+
+/// Path of the LNURL-auth hashing key, `m/138'/0`.
+pub const LNURL_AUTH_HASHING_PATH: [ChildNumber; 2] = [
+    ChildNumber::Hardened { index: 138 },
+    ChildNumber::Normal { index: 0 },
+];
+
+/// Prefixed to messages before they are signed with the node key.
+pub const SIGNED_MESSAGE_PREFIX: &str = "Lightning Signed Message:";
+
+pub fn node_key(seed: &[u8; 32]) -> Result<SecretKey> {
+    let key = Zeroizing::new(hkdf_sha256(seed, NODE_KEY_INFO.as_bytes(), &[]));
+    Ok(SecretKey::from_slice(&key[..])?)
+}
+
+pub fn node_id(seed: &[u8; 32]) -> Result<PublicKey> {
+    Ok(PublicKey::from_secret_key(
+        &Secp256k1::signing_only(),
+        &node_key(seed)?,
+    ))
+}
+
+/// The BIP32 master key. The key material does not depend on
+/// `network`, it only selects the serialization.
+pub fn bip32_master(seed: &[u8; 32], network: Network) -> Result<ExtendedPrivKey> {
+    let bip32_seed = Zeroizing::new(hkdf_sha256(seed, BIP32_SEED_INFO.as_bytes(), &[]));
+    Ok(ExtendedPrivKey::new_master(network, &bip32_seed[..])?)
+}
+
+/// The account key of the onchain wallet.
+pub fn wallet_account_key(seed: &[u8; 32], network: Network) -> Result<ExtendedPrivKey> {
+    let path = DerivationPath::from(&WALLET_ACCOUNT_PATH[..]);
+    Ok(bip32_master(seed, network)?.derive_priv(&Secp256k1::new(), &path)?)
+}
+
+/// The secret of the master rune.
+pub fn rune_secret(seed: &[u8; 32]) -> Zeroizing<[u8; 32]> {
+    Zeroizing::new(hkdf_sha256(seed, RUNE_SECRET_INFO.as_bytes(), &[]))
+}
+
+/// The key for the `index`-th swap, see [`crate::swaps`].
+pub fn swap_key(seed: &[u8; 32], index: u32) -> Result<SecretKey> {
+    let key = Zeroizing::new(hkdf_sha256(
+        seed,
+        SWAP_KEY_INFO.as_bytes(),
+        &index.to_be_bytes(),
+    ));
+    Ok(SecretKey::from_slice(&key[..])?)
+}
+
+/// The LNURL-auth linking key for `domain`, as specified in LUD-05.
+pub fn lnurl_auth_linking_key(seed: &[u8; 32], domain: &str) -> Result<SecretKey> {
+    let secp = Secp256k1::new();
+    let master = bip32_master(seed, Network::Bitcoin)?;
+    let hashing_key =
+        master.derive_priv(&secp, &DerivationPath::from(&LNURL_AUTH_HASHING_PATH[..]))?;
+
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(&hashing_key.private_key.secret_bytes());
+    engine.input(domain.as_bytes());
+    let hash = hmac::Hmac::<sha256::Hash>::from_engine(engine).into_inner();
+
+    let mut path = vec![LNURL_AUTH_HASHING_PATH[0]];
+    for chunk in hash[..16].chunks(4) {
+        let index = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        path.push(ChildNumber::from(index));
+    }
+    Ok(master
+        .derive_priv(&secp, &DerivationPath::from(path))?
+        .private_key)
+}
+
+/// The seed of the decoy node, see [`crate::signer::DuressConfig`].
+pub fn decoy_seed(seed: &[u8; 32]) -> Zeroizing<[u8; 32]> {
+    Zeroizing::new(hkdf_sha256(seed, DECOY_SEED_INFO.as_bytes(), &[]))
+}
+
+/// The digest of `challenge` that is signed with the node key, e.g.,
+/// to bootstrap a device certificate.
+pub fn challenge_hash(challenge: &[u8]) -> [u8; 32] {
+    sha256d::Hash::hash(&[SIGNED_MESSAGE_PREFIX.as_bytes(), challenge].concat()).into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::util::bip32::ExtendedPubKey;
+
+    const SEED: [u8; 32] = [0; 32];
+
+    fn public(key: &SecretKey) -> String {
+        PublicKey::from_secret_key(&Secp256k1::new(), key).to_string()
+    }
+
+    #[test]
+    fn test_vectors() {
+        assert_eq!(
+            node_id(&SEED).unwrap().to_string(),
+            "02058e8b6c2ad363ec59aa136429256d745164c2bdc87f98f0a68690ec2c5c9b0b"
+        );
+        let secp = Secp256k1::new();
+        assert_eq!(
+            ExtendedPubKey::from_priv(&secp, &bip32_master(&SEED, Network::Bitcoin).unwrap())
+                .public_key
+                .to_string(),
+            "021ac163598a9026d46e866a972850cc8deb4d0539d4ae63ba0b457f4936461222"
+        );
+        assert_eq!(
+            ExtendedPubKey::from_priv(&secp, &wallet_account_key(&SEED, Network::Bitcoin).unwrap())
+                .public_key
+                .to_string(),
+            "03a2551256f0b0b1545ef15c40af45a592654fb4c31b7508426e6424f67330c1bd"
+        );
+        assert_eq!(
+            hex::encode(&rune_secret(&SEED)[..]),
+            "42bcdfba3bc96ab8f55f3af2b841beda44c6c9dffc859b7c959e10393b174e80"
+        );
+        assert_eq!(
+            public(&swap_key(&SEED, 0).unwrap()),
+            "036f165953038d2aa4f405016114089a5b18c7423eef71ca2a2b7ca4c6544a8242"
+        );
+        assert_eq!(
+            public(&lnurl_auth_linking_key(&SEED, "site.com").unwrap()),
+            "022b741b6e55db7d5f631ec14408332222434154923be0ae2e52cba830fe89cd95"
+        );
+        assert_eq!(
+            hex::encode(challenge_hash(&[0; 32])),
+            "5ea79abdfeec232a59afd1b2799f2520ea3ac0f2ed2c27c7d19ab4b4b0e0732d"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "signer")]
+    fn test_signer_keys() {
+        use crate::credentials::Nobody;
+        use crate::signer::Signer;
+
+        let signer = Signer::new(SEED.to_vec(), Network::Bitcoin, Nobody::new()).unwrap();
+        assert_eq!(
+            signer.node_id(),
+            node_id(&SEED).unwrap().serialize().to_vec()
+        );
+        assert_eq!(
+            signer.swap_pubkey(3).unwrap().to_string(),
+            public(&swap_key(&SEED, 3).unwrap())
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! Events emitted by the client library.
+//!
+//! Components that detect something the application should know
+//! about, but that is not the direct result of a call, publish an
+//! [`Event`] on an [`EventBus`]. Applications subscribe to the bus
+//! and react as they see fit, e.g., by showing a notification.
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Number of events buffered per subscriber before the oldest ones
+/// are dropped.
+const CAPACITY: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub enum Event {
+    /// The static channel backup is missing some of the node's
+    /// channels, and would not recover their funds.
+    BackupStale { missing_channels: Vec<Vec<u8>> },
+
+    /// Another signer attached to the node claimed a signature
+    /// request, so this signer observes it until it is answered, or
+    /// the claim expires.
+    SignerConflict { request_id: u32, holder: Vec<u8> },
+
+    /// The node attached a grpc call to a signature request that the
+    /// signer does not know how to verify. The node should not do
+    /// this once the signer advertised its capabilities.
+    UnsupportedRequest { uri: String },
+
+    /// The amount the node can receive over its channels dropped
+    /// below the configured threshold.
+    LowInboundLiquidity {
+        receivable_msat: u64,
+        threshold_msat: u64,
+    },
+
+    /// [`Node::rebalance`] sent a payment over a circular route.
+    ///
+    /// [`Node::rebalance`]: crate::node::Node::rebalance
+    RebalanceAttempt {
+        payment_hash: Vec<u8>,
+        attempt: u32,
+        fee_msat: u64,
+    },
+
+    /// The circular payment of a rebalance completed.
+    RebalanceCompleted { payment_hash: Vec<u8>, fee_msat: u64 },
+
+    /// The signer could not reach the scheduler or the node for
+    /// longer than the configured threshold, so the node can not sign
+    /// off on payments.
+    SignerOffline { offline_secs: u64 },
+
+    /// The signer reached the node again after [`Event::SignerOffline`].
+    SignerReconnected { offline_secs: u64 },
+}
+
+/// A broadcast channel for [`Event`]s.
+///
+/// Cloning the bus is cheap, and all clones publish to the same
+/// subscribers.
+#[derive(Clone, Debug)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        EventBus { sender }
+    }
+
+    /// Publish an event. Events published while there are no
+    /// subscribers are discarded.
+    pub fn publish(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Receive all events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
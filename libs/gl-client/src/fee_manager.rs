@@ -0,0 +1,313 @@
+//! Adjust the routing fees of the node's channels.
+//!
+//! Nodes that route payments need to adapt their fees to how their
+//! channels are used, which usually requires running tools such as
+//! `charge-lnd` next to the node. The [`FeeManager`] applies a
+//! [`FeeStrategy`] to every active channel at a fixed interval, and
+//! uses `setchannel` to update the fees that changed.
+use crate::amount::Msat;
+use crate::channels::ChannelState;
+use crate::node::ClnClient;
+use crate::pb::cln::{
+    listforwards_request::ListforwardsStatus, Amount, ListchannelsRequest, ListforwardsRequest,
+    ListpeerchannelsChannels, ListpeerchannelsRequest, SetchannelRequest,
+};
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::time::SystemTime;
+use tokio::time::{sleep, Duration};
+
+/// The fees charged for forwarding over a channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Fees {
+    pub base_msat: u64,
+    pub ppm: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FeeStrategy {
+    /// Charge the same fees on all channels.
+    Static(Fees),
+
+    /// Raise the proportional fee by `step_ppm` on channels that
+    /// forwarded payments since the last run, and lower it on the
+    /// ones that did not, staying within `min_ppm` and `max_ppm`.
+    FlowBased {
+        base_msat: u64,
+        min_ppm: u32,
+        max_ppm: u32,
+        step_ppm: u32,
+    },
+
+    /// Charge what the peer charges for the opposite direction, with
+    /// the proportional fee kept within `min_ppm` and `max_ppm`.
+    MatchPeer { min_ppm: u32, max_ppm: u32 },
+}
+
+/// What a strategy knows about a channel when picking its fees.
+#[derive(Clone, Debug, Default)]
+pub struct ChannelContext {
+    pub current: Fees,
+    /// Whether a payment was forwarded out over the channel since the
+    /// last run.
+    pub forwarded_out: bool,
+    /// The fees the peer charges to forward to us, from gossip.
+    pub peer_fees: Option<Fees>,
+}
+
+impl FeeStrategy {
+    /// The fees the channel should have.
+    pub fn target(&self, ctx: &ChannelContext) -> Fees {
+        match self {
+            FeeStrategy::Static(fees) => *fees,
+            FeeStrategy::FlowBased {
+                base_msat,
+                min_ppm,
+                max_ppm,
+                step_ppm,
+            } => {
+                let ppm = if ctx.forwarded_out {
+                    ctx.current.ppm.saturating_add(*step_ppm)
+                } else {
+                    ctx.current.ppm.saturating_sub(*step_ppm)
+                };
+                Fees {
+                    base_msat: *base_msat,
+                    ppm: ppm.clamp(*min_ppm, *max_ppm),
+                }
+            }
+            FeeStrategy::MatchPeer { min_ppm, max_ppm } => match ctx.peer_fees {
+                Some(peer) => Fees {
+                    base_msat: peer.base_msat,
+                    ppm: peer.ppm.clamp(*min_ppm, *max_ppm),
+                },
+                None => ctx.current,
+            },
+        }
+    }
+}
+
+/// A fee change applied to a channel.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FeeUpdate {
+    pub short_channel_id: String,
+    pub previous: Fees,
+    pub current: Fees,
+}
+
+pub struct FeeManager {
+    strategy: FeeStrategy,
+    interval: Duration,
+    /// When the last run started, in seconds since the epoch, to find
+    /// the forwards since then.
+    last_run: f64,
+}
+
+impl FeeManager {
+    pub fn new(strategy: FeeStrategy, interval: Duration) -> Self {
+        FeeManager {
+            strategy,
+            interval,
+            // The first run looks at the forwards of one interval.
+            last_run: now() - interval.as_secs_f64(),
+        }
+    }
+
+    /// Apply the strategy to all active channels once.
+    pub async fn adjust(&mut self, node: &mut ClnClient) -> Result<Vec<FeeUpdate>> {
+        let started = now();
+        let channels = node
+            .list_peer_channels(ListpeerchannelsRequest::default())
+            .await
+            .map_err(|e| anyhow!(e))?
+            .into_inner()
+            .channels;
+
+        let forwarded_out = match self.strategy {
+            FeeStrategy::FlowBased { .. } => self.forwarded_out(node).await?,
+            _ => HashSet::new(),
+        };
+
+        let mut updates = vec![];
+        for c in channels
+            .iter()
+            .filter(|c| ChannelState::of(c) == Some(ChannelState::ChanneldNormal))
+        {
+            let scid = match &c.short_channel_id {
+                Some(scid) => scid.clone(),
+                None => continue,
+            };
+            let ctx = ChannelContext {
+                current: current_fees(c),
+                forwarded_out: forwarded_out.contains(&scid),
+                peer_fees: match self.strategy {
+                    FeeStrategy::MatchPeer { .. } => peer_fees(node, c, &scid).await?,
+                    _ => None,
+                },
+            };
+
+            let target = self.strategy.target(&ctx);
+            if target == ctx.current {
+                continue;
+            }
+            debug!(
+                "Updating fees of {} from {:?} to {:?}",
+                scid, ctx.current, target
+            );
+            node.set_channel(SetchannelRequest {
+                id: scid.clone(),
+                feebase: Some(Amount {
+                    msat: target.base_msat,
+                }),
+                feeppm: Some(target.ppm),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| anyhow!(e))?;
+            updates.push(FeeUpdate {
+                short_channel_id: scid,
+                previous: ctx.current,
+                current: target,
+            });
+        }
+
+        self.last_run = started;
+        Ok(updates)
+    }
+
+    /// The channels that forwarded payments out since the last run.
+    async fn forwarded_out(&self, node: &mut ClnClient) -> Result<HashSet<String>> {
+        let forwards = node
+            .list_forwards(ListforwardsRequest {
+                status: Some(ListforwardsStatus::Settled as i32),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| anyhow!(e))?
+            .into_inner()
+            .forwards;
+        Ok(forwards
+            .into_iter()
+            .filter(|f| f.received_time >= self.last_run)
+            .filter_map(|f| f.out_channel)
+            .collect())
+    }
+
+    /// Adjust the fees now, and then every interval. Failed runs
+    /// are logged and retried on the next interval.
+    pub async fn run(mut self, mut node: ClnClient) {
+        loop {
+            if let Err(e) = self.adjust(&mut node).await {
+                warn!("Could not adjust channel fees: {}", e);
+            }
+            sleep(self.interval).await;
+        }
+    }
+}
+
+pub(crate) fn current_fees(c: &ListpeerchannelsChannels) -> Fees {
+    Fees {
+        base_msat: Msat::from(&c.fee_base_msat).to_msat(),
+        ppm: c.fee_proportional_millionths.unwrap_or(0),
+    }
+}
+
+/// The fees our peer announced for its direction of the channel.
+async fn peer_fees(
+    node: &mut ClnClient,
+    c: &ListpeerchannelsChannels,
+    scid: &str,
+) -> Result<Option<Fees>> {
+    let peer_id = match &c.peer_id {
+        Some(id) => id.clone(),
+        None => return Ok(None),
+    };
+    let channels = node
+        .list_channels(ListchannelsRequest {
+            short_channel_id: Some(scid.to_string()),
+            source: None,
+            destination: None,
+        })
+        .await
+        .map_err(|e| anyhow!(e))?
+        .into_inner()
+        .channels;
+    Ok(channels
+        .into_iter()
+        .find(|h| h.source == peer_id)
+        .map(|h| Fees {
+            base_msat: h.base_fee_millisatoshi as u64,
+            ppm: h.fee_per_millionth,
+        }))
+}
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flow_based() {
+        let strategy = FeeStrategy::FlowBased {
+            base_msat: 1000,
+            min_ppm: 10,
+            max_ppm: 500,
+            step_ppm: 50,
+        };
+        let mut ctx = ChannelContext {
+            current: Fees {
+                base_msat: 0,
+                ppm: 480,
+            },
+            forwarded_out: true,
+            peer_fees: None,
+        };
+        assert_eq!(
+            strategy.target(&ctx),
+            Fees {
+                base_msat: 1000,
+                ppm: 500
+            }
+        );
+        ctx.forwarded_out = false;
+        ctx.current.ppm = 40;
+        assert_eq!(strategy.target(&ctx).ppm, 10);
+    }
+
+    #[test]
+    fn test_match_peer() {
+        let strategy = FeeStrategy::MatchPeer {
+            min_ppm: 0,
+            max_ppm: 1000,
+        };
+        let mut ctx = ChannelContext {
+            current: Fees {
+                base_msat: 1,
+                ppm: 1,
+            },
+            ..Default::default()
+        };
+        // Without gossip for the peer's side the fees are unchanged.
+        assert_eq!(strategy.target(&ctx), ctx.current);
+
+        ctx.peer_fees = Some(Fees {
+            base_msat: 500,
+            ppm: 5000,
+        });
+        assert_eq!(
+            strategy.target(&ctx),
+            Fees {
+                base_msat: 500,
+                ppm: 1000
+            }
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! Run the same call on many nodes of the fleet.
+use super::{Fleet, FleetError};
+use crate::balance::{self, BalanceSummary};
+use crate::node::ClnClient;
+use crate::pb::cln::{GetinfoRequest, GetinfoResponse, PayRequest, PayResponse};
+use crate::ratelimit::StatusExt;
+use futures::{Future, StreamExt};
+use std::collections::BTreeMap;
+
+/// The outcome of a bulk operation, by user id. Each user ends up in
+/// exactly one of the two maps.
+#[derive(Debug)]
+pub struct BulkResult<T> {
+    pub ok: BTreeMap<String, T>,
+    pub failed: BTreeMap<String, FleetError>,
+}
+
+impl<T> Default for BulkResult<T> {
+    fn default() -> Self {
+        BulkResult {
+            ok: BTreeMap::new(),
+            failed: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> BulkResult<T> {
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ok.len() + self.failed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Call `f` for each of `users`, with at most `concurrency` calls in
+/// flight.
+async fn scatter<T, F, Fut>(users: Vec<String>, concurrency: usize, f: F) -> BulkResult<T>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<T, FleetError>>,
+{
+    let mut results = futures::stream::iter(users)
+        .map(|user| {
+            let call = f(user.clone());
+            async move { (user, call.await) }
+        })
+        .buffer_unordered(concurrency.max(1));
+
+    let mut res = BulkResult::default();
+    while let Some((user, r)) = results.next().await {
+        match r {
+            Ok(v) => {
+                res.ok.insert(user, v);
+            }
+            Err(e) => {
+                res.failed.insert(user, e);
+            }
+        }
+    }
+    res
+}
+
+impl Fleet {
+    /// Call `f` with the fleet for each of `users`, running at most
+    /// `concurrency` calls of the [`super::FleetConfig`] at the same
+    /// time. The building block of the other bulk operations, for
+    /// jobs they do not cover.
+    pub async fn for_each<T, F, Fut>(&self, users: Vec<String>, f: F) -> BulkResult<T>
+    where
+        F: Fn(Fleet, String) -> Fut,
+        Fut: Future<Output = Result<T, FleetError>>,
+    {
+        scatter(users, self.fleet.concurrency, |user| f(self.clone(), user)).await
+    }
+
+    /// Call `f` for each user in the credential store.
+    pub async fn for_all<T, F, Fut>(&self, f: F) -> Result<BulkResult<T>, FleetError>
+    where
+        F: Fn(Fleet, String) -> Fut,
+        Fut: Future<Output = Result<T, FleetError>>,
+    {
+        let users = self.store.users().await?;
+        Ok(self.for_each(users, f).await)
+    }
+
+    /// `getinfo` of every node in the fleet.
+    pub async fn getinfo_all(&self) -> Result<BulkResult<GetinfoResponse>, FleetError> {
+        self.for_all(|fleet, user| async move {
+            let mut node: ClnClient = fleet.node(&user).await?;
+            Ok(node
+                .getinfo(GetinfoRequest::default())
+                .await
+                .or_rate_limited()?
+                .into_inner())
+        })
+        .await
+    }
+
+    /// The [`BalanceSummary`] of every node in the fleet, see
+    /// [`balance::balance_summary`].
+    pub async fn balance_all(
+        &self,
+        min_emergency_msat: u64,
+    ) -> Result<BulkResult<BalanceSummary>, FleetError> {
+        self.for_all(|fleet, user| async move {
+            let mut node: ClnClient = fleet.node(&user).await?;
+            Ok(balance::balance_summary(&mut node, min_emergency_msat).await?)
+        })
+        .await
+    }
+
+    /// Pay from the node of `user`.
+    pub async fn pay_from(&self, user: &str, req: PayRequest) -> Result<PayResponse, FleetError> {
+        let mut node: ClnClient = self.node(user).await?;
+        Ok(node.pay(req).await.or_rate_limited()?.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_scatter() {
+        let users: Vec<String> = (0..10).map(|i| format!("user{}", i)).collect();
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        let res = scatter(users, 3, |user| {
+            let running = &running;
+            let peak = &peak;
+            async move {
+                let n = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(n, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                if user == "user3" {
+                    Err(FleetError::UnknownUser(user))
+                } else {
+                    Ok(user.len())
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+        assert_eq!(res.len(), 10);
+        assert_eq!(res.ok.len(), 9);
+        assert!(!res.is_ok());
+        assert!(matches!(
+            res.failed.get("user3"),
+            Some(FleetError::UnknownUser(_))
+        ));
+    }
+}
@@ -0,0 +1,105 @@
+//! Per-tenant rate limits.
+use crate::ratelimit::RateLimited;
+use std::time::{Duration, Instant};
+
+/// Allow `calls` calls per `per`, in bursts of up to `calls`. No
+/// calls are allowed if `calls` is zero, and any number if `per` is
+/// zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    pub calls: u32,
+    pub per: Duration,
+}
+
+/// A token bucket enforcing a [`RateLimit`].
+#[derive(Debug)]
+pub(crate) struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    pub(crate) fn new(limit: &RateLimit, now: Instant) -> Self {
+        Bucket {
+            tokens: limit.calls as f64,
+            updated: now,
+        }
+    }
+
+    /// Take a token, or tell how long until the next one is available.
+    pub(crate) fn take(&mut self, limit: &RateLimit, now: Instant) -> Result<(), RateLimited> {
+        if limit.calls == 0 {
+            // There is no next token, ask to come back after a period
+            // in case the limit changes.
+            return Err(RateLimited {
+                retry_after: Some(limit.per),
+            });
+        }
+        if limit.per.is_zero() {
+            return Ok(());
+        }
+        let rate = limit.calls as f64 / limit.per.as_secs_f64();
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(limit.calls as f64);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(RateLimited {
+                retry_after: Some(Duration::from_secs_f64((1.0 - self.tokens) / rate)),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket() {
+        let limit = RateLimit {
+            calls: 2,
+            per: Duration::from_secs(10),
+        };
+        let now = Instant::now();
+        let mut bucket = Bucket::new(&limit, now);
+        assert!(bucket.take(&limit, now).is_ok());
+        assert!(bucket.take(&limit, now).is_ok());
+        let limited = bucket.take(&limit, now).unwrap_err();
+        assert_eq!(limited.retry_after, Some(Duration::from_secs(5)));
+        assert!(bucket.take(&limit, now + Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn test_bucket_zero() {
+        let now = Instant::now();
+        let none = RateLimit {
+            calls: 0,
+            per: Duration::from_secs(10),
+        };
+        let mut bucket = Bucket::new(&none, now);
+        let limited = bucket.take(&none, now).unwrap_err();
+        assert_eq!(limited.retry_after, Some(Duration::from_secs(10)));
+        let limited = bucket
+            .take(&none, now + Duration::from_secs(3600))
+            .unwrap_err();
+        assert_eq!(limited.retry_after, Some(Duration::from_secs(10)));
+
+        let never = RateLimit {
+            calls: 0,
+            per: Duration::ZERO,
+        };
+        assert!(Bucket::new(&never, now).take(&never, now).is_err());
+
+        let unlimited = RateLimit {
+            calls: 1,
+            per: Duration::ZERO,
+        };
+        let mut bucket = Bucket::new(&unlimited, now);
+        for _ in 0..10 {
+            assert!(bucket.take(&unlimited, now).is_ok());
+        }
+    }
+}
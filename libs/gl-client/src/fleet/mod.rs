@@ -0,0 +1,258 @@
+//! Drive many nodes from one process.
+//!
+//! Service providers acting on behalf of their users hold the
+//! credentials of thousands of nodes, but only talk to a few of them
+//! at any time. A [`Fleet`] looks the credentials up in a
+//! [`CredentialStore`], keyed by the provider's user id, schedules
+//! the node on first use, and keeps the connection around for the
+//! following calls. Once `max_connections` nodes are connected the
+//! least recently used connection is dropped to make room.
+//!
+//! A [`RateLimit`] caps the calls made on behalf of each user, so
+//! a single busy user cannot starve the others. Calls over the limit
+//! fail with [`FleetError::RateLimited`] without touching the node.
+//!
+//! For jobs over the whole fleet, e.g., a nightly balance report,
+//! [`Fleet::getinfo_all`], [`Fleet::balance_all`] and
+//! [`Fleet::for_each`] call the nodes with bounded concurrency and
+//! collect the results and errors per user in a [`BulkResult`].
+mod bulk;
+mod limit;
+mod store;
+
+pub use bulk::BulkResult;
+pub use limit::RateLimit;
+pub use store::{CredentialStore, DirectoryStore, MemoryStore};
+
+use crate::config::Config;
+use crate::credentials::{Device, NodeIdProvider};
+use crate::node::{self, AuthService, GrpcClient};
+use crate::ratelimit::RateLimited;
+use crate::scheduler::Scheduler;
+use limit::Bucket;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FleetError {
+    #[error("no credentials for user {0}")]
+    UnknownUser(String),
+
+    #[error(transparent)]
+    RateLimited(#[from] RateLimited),
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FleetConfig {
+    /// How many node connections to keep open at most.
+    pub max_connections: usize,
+    /// The calls allowed per user, `None` for no limit.
+    pub rate_limit: Option<RateLimit>,
+    /// How many nodes bulk operations call at the same time.
+    pub concurrency: usize,
+}
+
+impl Default for FleetConfig {
+    fn default() -> Self {
+        FleetConfig {
+            max_connections: 1000,
+            rate_limit: None,
+            concurrency: 16,
+        }
+    }
+}
+
+/// The nodes of a service provider's users.
+///
+/// Cloning is cheap, and all clones share the connections and rate
+/// limits.
+#[derive(Clone)]
+pub struct Fleet {
+    config: Config,
+    fleet: FleetConfig,
+    store: Arc<dyn CredentialStore>,
+    pool: Arc<Mutex<Pool>>,
+}
+
+impl Fleet {
+    /// A fleet on the network and scheduler of `config`.
+    pub fn new(config: Config, store: impl CredentialStore + 'static) -> Self {
+        Self::with_config(config, FleetConfig::default(), store)
+    }
+
+    pub fn with_config(
+        config: Config,
+        fleet: FleetConfig,
+        store: impl CredentialStore + 'static,
+    ) -> Self {
+        Fleet {
+            config,
+            fleet,
+            store: Arc::new(store),
+            pool: Arc::default(),
+        }
+    }
+
+    pub fn store(&self) -> &dyn CredentialStore {
+        self.store.as_ref()
+    }
+
+    /// A client to the node of `user`, connecting to it if there is no
+    /// open connection. Each call counts against the user's rate
+    /// limit, so get a new client for each batch of calls rather than
+    /// holding on to one.
+    pub async fn node<T>(&self, user: &str) -> Result<T, FleetError>
+    where
+        T: GrpcClient,
+    {
+        if let Some(channel) = self.pool.lock().unwrap().checkout(user, &self.fleet)? {
+            return Ok(T::new_with_inner(channel));
+        }
+
+        // Concurrent calls for a user that is not connected may both
+        // connect, the last one wins the slot in the pool.
+        let creds = self
+            .store
+            .get(user)
+            .await?
+            .ok_or_else(|| FleetError::UnknownUser(user.to_string()))?;
+        let channel = self.connect(creds).await?;
+        self.pool
+            .lock()
+            .unwrap()
+            .insert(user, channel.clone(), self.fleet.max_connections);
+        Ok(T::new_with_inner(channel))
+    }
+
+    async fn connect(&self, creds: Device) -> anyhow::Result<AuthService> {
+        let scheduler = Scheduler::with_config(&self.config, creds.clone()).await?;
+        let res = scheduler.schedule().await?;
+        node::Node::new(creds.node_id()?, creds)?.channel(res.grpc_uri)
+    }
+
+    /// Drop the connection to the node of `user`, e.g., after its
+    /// credentials changed.
+    pub fn evict(&self, user: &str) {
+        self.pool.lock().unwrap().connections.remove(user);
+    }
+
+    /// The number of open connections.
+    pub fn connections(&self) -> usize {
+        self.pool.lock().unwrap().connections.len()
+    }
+}
+
+#[derive(Default)]
+struct Pool {
+    connections: HashMap<String, Connection>,
+    buckets: HashMap<String, Bucket>,
+    tick: u64,
+}
+
+struct Connection {
+    channel: AuthService,
+    last_used: u64,
+}
+
+impl Pool {
+    /// Charge a call to `user`, and return the open connection, if
+    /// any.
+    fn checkout(
+        &mut self,
+        user: &str,
+        config: &FleetConfig,
+    ) -> Result<Option<AuthService>, RateLimited> {
+        if let Some(limit) = &config.rate_limit {
+            let now = Instant::now();
+            self.buckets
+                .entry(user.to_string())
+                .or_insert_with(|| Bucket::new(limit, now))
+                .take(limit, now)?;
+        }
+        self.tick += 1;
+        let tick = self.tick;
+        Ok(self.connections.get_mut(user).map(|c| {
+            c.last_used = tick;
+            c.channel.clone()
+        }))
+    }
+
+    fn insert(&mut self, user: &str, channel: AuthService, max: usize) {
+        while self.connections.len() >= max.max(1) && !self.connections.contains_key(user) {
+            let lru = self
+                .connections
+                .iter()
+                .min_by_key(|(_, c)| c.last_used)
+                .map(|(u, _)| u.clone());
+            match lru {
+                Some(u) => self.connections.remove(&u),
+                None => break,
+            };
+        }
+        self.tick += 1;
+        self.connections.insert(
+            user.to_string(),
+            Connection {
+                channel,
+                last_used: self.tick,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn channel() -> AuthService {
+        let creds = Device::with(
+            include_bytes!("../../.resources/tls/users-nobody.pem").to_vec(),
+            include_bytes!("../../.resources/tls/users-nobody-key.pem").to_vec(),
+            "rune",
+        );
+        node::Node::new(vec![2; 33], creds)
+            .unwrap()
+            .channel("https://localhost:1".to_string())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pool() {
+        let config = FleetConfig {
+            max_connections: 2,
+            rate_limit: Some(RateLimit {
+                calls: 2,
+                per: Duration::from_secs(60),
+            }),
+            ..Default::default()
+        };
+        let mut pool = Pool::default();
+        assert!(pool.checkout("alice", &config).unwrap().is_none());
+        pool.insert("alice", channel(), config.max_connections);
+        pool.insert("bob", channel(), config.max_connections);
+        assert!(pool.checkout("alice", &config).unwrap().is_some());
+
+        // Bob was used least recently, and makes room for Carol.
+        pool.insert("carol", channel(), config.max_connections);
+        let mut users: Vec<_> = pool.connections.keys().cloned().collect();
+        users.sort();
+        assert_eq!(users, vec!["alice", "carol"]);
+
+        // Alice used up her calls, the others are not affected.
+        assert!(pool.checkout("alice", &config).is_err());
+        assert!(pool.checkout("carol", &config).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_user() {
+        let fleet = Fleet::new(Config::default(), MemoryStore::new());
+        let res = fleet.node::<node::ClnClient>("alice").await;
+        assert!(matches!(res, Err(FleetError::UnknownUser(u)) if u == "alice"));
+    }
+}
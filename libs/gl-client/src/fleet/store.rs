@@ -0,0 +1,123 @@
+//! Where the fleet keeps the credentials of its users.
+use crate::credentials::Device;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Credentials of the fleet's nodes, keyed by the provider's user id.
+#[async_trait]
+pub trait CredentialStore: Send + Sync {
+    async fn get(&self, user: &str) -> Result<Option<Device>>;
+    async fn put(&self, user: &str, creds: &Device) -> Result<()>;
+    async fn users(&self) -> Result<Vec<String>>;
+}
+
+/// Keeps the credentials in memory, e.g., for tests, or when they
+/// are loaded from elsewhere at startup.
+#[derive(Default)]
+pub struct MemoryStore {
+    creds: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl CredentialStore for MemoryStore {
+    async fn get(&self, user: &str) -> Result<Option<Device>> {
+        Ok(self.creds.lock().unwrap().get(user).map(Device::from_bytes))
+    }
+
+    async fn put(&self, user: &str, creds: &Device) -> Result<()> {
+        self.creds
+            .lock()
+            .unwrap()
+            .insert(user.to_string(), creds.to_bytes());
+        Ok(())
+    }
+
+    async fn users(&self) -> Result<Vec<String>> {
+        let mut users: Vec<String> = self.creds.lock().unwrap().keys().cloned().collect();
+        users.sort();
+        Ok(users)
+    }
+}
+
+/// Keeps the credentials of each user in a file named after the
+/// user id.
+pub struct DirectoryStore {
+    dir: PathBuf,
+}
+
+impl DirectoryStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        DirectoryStore { dir: dir.into() }
+    }
+
+    fn path(&self, user: &str) -> Result<PathBuf> {
+        let valid = !user.is_empty()
+            && user
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(anyhow!("invalid user id {:?}", user));
+        }
+        Ok(self.dir.join(user))
+    }
+}
+
+#[async_trait]
+impl CredentialStore for DirectoryStore {
+    async fn get(&self, user: &str) -> Result<Option<Device>> {
+        match tokio::fs::read(self.path(user)?).await {
+            Ok(data) => Ok(Some(Device::from_bytes(data))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn put(&self, user: &str, creds: &Device) -> Result<()> {
+        let path = self.path(user)?;
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, creds.to_bytes()).await?;
+        tokio::fs::rename(tmp, path).await?;
+        Ok(())
+    }
+
+    async fn users(&self) -> Result<Vec<String>> {
+        let mut users = vec![];
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if self.path(&name).is_ok() {
+                users.push(name);
+            }
+        }
+        users.sort();
+        Ok(users)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_directory_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = DirectoryStore::new(dir.path());
+        let creds = Device::with(b"cert".to_vec(), b"key".to_vec(), "rune");
+
+        assert!(store.get("alice").await.unwrap().is_none());
+        store.put("alice", &creds).await.unwrap();
+        store.put("bob", &creds).await.unwrap();
+        assert_eq!(store.get("alice").await.unwrap().unwrap().rune, "rune");
+        assert_eq!(store.users().await.unwrap(), vec!["alice", "bob"]);
+        assert!(store.put("../etc", &creds).await.is_err());
+    }
+}
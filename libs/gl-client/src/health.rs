@@ -0,0 +1,190 @@
+//! Health checks for long-running signers.
+//!
+//! A signer that runs unattended should be restarted, or someone
+//! paged, when it silently stops working. [`Health`] collects what is
+//! needed to tell: whether the scheduler is reachable, the connection
+//! status, how long the signer has been streaming requests from the
+//! node, and when it last answered one. The signer updates it as it
+//! runs, see [`Signer::health`], and [`HealthReport`] renders a
+//! snapshot as JSON or Prometheus gauges.
+//!
+//! [`Signer::health`]: crate::signer::Signer::health
+use crate::connection::ConnectionStatus;
+use crate::credentials::{NodeIdProvider, RuneProvider, TlsConfigProvider};
+use crate::scheduler::Scheduler;
+use serde::Serialize;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long to wait for the scheduler to answer a probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HealthReport {
+    /// The result of the last probe, `None` if it was never probed.
+    pub scheduler_reachable: Option<bool>,
+    pub status: ConnectionStatus,
+    /// Seconds since the signer attached to the node, `None` while it
+    /// is not attached.
+    pub stream_age_secs: Option<u64>,
+    /// Seconds since the signer last answered a request.
+    pub last_request_secs: Option<u64>,
+}
+
+impl HealthReport {
+    /// Whether the signer is able to sign. Nodes are stopped when
+    /// nobody uses them, so a signer waiting for its node to be
+    /// scheduled is healthy.
+    pub fn is_healthy(&self) -> bool {
+        self.scheduler_reachable != Some(false)
+            && self.status >= ConnectionStatus::SchedulerConnected
+    }
+
+    /// The report in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut gauge = |name: &str, help: &str, value: Option<u64>| {
+            if let Some(v) = value {
+                let _ = writeln!(out, "# HELP {} {}", name, help);
+                let _ = writeln!(out, "# TYPE {} gauge", name);
+                let _ = writeln!(out, "{} {}", name, v);
+            }
+        };
+        gauge(
+            "gl_signer_healthy",
+            "Whether the signer is able to sign.",
+            Some(self.is_healthy() as u64),
+        );
+        gauge(
+            "gl_scheduler_reachable",
+            "Whether the last scheduler probe succeeded.",
+            self.scheduler_reachable.map(|r| r as u64),
+        );
+        gauge(
+            "gl_connection_status",
+            "How far along the connection to the node is, 0 is disconnected, 4 attached.",
+            Some(self.status as u64),
+        );
+        gauge(
+            "gl_signer_stream_age_seconds",
+            "Seconds since the signer attached to the node.",
+            self.stream_age_secs,
+        );
+        gauge(
+            "gl_signer_last_request_seconds",
+            "Seconds since the signer last answered a request.",
+            self.last_request_secs,
+        );
+        out
+    }
+}
+
+/// The health of a signer, shared with the components updating it.
+///
+/// Cloning is cheap, and all clones refer to the same state.
+#[derive(Clone, Debug, Default)]
+pub struct Health {
+    inner: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    scheduler_reachable: Option<bool>,
+    stream_opened: Option<Instant>,
+    last_request: Option<Instant>,
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check that the scheduler answers, and remember the result for
+    /// the following reports.
+    pub async fn probe_scheduler<Creds>(&self, scheduler: &Scheduler<Creds>) -> bool
+    where
+        Creds: TlsConfigProvider + RuneProvider + NodeIdProvider + Clone,
+    {
+        let reachable = matches!(
+            tokio::time::timeout(PROBE_TIMEOUT, scheduler.get_node_info(false)).await,
+            Ok(Ok(_))
+        );
+        self.inner.lock().unwrap().scheduler_reachable = Some(reachable);
+        reachable
+    }
+
+    pub fn report(&self, status: ConnectionStatus) -> HealthReport {
+        let state = self.inner.lock().unwrap();
+        HealthReport {
+            scheduler_reachable: state.scheduler_reachable,
+            status,
+            stream_age_secs: state.stream_opened.map(|t| t.elapsed().as_secs()),
+            last_request_secs: state.last_request.map(|t| t.elapsed().as_secs()),
+        }
+    }
+
+    /// Track the signer's request stream until the guard is dropped.
+    pub(crate) fn stream_opened(&self) -> StreamGuard {
+        self.inner.lock().unwrap().stream_opened = Some(Instant::now());
+        StreamGuard {
+            health: self.clone(),
+        }
+    }
+
+    pub(crate) fn request_completed(&self) {
+        self.inner.lock().unwrap().last_request = Some(Instant::now());
+    }
+
+    /// How long the signer has been attached without answering a
+    /// request, `None` while it is not attached.
+    pub(crate) fn idle_for(&self) -> Option<Duration> {
+        let state = self.inner.lock().unwrap();
+        let opened = state.stream_opened?;
+        let since = state.last_request.map_or(opened, |r| r.max(opened));
+        Some(since.elapsed())
+    }
+}
+
+pub(crate) struct StreamGuard {
+    health: Health,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.health.inner.lock().unwrap().stream_opened = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health() {
+        let health = Health::new();
+        let report = health.report(ConnectionStatus::Disconnected);
+        assert!(!report.is_healthy());
+        assert_eq!(report.stream_age_secs, None);
+        assert_eq!(health.idle_for(), None);
+
+        let stream = health.stream_opened();
+        health.request_completed();
+        let report = health.report(ConnectionStatus::SignerAttached);
+        assert!(report.is_healthy());
+        assert_eq!(report.stream_age_secs, Some(0));
+        assert_eq!(report.last_request_secs, Some(0));
+        assert!(health.idle_for().unwrap() < Duration::from_secs(1));
+
+        let metrics = report.to_prometheus();
+        assert!(metrics.contains("gl_signer_healthy 1\n"));
+        assert!(metrics.contains("gl_connection_status 4\n"));
+        assert!(!metrics.contains("gl_scheduler_reachable"));
+
+        drop(stream);
+        health.inner.lock().unwrap().scheduler_reachable = Some(false);
+        let report = health.report(ConnectionStatus::SignerAttached);
+        assert!(!report.is_healthy());
+        assert_eq!(report.stream_age_secs, None);
+    }
+}
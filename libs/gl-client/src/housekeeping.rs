@@ -0,0 +1,200 @@
+//! Delete old invoices, payments and forwards from the node.
+//!
+//! Busy nodes accumulate entries that are no longer useful, and
+//! `listinvoices`, `listpays` and `listforwards` get slower with each
+//! of them. `lightningd`'s `autoclean` plugin can delete them, but is
+//! disabled by default. A [`HousekeepingPolicy`] says how long to
+//! keep the entries of each kind, and [`run`] applies it on a
+//! schedule using `autoclean-once`, without changing the node's
+//! configuration. [`delete_failed_pays`] removes the failed attempts
+//! of a single payment right away.
+use crate::node::Client;
+use crate::pb::{
+    AutocleanOnceRequest, AutocleanStatusRequest, AutocleanSubsystemStatus, DelPayRequest,
+    DeletedPayment,
+};
+use anyhow::Result;
+use log::{debug, warn};
+use serde::Serialize;
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// The kinds of entries `autoclean` can delete.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Subsystem {
+    SucceededForwards,
+    FailedForwards,
+    SucceededPays,
+    FailedPays,
+    PaidInvoices,
+    ExpiredInvoices,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 6] = [
+        Subsystem::SucceededForwards,
+        Subsystem::FailedForwards,
+        Subsystem::SucceededPays,
+        Subsystem::FailedPays,
+        Subsystem::PaidInvoices,
+        Subsystem::ExpiredInvoices,
+    ];
+
+    /// The name `lightningd` uses for the subsystem.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Subsystem::SucceededForwards => "succeededforwards",
+            Subsystem::FailedForwards => "failedforwards",
+            Subsystem::SucceededPays => "succeededpays",
+            Subsystem::FailedPays => "failedpays",
+            Subsystem::PaidInvoices => "paidinvoices",
+            Subsystem::ExpiredInvoices => "expiredinvoices",
+        }
+    }
+}
+
+/// How long to keep the entries of each subsystem, `None` to keep
+/// them forever.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HousekeepingPolicy {
+    pub expired_invoices: Option<Duration>,
+    pub paid_invoices: Option<Duration>,
+    pub succeeded_pays: Option<Duration>,
+    pub failed_pays: Option<Duration>,
+    pub succeeded_forwards: Option<Duration>,
+    pub failed_forwards: Option<Duration>,
+    /// How often [`run`] cleans up.
+    pub interval: Duration,
+}
+
+impl Default for HousekeepingPolicy {
+    /// Delete expired invoices and failed forwards after a day, and
+    /// keep the entries that may matter for accounting.
+    fn default() -> Self {
+        let day = Duration::from_secs(24 * 3600);
+        HousekeepingPolicy {
+            expired_invoices: Some(day),
+            paid_invoices: None,
+            succeeded_pays: None,
+            failed_pays: Some(day),
+            succeeded_forwards: None,
+            failed_forwards: Some(day),
+            interval: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl HousekeepingPolicy {
+    pub fn age(&self, subsystem: Subsystem) -> Option<Duration> {
+        match subsystem {
+            Subsystem::SucceededForwards => self.succeeded_forwards,
+            Subsystem::FailedForwards => self.failed_forwards,
+            Subsystem::SucceededPays => self.succeeded_pays,
+            Subsystem::FailedPays => self.failed_pays,
+            Subsystem::PaidInvoices => self.paid_invoices,
+            Subsystem::ExpiredInvoices => self.expired_invoices,
+        }
+    }
+}
+
+/// The entries deleted from a subsystem in one run.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Cleaned {
+    pub subsystem: Subsystem,
+    pub cleaned: u64,
+    /// Entries that are old enough, but were not deleted, e.g.,
+    /// because they are still referenced.
+    pub uncleaned: u64,
+}
+
+/// Apply `policy` once.
+pub async fn run_once(node: &mut Client, policy: &HousekeepingPolicy) -> Result<Vec<Cleaned>> {
+    let mut report = vec![];
+    for subsystem in Subsystem::ALL {
+        let age = match policy.age(subsystem) {
+            Some(age) => age,
+            None => continue,
+        };
+        let res = node
+            .autoclean_once(AutocleanOnceRequest {
+                subsystem: subsystem.as_str().to_string(),
+                age: age.as_secs(),
+            })
+            .await?
+            .into_inner();
+        debug!(
+            "Deleted {} {} entries older than {:?}",
+            res.cleaned,
+            subsystem.as_str(),
+            age
+        );
+        report.push(Cleaned {
+            subsystem,
+            cleaned: res.cleaned,
+            uncleaned: res.uncleaned,
+        });
+    }
+    Ok(report)
+}
+
+/// Apply `policy` every interval. Failed runs are logged and retried
+/// on the next interval.
+pub async fn run(mut node: Client, policy: HousekeepingPolicy) {
+    loop {
+        if let Err(e) = run_once(&mut node, &policy).await {
+            warn!("Housekeeping failed: {}", e);
+        }
+        sleep(policy.interval).await;
+    }
+}
+
+/// The `autoclean` configuration of the node, and how many entries it
+/// deleted since the node started.
+pub async fn status(node: &mut Client) -> Result<Vec<AutocleanSubsystemStatus>> {
+    Ok(node
+        .autoclean_status(AutocleanStatusRequest::default())
+        .await?
+        .into_inner()
+        .subsystems)
+}
+
+/// Delete the failed attempts to pay `payment_hash`, e.g., once a
+/// retry succeeded, so they no longer show up in `listpays`.
+pub async fn delete_failed_pays(
+    node: &mut Client,
+    payment_hash: &[u8],
+) -> Result<Vec<DeletedPayment>> {
+    Ok(node
+        .del_pay(DelPayRequest {
+            payment_hash: payment_hash.to_vec(),
+            status: "failed".to_string(),
+            ..Default::default()
+        })
+        .await?
+        .into_inner()
+        .payments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy() {
+        let policy = HousekeepingPolicy::default();
+        let cleaned: Vec<&str> = Subsystem::ALL
+            .iter()
+            .filter(|s| policy.age(**s).is_some())
+            .map(|s| s.as_str())
+            .collect();
+        assert_eq!(
+            cleaned,
+            vec!["failedforwards", "failedpays", "expiredinvoices"]
+        );
+        assert_eq!(
+            serde_json::to_value(Subsystem::SucceededForwards).unwrap(),
+            "succeededforwards"
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! Hook into the calls the node and scheduler clients make.
+//!
+//! An [`Interceptor`] sees every call before it is sent, and may add
+//! headers, e.g., a tenant identifier for white-label deployments,
+//! rewrite the payload, or refuse the call. It is told how the call
+//! went once the response arrives, which is enough for logging and
+//! metrics. Register interceptors with [`Node::with_interceptor`] and
+//! [`Scheduler::with_interceptor`]; they run in the order they were
+//! added.
+//!
+//! Node calls are signed after the interceptors ran, so the node
+//! verifies the payload as modified by them.
+//!
+//! [`Node::with_interceptor`]: crate::node::Node::with_interceptor
+//! [`Scheduler::with_interceptor`]: crate::scheduler::Scheduler::with_interceptor
+
+// Failing with a `Status` is what tonic's own interceptors do.
+#![allow(clippy::result_large_err)]
+use bytes::Bytes;
+use http::{HeaderMap, Request, Response};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tonic::body::BoxBody;
+use tonic::transport::{Body, Channel};
+use tonic::Status;
+use tower::Service;
+
+/// A call about to be sent.
+pub struct CallRequest<'a> {
+    /// The gRPC method, e.g., `/cln.Node/Getinfo`.
+    pub path: &'a str,
+    pub headers: &'a mut HeaderMap,
+    /// The length-prefixed protobuf message.
+    pub payload: &'a mut Bytes,
+}
+
+/// A call that completed, or failed before a response arrived.
+pub struct CallResponse<'a> {
+    pub path: &'a str,
+    /// The response headers, `None` if the call failed.
+    pub headers: Option<&'a HeaderMap>,
+    /// Until the response headers arrived.
+    pub elapsed: Duration,
+}
+
+pub trait Interceptor: Send + Sync {
+    /// Inspect or modify the call. Returning an error fails the call
+    /// without sending it.
+    fn request(&self, _call: &mut CallRequest<'_>) -> Result<(), Status> {
+        Ok(())
+    }
+
+    fn response(&self, _response: &CallResponse<'_>) {}
+}
+
+/// The interceptors of a client, cheap to clone.
+#[derive(Clone, Default)]
+pub(crate) struct Interceptors(Arc<Vec<Arc<dyn Interceptor>>>);
+
+impl Interceptors {
+    pub(crate) fn with(&self, interceptor: Arc<dyn Interceptor>) -> Self {
+        let mut interceptors = (*self.0).clone();
+        interceptors.push(interceptor);
+        Interceptors(Arc::new(interceptors))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn request(
+        &self,
+        path: &str,
+        headers: &mut HeaderMap,
+        payload: &mut Bytes,
+    ) -> Result<(), Status> {
+        let mut call = CallRequest {
+            path,
+            headers,
+            payload,
+        };
+        self.0.iter().try_for_each(|i| i.request(&mut call))
+    }
+
+    pub(crate) fn response(&self, path: &str, headers: Option<&HeaderMap>, started: Instant) {
+        let response = CallResponse {
+            path,
+            headers,
+            elapsed: started.elapsed(),
+        };
+        self.0.iter().for_each(|i| i.response(&response));
+    }
+}
+
+/// A channel that runs the interceptors on each call.
+#[derive(Clone)]
+pub(crate) struct Intercepted {
+    inner: Channel,
+    interceptors: Interceptors,
+}
+
+impl Intercepted {
+    pub(crate) fn new(inner: Channel, interceptors: Interceptors) -> Self {
+        Intercepted {
+            inner,
+            interceptors,
+        }
+    }
+}
+
+impl Service<Request<BoxBody>> for Intercepted {
+    type Response = Response<Body>;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<BoxBody>) -> Self::Future {
+        // See `AuthService::call` for why we swap the inner channel.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let interceptors = self.interceptors.clone();
+        let deadline = crate::deadline::current();
+
+        Box::pin(async move {
+            let mut request = request;
+            crate::deadline::stamp(deadline, request.headers_mut())?;
+            if interceptors.is_empty() {
+                return tokio::select! {
+                    res = inner.call(request) => Ok(res?),
+                    status = crate::deadline::expired(deadline) => Err(status.into()),
+                };
+            }
+            use tonic::codegen::Body;
+            let (mut parts, mut body) = request.into_parts();
+            let mut payload = body.data().await.transpose()?.unwrap_or_default();
+            let path = parts.uri.path().to_string();
+            interceptors.request(&path, &mut parts.headers, &mut payload)?;
+
+            let body = crate::node::StashBody::new(payload).into();
+            let started = Instant::now();
+            let res: Result<_, Self::Error> = tokio::select! {
+                res = inner.call(Request::from_parts(parts, body)) => res.map_err(Into::into),
+                status = crate::deadline::expired(deadline) => Err(status.into()),
+            };
+            interceptors.response(&path, res.as_ref().ok().map(|r| r.headers()), started);
+            res
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Tenant {
+        seen: Mutex<Vec<String>>,
+    }
+
+    impl Interceptor for Tenant {
+        fn request(&self, call: &mut CallRequest<'_>) -> Result<(), Status> {
+            if call.path.ends_with("/Stop") {
+                return Err(Status::permission_denied("not for tenants"));
+            }
+            call.headers.insert("x-tenant", "acme".parse().unwrap());
+            Ok(())
+        }
+
+        fn response(&self, response: &CallResponse<'_>) {
+            self.seen.lock().unwrap().push(response.path.to_string());
+        }
+    }
+
+    #[test]
+    fn test_interceptors() {
+        let tenant = Arc::new(Tenant::default());
+        let interceptors = Interceptors::default().with(tenant.clone());
+        assert!(!interceptors.is_empty());
+
+        let mut headers = HeaderMap::new();
+        let mut payload = Bytes::new();
+        interceptors
+            .request("/cln.Node/Getinfo", &mut headers, &mut payload)
+            .unwrap();
+        assert_eq!(headers["x-tenant"], "acme");
+        assert!(interceptors
+            .request("/cln.Node/Stop", &mut headers, &mut payload)
+            .is_err());
+
+        interceptors.response("/cln.Node/Getinfo", None, Instant::now());
+        assert_eq!(*tenant.seen.lock().unwrap(), vec!["/cln.Node/Getinfo"]);
+    }
+}
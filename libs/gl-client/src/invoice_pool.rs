@@ -0,0 +1,182 @@
+//! Keep a pool of invoices for receiving while the app is closed.
+//!
+//! Invoices are signed by the signer, so a node whose app is not
+//! running can not create new ones. The [`InvoicePool`] creates a
+//! number of fixed-amount invoices while the signer is online, which
+//! the app can hand out, e.g., as a static QR code, in the meantime.
+//! The limits bound what can be received this way: each invoice is
+//! capped at `max_invoice_msat`, and the pool is only replenished as
+//! long as the outstanding invoices stay below `max_total_msat`. Set
+//! the same limits as [`crate::signer::SignerPolicy::offline_limits`]
+//! to have the signer enforce them, rather than trusting the invoices
+//! the node lists.
+use crate::amount::Msat;
+use crate::node::ClnClient;
+use crate::pb::cln::{
+    amount_or_any, listinvoices_invoices::ListinvoicesInvoicesStatus, Amount, AmountOrAny,
+    InvoiceRequest, ListinvoicesInvoices, ListinvoicesRequest,
+};
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+pub(crate) const LABEL_PREFIX: &str = "gl-pool-";
+
+/// How long pooled invoices stay valid, in seconds.
+const DEFAULT_EXPIRY: u64 = 7 * 24 * 3600;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OfflineLimits {
+    pub max_invoice_msat: u64,
+    /// The maximum amount of all unpaid pooled invoices together.
+    pub max_total_msat: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PoolStatus {
+    /// The unpaid invoices, ready to be handed out.
+    pub available: Vec<String>,
+    /// The amount of the available invoices.
+    pub outstanding_msat: u64,
+    /// The amount received over pooled invoices so far.
+    pub received_msat: u64,
+}
+
+pub struct InvoicePool {
+    amount_msat: u64,
+    size: usize,
+    limits: OfflineLimits,
+    description: String,
+}
+
+impl InvoicePool {
+    /// A pool of up to `size` invoices for `amount_msat` each.
+    pub fn new(amount_msat: u64, size: usize, limits: OfflineLimits) -> Result<Self> {
+        if amount_msat == 0 || amount_msat > limits.max_invoice_msat {
+            return Err(anyhow!(
+                "pooled invoices must be for 1 to {}msat, got {}msat",
+                limits.max_invoice_msat,
+                amount_msat
+            ));
+        }
+        Ok(InvoicePool {
+            amount_msat,
+            size,
+            limits,
+            description: "Payment".to_string(),
+        })
+    }
+
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = description.to_string();
+        self
+    }
+
+    /// Summarize the pooled invoices in `invoices`.
+    pub fn status(&self, invoices: &[ListinvoicesInvoices]) -> PoolStatus {
+        let mut status = PoolStatus::default();
+        for i in invoices
+            .iter()
+            .filter(|i| i.label.starts_with(LABEL_PREFIX))
+        {
+            match ListinvoicesInvoicesStatus::from_i32(i.status) {
+                Some(ListinvoicesInvoicesStatus::Unpaid) => {
+                    status.outstanding_msat += Msat::from(&i.amount_msat).to_msat();
+                    status.available.extend(i.bolt11.clone());
+                }
+                Some(ListinvoicesInvoicesStatus::Paid) => {
+                    status.received_msat += Msat::from(&i.amount_received_msat).to_msat();
+                }
+                _ => {}
+            }
+        }
+        status
+    }
+
+    /// How many invoices to add to a pool with `status`.
+    fn missing(&self, status: &PoolStatus) -> usize {
+        let budget = self
+            .limits
+            .max_total_msat
+            .saturating_sub(status.outstanding_msat)
+            / self.amount_msat;
+        self.size
+            .saturating_sub(status.available.len())
+            .min(budget as usize)
+    }
+
+    /// Top up the pool. Must be called while the signer is online,
+    /// since it signs the new invoices.
+    pub async fn replenish(&self, node: &mut ClnClient) -> Result<PoolStatus> {
+        let invoices = node
+            .list_invoices(ListinvoicesRequest::default())
+            .await
+            .map_err(|e| anyhow!(e))?
+            .into_inner()
+            .invoices;
+        let mut status = self.status(&invoices);
+
+        let missing = self.missing(&status);
+        debug!("Adding {} invoices to the pool", missing);
+        for _ in 0..missing {
+            let invoice = node
+                .invoice(InvoiceRequest {
+                    amount_msat: Some(AmountOrAny {
+                        value: Some(amount_or_any::Value::Amount(Amount {
+                            msat: self.amount_msat,
+                        })),
+                    }),
+                    description: self.description.clone(),
+                    label: format!("{}{:016x}", LABEL_PREFIX, rand::random::<u64>()),
+                    expiry: Some(DEFAULT_EXPIRY),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|e| anyhow!(e))?
+                .into_inner();
+            status.available.push(invoice.bolt11);
+            status.outstanding_msat += self.amount_msat;
+        }
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoice(label: &str, status: ListinvoicesInvoicesStatus) -> ListinvoicesInvoices {
+        ListinvoicesInvoices {
+            label: label.to_string(),
+            status: status as i32,
+            amount_msat: Some(Amount { msat: 1000 }),
+            amount_received_msat: Some(Amount { msat: 1000 }),
+            bolt11: Some(format!("lnbc-{}", label)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = OfflineLimits {
+            max_invoice_msat: 1000,
+            max_total_msat: 3500,
+        };
+        assert!(InvoicePool::new(2000, 5, limits).is_err());
+
+        let pool = InvoicePool::new(1000, 5, limits).unwrap();
+        let status = pool.status(&[
+            invoice("gl-pool-1", ListinvoicesInvoicesStatus::Unpaid),
+            invoice("gl-pool-2", ListinvoicesInvoicesStatus::Paid),
+            invoice("gl-pool-3", ListinvoicesInvoicesStatus::Expired),
+            invoice("other", ListinvoicesInvoicesStatus::Unpaid),
+        ]);
+        assert_eq!(status.available, vec!["lnbc-gl-pool-1".to_string()]);
+        assert_eq!(status.outstanding_msat, 1000);
+        assert_eq!(status.received_msat, 1000);
+
+        // The aggregate limit allows for 2 more, not the 4 missing.
+        assert_eq!(pool.missing(&status), 2);
+    }
+}
@@ -0,0 +1,229 @@
+//! Preview an invoice before creating it.
+//!
+//! Creating an invoice has the signer sign it, and stores it on the
+//! node, so an app that asks the user to confirm the details first
+//! leaves an unused invoice behind whenever the user cancels.
+//! [`preview_invoice`] computes the details from the node's channels
+//! instead: when the invoice expires, the route hints `lightningd`
+//! adds for the private channels, and whether the amount can be
+//! received at all. Once the user confirms,
+//! [`InvoicePreview::request`] returns the `invoice` call that
+//! creates the previewed invoice.
+use crate::amount::AmountExt;
+use crate::channels::ChannelState;
+use crate::node::ClnClient;
+use crate::pb::cln::{
+    amount_or_any, Amount, AmountOrAny, InvoiceRequest, ListpeerchannelsChannels,
+    ListpeerchannelsRequest, ListpeerchannelsResponse,
+};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::time::SystemTime;
+
+/// The expiry `lightningd` uses if none is given, in seconds.
+pub const DEFAULT_EXPIRY: u64 = 7 * 24 * 3600;
+
+/// How a payer reaches the node over one of its private channels.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RouteHint {
+    pub peer_id: Vec<u8>,
+    /// The alias the peer knows the channel by, or its short channel
+    /// id if it has none.
+    pub short_channel_id: String,
+    /// The fees and delta the peer charges to forward to us.
+    pub fee_base_msat: u64,
+    pub fee_proportional_millionths: u32,
+    pub cltv_expiry_delta: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct InvoicePreview {
+    /// `None` for invoices that let the payer choose the amount.
+    pub amount_msat: Option<u64>,
+    /// Seconds the invoice is valid for.
+    pub expiry: u64,
+    /// Seconds since the UNIX epoch.
+    pub expires_at: u64,
+    /// The candidates for the route hints, most receivable capacity
+    /// first. `lightningd` picks from these when creating the
+    /// invoice.
+    pub route_hints: Vec<RouteHint>,
+    /// How much the active channels can receive in total.
+    pub receivable_msat: u64,
+    /// Whether the amount, or any amount if none is set, can be
+    /// received over the active channels. If not, the payment fails
+    /// unless an LSP opens a channel for it.
+    pub receivable: bool,
+}
+
+impl InvoicePreview {
+    /// Preview an invoice for `amount_msat`, valid for `expiry`
+    /// seconds, or [`DEFAULT_EXPIRY`], on a node with `channels`, at
+    /// `now` seconds since the UNIX epoch.
+    pub fn from_channels(
+        amount_msat: Option<u64>,
+        expiry: Option<u64>,
+        channels: &ListpeerchannelsResponse,
+        now: u64,
+    ) -> InvoicePreview {
+        let active: Vec<&ListpeerchannelsChannels> = channels
+            .channels
+            .iter()
+            .filter(|c| ChannelState::of(c).is_some_and(|s| s.is_active()))
+            .collect();
+        let receivable_msat = active.iter().map(|c| msat(&c.receivable_msat)).sum();
+
+        let mut candidates: Vec<(u64, RouteHint)> = active
+            .iter()
+            .filter(|c| c.private.unwrap_or(false) && c.peer_connected.unwrap_or(false))
+            .filter(|c| msat(&c.receivable_msat) >= amount_msat.unwrap_or(1))
+            .filter_map(|c| Some((msat(&c.receivable_msat), route_hint(c)?)))
+            .collect();
+        candidates.sort_by_key(|c| std::cmp::Reverse(c.0));
+
+        let expiry = expiry.unwrap_or(DEFAULT_EXPIRY);
+        InvoicePreview {
+            amount_msat,
+            expiry,
+            expires_at: now + expiry,
+            route_hints: candidates.into_iter().map(|(_, h)| h).collect(),
+            receivable_msat,
+            receivable: receivable_msat > 0 && receivable_msat >= amount_msat.unwrap_or(0),
+        }
+    }
+
+    /// The `invoice` call that creates the previewed invoice.
+    pub fn request(&self, label: &str, description: &str) -> InvoiceRequest {
+        let value = match self.amount_msat {
+            Some(msat) => amount_or_any::Value::Amount(Amount { msat }),
+            None => amount_or_any::Value::Any(true),
+        };
+        InvoiceRequest {
+            amount_msat: Some(AmountOrAny { value: Some(value) }),
+            label: label.to_string(),
+            description: description.to_string(),
+            expiry: Some(self.expiry),
+            ..Default::default()
+        }
+    }
+}
+
+/// The hint for channel `c`, if the peer told us how it forwards to
+/// us.
+fn route_hint(c: &ListpeerchannelsChannels) -> Option<RouteHint> {
+    let remote = c.updates.as_ref()?.remote.as_ref()?;
+    let short_channel_id = c
+        .alias
+        .as_ref()
+        .and_then(|a| a.remote.clone())
+        .or_else(|| c.short_channel_id.clone())?;
+    Some(RouteHint {
+        peer_id: c.peer_id.clone()?,
+        short_channel_id,
+        fee_base_msat: msat(&remote.fee_base_msat),
+        fee_proportional_millionths: remote.fee_proportional_millionths?,
+        cltv_expiry_delta: remote.cltv_expiry_delta?,
+    })
+}
+
+/// Fetch the node's channels, and preview an invoice for
+/// `amount_msat`, valid for `expiry` seconds. Nothing is created on
+/// the node.
+pub async fn preview_invoice(
+    node: &mut ClnClient,
+    amount_msat: Option<u64>,
+    expiry: Option<u64>,
+) -> Result<InvoicePreview> {
+    let channels = node
+        .list_peer_channels(ListpeerchannelsRequest::default())
+        .await
+        .map_err(|e| anyhow!(e))?
+        .into_inner();
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    Ok(InvoicePreview::from_channels(
+        amount_msat,
+        expiry,
+        &channels,
+        now,
+    ))
+}
+
+fn msat(a: &Option<Amount>) -> u64 {
+    a.as_ref().and_then(AmountExt::msat).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::cln::{
+        listpeerchannels_channels::ListpeerchannelsChannelsState as PbState,
+        ListpeerchannelsChannelsAlias, ListpeerchannelsChannelsUpdates,
+        ListpeerchannelsChannelsUpdatesRemote,
+    };
+
+    fn channel(state: PbState, private: bool, receivable: u64) -> ListpeerchannelsChannels {
+        ListpeerchannelsChannels {
+            peer_id: Some(vec![2; 33]),
+            peer_connected: Some(true),
+            state: Some(state as i32),
+            private: Some(private),
+            short_channel_id: Some(format!("100x1x{}", receivable)),
+            receivable_msat: Some(Amount { msat: receivable }),
+            updates: Some(ListpeerchannelsChannelsUpdates {
+                local: None,
+                remote: Some(ListpeerchannelsChannelsUpdatesRemote {
+                    fee_base_msat: Some(Amount { msat: 1000 }),
+                    fee_proportional_millionths: Some(100),
+                    cltv_expiry_delta: Some(144),
+                    ..Default::default()
+                }),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_preview() {
+        let mut aliased = channel(PbState::ChanneldNormal, true, 50_000);
+        aliased.alias = Some(ListpeerchannelsChannelsAlias {
+            local: Some("1x2x3".to_string()),
+            remote: Some("4x5x6".to_string()),
+        });
+        let channels = ListpeerchannelsResponse {
+            channels: vec![
+                channel(PbState::ChanneldNormal, true, 20_000),
+                aliased,
+                // Too small for the amount, public, and not active.
+                channel(PbState::ChanneldNormal, true, 5_000),
+                channel(PbState::ChanneldNormal, false, 30_000),
+                channel(PbState::ChanneldAwaitingLockin, true, 90_000),
+            ],
+        };
+
+        let preview = InvoicePreview::from_channels(Some(10_000), None, &channels, 1000);
+        assert_eq!(preview.expires_at, 1000 + DEFAULT_EXPIRY);
+        assert_eq!(preview.receivable_msat, 105_000);
+        assert!(preview.receivable);
+        let hints: Vec<&str> = preview
+            .route_hints
+            .iter()
+            .map(|h| h.short_channel_id.as_str())
+            .collect();
+        assert_eq!(hints, vec!["4x5x6", "100x1x20000"]);
+        assert_eq!(preview.route_hints[0].cltv_expiry_delta, 144);
+
+        let preview = InvoicePreview::from_channels(Some(200_000), Some(60), &channels, 1000);
+        assert!(!preview.receivable);
+        assert!(preview.route_hints.is_empty());
+
+        let request = preview.request("label", "coffee");
+        assert_eq!(request.expiry, Some(60));
+        assert_eq!(
+            request.amount_msat.and_then(|a| a.value),
+            Some(amount_or_any::Value::Amount(Amount { msat: 200_000 }))
+        );
+    }
+}
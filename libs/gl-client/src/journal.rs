@@ -0,0 +1,577 @@
+//! Write-ahead journal of outgoing payments.
+//!
+//! If the app crashes, or is killed, while a `pay` or `withdraw` call
+//! is in flight, it can not tell whether the node received the call,
+//! and whether the funds left the node. Retrying blindly risks paying
+//! twice, giving up risks not paying at all.
+//!
+//! The [`Journal`] records the intent of each payment in a file
+//! before it is submitted, and its outcome once the node answered.
+//! After a restart [`Journal::reconcile`] asks the node about the
+//! intents that were never resolved, and tells definitively which of
+//! them were sent:
+//!
+//! ```no_run
+//! # use gl_client::journal::Journal;
+//! # use gl_client::node::ClnClient;
+//! # async fn example(mut node: ClnClient) -> anyhow::Result<()> {
+//! let mut journal = Journal::open("payments.journal")?;
+//! for (id, outcome) in journal.reconcile(&mut node).await? {
+//!     println!("payment {} was {:?}", id, outcome);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The file is a sequence of JSON lines, each recording an intent or
+//! an outcome, and flushed to disk before the call is made. A line
+//! torn by a crash is dropped when the journal is opened.
+use crate::bitcoin::{Address, Script};
+use crate::lightning_invoice::Bolt11Invoice;
+use crate::node::ClnClient;
+use crate::pb::cln::{
+    amount_or_all, listpays_pays::ListpaysPaysStatus, Amount, ListpaysRequest,
+    ListtransactionsRequest, ListtransactionsTransactions, PayRequest, PayResponse,
+    WithdrawRequest, WithdrawResponse,
+};
+use anyhow::{anyhow, Context, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// What the app set out to do.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Intent {
+    Pay {
+        bolt11: String,
+        /// Hex encoded.
+        payment_hash: String,
+        amount_msat: Option<u64>,
+    },
+    Withdraw {
+        destination: String,
+        /// `None` to withdraw all funds.
+        amount_sat: Option<u64>,
+        /// The hex encoded txids of the wallet transactions that paid
+        /// to `destination` before the intent was recorded, none of
+        /// which can prove it.
+        #[serde(default)]
+        known_txids: Vec<String>,
+    },
+}
+
+/// What became of an [`Intent`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Outcome {
+    /// The payment completed, with the hex encoded preimage of a
+    /// `pay`, or the txid of a `withdraw`.
+    Sent { proof: String },
+    /// The node tried, and the payment failed. No funds left the
+    /// node.
+    Failed { reason: String },
+    /// The node never saw the call.
+    NotSent,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    /// Seconds since the UNIX epoch.
+    pub created_at: u64,
+    pub intent: Intent,
+    /// `None` while the outcome is not known.
+    pub outcome: Option<Outcome>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Line {
+    Record(Entry),
+    Resolve {
+        id: u64,
+        outcome: Outcome,
+    },
+    /// Written first by [`Journal::compact`], so the ids of the
+    /// dropped entries are not reused.
+    Compacted {
+        next_id: u64,
+    },
+}
+
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    entries: BTreeMap<u64, Entry>,
+    next_id: u64,
+}
+
+impl Journal {
+    /// Open the journal at `path`, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut entries = BTreeMap::new();
+        let mut next_id = 1;
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e).with_context(|| format!("reading journal {}", path.display())),
+        };
+
+        // Everything after the last newline was torn by a crash, and
+        // must go before anything else is appended.
+        let complete = data
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        for line in String::from_utf8_lossy(&data[..complete]).lines() {
+            match serde_json::from_str(line) {
+                Ok(Line::Record(e)) => {
+                    next_id = next_id.max(e.id + 1);
+                    entries.insert(e.id, e);
+                }
+                Ok(Line::Compacted { next_id: id }) => next_id = next_id.max(id),
+                Ok(Line::Resolve { id, outcome }) => {
+                    if let Some(e) = entries.get_mut(&id) {
+                        e.outcome = Some(outcome);
+                    }
+                }
+                Err(e) => warn!("Skipping invalid journal line {:?}: {}", line, e),
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("opening journal {}", path.display()))?;
+        if complete < data.len() {
+            warn!(
+                "Dropping torn journal line {:?}",
+                String::from_utf8_lossy(&data[complete..])
+            );
+            file.set_len(complete as u64)?;
+        }
+        Ok(Journal {
+            path,
+            file,
+            entries,
+            next_id,
+        })
+    }
+
+    fn append(&mut self, line: &Line) -> Result<()> {
+        let mut data = serde_json::to_vec(line)?;
+        data.push(b'\n');
+        self.file.write_all(&data)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Record `intent` before it is submitted to the node.
+    pub fn record(&mut self, intent: Intent) -> Result<u64> {
+        let id = self.next_id;
+        let created_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let entry = Entry {
+            id,
+            created_at,
+            intent,
+            outcome: None,
+        };
+        self.append(&Line::Record(entry.clone()))?;
+        self.entries.insert(id, entry);
+        self.next_id = id + 1;
+        Ok(id)
+    }
+
+    /// Record the outcome of the intent `id`.
+    pub fn resolve(&mut self, id: u64, outcome: Outcome) -> Result<()> {
+        if !self.entries.contains_key(&id) {
+            return Err(anyhow!("no journal entry {}", id));
+        }
+        self.append(&Line::Resolve {
+            id,
+            outcome: outcome.clone(),
+        })?;
+        if let Some(e) = self.entries.get_mut(&id) {
+            e.outcome = Some(outcome);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Entry> {
+        self.entries.get(&id)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.values()
+    }
+
+    /// The intents whose outcome is not known.
+    pub fn pending(&self) -> Vec<&Entry> {
+        self.entries
+            .values()
+            .filter(|e| e.outcome.is_none())
+            .collect()
+    }
+
+    /// Rewrite the journal, dropping the resolved entries. While a
+    /// withdrawal is pending, the resolved withdrawals are kept, since
+    /// their transactions can not prove it.
+    pub fn compact(&mut self) -> Result<()> {
+        let withdrawing = self
+            .pending()
+            .iter()
+            .any(|e| matches!(e.intent, Intent::Withdraw { .. }));
+        self.entries.retain(|_, e| {
+            e.outcome.is_none() || (withdrawing && matches!(e.intent, Intent::Withdraw { .. }))
+        });
+        let tmp = self.path.with_extension("tmp");
+        {
+            let mut file = File::create(&tmp)?;
+            let lines = std::iter::once(Line::Compacted {
+                next_id: self.next_id,
+            })
+            .chain(self.entries.values().cloned().map(Line::Record));
+            for line in lines {
+                let mut data = serde_json::to_vec(&line)?;
+                data.push(b'\n');
+                file.write_all(&data)?;
+            }
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp, &self.path)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+
+    /// Pay `req` through the journal. If the call fails the intent
+    /// stays pending, since a transport error does not tell whether
+    /// the node started the payment; [`Journal::reconcile`] settles
+    /// it.
+    pub async fn pay(&mut self, node: &mut ClnClient, req: PayRequest) -> Result<PayResponse> {
+        let invoice =
+            Bolt11Invoice::from_str(&req.bolt11).map_err(|e| anyhow!("invalid invoice: {}", e))?;
+        let id = self.record(Intent::Pay {
+            bolt11: req.bolt11.clone(),
+            payment_hash: hex::encode(&invoice.payment_hash()[..]),
+            amount_msat: req.amount_msat.as_ref().map(|a| a.msat),
+        })?;
+        let res = node.pay(req).await.map_err(|e| anyhow!(e))?.into_inner();
+        self.resolve(
+            id,
+            Outcome::Sent {
+                proof: hex::encode(&res.payment_preimage),
+            },
+        )?;
+        Ok(res)
+    }
+
+    /// Withdraw `req` through the journal, see [`Journal::pay`].
+    pub async fn withdraw(
+        &mut self,
+        node: &mut ClnClient,
+        req: WithdrawRequest,
+    ) -> Result<WithdrawResponse> {
+        let amount_sat = match req.satoshi.as_ref().and_then(|a| a.value.as_ref()) {
+            Some(amount_or_all::Value::Amount(Amount { msat })) => Some(msat / 1000),
+            _ => None,
+        };
+        let script = Address::from_str(&req.destination)?.script_pubkey();
+        let transactions = node
+            .list_transactions(ListtransactionsRequest::default())
+            .await
+            .map_err(|e| anyhow!(e))?
+            .into_inner()
+            .transactions;
+        let id = self.record(Intent::Withdraw {
+            destination: req.destination.clone(),
+            amount_sat,
+            known_txids: paying_to(&transactions, &script).collect(),
+        })?;
+        let res = node
+            .withdraw(req)
+            .await
+            .map_err(|e| anyhow!(e))?
+            .into_inner();
+        self.resolve(
+            id,
+            Outcome::Sent {
+                proof: hex::encode(&res.txid),
+            },
+        )?;
+        Ok(res)
+    }
+
+    /// Find out what became of the pending intents, and resolve them.
+    /// Returns the newly resolved intents. Intents the node is still
+    /// working on stay pending.
+    ///
+    /// Call this on startup, before any new payment is made:
+    /// withdrawals are matched to the wallet transactions paying to
+    /// their destination that are newer than the intent, so one that
+    /// is in flight while reconciling would be reported as not sent.
+    pub async fn reconcile(&mut self, node: &mut ClnClient) -> Result<Vec<(u64, Outcome)>> {
+        let pending: Vec<Entry> = self.pending().into_iter().cloned().collect();
+        if pending.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // Transactions already attributed to a withdrawal can not
+        // prove another one.
+        let mut claimed: Vec<String> = self
+            .entries
+            .values()
+            .filter(|e| matches!(e.intent, Intent::Withdraw { .. }))
+            .filter_map(|e| match &e.outcome {
+                Some(Outcome::Sent { proof }) => Some(proof.clone()),
+                _ => None,
+            })
+            .collect();
+        let mut transactions = None;
+
+        let mut resolved = vec![];
+        for entry in pending {
+            let outcome = match &entry.intent {
+                Intent::Pay { payment_hash, .. } => {
+                    let pays = node
+                        .list_pays(ListpaysRequest {
+                            payment_hash: Some(hex::decode(payment_hash)?),
+                            ..Default::default()
+                        })
+                        .await
+                        .map_err(|e| anyhow!(e))?
+                        .into_inner()
+                        .pays;
+                    pay_outcome(&pays)
+                }
+                Intent::Withdraw {
+                    destination,
+                    known_txids,
+                    ..
+                } => {
+                    if transactions.is_none() {
+                        transactions = Some(
+                            node.list_transactions(ListtransactionsRequest::default())
+                                .await
+                                .map_err(|e| anyhow!(e))?
+                                .into_inner()
+                                .transactions,
+                        );
+                    }
+                    let script = Address::from_str(destination)?.script_pubkey();
+                    let txid = withdrawal_txid(
+                        transactions.as_deref().unwrap_or_default(),
+                        &script,
+                        known_txids,
+                        &claimed,
+                    );
+                    match txid {
+                        Some(txid) => {
+                            claimed.push(txid.clone());
+                            Some(Outcome::Sent { proof: txid })
+                        }
+                        None => Some(Outcome::NotSent),
+                    }
+                }
+            };
+
+            if let Some(outcome) = outcome {
+                debug!("Journal entry {} resolved as {:?}", entry.id, outcome);
+                self.resolve(entry.id, outcome.clone())?;
+                resolved.push((entry.id, outcome));
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+/// The hex encoded txids of the `transactions` paying to `script`.
+fn paying_to<'a>(
+    transactions: &'a [ListtransactionsTransactions],
+    script: &'a Script,
+) -> impl Iterator<Item = String> + 'a {
+    transactions
+        .iter()
+        .filter(move |tx| {
+            tx.outputs
+                .iter()
+                .any(|o| o.script_pub_key == script.as_bytes())
+        })
+        .map(|tx| hex::encode(&tx.hash))
+}
+
+/// The txid of the withdrawal to `script` in `transactions`: the first
+/// transaction paying to it that is neither `known` when the intent
+/// was recorded nor `claimed` by another withdrawal.
+fn withdrawal_txid(
+    transactions: &[ListtransactionsTransactions],
+    script: &Script,
+    known: &[String],
+    claimed: &[String],
+) -> Option<String> {
+    paying_to(transactions, script).find(|txid| !known.contains(txid) && !claimed.contains(txid))
+}
+
+/// The outcome of a payment given its `listpays` entries, `None` if
+/// it is still in flight.
+fn pay_outcome(pays: &[crate::pb::cln::ListpaysPays]) -> Option<Outcome> {
+    if let Some(p) = pays
+        .iter()
+        .find(|p| p.status() == ListpaysPaysStatus::Complete)
+    {
+        return Some(Outcome::Sent {
+            proof: hex::encode(p.preimage.clone().unwrap_or_default()),
+        });
+    }
+    if pays
+        .iter()
+        .any(|p| p.status() == ListpaysPaysStatus::Pending)
+    {
+        return None;
+    }
+    if pays.is_empty() {
+        return Some(Outcome::NotSent);
+    }
+    Some(Outcome::Failed {
+        reason: "all payment attempts failed".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::cln::{ListpaysPays, ListtransactionsTransactionsOutputs};
+
+    fn intent() -> Intent {
+        Intent::Withdraw {
+            destination: "bcrt1qexample".to_string(),
+            amount_sat: Some(1000),
+            known_txids: vec![],
+        }
+    }
+
+    fn pay_intent() -> Intent {
+        Intent::Pay {
+            bolt11: "lnbcrt1example".to_string(),
+            payment_hash: hex::encode([1; 32]),
+            amount_msat: None,
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal");
+
+        let mut journal = Journal::open(&path).unwrap();
+        let a = journal.record(pay_intent()).unwrap();
+        let b = journal.record(intent()).unwrap();
+        journal.resolve(a, Outcome::NotSent).unwrap();
+        drop(journal);
+
+        // A crash in the middle of a write leaves a torn line.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"resolve\":{\"id\":").unwrap();
+        drop(file);
+
+        let mut journal = Journal::open(&path).unwrap();
+        assert_eq!(journal.get(a).unwrap().outcome, Some(Outcome::NotSent));
+        assert!(std::fs::read_to_string(&path).unwrap().ends_with('\n'));
+        let pending: Vec<u64> = journal.pending().iter().map(|e| e.id).collect();
+        assert_eq!(pending, vec![b]);
+
+        journal.compact().unwrap();
+        let c = journal.record(intent()).unwrap();
+        assert_eq!(c, b + 1);
+        drop(journal);
+        let mut journal = Journal::open(&path).unwrap();
+        assert_eq!(journal.entries().count(), 2);
+        assert!(journal.get(a).is_none());
+
+        // Resolved withdrawals are kept while another is pending.
+        journal.resolve(c, Outcome::NotSent).unwrap();
+        journal.compact().unwrap();
+        assert_eq!(journal.entries().count(), 2);
+
+        // Ids of dropped entries are not reused.
+        journal.resolve(b, Outcome::NotSent).unwrap();
+        journal.compact().unwrap();
+        assert_eq!(journal.entries().count(), 0);
+        drop(journal);
+        let mut journal = Journal::open(&path).unwrap();
+        assert_eq!(journal.record(intent()).unwrap(), c + 1);
+    }
+
+    #[test]
+    fn test_withdrawal_txid() {
+        let script = Script::from(vec![0, 20, 1]);
+        let tx = |hash: u8, script: &Script| ListtransactionsTransactions {
+            hash: vec![hash; 32],
+            outputs: vec![ListtransactionsTransactionsOutputs {
+                script_pub_key: script.to_bytes(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let transactions = vec![
+            tx(1, &script),
+            tx(2, &Script::new()),
+            tx(3, &script),
+            tx(4, &script),
+        ];
+        let txid = |hash: u8| hex::encode([hash; 32]);
+
+        assert_eq!(
+            withdrawal_txid(&transactions, &script, &[], &[]),
+            Some(txid(1))
+        );
+        // Transactions from before the intent, or proving another
+        // withdrawal, are skipped.
+        assert_eq!(
+            withdrawal_txid(&transactions, &script, &[txid(1)], &[txid(3)]),
+            Some(txid(4))
+        );
+        assert_eq!(
+            withdrawal_txid(&transactions[..2], &script, &[txid(1)], &[]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_pay_outcome() {
+        let pay = |status: ListpaysPaysStatus| {
+            let mut p = ListpaysPays {
+                preimage: Some(vec![1; 32]),
+                ..Default::default()
+            };
+            p.set_status(status);
+            p
+        };
+        assert_eq!(pay_outcome(&[]), Some(Outcome::NotSent));
+        assert_eq!(pay_outcome(&[pay(ListpaysPaysStatus::Pending)]), None);
+        assert!(matches!(
+            pay_outcome(&[pay(ListpaysPaysStatus::Failed)]),
+            Some(Outcome::Failed { .. })
+        ));
+        assert_eq!(
+            pay_outcome(&[
+                pay(ListpaysPaysStatus::Failed),
+                pay(ListpaysPaysStatus::Complete)
+            ]),
+            Some(Outcome::Sent {
+                proof: hex::encode([1; 32])
+            })
+        );
+    }
+}
@@ -0,0 +1,437 @@
+//! Export the node's ledger for accounting and tax tools.
+//!
+//! The bookkeeper plugin tracks every movement of funds across the
+//! onchain wallet and the channels. [`Ledger`] collects these events
+//! into a single list, and [`Ledger::export`] renders them as CSV or
+//! JSON. If a [`RateProvider`], e.g., [`Rates`], is given, each
+//! entry is also valued in fiat, and disposals are assigned a cost
+//! basis using the average cost of the funds held at that time. Moves
+//! between the node's own accounts, such as funding a channel from
+//! the wallet, are neither acquisitions nor disposals.
+//!
+//! [`Rates`]: crate::rates::Rates
+use crate::node::Client;
+use crate::pb::{BkprAccountEvent, BkprListAccountEventsRequest};
+pub use crate::rates::RateProvider;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+const MSAT_PER_BTC: f64 = 100_000_000_000.0;
+
+/// The bookkeeper account of the onchain wallet.
+const WALLET_ACCOUNT: &str = "wallet";
+/// The bookkeeper account of funds paid to others onchain.
+const EXTERNAL_ACCOUNT: &str = "external";
+/// The tags of channel accounts for funds coming from, or going back
+/// to, the wallet.
+const CHANNEL_TRANSFER_TAGS: &[&str] = &[
+    "channel_open",
+    "channel_close",
+    "delayed_to_us",
+    "to_wallet",
+];
+
+const CSV_HEADER: &str = "timestamp,account,tag,credit_msat,debit_msat,fees_msat,reference,description,currency,rate,fiat_value,cost_basis";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            o => Err(anyhow!("unknown export format {}", o)),
+        }
+    }
+}
+
+/// An inclusive range of UNIX timestamps, in seconds. Open ends are
+/// `None`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DateRange {
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+}
+
+impl DateRange {
+    pub fn new(start: Option<u64>, end: Option<u64>) -> Self {
+        DateRange { start, end }
+    }
+
+    pub fn contains(&self, timestamp: u64) -> bool {
+        self.start.map_or(true, |s| timestamp >= s) && self.end.map_or(true, |e| timestamp <= e)
+    }
+}
+
+/// A single movement of funds.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct LedgerEntry {
+    pub timestamp: u64,
+    /// The bookkeeper account, i.e., `wallet` or a channel id.
+    pub account: String,
+    pub tag: String,
+    pub credit_msat: u64,
+    pub debit_msat: u64,
+    pub fees_msat: u64,
+    /// The outpoint or payment hash the entry refers to.
+    pub reference: String,
+    /// The hex encoded id of the transaction creating the funds of a
+    /// credit, or spending those of a debit, if the entry is onchain.
+    pub txid: String,
+    pub description: String,
+    /// Rebalances move funds between our own channels, and are
+    /// neither acquisitions nor disposals.
+    pub is_rebalance: bool,
+    /// Set by [`Ledger::new`] for moves between the wallet and the
+    /// channels, which are neither acquisitions nor disposals either.
+    pub is_internal: bool,
+}
+
+impl From<BkprAccountEvent> for LedgerEntry {
+    fn from(e: BkprAccountEvent) -> Self {
+        let txid = if e.debit_msat > 0 && !e.txid.is_empty() {
+            hex::encode(&e.txid)
+        } else {
+            e.outpoint.split(':').next().unwrap_or_default().to_string()
+        };
+        let reference = if !e.payment_id.is_empty() {
+            hex::encode(&e.payment_id)
+        } else if !e.outpoint.is_empty() {
+            e.outpoint
+        } else {
+            hex::encode(&e.txid)
+        };
+        LedgerEntry {
+            timestamp: e.timestamp as u64,
+            account: e.account,
+            tag: e.tag,
+            credit_msat: e.credit_msat,
+            debit_msat: e.debit_msat,
+            fees_msat: e.fees_msat,
+            reference,
+            txid,
+            description: e.description,
+            is_rebalance: e.is_rebalance,
+            is_internal: false,
+        }
+    }
+}
+
+/// An exported entry, with its fiat valuation if one was requested.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ExportEntry {
+    #[serde(flatten)]
+    pub entry: LedgerEntry,
+    pub currency: Option<String>,
+    /// The price of one bitcoin at the time of the entry.
+    pub rate: Option<f64>,
+    /// The fiat value of the credit or debit.
+    pub fiat_value: Option<f64>,
+    /// The average cost of the funds disposed of, for debits.
+    pub cost_basis: Option<f64>,
+}
+
+/// All entries of the node's bookkeeper, ordered by time.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Ledger {
+    entries: Vec<LedgerEntry>,
+}
+
+impl Ledger {
+    pub fn new(mut entries: Vec<LedgerEntry>) -> Self {
+        entries.sort_by_key(|e| e.timestamp);
+        mark_internal(&mut entries);
+        Ledger { entries }
+    }
+
+    /// Fetch the events of all accounts from the node.
+    pub async fn fetch(node: &mut Client) -> Result<Self> {
+        let events = node
+            .bkpr_list_account_events(BkprListAccountEventsRequest::default())
+            .await?
+            .into_inner()
+            .events;
+        Ok(Ledger::new(events.into_iter().map(|e| e.into()).collect()))
+    }
+
+    pub fn entries(&self) -> &[LedgerEntry] {
+        &self.entries
+    }
+
+    /// Value the entries in `range`. The cost basis accounts for all
+    /// entries up to the end of the range, so the rate is looked up
+    /// for entries before the start as well.
+    pub async fn valued_entries(
+        &self,
+        range: DateRange,
+        valuation: Option<(&dyn RateProvider, &str)>,
+    ) -> Result<Vec<ExportEntry>> {
+        let mut res = vec![];
+        let mut held_msat: u64 = 0;
+        let mut held_cost = 0.0;
+
+        for e in self.entries.iter() {
+            if range.end.is_some_and(|end| e.timestamp > end) {
+                break;
+            }
+
+            let mut row = ExportEntry {
+                entry: e.clone(),
+                currency: None,
+                rate: None,
+                fiat_value: None,
+                cost_basis: None,
+            };
+
+            if let Some((provider, currency)) = valuation {
+                let rate = provider.rate(currency, e.timestamp).await?;
+                let amount_msat = e.credit_msat.max(e.debit_msat);
+                row.currency = Some(currency.to_string());
+                row.rate = Some(rate);
+                row.fiat_value = Some(amount_msat as f64 / MSAT_PER_BTC * rate);
+
+                if !e.is_rebalance && !e.is_internal {
+                    held_msat += e.credit_msat;
+                    held_cost += e.credit_msat as f64 / MSAT_PER_BTC * rate;
+
+                    if e.debit_msat > 0 {
+                        let disposed = e.debit_msat.min(held_msat);
+                        let basis = if held_msat > 0 {
+                            held_cost * disposed as f64 / held_msat as f64
+                        } else {
+                            0.0
+                        };
+                        held_msat -= disposed;
+                        held_cost -= basis;
+                        row.cost_basis = Some(basis);
+                    }
+                }
+            }
+
+            if range.contains(e.timestamp) {
+                res.push(row);
+            }
+        }
+        Ok(res)
+    }
+
+    /// Render the entries in `range` in the given `format`. Pass a
+    /// rate provider and a currency code to include fiat valuations.
+    pub async fn export(
+        &self,
+        format: Format,
+        range: DateRange,
+        valuation: Option<(&dyn RateProvider, &str)>,
+    ) -> Result<String> {
+        let entries = self.valued_entries(range, valuation).await?;
+        match format {
+            Format::Json => Ok(serde_json::to_string_pretty(&entries)?),
+            Format::Csv => Ok(to_csv(&entries)),
+        }
+    }
+}
+
+/// Fetch the ledger of `node` and export the entries in `range`.
+pub async fn export(
+    node: &mut Client,
+    format: Format,
+    range: DateRange,
+    valuation: Option<(&dyn RateProvider, &str)>,
+) -> Result<String> {
+    Ledger::fetch(node)
+        .await?
+        .export(format, range, valuation)
+        .await
+}
+
+/// Mark the entries moving funds between the wallet and the channels:
+/// those of transactions touching both, and those channel accounts
+/// tag as coming from or going to the wallet. Onchain fees are paid
+/// to miners, and stay disposals.
+fn mark_internal(entries: &mut [LedgerEntry]) {
+    let mut wallet = HashSet::new();
+    let mut channels = HashSet::new();
+    for e in entries.iter().filter(|e| !e.txid.is_empty()) {
+        match e.account.as_str() {
+            WALLET_ACCOUNT => wallet.insert(e.txid.clone()),
+            EXTERNAL_ACCOUNT => false,
+            _ => channels.insert(e.txid.clone()),
+        };
+    }
+    for e in entries.iter_mut() {
+        if e.account == EXTERNAL_ACCOUNT || e.tag == "onchain_fee" {
+            continue;
+        }
+        let transfer = wallet.contains(&e.txid) && channels.contains(&e.txid);
+        let channel_transfer =
+            e.account != WALLET_ACCOUNT && CHANNEL_TRANSFER_TAGS.contains(&e.tag.as_str());
+        e.is_internal |= transfer || channel_transfer;
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn opt<T: ToString>(v: &Option<T>) -> String {
+    v.as_ref().map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn to_csv(entries: &[ExportEntry]) -> String {
+    let mut out = format!("{}\n", CSV_HEADER);
+    for r in entries {
+        let e = &r.entry;
+        let fields = [
+            e.timestamp.to_string(),
+            csv_field(&e.account),
+            csv_field(&e.tag),
+            e.credit_msat.to_string(),
+            e.debit_msat.to_string(),
+            e.fees_msat.to_string(),
+            csv_field(&e.reference),
+            csv_field(&e.description),
+            csv_field(&opt(&r.currency)),
+            opt(&r.rate),
+            opt(&r.fiat_value),
+            opt(&r.cost_basis),
+        ];
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct FixedRate;
+
+    #[async_trait]
+    impl RateProvider for FixedRate {
+        async fn rate(&self, _currency: &str, at: u64) -> Result<f64> {
+            // The price doubles after the first entry.
+            Ok(if at < 200 { 10_000.0 } else { 20_000.0 })
+        }
+    }
+
+    fn entry(timestamp: u64, credit_msat: u64, debit_msat: u64) -> LedgerEntry {
+        LedgerEntry {
+            timestamp,
+            account: "wallet".to_string(),
+            tag: if credit_msat > 0 {
+                "deposit"
+            } else {
+                "withdrawal"
+            }
+            .to_string(),
+            credit_msat,
+            debit_msat,
+            description: "coffee, \"large\"".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn ledger() -> Ledger {
+        Ledger::new(vec![
+            entry(300, 0, 100_000_000_000),
+            entry(100, 100_000_000_000, 0),
+            entry(200, 100_000_000_000, 0),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_cost_basis() {
+        let entries = ledger()
+            .valued_entries(DateRange::new(Some(150), None), Some((&FixedRate, "USD")))
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].fiat_value, Some(20_000.0));
+        assert_eq!(entries[0].cost_basis, None);
+        // Average cost of the two bitcoin held is 15'000.
+        assert_eq!(entries[1].fiat_value, Some(20_000.0));
+        assert_eq!(entries[1].cost_basis, Some(15_000.0));
+    }
+
+    #[tokio::test]
+    async fn test_export_csv() {
+        let csv = ledger()
+            .export(Format::Csv, DateRange::new(None, Some(100)), None)
+            .await
+            .unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(
+            lines[1],
+            "100,wallet,deposit,100000000000,0,0,,\"coffee, \"\"large\"\"\",,,,"
+        );
+        assert_eq!(lines.len(), 2);
+
+        let json = ledger()
+            .export(
+                Format::Json,
+                DateRange::default(),
+                Some((&FixedRate, "USD")),
+            )
+            .await
+            .unwrap();
+        let v: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(v[2]["cost_basis"], 15_000.0);
+        assert_eq!(v[2]["account"], "wallet");
+    }
+
+    #[tokio::test]
+    async fn test_internal_moves() {
+        let onchain = |timestamp, account: &str, tag: &str, credit_msat, debit_msat, txid: &str| {
+            LedgerEntry {
+                timestamp,
+                account: account.to_string(),
+                tag: tag.to_string(),
+                credit_msat,
+                debit_msat,
+                txid: txid.to_string(),
+                ..Default::default()
+            }
+        };
+        let btc = 100_000_000_000;
+        let ledger = Ledger::new(vec![
+            onchain(100, "wallet", "deposit", btc, 0, "aa"),
+            // Fund a channel, with change back to the wallet.
+            onchain(200, "wallet", "withdrawal", 0, btc, "bb"),
+            onchain(200, "wallet", "deposit", btc / 2, 0, "bb"),
+            onchain(200, "chan1", "channel_open", btc / 2, 0, "bb"),
+            // Close it back to the wallet.
+            onchain(300, "chan1", "channel_close", 0, btc / 2, "cc"),
+            onchain(300, "wallet", "deposit", btc / 2, 0, "cc"),
+            onchain(400, "wallet", "withdrawal", 0, btc, "dd"),
+            onchain(400, "external", "deposit", btc, 0, "dd"),
+        ]);
+        let internal: Vec<bool> = ledger.entries().iter().map(|e| e.is_internal).collect();
+        assert_eq!(
+            internal,
+            vec![false, true, true, true, true, true, false, false]
+        );
+
+        let entries = ledger
+            .valued_entries(DateRange::default(), Some((&FixedRate, "USD")))
+            .await
+            .unwrap();
+        // The bitcoin bought for 10'000 is disposed of at that cost.
+        assert_eq!(entries[6].cost_basis, Some(10_000.0));
+        assert!(entries[1..6].iter().all(|e| e.cost_basis.is_none()));
+    }
+}
@@ -26,10 +26,17 @@ pub mod scheduler;
 /// This module implements the logic to stream, verify and respond to
 /// signature requests from the node. Without this the node cannot
 /// move your funds.
+#[cfg(feature = "signer")]
 pub mod signer;
 
+// The signer decodes the requests of all methods.
+#[cfg(all(feature = "signer", cln_trimmed))]
+compile_error!("`GL_CLN_METHODS` requires building without the `signer` feature");
+
+#[cfg(feature = "signer")]
 pub mod persist;
 
+#[cfg(not(cln_trimmed))]
 pub mod lnurl;
 
 /// Helpers to configure the mTLS connection authentication.
@@ -50,11 +57,142 @@ pub mod utils;
 
 pub mod credentials;
 
+/// The keys derived from the node seed.
+pub mod derivation;
+
+/// Key material that is wiped from memory when dropped.
+pub mod secret;
+
 /// Functionality to integrate greenlight with a Lightning Service Provider
+#[cfg(not(cln_trimmed))]
 pub mod lsps;
 
 pub mod util;
 
+/// Summarize the node's balance, including reserves and emergency
+/// funds that can not be spent.
+#[cfg(not(cln_trimmed))]
+pub mod balance;
+
+/// Track closing channels until their funds are back onchain.
+#[cfg(not(cln_trimmed))]
+pub mod closures;
+
+/// Events published by the library to inform the application.
+pub mod events;
+
+/// Verify static channel backups against the node's channels.
+#[cfg(not(cln_trimmed))]
+pub mod backup;
+
+/// Move funds between onchain and Lightning using submarine swaps.
+#[cfg(all(feature = "signer", not(cln_trimmed)))]
+pub mod swaps;
+
+/// Export the node's ledger as CSV or JSON for accounting tools.
+pub mod ledger;
+
+/// Look up fiat exchange rates for bitcoin.
+pub mod rates;
+
+/// Notify when the inbound liquidity of the node runs low.
+#[cfg(not(cln_trimmed))]
+pub mod liquidity;
+
+/// Adjust the routing fees of the node's channels automatically.
+#[cfg(not(cln_trimmed))]
+pub mod fee_manager;
+
+/// Compute when the node must be woken up to settle pending payments.
+#[cfg(not(cln_trimmed))]
+pub mod wakeup;
+
+/// Decode push notifications for incoming payments.
+pub mod push;
+
+/// Pregenerate invoices to receive while the app is closed.
+#[cfg(not(cln_trimmed))]
+pub mod invoice_pool;
+
+/// Preview an invoice before the node creates it.
+#[cfg(not(cln_trimmed))]
+pub mod invoice_preview;
+
+/// Observe the state of the connection to the node.
+pub mod connection;
+
+/// Shut down clients and signers without racing against drop order.
+pub mod shutdown;
+
+/// Choose the tokio runtime the library spawns its tasks on.
+pub mod runtime;
+
+/// Timings of the steps to get a node running and connected.
+pub mod metrics;
+
+/// Load the client setup from a TOML file and the environment.
+pub mod config;
+
+/// Target Greenlight or a local node with the same client.
+pub mod backend;
+
+/// Check that a long-running signer is still able to sign.
+pub mod health;
+
+/// Detect rate limit responses and when to retry.
+pub mod ratelimit;
+
+/// Pin the node to a CLN version, and opt in to upgrades.
+pub mod versions;
+
+/// Inject custom behavior into the calls of the clients.
+pub mod interceptor;
+
+/// Abandon all calls of an operation once its deadline passes.
+pub mod deadline;
+
+/// Record the calls to a node, and replay them without one.
+pub mod record;
+
+/// Drive many nodes from one process.
+#[cfg(not(cln_trimmed))]
+pub mod fleet;
+
+/// Record payments before they are made, to tell after a crash
+/// whether they were sent.
+#[cfg(not(cln_trimmed))]
+pub mod journal;
+
+/// Compare the node's settings to a desired config, and converge them.
+#[cfg(not(cln_trimmed))]
+pub mod node_config;
+
+/// Delete expired invoices and old forwards and payments.
+pub mod housekeeping;
+
+/// Follow the changes to invoices, payments and forwards.
+#[cfg(not(cln_trimmed))]
+pub mod wait;
+
+/// Refresh a local cache with the records changed since the last sync.
+#[cfg(not(cln_trimmed))]
+pub mod sync;
+
+/// A typed view of the node's channels, from `listpeerchannels`.
+#[cfg(not(cln_trimmed))]
+pub mod channels;
+
+/// Conversions between the amount types of the generated models.
+pub mod amount;
+
+/// Experimental routing with the `askrene` plugin.
+#[cfg(feature = "experimental")]
+pub mod askrene;
+
+/// Render invoices, offers and LNURLs as QR codes.
+#[cfg(feature = "qr")]
+pub mod qr;
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -70,4 +208,5 @@ pub use lightning_signer::lightning_invoice;
 pub(crate) const TCP_KEEPALIVE: Duration = Duration::from_secs(5);
 pub(crate) const TCP_KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(90);
 
+#[cfg(feature = "signer")]
 pub mod runes;
@@ -0,0 +1,193 @@
+//! Notify the application when the node can no longer receive.
+//!
+//! Mobile users mostly receive over a handful of channels, and once
+//! their inbound capacity is used up incoming payments start failing
+//! without any indication on their side. The [`LiquidityMonitor`]
+//! recomputes the receivable capacity whenever a payment arrives, and
+//! periodically to catch channel changes, and publishes
+//! [`Event::LowInboundLiquidity`] when it falls below a threshold. It
+//! can also forward the event to a webhook, and buy a new channel
+//! from an LSP using LSPS1.
+use crate::balance::receivable_msat;
+use crate::events::{Event, EventBus};
+use crate::lsps::client::LspClient;
+use crate::lsps::json_rpc::JsonRpcResponse;
+use crate::lsps::lsps0::common_schemas::SatAmount;
+use crate::lsps::lsps1::schema::Lsps1GetOrderRequest;
+use crate::lsps::message::LSPS1_GETORDER;
+use crate::node::{Client, ClnClient};
+use crate::pb::cln::ListpeerchannelsRequest;
+use crate::pb::StreamIncomingFilter;
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use tokio::time::{sleep, Duration};
+
+/// How often the capacity is recomputed if no payments arrive, to
+/// account for channels being opened or closed.
+const POLL_INTERVAL: Duration = Duration::from_secs(300);
+
+/// The channel to request from an LSP when inbound liquidity is low.
+#[derive(Clone, Debug)]
+pub struct Lsps1AutoOrder {
+    /// The node id of the LSP.
+    pub lsp_id: Vec<u8>,
+    /// The inbound capacity to buy.
+    pub lsp_balance_sat: u64,
+    pub confirms_within_blocks: u8,
+    pub channel_expiry_blocks: u32,
+}
+
+impl Lsps1AutoOrder {
+    fn request(&self) -> Lsps1GetOrderRequest {
+        Lsps1GetOrderRequest {
+            api_version: 1,
+            lsp_balance_sat: SatAmount::new(self.lsp_balance_sat),
+            client_balance_sat: SatAmount::new(0),
+            confirms_within_blocks: self.confirms_within_blocks,
+            channel_expiry_blocks: self.channel_expiry_blocks,
+            token: None,
+            refund_onchain_address: None,
+            announce_channel: "false".to_string(),
+        }
+    }
+}
+
+pub struct LiquidityMonitor {
+    threshold_msat: u64,
+    events: EventBus,
+    webhook: Option<String>,
+    auto_order: Option<Lsps1AutoOrder>,
+    low: bool,
+    http: reqwest::Client,
+}
+
+impl LiquidityMonitor {
+    /// Publish an event on `events` when the receivable capacity
+    /// drops below `threshold_msat`.
+    pub fn new(threshold_msat: u64, events: EventBus) -> Self {
+        LiquidityMonitor {
+            threshold_msat,
+            events,
+            webhook: None,
+            auto_order: None,
+            low: false,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Also `POST` the event as JSON to `url`.
+    pub fn with_webhook(mut self, url: &str) -> Self {
+        self.webhook = Some(url.to_string());
+        self
+    }
+
+    /// Also order a new channel from an LSP.
+    pub fn with_auto_order(mut self, order: Lsps1AutoOrder) -> Self {
+        self.auto_order = Some(order);
+        self
+    }
+
+    /// Record the current receivable capacity. Returns the event to
+    /// publish if the capacity just dropped below the threshold, so
+    /// we notify once, and again only after it recovered.
+    pub fn update(&mut self, receivable_msat: u64) -> Option<Event> {
+        let low = receivable_msat < self.threshold_msat;
+        let notify = low && !self.low;
+        self.low = low;
+        if notify {
+            Some(Event::LowInboundLiquidity {
+                receivable_msat,
+                threshold_msat: self.threshold_msat,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Recompute the receivable capacity, and notify if it is low.
+    pub async fn check(&mut self, node: &Client, cln: &ClnClient) -> Result<u64> {
+        let channels = cln
+            .clone()
+            .list_peer_channels(ListpeerchannelsRequest::default())
+            .await
+            .map_err(|e| anyhow!(e))?
+            .into_inner();
+        let receivable = receivable_msat(&channels);
+        debug!("Receivable capacity is {}msat", receivable);
+
+        if let Some(event) = self.update(receivable) {
+            self.events.publish(event.clone());
+            if let Some(url) = &self.webhook {
+                if let Err(e) = self.http.post(url).json(&event).send().await {
+                    warn!("Could not deliver liquidity webhook to {}: {}", url, e);
+                }
+            }
+            if let Some(order) = &self.auto_order {
+                self.order(order, node, cln).await;
+            }
+        }
+        Ok(receivable)
+    }
+
+    async fn order(&self, order: &Lsps1AutoOrder, node: &Client, cln: &ClnClient) {
+        let mut lsp = LspClient::new(node.clone(), cln.clone());
+        match lsp
+            .request(&order.lsp_id, &LSPS1_GETORDER, order.request())
+            .await
+        {
+            Ok(JsonRpcResponse::Ok(res)) => info!("Ordered inbound liquidity: {:?}", res.result),
+            Ok(JsonRpcResponse::Error(e)) => {
+                warn!("LSP refused the liquidity order: {}", e.error.message)
+            }
+            Err(e) => warn!("Could not order inbound liquidity: {}", e),
+        }
+    }
+
+    /// Check the capacity after every incoming payment, and every
+    /// [`POLL_INTERVAL`]. Returns when the node closes the stream.
+    pub async fn run(mut self, node: Client, cln: ClnClient) -> Result<()> {
+        let mut incoming = node
+            .clone()
+            .stream_incoming(StreamIncomingFilter {})
+            .await
+            .map_err(|e| anyhow!(e))?
+            .into_inner();
+
+        loop {
+            if let Err(e) = self.check(&node, &cln).await {
+                warn!("Could not check inbound liquidity: {}", e);
+            }
+            tokio::select! {
+                _ = sleep(POLL_INTERVAL) => {},
+                msg = incoming.message() => {
+                    if msg.map_err(|e| anyhow!(e))?.is_none() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notify_once() {
+        let mut monitor = LiquidityMonitor::new(100_000, EventBus::new());
+        assert_eq!(monitor.update(200_000), None);
+        assert_eq!(
+            monitor.update(50_000),
+            Some(Event::LowInboundLiquidity {
+                receivable_msat: 50_000,
+                threshold_msat: 100_000,
+            })
+        );
+        assert_eq!(monitor.update(40_000), None);
+
+        // Notify again once the capacity recovered and dropped again.
+        assert_eq!(monitor.update(150_000), None);
+        assert!(monitor.update(10_000).is_some());
+    }
+}
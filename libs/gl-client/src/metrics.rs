@@ -0,0 +1,132 @@
+//! Measure where the time goes when starting to talk to a node.
+//!
+//! The first call to a node that is not running pays for contacting
+//! the scheduler, booting the node, and opening the connection to
+//! it. [`Startup::last`] returns how long each of these took on the
+//! most recent startup, so applications can report slow starts, and
+//! see whether [`Scheduler::prewarm`] helps.
+//!
+//! Each [`Scheduler`] and [`Node`] measures its own startups, see
+//! [`Scheduler::startup`] and [`Node::startup`]. Share one [`Startup`]
+//! between them with [`Node::with_startup`].
+//!
+//! [`Scheduler`]: crate::scheduler::Scheduler
+//! [`Scheduler::prewarm`]: crate::scheduler::Scheduler::prewarm
+//! [`Scheduler::startup`]: crate::scheduler::Scheduler::startup
+//! [`Node`]: crate::node::Node
+//! [`Node::startup`]: crate::node::Node::startup
+//! [`Node::with_startup`]: crate::node::Node::with_startup
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct StartupTimings {
+    /// Until the scheduler returned the node's address. This includes
+    /// booting the node if it was not running.
+    pub schedule: Duration,
+    /// From the node's address to the response to the first RPC,
+    /// which includes opening the connection to the node.
+    pub connect: Duration,
+}
+
+impl StartupTimings {
+    pub fn total(&self) -> Duration {
+        self.schedule + self.connect
+    }
+}
+
+/// The startup measurements of the clients of one scheduler or node.
+#[derive(Clone, Debug, Default)]
+pub struct Startup {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// The startup that waits for its first RPC to complete.
+    pending: Option<StartupTimer>,
+    /// The timings of the last completed startup.
+    last: Option<StartupTimings>,
+}
+
+impl Startup {
+    pub fn new() -> Self {
+        Startup::default()
+    }
+
+    /// The timings of the most recent startup, once its first RPC
+    /// completed.
+    pub fn last(&self) -> Option<StartupTimings> {
+        self.inner.lock().unwrap().last.clone()
+    }
+
+    /// The node is scheduled, the remaining time until the next RPC
+    /// completes is attributed to connecting, see
+    /// [`Startup::rpc_completed`].
+    pub(crate) fn scheduled(&self, mut timer: StartupTimer) {
+        timer.timings.schedule = timer.lap();
+        self.inner.lock().unwrap().pending = Some(timer);
+    }
+
+    /// Called when an RPC through a client of this startup completed,
+    /// to finish a pending measurement.
+    pub(crate) fn rpc_completed(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(mut timer) = inner.pending.take() {
+            timer.timings.connect = timer.lap();
+            inner.last = Some(timer.timings);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct StartupTimer {
+    lap: Instant,
+    timings: StartupTimings,
+}
+
+impl StartupTimer {
+    pub(crate) fn start() -> Self {
+        StartupTimer {
+            lap: Instant::now(),
+            timings: StartupTimings::default(),
+        }
+    }
+
+    fn lap(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now - self.lap;
+        self.lap = now;
+        elapsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_startup_timer() {
+        let startup = Startup::new();
+        let other = Startup::new();
+        let timer = StartupTimer::start();
+        std::thread::sleep(Duration::from_millis(5));
+        startup.scheduled(timer);
+        std::thread::sleep(Duration::from_millis(5));
+
+        // RPCs of other nodes do not finish the measurement.
+        other.rpc_completed();
+        assert_eq!(other.last(), None);
+        assert_eq!(startup.last(), None);
+
+        startup.rpc_completed();
+        let timings = startup.last().unwrap();
+        assert!(timings.schedule >= Duration::from_millis(5));
+        assert!(timings.connect >= Duration::from_millis(5));
+
+        // Later RPCs do not overwrite the measurement.
+        startup.rpc_completed();
+        assert_eq!(startup.last(), Some(timings));
+    }
+}
@@ -0,0 +1,193 @@
+//! Tell apart the reasons a node call failed.
+//!
+//! Node calls fail with a [`Status`], and the code alone does not say
+//! whether `lightningd` rejected the command, the signer refused to
+//! sign for it, the node asked us to slow down, or the connection was
+//! lost. [`NodeError`] sorts the
+//! status into the cases applications handle differently, e.g., to
+//! retry a call, or to show the error of a command to the user.
+use crate::ratelimit::RateLimited;
+use serde_json::Value;
+use thiserror::Error;
+use tonic::{Code, Status};
+
+/// The marker of the signer's policy violations, e.g., `policy
+/// failure: validate_payment_balance: ...`.
+const POLICY_FAILURE: &str = "policy failure";
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum NodeError {
+    /// The node could not be reached, or the connection was lost
+    /// before it answered. The call may be retried.
+    #[error("connection to the node failed: {0}")]
+    Connection(String),
+
+    /// The node, or a proxy in front of it, rate limited the call,
+    /// see [`crate::ratelimit`]. The call may be retried after
+    /// [`RateLimited::backoff`].
+    #[error(transparent)]
+    RateLimited(RateLimited),
+
+    /// The credentials of the client were not accepted, e.g., because
+    /// the rune does not allow the method.
+    #[error("not authorized: {0}")]
+    Auth(String),
+
+    /// The signer refused to sign for the call, e.g., because it
+    /// violates the signer's policy.
+    #[error("signer rejected the request: {0}")]
+    SignerRejected(String),
+
+    /// `lightningd` returned a JSON-RPC error for the command.
+    #[error("error calling {method}: {message} (code {code:?})")]
+    Rpc {
+        method: String,
+        /// The JSON-RPC error code, e.g., `-32602` for invalid
+        /// parameters, or one of the command's error codes.
+        code: Option<i64>,
+        message: String,
+        data: Option<Value>,
+    },
+
+    #[error("node returned an error: {0}")]
+    Other(String),
+}
+
+impl From<Status> for NodeError {
+    fn from(status: Status) -> Self {
+        NodeError::from(&status)
+    }
+}
+
+impl From<&Status> for NodeError {
+    fn from(status: &Status) -> Self {
+        if let Some(limited) = RateLimited::from_status(status) {
+            return NodeError::RateLimited(limited);
+        }
+        let message = status.message().to_string();
+        match status.code() {
+            Code::Unavailable | Code::Cancelled | Code::DeadlineExceeded => {
+                NodeError::Connection(message)
+            }
+            Code::Unauthenticated | Code::PermissionDenied => NodeError::Auth(message),
+            _ if message.contains(POLICY_FAILURE) => NodeError::SignerRejected(message),
+            // Transport errors surface with an unknown code.
+            Code::Unknown if message.starts_with("transport error") => {
+                NodeError::Connection(message)
+            }
+            _ => rpc_error(&message).unwrap_or(NodeError::Other(message)),
+        }
+    }
+}
+
+impl NodeError {
+    /// Whether the call may succeed if it is retried as is.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, NodeError::Connection(_) | NodeError::RateLimited(_))
+    }
+}
+
+/// Parse the JSON-RPC error the node forwards from `lightningd`, e.g.,
+/// `Error calling method Pay: RpcError { code: Some(210), message:
+/// "Ran out of routes to try", data: None }`.
+fn rpc_error(message: &str) -> Option<NodeError> {
+    let (prefix, rest) = message.split_once("RpcError {")?;
+    let method = prefix
+        .trim()
+        .trim_end_matches(':')
+        .rsplit(' ')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let code = rest
+        .split_once("code: Some(")
+        .and_then(|(_, c)| c.split_once(')'))
+        .and_then(|(c, _)| c.trim().parse().ok());
+    let (_, msg) = rest.split_once("message: \"")?;
+    let (msg, data) = unescape_debug(msg)?;
+    // Values print in their `Debug` format, which only matches JSON
+    // for strings and numbers, anything else is kept as a string.
+    let data = data
+        .split_once("data: Some(")
+        .and_then(|(_, d)| d.rsplit_once(')'))
+        .map(|(d, _)| {
+            let d = d
+                .strip_prefix("String(")
+                .or_else(|| d.strip_prefix("Number("))
+                .and_then(|d| d.strip_suffix(')'))
+                .unwrap_or(d);
+            serde_json::from_str(d).unwrap_or_else(|_| Value::String(d.to_string()))
+        });
+    Some(NodeError::Rpc {
+        method,
+        code,
+        message: msg,
+        data,
+    })
+}
+
+/// Read a string printed with `Debug` up to its closing quote,
+/// returning it and the remainder.
+fn unescape_debug(s: &str) -> Option<(String, &str)> {
+    let mut out = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((out, &s[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                c => out.push(c),
+            },
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let err = NodeError::from(Status::unknown(
+            "Error calling method Pay: RpcError { code: Some(210), message: \"Ran out of \\\"routes\\\"\", data: None }",
+        ));
+        assert_eq!(
+            err,
+            NodeError::Rpc {
+                method: "Pay".to_string(),
+                code: Some(210),
+                message: "Ran out of \"routes\"".to_string(),
+                data: None,
+            }
+        );
+
+        let err = NodeError::from(Status::unknown(
+            "Error calling method Invoice: RpcError { code: Some(900), message: \"Duplicate label\", data: Some(String(\"label\")) }",
+        ));
+        assert!(matches!(err, NodeError::Rpc { data: Some(Value::String(d)), .. } if d == "label"));
+
+        let err = NodeError::from(Status::unknown(
+            "Error calling method Pay: RpcError { code: Some(-1), message: \"policy failure: validate_payment_balance\", data: None }",
+        ));
+        assert!(matches!(err, NodeError::SignerRejected(_)));
+
+        assert!(NodeError::from(Status::unavailable("node restarting")).is_retryable());
+        assert!(matches!(
+            NodeError::from(Status::permission_denied("rune does not allow Pay")),
+            NodeError::Auth(_)
+        ));
+        let err = NodeError::from(Status::resource_exhausted("rate limit exceeded"));
+        assert!(matches!(err, NodeError::RateLimited(_)) && err.is_retryable());
+        assert!(matches!(
+            NodeError::from(Status::resource_exhausted("message too large")),
+            NodeError::Other(_)
+        ));
+        assert!(matches!(
+            NodeError::from(Status::internal("something else")),
+            NodeError::Other(_)
+        ));
+    }
+}
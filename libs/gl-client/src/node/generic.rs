@@ -9,6 +9,9 @@
 //! `tonic::Service` and method on that service is spelled out, and
 //! would make for a very wide interface to be mapped.
 
+use super::LazyList;
+#[cfg(not(cln_trimmed))]
+use crate::pb::cln;
 use bytes::{Buf, BufMut, Bytes};
 use http_body::Body;
 use log::trace;
@@ -41,6 +44,12 @@ where
         Self { inner }
     }
 
+    /// Accept responses compressed with `encoding`.
+    pub fn accept_compressed(mut self, encoding: tonic::codec::CompressionEncoding) -> Self {
+        self.inner = self.inner.accept_compressed(encoding);
+        self
+    }
+
     pub async fn call(
         &mut self,
         path: &str,
@@ -65,6 +74,41 @@ where
             .await
     }
 
+    /// Call the list method `path`, returning the entries of the
+    /// repeated field `tag` of the response, to be decoded as they
+    /// are iterated, see [`LazyList`].
+    pub async fn call_lazy<R, E>(
+        &mut self,
+        path: &str,
+        request: R,
+        tag: u32,
+    ) -> Result<LazyList<E>, tonic::Status>
+    where
+        R: prost::Message,
+        E: prost::Message + Default,
+    {
+        let res = self.call(path, request.encode_to_vec()).await?;
+        Ok(LazyList::new(res.into_inner(), tag))
+    }
+
+    /// `listforwards`, decoding the forwards as they are iterated.
+    #[cfg(not(cln_trimmed))]
+    pub async fn list_forwards_lazy(
+        &mut self,
+        request: cln::ListforwardsRequest,
+    ) -> Result<LazyList<cln::ListforwardsForwards>, tonic::Status> {
+        self.call_lazy("/cln.Node/ListForwards", request, 1).await
+    }
+
+    /// `listchannels`, decoding the channels as they are iterated.
+    #[cfg(not(cln_trimmed))]
+    pub async fn list_channels_lazy(
+        &mut self,
+        request: cln::ListchannelsRequest,
+    ) -> Result<LazyList<cln::ListchannelsChannels>, tonic::Status> {
+        self.call_lazy("/cln.Node/ListChannels", request, 1).await
+    }
+
     // TODO Add a `streaming_call` for methods that return a stream to the client
 }
 
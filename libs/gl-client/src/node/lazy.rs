@@ -0,0 +1,154 @@
+//! Decode the entries of large list responses one at a time.
+//!
+//! The generated clients decode a response completely before
+//! returning it, so listing the forwards or channels of a big node
+//! holds the encoded response and all decoded entries in memory at
+//! once. A [`LazyList`] keeps only the encoded response, and decodes
+//! the entries as they are iterated. Callers that process the entries
+//! one by one, and drop them, need little memory on top of the
+//! response itself.
+use bytes::{Buf, Bytes};
+use prost::encoding::{decode_key, decode_varint, skip_field, DecodeContext, WireType};
+use prost::{DecodeError, Message};
+use std::marker::PhantomData;
+
+/// The entries of a repeated message field in an encoded response.
+#[derive(Clone, Debug)]
+pub struct LazyList<T> {
+    buf: Bytes,
+    tag: u32,
+    entry: PhantomData<fn() -> T>,
+}
+
+impl<T: Message + Default> LazyList<T> {
+    /// The entries of the repeated field `tag` of the encoded message
+    /// `buf`.
+    pub fn new(buf: Bytes, tag: u32) -> Self {
+        LazyList {
+            buf,
+            tag,
+            entry: PhantomData,
+        }
+    }
+
+    /// Decode the entries in order. Iteration stops after the first
+    /// error, since the rest of the message can not be located.
+    pub fn iter(&self) -> Iter<T> {
+        Iter {
+            rest: self.buf.clone(),
+            tag: self.tag,
+            entry: PhantomData,
+        }
+    }
+
+    /// The number of entries, counted without decoding them.
+    pub fn len(&self) -> Result<usize, DecodeError> {
+        let mut iter = self.iter();
+        let mut len = 0;
+        while iter.rest.has_remaining() {
+            if iter.next_field()?.is_some() {
+                len += 1;
+            }
+        }
+        Ok(len)
+    }
+
+    pub fn is_empty(&self) -> Result<bool, DecodeError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Decode all entries at once.
+    pub fn to_vec(&self) -> Result<Vec<T>, DecodeError> {
+        self.iter().collect()
+    }
+
+    /// The size of the encoded response.
+    pub fn encoded_len(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+impl<T: Message + Default> IntoIterator for &LazyList<T> {
+    type Item = Result<T, DecodeError>;
+    type IntoIter = Iter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Iter<T> {
+    rest: Bytes,
+    tag: u32,
+    entry: PhantomData<fn() -> T>,
+}
+
+impl<T> Iter<T> {
+    /// Consume the next field, returning it if it is an entry.
+    fn next_field(&mut self) -> Result<Option<Bytes>, DecodeError> {
+        let (tag, wire_type) = decode_key(&mut self.rest)?;
+        if tag != self.tag || wire_type != WireType::LengthDelimited {
+            skip_field(wire_type, tag, &mut self.rest, DecodeContext::default())?;
+            return Ok(None);
+        }
+        let len = decode_varint(&mut self.rest)? as usize;
+        if len > self.rest.len() {
+            return Err(DecodeError::new("buffer underflow"));
+        }
+        Ok(Some(self.rest.split_to(len)))
+    }
+}
+
+impl<T: Message + Default> Iterator for Iter<T> {
+    type Item = Result<T, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.rest.has_remaining() {
+            match self.next_field() {
+                Ok(Some(entry)) => return Some(T::decode(entry)),
+                Ok(None) => continue,
+                Err(e) => {
+                    self.rest.clear();
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Entry {
+        #[prost(uint64, tag = "1")]
+        id: u64,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct List {
+        #[prost(message, repeated, tag = "1")]
+        entries: Vec<Entry>,
+        #[prost(string, tag = "2")]
+        note: String,
+    }
+
+    #[test]
+    fn test_lazy_list() {
+        let list = List {
+            entries: (0..3).map(|id| Entry { id }).collect(),
+            note: "other fields are skipped".to_string(),
+        };
+        let lazy: LazyList<Entry> = LazyList::new(list.encode_to_vec().into(), 1);
+        assert_eq!(lazy.len().unwrap(), 3);
+        assert_eq!(lazy.to_vec().unwrap(), list.entries);
+
+        let mut truncated = list.encode_to_vec();
+        truncated.truncate(5);
+        let lazy: LazyList<Entry> = LazyList::new(truncated.into(), 1);
+        assert!(lazy.len().is_err());
+        assert!(lazy.iter().any(|e| e.is_err()));
+    }
+}
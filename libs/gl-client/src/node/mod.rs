@@ -1,12 +1,22 @@
 use crate::credentials::{RuneProvider, TlsConfigProvider};
+use crate::interceptor::{Interceptor, Interceptors};
+use crate::metrics::{Startup, StartupTimer};
+use crate::shutdown::Shutdown;
 use crate::pb::cln::node_client as cln_client;
 use crate::pb::node_client::NodeClient;
 use crate::pb::scheduler::{scheduler_client::SchedulerClient, ScheduleRequest};
-use crate::tls::TlsConfig;
+use crate::record::Recorder;
+#[cfg(feature = "pinning")]
+use crate::tls::pinned::PinnedConnector;
+#[cfg(feature = "resumption")]
+use crate::tls::resumption::ResumingConnector;
+use crate::tls::{Pinning, TlsConfig};
 use crate::utils;
 use anyhow::{anyhow, Result};
 use log::{debug, info, trace};
-use tonic::transport::{Channel, Uri};
+use std::sync::Arc;
+use tonic::codec::CompressionEncoding;
+use tonic::transport::{Channel, Endpoint, Uri};
 use tower::ServiceBuilder;
 
 /// A client to the remotely running node on the greenlight
@@ -34,23 +44,37 @@ pub struct Node {
     node_id: Vec<u8>,
     tls: TlsConfig,
     rune: String,
+    shutdown: Shutdown,
+    interceptors: Interceptors,
+    compression: bool,
+    recorder: Option<Recorder>,
+    startup: Startup,
 }
 
 impl GrpcClient for Client {
     fn new_with_inner(inner: service::AuthService) -> Self {
-        Client::new(inner)
+        match inner.compression() {
+            true => Client::new(inner).accept_compressed(CompressionEncoding::Gzip),
+            false => Client::new(inner),
+        }
     }
 }
 
 impl GrpcClient for GClient {
     fn new_with_inner(inner: service::AuthService) -> Self {
-        GenericClient::new(inner)
+        match inner.compression() {
+            true => GenericClient::new(inner).accept_compressed(CompressionEncoding::Gzip),
+            false => GenericClient::new(inner),
+        }
     }
 }
 
 impl GrpcClient for ClnClient {
     fn new_with_inner(inner: service::AuthService) -> Self {
-        ClnClient::new(inner)
+        match inner.compression() {
+            true => ClnClient::new(inner).accept_compressed(CompressionEncoding::Gzip),
+            false => ClnClient::new(inner),
+        }
     }
 }
 
@@ -59,19 +83,77 @@ impl Node {
     where
         Creds: TlsConfigProvider + RuneProvider,
     {
-        let tls = creds.tls_config();
-        let rune = creds.rune();
+        let tls = creds.try_tls_config()?;
+        let rune = creds.try_rune()?;
         Ok(Node {
             node_id,
             tls,
             rune,
+            shutdown: Shutdown::new(),
+            interceptors: Interceptors::default(),
+            compression: false,
+            recorder: None,
+            startup: Startup::new(),
         })
     }
 
+    /// Track the calls of the clients created by this node on
+    /// `shutdown`, so they can be shut down together with the rest of
+    /// the application.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Run `interceptor` on the calls of the clients created by this
+    /// node, see [`crate::interceptor`].
+    pub fn with_interceptor(mut self, interceptor: impl Interceptor + 'static) -> Self {
+        self.interceptors = self.interceptors.with(Arc::new(interceptor));
+        self
+    }
+
+    pub(crate) fn with_interceptors(mut self, interceptors: Interceptors) -> Self {
+        self.interceptors = interceptors;
+        self
+    }
+
+    /// Ask the node to gzip its responses. Large list responses
+    /// shrink considerably, at the cost of some CPU on both ends.
+    /// Nodes that do not support compression keep responding
+    /// uncompressed. Requests are sent uncompressed either way.
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        self.compression = enabled;
+        self
+    }
+
+    /// Record the calls of the clients created by this node on
+    /// `recorder`, see [`crate::record`].
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Measure the startups of the clients created by this node on
+    /// `startup`, see [`crate::metrics`].
+    pub fn with_startup(mut self, startup: Startup) -> Self {
+        self.startup = startup;
+        self
+    }
+
+    pub fn startup(&self) -> &Startup {
+        &self.startup
+    }
+
     pub async fn connect<C>(&self, node_uri: String) -> Result<C>
     where
         C: GrpcClient,
     {
+        Ok(C::new_with_inner(self.channel(node_uri)?))
+    }
+
+    /// The authenticated channel to the node, which all client types
+    /// are built on.
+    pub(crate) fn channel(&self, node_uri: String) -> Result<service::AuthService> {
         let node_uri = Uri::from_maybe_shared(node_uri)?;
         info!("Connecting to node at {}", node_uri);
 
@@ -94,8 +176,13 @@ impl Node {
             tls
         };
 
-        let layer = match tls.private_key {
-            Some(k) => service::AuthLayer::new(k, self.rune.clone())?,
+        let layer = match tls.private_key.clone() {
+            Some(k) => service::AuthLayer::new(k, self.rune.clone())?
+                .with_shutdown(self.shutdown.clone())
+                .with_interceptors(self.interceptors.clone())
+                .with_compression(self.compression)
+                .with_recorder(self.recorder.clone())
+                .with_startup(self.startup.clone()),
             None => {
                 return Err(anyhow!(
                     "Cannot connect a node::Client without first configuring its identity"
@@ -103,16 +190,48 @@ impl Node {
             }
         };
 
-        let chan = tonic::transport::Endpoint::from_shared(node_uri.to_string())?
+        tls.check_ca()?;
+        #[cfg(any(feature = "pinning", feature = "resumption"))]
+        let domain = if host.starts_with("gl") {
+            host
+        } else {
+            "localhost"
+        };
+        #[cfg(feature = "pinning")]
+        if let Pinning::Spki(pins) = &tls.pinning {
+            let connector = PinnedConnector::new(&tls, domain, pins.clone())?;
+            // The connector does the TLS handshake, see `tls::pinned`.
+            let authority = node_uri
+                .authority()
+                .ok_or_else(|| anyhow!("node URI has no authority: {}", node_uri))?;
+            let uri = format!("http://{}", authority);
+            let chan = endpoint(uri)?.connect_with_connector_lazy(connector);
+            return Ok(ServiceBuilder::new().layer(layer).service(chan));
+        }
+
+        #[cfg(feature = "resumption")]
+        let chan = {
+            let connector = ResumingConnector::new(&tls, domain)?;
+            // The connector does the TLS handshake, see
+            // `tls::resumption`.
+            let authority = node_uri
+                .authority()
+                .ok_or_else(|| anyhow!("node URI has no authority: {}", node_uri))?;
+            let uri = format!("http://{}", authority);
+            endpoint(uri)?.connect_with_connector_lazy(connector)
+        };
+        #[cfg(not(feature = "resumption"))]
+        let chan = endpoint(node_uri.to_string())?
             .tls_config(tls.inner)?
-            .tcp_keepalive(Some(crate::TCP_KEEPALIVE))
-            .http2_keep_alive_interval(crate::TCP_KEEPALIVE)
-            .keep_alive_timeout(crate::TCP_KEEPALIVE_TIMEOUT)
-            .keep_alive_while_idle(true)
             .connect_lazy();
-        let chan = ServiceBuilder::new().layer(layer).service(chan);
+        Ok(ServiceBuilder::new().layer(layer).service(chan))
+    }
 
-        Ok(C::new_with_inner(chan))
+    /// Restrict the certificates accepted from the node, see
+    /// [`Pinning`].
+    pub fn with_pinning(mut self, pinning: Pinning) -> Self {
+        self.tls = self.tls.pinning(pinning);
+        self
     }
 
     pub async fn schedule_with_uri<C>(self, scheduler_uri: String) -> Result<C>
@@ -124,11 +243,15 @@ impl Node {
             scheduler_uri
         );
 
+        let timer = StartupTimer::start();
         let channel = Channel::from_shared(scheduler_uri)?
             .tls_config(self.tls.inner.clone())?
             .connect()
             .await?;
         let mut scheduler = SchedulerClient::new(channel);
+        if self.compression {
+            scheduler = scheduler.accept_compressed(CompressionEncoding::Gzip);
+        }
 
         let node_info = scheduler
             .schedule(ScheduleRequest {
@@ -138,6 +261,7 @@ impl Node {
             .map(|v| v.into_inner())?;
 
         debug!("Node scheduled at {}", node_info.grpc_uri);
+        self.startup.scheduled(timer);
 
         self.connect(node_info.grpc_uri).await
     }
@@ -151,9 +275,31 @@ impl Node {
     }
 }
 
+fn endpoint(uri: String) -> Result<Endpoint> {
+    Ok(Endpoint::from_shared(uri)?
+        .tcp_keepalive(Some(crate::TCP_KEEPALIVE))
+        .http2_keep_alive_interval(crate::TCP_KEEPALIVE)
+        .keep_alive_timeout(crate::TCP_KEEPALIVE_TIMEOUT)
+        .keep_alive_while_idle(true))
+}
+
+mod error;
 mod generic;
+mod lazy;
+#[cfg(not(cln_trimmed))]
+mod rebalance;
 mod service;
+#[cfg(not(cln_trimmed))]
+mod sweep;
+pub use error::NodeError;
 pub use generic::GenericClient;
+pub use lazy::LazyList;
+pub(crate) use service::AuthService;
+pub(crate) use stasher::StashBody;
+#[cfg(not(cln_trimmed))]
+pub use rebalance::RebalanceResult;
+#[cfg(not(cln_trimmed))]
+pub use sweep::SweepResult;
 
 mod stasher {
     use bytes::Bytes;
@@ -0,0 +1,398 @@
+//! Move liquidity between two of the node's channels with a circular
+//! payment to ourselves.
+use super::{ClnClient, Node};
+use crate::amount::Msat;
+use crate::events::{Event, EventBus};
+use crate::pb::cln::{
+    amount_or_any, delinvoice_request::DelinvoiceStatus, Amount, AmountOrAny, DelinvoiceRequest,
+    GetinfoRequest, GetrouteRequest, GetrouteRoute, InvoiceRequest, ListchannelsRequest,
+    ListpeerchannelsRequest, SendpayRequest, SendpayRoute, WaitsendpayRequest,
+};
+use crate::ratelimit::StatusExt;
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+
+/// The CLTV delta of the invoice we pay to ourselves.
+const FINAL_CLTV: u32 = 18;
+
+/// Number of routes tried before giving up.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// How long to wait for an attempt to complete, in seconds.
+const ATTEMPT_TIMEOUT: u32 = 60;
+
+/// The outcome of [`Node::rebalance`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RebalanceResult {
+    pub payment_hash: Vec<u8>,
+    pub amount_msat: u64,
+    /// The fees paid to the nodes along the route.
+    pub fee_msat: u64,
+    pub attempts: u32,
+}
+
+/// The forwarding policy of a node for one direction of a channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Policy {
+    base_msat: u64,
+    ppm: u32,
+    cltv_delta: u32,
+}
+
+impl Policy {
+    fn fee(&self, amount_msat: u64) -> u64 {
+        self.base_msat + amount_msat * self.ppm as u64 / 1_000_000
+    }
+}
+
+/// One of our channels, with the peer at the other end.
+struct Endpoint {
+    channel: String,
+    peer_id: Vec<u8>,
+}
+
+impl Node {
+    /// Send `amount_msat` out over `from_channel`, and back to us over
+    /// `to_channel`, paying at most `max_fee_msat` in routing fees.
+    ///
+    /// Channels are identified by their short channel id. Routes that
+    /// exceed the budget or fail are excluded, and another route is
+    /// tried, up to a limit. If `events` is given, each attempt and
+    /// the completion are published on it. The invoice paid to
+    /// ourselves is deleted again if the rebalance fails.
+    pub async fn rebalance(
+        &self,
+        from_channel: &str,
+        to_channel: &str,
+        amount_msat: u64,
+        max_fee_msat: u64,
+        events: Option<&EventBus>,
+    ) -> Result<RebalanceResult> {
+        let mut node: ClnClient = self.clone().schedule().await?;
+
+        let us = node
+            .getinfo(GetinfoRequest::default())
+            .await
+            .or_rate_limited()?
+            .into_inner()
+            .id;
+        let channels = node
+            .list_peer_channels(ListpeerchannelsRequest::default())
+            .await
+            .or_rate_limited()?
+            .into_inner()
+            .channels;
+        let find = |scid: &str| {
+            channels
+                .iter()
+                .find(|c| c.short_channel_id.as_deref() == Some(scid))
+                .ok_or_else(|| anyhow!("unknown channel {}", scid))
+        };
+
+        let from = find(from_channel)?;
+        let to = find(to_channel)?;
+        if Msat::from(&from.spendable_msat).to_msat() < amount_msat {
+            return Err(anyhow!("{} can not send {}msat", from_channel, amount_msat));
+        }
+        if Msat::from(&to.receivable_msat).to_msat() < amount_msat {
+            return Err(anyhow!(
+                "{} can not receive {}msat",
+                to_channel,
+                amount_msat
+            ));
+        }
+        let from = Endpoint {
+            channel: from_channel.to_string(),
+            peer_id: from.peer_id.clone().unwrap_or_default(),
+        };
+        let to = Endpoint {
+            channel: to_channel.to_string(),
+            peer_id: to.peer_id.clone().unwrap_or_default(),
+        };
+
+        let label = format!("rebalance-{:016x}", rand::random::<u64>());
+        let invoice = node
+            .invoice(InvoiceRequest {
+                amount_msat: Some(AmountOrAny {
+                    value: Some(amount_or_any::Value::Amount(Amount { msat: amount_msat })),
+                }),
+                description: format!("Rebalance from {} to {}", from_channel, to_channel),
+                label: label.clone(),
+                cltv: Some(FINAL_CLTV),
+                ..Default::default()
+            })
+            .await
+            .or_rate_limited()?
+            .into_inner();
+
+        let res: Result<RebalanceResult> = async {
+            let to_policy = policy(&mut node, &to.channel, &to.peer_id).await?;
+            // Do not route back over the channels we are rebalancing.
+            let mut exclude: Vec<String> = [&from.channel, &to.channel]
+                .iter()
+                .flat_map(|c| vec![format!("{}/0", c), format!("{}/1", c)])
+                .collect();
+
+            for attempt in 1..=MAX_ATTEMPTS {
+                let middle = if from.peer_id == to.peer_id {
+                    vec![]
+                } else {
+                    node.get_route(GetrouteRequest {
+                        id: to.peer_id.clone(),
+                        amount_msat: Some(Amount {
+                            msat: amount_msat + to_policy.fee(amount_msat),
+                        }),
+                        riskfactor: 10,
+                        cltv: Some(FINAL_CLTV + to_policy.cltv_delta),
+                        fromid: Some(from.peer_id.clone()),
+                        exclude: exclude.clone(),
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|e| anyhow!("no route between the channels: {}", e))?
+                    .into_inner()
+                    .route
+                };
+                let from_policy = match middle.first() {
+                    Some(h) => policy(&mut node, &h.channel, &from.peer_id).await?,
+                    None => Policy::default(),
+                };
+
+                let route = circular_route(
+                    &us,
+                    amount_msat,
+                    &from,
+                    &to,
+                    &middle,
+                    from_policy,
+                    to_policy,
+                );
+                let fee_msat = route_amount(&route) - amount_msat;
+                let middle_channels = middle
+                    .iter()
+                    .map(|h| format!("{}/{}", h.channel, h.direction));
+
+                if fee_msat > max_fee_msat {
+                    debug!(
+                        "Route costs {}msat, more than the budget of {}msat",
+                        fee_msat, max_fee_msat
+                    );
+                    if middle.is_empty() {
+                        break;
+                    }
+                    exclude.extend(middle_channels);
+                    continue;
+                }
+
+                if let Some(events) = events {
+                    events.publish(Event::RebalanceAttempt {
+                        payment_hash: invoice.payment_hash.clone(),
+                        attempt,
+                        fee_msat,
+                    });
+                }
+                node.send_pay(SendpayRequest {
+                    route,
+                    payment_hash: invoice.payment_hash.clone(),
+                    amount_msat: Some(Amount { msat: amount_msat }),
+                    payment_secret: Some(invoice.payment_secret.clone()),
+                    ..Default::default()
+                })
+                .await
+                .or_rate_limited()?;
+
+                match node
+                    .wait_send_pay(WaitsendpayRequest {
+                        payment_hash: invoice.payment_hash.clone(),
+                        timeout: Some(ATTEMPT_TIMEOUT),
+                        ..Default::default()
+                    })
+                    .await
+                {
+                    Ok(_) => {
+                        info!(
+                            "Rebalanced {}msat from {} to {} for {}msat",
+                            amount_msat, from_channel, to_channel, fee_msat
+                        );
+                        if let Some(events) = events {
+                            events.publish(Event::RebalanceCompleted {
+                                payment_hash: invoice.payment_hash.clone(),
+                                fee_msat,
+                            });
+                        }
+                        return Ok(RebalanceResult {
+                            payment_hash: invoice.payment_hash,
+                            amount_msat,
+                            fee_msat,
+                            attempts: attempt,
+                        });
+                    }
+                    Err(e) => {
+                        debug!("Rebalance attempt {} failed: {}", attempt, e);
+                        if middle.is_empty() {
+                            break;
+                        }
+                        exclude.extend(middle_channels);
+                    }
+                }
+            }
+
+            Err(anyhow!(
+                "could not find a route from {} to {} within the budget of {}msat",
+                from_channel,
+                to_channel,
+                max_fee_msat
+            ))
+        }
+        .await;
+
+        if res.is_err() {
+            // Do not leave the invoice around to be paid later.
+            let req = DelinvoiceRequest {
+                label: label.clone(),
+                status: DelinvoiceStatus::Unpaid as i32,
+                desconly: None,
+            };
+            if let Err(e) = node.del_invoice(req).await {
+                warn!("Could not delete rebalance invoice {}: {}", label, e);
+            }
+        }
+        res
+    }
+}
+
+/// Look up the policy `node_id` announced for `channel`.
+async fn policy(node: &mut ClnClient, channel: &str, node_id: &[u8]) -> Result<Policy> {
+    node.list_channels(ListchannelsRequest {
+        short_channel_id: Some(channel.to_string()),
+        source: None,
+        destination: None,
+    })
+    .await
+    .or_rate_limited()?
+    .into_inner()
+    .channels
+    .into_iter()
+    .find(|h| h.source == node_id)
+    .map(|h| Policy {
+        base_msat: h.base_fee_millisatoshi as u64,
+        ppm: h.fee_per_millionth,
+        cltv_delta: h.delay,
+    })
+    .ok_or_else(|| anyhow!("no channel update for {} yet", channel))
+}
+
+/// Extend the route `middle` from the peer of `from` to the peer of
+/// `to` into a route from us back to us. `from_policy` is the policy
+/// of the peer of `from` for the first hop of `middle`, and
+/// `to_policy` the one of the peer of `to` for the channel to us.
+fn circular_route(
+    us: &[u8],
+    amount_msat: u64,
+    from: &Endpoint,
+    to: &Endpoint,
+    middle: &[GetrouteRoute],
+    from_policy: Policy,
+    to_policy: Policy,
+) -> Vec<SendpayRoute> {
+    let hop = |id: &[u8], channel: &str, amount_msat: u64, delay: u32| SendpayRoute {
+        amount_msat: Some(Amount { msat: amount_msat }),
+        id: id.to_vec(),
+        delay,
+        channel: channel.to_string(),
+    };
+
+    // Both channels with the same peer leave no hops in between, and
+    // the peer only charges for forwarding to us.
+    let (first_msat, first_delay) = match middle.first() {
+        Some(h) => {
+            let next_msat = Msat::from(&h.amount_msat).to_msat();
+            (
+                next_msat + from_policy.fee(next_msat),
+                h.delay + from_policy.cltv_delta,
+            )
+        }
+        None => (
+            amount_msat + to_policy.fee(amount_msat),
+            FINAL_CLTV + to_policy.cltv_delta,
+        ),
+    };
+
+    let mut route = vec![hop(&from.peer_id, &from.channel, first_msat, first_delay)];
+    route.extend(middle.iter().map(|h| {
+        hop(
+            &h.id,
+            &h.channel,
+            Msat::from(&h.amount_msat).to_msat(),
+            h.delay,
+        )
+    }));
+    route.push(hop(us, &to.channel, amount_msat, FINAL_CLTV));
+    route
+}
+
+/// The amount we send out on the first hop of `route`.
+fn route_amount(route: &[SendpayRoute]) -> u64 {
+    route
+        .first()
+        .map(|h| Msat::from(&h.amount_msat).to_msat())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(channel: &str, peer: u8) -> Endpoint {
+        Endpoint {
+            channel: channel.to_string(),
+            peer_id: vec![peer; 33],
+        }
+    }
+
+    #[test]
+    fn test_circular_route() {
+        let from = endpoint("1x1x1", 1);
+        let to = endpoint("2x2x2", 2);
+        let policy = Policy {
+            base_msat: 1000,
+            ppm: 100,
+            cltv_delta: 6,
+        };
+
+        // The peer of `to` charges 1000 + 100 msat, and `middle`
+        // delivers that to it, including the fees of the node in
+        // between.
+        let middle = vec![
+            GetrouteRoute {
+                id: vec![3; 33],
+                channel: "3x3x3".to_string(),
+                amount_msat: Some(Amount { msat: 1_002_201 }),
+                delay: 30,
+                ..Default::default()
+            },
+            GetrouteRoute {
+                id: to.peer_id.clone(),
+                channel: "4x4x4".to_string(),
+                amount_msat: Some(Amount { msat: 1_001_100 }),
+                delay: 24,
+                ..Default::default()
+            },
+        ];
+        let route = circular_route(&[0; 33], 1_000_000, &from, &to, &middle, policy, policy);
+        assert_eq!(route.len(), 4);
+        assert_eq!(route[0].id, from.peer_id);
+        assert_eq!(route[0].channel, from.channel);
+        assert_eq!(route[0].delay, 36);
+        assert_eq!(route_amount(&route), 1_003_301);
+        assert_eq!(route[3].channel, to.channel);
+        assert_eq!(route[3].amount_msat, Some(Amount { msat: 1_000_000 }));
+        assert_eq!(route[3].delay, FINAL_CLTV);
+
+        // Both channels with the same peer.
+        let route = circular_route(&[0; 33], 1_000_000, &from, &from, &[], policy, policy);
+        assert_eq!(route.len(), 2);
+        assert_eq!(route_amount(&route), 1_001_100);
+        assert_eq!(route[0].delay, FINAL_CLTV + 6);
+    }
+}
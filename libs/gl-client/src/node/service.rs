@@ -1,3 +1,8 @@
+use crate::interceptor::Interceptors;
+use crate::metrics::Startup;
+use crate::record::{self, Recorder, Recording};
+use crate::secret::SecretBytes;
+use crate::shutdown::{Shutdown, ShutdownBody, ShuttingDown};
 use anyhow::{anyhow, Result};
 use http::{Request, Response};
 use log::{debug, trace};
@@ -17,15 +22,20 @@ use ring::{
 };
 
 pub struct AuthLayer {
-    key: Vec<u8>,
+    key: SecretBytes,
     rune: String,
+    shutdown: Shutdown,
+    interceptors: Interceptors,
+    compression: bool,
+    recorder: Option<Recorder>,
+    startup: Startup,
 }
 
 impl AuthLayer {
-    pub fn new(pem: Vec<u8>, rune: String) -> Result<Self> {
+    pub fn new(pem: SecretBytes, rune: String) -> Result<Self> {
         // Try to convert the key into a keypair to make sure it works later
         // when we need it.
-        let key = {
+        let key: SecretBytes = {
             let mut key = std::io::Cursor::new(&pem[..]);
             match pemfile::pkcs8_private_keys(&mut key) {
                 Ok(v) => v,
@@ -37,6 +47,7 @@ impl AuthLayer {
                 }
             }
             .remove(0)
+            .into()
         };
 
         match EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, key.as_ref()) {
@@ -44,7 +55,41 @@ impl AuthLayer {
             Err(e) => return Err(anyhow!("Could not decide keypair from PEM string: {}", e)),
         };
 
-        Ok(AuthLayer { key, rune })
+        Ok(AuthLayer {
+            key,
+            rune,
+            shutdown: Shutdown::new(),
+            interceptors: Interceptors::default(),
+            compression: false,
+            recorder: None,
+            startup: Startup::new(),
+        })
+    }
+
+    /// Track the calls through the service on `shutdown`.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub(crate) fn with_interceptors(mut self, interceptors: Interceptors) -> Self {
+        self.interceptors = interceptors;
+        self
+    }
+
+    pub(crate) fn with_compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub(crate) fn with_recorder(mut self, recorder: Option<Recorder>) -> Self {
+        self.recorder = recorder;
+        self
+    }
+
+    pub(crate) fn with_startup(mut self, startup: Startup) -> Self {
+        self.startup = startup;
+        self
     }
 }
 
@@ -56,6 +101,11 @@ impl Layer<Channel> for AuthLayer {
             key: self.key.clone(),
             inner,
             rune: self.rune.clone(),
+            shutdown: self.shutdown.clone(),
+            interceptors: self.interceptors.clone(),
+            compression: self.compression,
+            recorder: self.recorder.clone(),
+            startup: self.startup.clone(),
         }
     }
 }
@@ -63,12 +113,25 @@ impl Layer<Channel> for AuthLayer {
 #[derive(Clone)]
 pub struct AuthService {
     // PKCS#8 formatted private key
-    key: Vec<u8>,
+    key: SecretBytes,
     inner: Channel,
     rune: String,
+    shutdown: Shutdown,
+    interceptors: Interceptors,
+    compression: bool,
+    recorder: Option<Recorder>,
+    startup: Startup,
+}
+
+impl AuthService {
+    /// Whether clients on this service accept compressed responses.
+    pub(crate) fn compression(&self) -> bool {
+        self.compression
+    }
 }
+
 impl Service<Request<BoxBody>> for AuthService {
-    type Response = Response<Body>;
+    type Response = Response<ShutdownBody<Recording<Body>>>;
     type Error = Box<dyn std::error::Error + Send + Sync>;
     #[allow(clippy::type_complexity)]
     type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
@@ -93,8 +156,16 @@ impl Service<Request<BoxBody>> for AuthService {
         .unwrap();
 
         let rune = self.rune.clone();
+        let shutdown = self.shutdown.clone();
+        let interceptors = self.interceptors.clone();
+        let recorder = self.recorder.clone();
+        let startup = self.startup.clone();
+        // Task-locals are only visible here, not in the future.
+        let deadline = crate::deadline::current();
 
         Box::pin(async move {
+            let _call = shutdown.call()?;
+
             use bytes::BufMut;
             use std::convert::TryInto;
             use tonic::codegen::Body;
@@ -107,7 +178,10 @@ impl Service<Request<BoxBody>> for AuthService {
 
             let (mut parts, mut body) = request.into_parts();
 
-            let data = body.data().await.unwrap().unwrap();
+            let mut data = body.data().await.unwrap().unwrap();
+            let path = parts.uri.path().to_string();
+            interceptors.request(&path, &mut parts.headers, &mut data)?;
+            crate::deadline::stamp(deadline, &mut parts.headers)?;
 
             // Copy used to create the signature (payload + associated data)
             let mut buf = data.to_vec();
@@ -148,11 +222,21 @@ impl Service<Request<BoxBody>> for AuthService {
 
             trace!("Payload size: {} (timestamp {})", data.len(), time);
 
+            let payload = data.clone();
             let body = crate::node::stasher::StashBody::new(data).into();
             let request = Request::from_parts(parts, body);
             debug!("Sending request {:?}", request);
-            let response = inner.call(request).await?;
-            Ok(response)
+            let started = std::time::Instant::now();
+            let response: Result<_, Self::Error> = tokio::select! {
+                res = inner.call(request) => res.map_err(Into::into),
+                _ = shutdown.cancelled() => return Err(ShuttingDown.into()),
+                status = crate::deadline::expired(deadline) => Err(status.into()),
+            };
+            interceptors.response(&path, response.as_ref().ok().map(|r| r.headers()), started);
+            let response = response?;
+            startup.rpc_completed();
+            let response = record::record(recorder.as_ref(), &path, &payload, response);
+            Ok(response.map(|body| shutdown.body(body)))
         })
     }
 }
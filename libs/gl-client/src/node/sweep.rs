@@ -0,0 +1,391 @@
+//! Empty the onchain wallet of a node into an address or descriptor
+//! controlled by the user.
+use super::{ClnClient, Node};
+use crate::amount::Msat;
+use crate::balance;
+use crate::bitcoin::consensus::encode::deserialize;
+use crate::bitcoin::secp256k1::Secp256k1;
+use crate::bitcoin::util::bip32::{ChildNumber, DerivationPath, ExtendedPubKey};
+use crate::bitcoin::{Address, PublicKey, Transaction};
+use crate::pb::cln::{
+    amount_or_all, feerate, listfunds_outputs::ListfundsOutputsStatus, AmountOrAll, Feerate,
+    ListfundsOutputs, ListfundsRequest, ListpeerchannelsRequest, ListpeersRequest, Outpoint,
+    WithdrawRequest,
+};
+use crate::ratelimit::StatusExt;
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+use std::str::FromStr;
+
+/// Outputs below this value are not relayed.
+const DUST_LIMIT_SAT: u64 = 546;
+
+/// The weight of a P2WPKH input, used to skip outputs that cost more
+/// to spend than they are worth.
+const P2WPKH_INPUT_WEIGHT: u64 = 272;
+
+/// Maximum number of inputs per sweep transaction, to stay well
+/// below the standardness limit on transaction weight.
+const MAX_INPUTS_PER_TX: usize = 200;
+
+/// The outcome of [`Node::sweep_all`].
+#[derive(Clone, Debug, Default)]
+pub struct SweepResult {
+    /// The transactions that were broadcast.
+    pub txids: Vec<Vec<u8>>,
+    /// The amount sent to the destination, after fees.
+    pub swept_msat: u64,
+    /// The fees paid by the sweep transactions.
+    pub fees_msat: u64,
+    /// Outputs not swept because they cost more in fees than they
+    /// are worth.
+    pub skipped_dust_msat: u64,
+    /// Outputs kept in the wallet to cover the emergency reserve for
+    /// anchor channels.
+    pub reserved_msat: u64,
+}
+
+/// The outputs to sweep, split into transactions.
+#[derive(Debug, Default)]
+struct SweepPlan<'a> {
+    batches: Vec<Vec<&'a ListfundsOutputs>>,
+    skipped_dust_msat: u64,
+    reserved_msat: u64,
+}
+
+impl Node {
+    /// Send all confirmed onchain funds to `destination`, which is
+    /// either an address, or a single-key descriptor (`addr(...)`,
+    /// `wpkh(...)` or `sh(wpkh(...))`), in which case the funds go to
+    /// the first address derived from it.
+    ///
+    /// Outputs that would cost more to spend than they are worth are
+    /// left behind, and large wallets are swept in several
+    /// transactions. If the node still has anchor channels, outputs
+    /// covering the emergency reserve are left in the wallet, so
+    /// `lightningd` does not keep a reserve from every transaction.
+    pub async fn sweep_all(&self, destination: &str, feerate: Feerate) -> Result<SweepResult> {
+        let destination = destination_address(destination)?;
+        let mut node: ClnClient = self.clone().schedule().await?;
+
+        let funds = node
+            .list_funds(ListfundsRequest::default())
+            .await
+            .or_rate_limited()?
+            .into_inner();
+        let channels = node
+            .list_peer_channels(ListpeerchannelsRequest::default())
+            .await
+            .or_rate_limited()?
+            .into_inner();
+        let peers = node
+            .list_peers(ListpeersRequest::default())
+            .await
+            .or_rate_limited()?
+            .into_inner();
+
+        let anchors = balance::has_anchor_channels(&peers, &channels);
+        let summary = balance::BalanceSummary::from_responses(
+            &funds,
+            &channels,
+            anchors,
+            balance::DEFAULT_MIN_EMERGENCY_MSAT,
+        );
+        if summary.onchain_spendable_msat() == 0 {
+            return Err(anyhow!(
+                "No spendable onchain funds, {}msat are held as emergency reserve",
+                summary.reserves.emergency_msat
+            ));
+        }
+
+        let plan = plan(
+            &funds.outputs,
+            dust_threshold_msat(&feerate),
+            summary.reserves.emergency_msat,
+        )?;
+        let mut result = SweepResult {
+            skipped_dust_msat: plan.skipped_dust_msat,
+            reserved_msat: plan.reserved_msat,
+            ..Default::default()
+        };
+        for batch in plan.batches {
+            debug!("Sweeping {} outputs to {}", batch.len(), destination);
+            let res = node
+                .withdraw(WithdrawRequest {
+                    destination: destination.to_string(),
+                    satoshi: Some(AmountOrAll {
+                        value: Some(amount_or_all::Value::All(true)),
+                    }),
+                    feerate: Some(feerate.clone()),
+                    utxos: batch
+                        .iter()
+                        .map(|o| Outpoint {
+                            txid: o.txid.clone(),
+                            outnum: o.output,
+                        })
+                        .collect(),
+                    ..Default::default()
+                })
+                .await
+                .or_rate_limited()?
+                .into_inner();
+            info!("Broadcast sweep transaction {}", hex::encode(&res.txid));
+            let inputs_msat = batch
+                .iter()
+                .map(|o| Msat::from(&o.amount_msat).to_msat())
+                .sum();
+            let (swept_msat, fees_msat) = net_amounts(&res.tx, &destination, inputs_msat)?;
+            result.txids.push(res.txid);
+            result.swept_msat += swept_msat;
+            result.fees_msat += fees_msat;
+        }
+
+        Ok(result)
+    }
+}
+
+/// Split the confirmed outputs into batches of at most
+/// [`MAX_INPUTS_PER_TX`], leaving behind the dust, and enough outputs
+/// to cover `reserve_msat`: the smallest one that covers it on its
+/// own, or else the largest ones.
+fn plan(
+    outputs: &[ListfundsOutputs],
+    threshold_msat: u64,
+    reserve_msat: u64,
+) -> Result<SweepPlan<'_>> {
+    let amount = |o: &ListfundsOutputs| Msat::from(&o.amount_msat).to_msat();
+    let mut plan = SweepPlan::default();
+    let mut utxos: Vec<&ListfundsOutputs> = vec![];
+    for o in outputs.iter().filter(|o| {
+        !o.reserved
+            && ListfundsOutputsStatus::from_i32(o.status) == Some(ListfundsOutputsStatus::Confirmed)
+    }) {
+        if amount(o) <= threshold_msat {
+            plan.skipped_dust_msat += amount(o);
+        } else {
+            utxos.push(o);
+        }
+    }
+
+    utxos.sort_by_key(|o| amount(o));
+    if reserve_msat > 0 {
+        match utxos.iter().position(|o| amount(o) >= reserve_msat) {
+            Some(i) => plan.reserved_msat = amount(utxos.remove(i)),
+            None => {
+                while plan.reserved_msat < reserve_msat {
+                    match utxos.pop() {
+                        Some(o) => plan.reserved_msat += amount(o),
+                        None => break,
+                    }
+                }
+            }
+        }
+    }
+    if utxos.is_empty() {
+        return Err(anyhow!(
+            "Nothing to sweep, {}msat are dust and {}msat are held as emergency reserve",
+            plan.skipped_dust_msat,
+            plan.reserved_msat
+        ));
+    }
+
+    plan.batches = utxos
+        .chunks(MAX_INPUTS_PER_TX)
+        .map(|c| c.to_vec())
+        .collect();
+    Ok(plan)
+}
+
+/// The amount the raw transaction `tx` pays to `destination`, and the
+/// fee it pays for spending `inputs_msat`.
+fn net_amounts(tx: &[u8], destination: &Address, inputs_msat: u64) -> Result<(u64, u64)> {
+    let tx: Transaction = deserialize(tx)?;
+    let script = destination.script_pubkey();
+    let swept_sat: u64 = tx
+        .output
+        .iter()
+        .filter(|o| o.script_pubkey == script)
+        .map(|o| o.value)
+        .sum();
+    let outputs_sat: u64 = tx.output.iter().map(|o| o.value).sum();
+    Ok((
+        swept_sat * 1000,
+        inputs_msat.saturating_sub(outputs_sat * 1000),
+    ))
+}
+
+/// The value below which an output is not worth spending at
+/// `feerate`. Named feerates are resolved by the node, so we can only
+/// apply the dust limit for them.
+fn dust_threshold_msat(feerate: &Feerate) -> u64 {
+    let fee_sat = match feerate.style {
+        Some(feerate::Style::Perkw(r)) => r as u64 * P2WPKH_INPUT_WEIGHT / 1000,
+        Some(feerate::Style::Perkb(r)) => r as u64 * P2WPKH_INPUT_WEIGHT / 4 / 1000,
+        _ => 0,
+    };
+    fee_sat.max(DUST_LIMIT_SAT) * 1000
+}
+
+/// Resolve `destination` to an address, deriving the first address
+/// of a descriptor if needed.
+fn destination_address(destination: &str) -> Result<Address> {
+    // Strip the optional descriptor checksum.
+    let desc = destination.split('#').next().unwrap_or_default().trim();
+
+    if let Some(inner) = unwrap_fn(desc, "addr") {
+        return Ok(Address::from_str(inner)?);
+    }
+    if let Some(inner) = unwrap_fn(desc, "sh").and_then(|i| unwrap_fn(i, "wpkh")) {
+        let (pk, network) = derive_key(inner)?;
+        return Ok(Address::p2shwpkh(&pk, network)?);
+    }
+    if let Some(inner) = unwrap_fn(desc, "wpkh") {
+        let (pk, network) = derive_key(inner)?;
+        return Ok(Address::p2wpkh(&pk, network)?);
+    }
+    if desc.contains('(') {
+        return Err(anyhow!("Unsupported descriptor {}", desc));
+    }
+    Ok(Address::from_str(desc)?)
+}
+
+fn unwrap_fn<'a>(s: &'a str, name: &str) -> Option<&'a str> {
+    s.strip_prefix(name)?.strip_prefix('(')?.strip_suffix(')')
+}
+
+/// Derive the first key from a descriptor key expression such as
+/// `[d34db33f/84h/0h/0h]xpub.../0/*`.
+fn derive_key(key: &str) -> Result<(PublicKey, crate::bitcoin::Network)> {
+    // Drop the key origin, it is informational only.
+    let key = match key.find(']') {
+        Some(i) => &key[i + 1..],
+        None => key,
+    };
+
+    let mut parts = key.split('/');
+    let xpub = ExtendedPubKey::from_str(parts.next().unwrap_or_default())?;
+    let path = parts
+        .map(|p| match p {
+            "*" => Ok(ChildNumber::Normal { index: 0 }),
+            p if p.ends_with('h') || p.ends_with('\'') => {
+                Err(anyhow!("Cannot derive hardened step {} from an xpub", p))
+            }
+            p => Ok(ChildNumber::from_normal_idx(p.parse()?)?),
+        })
+        .collect::<Result<Vec<ChildNumber>>>()?;
+
+    let derived = xpub.derive_pub(&Secp256k1::verification_only(), &DerivationPath::from(path))?;
+    Ok((PublicKey::new(derived.public_key), xpub.network))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::{PackedLockTime, TxOut};
+    use crate::pb::cln::Amount;
+
+    fn output(sat: u64) -> ListfundsOutputs {
+        ListfundsOutputs {
+            amount_msat: Some(Amount { msat: sat * 1000 }),
+            status: ListfundsOutputsStatus::Confirmed as i32,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_destination_address() {
+        let addr = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+        assert_eq!(destination_address(addr).unwrap().to_string(), addr);
+        assert_eq!(
+            destination_address(&format!("addr({})#abcdefgh", addr))
+                .unwrap()
+                .to_string(),
+            addr
+        );
+
+        // BIP84 test vector, first receive address.
+        let xpub = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+        assert!(destination_address(&format!("wpkh({}/0/*)", xpub)).is_err());
+        let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+        assert_eq!(
+            destination_address(&format!("wpkh([73c5da0a/84h/0h/0h]{}/0/*)", xpub))
+                .unwrap()
+                .to_string(),
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+        assert!(destination_address(&format!("wpkh({}/0h/*)", xpub)).is_err());
+        assert!(destination_address(&format!("tr({}/0/*)", xpub)).is_err());
+    }
+
+    #[test]
+    fn test_dust_threshold() {
+        assert_eq!(
+            dust_threshold_msat(&Feerate {
+                style: Some(feerate::Style::Normal(true))
+            }),
+            546_000
+        );
+        assert_eq!(
+            dust_threshold_msat(&Feerate {
+                style: Some(feerate::Style::Perkw(10_000))
+            }),
+            2_720_000
+        );
+    }
+
+    #[test]
+    fn test_plan_batches() {
+        let mut outputs: Vec<_> = (0..450).map(|_| output(10_000)).collect();
+        outputs.push(output(100));
+        outputs.push(ListfundsOutputs {
+            status: ListfundsOutputsStatus::Unconfirmed as i32,
+            ..output(50_000)
+        });
+
+        // No single output covers the reserve, so the largest ones are
+        // kept, once for all batches.
+        let p = plan(&outputs, 546_000, 25_000_000).unwrap();
+        assert_eq!(p.reserved_msat, 30_000_000);
+        assert_eq!(p.skipped_dust_msat, 100_000);
+        let sizes: Vec<_> = p.batches.iter().map(|b| b.len()).collect();
+        assert_eq!(sizes, vec![200, 200, 47]);
+
+        // Otherwise the smallest one that does.
+        outputs.push(output(40_000));
+        outputs.push(output(30_000));
+        let p = plan(&outputs, 546_000, 25_000_000).unwrap();
+        assert_eq!(p.reserved_msat, 30_000_000);
+        assert_eq!(p.batches.iter().map(|b| b.len()).sum::<usize>(), 451);
+
+        let p = plan(&outputs, 546_000, 0).unwrap();
+        assert_eq!(p.reserved_msat, 0);
+        assert_eq!(p.batches.iter().map(|b| b.len()).sum::<usize>(), 452);
+
+        assert!(plan(&[output(10_000)], 546_000, 25_000_000).is_err());
+    }
+
+    #[test]
+    fn test_net_amounts() {
+        let destination = Address::from_str("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").unwrap();
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: 90_000,
+                    script_pubkey: destination.script_pubkey(),
+                },
+                TxOut {
+                    value: 5_000,
+                    script_pubkey: Default::default(),
+                },
+            ],
+        };
+        let tx = crate::bitcoin::consensus::encode::serialize(&tx);
+        assert_eq!(
+            net_amounts(&tx, &destination, 100_000_000).unwrap(),
+            (90_000_000, 5_000_000)
+        );
+    }
+}
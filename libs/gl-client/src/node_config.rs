@@ -0,0 +1,529 @@
+//! Converge the settings of a node to a desired configuration.
+//!
+//! Operators of many nodes want to declare how the nodes should be
+//! configured, rather than issue the commands to get there. A
+//! [`DesiredConfig`] lists the settings to enforce, [`diff`] compares
+//! them to the [`NodeSettings`] read from the node, and [`apply`]
+//! makes the changes that can be made at runtime:
+//!
+//!  - Channel fees are updated with `setchannel`, for each channel
+//!    whose fees differ. The `fee-base` and `fee-per-satoshi` options
+//!    only apply to new channels.
+//!  - The `autoclean-cycle` and `autoclean-expiredinvoices-age`
+//!    options are updated with `autocleaninvoice`.
+//!  - Options the node lists as dynamic are updated with
+//!    `setconfig`.
+//!
+//! Other differences are reported as [`Action::Unsupported`], for the
+//! operator to resolve. [`set_option`] changes a single
+//! [`DynamicOption`] directly.
+use crate::channels::ChannelState;
+use crate::fee_manager::{current_fees, Fees};
+use crate::node::{Client, ClnClient};
+use crate::pb::cln::{
+    Amount, AutocleaninvoiceRequest, ListpeerchannelsChannels, ListpeerchannelsRequest,
+    SetchannelRequest,
+};
+use crate::pb::{ConfigOption, ListConfigsRequest, SetConfigRequest};
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+const FEE_BASE: &str = "fee-base";
+const FEE_PPM: &str = "fee-per-satoshi";
+const AUTOCLEAN_CYCLE: &str = "autoclean-cycle";
+const AUTOCLEAN_EXPIRED_INVOICES: &str = "autoclean-expiredinvoices-age";
+
+/// The settings to enforce on a node, `None` to leave a setting
+/// alone. Can be read from TOML:
+///
+/// ```toml
+/// fee_base_msat = 1000
+/// fee_ppm = 100
+/// autoclean_expiredinvoices_age_secs = 86400
+///
+/// [options]
+/// alias = "my node"
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DesiredConfig {
+    /// The base fee of all channels.
+    pub fee_base_msat: Option<u64>,
+    /// The proportional fee of all channels, in parts per million.
+    pub fee_ppm: Option<u32>,
+    pub autoclean_cycle_secs: Option<u64>,
+    pub autoclean_expiredinvoices_age_secs: Option<u64>,
+    /// Other `lightningd` options, compared to their value as listed
+    /// by `listconfigs`.
+    pub options: BTreeMap<String, String>,
+}
+
+impl DesiredConfig {
+    pub fn from_toml(s: &str) -> Result<Self> {
+        Ok(toml::from_str(s)?)
+    }
+}
+
+/// The fees of a channel that can be changed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ChannelSettings {
+    /// The short channel id, or the hex encoded channel id if the
+    /// channel is not confirmed yet.
+    pub id: String,
+    pub fees: Fees,
+}
+
+/// An option `lightningd` can change at runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "option", content = "value", rename_all = "kebab-case")]
+pub enum DynamicOption {
+    /// The base fee of new channels, in msat.
+    FeeBase(u64),
+    /// The proportional fee of new channels, in parts per million.
+    FeePerSatoshi(u32),
+    /// The smallest HTLC new channels accept.
+    HtlcMinimumMsat(u64),
+    /// The largest HTLC new channels accept.
+    HtlcMaximumMsat(u64),
+    /// The smallest channel peers may open to the node.
+    MinCapacitySat(u64),
+}
+
+impl DynamicOption {
+    /// The name of the `lightningd` option.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DynamicOption::FeeBase(_) => FEE_BASE,
+            DynamicOption::FeePerSatoshi(_) => FEE_PPM,
+            DynamicOption::HtlcMinimumMsat(_) => "htlc-minimum-msat",
+            DynamicOption::HtlcMaximumMsat(_) => "htlc-maximum-msat",
+            DynamicOption::MinCapacitySat(_) => "min-capacity-sat",
+        }
+    }
+
+    pub fn value(&self) -> String {
+        match self {
+            DynamicOption::FeeBase(v)
+            | DynamicOption::HtlcMinimumMsat(v)
+            | DynamicOption::HtlcMaximumMsat(v)
+            | DynamicOption::MinCapacitySat(v) => v.to_string(),
+            DynamicOption::FeePerSatoshi(v) => v.to_string(),
+        }
+    }
+}
+
+/// Change `option` at runtime, and return it as the node now has it.
+pub async fn set_option(node: &mut Client, option: DynamicOption) -> Result<ConfigOption> {
+    set_config(node, option.name(), option.value()).await
+}
+
+async fn set_config(node: &mut Client, name: &str, value: String) -> Result<ConfigOption> {
+    debug!("Setting {} to {}", name, value);
+    node.set_config(SetConfigRequest {
+        config: name.to_string(),
+        val: Some(value),
+    })
+    .await?
+    .into_inner()
+    .config
+    .ok_or_else(|| anyhow!("setconfig did not return the option"))
+}
+
+/// What the node is currently configured with.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct NodeSettings {
+    /// The values of each option, as returned by `listconfigs`.
+    pub options: BTreeMap<String, Vec<String>>,
+    /// The options that can be changed at runtime.
+    pub dynamic: BTreeSet<String>,
+    pub channels: Vec<ChannelSettings>,
+}
+
+impl NodeSettings {
+    /// Read the options and the fees of the open channels from the
+    /// node.
+    pub async fn fetch(node: &mut Client, cln: &mut ClnClient) -> Result<Self> {
+        let configs = node
+            .list_configs(ListConfigsRequest::default())
+            .await?
+            .into_inner()
+            .configs;
+        let dynamic = configs
+            .iter()
+            .filter(|o| o.dynamic)
+            .map(|o| o.name.clone())
+            .collect();
+        let options = configs.into_iter().map(|o| (o.name, o.values)).collect();
+        let channels = cln
+            .list_peer_channels(ListpeerchannelsRequest::default())
+            .await
+            .map_err(|e| anyhow!(e))?
+            .into_inner()
+            .channels
+            .iter()
+            .filter_map(channel_settings)
+            .collect();
+        Ok(NodeSettings {
+            options,
+            dynamic,
+            channels,
+        })
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .get(name)
+            .and_then(|v| v.first())
+            .map(|v| v.as_str())
+    }
+}
+
+fn channel_settings(c: &ListpeerchannelsChannels) -> Option<ChannelSettings> {
+    let state = ChannelState::of(c)?;
+    if !matches!(
+        state,
+        ChannelState::ChanneldNormal
+            | ChannelState::ChanneldAwaitingSplice
+            | ChannelState::ChanneldAwaitingLockin
+            | ChannelState::DualopendAwaitingLockin
+    ) {
+        return None;
+    }
+    let id = c
+        .short_channel_id
+        .clone()
+        .or_else(|| c.channel_id.as_ref().map(hex::encode))?;
+    Some(ChannelSettings {
+        id,
+        fees: current_fees(c),
+    })
+}
+
+/// How [`apply`] resolves a [`Change`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// `setchannel` on the channel.
+    SetChannel { id: String, fees: Fees },
+    /// `setconfig` with the desired value.
+    SetConfig,
+    /// `autocleaninvoice` with both values, since it resets the one
+    /// that is not given.
+    AutoClean {
+        cycle_secs: u64,
+        expired_by_secs: u64,
+    },
+    /// The setting can not be changed at runtime.
+    Unsupported,
+}
+
+/// A setting that differs from the desired config.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Change {
+    /// The `lightningd` option, or `channel <id>` for the fees of a
+    /// single channel.
+    pub setting: String,
+    /// `None` if the node does not know the option.
+    pub current: Option<String>,
+    pub desired: String,
+    pub action: Action,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ConfigDiff {
+    pub changes: Vec<Change>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// The changes [`apply`] can not make.
+    pub fn unsupported(&self) -> impl Iterator<Item = &Change> {
+        self.changes
+            .iter()
+            .filter(|c| c.action == Action::Unsupported)
+    }
+}
+
+/// Compare the `current` settings to the `desired` ones.
+pub fn diff(current: &NodeSettings, desired: &DesiredConfig) -> ConfigDiff {
+    let mut changes = vec![];
+    let mut option = |name: &str, value: String, action: Action| {
+        let action = match action {
+            Action::Unsupported if current.dynamic.contains(name) => Action::SetConfig,
+            action => action,
+        };
+        let current = current.option(name);
+        if current != Some(value.as_str()) {
+            changes.push(Change {
+                setting: name.to_string(),
+                current: current.map(|v| v.to_string()),
+                desired: value,
+                action,
+            });
+        }
+    };
+
+    if let Some(v) = desired.fee_base_msat {
+        option(FEE_BASE, v.to_string(), Action::Unsupported);
+    }
+    if let Some(v) = desired.fee_ppm {
+        option(FEE_PPM, v.to_string(), Action::Unsupported);
+    }
+
+    // Both autoclean values are set at once, keeping the current value
+    // of the one that is not part of the desired config.
+    let current_secs = |name| current.option(name).and_then(|v| v.parse().ok());
+    let autoclean = Action::AutoClean {
+        cycle_secs: desired
+            .autoclean_cycle_secs
+            .or_else(|| current_secs(AUTOCLEAN_CYCLE))
+            .unwrap_or(3600),
+        expired_by_secs: desired
+            .autoclean_expiredinvoices_age_secs
+            .or_else(|| current_secs(AUTOCLEAN_EXPIRED_INVOICES))
+            .unwrap_or(0),
+    };
+    if let Some(v) = desired.autoclean_cycle_secs {
+        option(AUTOCLEAN_CYCLE, v.to_string(), autoclean.clone());
+    }
+    if let Some(v) = desired.autoclean_expiredinvoices_age_secs {
+        option(AUTOCLEAN_EXPIRED_INVOICES, v.to_string(), autoclean);
+    }
+
+    for (name, value) in &desired.options {
+        option(name.as_str(), value.clone(), Action::Unsupported);
+    }
+
+    if desired.fee_base_msat.is_some() || desired.fee_ppm.is_some() {
+        for c in &current.channels {
+            let fees = Fees {
+                base_msat: desired.fee_base_msat.unwrap_or(c.fees.base_msat),
+                ppm: desired.fee_ppm.unwrap_or(c.fees.ppm),
+            };
+            if fees != c.fees {
+                changes.push(Change {
+                    setting: format!("channel {}", c.id),
+                    current: Some(format_fees(&c.fees)),
+                    desired: format_fees(&fees),
+                    action: Action::SetChannel {
+                        id: c.id.clone(),
+                        fees,
+                    },
+                });
+            }
+        }
+    }
+
+    ConfigDiff { changes }
+}
+
+fn format_fees(fees: &Fees) -> String {
+    format!("{}msat+{}ppm", fees.base_msat, fees.ppm)
+}
+
+/// Make the changes of `diff` that can be made at runtime, and return
+/// the ones that were made.
+pub async fn apply(
+    node: &mut Client,
+    cln: &mut ClnClient,
+    diff: &ConfigDiff,
+) -> Result<Vec<Change>> {
+    let mut applied = vec![];
+    let mut autocleaned = false;
+    for change in &diff.changes {
+        match &change.action {
+            Action::SetChannel { id, fees } => {
+                debug!("Updating fees of {} to {:?}", id, fees);
+                cln.set_channel(SetchannelRequest {
+                    id: id.clone(),
+                    feebase: Some(Amount {
+                        msat: fees.base_msat,
+                    }),
+                    feeppm: Some(fees.ppm),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|e| anyhow!(e))?;
+            }
+            Action::AutoClean {
+                cycle_secs,
+                expired_by_secs,
+            } => {
+                if !autocleaned {
+                    debug!(
+                        "Setting autoclean cycle to {}s and expired invoice age to {}s",
+                        cycle_secs, expired_by_secs
+                    );
+                    cln.auto_clean_invoice(AutocleaninvoiceRequest {
+                        cycle_seconds: Some(*cycle_secs),
+                        expired_by: Some(*expired_by_secs),
+                    })
+                    .await
+                    .map_err(|e| anyhow!(e))?;
+                    autocleaned = true;
+                }
+            }
+            Action::SetConfig => {
+                set_config(node, &change.setting, change.desired.clone()).await?;
+            }
+            Action::Unsupported => continue,
+        }
+        applied.push(change.clone());
+    }
+    Ok(applied)
+}
+
+/// Fetch the settings of the node, apply `desired`, and return what
+/// is left to change by other means.
+pub async fn converge(
+    node: &mut Client,
+    cln: &mut ClnClient,
+    desired: &DesiredConfig,
+) -> Result<ConfigDiff> {
+    let current = NodeSettings::fetch(node, cln).await?;
+    let diff = diff(&current, desired);
+    apply(node, cln, &diff).await?;
+    Ok(ConfigDiff {
+        changes: diff.unsupported().cloned().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> NodeSettings {
+        let options = [
+            (FEE_BASE, "1000"),
+            (FEE_PPM, "10"),
+            (AUTOCLEAN_CYCLE, "3600"),
+            (AUTOCLEAN_EXPIRED_INVOICES, "0"),
+            ("alias", "node"),
+        ];
+        NodeSettings {
+            options: options
+                .iter()
+                .map(|(k, v)| (k.to_string(), vec![v.to_string()]))
+                .collect(),
+            dynamic: BTreeSet::new(),
+            channels: vec![
+                ChannelSettings {
+                    id: "1x1x1".to_string(),
+                    fees: Fees {
+                        base_msat: 1000,
+                        ppm: 10,
+                    },
+                },
+                ChannelSettings {
+                    id: "2x2x2".to_string(),
+                    fees: Fees {
+                        base_msat: 0,
+                        ppm: 10,
+                    },
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let desired = DesiredConfig::from_toml(
+            r#"
+            fee_base_msat = 1000
+            autoclean_expiredinvoices_age_secs = 86400
+
+            [options]
+            alias = "node"
+            "#,
+        )
+        .unwrap();
+        let diff = diff(&settings(), &desired);
+
+        // The defaults and the first channel already match.
+        assert_eq!(
+            diff.changes,
+            vec![
+                Change {
+                    setting: AUTOCLEAN_EXPIRED_INVOICES.to_string(),
+                    current: Some("0".to_string()),
+                    desired: "86400".to_string(),
+                    action: Action::AutoClean {
+                        cycle_secs: 3600,
+                        expired_by_secs: 86400
+                    },
+                },
+                Change {
+                    setting: "channel 2x2x2".to_string(),
+                    current: Some("0msat+10ppm".to_string()),
+                    desired: "1000msat+10ppm".to_string(),
+                    action: Action::SetChannel {
+                        id: "2x2x2".to_string(),
+                        fees: Fees {
+                            base_msat: 1000,
+                            ppm: 10
+                        },
+                    },
+                },
+            ]
+        );
+        assert_eq!(diff.unsupported().count(), 0);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut desired = DesiredConfig {
+            fee_ppm: Some(10),
+            ..Default::default()
+        };
+        desired
+            .options
+            .insert("alias".to_string(), "other".to_string());
+        desired
+            .options
+            .insert("unknown".to_string(), "1".to_string());
+        let diff = diff(&settings(), &desired);
+
+        let unsupported: Vec<_> = diff
+            .unsupported()
+            .map(|c| (c.setting.as_str(), c.current.as_deref()))
+            .collect();
+        assert_eq!(
+            unsupported,
+            vec![("alias", Some("node")), ("unknown", None)]
+        );
+        assert_eq!(diff.changes.len(), 2);
+    }
+
+    #[test]
+    fn test_dynamic() {
+        let mut current = settings();
+        current.dynamic.insert(FEE_PPM.to_string());
+        let desired = DesiredConfig {
+            fee_ppm: Some(20),
+            fee_base_msat: Some(0),
+            ..Default::default()
+        };
+        let diff = diff(&current, &desired);
+
+        let actions: Vec<_> = diff
+            .changes
+            .iter()
+            .filter(|c| !c.setting.starts_with("channel"))
+            .map(|c| (c.setting.as_str(), &c.action))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                (FEE_BASE, &Action::Unsupported),
+                (FEE_PPM, &Action::SetConfig)
+            ]
+        );
+        assert_eq!(DynamicOption::FeePerSatoshi(20).name(), FEE_PPM);
+        assert_eq!(DynamicOption::HtlcMinimumMsat(1).value(), "1");
+    }
+}
@@ -8,4 +8,12 @@ pub mod scheduler {
 
 }
 pub use greenlight::*;
+#[cfg(not(cln_trimmed))]
 pub use cln_grpc::pb as cln;
+
+/// Only the methods listed in `GL_CLN_METHODS` at build time, and the
+/// messages they use.
+#[cfg(cln_trimmed)]
+pub mod cln {
+    tonic::include_proto!("cln");
+}
@@ -165,6 +165,18 @@ impl State {
         self.values.clear();
         Ok(())
     }
+
+    /// An entry the signer stores for itself, rather than for VLS.
+    pub(crate) fn get_entry(&self, key: &str) -> Option<&serde_json::Value> {
+        self.values.get(key).map(|v| &v.1)
+    }
+
+    /// Insert or update an entry the signer stores for itself,
+    /// bumping its version so the node keeps the new value.
+    pub(crate) fn put_entry(&mut self, key: &str, value: serde_json::Value) {
+        let version = self.values.get(key).map_or(0, |v| v.0 + 1);
+        self.values.insert(key.to_string(), (version, value));
+    }
 }
 
 #[derive(Debug)]
@@ -273,15 +285,24 @@ impl Into<Vec<crate::pb::SignerStateEntry>> for State {
 
 impl From<Vec<crate::pb::SignerStateEntry>> for State {
     fn from(v: Vec<crate::pb::SignerStateEntry>) -> State {
-        use std::iter::FromIterator;
-        let values = BTreeMap::from_iter(v.iter().map(|v| {
-            (
-                v.key.to_owned(),
-                (v.version, serde_json::from_slice(&v.value).unwrap()),
-            )
-        }));
+        State::from_entries(&v).unwrap()
+    }
+}
 
-        State { values }
+impl State {
+    /// Decode the entries sent by the node, failing if a value is not
+    /// valid JSON.
+    pub(crate) fn from_entries(v: &[crate::pb::SignerStateEntry]) -> anyhow::Result<State> {
+        let values = v
+            .iter()
+            .map(|v| {
+                Ok((
+                    v.key.to_owned(),
+                    (v.version, serde_json::from_slice(&v.value)?),
+                ))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(State { values })
     }
 }
 
@@ -475,8 +496,10 @@ impl Persist for MemoryPersister {
         let state = self.state.lock().unwrap();
         let key = hex::encode(node_id.serialize());
         let key = format!("{ALLOWLIST_PREFIX}/{key}");
-        let allowlist: Vec<String> =
-            serde_json::from_value(state.values.get(&key).unwrap().1.clone()).unwrap();
+        let allowlist: Vec<String> = match state.values.get(&key) {
+            Some(v) => serde_json::from_value(v.1.clone()).unwrap(),
+            None => vec![],
+        };
 
         Ok(allowlist)
     }
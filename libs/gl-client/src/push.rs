@@ -0,0 +1,84 @@
+//! Decode the push notifications sent for an offline node.
+//!
+//! When a payment arrives for a node that is not running, the
+//! scheduler notifies the devices registered with
+//! [`Scheduler::register_push_token`]. The payload tells the app why
+//! it should start the node, and is signed with the secret returned
+//! on registration, so the app does not act on forged notifications.
+//!
+//! [`Scheduler::register_push_token`]: crate::scheduler::Scheduler::register_push_token
+use anyhow::{anyhow, Result};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PushEvent {
+    /// A payment is waiting for the node to come online.
+    IncomingPayment {
+        /// Hex-encoded.
+        payment_hash: String,
+        amount_msat: u64,
+    },
+    /// A wakeup registered with the scheduler is due.
+    Wakeup { reference: String },
+    /// An event this version of the library does not know about.
+    /// Apps should still start the node.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PushNotification {
+    /// Hex-encoded id of the node the notification is for.
+    pub node_id: String,
+    /// Seconds since the UNIX epoch.
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub event: PushEvent,
+}
+
+impl PushNotification {
+    /// Decode a notification payload without checking its signature.
+    pub fn decode(payload: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(payload)?)
+    }
+
+    /// Check that `signature`, the hex-encoded HMAC-SHA256 of the
+    /// payload, was made with `secret`, and decode the payload.
+    pub fn verify(payload: &[u8], signature: &str, secret: &str) -> Result<Self> {
+        let signature = hex::decode(signature)?;
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+        hmac::verify(&key, payload, &signature)
+            .map_err(|_| anyhow!("invalid push notification signature"))?;
+        Self::decode(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let payload = br#"{"node_id":"02aa","timestamp":1700000000,"event":"incoming_payment","payment_hash":"00ff","amount_msat":1000}"#;
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+        let signature = hex::encode(hmac::sign(&key, payload));
+
+        let n = PushNotification::verify(payload, &signature, "secret").unwrap();
+        assert_eq!(
+            n.event,
+            PushEvent::IncomingPayment {
+                payment_hash: "00ff".to_string(),
+                amount_msat: 1000,
+            }
+        );
+        assert!(PushNotification::verify(payload, &signature, "other").is_err());
+
+        let n = PushNotification::decode(
+            br#"{"node_id":"02aa","timestamp":1,"event":"channel_closed"}"#,
+        )
+        .unwrap();
+        assert_eq!(n.event, PushEvent::Unknown);
+    }
+}
@@ -0,0 +1,135 @@
+//! Render invoices, offers and LNURLs as QR codes.
+//!
+//! Bech32 strings are case insensitive, and encoded in uppercase they
+//! fit the QR alphanumeric mode, which takes about a third less space
+//! than the byte mode used for lowercase. [`payload`] uppercases them,
+//! including with a `lightning:` prefix, and leaves anything else,
+//! e.g., LNURLs given as URLs, as it is. The codes use error
+//! correction level M, and include the quiet zone around them.
+use qrcode::{Color, EcLevel, QrCode};
+
+/// The width of the quiet zone around the code, in modules.
+const QUIET_ZONE: u32 = 4;
+
+/// The characters of the data part of a bech32 string.
+const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+#[derive(thiserror::Error, Debug)]
+pub enum QrError {
+    #[error("could not encode QR code: {0}")]
+    Encode(#[from] qrcode::types::QrError),
+
+    #[error("could not write PNG: {0}")]
+    Png(#[from] png::EncodingError),
+}
+
+/// The string to encode for `data`, uppercased if it is a bech32
+/// string, e.g., an invoice.
+pub fn payload(data: &str) -> String {
+    let data = data.trim();
+    let rest = match data.get(..10) {
+        Some(p) if p.eq_ignore_ascii_case("lightning:") => &data[10..],
+        _ => data,
+    };
+    if is_bech32(rest) {
+        data.to_ascii_uppercase()
+    } else {
+        data.to_string()
+    }
+}
+
+fn is_bech32(s: &str) -> bool {
+    // Bech32 strings must not mix cases.
+    if s != s.to_ascii_lowercase() && s != s.to_ascii_uppercase() {
+        return false;
+    }
+    let s = s.to_ascii_lowercase();
+    let (hrp, data) = match s.rsplit_once('1') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    // Invoices, offers and LNURLs all start with `ln`.
+    hrp.starts_with("ln") && !data.is_empty() && data.chars().all(|c| BECH32_CHARSET.contains(c))
+}
+
+fn encode(data: &str) -> Result<QrCode, QrError> {
+    QrCode::with_error_correction_level(payload(data), EcLevel::M).map_err(QrError::from)
+}
+
+/// `data` as an SVG image, at least `size` pixels wide.
+pub fn svg(data: &str, size: u32) -> Result<String, QrError> {
+    Ok(encode(data)?
+        .render::<qrcode::render::svg::Color>()
+        .min_dimensions(size, size)
+        .quiet_zone(true)
+        .build())
+}
+
+/// `data` as a grayscale PNG image, with each module `scale` pixels
+/// wide.
+pub fn png(data: &str, scale: u32) -> Result<Vec<u8>, QrError> {
+    let code = encode(data)?;
+    let scale = scale.max(1);
+    let modules = code.width() as u32;
+    let size = (modules + 2 * QUIET_ZONE) * scale;
+    let colors = code.to_colors();
+
+    let mut pixels = vec![0xffu8; (size * size) as usize];
+    for y in 0..size {
+        for x in 0..size {
+            let (mx, my) = (x / scale, y / scale);
+            let inside = (QUIET_ZONE..QUIET_ZONE + modules).contains(&mx)
+                && (QUIET_ZONE..QUIET_ZONE + modules).contains(&my);
+            if inside {
+                let i = ((my - QUIET_ZONE) * modules + (mx - QUIET_ZONE)) as usize;
+                if colors[i] == Color::Dark {
+                    pixels[(y * size + x) as usize] = 0;
+                }
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, size, size);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    #[test]
+    fn test_payload() {
+        assert_eq!(payload("lnbcrt10u1pjqq"), "LNBCRT10U1PJQQ");
+        assert_eq!(payload(" lightning:lno1qgsq "), "LIGHTNING:LNO1QGSQ");
+        assert_eq!(payload("LNURL1DP68GURN"), "LNURL1DP68GURN");
+        // Not bech32: mixed case, invalid characters, or a URL.
+        assert_eq!(payload("lnbc1pJqq"), "lnbc1pJqq");
+        assert_eq!(payload("lnbc1pbqq"), "lnbc1pbqq");
+        assert_eq!(
+            payload("lightning:https://example.com/lnurl?q=1"),
+            "lightning:https://example.com/lnurl?q=1"
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let invoice = "lnbcrt10u1pjqqxyzpp5";
+        assert!(svg(invoice, 256).unwrap().contains("<svg"));
+
+        let png = png(invoice, 3).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+        // The width in the IHDR chunk covers the modules and the
+        // quiet zone.
+        let width = u32::from_be_bytes(png[16..20].try_into().unwrap());
+        assert_eq!(width % 3, 0);
+        assert_eq!(
+            width / 3,
+            encode(invoice).unwrap().width() as u32 + 2 * QUIET_ZONE
+        );
+    }
+}
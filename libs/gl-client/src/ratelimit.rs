@@ -0,0 +1,118 @@
+//! Recognize when the scheduler or the node asks us to slow down.
+//!
+//! Services signal rate limits with `RESOURCE_EXHAUSTED`, and proxies
+//! in front of them with HTTP status 429, which tonic reports as
+//! `UNAVAILABLE`. Either may say when to retry, in the `retry-after`
+//! header, in seconds, or in gRPC's `grpc-retry-pushback-ms`. Since
+//! `RESOURCE_EXHAUSTED` is also used for, e.g., messages that are too
+//! large, it only counts as a rate limit if it says when to retry, or
+//! its message mentions the rate limit.
+//!
+//! Scheduler calls fail with [`RateLimited`] in that case, which can
+//! be found with `downcast_ref` on the returned error, alongside the
+//! original [`tonic::Status`]. Node calls are classified as
+//! [`NodeError::RateLimited`]. The signer's reconnect loop waits for
+//! as long as asked before trying again.
+//!
+//! [`NodeError::RateLimited`]: crate::node::NodeError::RateLimited
+use std::time::Duration;
+use tonic::{Code, Status};
+
+/// How long to back off if the service does not say.
+pub(crate) const DEFAULT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Phrases in the message of a `RESOURCE_EXHAUSTED` status that mark
+/// it as a rate limit, matched case-insensitively.
+const RATE_LIMIT_MARKERS: &[&str] = &["rate limit", "ratelimit", "too many requests"];
+
+#[derive(thiserror::Error, Clone, Debug, PartialEq, Eq)]
+#[error("rate limited by the service, retry after {retry_after:?}")]
+pub struct RateLimited {
+    /// How long the service asked us to wait, if it said.
+    pub retry_after: Option<Duration>,
+}
+
+impl RateLimited {
+    /// Whether `status` is a rate limit response.
+    pub fn from_status(status: &Status) -> Option<RateLimited> {
+        let header = |name: &str| {
+            status
+                .metadata()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+        };
+        let retry_after = header("grpc-retry-pushback-ms")
+            .map(Duration::from_millis)
+            .or_else(|| header("retry-after").map(Duration::from_secs));
+        let limited = match status.code() {
+            Code::ResourceExhausted => {
+                let message = status.message().to_lowercase();
+                retry_after.is_some() || RATE_LIMIT_MARKERS.iter().any(|m| message.contains(m))
+            }
+            Code::Unavailable => status.message().contains("HTTP status code 429"),
+            _ => false,
+        };
+        match limited {
+            true => Some(RateLimited { retry_after }),
+            false => None,
+        }
+    }
+
+    /// How long to wait before retrying.
+    pub fn backoff(&self) -> Duration {
+        self.retry_after.unwrap_or(DEFAULT_BACKOFF)
+    }
+}
+
+pub(crate) trait StatusExt<T> {
+    /// Convert the error, attaching [`RateLimited`] if it is a rate
+    /// limit response.
+    fn or_rate_limited(self) -> anyhow::Result<T>;
+}
+
+impl<T> StatusExt<T> for Result<T, Status> {
+    fn or_rate_limited(self) -> anyhow::Result<T> {
+        self.map_err(|status| match RateLimited::from_status(&status) {
+            Some(limited) => anyhow::Error::new(status).context(limited),
+            None => status.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limited() {
+        assert_eq!(RateLimited::from_status(&Status::internal("boom")), None);
+        assert_eq!(RateLimited::from_status(&Status::unavailable("down")), None);
+        assert_eq!(
+            RateLimited::from_status(&Status::resource_exhausted("message too large")),
+            None
+        );
+        assert_eq!(
+            RateLimited::from_status(&Status::resource_exhausted("Rate limit exceeded")),
+            Some(RateLimited { retry_after: None })
+        );
+
+        let mut status = Status::resource_exhausted("slow down");
+        status
+            .metadata_mut()
+            .insert("retry-after", "5".parse().unwrap());
+        let limited = RateLimited::from_status(&status).unwrap();
+        assert_eq!(limited.backoff(), Duration::from_secs(5));
+
+        let status =
+            Status::unavailable("grpc-status header missing, mapped from HTTP status code 429");
+        assert_eq!(
+            RateLimited::from_status(&status).unwrap().backoff(),
+            DEFAULT_BACKOFF
+        );
+
+        let err = Err::<(), _>(status).or_rate_limited().unwrap_err();
+        assert!(err.downcast_ref::<RateLimited>().is_some());
+        assert!(err.downcast_ref::<Status>().is_some());
+    }
+}
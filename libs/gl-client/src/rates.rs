@@ -0,0 +1,333 @@
+//! Fiat exchange rates for bitcoin.
+//!
+//! Wallets show fiat values next to amounts, and the [`ledger`]
+//! export values entries at the time they happened. [`Rates`] looks
+//! prices up from public price APIs, falling back to the next
+//! provider if one fails, and caches the results so repeated lookups
+//! for the same period do not hit the network.
+//!
+//! [`ledger`]: crate::ledger
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::{debug, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+const MSAT_PER_BTC: f64 = 100_000_000_000.0;
+
+/// Prices are cached per currency and hour by default.
+const DEFAULT_GRANULARITY: u64 = 3600;
+
+/// Looks up the price of bitcoin, so amounts can be valued in fiat.
+#[async_trait]
+pub trait RateProvider: Send + Sync {
+    /// The price of one bitcoin in `currency` at the UNIX timestamp
+    /// `at`.
+    async fn rate(&self, currency: &str, at: u64) -> Result<f64>;
+}
+
+#[async_trait]
+impl<T: RateProvider + ?Sized> RateProvider for std::sync::Arc<T> {
+    async fn rate(&self, currency: &str, at: u64) -> Result<f64> {
+        (**self).rate(currency, at).await
+    }
+}
+
+/// Convert `msat` to `currency` using the price at `at_time`.
+pub async fn convert(
+    provider: &dyn RateProvider,
+    msat: u64,
+    currency: &str,
+    at_time: u64,
+) -> Result<f64> {
+    let rate = provider.rate(currency, at_time).await?;
+    Ok(msat as f64 / MSAT_PER_BTC * rate)
+}
+
+async fn get<T: for<'de> Deserialize<'de>>(client: &reqwest::Client, url: &str) -> Result<T> {
+    debug!("Fetching exchange rate from {}", url);
+    Ok(client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<T>()
+        .await?)
+}
+
+#[derive(Deserialize)]
+struct CoinbaseResponse {
+    data: CoinbaseData,
+}
+
+#[derive(Deserialize)]
+struct CoinbaseData {
+    amount: String,
+}
+
+/// Daily spot prices from Coinbase.
+pub struct Coinbase {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl Coinbase {
+    pub fn new(base_url: &str) -> Self {
+        Coinbase {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+impl Default for Coinbase {
+    fn default() -> Self {
+        Coinbase::new("https://api.coinbase.com")
+    }
+}
+
+#[async_trait]
+impl RateProvider for Coinbase {
+    async fn rate(&self, currency: &str, at: u64) -> Result<f64> {
+        let date = time::OffsetDateTime::from_unix_timestamp(at as i64)?.date();
+        let url = format!(
+            "{}/v2/prices/BTC-{}/spot?date={:04}-{:02}-{:02}",
+            self.base_url,
+            currency.to_uppercase(),
+            date.year(),
+            date.month() as u8,
+            date.day()
+        );
+        let res: CoinbaseResponse = get(&self.client, &url).await?;
+        Ok(res.data.amount.parse()?)
+    }
+}
+
+#[derive(Deserialize)]
+struct KrakenResponse {
+    error: Vec<String>,
+    #[serde(default)]
+    result: HashMap<String, serde_json::Value>,
+}
+
+/// Daily closing prices from Kraken. Kraken only returns the last 720
+/// days, older lookups fail and fall through to the next provider.
+pub struct Kraken {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl Kraken {
+    pub fn new(base_url: &str) -> Self {
+        Kraken {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn parse(res: KrakenResponse) -> Result<f64> {
+        if !res.error.is_empty() {
+            return Err(anyhow!(
+                "kraken returned an error: {}",
+                res.error.join(", ")
+            ));
+        }
+        // The result is keyed by Kraken's name for the pair, next to
+        // a `last` cursor.
+        let close = res
+            .result
+            .iter()
+            .filter(|(k, _)| *k != "last")
+            .find_map(|(_, v)| v.get(0)?.get(4)?.as_str())
+            .ok_or_else(|| anyhow!("kraken returned no prices"))?;
+        Ok(close.parse()?)
+    }
+}
+
+impl Default for Kraken {
+    fn default() -> Self {
+        Kraken::new("https://api.kraken.com")
+    }
+}
+
+#[async_trait]
+impl RateProvider for Kraken {
+    async fn rate(&self, currency: &str, at: u64) -> Result<f64> {
+        let url = format!(
+            "{}/0/public/OHLC?pair=XBT{}&interval=1440&since={}",
+            self.base_url,
+            currency.to_uppercase(),
+            at.saturating_sub(86400)
+        );
+        Kraken::parse(get(&self.client, &url).await?)
+    }
+}
+
+#[derive(Deserialize)]
+struct MempoolResponse {
+    prices: Vec<HashMap<String, f64>>,
+}
+
+/// Historical prices from a mempool.space instance.
+pub struct MempoolSpace {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl MempoolSpace {
+    pub fn new(base_url: &str) -> Self {
+        MempoolSpace {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn parse(res: MempoolResponse, currency: &str) -> Result<f64> {
+        res.prices
+            .first()
+            .and_then(|p| p.get(currency))
+            .copied()
+            .ok_or_else(|| anyhow!("mempool.space has no {} price", currency))
+    }
+}
+
+impl Default for MempoolSpace {
+    fn default() -> Self {
+        MempoolSpace::new("https://mempool.space")
+    }
+}
+
+#[async_trait]
+impl RateProvider for MempoolSpace {
+    async fn rate(&self, currency: &str, at: u64) -> Result<f64> {
+        let currency = currency.to_uppercase();
+        let url = format!(
+            "{}/api/v1/historical-price?currency={}&timestamp={}",
+            self.base_url, currency, at
+        );
+        MempoolSpace::parse(get(&self.client, &url).await?, &currency)
+    }
+}
+
+/// Query a list of providers in order, and cache their answers.
+pub struct Rates {
+    providers: Vec<Box<dyn RateProvider>>,
+    granularity: u64,
+    cache: Mutex<HashMap<(String, u64), f64>>,
+}
+
+impl Rates {
+    pub fn new(providers: Vec<Box<dyn RateProvider>>) -> Self {
+        Rates {
+            providers,
+            granularity: DEFAULT_GRANULARITY,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Lookups within the same `seconds` long period share a price.
+    pub fn with_granularity(mut self, seconds: u64) -> Self {
+        self.granularity = seconds.max(1);
+        self
+    }
+
+    /// Convert `msat` to `currency` using the price at `at_time`.
+    pub async fn convert(&self, msat: u64, currency: &str, at_time: u64) -> Result<f64> {
+        convert(self, msat, currency, at_time).await
+    }
+}
+
+impl Default for Rates {
+    fn default() -> Self {
+        Rates::new(vec![
+            Box::<MempoolSpace>::default(),
+            Box::<Coinbase>::default(),
+            Box::<Kraken>::default(),
+        ])
+    }
+}
+
+#[async_trait]
+impl RateProvider for Rates {
+    async fn rate(&self, currency: &str, at: u64) -> Result<f64> {
+        let key = (currency.to_uppercase(), at / self.granularity);
+        if let Some(rate) = self.cache.lock().unwrap().get(&key) {
+            return Ok(*rate);
+        }
+
+        let mut last_err = anyhow!("no rate providers configured");
+        for p in self.providers.iter() {
+            match p.rate(currency, at).await {
+                Ok(rate) => {
+                    self.cache.lock().unwrap().insert(key, rate);
+                    return Ok(rate);
+                }
+                Err(e) => {
+                    warn!("Rate provider failed, trying the next one: {}", e);
+                    last_err = e;
+                }
+            }
+        }
+        Err(last_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct Counting {
+        calls: Arc<AtomicUsize>,
+        rate: Option<f64>,
+    }
+
+    #[async_trait]
+    impl RateProvider for Counting {
+        async fn rate(&self, _currency: &str, _at: u64) -> Result<f64> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.rate.ok_or_else(|| anyhow!("unavailable"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fallback_and_cache() {
+        let failing = Arc::new(AtomicUsize::new(0));
+        let working = Arc::new(AtomicUsize::new(0));
+        let rates = Rates::new(vec![
+            Box::new(Counting {
+                calls: failing.clone(),
+                rate: None,
+            }),
+            Box::new(Counting {
+                calls: working.clone(),
+                rate: Some(40_000.0),
+            }),
+        ]);
+
+        assert_eq!(rates.convert(50_000_000, "usd", 7200).await.unwrap(), 20.0);
+        assert_eq!(rates.convert(100_000_000, "USD", 7300).await.unwrap(), 40.0);
+        assert_eq!(failing.load(Ordering::SeqCst), 1);
+        assert_eq!(working.load(Ordering::SeqCst), 1);
+
+        rates.rate("USD", 10800).await.unwrap();
+        assert_eq!(working.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_parse() {
+        let kraken: KrakenResponse = serde_json::from_str(
+            r#"{"error":[],"result":{"XXBTZUSD":[[1700000000,"36500.0","37000.0","36000.0","36800.5","36600.0","100.0",5000]],"last":1700000000}}"#,
+        )
+        .unwrap();
+        assert_eq!(Kraken::parse(kraken).unwrap(), 36800.5);
+
+        let mempool: MempoolResponse = serde_json::from_str(
+            r#"{"prices":[{"time":1700000000,"USD":36721,"EUR":33623}],"exchangeRates":{}}"#,
+        )
+        .unwrap();
+        assert_eq!(MempoolSpace::parse(mempool, "EUR").unwrap(), 33623.0);
+    }
+}
@@ -0,0 +1,363 @@
+//! Capture the calls to a node, and serve them back later.
+//!
+//! A bug that only shows with the data of a particular node is hard to
+//! reproduce without access to that node. A [`Recorder`], registered
+//! with [`Node::with_recorder`], writes every call the node clients
+//! make, and the node's response, to a trace file. The trace can be
+//! attached to a bug report, and [`Replay`] serves the responses back
+//! to a client, without a node:
+//!
+//! ```no_run
+//! # use gl_client::pb::cln::{node_client::NodeClient, GetinfoRequest};
+//! # use gl_client::record::Replay;
+//! # async fn example() -> anyhow::Result<()> {
+//! let mut node = NodeClient::new(Replay::load("trace.jsonl")?);
+//! let info = node.getinfo(GetinfoRequest::default()).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each line of the trace is an [`Exchange`] in JSON. The request and
+//! response messages are recorded, the headers, which carry the
+//! credentials of the client, are not. The messages themselves may
+//! still reveal invoices, peers and balances, so review traces before
+//! sharing them.
+//!
+//! [`Node::with_recorder`]: crate::node::Node::with_recorder
+use anyhow::{Context as _, Result};
+use bytes::{Bytes, BytesMut};
+use http::{HeaderMap, HeaderValue, Request, Response};
+use http_body::Body;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::future::Future;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tonic::body::BoxBody;
+use tonic::Code;
+use tower::Service;
+
+/// A call and its response, as recorded in the trace.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Exchange {
+    /// The gRPC method, e.g., `/cln.Node/Getinfo`.
+    pub path: String,
+    /// The length-prefixed request message, hex encoded.
+    pub request: String,
+    /// The length-prefixed response messages, hex encoded.
+    pub response: String,
+    /// The `grpc-encoding` of the response, if it is compressed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    /// The gRPC status code the call completed with.
+    pub status: i32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub message: String,
+}
+
+/// Appends the calls of the node clients to a trace file.
+///
+/// Cloning is cheap, and all clones write to the same file.
+#[derive(Clone)]
+pub struct Recorder {
+    file: Arc<Mutex<File>>,
+}
+
+impl Recorder {
+    /// Record to `path`, replacing the file if it exists.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("creating trace {}", path.display()))?;
+        Ok(Recorder {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    fn write(&self, exchange: &Exchange) {
+        let mut line = serde_json::to_vec(exchange).expect("exchanges serialize to JSON");
+        line.push(b'\n');
+        if let Err(e) = self.file.lock().unwrap().write_all(&line) {
+            warn!("Could not record call to {}: {}", exchange.path, e);
+        }
+    }
+}
+
+/// Record the response to the call to `path` with `request` on
+/// `recorder`, if any, while it is read. The exchange is written once
+/// the response completes, or is dropped.
+pub(crate) fn record<B>(
+    recorder: Option<&Recorder>,
+    path: &str,
+    request: &[u8],
+    response: Response<B>,
+) -> Response<Recording<B>> {
+    let (parts, body) = response.into_parts();
+    let tape = recorder.map(|recorder| {
+        let mut exchange = Exchange {
+            path: path.to_string(),
+            request: hex::encode(request),
+            encoding: header(&parts.headers, "grpc-encoding"),
+            ..Default::default()
+        };
+        // Failed calls carry their status in the headers.
+        complete(&mut exchange, &parts.headers);
+        (recorder.clone(), exchange)
+    });
+    let body = Recording {
+        inner: body,
+        response: BytesMut::new(),
+        tape,
+    };
+    Response::from_parts(parts, body)
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+fn complete(exchange: &mut Exchange, headers: &HeaderMap) {
+    if let Some(status) = header(headers, "grpc-status").and_then(|s| s.parse().ok()) {
+        exchange.status = status;
+        exchange.message = header(headers, "grpc-message").unwrap_or_default();
+    }
+}
+
+/// A response body that is recorded while it is read.
+pub struct Recording<B> {
+    inner: B,
+    response: BytesMut,
+    tape: Option<(Recorder, Exchange)>,
+}
+
+impl<B> Recording<B> {
+    fn finish(&mut self, trailers: Option<&HeaderMap>) {
+        if let Some((recorder, mut exchange)) = self.tape.take() {
+            if let Some(trailers) = trailers {
+                complete(&mut exchange, trailers);
+            }
+            exchange.response = hex::encode(&self.response);
+            recorder.write(&exchange);
+        }
+    }
+}
+
+impl<B> Drop for Recording<B> {
+    fn drop(&mut self) {
+        self.finish(None);
+    }
+}
+
+impl<B> Body for Recording<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let res = Pin::new(&mut self.inner).poll_data(cx);
+        if let Poll::Ready(Some(Ok(data))) = &res {
+            if self.tape.is_some() {
+                self.response.extend_from_slice(data);
+            }
+        }
+        res
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let res = Pin::new(&mut self.inner).poll_trailers(cx);
+        if let Poll::Ready(Ok(trailers)) = &res {
+            self.finish(trailers.as_ref());
+        }
+        res
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// A transport that answers calls with the responses of a trace.
+///
+/// Each call is answered with the first recorded exchange for the
+/// same method and request that was not served yet, or else the
+/// first one for the same method, so calls may be replayed in a
+/// different order. Calls that were not recorded fail with
+/// `NOT_FOUND`.
+#[derive(Clone)]
+pub struct Replay {
+    exchanges: Arc<Mutex<VecDeque<Exchange>>>,
+}
+
+impl Replay {
+    pub fn new(exchanges: Vec<Exchange>) -> Self {
+        Replay {
+            exchanges: Arc::new(Mutex::new(exchanges.into())),
+        }
+    }
+
+    /// Replay the trace written by a [`Recorder`] to `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("opening trace {}", path.display()))?;
+        let exchanges = BufReader::new(file)
+            .lines()
+            .filter(|l| !matches!(l, Ok(l) if l.trim().is_empty()))
+            .map(|l| Ok(serde_json::from_str(&l?)?))
+            .collect::<Result<Vec<Exchange>>>()
+            .with_context(|| format!("reading trace {}", path.display()))?;
+        Ok(Replay::new(exchanges))
+    }
+
+    /// The recorded exchanges that were not served yet.
+    pub fn remaining(&self) -> usize {
+        self.exchanges.lock().unwrap().len()
+    }
+
+    fn take(&self, path: &str, request: &str) -> Option<Exchange> {
+        let mut exchanges = self.exchanges.lock().unwrap();
+        let i = exchanges
+            .iter()
+            .position(|e| e.path == path && e.request == request)
+            .or_else(|| exchanges.iter().position(|e| e.path == path))?;
+        exchanges.remove(i)
+    }
+}
+
+impl Service<Request<BoxBody>> for Replay {
+    type Response = Response<ReplayBody>;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<BoxBody>) -> Self::Future {
+        let replay = self.clone();
+        Box::pin(async move {
+            let path = request.uri().path().to_string();
+            let mut body = request.into_body();
+            let payload = body.data().await.transpose()?.unwrap_or_default();
+            let exchange = replay
+                .take(&path, &hex::encode(&payload))
+                .unwrap_or_else(|| Exchange {
+                    path: path.clone(),
+                    status: Code::NotFound as i32,
+                    message: format!("no recorded call to {}", path),
+                    ..Default::default()
+                });
+
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", exchange.status.into());
+            if !exchange.message.is_empty() {
+                trailers.insert("grpc-message", HeaderValue::from_str(&exchange.message)?);
+            }
+            let mut response = Response::builder().header("content-type", "application/grpc");
+            if let Some(encoding) = &exchange.encoding {
+                response = response.header("grpc-encoding", encoding.as_str());
+            }
+            Ok(response.body(ReplayBody {
+                data: Some(hex::decode(&exchange.response)?.into()),
+                trailers: Some(trailers),
+            })?)
+        })
+    }
+}
+
+/// The recorded response messages, followed by the recorded status.
+pub struct ReplayBody {
+    data: Option<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+impl Body for ReplayBody {
+    type Data = Bytes;
+    type Error = tonic::Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Poll::Ready(self.data.take().filter(|d| !d.is_empty()).map(Ok))
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(self.trailers.take()))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_none() && self.trailers.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::cln::{node_client::NodeClient, GetinfoRequest, GetinfoResponse};
+    use prost::Message;
+
+    /// A message with the gRPC length prefix.
+    fn frame(msg: impl Message) -> Vec<u8> {
+        let mut buf = vec![0];
+        buf.extend_from_slice(&(msg.encoded_len() as u32).to_be_bytes());
+        buf.extend_from_slice(&msg.encode_to_vec());
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let trace = tempfile::NamedTempFile::new().unwrap();
+        let recorder = Recorder::create(trace.path()).unwrap();
+
+        // Record a response, as the node would have sent it.
+        let getinfo = GetinfoResponse {
+            alias: Some("recorded".to_string()),
+            ..Default::default()
+        };
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from(0));
+        let response = Response::new(ReplayBody {
+            data: Some(frame(getinfo.clone()).into()),
+            trailers: Some(trailers),
+        });
+        let request = frame(GetinfoRequest::default());
+        let mut body = record(Some(&recorder), "/cln.Node/Getinfo", &request, response).into_body();
+        while body.data().await.is_some() {}
+        body.trailers().await.unwrap();
+        drop(body);
+
+        let replay = Replay::load(trace.path()).unwrap();
+        assert_eq!(replay.remaining(), 1);
+        let mut node = NodeClient::new(replay.clone());
+        let res = node.getinfo(GetinfoRequest::default()).await.unwrap();
+        assert_eq!(res.into_inner(), getinfo);
+        assert_eq!(replay.remaining(), 0);
+
+        let err = node.getinfo(GetinfoRequest::default()).await.unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+    }
+}
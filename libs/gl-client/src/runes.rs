@@ -1,5 +1,10 @@
+use crate::bitcoin::hashes::{sha256, HashEngine};
+use base64::{engine::general_purpose, Engine as _};
 use runeauth::{Alternative, Check, Condition, ConditionChecker, Restriction, Rune, RuneError};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 use std::fmt::Display;
+use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Represents an entity that can provide restrictions.
@@ -41,15 +46,55 @@ impl RuneFactory {
             acc.append(&mut r);
             Ok(acc)
         })?;
+        Self::append(origin, restrictions)
+    }
+
+    /// Like [`RuneFactory::carve`], but emits the `method` restrictions
+    /// using core-lightning's lowercase method names, e.g.,
+    /// `method^list` instead of `method^List`, so the rune can also be
+    /// checked by commando.
+    pub fn carve_commando<T: Restrictor + Copy>(
+        origin: &Rune,
+        append: &[T],
+    ) -> Result<String, RuneError> {
+        let restrictions = append.into_iter().try_fold(Vec::new(), |mut acc, res| {
+            for r in res.generate()? {
+                let alts = r
+                    .alternatives
+                    .into_iter()
+                    .map(|a| match a.get_field().as_str() {
+                        "method" => alternative(
+                            "method",
+                            a.get_condition(),
+                            &commando_method(&a.get_value()),
+                        ),
+                        _ => Ok(a),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                acc.push(Restriction::new(alts)?);
+            }
+            Ok(acc)
+        })?;
+        Self::append(origin, restrictions)
+    }
 
-        let mut originc = origin.clone();
-        restrictions.into_iter().for_each(|r| {
-            // Changes are applied in place, as well as returned, so
-            // this is ok.
-            let _ = originc.add_restriction(r);
-        });
+    fn append(origin: &Rune, restrictions: Vec<Restriction>) -> Result<String, RuneError> {
+        // Derive the authcode here rather than with
+        // `Rune::add_restriction`, which gets it wrong for decoded
+        // runes, see [`is_authorized`].
+        let mut all = self::restrictions(origin)?;
+        let authcode = derive_authcode(origin.authcode(), &all, &restrictions);
+        all.extend(restrictions);
+        Ok(Rune::from_authcode(authcode, all).to_base64())
+    }
+}
 
-        Ok(originc.to_base64())
+/// Carve an arbitrary restriction, e.g., one on a field `DefRules` has
+/// no rule for. Values are escaped as core-lightning does, so they may
+/// contain `|`, `&` and `\`.
+impl Restrictor for &Restriction {
+    fn generate(self) -> Result<Vec<Restriction>, RuneError> {
+        Ok(vec![self.clone()])
     }
 }
 
@@ -66,6 +111,21 @@ pub enum DefRules<'a> {
     /// in a disjunctive set. Example: Add(vec![ReadOnly, Pay]) translates
     /// to a `Restriction` that is "method^Get|method^List|method=pay".
     Add(&'a [DefRules<'a>]),
+    /// Represents a rule set where requests are only allowed from the
+    /// first to before the second hour of the day, in UTC. This
+    /// translates to the `Restriction`s "hour>8" and "hour<17" for
+    /// `Hours(9, 17)`. The hours range from 0 to 23, and the first has
+    /// to come before the second. Must not be used in `Add`, since the
+    /// window needs both restrictions to hold.
+    Hours(u8, u8),
+    /// Represents a rule set where requests are only allowed from Monday
+    /// to Friday, in UTC. This translates to a `Restriction` that is
+    /// "weekday<6".
+    Weekdays,
+    /// Represents a rule set where requests are only allowed before the
+    /// given UNIX time, in seconds. This translates to a `Restriction`
+    /// that is "time<1700000000" for `Until(1700000000)`.
+    Until(u64),
 }
 
 impl<'a> Restrictor for DefRules<'a> {
@@ -98,8 +158,16 @@ impl<'a> Restrictor for DefRules<'a> {
                     rules
                         .into_iter()
                         .try_fold(Vec::new(), |mut acc: Vec<Alternative>, rule| {
-                            let mut alts = rule
-                                .generate()?
+                            let restrictions = rule.generate()?;
+                            // Merging the restrictions into one would
+                            // only require one of them to hold.
+                            if restrictions.len() > 1 {
+                                return Err(RuneError::ValueError(format!(
+                                    "{} can not be combined with other rules",
+                                    rule
+                                )));
+                            }
+                            let mut alts = restrictions
                                 .into_iter()
                                 .flat_map(|r| r.alternatives)
                                 .collect();
@@ -109,6 +177,38 @@ impl<'a> Restrictor for DefRules<'a> {
                 let a = vec![Restriction::new(alt_set)?];
                 Ok(a)
             }
+            DefRules::Hours(start, end) => {
+                check_hours(start, end)?;
+                let a = vec![
+                    Restriction::new(vec![alternative(
+                        "hour",
+                        Condition::IntGT,
+                        &(i32::from(start) - 1).to_string(),
+                    )?])?,
+                    Restriction::new(vec![alternative(
+                        "hour",
+                        Condition::IntLT,
+                        &end.to_string(),
+                    )?])?,
+                ];
+                Ok(a)
+            }
+            DefRules::Weekdays => {
+                let a = vec![Restriction::new(vec![alternative(
+                    "weekday",
+                    Condition::IntLT,
+                    "6",
+                )?])?];
+                Ok(a)
+            }
+            DefRules::Until(time) => {
+                let a = vec![Restriction::new(vec![alternative(
+                    "time",
+                    Condition::IntLT,
+                    &time.to_string(),
+                )?])?];
+                Ok(a)
+            }
         }
     }
 }
@@ -118,6 +218,9 @@ impl<'a> Display for DefRules<'a> {
         match self {
             DefRules::ReadOnly => write!(f, "readonly"),
             DefRules::Pay => write!(f, "pay"),
+            DefRules::Hours(start, end) => write!(f, "hours({}-{})", start, end),
+            DefRules::Weekdays => write!(f, "weekdays"),
+            DefRules::Until(time) => write!(f, "until({})", time),
             DefRules::Add(rules) => {
                 write!(
                     f,
@@ -135,6 +238,272 @@ impl<'a> Display for DefRules<'a> {
     }
 }
 
+/// Parses the `Display` representation of a single rule, e.g., `pay` or
+/// `hours(9-17)`. A disjunction `pay|readonly` has to be split by the
+/// caller and combined with `DefRules::Add`, since the latter borrows
+/// its rules.
+impl FromStr for DefRules<'static> {
+    type Err = RuneError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hours = s
+            .strip_prefix("hours(")
+            .and_then(|h| h.strip_suffix(')'))
+            .and_then(|h| h.split_once('-'));
+        let until = s.strip_prefix("until(").and_then(|t| t.strip_suffix(')'));
+        if let Some(time) = until {
+            return time
+                .parse()
+                .map(DefRules::Until)
+                .map_err(|_| RuneError::ValueError(format!("invalid time in {}", s)));
+        }
+        match (s, hours) {
+            ("readonly", _) => Ok(DefRules::ReadOnly),
+            ("pay", _) => Ok(DefRules::Pay),
+            ("weekdays", _) => Ok(DefRules::Weekdays),
+            (_, Some((start, end))) => match (start.parse(), end.parse()) {
+                (Ok(start), Ok(end)) => {
+                    check_hours(start, end)?;
+                    Ok(DefRules::Hours(start, end))
+                }
+                _ => Err(RuneError::ValueError(format!("invalid hours in {}", s))),
+            },
+            _ => Err(RuneError::ValueError(format!("unknown rule {}", s))),
+        }
+    }
+}
+
+fn check_hours(start: u8, end: u8) -> Result<(), RuneError> {
+    if end > 23 || start >= end {
+        return Err(RuneError::ValueError(format!(
+            "invalid hours {}-{}, expected a window within 0-23",
+            start, end
+        )));
+    }
+    Ok(())
+}
+
+/// The contents of a rune, for display and inspection.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RuneInfo {
+    /// The hex encoded authcode, identifying the rune in revocations.
+    pub authcode: String,
+    pub unique_id: Option<String>,
+    /// The restrictions, each a list of alternatives of which one has to
+    /// hold, e.g., `[["method^get", "method^list"]]`.
+    pub restrictions: Vec<Vec<String>>,
+}
+
+impl RuneInfo {
+    /// Decode the base64 encoded `rune`.
+    pub fn decode(rune: &str) -> Result<Self, RuneError> {
+        let rune = decode_rune(rune)?;
+        let restrictions = restrictions(&rune)?
+            .iter()
+            .map(|r| r.alternatives.iter().map(|a| a.encode()).collect())
+            .collect();
+        Ok(RuneInfo {
+            authcode: hex::encode(rune.authcode()),
+            unique_id: rune.get_id(),
+            restrictions,
+        })
+    }
+}
+
+/// Decode the base64 encoded `rune`.
+///
+/// Use this instead of `Rune::from_base64`, which misplaces the
+/// alternatives following a value with multi-byte characters, see
+/// [`decode_restrictions`].
+pub fn decode_rune(rune: &str) -> Result<Rune, RuneError> {
+    let bytes = general_purpose::URL_SAFE
+        .decode(rune)
+        .map_err(|e| RuneError::Unknown(e.to_string()))?;
+    if bytes.len() < 32 {
+        return Err(RuneError::ValueError(
+            "expected decoded len to be contain 32byte authcode".to_string(),
+        ));
+    }
+    let mut authcode = [0; 32];
+    authcode.copy_from_slice(&bytes[..32]);
+    let restrictions =
+        std::str::from_utf8(&bytes[32..]).map_err(|e| RuneError::Unknown(e.to_string()))?;
+    Ok(Rune::from_authcode(
+        authcode,
+        decode_restrictions(restrictions)?,
+    ))
+}
+
+/// Decode the `&` separated restrictions of a rune, e.g.,
+/// `=0&method^Get|method^List`. The first restriction may be the
+/// unique id.
+///
+/// `Restriction::decode` and `Alternative::decode` locate the rest of
+/// the input by counting characters rather than bytes, so they cut
+/// into the next alternative once a value contains multi-byte
+/// characters. Here the alternatives are split at the unescaped
+/// separators first, and decoded one by one.
+pub fn decode_restrictions(encoded: &str) -> Result<Vec<Restriction>, RuneError> {
+    let mut restrictions = vec![];
+    if encoded.is_empty() {
+        return Ok(restrictions);
+    }
+    let mut alternatives = vec![];
+    let mut raw = String::new();
+    let mut chars = encoded.chars();
+    loop {
+        let c = chars.next();
+        match c {
+            Some('\\') => {
+                raw.push('\\');
+                raw.extend(chars.next());
+            }
+            Some(c) if c != '|' && c != '&' => raw.push(c),
+            _ => {
+                alternatives.push(decode_alternative(&raw, restrictions.is_empty())?);
+                raw.clear();
+                if c == Some('|') {
+                    continue;
+                }
+                restrictions.push(Restriction::new(std::mem::take(&mut alternatives))?);
+                if c.is_none() {
+                    return Ok(restrictions);
+                }
+            }
+        }
+    }
+}
+
+/// Decode a single alternative, e.g., `method^list`, unescaping its
+/// value.
+fn decode_alternative(encoded: &str, allow_idfield: bool) -> Result<Alternative, RuneError> {
+    // The field ends at the first punctuation, which is the condition.
+    let (i, c) = encoded
+        .char_indices()
+        .find(|(_, c)| c.is_ascii_punctuation() && *c != '_')
+        .ok_or_else(|| RuneError::NoOperator(encoded.to_string()))?;
+    let cond = Condition::try_from(c)?;
+    let mut value = String::new();
+    let mut chars = encoded[i + 1..].chars();
+    while let Some(c) = chars.next() {
+        value.extend(if c == '\\' { chars.next() } else { Some(c) });
+    }
+    Alternative::new(&encoded[..i], cond, &value, allow_idfield)
+}
+
+/// The restrictions of `rune`, including its unique id.
+fn restrictions(rune: &Rune) -> Result<Vec<Restriction>, RuneError> {
+    let encoded = rune.to_string();
+    let rest = encoded.split_once(':').map(|(_, r)| r).unwrap_or_default();
+    decode_restrictions(rest)
+}
+
+/// Whether `rune` was carved from `master`.
+///
+/// Use this instead of `Rune::is_authorized`, which also compares the
+/// number of bytes hashed into the runes. `Rune::from_authcode`, and
+/// so [`decode_rune`] and `Rune::from_base64`, leave the length
+/// suffix of the SHA-256 padding out of that number, so a decoded
+/// rune fails the check whenever its restrictions end within 9 bytes
+/// of a block boundary. Here only the authcodes are compared.
+pub fn is_authorized(master: &Rune, rune: &Rune) -> bool {
+    let (ours, theirs) = match (restrictions(master), restrictions(rune)) {
+        (Ok(ours), Ok(theirs)) => (ours, theirs),
+        _ => return false,
+    };
+    if theirs.len() < ours.len()
+        || ours
+            .iter()
+            .zip(theirs.iter())
+            .any(|(o, t)| o.encode() != t.encode())
+    {
+        return false;
+    }
+    derive_authcode(master.authcode(), &ours, &theirs[ours.len()..]) == rune.authcode()
+}
+
+/// The authcode of a rune with `authcode` and `restrictions`, once
+/// `append` is added to it.
+fn derive_authcode(
+    authcode: [u8; 32],
+    restrictions: &[Restriction],
+    append: &[Restriction],
+) -> [u8; 32] {
+    // The secret fills the first block, and each restriction is
+    // padded to a full block, including the 0x80 byte and the 8 bytes
+    // of the length.
+    let len = restrictions
+        .iter()
+        .fold(64, |len, r| (len + r.encode().len() + 9).div_ceil(64) * 64);
+    let mut engine = sha256::HashEngine::from_midstate(sha256::Midstate::from_inner(authcode), len);
+    for r in append {
+        let mut data = r.encode().into_bytes();
+        runeauth::add_padding(engine.n_bytes_hashed() + data.len(), &mut data);
+        engine.input(&data);
+    }
+    engine.midstate().into_inner()
+}
+
+/// Escape `value` to be placed into an encoded alternative, e.g.,
+/// `format!("description={}", escape("a|b"))`. Like core-lightning,
+/// `\`, `|` and `&` are escaped with a backslash.
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | '|' | '&') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Split an encoded restriction, e.g., `method^list|description=a\|b`,
+/// into its alternatives at the unescaped `|`. The alternatives keep
+/// their escapes, to be parsed with [`parse_alternative`].
+pub fn split_alternatives(encoded: &str) -> Vec<&str> {
+    let mut alternatives = vec![];
+    let mut start = 0;
+    let mut chars = encoded.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '|' => {
+                alternatives.push(&encoded[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    alternatives.push(&encoded[start..]);
+    alternatives
+}
+
+/// Parse a single encoded alternative, e.g., `method^list`. Values have
+/// to be [escaped](escape): an unescaped `|` or `&` would start another
+/// alternative or restriction, silently changing what the rune allows,
+/// and is rejected.
+pub fn parse_alternative(encoded: &str) -> Result<Alternative, RuneError> {
+    let mut chars = encoded.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '|' | '&' => {
+                return Err(RuneError::ValueError(format!(
+                    "unescaped '{}' in alternative {}",
+                    c, encoded
+                )))
+            }
+            _ => {}
+        }
+    }
+    decode_alternative(encoded, false)
+}
+
 /// Creates an `Alternative` based on the provided field, condition, and value.
 ///
 /// This function is a shorthand for creating new `Alternative` entities
@@ -153,8 +522,38 @@ fn alternative(field: &str, cond: Condition, value: &str) -> Result<Alternative,
     Alternative::new(field.to_string(), cond, value.to_string(), false)
 }
 
+/// Normalize a method name to the naming core-lightning uses, e.g.,
+/// `ListFunds` becomes `listfunds`. The few gRPC methods that are named
+/// differently from their JSON-RPC counterpart are renamed.
+pub fn commando_method(method: &str) -> String {
+    match method.to_lowercase().as_str() {
+        "connectpeer" => "connect".to_string(),
+        m => m.to_string(),
+    }
+}
+
+/// How often a rune was used to call a method, as recorded by the
+/// signer, see [`crate::signer::AuditLog::rune_usage`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuneUsage {
+    /// The unique id of the rune, empty for runes without one.
+    pub unique_id: String,
+    pub method: String,
+    pub count: u64,
+    /// Seconds since the UNIX epoch.
+    pub last_used: u64,
+}
+
 /// A context struct that holds information relevant to check a command against
 /// a rune.
+///
+/// Besides the fields of the struct, restrictions can check the `hour`
+/// of the day (0-23) and the ISO `weekday` (1 is Monday, 7 is Sunday)
+/// of the `time`, both in UTC.
+///
+/// The `method` is compared after normalizing both it and the restriction
+/// with [`commando_method`], so `method^List` and `method^list` are
+/// equivalent, and runes carved for commando check the same here.
 #[derive(Clone)]
 pub struct Context {
     // The rpc method associated with the request.
@@ -181,18 +580,30 @@ impl Check for Context {
     ///
     /// * `Ok(())` if the check is successful, an `Err` containing a `RuneError` otherwise.
     fn check_alternative(&self, alt: &Alternative) -> anyhow::Result<(), RuneError> {
-        let value = match alt.get_field().as_str() {
-            "" => self.unique_id.clone(),
-            "method" => self.method.clone(),
-            "pubkey" => self.pubkey.clone(),
-            "time" => self
-                .time
+        let secs = || {
+            self.time
                 .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
                 .map_err(|e| {
                     RuneError::Unknown(format!("Can not extract seconds from timestamp {:?}", e))
-                })?
-                .as_secs()
-                .to_string(),
+                })
+        };
+        let value = match alt.get_field().as_str() {
+            "" => self.unique_id.clone(),
+            "method" => {
+                let alt = alternative(
+                    "method",
+                    alt.get_condition(),
+                    &commando_method(&alt.get_value()),
+                )?;
+                let value = commando_method(&self.method);
+                return ConditionChecker { value }.check_alternative(&alt);
+            }
+            "pubkey" => self.pubkey.clone(),
+            "time" => secs()?.to_string(),
+            "hour" => (secs()? % 86400 / 3600).to_string(),
+            // The epoch was on a Thursday.
+            "weekday" => ((secs()? / 86400 + 3) % 7 + 1).to_string(),
             _ => String::new(), // If we don't know the field we can not set it!
         };
         ConditionChecker { value }.check_alternative(alt)
@@ -201,10 +612,34 @@ impl Check for Context {
 
 #[cfg(test)]
 mod tests {
-    use super::{Context, DefRules, RuneFactory};
+    use super::{
+        decode_restrictions, decode_rune, escape, is_authorized, parse_alternative,
+        split_alternatives, Context, DefRules, RuneFactory, RuneInfo,
+    };
     use base64::{engine::general_purpose, Engine as _};
+    use proptest::prelude::*;
     use runeauth::{Alternative, Condition, Restriction, Rune};
-    use std::time::SystemTime;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    /// Restrictions and their encoding, as core-lightning's `runes`
+    /// encodes them: `\`, `|` and `&` in values are escaped with a
+    /// backslash, anything else, including unicode, is kept as is.
+    const VECTORS: &[(&str, Condition, &str, &str)] = &[
+        ("method", Condition::Equal, "pay", "method=pay"),
+        ("pubkey", Condition::Equal, "a|b", "pubkey=a\\|b"),
+        ("pubkey", Condition::Equal, "a&b", "pubkey=a\\&b"),
+        ("pubkey", Condition::Equal, "a\\b", "pubkey=a\\\\b"),
+        ("pubkey", Condition::Equal, "\\|&", "pubkey=\\\\\\|\\&"),
+        ("pubkey", Condition::Equal, "\\", "pubkey=\\\\"),
+        ("pubkey", Condition::BeginsWith, "ünï¢ødé", "pubkey^ünï¢ødé"),
+        ("pubkey", Condition::Equal, "€|€&€", "pubkey=€\\|€\\&€"),
+        ("pubkey", Condition::Missing, "", "pubkey!"),
+    ];
+
+    fn carved_restrictions(carved: &str) -> String {
+        let carved_byt = general_purpose::URL_SAFE.decode(carved).unwrap();
+        String::from_utf8(carved_byt[32..].to_vec()).unwrap()
+    }
 
     #[test]
     fn test_carve_readonly_rune() {
@@ -240,6 +675,127 @@ mod tests {
         assert!(mr.is_authorized(&carved_rune));
     }
 
+    #[test]
+    fn test_carve_commando_rune() {
+        let seed = [0; 32];
+        let mr = Rune::new_master_rune(&seed, vec![], None, None).unwrap();
+
+        let carved = RuneFactory::carve_commando(
+            &mr,
+            &[DefRules::Add(&[DefRules::ReadOnly, DefRules::Pay])],
+        )
+        .unwrap();
+
+        let carved_byt = general_purpose::URL_SAFE.decode(&carved).unwrap();
+        let carved_restr = String::from_utf8(carved_byt[32..].to_vec()).unwrap();
+        assert_eq!(carved_restr, *"method^get|method^list|method=pay");
+
+        // Both namings check the same against either method name.
+        let carved_rune = Rune::from_base64(&carved).unwrap();
+        let grpc_rune =
+            Rune::from_base64(&RuneFactory::carve(&mr, &[DefRules::ReadOnly]).unwrap()).unwrap();
+        for method in ["ListFunds", "listfunds", "GetInfo"] {
+            let ctx = Context {
+                method: method.to_string(),
+                pubkey: String::new(),
+                time: SystemTime::now(),
+                unique_id: String::new(),
+            };
+            assert!(carved_rune.are_restrictions_met(ctx.clone()).is_ok());
+            assert!(grpc_rune.are_restrictions_met(ctx).is_ok());
+        }
+        let ctx = Context {
+            method: String::from("Withdraw"),
+            pubkey: String::new(),
+            time: SystemTime::now(),
+            unique_id: String::new(),
+        };
+        assert!(carved_rune.are_restrictions_met(ctx).is_err());
+    }
+
+    #[test]
+    fn test_time_window() {
+        let seed = [0; 32];
+        let mr = Rune::new_master_rune(&seed, vec![], None, None).unwrap();
+
+        let carved =
+            RuneFactory::carve(&mr, &[DefRules::Hours(9, 17), DefRules::Weekdays]).unwrap();
+        let carved_byt = general_purpose::URL_SAFE.decode(&carved).unwrap();
+        let carved_restr = String::from_utf8(carved_byt[32..].to_vec()).unwrap();
+        assert_eq!(carved_restr, *"hour>8&hour<17&weekday<6");
+
+        let rune = Rune::from_base64(&carved).unwrap();
+        let ctx = |secs: u64| Context {
+            method: String::new(),
+            pubkey: String::new(),
+            time: UNIX_EPOCH + Duration::from_secs(secs),
+            unique_id: String::new(),
+        };
+        // 2024-01-01 was a Monday.
+        let monday = 1704067200;
+        assert!(rune.are_restrictions_met(ctx(monday + 9 * 3600)).is_ok());
+        assert!(rune
+            .are_restrictions_met(ctx(monday + 17 * 3600 - 1))
+            .is_ok());
+        assert!(rune.are_restrictions_met(ctx(monday + 8 * 3600)).is_err());
+        assert!(rune.are_restrictions_met(ctx(monday + 17 * 3600)).is_err());
+        // Saturday.
+        let saturday = monday + 5 * 86400;
+        assert!(rune
+            .are_restrictions_met(ctx(saturday + 10 * 3600))
+            .is_err());
+        assert!(rune
+            .are_restrictions_met(ctx(saturday - 86400 + 10 * 3600))
+            .is_ok());
+
+        // Invalid windows, and windows in a disjunction, are rejected.
+        assert!(RuneFactory::carve(&mr, &[DefRules::Hours(9, 24)]).is_err());
+        assert!(RuneFactory::carve(&mr, &[DefRules::Hours(17, 9)]).is_err());
+        assert!(RuneFactory::carve(&mr, &[DefRules::Hours(9, 9)]).is_err());
+        assert!(RuneFactory::carve(
+            &mr,
+            &[DefRules::Add(&[DefRules::Pay, DefRules::Hours(9, 17)])]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_defrules_parse() {
+        for r in [
+            DefRules::ReadOnly,
+            DefRules::Pay,
+            DefRules::Weekdays,
+            DefRules::Hours(9, 17),
+            DefRules::Until(1700000000),
+        ] {
+            let parsed: DefRules = r.to_string().parse().unwrap();
+            assert_eq!(parsed.to_string(), r.to_string());
+        }
+        assert!("hours(9)".parse::<DefRules>().is_err());
+        assert!("hours(17-9)".parse::<DefRules>().is_err());
+        assert!("pay|readonly".parse::<DefRules>().is_err());
+    }
+
+    #[test]
+    fn test_rune_info() {
+        let seed = [0; 32];
+        let mr = Rune::new_master_rune(&seed, vec![], Some("3".to_string()), None).unwrap();
+        let carved = RuneFactory::carve(&mr, &[DefRules::ReadOnly, DefRules::Weekdays]).unwrap();
+
+        let info = RuneInfo::decode(&carved).unwrap();
+        assert_eq!(info.unique_id.as_deref(), Some("3"));
+        let authcode = Rune::from_base64(&carved).unwrap().authcode();
+        assert_eq!(info.authcode, hex::encode(authcode));
+        assert_eq!(
+            info.restrictions,
+            vec![
+                vec!["=3".to_string()],
+                vec!["method^Get".to_string(), "method^List".to_string()],
+                vec!["weekday<6".to_string()],
+            ]
+        );
+    }
+
     #[test]
     fn test_defrules_display() {
         let r = DefRules::Pay;
@@ -248,6 +804,8 @@ mod tests {
         assert_eq!(format!("{}", r), "pay");
         let r = DefRules::Add(&[DefRules::Pay, DefRules::ReadOnly]);
         assert_eq!(format!("{}", r), "pay|readonly");
+        let r = DefRules::Hours(9, 17);
+        assert_eq!(format!("{}", r), "hours(9-17)");
     }
 
     #[test]
@@ -403,4 +961,116 @@ mod tests {
         };
         assert!(r4.are_restrictions_met(ctx).is_err());
     }
+
+    #[test]
+    fn test_carve_vectors() {
+        let mr = Rune::new_master_rune(&[0; 32], vec![], None, None).unwrap();
+        for (field, cond, value, encoded) in VECTORS {
+            let alt = Alternative::new(*field, cond.clone(), *value, false).unwrap();
+            let restriction = Restriction::new(vec![alt]).unwrap();
+            let carved = RuneFactory::carve(&mr, &[&restriction]).unwrap();
+            assert_eq!(carved_restrictions(&carved), *encoded);
+            assert!(encoded.ends_with(&escape(value)));
+            assert_eq!(parse_alternative(encoded).unwrap().get_value(), *value);
+
+            let decoded = decode_rune(&carved).unwrap();
+            assert!(mr.is_authorized(&decoded));
+            assert_eq!(decode_restrictions(encoded).unwrap(), vec![restriction]);
+        }
+
+        // Values followed by further alternatives and restrictions.
+        let encoded = VECTORS.iter().map(|v| v.3).collect::<Vec<_>>().join("|");
+        let encoded = format!("=1&{}&{}", encoded, encoded);
+        let restrictions = decode_restrictions(&encoded).unwrap();
+        assert_eq!(restrictions.len(), 3);
+        assert_eq!(restrictions[1].alternatives.len(), VECTORS.len());
+        for (alt, (_, _, value, _)) in restrictions[2].alternatives.iter().zip(VECTORS) {
+            assert_eq!(alt.get_value(), *value);
+        }
+        assert_eq!(
+            restrictions
+                .iter()
+                .map(|r| r.encode())
+                .collect::<Vec<_>>()
+                .join("&"),
+            encoded
+        );
+    }
+
+    #[test]
+    fn test_parse_alternative() {
+        let description = format!("description={}", escape("a|b&c"));
+        assert_eq!(description, "description=a\\|b\\&c");
+        assert_eq!(
+            parse_alternative(&description).unwrap().get_value(),
+            "a|b&c"
+        );
+
+        // Unescaped separators would change what the rune allows.
+        assert!(parse_alternative("description=a|method=pay").is_err());
+        assert!(parse_alternative("description=a&b").is_err());
+        assert!(parse_alternative("description").is_err());
+        assert!(parse_alternative("desc-ription=a").is_err());
+
+        let restriction = format!("method^list|{}", description);
+        assert_eq!(
+            split_alternatives(&restriction),
+            vec!["method^list", "description=a\\|b\\&c"]
+        );
+        assert_eq!(split_alternatives("a=\\\\|b=c"), vec!["a=\\\\", "b=c"]);
+    }
+
+    #[test]
+    fn test_decoded_rune_at_block_boundary() {
+        let mr = Rune::new_master_rune(&[0; 32], vec![], None, None).unwrap();
+        // 56 bytes leave no room for the padding in the block.
+        let value = "a".repeat(56 - "pubkey=".len());
+        let alt = Alternative::new("pubkey", Condition::Equal, value.as_str(), false).unwrap();
+        let restriction = Restriction::new(vec![alt]).unwrap();
+        let carved = RuneFactory::carve(&mr, &[&restriction]).unwrap();
+        let decoded = decode_rune(&carved).unwrap();
+        assert!(is_authorized(&mr, &decoded));
+
+        // Carving from the decoded rune continues from the right length.
+        let carved = RuneFactory::carve(&decoded, &[DefRules::ReadOnly]).unwrap();
+        assert!(is_authorized(&mr, &decode_rune(&carved).unwrap()));
+        assert!(!is_authorized(
+            &Rune::new_master_rune(&[1; 32], vec![], None, None).unwrap(),
+            &decoded
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_carve_escapes_values(pubkey in ".*", method in ".*") {
+            let mr = Rune::new_master_rune(&[0; 32], vec![], Some("0".to_string()), None).unwrap();
+            let restriction = Restriction::new(vec![
+                Alternative::new("pubkey", Condition::Equal, pubkey.as_str(), false).unwrap(),
+                Alternative::new("method", Condition::Equal, method.as_str(), false).unwrap(),
+            ])
+            .unwrap();
+            let carved = RuneFactory::carve(&mr, &[&restriction, &restriction]).unwrap();
+
+            let restrictions = format!("pubkey={}|method={}", escape(&pubkey), escape(&method));
+            prop_assert_eq!(
+                carved_restrictions(&carved),
+                format!("=0&{}&{}", restrictions, restrictions)
+            );
+
+            let rune = decode_rune(&carved).unwrap();
+            prop_assert!(is_authorized(&mr, &rune));
+            prop_assert_eq!(rune.to_base64(), carved.clone());
+            let ctx = Context {
+                method: String::new(),
+                pubkey: pubkey.clone(),
+                time: SystemTime::now(),
+                unique_id: "0".to_string(),
+            };
+            prop_assert!(rune.are_restrictions_met(ctx).is_ok());
+
+            let info = RuneInfo::decode(&carved).unwrap();
+            prop_assert_eq!(info.restrictions.len(), 3);
+            prop_assert_eq!(&info.restrictions[2][0], &format!("pubkey={}", escape(&pubkey)));
+        }
+    }
 }
@@ -0,0 +1,48 @@
+//! Pick the tokio runtime that background tasks are spawned on.
+//!
+//! The library spawns some tasks of its own, e.g., for
+//! [`Scheduler::prewarm`]. By default these end up on the runtime
+//! the caller is running on. Applications that manage their own
+//! runtime, and call into the library from threads outside of it, can
+//! register its [`Handle`] once at startup with [`set_handle`]. A
+//! current-thread runtime works too, but its tasks only make progress
+//! while the application drives the runtime.
+//!
+//! [`Scheduler::prewarm`]: crate::scheduler::Scheduler::prewarm
+use anyhow::{anyhow, Result};
+use std::future::Future;
+use std::sync::OnceLock;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+static HANDLE: OnceLock<Handle> = OnceLock::new();
+
+/// Spawn the library's tasks on the runtime of `handle`. Can only be
+/// called once, before any tasks are spawned.
+pub fn set_handle(handle: Handle) -> Result<()> {
+    HANDLE
+        .set(handle)
+        .map_err(|_| anyhow!("the runtime handle was set already"))
+}
+
+/// The registered runtime, or the one we are currently running on.
+///
+/// # Panics
+///
+/// If no runtime was registered and we are not running on one.
+pub fn handle() -> Handle {
+    match HANDLE.get() {
+        Some(h) => h.clone(),
+        None => Handle::try_current().expect(
+            "gl-client needs a tokio runtime, either run on one or register it with `set_handle`",
+        ),
+    }
+}
+
+pub(crate) fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    handle().spawn(future)
+}
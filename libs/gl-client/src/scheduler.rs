@@ -1,28 +1,52 @@
-use crate::credentials::{self, RuneProvider, NodeIdProvider, TlsConfigProvider};
+use crate::config::{Config, Timeouts};
+use crate::connection::{ConnectionStatus, StatusWatch};
+use crate::interceptor::{Intercepted, Interceptor, Interceptors};
+use crate::credentials::{RuneProvider, NodeIdProvider, TlsConfigProvider};
+use crate::metrics::{Startup, StartupTimer};
 use crate::node::{self, GrpcClient};
+use crate::pb;
 use crate::pb::scheduler::scheduler_client::SchedulerClient;
-use crate::tls::{self};
+use crate::ratelimit::StatusExt;
+use crate::record::Recorder;
+use crate::shutdown::Shutdown;
+#[cfg(feature = "resumption")]
+use crate::tls::resumption::ResumingConnector;
+use crate::tls::{self, TlsConfig};
 use crate::utils::scheduler_uri;
-use crate::{pb, signer::Signer};
-use anyhow::{Result};
+use crate::versions::VersionStatusExt;
+#[cfg(feature = "signer")]
+use crate::{credentials, signer::Signer};
+use anyhow::{anyhow, Result};
 use lightning_signer::bitcoin::Network;
 use log::debug;
-use runeauth;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tonic::codec::CompressionEncoding;
 use tonic::transport::Channel;
 
-type Client = SchedulerClient<Channel>;
+type Client = SchedulerClient<Intercepted>;
 
 /// A scheduler client to interact with the scheduler service. It has
 /// different implementations depending on the implementations
 #[derive(Clone)]
 pub struct Scheduler<Creds> {
-    client: Client,
     network: Network,
     grpc_uri: String,
     creds: Creds,
     ca: Vec<u8>,
+    status: StatusWatch,
+    shutdown: Shutdown,
+    timeouts: Timeouts,
+    interceptors: Interceptors,
+    compression: bool,
+    recorder: Option<Recorder>,
+    startup: Startup,
+    channel: CachedChannel,
 }
 
+/// The channel to the scheduler, and the TLS config it was built with.
+type CachedChannel = Arc<Mutex<Option<(TlsConfig, Channel)>>>;
+
 impl<Creds> Scheduler<Creds>
 where
     Creds: TlsConfigProvider,
@@ -73,30 +97,95 @@ where
         creds: Creds,
         uri: impl Into<String>,
     ) -> Result<Scheduler<Creds>> {
-        let uri = uri.into();
-        debug!("Connecting to scheduler at {}", uri);
-        let channel = tonic::transport::Endpoint::from_shared(uri.clone())?
-            .tls_config(creds.tls_config().inner.clone())?
-            .tcp_keepalive(Some(crate::TCP_KEEPALIVE))
-            .http2_keep_alive_interval(crate::TCP_KEEPALIVE)
-            .keep_alive_timeout(crate::TCP_KEEPALIVE_TIMEOUT)
-            .keep_alive_while_idle(true)
-            .connect_lazy();
-
-        let client = SchedulerClient::new(channel);
-        let ca = creds.tls_config().ca.clone();
+        Self::connect(network, creds, uri.into(), Timeouts::default())
+    }
+
+    /// Creates a new scheduler client for the network, scheduler,
+    /// timeouts and compression of `config`.
+    pub async fn with_config(config: &Config, creds: Creds) -> Result<Scheduler<Creds>> {
+        Ok(Self::connect(
+            config.network,
+            creds,
+            config.scheduler_uri(),
+            config.timeouts,
+        )?
+        .with_compression(config.compression))
+    }
+
+    fn connect(
+        network: Network,
+        creds: Creds,
+        uri: String,
+        timeouts: Timeouts,
+    ) -> Result<Scheduler<Creds>> {
+        let ca = creds.try_tls_config()?.ca;
 
         Ok(Scheduler {
-            client,
             network,
             creds,
             grpc_uri: uri,
             ca,
+            status: StatusWatch::new(),
+            shutdown: Shutdown::new(),
+            timeouts,
+            interceptors: Interceptors::default(),
+            compression: false,
+            recorder: None,
+            startup: Startup::new(),
+            channel: CachedChannel::default(),
         })
     }
 }
 
-impl<Creds> Scheduler<Creds> {
+impl<Creds: TlsConfigProvider> Scheduler<Creds> {
+    /// Report the connection status on `status`, e.g., to share it
+    /// with the [`Signer`].
+    pub fn with_status(mut self, status: StatusWatch) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn status(&self) -> &StatusWatch {
+        &self.status
+    }
+
+    /// The startup measurements of the node clients returned by
+    /// [`Self::node`] and [`Self::prewarm`], see [`crate::metrics`].
+    pub fn startup(&self) -> &Startup {
+        &self.startup
+    }
+
+    /// Track the calls of the node clients returned by [`Self::node`]
+    /// on `shutdown`.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Run `interceptor` on the calls to the scheduler, and on the
+    /// calls of the node clients returned by [`Self::node`], see
+    /// [`crate::interceptor`].
+    pub fn with_interceptor(mut self, interceptor: impl Interceptor + 'static) -> Self {
+        self.interceptors = self.interceptors.with(Arc::new(interceptor));
+        self
+    }
+
+    /// Ask the scheduler, and the nodes returned by [`Self::node`], to
+    /// gzip their responses. Servers that do not support compression
+    /// keep responding uncompressed. Requests are sent uncompressed
+    /// either way.
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        self.compression = enabled;
+        self
+    }
+
+    /// Record the calls of the node clients returned by [`Self::node`]
+    /// on `recorder`, see [`crate::record`].
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
     /// Registers a new node with the scheduler service.
     ///
     /// # Arguments
@@ -120,6 +209,7 @@ impl<Creds> Scheduler<Creds> {
     /// let registration_response = scheduler.register(&signer, None).await.unwrap();
     /// # }
     /// ```
+    #[cfg(feature = "signer")]
     pub async fn register(
         &self,
         signer: &Signer,
@@ -133,6 +223,7 @@ impl<Creds> Scheduler<Creds> {
     /// without an invite code in order to keep the api stable. We might want to
     /// remove the invite system in the future and so it does not make sense to
     /// change the signature of the register method.
+    #[cfg(feature = "signer")]
     async fn inner_register(
         &self,
         signer: &Signer,
@@ -140,13 +231,13 @@ impl<Creds> Scheduler<Creds> {
     ) -> Result<pb::scheduler::RegistrationResponse> {
         log::debug!("Retrieving challenge for registration");
         let challenge = self
-            .client
-            .clone()
+            .client()?
             .get_challenge(pb::scheduler::ChallengeRequest {
                 scope: pb::scheduler::ChallengeScope::Register as i32,
                 node_id: signer.node_id(),
             })
-            .await?
+            .await
+            .or_rate_limited()?
             .into_inner();
 
         log::trace!("Got a challenge: {}", hex::encode(&challenge.challenge));
@@ -167,8 +258,7 @@ impl<Creds> Scheduler<Creds> {
             .collect();
 
         let mut res = self
-            .client
-            .clone()
+            .client()?
             .register(pb::scheduler::RegistrationRequest {
                 node_id: signer.node_id(),
                 bip32_key: signer.bip32_ext_key(),
@@ -181,7 +271,8 @@ impl<Creds> Scheduler<Creds> {
                 invite_code: invite_code.into(),
                 startupmsgs,
             })
-            .await?
+            .await
+            .or_rate_limited()?
             .into_inner();
 
         // This step ensures backwards compatibility with the backend. If we did
@@ -247,15 +338,16 @@ impl<Creds> Scheduler<Creds> {
     /// let recovery_response = scheduler.recover(&signer).await.unwrap();
     /// # }
     /// ```
+    #[cfg(feature = "signer")]
     pub async fn recover(&self, signer: &Signer) -> Result<pb::scheduler::RecoveryResponse> {
         let challenge = self
-            .client
-            .clone()
+            .client()?
             .get_challenge(pb::scheduler::ChallengeRequest {
                 scope: pb::scheduler::ChallengeScope::Recover as i32,
                 node_id: signer.node_id(),
             })
-            .await?
+            .await
+            .or_rate_limited()?
             .into_inner();
 
         let signature = signer.sign_challenge(challenge.challenge.clone())?;
@@ -269,15 +361,15 @@ impl<Creds> Scheduler<Creds> {
         debug!("Requesting recovery with csr:\n{}", device_csr);
 
         let mut res = self
-            .client
-            .clone()
+            .client()?
             .recover(pb::scheduler::RecoveryRequest {
                 node_id: signer.node_id(),
                 challenge: challenge.challenge,
                 signature,
                 csr: device_csr.into_bytes(),
             })
-            .await?
+            .await
+            .or_rate_limited()?
             .into_inner();
 
         // This step ensures backwards compatibility with the backend. If we did
@@ -351,27 +443,85 @@ impl<Creds> Scheduler<Creds> {
     where
         Auth: TlsConfigProvider + RuneProvider,
     {
-        debug!("Connecting to scheduler at {}", self.grpc_uri);
-        let channel = tonic::transport::Endpoint::from_shared(self.grpc_uri.clone())?
-            .tls_config(creds.tls_config().inner.clone())?
-            .tcp_keepalive(Some(crate::TCP_KEEPALIVE))
-            .http2_keep_alive_interval(crate::TCP_KEEPALIVE)
-            .keep_alive_timeout(crate::TCP_KEEPALIVE_TIMEOUT)
-            .keep_alive_while_idle(true)
-            .connect_lazy();
-
-        let client = SchedulerClient::new(channel);
-
         Ok(Scheduler {
-            client,
             network: self.network,
             creds,
             grpc_uri: self.grpc_uri.clone(),
             ca: self.ca.clone(),
+            status: self.status.clone(),
+            shutdown: self.shutdown.clone(),
+            timeouts: self.timeouts,
+            interceptors: self.interceptors.clone(),
+            compression: self.compression,
+            recorder: self.recorder.clone(),
+            startup: self.startup.clone(),
+            channel: CachedChannel::default(),
+        })
+    }
+}
+
+impl<Creds: TlsConfigProvider> Scheduler<Creds> {
+    /// A client on the channel to the scheduler. The credentials are
+    /// asked for their identity on each call, and the channel is only
+    /// rebuilt if it changed, e.g., if [`crate::credentials::OnDemand`]
+    /// credentials were rotated.
+    fn client(&self) -> Result<Client> {
+        let tls = self.creds.try_tls_config()?;
+        let channel = {
+            let mut cached = self.channel.lock().unwrap();
+            match cached.as_ref() {
+                Some((t, channel)) if t.same_identity(&tls) => channel.clone(),
+                _ => {
+                    debug!("Connecting to scheduler at {}", self.grpc_uri);
+                    let channel = channel(&self.grpc_uri, &tls, &self.timeouts)?;
+                    *cached = Some((tls, channel.clone()));
+                    channel
+                }
+            }
+        };
+        let client = SchedulerClient::new(Intercepted::new(channel, self.interceptors.clone()));
+        Ok(match self.compression {
+            true => client.accept_compressed(CompressionEncoding::Gzip),
+            false => client,
         })
     }
 }
 
+/// A channel to the scheduler at `uri`, connecting on first use.
+fn channel(uri: &str, tls: &TlsConfig, timeouts: &Timeouts) -> Result<Channel> {
+    #[cfg(feature = "resumption")]
+    {
+        let uri: tonic::transport::Uri = uri.parse()?;
+        let (host, authority) = match (uri.host(), uri.authority()) {
+            (Some(host), Some(authority)) => (host, authority),
+            _ => return Err(anyhow!("no host in scheduler URI {}", uri)),
+        };
+        let connector = ResumingConnector::new(tls, host)?;
+        // The connector does the TLS handshake, see `tls::resumption`.
+        let uri = format!("http://{}", authority);
+        Ok(endpoint(&uri, timeouts)?.connect_with_connector_lazy(connector))
+    }
+    #[cfg(not(feature = "resumption"))]
+    Ok(endpoint(uri, timeouts)?
+        .tls_config(tls.inner.clone())?
+        .connect_lazy())
+}
+
+fn endpoint(uri: &str, timeouts: &Timeouts) -> Result<tonic::transport::Endpoint> {
+    let mut endpoint = tonic::transport::Endpoint::from_shared(uri.to_string())?
+        .tcp_keepalive(Some(crate::TCP_KEEPALIVE))
+        .http2_keep_alive_interval(crate::TCP_KEEPALIVE)
+        .keep_alive_timeout(crate::TCP_KEEPALIVE_TIMEOUT)
+        .keep_alive_while_idle(true);
+    if let Some(t) = timeouts.connect() {
+        endpoint = endpoint.connect_timeout(t);
+    }
+    if let Some(t) = timeouts.request() {
+        endpoint = endpoint.timeout(t);
+    }
+    Ok(endpoint)
+}
+
 impl<Creds> Scheduler<Creds>
 where
     Creds: TlsConfigProvider + RuneProvider + NodeIdProvider + Clone,
@@ -395,13 +545,44 @@ where
     /// # }
     /// ```
     pub async fn schedule(&self) -> Result<pb::scheduler::NodeInfoResponse> {
+        let node_id = self.creds.node_id()?;
+        self.status.raise(ConnectionStatus::NodeStarting);
         let res = self
-            .client
-            .clone()
-            .schedule(pb::scheduler::ScheduleRequest {
-                node_id: self.creds.node_id()?,
+            .client()?
+            .schedule(pb::scheduler::ScheduleRequest { node_id })
+            .await;
+        match res {
+            Ok(res) => {
+                self.status.raise(ConnectionStatus::NodeReady);
+                Ok(res.into_inner())
+            }
+            Err(e) => {
+                self.status.revert(
+                    ConnectionStatus::NodeStarting,
+                    ConnectionStatus::Disconnected,
+                );
+                Err(e).or_rate_limited()
+            }
+        }
+    }
+
+    /// Have the scheduler start the node `node_id` ahead of its use
+    /// in `eta`, e.g., as soon as the user scanned an invoice, so
+    /// the payment does not wait for the node to boot. Returns once
+    /// the scheduler took note, use [`Self::node`] to connect.
+    pub async fn hint_upcoming_activity(
+        &self,
+        node_id: Vec<u8>,
+        eta: Duration,
+    ) -> Result<pb::greenlight::Empty> {
+        let res = self
+            .client()?
+            .hint_upcoming_activity(pb::scheduler::HintUpcomingActivityRequest {
+                node_id,
+                eta_ms: eta.as_millis() as u64,
             })
-            .await?;
+            .await
+            .or_rate_limited()?;
         Ok(res.into_inner())
     }
 
@@ -427,39 +608,79 @@ where
     where
         T: GrpcClient,
     {
+        let timer = StartupTimer::start();
         let res = self.schedule().await?;
-        node::Node::new(self.creds.node_id()?, self.creds.clone())?
-            .connect(res.grpc_uri)
-            .await
+        self.startup.scheduled(timer);
+        self.node_builder()?.connect(res.grpc_uri).await
+    }
+
+    /// A [`node::Node`] for the node of this scheduler, configured
+    /// like the scheduler itself.
+    pub(crate) fn node_builder(&self) -> Result<node::Node> {
+        let node = node::Node::new(self.creds.node_id()?, self.creds.clone())?
+            .with_shutdown(self.shutdown.clone())
+            .with_interceptors(self.interceptors.clone())
+            .with_compression(self.compression)
+            .with_startup(self.startup.clone());
+        Ok(match &self.recorder {
+            Some(recorder) => node.with_recorder(recorder.clone()),
+            None => node,
+        })
+    }
+
+    /// Schedule the node and open the connection to it in the
+    /// background, e.g., while the application's UI is loading. The
+    /// returned [`Prewarm`] hands out clients once the node is ready.
+    pub fn prewarm(&self) -> Prewarm
+    where
+        Creds: Send + Sync + 'static,
+    {
+        let scheduler = self.clone();
+        let handle = crate::runtime::spawn(async move {
+            let timer = StartupTimer::start();
+            let res = scheduler.schedule().await?;
+            scheduler.startup.scheduled(timer);
+            let channel = scheduler.node_builder()?.channel(res.grpc_uri)?;
+
+            // The channel connects lazily, so issue a cheap call to
+            // open the connection.
+            let mut client = node::ClnClient::new(channel.clone());
+            client
+                .getinfo(pb::cln::GetinfoRequest::default())
+                .await
+                .map_err(|e| anyhow!("node did not respond: {}", e))?;
+            Ok(channel)
+        });
+        Prewarm { handle }
     }
 
     pub async fn get_node_info(&self, wait: bool) -> Result<pb::scheduler::NodeInfoResponse> {
         Ok(self
-            .client
-            .clone()
+            .client()?
             .get_node_info(pb::scheduler::NodeInfoRequest {
                 node_id: self.creds.node_id()?,
                 wait: wait,
             })
-            .await?
+            .await
+            .or_rate_limited()?
             .into_inner())
     }
 
     pub async fn export_node(&self) -> Result<pb::scheduler::ExportNodeResponse> {
         Ok(self
-            .client
-            .clone()
+            .client()?
             .export_node(pb::scheduler::ExportNodeRequest {})
-            .await?
+            .await
+            .or_rate_limited()?
             .into_inner())
     }
 
     pub async fn get_invite_codes(&self) -> Result<pb::scheduler::ListInviteCodesResponse> {
         let res = self
-            .client
-            .clone()
+            .client()?
             .list_invite_codes(pb::scheduler::ListInviteCodesRequest {})
-            .await?;
+            .await
+            .or_rate_limited()?;
         Ok(res.into_inner())
     }
 
@@ -469,10 +690,10 @@ where
     ) -> Result<pb::scheduler::AddOutgoingWebhookResponse> {
         let node_id = self.creds.node_id()?;
         let res = self
-            .client
-            .clone()
+            .client()?
             .add_outgoing_webhook(pb::scheduler::AddOutgoingWebhookRequest { node_id, uri })
-            .await?;
+            .await
+            .or_rate_limited()?;
         Ok(res.into_inner())
     }
 
@@ -481,23 +702,23 @@ where
     ) -> Result<pb::scheduler::ListOutgoingWebhooksResponse> {
         let node_id = self.creds.node_id()?;
         let res = self
-            .client
-            .clone()
+            .client()?
             .list_outgoing_webhooks(pb::scheduler::ListOutgoingWebhooksRequest { node_id })
-            .await?;
+            .await
+            .or_rate_limited()?;
         Ok(res.into_inner())
     }
 
     pub async fn delete_webhooks(&self, webhook_ids: Vec<i64>) -> Result<pb::greenlight::Empty> {
         let node_id = self.creds.node_id()?;
         let res = self
-            .client
-            .clone()
+            .client()?
             .delete_webhooks(pb::scheduler::DeleteOutgoingWebhooksRequest {
                 node_id,
                 ids: webhook_ids,
             })
-            .await?;
+            .await
+            .or_rate_limited()?;
         Ok(res.into_inner())
     }
 
@@ -507,13 +728,267 @@ where
     ) -> Result<pb::scheduler::WebhookSecretResponse> {
         let node_id = self.creds.node_id()?;
         let res = self
-            .client
-            .clone()
+            .client()?
             .rotate_outgoing_webhook_secret(pb::scheduler::RotateOutgoingWebhookSecretRequest {
                 node_id,
                 webhook_id,
             })
-            .await?;
+            .await
+            .or_rate_limited()?;
+        Ok(res.into_inner())
+    }
+
+    /// Have the scheduler call `uri` when the node goes through one
+    /// of `events`, or through any of them if `events` is empty.
+    pub async fn add_lifecycle_webhook(
+        &self,
+        uri: String,
+        events: &[pb::scheduler::NodeLifecycleEvent],
+    ) -> Result<pb::scheduler::AddOutgoingWebhookResponse> {
+        let node_id = self.creds.node_id()?;
+        let res = self
+            .client()?
+            .add_lifecycle_webhook(pb::scheduler::AddLifecycleWebhookRequest {
+                node_id,
+                uri,
+                events: events.iter().map(|e| *e as i32).collect(),
+            })
+            .await
+            .or_rate_limited()?;
+        Ok(res.into_inner())
+    }
+
+    pub async fn list_lifecycle_webhooks(
+        &self,
+    ) -> Result<pb::scheduler::ListLifecycleWebhooksResponse> {
+        let node_id = self.creds.node_id()?;
+        let res = self
+            .client()?
+            .list_lifecycle_webhooks(pb::scheduler::ListLifecycleWebhooksRequest { node_id })
+            .await
+            .or_rate_limited()?;
+        Ok(res.into_inner())
+    }
+
+    pub async fn delete_lifecycle_webhook(&self, id: i64) -> Result<pb::greenlight::Empty> {
+        let node_id = self.creds.node_id()?;
+        let res = self
+            .client()?
+            .delete_lifecycle_webhook(pb::scheduler::DeleteLifecycleWebhookRequest { node_id, id })
+            .await
+            .or_rate_limited()?;
+        Ok(res.into_inner())
+    }
+
+    /// Have the node started at `wake_at`, in seconds since the UNIX
+    /// epoch. See [`crate::wakeup`] to compute the wakeups needed by
+    /// pending payments.
+    pub async fn add_wakeup(
+        &self,
+        wake_at: u64,
+        reason: pb::scheduler::WakeupReason,
+        reference: String,
+    ) -> Result<pb::scheduler::AddWakeupResponse> {
+        let node_id = self.creds.node_id()?;
+        let res = self
+            .client()?
+            .add_wakeup(pb::scheduler::AddWakeupRequest {
+                node_id,
+                wake_at,
+                reason: reason as i32,
+                reference,
+            })
+            .await
+            .or_rate_limited()?;
+        Ok(res.into_inner())
+    }
+
+    pub async fn list_wakeups(&self) -> Result<pb::scheduler::ListWakeupsResponse> {
+        let node_id = self.creds.node_id()?;
+        let res = self
+            .client()?
+            .list_wakeups(pb::scheduler::ListWakeupsRequest { node_id })
+            .await
+            .or_rate_limited()?;
+        Ok(res.into_inner())
+    }
+
+    pub async fn delete_wakeup(&self, id: i64) -> Result<pb::greenlight::Empty> {
+        let node_id = self.creds.node_id()?;
+        let res = self
+            .client()?
+            .delete_wakeup(pb::scheduler::DeleteWakeupRequest { node_id, id })
+            .await
+            .or_rate_limited()?;
+        Ok(res.into_inner())
+    }
+
+    /// Register a push token, so the app is notified of incoming
+    /// payments while the node is offline. See [`crate::push`] to
+    /// decode the notifications.
+    pub async fn register_push_token(
+        &self,
+        platform: pb::scheduler::PushPlatform,
+        token: String,
+    ) -> Result<pb::scheduler::RegisterPushTokenResponse> {
+        let node_id = self.creds.node_id()?;
+        let res = self
+            .client()?
+            .register_push_token(pb::scheduler::RegisterPushTokenRequest {
+                node_id,
+                platform: platform as i32,
+                token,
+            })
+            .await
+            .or_rate_limited()?;
+        Ok(res.into_inner())
+    }
+
+    pub async fn unregister_push_token(&self, id: i64) -> Result<pb::greenlight::Empty> {
+        let node_id = self.creds.node_id()?;
+        let res = self
+            .client()?
+            .unregister_push_token(pb::scheduler::UnregisterPushTokenRequest { node_id, id })
+            .await
+            .or_rate_limited()?;
         Ok(res.into_inner())
     }
+
+    /// The attestations of the signers that attached to the node,
+    /// see [`Signer::with_attestation`]. Attestations that are not
+    /// signed by the node are skipped.
+    #[cfg(feature = "signer")]
+    pub async fn list_signer_attestations(&self) -> Result<Vec<pb::scheduler::SignerAttestation>> {
+        let node_id = self.creds.node_id()?;
+        let res = self
+            .client()?
+            .list_signer_attestations(pb::scheduler::ListSignerAttestationsRequest {
+                node_id: node_id.clone(),
+            })
+            .await
+            .or_rate_limited()?;
+        Ok(res
+            .into_inner()
+            .attestations
+            .into_iter()
+            .filter(|a| match crate::signer::verify_attestation(&node_id, a) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::warn!("Skipping invalid signer attestation: {}", e);
+                    false
+                }
+            })
+            .collect())
+    }
+
+    /// The versions and platform the clients of the node reported,
+    /// and the deployment the node currently runs.
+    pub async fn get_node_metadata(&self) -> Result<pb::scheduler::NodeMetadata> {
+        let node_id = self.creds.node_id()?;
+        let res = self
+            .client()?
+            .get_node_metadata(pb::scheduler::GetNodeMetadataRequest { node_id })
+            .await
+            .or_rate_limited()?;
+        Ok(res.into_inner())
+    }
+
+    /// Update the metadata of the node, leaving the fields that are
+    /// not set in `update` unchanged. Returns the updated metadata.
+    pub async fn update_node_metadata(
+        &self,
+        update: NodeMetadataUpdate,
+    ) -> Result<pb::scheduler::NodeMetadata> {
+        let node_id = self.creds.node_id()?;
+        let res = self
+            .client()?
+            .update_node_metadata(pb::scheduler::UpdateNodeMetadataRequest {
+                node_id,
+                signer_version: update.signer_version,
+                client_version: update.client_version,
+                platform_tags: update
+                    .platform_tags
+                    .map(|tags| pb::scheduler::PlatformTags { tags }),
+            })
+            .await
+            .or_rate_limited()?;
+        Ok(res.into_inner())
+    }
+
+    /// The versions of Core Lightning offered for the node, and the
+    /// version it runs the next time it starts.
+    pub async fn list_cln_versions(&self) -> Result<pb::scheduler::ListClnVersionsResponse> {
+        let node_id = self.creds.node_id()?;
+        let res = self
+            .client()?
+            .list_cln_versions(pb::scheduler::ListClnVersionsRequest { node_id })
+            .await
+            .or_rate_limited()?;
+        Ok(res.into_inner())
+    }
+
+    /// Pin the node to `version`, or unpin it with `None`. Fails with
+    /// [`VersionNotOffered`](crate::versions::VersionNotOffered) if the
+    /// scheduler does not offer the version.
+    pub async fn pin_cln_version(
+        &self,
+        version: Option<String>,
+    ) -> Result<pb::scheduler::ClnVersionPolicy> {
+        let node_id = self.creds.node_id()?;
+        let version = version.unwrap_or_default();
+        let res = self
+            .client()?
+            .pin_cln_version(pb::scheduler::PinClnVersionRequest {
+                node_id,
+                version: version.clone(),
+            })
+            .await
+            .or_not_offered(&version)?;
+        Ok(res.into_inner())
+    }
+
+    /// Opt the node in to upgrades before they become the default,
+    /// or out of them again.
+    pub async fn set_cln_upgrade_opt_in(
+        &self,
+        opt_in: bool,
+    ) -> Result<pb::scheduler::ClnVersionPolicy> {
+        let node_id = self.creds.node_id()?;
+        let res = self
+            .client()?
+            .set_cln_upgrade_opt_in(pb::scheduler::SetClnUpgradeOptInRequest { node_id, opt_in })
+            .await
+            .or_rate_limited()?;
+        Ok(res.into_inner())
+    }
+}
+
+/// The changes to the node's metadata, see
+/// [`Scheduler::update_node_metadata`].
+#[derive(Clone, Debug, Default)]
+pub struct NodeMetadataUpdate {
+    pub signer_version: Option<String>,
+    pub client_version: Option<String>,
+    /// Replaces the platform tags, an empty list removes them.
+    pub platform_tags: Option<Vec<String>>,
+}
+
+/// A node being scheduled in the background, see
+/// [`Scheduler::prewarm`].
+pub struct Prewarm {
+    handle: tokio::task::JoinHandle<Result<node::AuthService>>,
+}
+
+impl Prewarm {
+    /// Wait for the node to be ready, and return a client for it.
+    pub async fn node<T>(self) -> Result<T>
+    where
+        T: GrpcClient,
+    {
+        let channel = self
+            .handle
+            .await
+            .map_err(|e| anyhow!("prewarming the node failed: {}", e))??;
+        Ok(T::new_with_inner(channel))
+    }
 }
@@ -0,0 +1,303 @@
+//! Shut down the node clients and signers of an application together.
+//!
+//! Dropping clients while calls are still in flight, or while a
+//! signer is in the middle of a request, depends on the order in which
+//! the components are dropped, which is racy in multi-threaded
+//! applications. Instead, share a [`Shutdown`] between the components,
+//! see [`Node::with_shutdown`] and [`Shutdown::signer`], and call
+//! [`Shutdown::shutdown`] before dropping them:
+//!
+//! 1. New calls are refused.
+//! 2. Calls in flight get until the deadline to complete, and are
+//!    cancelled after that.
+//! 3. Open streams, e.g., `stream_log`, are closed.
+//! 4. Signers are stopped, and get until the deadline to exit.
+//!
+//! [`Node::with_shutdown`]: crate::node::Node::with_shutdown
+use http_body::Body;
+use log::{debug, warn};
+use serde::Serialize;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::{mpsc, watch, Notify};
+use tokio::time::{timeout_at, Duration, Instant};
+use tonic::codegen::StdError;
+
+#[derive(thiserror::Error, Debug)]
+#[error("the client is shutting down")]
+pub struct ShuttingDown;
+
+/// What [`Shutdown::shutdown`] had to cut short.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ShutdownReport {
+    /// Calls that did not complete before the deadline.
+    pub cancelled_calls: usize,
+    /// Streams and responses that were still being read.
+    pub closed_streams: usize,
+    /// Signers that did not exit before the deadline.
+    pub stuck_signers: usize,
+}
+
+impl ShutdownReport {
+    /// Whether everything shut down before the deadline.
+    pub fn is_clean(&self) -> bool {
+        self.cancelled_calls == 0 && self.stuck_signers == 0
+    }
+}
+
+/// Coordinates the shutdown of the components it is shared with.
+///
+/// Cloning is cheap, and all clones refer to the same components.
+#[derive(Clone)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    closed: AtomicBool,
+    calls: AtomicUsize,
+    streams: AtomicUsize,
+    idle: Notify,
+    cancel: watch::Sender<bool>,
+    signers: Mutex<Vec<mpsc::Sender<()>>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (cancel, _) = watch::channel(false);
+        Shutdown {
+            inner: Arc::new(Inner {
+                closed: AtomicBool::new(false),
+                calls: AtomicUsize::new(0),
+                streams: AtomicUsize::new(0),
+                idle: Notify::new(),
+                cancel,
+                signers: Mutex::new(vec![]),
+            }),
+        }
+    }
+
+    /// A shutdown signal for [`Signer::run_forever`]. The signer is
+    /// considered detached once it drops the receiver.
+    ///
+    /// [`Signer::run_forever`]: crate::signer::Signer::run_forever
+    pub fn signer(&self) -> mpsc::Receiver<()> {
+        let (tx, rx) = mpsc::channel(1);
+        self.inner.signers.lock().unwrap().push(tx);
+        rx
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.inner.closed.load(Ordering::SeqCst)
+    }
+
+    /// Shut down all components, giving them until `deadline` to
+    /// complete what they are doing.
+    pub async fn shutdown(&self, deadline: Duration) -> ShutdownReport {
+        let deadline = Instant::now() + deadline;
+        let mut report = ShutdownReport::default();
+        self.inner.closed.store(true, Ordering::SeqCst);
+
+        debug!("Waiting for calls in flight to complete");
+        let drained = timeout_at(deadline, async {
+            loop {
+                let idle = self.inner.idle.notified();
+                if self.inner.calls.load(Ordering::SeqCst) == 0 {
+                    break;
+                }
+                idle.await;
+            }
+        })
+        .await;
+        if drained.is_err() {
+            report.cancelled_calls = self.inner.calls.load(Ordering::SeqCst);
+            warn!("Cancelling {} calls in flight", report.cancelled_calls);
+        }
+        report.closed_streams = self.inner.streams.load(Ordering::SeqCst);
+        self.inner.cancel.send_replace(true);
+
+        let signers: Vec<_> = self.inner.signers.lock().unwrap().drain(..).collect();
+        for signer in signers {
+            // The signer stopped already if the send fails.
+            if signer.try_send(()).is_ok() && timeout_at(deadline, signer.closed()).await.is_err() {
+                report.stuck_signers += 1;
+            }
+        }
+        if report.stuck_signers > 0 {
+            warn!("{} signers did not stop in time", report.stuck_signers);
+        }
+        report
+    }
+
+    /// Track a call, refusing it if we are shutting down.
+    pub(crate) fn call(&self) -> Result<CallGuard, ShuttingDown> {
+        if self.is_shutting_down() {
+            return Err(ShuttingDown);
+        }
+        self.inner.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(CallGuard {
+            shutdown: self.clone(),
+        })
+    }
+
+    /// Resolves once calls and streams must be cancelled.
+    pub(crate) fn cancelled(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut rx = self.inner.cancel.subscribe();
+        async move {
+            // An error means the sender is gone, and nobody can shut
+            // us down anymore.
+            if rx.wait_for(|c| *c).await.is_err() {
+                futures::future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// Wrap a response body so it is closed on shutdown.
+    pub(crate) fn body<B>(&self, inner: B) -> ShutdownBody<B> {
+        self.inner.streams.fetch_add(1, Ordering::SeqCst);
+        ShutdownBody {
+            inner,
+            cancelled: Some(Box::pin(self.cancelled())),
+            shutdown: self.clone(),
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub(crate) struct CallGuard {
+    shutdown: Shutdown,
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        if self.shutdown.inner.calls.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.shutdown.inner.idle.notify_waiters();
+        }
+    }
+}
+
+/// A response body that fails with [`ShuttingDown`] once the
+/// [`Shutdown`] cancels it.
+pub struct ShutdownBody<B> {
+    inner: B,
+    cancelled: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    shutdown: Shutdown,
+}
+
+impl<B> ShutdownBody<B> {
+    /// Whether the body was cancelled, reporting it the first time.
+    fn poll_cancelled(&mut self, cx: &mut Context<'_>) -> Option<bool> {
+        match &mut self.cancelled {
+            None => None,
+            Some(c) => match c.as_mut().poll(cx) {
+                Poll::Ready(()) => {
+                    self.cancelled = None;
+                    Some(true)
+                }
+                Poll::Pending => Some(false),
+            },
+        }
+    }
+}
+
+impl<B> Drop for ShutdownBody<B> {
+    fn drop(&mut self) {
+        self.shutdown.inner.streams.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<B> Body for ShutdownBody<B>
+where
+    B: Body + Unpin,
+    B::Error: Into<StdError>,
+{
+    type Data = B::Data;
+    type Error = StdError;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.poll_cancelled(cx) {
+            Some(true) => return Poll::Ready(Some(Err(ShuttingDown.into()))),
+            None => return Poll::Ready(None),
+            Some(false) => {}
+        }
+        Pin::new(&mut self.inner).poll_data(cx).map_err(Into::into)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        if self.cancelled.is_none() {
+            return Poll::Ready(Err(ShuttingDown.into()));
+        }
+        Pin::new(&mut self.inner)
+            .poll_trailers(cx)
+            .map_err(Into::into)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.cancelled.is_none() || self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let shutdown = Shutdown::new();
+        let call = shutdown.call().unwrap();
+        let mut signer = shutdown.signer();
+        let stream = shutdown.body(tonic::transport::Body::empty());
+
+        // The call completes while draining, and the signer exits
+        // when told to.
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            drop(call);
+        });
+        tokio::spawn(async move {
+            signer.recv().await;
+        });
+        let report = shutdown.shutdown(Duration::from_secs(5)).await;
+        assert_eq!(
+            report,
+            ShutdownReport {
+                cancelled_calls: 0,
+                closed_streams: 1,
+                stuck_signers: 0,
+            }
+        );
+        assert!(report.is_clean());
+        assert!(shutdown.call().is_err());
+        drop(stream);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_deadline() {
+        let shutdown = Shutdown::new();
+        let _call = shutdown.call().unwrap();
+        let _signer = shutdown.signer();
+
+        let report = shutdown.shutdown(Duration::from_millis(20)).await;
+        assert_eq!(report.cancelled_calls, 1);
+        assert_eq!(report.stuck_signers, 1);
+        assert!(!report.is_clean());
+    }
+}
@@ -0,0 +1,121 @@
+//! Attestations of the software instance a signer runs in.
+//!
+//! When the application enables them, the signer includes an
+//! attestation in the `UpgradeRequest` it sends before attaching to
+//! the node. It names the application version and platform, and the
+//! hash of the signer policy, and is signed with the node key. Other
+//! devices of the same user can list them on the scheduler, and
+//! check which software instances are co-signing for the node.
+use super::Signer;
+use crate::bitcoin::hashes::{sha256, Hash};
+use crate::bitcoin::secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use crate::bitcoin::secp256k1::{Message, PublicKey, Secp256k1};
+use crate::derivation;
+use crate::pb::scheduler::SignerAttestation;
+use anyhow::{anyhow, Result};
+use std::time::SystemTime;
+
+/// What the application tells about itself in the attestations.
+#[derive(Clone, Debug)]
+pub(super) struct AppInfo {
+    version: String,
+    platform: String,
+}
+
+impl Signer {
+    /// Include an attestation naming the application `app_version`
+    /// running on `platform` when attaching to the node.
+    pub fn with_attestation(mut self, app_version: &str, platform: &str) -> Self {
+        self.app = Some(AppInfo {
+            version: app_version.to_string(),
+            platform: platform.to_string(),
+        });
+        self
+    }
+
+    /// A freshly signed attestation, or `None` if attestations are not
+    /// enabled, see [`Signer::with_attestation`].
+    pub fn attestation(&self) -> Result<Option<SignerAttestation>> {
+        let app = match &self.app {
+            Some(app) => app,
+            None => return Ok(None),
+        };
+        let policy = serde_json::to_vec(&self.signer_policy())?;
+        let mut attestation = SignerAttestation {
+            app_version: app.version.clone(),
+            platform: app.platform.clone(),
+            signer_version: self.version().to_string(),
+            policy_hash: sha256::Hash::hash(&policy).into_inner().to_vec(),
+            timestamp: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_secs(),
+            signature: vec![],
+        };
+        let (mut sig, recovery_id) = self.sign_message(message(&attestation))?;
+        sig.push(recovery_id);
+        attestation.signature = sig;
+        Ok(Some(attestation))
+    }
+}
+
+/// Check that `attestation` was signed by the node `node_id`.
+pub fn verify_attestation(node_id: &[u8], attestation: &SignerAttestation) -> Result<()> {
+    let signer = recover_signer(&message(attestation), &attestation.signature)?;
+    if signer != PublicKey::from_slice(node_id)? {
+        return Err(anyhow!("attestation is not signed by the node"));
+    }
+    Ok(())
+}
+
+/// Recover the key that signed `msg` with [`Signer::sign_message`],
+/// from the signature followed by the recovery id.
+pub(super) fn recover_signer(msg: &[u8], sig: &[u8]) -> Result<PublicKey> {
+    if sig.len() != 65 {
+        return Err(anyhow!("signature is not 65 bytes long"));
+    }
+    let sig =
+        RecoverableSignature::from_compact(&sig[..64], RecoveryId::from_i32(sig[64] as i32)?)?;
+    let hash = derivation::challenge_hash(msg);
+    Ok(Secp256k1::verification_only().recover_ecdsa(&Message::from_slice(&hash)?, &sig)?)
+}
+
+/// The signed message: the fields of the attestation, each prefixed
+/// with its big-endian `u32` length.
+fn message(attestation: &SignerAttestation) -> Vec<u8> {
+    let timestamp = attestation.timestamp.to_be_bytes();
+    let fields: [&[u8]; 5] = [
+        attestation.app_version.as_bytes(),
+        attestation.platform.as_bytes(),
+        attestation.signer_version.as_bytes(),
+        &attestation.policy_hash,
+        &timestamp,
+    ];
+    let mut msg = vec![];
+    for field in fields.iter() {
+        msg.extend((field.len() as u32).to_be_bytes());
+        msg.extend(*field);
+    }
+    msg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::Network;
+    use crate::credentials;
+
+    #[test]
+    fn test_attestation() {
+        let signer =
+            Signer::new(vec![0u8; 32], Network::Bitcoin, credentials::Nobody::new()).unwrap();
+        assert!(signer.attestation().unwrap().is_none());
+
+        let signer = signer.with_attestation("1.2.3", "android");
+        let mut attestation = signer.attestation().unwrap().unwrap();
+        assert_eq!(attestation.platform, "android");
+        verify_attestation(&signer.node_id(), &attestation).unwrap();
+
+        attestation.app_version = "6.6.6".to_string();
+        assert!(verify_attestation(&signer.node_id(), &attestation).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+//! Audit log of decisions taken by the signer.
+//!
+//! The signer refuses requests that violate its policies, but the
+//! node only sees a failed signature. The audit log keeps a record of
+//! these decisions on the signer side, so the user can find out why
+//! an operation failed, and notice if the node is misbehaving.
+use super::SignerPolicy;
+use crate::runes::RuneUsage;
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Number of entries kept before the oldest ones are dropped.
+const CAPACITY: usize = 1000;
+
+/// Number of lines appended to a persisted log before it is rewritten
+/// with only the current entries and rune usage.
+const COMPACT_AFTER: usize = 4 * CAPACITY;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum AuditEvent {
+    /// A request from the node violated a policy and was not signed.
+    PolicyViolation {
+        request_id: u32,
+        /// The type of the `hsmd` message that was refused.
+        message_type: u16,
+        reason: String,
+    },
+
+    /// The signer policy was replaced at runtime.
+    PolicyChanged {
+        previous: Box<SignerPolicy>,
+        current: Box<SignerPolicy>,
+    },
+
+    /// The application denied signing for a call at the signing
+    /// gate, see [`super::Signer::with_signing_gate`].
+    GateDenied { uri: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Seconds since the UNIX epoch.
+    pub timestamp: u64,
+    pub event: AuditEvent,
+}
+
+/// A bounded audit log, kept in memory, or persisted to a file with
+/// [`AuditLog::open`]. Clones share the same entries.
+///
+/// Next to the entries the log counts the uses of runes per method,
+/// see [`AuditLog::rune_usage`].
+#[derive(Clone, Debug, Default)]
+pub struct AuditLog {
+    entries: Arc<Mutex<VecDeque<AuditEntry>>>,
+    usage: Arc<Mutex<BTreeMap<(String, String), RuneUsage>>>,
+    /// The calls whose rune use was counted already, since a call is
+    /// attached to every signature request it causes.
+    counted: Arc<Mutex<VecDeque<[u8; 32]>>>,
+    file: Option<Arc<Mutex<AuditFile>>>,
+}
+
+/// The file a log is persisted to, one JSON encoded [`Record`] per
+/// line.
+#[derive(Debug)]
+struct AuditFile {
+    path: PathBuf,
+    /// Lines appended since the file was last rewritten.
+    appended: usize,
+}
+
+/// A line of a persisted log. Later usage records for the same rune
+/// and method replace earlier ones.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Record {
+    Entry(AuditEntry),
+    Usage(RuneUsage),
+}
+
+impl AuditLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open the log persisted at `path`, or start a new one there if
+    /// it does not exist. Entries and rune uses are appended to the
+    /// file as they are recorded.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let data = match std::fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("reading audit log {}", path.display()))
+            }
+        };
+        let mut entries = VecDeque::new();
+        let mut usage = BTreeMap::new();
+        for line in data.lines().filter(|l| !l.trim().is_empty()) {
+            match serde_json::from_str(line)
+                .with_context(|| format!("decoding audit log {}", path.display()))?
+            {
+                Record::Entry(e) => {
+                    if entries.len() >= CAPACITY {
+                        entries.pop_front();
+                    }
+                    entries.push_back(e);
+                }
+                Record::Usage(u) => {
+                    usage.insert((u.unique_id.clone(), u.method.clone()), u);
+                }
+            }
+        }
+        let log = AuditLog {
+            entries: Arc::new(Mutex::new(entries)),
+            usage: Arc::new(Mutex::new(usage)),
+            counted: Arc::default(),
+            file: Some(Arc::new(Mutex::new(AuditFile { path, appended: 0 }))),
+        };
+        // Drop the entries and usage records that were replaced.
+        log.compact()?;
+        Ok(log)
+    }
+
+    /// Rewrite the file with the current entries and rune usage.
+    fn compact(&self) -> Result<()> {
+        let mut file = match &self.file {
+            Some(file) => file.lock().unwrap(),
+            None => return Ok(()),
+        };
+        let mut data = vec![];
+        let records = self
+            .entries()
+            .into_iter()
+            .map(Record::Entry)
+            .chain(self.rune_usage().into_iter().map(Record::Usage));
+        for r in records {
+            serde_json::to_writer(&mut data, &r)?;
+            data.push(b'\n');
+        }
+        let tmp = file.path.with_extension("tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, &file.path)?;
+        file.appended = 0;
+        Ok(())
+    }
+
+    /// Append `record` to the file, if the log is persisted.
+    fn persist(&self, record: Record) {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return,
+        };
+        let compact = {
+            let mut file = file.lock().unwrap();
+            let res = serde_json::to_vec(&record)
+                .map_err(anyhow::Error::from)
+                .and_then(|mut line| {
+                    line.push(b'\n');
+                    OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&file.path)?
+                        .write_all(&line)?;
+                    Ok(())
+                });
+            if let Err(e) = res {
+                warn!("Could not persist audit log {}: {}", file.path.display(), e);
+            }
+            file.appended += 1;
+            file.appended >= COMPACT_AFTER
+        };
+        if compact {
+            if let Err(e) = self.compact() {
+                warn!("Could not compact audit log: {}", e);
+            }
+        }
+    }
+
+    pub fn record(&self, event: AuditEvent) {
+        warn!("Audit: {:?}", event);
+        let entry = AuditEntry {
+            timestamp: now(),
+            event,
+        };
+
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if entries.len() >= CAPACITY {
+                entries.pop_front();
+            }
+            entries.push_back(entry.clone());
+        }
+        self.persist(Record::Entry(entry));
+    }
+
+    /// The recorded entries, oldest first.
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Count a successful use of the rune `unique_id` for `method` by
+    /// the call identified by `call`, unless it was counted already.
+    pub fn record_rune_use(&self, call: [u8; 32], unique_id: &str, method: &str) {
+        {
+            let mut counted = self.counted.lock().unwrap();
+            if counted.contains(&call) {
+                return;
+            }
+            if counted.len() >= CAPACITY {
+                counted.pop_front();
+            }
+            counted.push_back(call);
+        }
+        let u = {
+            let mut usage = self.usage.lock().unwrap();
+            let u = usage
+                .entry((unique_id.to_string(), method.to_string()))
+                .or_insert_with(|| RuneUsage {
+                    unique_id: unique_id.to_string(),
+                    method: method.to_string(),
+                    count: 0,
+                    last_used: 0,
+                });
+            u.count += 1;
+            u.last_used = now();
+            u.clone()
+        };
+        self.persist(Record::Usage(u));
+    }
+
+    /// Which runes, by unique id, were used for which methods, and
+    /// how often, ordered by unique id and method. Check this before
+    /// revoking a delegated rune to see whether it is still in use.
+    pub fn rune_usage(&self) -> Vec<RuneUsage> {
+        self.usage.lock().unwrap().values().cloned().collect()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded() {
+        let log = AuditLog::new();
+        for i in 0..CAPACITY as u32 + 5 {
+            log.clone().record(AuditEvent::PolicyViolation {
+                request_id: i,
+                message_type: 5,
+                reason: "policy failure".to_string(),
+            });
+        }
+        let entries = log.entries();
+        assert_eq!(entries.len(), CAPACITY);
+        assert!(matches!(
+            entries[0].event,
+            AuditEvent::PolicyViolation { request_id: 5, .. }
+        ));
+    }
+
+    #[test]
+    fn test_rune_usage() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.json");
+        let log = AuditLog::open(&path).unwrap();
+        log.record_rune_use([1; 32], "1", "listfunds");
+        log.clone().record_rune_use([2; 32], "0", "pay");
+        log.record_rune_use([3; 32], "1", "listfunds");
+        // Another signature request for the same call.
+        log.record_rune_use([3; 32], "1", "listfunds");
+        log.record(AuditEvent::GateDenied {
+            uri: "/cln.Node/Pay".to_string(),
+        });
+
+        let usage = log.rune_usage();
+        assert_eq!(usage.len(), 2);
+        assert_eq!((usage[0].unique_id.as_str(), usage[0].count), ("0", 1));
+        assert_eq!((usage[1].method.as_str(), usage[1].count), ("listfunds", 2));
+
+        // Records are appended, and replaced ones dropped on open.
+        let lines = || std::fs::read_to_string(&path).unwrap().lines().count();
+        assert_eq!(lines(), 4);
+        let reopened = AuditLog::open(&path).unwrap();
+        assert_eq!(reopened.rune_usage(), usage);
+        assert_eq!(reopened.entries(), log.entries());
+        assert_eq!(lines(), 3);
+    }
+}
@@ -1,8 +1,7 @@
 //! Utilities used to authorize a signature request based on pending RPCs
-use std::str::FromStr;
-use lightning_signer::invoice::Invoice;
 use vls_protocol_signer::approver::Approval;
 use crate::signer::model::Request;
+use crate::signer::resolve::context;
 use crate::Error;
 
 pub trait Authorizer {
@@ -30,16 +29,10 @@ impl Authorizer for GreenlightAuthorizer {
         &self,
         requests: &Vec<Request>,
     ) -> Result<Vec<Approval>, Error> {
-        let approvals : Vec<_> = requests.iter().flat_map(|request| {
-            match request {
-                Request::GlPay(req) => {
-                    // TODO error handling
-                    Some(Approval::Invoice(Invoice::from_str(&req.bolt11)
-                        .expect("")))
-                }
-                _ => None,
-            }
-        }).collect();
-        Ok(approvals)
+        let ctx: Vec<_> = requests.iter().map(context).collect();
+        gl_signer_core::approvals(&ctx).map_err(|e| {
+            log::warn!("Could not authorize context requests: {}", e);
+            Error::MissingAuthorization
+        })
     }
 }
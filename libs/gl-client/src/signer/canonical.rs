@@ -0,0 +1,359 @@
+//! Canonical encoding of the requests the signer sees.
+//!
+//! Audit logs and approval UIs need to refer to a request in a way
+//! that survives a round-trip through the node, and an upgrade of the
+//! client. The protobuf payload the node forwards is not suitable:
+//! encoders may order fields freely, and repeat or omit default
+//! values. The canonical encoding re-encodes the decoded request, so
+//! it is the same for all equivalent payloads:
+//!
+//! ```text
+//! version (1 byte) || len(method) (2 bytes, BE) || method || body
+//! ```
+//!
+//! where `method` is the gRPC URI of the call and `body` the protobuf
+//! encoding of the message with fields in tag order and default
+//! values omitted. Since fields added in later versions are omitted
+//! while unset, the digest of a request does not change when the
+//! schema grows.
+use super::gate::RequestClass;
+use super::model::{cln, greenlight, Request};
+use crate::bitcoin::hashes::{sha256, Hash};
+use prost::Message;
+
+/// The version of the encoding, bumped if it ever changes.
+pub const VERSION: u8 = 1;
+
+macro_rules! spending {
+    () => {
+        false
+    };
+    (spending) => {
+        true
+    };
+}
+
+macro_rules! class {
+    () => {
+        None
+    };
+    ($class:ident) => {
+        Some(RequestClass::$class)
+    };
+}
+
+/// The calls the signer can decode, with the variant and message of
+/// each, whether it is spending, and its class for the signing gate.
+/// This table is the only list of methods: the capabilities the
+/// signer advertises, the decoding of requests, and the classes the
+/// gate applies to are derived from it.
+macro_rules! methods {
+    ($($(#[$meta:meta])* $variant:ident($msg:ty) => $uri:literal $([$flag:ident])? $(($class:ident))?,)*) => {
+        /// The gRPC URIs of all calls the signer can decode.
+        pub const METHODS: &[&str] = &[$($(#[$meta])* $uri,)*];
+
+        /// The class of a call to the gRPC `uri`, if it has one, see
+        /// [`RequestClass`].
+        pub fn request_class(uri: &str) -> Option<RequestClass> {
+            match uri {
+                $($(#[$meta])* $uri => class!($($class)?),)*
+                _ => None,
+            }
+        }
+
+        impl Request {
+            /// Decode the `payload` of a call to the gRPC `uri`.
+            pub fn decode(uri: &str, payload: &[u8]) -> anyhow::Result<Request> {
+                Ok(match uri {
+                    $($(#[$meta])* $uri => Request::$variant(<$msg>::decode(payload)?),)*
+                    uri => return Err(anyhow::anyhow!("Unknown URI {}, can't decode payload", uri)),
+                })
+            }
+
+            /// The gRPC URI of the call this request belongs to.
+            pub fn method(&self) -> &'static str {
+                match self {
+                    $($(#[$meta])* Request::$variant(_) => $uri,)*
+                }
+            }
+
+            /// Whether the call may move funds out of the node, either
+            /// directly or by pre-approving a later payment.
+            pub fn is_spending(&self) -> bool {
+                match self {
+                    $($(#[$meta])* Request::$variant(_) => spending!($($flag)?),)*
+                }
+            }
+
+            /// The protobuf encoding of the request message.
+            fn encode_body(&self) -> Vec<u8> {
+                match self {
+                    $($(#[$meta])* Request::$variant(r) => r.encode_to_vec(),)*
+                }
+            }
+        }
+    };
+}
+
+methods! {
+    #[cfg(feature = "legacy-proto")]
+    GlGetinfo(greenlight::GetInfoRequest) => "/greenlight.Node/GetInfo",
+    #[cfg(feature = "legacy-proto")]
+    GlStop(greenlight::StopRequest) => "/greenlight.Node/Stop",
+    #[cfg(feature = "legacy-proto")]
+    GlListPeers(greenlight::ListPeersRequest) => "/greenlight.Node/ListPeers",
+    #[cfg(feature = "legacy-proto")]
+    GlDisconnect(greenlight::DisconnectRequest) => "/greenlight.Node/Disconnect",
+    #[cfg(feature = "legacy-proto")]
+    GlNewAddr(greenlight::NewAddrRequest) => "/greenlight.Node/NewAddr",
+    #[cfg(feature = "legacy-proto")]
+    GlListFunds(greenlight::ListFundsRequest) => "/greenlight.Node/ListFunds",
+    #[cfg(feature = "legacy-proto")]
+    GlWithdraw(greenlight::WithdrawRequest) => "/greenlight.Node/Withdraw" [spending] (Withdrawal),
+    #[cfg(feature = "legacy-proto")]
+    GlFundChannel(greenlight::FundChannelRequest) => "/greenlight.Node/FundChannel" [spending] (ChannelOpen),
+    #[cfg(feature = "legacy-proto")]
+    GlCloseChannel(greenlight::CloseChannelRequest) => "/greenlight.Node/CloseChannel" [spending] (ChannelClose),
+    #[cfg(feature = "legacy-proto")]
+    GlCreateInvoice(greenlight::InvoiceRequest) => "/greenlight.Node/CreateInvoice" (Invoice),
+    #[cfg(feature = "legacy-proto")]
+    GlPay(greenlight::PayRequest) => "/greenlight.Node/Pay" [spending] (Payment),
+    #[cfg(feature = "legacy-proto")]
+    GlKeysend(greenlight::KeysendRequest) => "/greenlight.Node/Keysend" [spending] (Payment),
+    #[cfg(feature = "legacy-proto")]
+    GlListPayments(greenlight::ListPaymentsRequest) => "/greenlight.Node/ListPayments",
+    #[cfg(feature = "legacy-proto")]
+    GlListInvoices(greenlight::ListInvoicesRequest) => "/greenlight.Node/ListInvoices",
+    #[cfg(feature = "legacy-proto")]
+    GlConnectPeer(greenlight::ConnectRequest) => "/greenlight.Node/ConnectPeer",
+    GlConfig(greenlight::GlConfig) => "/greenlight.Node/Configure",
+    FundChannelStart(greenlight::FundChannelStartRequest) => "/greenlight.Node/FundChannelStart" [spending] (ChannelOpen),
+    FundChannelComplete(greenlight::FundChannelCompleteRequest) => "/greenlight.Node/FundChannelComplete" [spending] (ChannelOpen),
+    FundChannelCancel(greenlight::FundChannelCancelRequest) => "/greenlight.Node/FundChannelCancel",
+    MultiFundChannel(greenlight::MultiFundChannelRequest) => "/greenlight.Node/MultiFundChannel" [spending] (ChannelOpen),
+    EmergencyRecover(greenlight::EmergencyRecoverRequest) => "/greenlight.Node/EmergencyRecover",
+    RecoverChannel(greenlight::RecoverChannelRequest) => "/greenlight.Node/RecoverChannel",
+    Getinfo(cln::GetinfoRequest) => "/cln.Node/Getinfo",
+    ListPeers(cln::ListpeersRequest) => "/cln.Node/ListPeers",
+    ListFunds(cln::ListfundsRequest) => "/cln.Node/ListFunds",
+    SendPay(cln::SendpayRequest) => "/cln.Node/SendPay" [spending] (Payment),
+    ListChannels(cln::ListchannelsRequest) => "/cln.Node/ListChannels",
+    AddGossip(cln::AddgossipRequest) => "/cln.Node/AddGossip",
+    AutoCleanInvoice(cln::AutocleaninvoiceRequest) => "/cln.Node/AutoCleanInvoice",
+    CheckMessage(cln::CheckmessageRequest) => "/cln.Node/CheckMessage",
+    Close(cln::CloseRequest) => "/cln.Node/Close" [spending] (ChannelClose),
+    Connect(cln::ConnectRequest) => "/cln.Node/ConnectPeer",
+    CreateInvoice(cln::CreateinvoiceRequest) => "/cln.Node/CreateInvoice" (Invoice),
+    Datastore(cln::DatastoreRequest) => "/cln.Node/Datastore",
+    CreateOnion(cln::CreateonionRequest) => "/cln.Node/CreateOnion",
+    DelDatastore(cln::DeldatastoreRequest) => "/cln.Node/DelDatastore",
+    DelExpiredInvoice(cln::DelexpiredinvoiceRequest) => "/cln.Node/DelExpiredInvoice",
+    DelInvoice(cln::DelinvoiceRequest) => "/cln.Node/DelInvoice",
+    Invoice(cln::InvoiceRequest) => "/cln.Node/Invoice" (Invoice),
+    ListDatastore(cln::ListdatastoreRequest) => "/cln.Node/ListDatastore",
+    ListInvoices(cln::ListinvoicesRequest) => "/cln.Node/ListInvoices",
+    SendOnion(cln::SendonionRequest) => "/cln.Node/SendOnion" [spending] (Payment),
+    ListSendPays(cln::ListsendpaysRequest) => "/cln.Node/ListSendPays",
+    ListTransactions(cln::ListtransactionsRequest) => "/cln.Node/ListTransactions",
+    Pay(cln::PayRequest) => "/cln.Node/Pay" [spending] (Payment),
+    PreApproveInvoice(cln::PreapproveinvoiceRequest) => "/cln.Node/PreApproveInvoice" [spending] (Payment),
+    ListNodes(cln::ListnodesRequest) => "/cln.Node/ListNodes",
+    WaitAnyInvoice(cln::WaitanyinvoiceRequest) => "/cln.Node/WaitAnyInvoice",
+    WaitInvoice(cln::WaitinvoiceRequest) => "/cln.Node/WaitInvoice",
+    WaitSendPay(cln::WaitsendpayRequest) => "/cln.Node/WaitSendPay",
+    NewAddr(cln::NewaddrRequest) => "/cln.Node/NewAddr",
+    Withdraw(cln::WithdrawRequest) => "/cln.Node/Withdraw" [spending] (Withdrawal),
+    KeySend(cln::KeysendRequest) => "/cln.Node/KeySend" [spending] (Payment),
+    FundPsbt(cln::FundpsbtRequest) => "/cln.Node/FundPsbt",
+    SendPsbt(cln::SendpsbtRequest) => "/cln.Node/SendPsbt" [spending] (Withdrawal),
+    SignPsbt(cln::SignpsbtRequest) => "/cln.Node/SignPsbt",
+    UtxoPsbt(cln::UtxopsbtRequest) => "/cln.Node/UtxoPsbt",
+    TxDiscard(cln::TxdiscardRequest) => "/cln.Node/TxDiscard",
+    TxPrepare(cln::TxprepareRequest) => "/cln.Node/TxPrepare",
+    TxSend(cln::TxsendRequest) => "/cln.Node/TxSend" [spending] (Withdrawal),
+    Disconnect(cln::DisconnectRequest) => "/cln.Node/Disconnect",
+    Feerates(cln::FeeratesRequest) => "/cln.Node/Feerates",
+    FundChannel(cln::FundchannelRequest) => "/cln.Node/FundChannel" [spending] (ChannelOpen),
+    GetRoute(cln::GetrouteRequest) => "/cln.Node/GetRoute",
+    ListForwards(cln::ListforwardsRequest) => "/cln.Node/ListForwards",
+    ListPays(cln::ListpaysRequest) => "/cln.Node/ListPays",
+    Ping(cln::PingRequest) => "/cln.Node/Ping",
+    SetChannel(cln::SetchannelRequest) => "/cln.Node/SetChannel",
+    SignMessage(cln::SignmessageRequest) => "/cln.Node/SignMessage",
+    FetchInvoice(cln::FetchinvoiceRequest) => "/cln.Node/FetchInvoice",
+    Stop(cln::StopRequest) => "/cln.Node/Stop",
+    ListClosedChannels(cln::ListclosedchannelsRequest) => "/cln.Node/ListClosedChannels",
+    StaticBackup(cln::StaticbackupRequest) => "/cln.Node/StaticBackup",
+    Wait(cln::WaitRequest) => "/cln.Node/Wait",
+    ListPeerChannels(cln::ListpeerchannelsRequest) => "/cln.Node/ListPeerChannels",
+    DecodePay(cln::DecodepayRequest) => "/cln.Node/DecodePay",
+    Decode(cln::DecodeRequest) => "/cln.Node/Decode",
+    PreApproveKeysend(cln::PreapprovekeysendRequest) => "/cln.Node/PreApproveKeysend" [spending] (Payment),
+    SignInvoice(cln::SigninvoiceRequest) => "/cln.Node/SignInvoice" (Invoice),
+}
+
+impl Request {
+    /// The canonical encoding of the request, see
+    /// [`crate::signer::canonical`].
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        encode(self.method(), self.encode_body())
+    }
+
+    /// The SHA256 of the canonical encoding, to refer to the request
+    /// in logs and signed approvals.
+    pub fn digest(&self) -> [u8; 32] {
+        sha256::Hash::hash(&self.canonical_bytes()).into_inner()
+    }
+
+    /// The name of the method, i.e., the last segment of
+    /// [`Request::method`], e.g., `ListPeers`. Rune checks match on
+    /// the lowercase version of this name.
+    pub fn method_name(&self) -> &'static str {
+        method_name(self.method())
+    }
+
+    /// Decode the `payload` of a call to the method `name`, which is
+    /// either a full gRPC URI or a method name as returned by
+    /// [`Request::method_name`]. Names are compared ignoring case, and
+    /// prefer the `cln.Node` method when both services have one.
+    pub fn from_method(name: &str, payload: &[u8]) -> anyhow::Result<Request> {
+        let uri = if name.starts_with('/') {
+            name
+        } else {
+            METHODS
+                .iter()
+                .copied()
+                .filter(|uri| method_name(uri).eq_ignore_ascii_case(name))
+                .min_by_key(|uri| !uri.starts_with("/cln."))
+                .ok_or_else(|| anyhow::anyhow!("unknown method {}", name))?
+        };
+        Request::decode(uri, payload)
+    }
+
+    /// Decode a request from its canonical encoding.
+    pub fn from_canonical_bytes(data: &[u8]) -> anyhow::Result<Request> {
+        let (method, body) = split(data)?;
+        Request::decode(method, body)
+    }
+}
+
+/// The canonical encoding of `response` to a call of `method`.
+pub fn canonical_response<M: Message>(method: &str, response: &M) -> Vec<u8> {
+    encode(method, response.encode_to_vec())
+}
+
+/// The SHA256 of [`canonical_response`].
+pub fn response_digest<M: Message>(method: &str, response: &M) -> [u8; 32] {
+    sha256::Hash::hash(&canonical_response(method, response)).into_inner()
+}
+
+/// The method name part of a gRPC `uri`.
+pub fn method_name(uri: &str) -> &str {
+    uri.rsplit('/').next().unwrap_or(uri)
+}
+
+fn encode(method: &str, body: Vec<u8>) -> Vec<u8> {
+    let mut data = Vec::with_capacity(3 + method.len() + body.len());
+    data.push(VERSION);
+    data.extend_from_slice(&(method.len() as u16).to_be_bytes());
+    data.extend_from_slice(method.as_bytes());
+    data.extend(body);
+    data
+}
+
+fn split(data: &[u8]) -> anyhow::Result<(&str, &[u8])> {
+    if data.len() < 3 || data[0] != VERSION {
+        return Err(anyhow::anyhow!("not a canonical request encoding"));
+    }
+    let len = u16::from_be_bytes([data[1], data[2]]) as usize;
+    let rest = &data[3..];
+    if rest.len() < len {
+        return Err(anyhow::anyhow!("truncated canonical request encoding"));
+    }
+    Ok((std::str::from_utf8(&rest[..len])?, &rest[len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::cln::{Amount, PayRequest};
+
+    #[test]
+    fn test_canonical() {
+        let pay = PayRequest {
+            bolt11: "lnbc1".to_string(),
+            amount_msat: Some(Amount { msat: 1000 }),
+            ..Default::default()
+        };
+        let body = pay.encode_to_vec();
+
+        // The same message with the fields in reverse order, and a
+        // field repeated, is the same request.
+        let mut reordered = Amount { msat: 1000 }.encode_length_delimited_to_vec();
+        reordered.insert(0, 13 << 3 | 2);
+        reordered.extend_from_slice(&[1 << 3 | 2, 3, b'f', b'o', b'o']);
+        reordered.extend_from_slice(&body[..7]);
+        assert_eq!(body[..7], [1 << 3 | 2, 5, b'l', b'n', b'b', b'c', b'1']);
+
+        let a = cln::decode_request("/cln.Node/Pay", &body).unwrap();
+        let b = cln::decode_request("/cln.Node/Pay", &reordered).unwrap();
+        assert_eq!(a.method(), "/cln.Node/Pay");
+        assert_eq!(a.canonical_bytes(), b.canonical_bytes());
+        assert_eq!(a.digest(), b.digest());
+
+        let c = Request::from_canonical_bytes(&b.canonical_bytes()).unwrap();
+        assert_eq!(c.digest(), a.digest());
+    }
+
+    #[test]
+    fn test_methods_decode() {
+        // Every method maps back to the variant it came from.
+        for method in METHODS {
+            let req = Request::decode(method, &[]).unwrap();
+            assert_eq!(req.method(), *method);
+        }
+        assert!(Request::from_canonical_bytes(&[2, 0, 0]).is_err());
+        assert!(Request::from_canonical_bytes(&[VERSION, 0, 9, b'/']).is_err());
+    }
+
+    #[test]
+    fn test_request_class() {
+        // Every spending method is gated as something other than
+        // receiving, and no other method is.
+        for method in METHODS {
+            let spending = Request::decode(method, &[]).unwrap().is_spending();
+            let class = request_class(method);
+            assert_eq!(
+                spending,
+                class.is_some() && class != Some(RequestClass::Invoice),
+                "{}",
+                method
+            );
+        }
+        assert_eq!(request_class("/cln.Node/SignPsbt"), None);
+        assert_eq!(request_class("/cln.Node/NoSuchMethod"), None);
+    }
+
+    #[test]
+    fn test_method_name() {
+        let pay = Request::from_method("pay", &[]).unwrap();
+        assert_eq!(pay.method(), "/cln.Node/Pay");
+        assert_eq!(pay.method_name(), "Pay");
+        assert!(pay.is_spending());
+
+        #[cfg(feature = "legacy-proto")]
+        {
+            let gl = Request::from_method("/greenlight.Node/Pay", &[]).unwrap();
+            assert_eq!(gl.method_name(), "Pay");
+            assert!(gl.is_spending());
+        }
+
+        // Closing may send the funds to any address.
+        assert!(Request::from_method("close", &[]).unwrap().is_spending());
+
+        let info = Request::from_method("GetInfo", &[]).unwrap();
+        assert_eq!(info.method(), "/cln.Node/Getinfo");
+        assert!(!info.is_spending());
+        assert!(Request::from_method("nosuchmethod", &[]).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+//! Capabilities the signer advertises to the node.
+//!
+//! The node attaches the pending grpc calls to every signature
+//! request, so the signer can verify that the request was caused by
+//! an authorized call. The signer can only verify calls it knows how
+//! to decode, hence it tells the node which ones those are, and the
+//! node leaves out any others. This allows adding new methods to the
+//! node without breaking older signers.
+use crate::pb::SignerCapabilities;
+
+/// The grpc methods the signer can decode into a
+/// [`super::model::Request`], see [`super::canonical::METHODS`].
+pub const REQUEST_URIS: &[&str] = super::canonical::METHODS;
+
+pub fn capabilities(version: &str) -> SignerCapabilities {
+    SignerCapabilities {
+        version: version.to_string(),
+        request_uris: REQUEST_URIS.iter().map(|u| u.to_string()).collect(),
+    }
+}
+
+/// Whether the signer can decode requests for `uri`.
+pub fn supports(uri: &str) -> bool {
+    REQUEST_URIS.contains(&uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::model;
+
+    #[test]
+    fn test_request_uris_decode() {
+        for uri in REQUEST_URIS {
+            assert!(
+                model::cln::decode_request(uri, &[]).is_ok()
+                    || model::greenlight::decode_request(uri, &[]).is_ok(),
+                "{} cannot be decoded",
+                uri
+            );
+        }
+        assert!(!supports("/cln.Node/Unknown"));
+    }
+}
@@ -0,0 +1,157 @@
+//! Watch-only descriptors of the node's onchain wallet.
+//!
+//! `lightningd` derives all wallet addresses, including change, from
+//! the BIP32 base key the signer reports on init, as non-hardened
+//! children `base/i`. Exporting that key as descriptors lets external
+//! wallets discover and watch the onchain funds, without being able
+//! to spend them.
+use super::Signer;
+use crate::bitcoin::secp256k1::PublicKey;
+use crate::bitcoin::util::bip32::{ChainCode, ChildNumber, ExtendedPubKey, Fingerprint};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::convert::TryInto;
+
+const INPUT_CHARSET: &str =
+    "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// The descriptors covering all outputs of the onchain wallet, with
+/// checksums.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct WalletDescriptors {
+    /// The wallet's base key, from which all addresses are derived.
+    pub xpub: String,
+    pub p2wpkh: String,
+    pub p2sh_p2wpkh: String,
+    pub p2tr: String,
+}
+
+impl Signer {
+    /// Export descriptors for the onchain wallet. They contain no
+    /// private keys, and are safe to hand to watch-only wallets.
+    pub fn wallet_descriptors(&self) -> Result<WalletDescriptors> {
+        let xpub = parse_ext_key(&self.bip32_ext_key(), self.network)?.to_string();
+        Ok(WalletDescriptors {
+            p2wpkh: with_checksum(&format!("wpkh({}/*)", xpub)),
+            p2sh_p2wpkh: with_checksum(&format!("sh(wpkh({}/*))", xpub)),
+            p2tr: with_checksum(&format!("tr({}/*)", xpub)),
+            xpub,
+        })
+    }
+}
+
+/// Parse the serialized extended key from the init reply. The signer
+/// is initialized without version bytes, so we cannot rely on them
+/// to decode the key, and use `network` instead.
+fn parse_ext_key(raw: &[u8], network: crate::bitcoin::Network) -> Result<ExtendedPubKey> {
+    if raw.len() != 78 {
+        return Err(anyhow!("extended key must be 78 bytes, got {}", raw.len()));
+    }
+    Ok(ExtendedPubKey {
+        network,
+        depth: raw[4],
+        parent_fingerprint: Fingerprint::from(&raw[5..9]),
+        child_number: ChildNumber::from(u32::from_be_bytes(raw[9..13].try_into()?)),
+        chain_code: ChainCode::from(&raw[13..45]),
+        public_key: PublicKey::from_slice(&raw[45..78])?,
+    })
+}
+
+fn polymod(c: u64, val: u64) -> u64 {
+    let c0 = c >> 35;
+    let mut c = ((c & 0x7ffffffff) << 5) ^ val;
+    for (bit, gen) in [
+        0xf5dee51989,
+        0xa9fdca3312,
+        0x1bab10e32d,
+        0x3706b1677a,
+        0x644d626ffd,
+    ]
+    .iter()
+    .enumerate()
+    {
+        if c0 & (1 << bit) != 0 {
+            c ^= gen;
+        }
+    }
+    c
+}
+
+/// Append the BIP380 checksum to `desc`.
+fn with_checksum(desc: &str) -> String {
+    let mut c = 1;
+    let mut cls = 0;
+    let mut clscount = 0;
+    for ch in desc.chars() {
+        // Our descriptors only contain characters from the charset.
+        let pos = INPUT_CHARSET
+            .find(ch)
+            .expect("invalid descriptor character") as u64;
+        c = polymod(c, pos & 31);
+        cls = cls * 3 + (pos >> 5);
+        clscount += 1;
+        if clscount == 3 {
+            c = polymod(c, cls);
+            cls = 0;
+            clscount = 0;
+        }
+    }
+    if clscount > 0 {
+        c = polymod(c, cls);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+
+    let checksum: String = (0..8)
+        .map(|j| CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char)
+        .collect();
+    format!("{}#{}", desc, checksum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::secp256k1::Secp256k1;
+    use crate::bitcoin::{Address, Network};
+    use crate::credentials;
+
+    #[test]
+    fn test_checksum() {
+        // Test vector from BIP380.
+        assert_eq!(with_checksum("raw(deadbeef)"), "raw(deadbeef)#89f8spxm");
+    }
+
+    #[test]
+    fn test_wallet_descriptors() {
+        let signer = Signer::new(
+            vec![0; 32],
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .unwrap();
+        let desc = signer.wallet_descriptors().unwrap();
+        assert!(desc.xpub.starts_with("xpub"));
+        assert!(desc.p2wpkh.starts_with(&format!("wpkh({}/*)#", desc.xpub)));
+        assert!(desc.p2tr.starts_with(&format!("tr({}/*)#", desc.xpub)));
+
+        // Addresses derived from the descriptor match the wallet's.
+        use lightning_signer::wallet::Wallet;
+        use vls_protocol_signer::handler::Handler;
+        let node = signer.handler().unwrap().node().clone();
+        let xpub: ExtendedPubKey = desc.xpub.parse().unwrap();
+        for index in [0, 7] {
+            let child = xpub
+                .ckd_pub(&Secp256k1::new(), ChildNumber::Normal { index })
+                .unwrap();
+            let addr = Address::p2wpkh(
+                &crate::bitcoin::PublicKey::new(child.public_key),
+                Network::Bitcoin,
+            )
+            .unwrap();
+            assert_eq!(addr, node.get_native_address(&[index]).unwrap());
+        }
+    }
+}
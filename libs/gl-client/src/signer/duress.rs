@@ -0,0 +1,261 @@
+//! Duress mode for the signer.
+//!
+//! A user forced to unlock their wallet can enter a secondary duress
+//! passphrase instead of the real one. [`Signer::unlock`] then starts a
+//! decoy signer: its seed is derived from the real one, so it signs
+//! for a separate node the user funded with a small balance up front,
+//! and its policy rejects anything but small operations. To someone
+//! watching, the decoy looks like a regular, if modest, wallet.
+use super::{Signer, SignerPolicy};
+use crate::credentials::TlsConfigProvider;
+use crate::derivation;
+use crate::invoice_pool::OfflineLimits;
+use crate::secret::SecretBytes;
+use anyhow::{anyhow, Result};
+use lightning_signer::bitcoin::Network;
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU32;
+use zeroize::Zeroizing;
+
+/// The passphrase is stretched, so the stored configuration does not
+/// lend itself to brute-forcing it.
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// The real and duress passphrases and the limits of the decoy
+/// signer. Contains salted hashes of the passphrases only, so it can
+/// be stored next to the wallet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DuressConfig {
+    salt: Vec<u8>,
+    passphrase_hash: Vec<u8>,
+    /// The hash of the real passphrase, so that mistyped passphrases
+    /// are rejected rather than unlocking the real signer.
+    unlock_hash: Vec<u8>,
+    /// The policy the decoy signer starts with. Its limits cannot be
+    /// raised later on, see [`Signer::update_policy`].
+    pub policy: SignerPolicy,
+}
+
+impl DuressConfig {
+    /// Configure `passphrase` as the real passphrase, and
+    /// `duress_passphrase` as the duress passphrase, with the default
+    /// decoy policy.
+    pub fn new(passphrase: &str, duress_passphrase: &str) -> Result<Self> {
+        if passphrase == duress_passphrase {
+            return Err(anyhow!(
+                "the duress passphrase must differ from the real one"
+            ));
+        }
+        let mut salt = vec![0u8; 16];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .map_err(|_| anyhow!("could not generate salt"))?;
+        Ok(DuressConfig {
+            passphrase_hash: hash(&salt, duress_passphrase).to_vec(),
+            unlock_hash: hash(&salt, passphrase).to_vec(),
+            salt,
+            policy: SignerPolicy::decoy(),
+        })
+    }
+
+    pub fn with_policy(mut self, policy: SignerPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Whether `passphrase` is the duress passphrase.
+    pub fn is_duress(&self, passphrase: &str) -> bool {
+        verify(&self.salt, passphrase, &self.passphrase_hash)
+    }
+
+    /// Whether `passphrase` is the real passphrase.
+    pub fn is_real(&self, passphrase: &str) -> bool {
+        verify(&self.salt, passphrase, &self.unlock_hash)
+    }
+}
+
+fn iterations() -> NonZeroU32 {
+    NonZeroU32::new(PBKDF2_ITERATIONS).unwrap()
+}
+
+fn verify(salt: &[u8], passphrase: &str, hash: &[u8]) -> bool {
+    pbkdf2::verify(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations(),
+        salt,
+        passphrase.as_bytes(),
+        hash,
+    )
+    .is_ok()
+}
+
+fn hash(salt: &[u8], passphrase: &str) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations(),
+        salt,
+        passphrase.as_bytes(),
+        &mut out,
+    );
+    out
+}
+
+impl SignerPolicy {
+    /// Limits suitable for a decoy wallet: small payments and
+    /// channels only.
+    pub fn decoy() -> Self {
+        SignerPolicy {
+            max_routing_fee_msat: 10_000,
+            max_invoices: 1_000,
+            max_htlc_value_sat: Some(50_000),
+            max_channel_size_sat: Some(200_000),
+            ..Default::default()
+        }
+    }
+
+    /// Keep the limits of `self` from exceeding those of `limits`.
+    pub(super) fn clamp(mut self, limits: &SignerPolicy) -> Self {
+        fn min(v: Option<u64>, limit: Option<u64>) -> Option<u64> {
+            match (v, limit) {
+                (Some(v), Some(l)) => Some(v.min(l)),
+                (v, None) => v,
+                (None, l) => l,
+            }
+        }
+        self.max_routing_fee_msat = self.max_routing_fee_msat.min(limits.max_routing_fee_msat);
+        self.max_invoices = self.max_invoices.min(limits.max_invoices);
+        self.max_htlc_value_sat = min(self.max_htlc_value_sat, limits.max_htlc_value_sat);
+        self.max_channel_size_sat = min(self.max_channel_size_sat, limits.max_channel_size_sat);
+        self.offline_limits = match (self.offline_limits, limits.offline_limits) {
+            (Some(v), Some(l)) => Some(OfflineLimits {
+                max_invoice_msat: v.max_invoice_msat.min(l.max_invoice_msat),
+                max_total_msat: v.max_total_msat.min(l.max_total_msat),
+            }),
+            (v, None) => v,
+            (None, l) => l,
+        };
+        self
+    }
+}
+
+impl Signer {
+    /// Create the decoy signer for the real `secret`. `creds` are
+    /// those of the decoy node, see [`Signer::decoy_node_id`].
+    pub fn new_decoy<S, T>(
+        secret: S,
+        config: &DuressConfig,
+        network: Network,
+        creds: T,
+    ) -> Result<Signer>
+    where
+        S: Into<SecretBytes>,
+        T: TlsConfigProvider + 'static,
+    {
+        let mut signer = Signer::new(decoy_seed(secret.into())?, network, creds)?;
+        signer.update_policy(config.policy.clone())?;
+        signer.decoy = Some(config.policy.clone());
+        Ok(signer)
+    }
+
+    /// Create the real signer if `passphrase` is the real passphrase
+    /// of `config`, or the decoy signer if it is the duress
+    /// passphrase. Any other passphrase is rejected. `creds` returns
+    /// the credentials of the real or the decoy node respectively.
+    pub fn unlock<S, T, F>(
+        secret: S,
+        passphrase: &str,
+        config: &DuressConfig,
+        network: Network,
+        creds: F,
+    ) -> Result<Signer>
+    where
+        S: Into<SecretBytes>,
+        T: TlsConfigProvider + 'static,
+        F: FnOnce(bool) -> T,
+    {
+        if config.is_duress(passphrase) {
+            Signer::new_decoy(secret, config, network, creds(true))
+        } else if config.is_real(passphrase) {
+            Signer::new(secret, network, creds(false))
+        } else {
+            Err(anyhow!("wrong passphrase"))
+        }
+    }
+
+    /// The node id of the decoy node for the real `secret`, to
+    /// register it while setting up duress mode.
+    pub fn decoy_node_id<S: Into<SecretBytes>>(secret: S, network: Network) -> Result<Vec<u8>> {
+        let signer = Signer::new(
+            decoy_seed(secret.into())?,
+            network,
+            crate::credentials::Nobody::new(),
+        )?;
+        Ok(signer.node_id())
+    }
+
+    /// Whether this is a decoy signer, created with the duress
+    /// passphrase.
+    pub fn is_decoy(&self) -> bool {
+        self.decoy.is_some()
+    }
+}
+
+fn decoy_seed(secret: SecretBytes) -> Result<SecretBytes> {
+    if secret.len() < 32 {
+        return Err(anyhow!("seed must be at least 32 bytes"));
+    }
+    let mut seed = Zeroizing::new([0u8; 32]);
+    seed.copy_from_slice(&secret[0..32]);
+    Ok(SecretBytes::from(&derivation::decoy_seed(&seed)[..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials;
+
+    #[test]
+    fn test_duress_unlock() {
+        assert!(DuressConfig::new("real", "real").is_err());
+        let config = DuressConfig::new("real", "coerced").unwrap();
+        assert!(config.is_duress("coerced"));
+        assert!(!config.is_duress("real"));
+        assert!(config.is_real("real"));
+
+        let seed = vec![0u8; 32];
+        let signer = Signer::unlock(seed.clone(), "coerced", &config, Network::Bitcoin, |_| {
+            credentials::Nobody::new()
+        })
+        .unwrap();
+        assert!(signer.is_decoy());
+        assert_eq!(
+            signer.node_id(),
+            Signer::decoy_node_id(seed.clone(), Network::Bitcoin).unwrap()
+        );
+
+        let real = Signer::unlock(seed.clone(), "real", &config, Network::Bitcoin, |_| {
+            credentials::Nobody::new()
+        })
+        .unwrap();
+        assert!(!real.is_decoy());
+        assert_ne!(real.node_id(), signer.node_id());
+        // A mistyped passphrase unlocks neither.
+        assert!(
+            Signer::unlock(seed, "reel", &config, Network::Bitcoin, |_| {
+                credentials::Nobody::new()
+            })
+            .is_err()
+        );
+
+        // Decoy limits can be tightened, but not raised.
+        signer.update_policy(SignerPolicy::default()).unwrap();
+        assert_eq!(signer.signer_policy(), SignerPolicy::decoy());
+        let mut stricter = SignerPolicy::decoy();
+        stricter.max_htlc_value_sat = Some(1_000);
+        signer.update_policy(stricter.clone()).unwrap();
+        assert_eq!(signer.signer_policy(), stricter);
+    }
+}
@@ -0,0 +1,79 @@
+//! Entry points for the fuzz targets in `fuzz/`.
+//!
+//! The node is not trusted: it decides which requests the signer
+//! sees, which context is attached to them, and which state the
+//! signer starts from. The targets feed arbitrary [`HsmRequest`]s
+//! through the decoding, the resolver and the policies, which must
+//! reject what they do not understand rather than panic. The tests
+//! below replay the corpus of the targets, so inputs that once
+//! crashed the signer keep being checked.
+use super::{auth::Authorizer, model, Resolver, Signer};
+use crate::credentials::Nobody;
+use crate::pb::HsmRequest;
+use lightning_signer::bitcoin::Network;
+use prost::Message;
+
+/// Decode `data` as a request from the node, along with its context,
+/// and match the request against the context, as if the context was
+/// signed by the user.
+pub fn decode_request(data: &[u8]) {
+    let req = match HsmRequest::decode(data) {
+        Ok(req) => req,
+        Err(_) => return,
+    };
+    let _ = crate::persist::State::from_entries(&req.signer_state);
+    let context: Vec<model::Request> = req
+        .requests
+        .into_iter()
+        .filter_map(|r| super::decode_request(r).ok())
+        .collect();
+    if let Ok(msg) = vls_protocol::msgs::from_vec(req.raw) {
+        let _ = Resolver::try_resolve(&msg, &context, None);
+    }
+    let _ = super::auth::GreenlightAuthorizer {}.authorize(&context);
+}
+
+/// A signer for [`process_request`], the same for every run.
+pub fn signer() -> Signer {
+    Signer::new(vec![1; 32], Network::Regtest, Nobody::default()).unwrap()
+}
+
+/// Process `data` as a request from the node, all the way to the
+/// validator of the signer.
+pub async fn process_request(signer: &Signer, data: &[u8]) {
+    if let Ok(req) = HsmRequest::decode(data) {
+        let _ = signer.process_request(req).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// The inputs of all targets, which all decode an `HsmRequest`.
+    fn corpus() -> Vec<Vec<u8>> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus");
+        let mut inputs = vec![];
+        for target in std::fs::read_dir(dir).unwrap() {
+            for input in std::fs::read_dir(target.unwrap().path()).unwrap() {
+                inputs.push(std::fs::read(input.unwrap().path()).unwrap());
+            }
+        }
+        assert!(!inputs.is_empty());
+        inputs
+    }
+
+    #[test]
+    fn test_decode_request_corpus() {
+        corpus().iter().for_each(|input| decode_request(input));
+    }
+
+    #[tokio::test]
+    async fn test_process_request_corpus() {
+        let signer = signer();
+        for input in corpus() {
+            process_request(&signer, &input).await;
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Authentication gate before signing.
+//!
+//! Wallets often want the user to authenticate, e.g., with a PIN or
+//! biometrics, before funds leave the wallet. The gate asks the
+//! application before the signer handles a request caused by a call
+//! of one of the gated classes. Unlike the approvals, which confirm
+//! individual payments the node did not attach a call for, the gate
+//! applies to every call of a class, authorized or not.
+//!
+//! The node attaches a pending call to every signature request it
+//! causes, so the decision is remembered per call, and the user is
+//! asked only once per call.
+use super::Signer;
+use crate::pb::PendingRequest;
+use futures::future::BoxFuture;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+/// Number of decisions remembered before the oldest are forgotten.
+const DECISION_CAPACITY: usize = 64;
+
+/// The classes of calls the gate may apply to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RequestClass {
+    /// Lightning payments, including keysends.
+    Payment,
+    /// On-chain withdrawals and transactions spending from the
+    /// wallet.
+    Withdrawal,
+    ChannelOpen,
+    ChannelClose,
+    /// Signing invoices, i.e., receiving payments.
+    Invoice,
+}
+
+impl RequestClass {
+    /// The class of the grpc method `uri`, if it has one. The classes
+    /// are part of the table of methods in [`super::canonical`].
+    pub fn of(uri: &str) -> Option<RequestClass> {
+        super::canonical::request_class(uri)
+    }
+}
+
+type Callback = dyn Fn(RequestClass, String) -> BoxFuture<'static, bool> + Send + Sync;
+
+pub(super) struct SigningGate {
+    classes: Vec<RequestClass>,
+    callback: Box<Callback>,
+    /// Decisions by signature of the pending call.
+    decisions: Mutex<VecDeque<(Vec<u8>, bool)>>,
+}
+
+impl SigningGate {
+    /// Ask for the calls in `requests` that are gated, and return
+    /// the method of the first call that was denied.
+    pub(super) async fn check(&self, requests: &[PendingRequest]) -> Result<(), String> {
+        for r in requests {
+            let class = match RequestClass::of(&r.uri) {
+                Some(c) if self.classes.contains(&c) => c,
+                _ => continue,
+            };
+            let allowed = match self.decision(&r.signature) {
+                Some(allowed) => allowed,
+                None => {
+                    let allowed = (self.callback)(class, r.uri.clone()).await;
+                    self.remember(&r.signature, allowed);
+                    allowed
+                }
+            };
+            if !allowed {
+                return Err(r.uri.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn decision(&self, signature: &[u8]) -> Option<bool> {
+        self.decisions
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(s, _)| s == signature)
+            .map(|(_, allowed)| *allowed)
+    }
+
+    fn remember(&self, signature: &[u8], allowed: bool) {
+        let mut decisions = self.decisions.lock().unwrap();
+        if decisions.len() >= DECISION_CAPACITY {
+            decisions.pop_front();
+        }
+        decisions.push_back((signature.to_vec(), allowed));
+    }
+}
+
+impl Signer {
+    /// Ask `callback` before handling requests caused by calls of
+    /// one of `classes`. The callback receives the class and the
+    /// method of the call, and returns whether the signer may go
+    /// ahead, e.g., after the user authenticated.
+    pub fn with_signing_gate<F, Fut>(mut self, classes: &[RequestClass], callback: F) -> Self
+    where
+        F: Fn(RequestClass, String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        self.gate = Some(Arc::new(SigningGate {
+            classes: classes.to_vec(),
+            callback: Box::new(move |class, uri| Box::pin(callback(class, uri))),
+            decisions: Mutex::new(VecDeque::new()),
+        }));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn request(uri: &str, signature: u8) -> PendingRequest {
+        PendingRequest {
+            uri: uri.to_string(),
+            signature: vec![signature],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_request_class() {
+        assert_eq!(
+            RequestClass::of("/cln.Node/Pay"),
+            Some(RequestClass::Payment)
+        );
+        assert_eq!(
+            RequestClass::of("/cln.Node/PreApproveInvoice"),
+            Some(RequestClass::Payment)
+        );
+        assert_eq!(
+            RequestClass::of("/cln.Node/PreApproveKeysend"),
+            Some(RequestClass::Payment)
+        );
+        #[cfg(feature = "legacy-proto")]
+        assert_eq!(
+            RequestClass::of("/greenlight.Node/Withdraw"),
+            Some(RequestClass::Withdrawal)
+        );
+        assert_eq!(RequestClass::of("/cln.Node/SignPsbt"), None);
+        assert_eq!(RequestClass::of("/cln.Node/ListFunds"), None);
+    }
+
+    #[tokio::test]
+    async fn test_signing_gate() {
+        let asked = Arc::new(AtomicUsize::new(0));
+        let counter = asked.clone();
+        let gate = SigningGate {
+            classes: vec![RequestClass::Payment],
+            callback: Box::new(move |_, uri| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async move { uri != "/cln.Node/KeySend" })
+            }),
+            decisions: Mutex::new(VecDeque::new()),
+        };
+
+        // Not gated.
+        gate.check(&[request("/cln.Node/Withdraw", 1)])
+            .await
+            .unwrap();
+        assert_eq!(asked.load(Ordering::SeqCst), 0);
+
+        // Pre-approving a payment is a payment.
+        gate.check(&[request("/cln.Node/PreApproveKeysend", 4)])
+            .await
+            .unwrap();
+        assert_eq!(asked.load(Ordering::SeqCst), 1);
+
+        // Asked once per call.
+        gate.check(&[request("/cln.Node/Pay", 2)]).await.unwrap();
+        gate.check(&[request("/cln.Node/Pay", 2)]).await.unwrap();
+        assert_eq!(asked.load(Ordering::SeqCst), 2);
+
+        assert_eq!(
+            gate.check(&[request("/cln.Node/KeySend", 3)]).await,
+            Err("/cln.Node/KeySend".to_string())
+        );
+    }
+}
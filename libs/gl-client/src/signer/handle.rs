@@ -0,0 +1,105 @@
+//! Run the signer in the background, and control it while it runs.
+//!
+//! [`Signer::start`] spawns the signer loop on the current runtime,
+//! and stops it once the [`CancellationToken`] is cancelled. The
+//! returned [`SignerHandle`] reports the connection status, asks the
+//! signer to reconnect, e.g., after the host switched networks, and
+//! waits for the loop to exit.
+use super::Signer;
+use crate::connection::ConnectionStatus;
+use anyhow::{anyhow, Result};
+use log::debug;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+pub struct SignerHandle {
+    signer: Signer,
+    task: JoinHandle<Result<()>>,
+}
+
+impl Signer {
+    /// Run the signer in the background until `shutdown` is
+    /// cancelled. Must be called from within a tokio runtime.
+    pub fn start(&self, shutdown: CancellationToken) -> SignerHandle {
+        self.start_with_uri(shutdown, crate::utils::scheduler_uri())
+    }
+
+    /// Like [`Signer::start`], connecting to the scheduler at
+    /// `scheduler_uri`.
+    pub fn start_with_uri(
+        &self,
+        shutdown: CancellationToken,
+        scheduler_uri: String,
+    ) -> SignerHandle {
+        let signer = self.clone();
+        let task = tokio::spawn(async move {
+            let (tx, rx) = mpsc::channel(1);
+            let run = signer.run_forever_with_uri(rx, scheduler_uri);
+            tokio::pin!(run);
+            tokio::select! {
+                res = &mut run => res,
+                _ = shutdown.cancelled() => {
+                    debug!("Signer cancelled, waiting for it to stop");
+                    let _ = tx.send(()).await;
+                    run.await
+                }
+            }
+        });
+        SignerHandle {
+            signer: self.clone(),
+            task,
+        }
+    }
+}
+
+impl SignerHandle {
+    /// The status of the connection to the node.
+    pub fn status(&self) -> ConnectionStatus {
+        self.signer.status().current()
+    }
+
+    /// Drop the connection to the node and connect again right away,
+    /// skipping any backoff, see [`Signer::reconnect`].
+    pub fn trigger_reconnect(&self) {
+        self.signer.reconnect();
+    }
+
+    /// Whether the signer loop exited.
+    pub fn is_stopped(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Wait for the signer loop to exit, either because the token was
+    /// cancelled, or because the signer gave up, returning its error.
+    pub async fn await_stopped(self) -> Result<()> {
+        self.task
+            .await
+            .map_err(|e| anyhow!("signer task failed: {}", e))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials::Device;
+    use lightning_signer::bitcoin::Network;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_cancel_while_connecting() {
+        let signer = Signer::new(vec![0; 32], Network::Regtest, Device::default()).unwrap();
+        let shutdown = CancellationToken::new();
+        // Nothing listens there, so the signer keeps trying to connect.
+        let handle = signer.start_with_uri(shutdown.clone(), "https://localhost:1".to_string());
+        assert_eq!(handle.status(), ConnectionStatus::Disconnected);
+        handle.trigger_reconnect();
+        assert!(!handle.is_stopped());
+
+        shutdown.cancel();
+        tokio::time::timeout(Duration::from_secs(5), handle.await_stopped())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -1,29 +1,41 @@
+use crate::connection::{ConnectionStatus, StatusWatch};
 use crate::credentials::{RuneProvider, TlsConfigProvider};
+use crate::derivation;
+use crate::events::{Event, EventBus};
+use crate::health::{Health, HealthReport};
 use crate::pb::scheduler::{scheduler_client::SchedulerClient, NodeInfoRequest, UpgradeRequest};
 /// The core signer system. It runs in a dedicated thread or using the
 /// caller thread, streaming incoming requests, verifying them,
 /// signing if ok, and then shipping the response to the node.
-use crate::pb::{node_client::NodeClient, Empty, HsmRequest, HsmRequestContext, HsmResponse};
+use crate::pb::{
+    node_client::NodeClient, Empty, HsmRequest, HsmRequestClaim, HsmRequestContext, HsmResponse,
+};
+use crate::ratelimit::RateLimited;
 use crate::runes;
+use crate::secret::SecretBytes;
+use crate::signer::policy::ReloadableValidatorFactory;
 use crate::signer::resolve::Resolver;
-use crate::tls::TlsConfig;
 use crate::{node, node::Client};
 use anyhow::{anyhow, Result};
 use base64::engine::general_purpose;
 use base64::Engine;
 use bytes::BufMut;
+use futures::stream::{FuturesUnordered, StreamExt};
+use futures::FutureExt;
 use http::uri::InvalidUri;
 use lightning_signer::bitcoin::hashes::Hash;
-use lightning_signer::bitcoin::secp256k1::PublicKey;
+use lightning_signer::bitcoin::secp256k1::{PublicKey, Secp256k1};
 use lightning_signer::bitcoin::Network;
+use lightning_signer::channel::ChannelId;
+use lightning_signer::invoice::{Invoice, InvoiceAttributes};
 use lightning_signer::node::NodeServices;
-use lightning_signer::policy::filter::FilterRule;
-use lightning_signer::util::crypto_utils;
 use log::{debug, error, info, trace, warn};
 use runeauth::{Condition, Restriction, Rune, RuneError};
 use std::convert::{TryFrom, TryInto};
+use std::panic::AssertUnwindSafe;
+use std::str::FromStr;
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::{Mutex, RwLock};
 use std::time::SystemTime;
 use tokio::sync::mpsc;
 use tokio::time::{sleep, Duration};
@@ -31,28 +43,84 @@ use tonic::transport::{Endpoint, Uri};
 use tonic::{Code, Request};
 use vls_protocol::msgs::{DeBolt, HsmdInitReplyV4};
 use vls_protocol::serde_bolt::Octets;
-use vls_protocol_signer::approver::{Approve, MemoApprover};
+use vls_protocol_signer::approver::{Approval, Approve, MemoApprover};
 use vls_protocol_signer::handler;
 use vls_protocol_signer::handler::Handler;
+use zeroize::Zeroizing;
 
 mod approver;
+mod attestation;
+mod audit;
 mod auth;
+pub mod canonical;
+mod capabilities;
+mod descriptors;
+mod duress;
+#[cfg(any(test, fuzzing))]
+#[doc(hidden)]
+pub mod fuzz;
+mod gate;
+mod handle;
 pub mod model;
+#[cfg(not(cln_trimmed))]
+mod offline;
+mod ownership;
+mod pipeline;
+mod policy;
+mod reconnect;
 mod report;
 mod resolve;
+mod seed;
+mod selftest;
+mod supervisor;
+#[cfg(not(cln_trimmed))]
+mod swap;
+mod telemetry;
+#[cfg(feature = "websocket")]
+mod ws;
+
+pub use attestation::verify_attestation;
+pub use audit::{AuditEntry, AuditEvent, AuditLog};
+pub use descriptors::WalletDescriptors;
+pub use duress::DuressConfig;
+pub use gate::RequestClass;
+pub use handle::SignerHandle;
+pub use ownership::verify_ownership_proof;
+pub use policy::SignerPolicy;
+pub use reconnect::DEFAULT_OFFLINE_THRESHOLD;
+#[cfg(feature = "pkcs11")]
+pub use seed::Pkcs11SeedProvider;
+pub use seed::{CallbackSeedProvider, RawSeed, SeedError, SeedProvider};
+pub use selftest::{CheckResult, SelfTestReport};
+pub use supervisor::{RestartPolicy, SupervisorStatus, Task, TaskState, TaskStatus};
+#[cfg(not(cln_trimmed))]
+pub use swap::MAX_SWAP_FEERATE_SAT_PER_VB;
+pub use telemetry::{
+    RejectionCount, RejectionKind, TelemetryConfig, TelemetryReport, DEFAULT_TELEMETRY_INTERVAL,
+};
+pub use tokio_util::sync::CancellationToken;
 
 const VERSION: &str = "v24.02";
 const GITHASH: &str = env!("GIT_HASH");
 const RUNE_VERSION: &str = "gl0";
-// This is the same derivation key that is used by core lightning itself.
-const RUNE_DERIVATION_SECRET: &str = "gl-commando";
+/// Requests received from the node but not yet answered, before we
+/// stop reading more. See [`pipeline`].
+const MAX_INFLIGHT: usize = 32;
+/// Invoices an operator may have approved at the same time, see
+/// [`Signer::approve_invoice`].
+const MAX_OPERATOR_APPROVALS: usize = 64;
+/// How often to check on a request claimed by another signer, see
+/// [`Signer::claim_request`].
+const CLAIM_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 #[derive(Clone)]
 pub struct Signer {
-    secret: [u8; 32],
+    secret: Zeroizing<[u8; 32]>,
     master_rune: Rune,
     services: NodeServices,
-    tls: TlsConfig,
+    /// Asked for the TLS config whenever the signer connects, rather
+    /// than holding it for its whole lifetime.
+    creds: Arc<dyn TlsConfigProvider>,
     id: Vec<u8>,
 
     /// Cached version of the init response
@@ -60,6 +128,26 @@ pub struct Signer {
 
     network: Network,
     state: Arc<Mutex<crate::persist::State>>,
+    events: EventBus,
+    status: StatusWatch,
+    health: Health,
+    audit: AuditLog,
+    policy: Arc<RwLock<SignerPolicy>>,
+    validator_factory: Arc<ReloadableValidatorFactory>,
+    /// Invoices approved by the operator, see
+    /// [`Signer::approve_invoice`].
+    approved: Arc<Mutex<Vec<String>>>,
+    /// Included in the attestations, see
+    /// [`Signer::with_attestation`].
+    app: Option<attestation::AppInfo>,
+    /// The limits of a decoy signer, see [`Signer::new_decoy`].
+    decoy: Option<SignerPolicy>,
+    /// Asked before signing, see [`Signer::with_signing_gate`].
+    gate: Option<Arc<gate::SigningGate>>,
+    supervisor: supervisor::Supervisor,
+    reconnect: reconnect::Reconnect,
+    /// Counts the rejected requests, see [`Signer::with_telemetry`].
+    telemetry: Option<telemetry::Telemetry>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -91,67 +179,47 @@ pub enum Error {
     #[error("protocol error: {0}")]
     Protocol(#[from] vls_protocol::Error),
 
+    #[cfg(feature = "websocket")]
+    #[error("websocket error: {0}")]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+
     #[error("other: {0}")]
     Other(anyhow::Error),
 }
 
 impl Signer {
-    pub fn new<T>(secret: Vec<u8>, network: Network, creds: T) -> Result<Signer, anyhow::Error>
+    pub fn new<S, T>(secret: S, network: Network, creds: T) -> Result<Signer, anyhow::Error>
     where
-        T: TlsConfigProvider,
+        S: Into<SecretBytes>,
+        T: TlsConfigProvider + 'static,
     {
-        use lightning_signer::policy::{
-            filter::PolicyFilter, simple_validator::SimpleValidatorFactory,
-        };
+        use lightning_signer::policy::simple_validator::SimpleValidatorFactory;
         use lightning_signer::signer::ClockStartingTimeFactory;
         use lightning_signer::util::clock::StandardClock;
 
         info!("Initializing signer for {VERSION} ({GITHASH}) (VLS)");
-        let mut sec: [u8; 32] = [0; 32];
+        let secret: SecretBytes = secret.into();
+        let mut sec = Zeroizing::new([0u8; 32]);
         sec.copy_from_slice(&secret[0..32]);
 
         // The persister takes care of persisting metadata across
         // restarts
         let persister = Arc::new(crate::persist::MemoryPersister::new());
-        let mut policy = lightning_signer::policy::simple_validator::make_simple_policy(network);
-
-        policy.filter = PolicyFilter::default();
-        policy.filter.merge(PolicyFilter {
-            // TODO: Remove once we have fully switched over to zero-fee anchors
-            rules: vec![
-                FilterRule::new_warn("policy-channel-safe-type-anchors"),
-                FilterRule::new_warn("policy-routing-balanced"),
-            ],
-        });
-
-        policy.filter.merge(PolicyFilter {
-            // TODO: Remove once we have implemented zero invoice support
-            rules: vec![
-                FilterRule::new_warn("policy-routing-balanced"),
-                FilterRule::new_warn("policy-htlc-fee-range"),
-            ],
-        });
-
-        // Increase the invoices limit. Results in a larger state, but
-        // bumping into this is rather annoying.
-        policy.max_invoices = 10_000usize;
-
-        // Relaxed max_routing_fee since we no longer have the
-        // presplitter which was causing the HTLCs to be smaller.
-        policy.max_routing_fee_msat = 1_000_000;
-
-        let validator_factory = Arc::new(SimpleValidatorFactory::new_with_policy(policy));
+        let signer_policy = SignerPolicy::default();
+        let validator_factory = Arc::new(ReloadableValidatorFactory::new(
+            SimpleValidatorFactory::new_with_policy(signer_policy.vls_policy(network)),
+        ));
         let starting_time_factory = ClockStartingTimeFactory::new();
         let clock = Arc::new(StandardClock());
 
         let services = NodeServices {
-            validator_factory,
+            validator_factory: validator_factory.clone(),
             starting_time_factory,
             persister: persister.clone(),
             clock,
         };
 
-        let mut handler = handler::HandlerBuilder::new(network, 0 as u64, services.clone(), sec)
+        let mut handler = handler::HandlerBuilder::new(network, 0 as u64, services.clone(), *sec)
             .build()
             .map_err(|e| anyhow!("building root_handler: {:?}", e))?
             .0;
@@ -169,28 +237,167 @@ impl Signer {
 
         // Init master rune. We create the rune seed from the nodes
         // seed by deriving a hardened key tagged with "rune secret".
-        let rune_secret = crypto_utils::hkdf_sha256(&sec, RUNE_DERIVATION_SECRET.as_bytes(), &[]);
-        let mr = Rune::new_master_rune(&rune_secret, vec![], None, Some(RUNE_VERSION.to_string()))?;
+        let rune_secret = derivation::rune_secret(&sec);
+        let mr = Rune::new_master_rune(
+            &rune_secret[..],
+            vec![],
+            None,
+            Some(RUNE_VERSION.to_string()),
+        )?;
 
         trace!("Initialized signer for node_id={}", hex::encode(&id));
         Ok(Signer {
             secret: sec,
             master_rune: mr,
             services,
-            tls: creds.tls_config(),
+            creds: Arc::new(creds),
             id,
             init,
             network,
             state: persister.state(),
+            events: EventBus::new(),
+            status: StatusWatch::new(),
+            health: Health::new(),
+            audit: AuditLog::new(),
+            policy: Arc::new(RwLock::new(signer_policy)),
+            validator_factory,
+            approved: Arc::new(Mutex::new(vec![])),
+            app: None,
+            decoy: None,
+            gate: None,
+            supervisor: supervisor::Supervisor::default(),
+            reconnect: reconnect::Reconnect::default(),
+            telemetry: None,
         })
     }
 
+    /// Approve payments of `bolt11` that the node did not attach a
+    /// matching call for, e.g., when an operator confirms a payment
+    /// out of band. The approval lasts until the invoice expires.
+    pub fn approve_invoice(&self, bolt11: &str) -> Result<(), anyhow::Error> {
+        let invoice = Invoice::from_str(bolt11).map_err(|e| anyhow!("invalid invoice: {:?}", e))?;
+        if invoice_expired(&invoice) {
+            return Err(anyhow!("invoice has expired"));
+        }
+        let mut approved = self.approved.lock().unwrap();
+        if approved.len() >= MAX_OPERATOR_APPROVALS {
+            approved.remove(0);
+        }
+        approved.push(bolt11.to_string());
+        Ok(())
+    }
+
+    /// The operator approvals that have not expired yet.
+    fn operator_approvals(&self) -> Vec<Approval> {
+        let mut approved = self.approved.lock().unwrap();
+        let invoices: Vec<Invoice> = approved
+            .iter()
+            .filter_map(|b| Invoice::from_str(b).ok())
+            .collect();
+        let (live, expired): (Vec<_>, Vec<_>) = approved
+            .drain(..)
+            .zip(invoices)
+            .partition(|(_, i)| !invoice_expired(i));
+        if !expired.is_empty() {
+            debug!("Dropping {} expired operator approvals", expired.len());
+        }
+        let (bolt11s, invoices): (Vec<_>, Vec<_>) = live.into_iter().unzip();
+        *approved = bolt11s;
+        invoices.into_iter().map(Approval::Invoice).collect()
+    }
+
+    /// Revoke `rune` and return a replacement with the same
+    /// restrictions. The replacement is derived from the master rune
+    /// directly, so it cannot be derived from the revoked rune. Only
+    /// the rune itself is revoked, runes previously derived from it
+    /// stay valid.
+    pub fn rotate_rune(&self, rune: &str) -> Result<String, anyhow::Error> {
+        let old = runes::decode_rune(rune)?;
+        if !runes::is_authorized(&self.master_rune, &old) {
+            return Err(anyhow!("rune was not issued by this signer"));
+        }
+
+        // Skip the unique id, the new rune gets its own.
+        let encoded = old.to_string();
+        let rest = encoded.split_once(':').map(|(_, r)| r).unwrap_or_default();
+        let mut restrictions: Vec<Restriction> = runes::decode_restrictions(rest)?
+            .into_iter()
+            .filter(|r| !r.alternatives.iter().all(|a| a.get_field().is_empty()))
+            .collect();
+
+        // The rotation marker goes first, so the old rune is not a
+        // prefix of the new one.
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+        let marker = Restriction::try_from(format!("time>{}", now.as_secs() - 1).as_str())?;
+        restrictions.insert(0, marker);
+        let new = Rune::new(
+            self.master_rune.authcode(),
+            restrictions,
+            Some("0".to_string()),
+            Some(RUNE_VERSION.to_string()),
+        )?;
+
+        let mut policy = self.signer_policy();
+        policy.rune_blacklist.push(hex::encode(old.authcode()));
+        self.update_policy(policy)?;
+        Ok(new.to_base64())
+    }
+
+    /// The policy currently applied by the signer.
+    pub fn signer_policy(&self) -> SignerPolicy {
+        self.policy.read().unwrap().clone()
+    }
+
+    /// Replace the policy of a running signer. The change applies
+    /// atomically: requests being processed complete under the old
+    /// policy, and all later ones use the new one. A decoy signer
+    /// keeps its limits, they can only be tightened.
+    ///
+    /// The addresses of the old policy's allowlist are replaced by
+    /// the new one's, others stay on the node's allowlist, e.g., the
+    /// `close_to_addr` of the node's config.
+    pub fn update_policy(&self, policy: SignerPolicy) -> Result<(), anyhow::Error> {
+        use lightning_signer::policy::simple_validator::SimpleValidatorFactory;
+        let node_id = PublicKey::from_slice(&self.id)?;
+        let policy = match &self.decoy {
+            Some(limits) => policy.clamp(limits),
+            None => policy,
+        };
+
+        let mut current = self.policy.write().unwrap();
+        let persister = &self.services.persister;
+        let mut allowlist = persister
+            .get_node_allowlist(&node_id)
+            .map_err(|e| anyhow!("reading allowlist: {:?}", e))?;
+        allowlist.retain(|a| !current.allowlist.contains(a));
+        for a in policy.allowlist.iter() {
+            if !allowlist.contains(a) {
+                allowlist.push(a.clone());
+            }
+        }
+        persister
+            .update_node_allowlist(&node_id, allowlist)
+            .map_err(|e| anyhow!("updating allowlist: {:?}", e))?;
+        self.validator_factory
+            .replace(SimpleValidatorFactory::new_with_policy(
+                policy.vls_policy(self.network),
+            ));
+
+        info!("Updated signer policy");
+        self.audit.record(AuditEvent::PolicyChanged {
+            previous: Box::new(current.clone()),
+            current: Box::new(policy.clone()),
+        });
+        *current = policy;
+        Ok(())
+    }
+
     fn init_handler(&self) -> Result<handler::InitHandler, anyhow::Error> {
         let h = handler::HandlerBuilder::new(
             self.network,
             0 as u64,
             self.services.clone(),
-            self.secret,
+            *self.secret,
         )
         .build()
         .map_err(|e| anyhow!("building root_handler: {:?}", e))?
@@ -214,7 +421,7 @@ impl Signer {
             self.network,
             0 as u64,
             self.services.clone(),
-            self.secret,
+            *self.secret,
         )
         .approver(approver)
         .build()
@@ -306,7 +513,7 @@ impl Signer {
     /// match the corresponding restrictions of the rune.
     fn verify_rune(&self, request: crate::pb::PendingRequest) -> Result<(), anyhow::Error> {
         let rune64 = general_purpose::URL_SAFE.encode(request.rune);
-        let rune = Rune::from_base64(&rune64)?;
+        let rune = runes::decode_rune(&rune64)?;
 
         // A valid gl-rune must contain a pubkey field as this  is bound to the
         // signer. Against the rules of runes we do not accept a rune that has
@@ -319,10 +526,14 @@ impl Signer {
         // for delegation in the future but we could also set the public 
         // key as the unique_id in the future and add a method that allows
         // to create new empty runes.
-        let unique_id = rune.get_id();
-        let ver_id = match unique_id {
-            Some(id) => format!("{}-{}", id, RUNE_VERSION),
-            None => String::default(),
+        if self.signer_policy().is_blacklisted(&rune.authcode()) {
+            return Err(anyhow!("rune has been revoked"));
+        }
+
+        let unique_id = rune.clone().get_id().unwrap_or_default();
+        let ver_id = match unique_id.as_str() {
+            "" => String::default(),
+            id => format!("{}-{}", id, RUNE_VERSION),
         };
 
         // Check that the request points to `cln.Node`.
@@ -352,25 +563,46 @@ impl Signer {
         };
 
         let ctx = runes::Context {
-            method,
+            method: method.clone(),
             pubkey: hex::encode(request.pubkey),
             time: SystemTime::now(),
             unique_id: ver_id,
         };
 
-        match self.master_rune.check_with_reason(&rune64, ctx) {
-            Ok(_) => Ok(()),
+        if !runes::is_authorized(&self.master_rune, &rune) {
+            return Err(RuneError::Unauthorized.into());
+        }
+        match rune.are_restrictions_met(ctx) {
+            Ok(_) => {
+                // The same call is attached to every signature request
+                // it causes, count it once.
+                let call = [
+                    &request.signature[..],
+                    &request.request[..],
+                    &request.timestamp.to_be_bytes()[..],
+                ]
+                .concat();
+                let call = lightning_signer::bitcoin::hashes::sha256::Hash::hash(&call);
+                self.audit
+                    .record_rune_use(call.into_inner(), &unique_id, &method);
+                Ok(())
+            }
             Err(e) => Err(e.into()),
         }
     }
 
     /// Given the URI of the running node, connect to it and stream
     /// requests from it. The requests are then verified and processed
-    /// using the `Hsmd`.
+    /// using the `Hsmd`. Requests from different hsmd clients are
+    /// handled concurrently, see [`pipeline`].
     pub async fn run_once(&self, node_uri: Uri) -> Result<(), Error> {
         debug!("Connecting to node at {}", node_uri);
+        let tls = self
+            .creds
+            .try_tls_config()
+            .map_err(|e| Error::Other(e.into()))?;
         let c = Endpoint::from_shared(node_uri.to_string())?
-            .tls_config(self.tls.inner.clone().domain_name("localhost"))?
+            .tls_config(tls.inner.domain_name("localhost"))?
             .tcp_keepalive(Some(crate::TCP_KEEPALIVE))
             .http2_keep_alive_interval(crate::TCP_KEEPALIVE)
             .keep_alive_timeout(crate::TCP_KEEPALIVE_TIMEOUT)
@@ -384,44 +616,289 @@ impl Signer {
             .await?
             .into_inner();
 
+        match client
+            .advertise_signer_capabilities(capabilities::capabilities(self.version()))
+            .await
+        {
+            Ok(_) => {}
+            // Older nodes send all requests regardless.
+            Err(e) if e.code() == Code::Unimplemented => {
+                debug!("Node does not support capability advertisement")
+            }
+            Err(e) => return Err(Error::NodeDisconnect(e)),
+        }
+
+        self.status.set(ConnectionStatus::SignerAttached);
+        self.connected(&[Task::Connection, Task::Stream]);
+        let _stream = self.health.stream_opened();
+        self.supervisor.set_state(Task::Stream, TaskState::Running);
+        self.supervisor
+            .set_state(Task::Resolver, TaskState::Running);
+
+        // Identifies this connection when claiming requests, in case
+        // other signers are attached to the same node.
+        let signer_id: [u8; 16] = rand::random();
+
         debug!("Starting to stream signer requests");
+        let mut lanes = pipeline::Lanes::default();
+        let mut inflight = FuturesUnordered::new();
+        let mut stream_ended = false;
         loop {
-            let req = match stream
-                .message()
-                .await
-                .map_err(|e| Error::NodeDisconnect(e))?
-            {
-                Some(r) => r,
-                None => {
-                    warn!("Signer request stream ended, the node shouldn't do this.");
-                    return Ok(());
+            let accepting = !stream_ended && inflight.len() + lanes.queued() < MAX_INFLIGHT;
+            tokio::select! {
+                msg = stream.message(), if accepting => {
+                    match msg.map_err(Error::NodeDisconnect)? {
+                        Some(req) => {
+                            trace!("Received request {}", hex::encode(&req.raw));
+                            if let Some(req) = lanes.push(req) {
+                                inflight.push(self.supervise_request(client.clone(), signer_id, req));
+                            }
+                        }
+                        None => {
+                            warn!("Signer request stream ended, the node shouldn't do this.");
+                            stream_ended = true;
+                        }
+                    }
                 }
-            };
-            let hex_req = hex::encode(&req.raw);
-            let signer_state = req.signer_state.clone();
-            trace!("Received request {}", hex_req);
-
-            match self.process_request(req).await {
-                Ok(response) => {
-                    trace!("Sending response {}", hex::encode(&response.raw));
-                    client
-                        .respond_hsm_request(response)
-                        .await
-                        .map_err(|e| Error::NodeDisconnect(e))?;
+                Some((lane, res)) = inflight.next() => {
+                    res?;
+                    if let Some(req) = lanes.complete(&lane) {
+                        inflight.push(self.supervise_request(client.clone(), signer_id, req));
+                    }
                 }
-                Err(e) => {
-                    warn!(
-                        "Ignoring error {} for request {} with state {:?}",
-                        e, hex_req, signer_state,
-                    )
+                _ = self.reconnect.requested(), if !stream_ended => {
+                    debug!("Detaching from the node to reconnect");
+                    return Ok(());
                 }
+                else => return Ok(()),
+            }
+        }
+    }
+
+    /// Handle a request, and let the [`supervisor`] decide how to
+    /// carry on if its resolver panics. The request is dropped, the
+    /// node retries it once the signer reconnects.
+    async fn supervise_request(
+        &self,
+        client: NodeClient<tonic::transport::Channel>,
+        signer_id: [u8; 16],
+        req: HsmRequest,
+    ) -> (pipeline::Lane, Result<(), Error>) {
+        let lane = pipeline::lane(&req);
+        let request_id = req.request_id;
+        let panic = match AssertUnwindSafe(self.handle_request(client, signer_id, req))
+            .catch_unwind()
+            .await
+        {
+            Ok(res) => return res,
+            Err(panic) => supervisor::panic_message(&*panic),
+        };
+        error!("Resolver panicked on request {}: {}", request_id, panic);
+        match self.supervisor.failed(Task::Resolver, panic) {
+            supervisor::Decision::Restart(_) => (lane, Ok(())),
+            supervisor::Decision::Escalate => (
+                lane,
+                Err(Error::Other(anyhow!(
+                    "resolvers panicked too often, reconnecting"
+                ))),
+            ),
+        }
+    }
+
+    /// Claim, process and respond to a single request, returning the
+    /// lane it belongs to, so the next one can be started.
+    async fn handle_request(
+        &self,
+        mut client: NodeClient<tonic::transport::Channel>,
+        signer_id: [u8; 16],
+        req: HsmRequest,
+    ) -> (pipeline::Lane, Result<(), Error>) {
+        let lane = pipeline::lane(&req);
+        let hex_req = hex::encode(&req.raw);
+        let signer_state = req.signer_state.clone();
+
+        match self.claim_request(&mut client, signer_id, &req).await {
+            Ok(true) => {}
+            Ok(false) => return (lane, Ok(())),
+            Err(e) => return (lane, Err(e)),
+        }
+
+        let res = match self.process_request(req).await {
+            Ok(response) => {
+                trace!("Sending response {}", hex::encode(&response.raw));
+                client
+                    .respond_hsm_request(response)
+                    .await
+                    .map(|_| self.health.request_completed())
+                    .map_err(Error::NodeDisconnect)
+            }
+            Err(e) => {
+                warn!(
+                    "Ignoring error {} for request {} with state {:?}",
+                    e, hex_req, signer_state,
+                );
+                Ok(())
+            }
+        };
+        (lane, res)
+    }
+
+    /// Claim `req`, and return whether to handle it. While another
+    /// signer holds the claim we observe the request, until the
+    /// holder responded and we merged the state changes of its
+    /// response, or the claim expired and we take over.
+    async fn claim_request(
+        &self,
+        client: &mut NodeClient<tonic::transport::Channel>,
+        signer_id: [u8; 16],
+        req: &HsmRequest,
+    ) -> Result<bool, Error> {
+        let mut observing = false;
+        loop {
+            let claim = client
+                .claim_hsm_request(HsmRequestClaim {
+                    request_id: req.request_id,
+                    signer_id: signer_id.to_vec(),
+                })
+                .await;
+            let claim = match claim {
+                Ok(c) => c.into_inner(),
+                // Nodes that predate request claiming let all
+                // signers respond.
+                Err(e) if e.code() == Code::Unimplemented => return Ok(true),
+                Err(e) => return Err(Error::NodeDisconnect(e)),
             };
+
+            if claim.granted {
+                if observing {
+                    debug!("Claim on request {} expired, taking over", req.request_id);
+                }
+                return Ok(true);
+            }
+            if claim.answered {
+                self.observe_response(&claim.signer_state);
+                return Ok(false);
+            }
+            if !observing {
+                debug!(
+                    "Request {} is handled by signer {}, observing",
+                    req.request_id,
+                    hex::encode(&claim.holder)
+                );
+                self.events.publish(Event::SignerConflict {
+                    request_id: req.request_id,
+                    holder: claim.holder,
+                });
+                observing = true;
+            }
+            sleep(CLAIM_POLL_INTERVAL).await;
         }
     }
 
+    /// Merge the state changes of a response by another signer, so
+    /// we stay in sync with the node.
+    fn observe_response(&self, signer_state: &[crate::pb::SignerStateEntry]) {
+        let diff = match crate::persist::State::from_entries(signer_state) {
+            Ok(diff) => diff,
+            Err(e) => {
+                warn!("Could not decode state of observed response: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = self.state.lock().unwrap().merge(&diff) {
+            warn!("Could not merge state of observed response: {}", e);
+        }
+    }
+
+    /// Change how often `task` may fail before the signer escalates,
+    /// see [`RestartPolicy`].
+    pub fn with_restart_policy(self, task: Task, policy: RestartPolicy) -> Self {
+        self.supervisor.set_policy(task, policy);
+        self
+    }
+
+    /// The state of the tasks of the signer loop.
+    pub fn supervisor_status(&self) -> SupervisorStatus {
+        self.supervisor.status()
+    }
+
+    /// Receive the [`SupervisorStatus`] whenever it changes, e.g., to
+    /// notice that the signer is reconnecting.
+    pub fn subscribe_supervisor(&self) -> tokio::sync::watch::Receiver<SupervisorStatus> {
+        self.supervisor.subscribe()
+    }
+
+    /// Publish [`Event::SignerOffline`] once the signer could not
+    /// reach the scheduler or the node for `threshold`, instead of
+    /// [`DEFAULT_OFFLINE_THRESHOLD`].
+    pub fn with_offline_threshold(mut self, threshold: Duration) -> Self {
+        self.reconnect.set_threshold(threshold);
+        self
+    }
+
+    /// Tell the signer that the network of the host changed, e.g.,
+    /// when the device comes back online. If the signer is waiting to
+    /// reconnect, it retries right away, and with the initial backoff
+    /// should that fail too.
+    pub fn network_changed(&self) {
+        debug!("Network changed, retrying to connect");
+        self.supervisor.recovered(Task::Connection);
+        self.supervisor.recovered(Task::Stream);
+        self.reconnect.network_changed();
+    }
+
+    /// Drop the connection to the node and connect again right away.
+    /// Requests in flight are answered by the node once the signer is
+    /// attached again.
+    pub fn reconnect(&self) {
+        debug!("Reconnecting to the node");
+        self.supervisor.recovered(Task::Connection);
+        self.supervisor.recovered(Task::Stream);
+        self.reconnect.reconnect();
+    }
+
+    /// Subscribe to events emitted by the signer, such as requests
+    /// that were handled by another signer.
+    pub fn subscribe_events(&self) -> tokio::sync::broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Report the connection status on `status`, e.g., to share it
+    /// with the [`Scheduler`](crate::scheduler::Scheduler).
+    pub fn with_status(mut self, status: StatusWatch) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn status(&self) -> &StatusWatch {
+        &self.status
+    }
+
+    /// The health of the signer, e.g., to probe the scheduler.
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    pub fn health_report(&self) -> HealthReport {
+        self.health.report(self.status.current())
+    }
+
+    /// The log of requests the signer refused.
+    pub fn audit_log(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    /// Record to `log`, e.g., one persisted with [`AuditLog::open`],
+    /// instead of an in-memory log.
+    pub fn with_audit_log(mut self, log: AuditLog) -> Self {
+        self.audit = log;
+        self
+    }
+
     fn authenticate_request(
         &self,
         msg: &vls_protocol::msgs::Message,
+        context: Option<&HsmRequestContext>,
         reqs: &Vec<model::Request>,
     ) -> Result<(), Error> {
         log::trace!(
@@ -429,20 +906,45 @@ impl Signer {
             reqs
         );
 
+        // Setting up a channel funded from outside the node is checked
+        // against the channel's funding script.
+        let funding_pubkey = match msg {
+            vls_protocol::msgs::Message::SetupChannel(_) => {
+                context.and_then(|c| self.funding_pubkey(c))
+            }
+            _ => None,
+        };
+
         // Quick path out of here: we can't find a resolution for a
         // request, then abort!
-        Resolver::try_resolve(msg, &reqs)?;
+        Resolver::try_resolve(msg, &reqs, funding_pubkey.as_ref())?;
 
         Ok(())
     }
 
+    /// The local funding pubkey of the channel a request with
+    /// `context` is for, if the signer knows the channel.
+    fn funding_pubkey(&self, context: &HsmRequestContext) -> Option<PublicKey> {
+        let mut nonce = context.node_id.clone();
+        nonce.extend_from_slice(&context.dbid.to_le_bytes());
+        let channel_id = ChannelId::new(&nonce);
+        self.handler()
+            .ok()?
+            .node()
+            .with_channel_base(&channel_id, |c| {
+                Ok(c.get_channel_basepoints().funding_pubkey)
+            })
+            .ok()
+    }
+
     async fn process_request(&self, req: HsmRequest) -> Result<HsmResponse, Error> {
-        let diff: crate::persist::State = req.signer_state.clone().into();
+        let diff = crate::persist::State::from_entries(&req.signer_state)
+            .map_err(|e| Error::Other(e.context("decoding the state from the node")))?;
 
         let prestate = {
             debug!("Updating local signer state with state from node");
             let mut state = self.state.lock().unwrap();
-            state.merge(&diff).unwrap();
+            state.merge(&diff).map_err(Error::Other)?;
             trace!("Processing request {}", hex::encode(&req.raw));
             state.clone()
         };
@@ -459,10 +961,30 @@ impl Signer {
             }
         }
 
-        let ctxrequests: Vec<model::Request> = self
+        let verified: Vec<crate::pb::PendingRequest> = self
             .check_request_auth(req.requests.clone())
             .into_iter()
             .filter_map(|r| r.ok())
+            .filter(|r| {
+                let supported = capabilities::supports(&r.uri);
+                if !supported {
+                    warn!("Node attached unsupported request {} to the context", r.uri);
+                    self.events.publish(Event::UnsupportedRequest { uri: r.uri.clone() });
+                }
+                supported
+            })
+            .collect();
+
+        if let Some(gate) = &self.gate {
+            if let Err(uri) = gate.check(&verified).await {
+                self.audit.record(AuditEvent::GateDenied { uri: uri.clone() });
+                self.record_rejection(RejectionKind::Gate, &uri);
+                return Err(Error::Other(anyhow!("signing {} was denied", uri)));
+            }
+        }
+
+        let ctxrequests: Vec<model::Request> = verified
+            .into_iter()
             .map(|r| decode_request(r))
             .filter_map(|r| match r {
                 Ok(r) => Some(r),
@@ -474,10 +996,18 @@ impl Signer {
             .collect::<Vec<model::Request>>();
 
         let msg = vls_protocol::msgs::from_vec(req.raw.clone()).map_err(|e| Error::Protocol(e))?;
+        // The handlers panic on message types they do not know.
+        if let vls_protocol::msgs::Message::Unknown(u) = &msg {
+            return Err(Error::Other(anyhow!(
+                "unknown message type {}",
+                u.message_type
+            )));
+        }
         log::debug!("Handling message {:?}", msg);
         log::trace!("Signer state {}", serde_json::to_string(&prestate).unwrap());
 
-        if let Err(e) = self.authenticate_request(&msg, &ctxrequests) {
+        if let Err(e) = self.authenticate_request(&msg, req.context.as_ref(), &ctxrequests) {
+            self.record_rejection(RejectionKind::Resolver, &e.to_string());
             report::Reporter::report(crate::pb::scheduler::SignerRejection {
                 msg: e.to_string(),
                 request: Some(req.clone()),
@@ -495,10 +1025,13 @@ impl Signer {
                     let pubkey = PublicKey::from_slice(&self.id);
                     match pubkey {
                         Ok(p) => {
-                            let _ = self
-                                .services
-                                .persister
-                                .update_node_allowlist(&p, vec![gl_config.close_to_addr.clone()]);
+                            let persister = &self.services.persister;
+                            let mut allowlist =
+                                persister.get_node_allowlist(&p).unwrap_or_default();
+                            if !allowlist.contains(&gl_config.close_to_addr) {
+                                allowlist.push(gl_config.close_to_addr.clone());
+                                let _ = persister.update_node_allowlist(&p, allowlist);
+                            }
                         }
                         Err(e) => debug!("Could not parse public key {:?}: {:?}", self.id, e),
                     }
@@ -509,7 +1042,8 @@ impl Signer {
 
         use auth::Authorizer;
         let auth = auth::GreenlightAuthorizer {};
-        let approvals = auth.authorize(&ctxrequests).map_err(|e| Error::Auth(e))?;
+        let mut approvals = auth.authorize(&ctxrequests).map_err(Error::Auth)?;
+        approvals.extend(self.operator_approvals());
         debug!("Current approvals: {:?}", approvals);
 
         let approver = Arc::new(MemoApprover::new(approver::ReportingApprover::new(
@@ -519,13 +1053,31 @@ impl Signer {
             vls_protocol_signer::approver::NegativeApprover(),
         )));
         approver.approve(approvals);
+
+        // Hold the policy while handling the request, so a
+        // concurrent update does not apply halfway through.
+        let policy_guard = self.policy.read().unwrap();
         let root_handler = self.handler_with_approver(approver)?;
 
         log::trace!("Updating state from context");
-        update_state_from_context(&ctxrequests, &root_handler)
-            .expect("Updating state from context requests");
+        update_state_from_context(&ctxrequests, &root_handler)?;
         log::trace!("State updated");
 
+        let pooled = self
+            .check_pooled_invoice(&policy_guard, &msg, &ctxrequests, root_handler.node())
+            .map_err(|e| {
+                self.audit.record(AuditEvent::PolicyViolation {
+                    request_id: req.request_id,
+                    message_type: u16::from_be_bytes([req.raw[0], req.raw[1]]),
+                    reason: e.to_string(),
+                });
+                self.record_rejection(RejectionKind::Policy, &e.to_string());
+                Error::Other(e)
+            })?;
+
+        let request_id = req.request_id;
+        let message_type = u16::from_be_bytes([req.raw[0], req.raw[1]]);
+
         // Match over root and client handler.
         let response = match req.context {
             Some(HsmRequestContext { dbid: 0, .. }) | None => {
@@ -533,14 +1085,33 @@ impl Signer {
                 root_handler.handle(msg)
             }
             Some(c) => {
-                let pk: [u8; 33] = c.node_id.try_into().unwrap();
+                let pk: [u8; 33] = c
+                    .node_id
+                    .try_into()
+                    .map_err(|_| Error::Other(anyhow!("invalid node_id in request context")))?;
                 let pk = vls_protocol::model::PubKey(pk);
                 root_handler
                     .for_new_client(1 as u64, pk, c.dbid)
                     .handle(msg)
             }
         }
-        .map_err(|e| Error::Other(anyhow!("processing request: {e:?}")))?;
+        .map_err(|e| {
+            // Validation failures are reported as signing errors,
+            // temporary ones are retried by the node.
+            if let handler::Error::Signing(s) = &e {
+                self.audit.record(AuditEvent::PolicyViolation {
+                    request_id,
+                    message_type,
+                    reason: s.message().to_string(),
+                });
+                self.record_rejection(RejectionKind::Policy, s.message());
+            }
+            Error::Other(anyhow!("processing request: {e:?}"))
+        })?;
+        drop(policy_guard);
+        if let Some(invoice) = pooled {
+            self.record_pooled_invoice(invoice, root_handler.node());
+        }
 
         let signer_state: Vec<crate::pb::SignerStateEntry> = {
             debug!("Serializing state changes to report to node");
@@ -649,7 +1220,9 @@ impl Signer {
     /// Connect to the scheduler given by the environment variable
     /// `GL_SCHEDULER_GRPC_URI` (of the default URI) and wait for the
     /// node to be scheduled. Once scheduled, connect to the node
-    /// directly and start streaming and processing requests.
+    /// directly and start streaming and processing requests. Returns
+    /// an error if the signer loop gave up, e.g., because it failed to
+    /// connect too often.
     pub async fn run_forever(&self, shutdown: mpsc::Receiver<()>) -> Result<(), anyhow::Error> {
         let scheduler_uri = crate::utils::scheduler_uri();
         Self::run_forever_with_uri(&self, shutdown, scheduler_uri).await
@@ -663,7 +1236,7 @@ impl Signer {
         debug!("Connecting to scheduler at {scheduler_uri}");
 
         let channel = Endpoint::from_shared(scheduler_uri)?
-            .tls_config(self.tls.inner.clone())?
+            .tls_config(self.creds.try_tls_config()?.inner)?
             .tcp_keepalive(Some(crate::TCP_KEEPALIVE))
             .http2_keep_alive_interval(crate::TCP_KEEPALIVE)
             .keep_alive_timeout(crate::TCP_KEEPALIVE_TIMEOUT)
@@ -684,14 +1257,22 @@ impl Signer {
                         .into_iter()
                         .map(|s| s.into())
                         .collect(),
+                    attestation: self.attestation()?,
                 })
                 .await;
 
             if let Err(err_status) = maybe_upgrade_res {
+                if let Some(limited) = RateLimited::from_status(&err_status) {
+                    warn!("Scheduler is rate limiting us, retrying in {:?}", limited.backoff());
+                    sleep(limited.backoff()).await;
+                    continue;
+                }
                 match err_status.code() {
                     Code::Unavailable => {
                         debug!("Cannot connect to scheduler, sleeping and retrying");
-                        sleep(Duration::from_secs(3)).await;
+                        let backoff =
+                            self.restart_after(Task::Connection, err_status.to_string())?;
+                        self.reconnect.backoff(backoff).await;
                         continue;
                     }
                     _ => {
@@ -702,6 +1283,7 @@ impl Signer {
 
             break;
         }
+        self.connected(&[Task::Connection]);
         Ok(scheduler)
     }
 
@@ -714,6 +1296,8 @@ impl Signer {
     ) -> Result<(), anyhow::Error> {
         loop {
             debug!("Calling scheduler.get_node_info");
+            self.supervisor
+                .set_state(Task::Connection, TaskState::Running);
             let node_info_res = scheduler
                 .get_node_info(NodeInfoRequest {
                     node_id: self.id.clone(),
@@ -730,27 +1314,78 @@ impl Signer {
             let node_info = match node_info_res.map(|v| v.into_inner()) {
                 Ok(v) => {
                     debug!("Got node_info from scheduler: {:?}", v);
+                    self.connected(&[Task::Connection]);
                     v
                 }
                 Err(e) => {
                     trace!("Got an error from the scheduler: {e}. Sleeping before retrying");
-                    sleep(Duration::from_millis(1000)).await;
+                    self.status.set(ConnectionStatus::Disconnected);
+                    let backoff = match RateLimited::from_status(&e) {
+                        Some(limited) => limited.backoff(),
+                        None => self.restart_after(Task::Connection, e.to_string())?,
+                    };
+                    self.reconnect.backoff(backoff).await;
                     continue;
                 }
             };
 
             if node_info.grpc_uri.is_empty() {
                 trace!("Got an empty GRPC URI, node is not scheduled, sleeping and retrying");
+                self.status.set(ConnectionStatus::SchedulerConnected);
                 sleep(Duration::from_millis(1000)).await;
                 continue;
             }
 
-            if let Err(e) = self
+            self.status.set(ConnectionStatus::NodeReady);
+            self.supervisor.set_state(Task::Connection, TaskState::Idle);
+            match self
                 .run_once(Uri::from_maybe_shared(node_info.grpc_uri)?)
                 .await
             {
-                warn!("Error running against node: {e}");
+                Ok(()) => {
+                    self.supervisor.set_state(Task::Stream, TaskState::Idle);
+                    self.supervisor.set_state(Task::Resolver, TaskState::Idle);
+                }
+                Err(e) => {
+                    warn!("Error running against node: {e}");
+                    let mut backoff = self.restart_after(Task::Stream, e.to_string())?;
+                    if let Error::NodeDisconnect(status) = &e {
+                        if let Some(limited) = RateLimited::from_status(status) {
+                            backoff = backoff.max(limited.backoff());
+                        }
+                    }
+                    self.reconnect.backoff(backoff).await;
+                }
             }
+            // The node stopped, or we lost the connection to it.
+            self.status.set(ConnectionStatus::SchedulerConnected);
+        }
+    }
+
+    /// Record that `task` failed, returning how long to wait before
+    /// restarting it, or an error if it failed too often.
+    fn restart_after(&self, task: Task, error: String) -> Result<Duration> {
+        if matches!(task, Task::Connection | Task::Stream) {
+            if let Some(event) = self.reconnect.failed() {
+                warn!("Signer is offline: {:?}", event);
+                self.events.publish(event);
+            }
+        }
+        match self.supervisor.failed(task, error) {
+            supervisor::Decision::Restart(backoff) => Ok(backoff),
+            supervisor::Decision::Escalate => Err(anyhow!("{:?} failed too often", task)),
+        }
+    }
+
+    /// Record that the signer reached the scheduler or the node, so
+    /// `tasks` recovered.
+    fn connected(&self, tasks: &[Task]) {
+        for task in tasks {
+            self.supervisor.recovered(*task);
+        }
+        if let Some(event) = self.reconnect.connected() {
+            info!("Signer is back online: {:?}", event);
+            self.events.publish(event);
         }
     }
 
@@ -759,16 +1394,29 @@ impl Signer {
         mut shutdown: mpsc::Receiver<()>,
         scheduler_uri: String,
     ) -> Result<(), anyhow::Error> {
-        let scheduler = self.init_scheduler(scheduler_uri).await?;
-        tokio::select! {
+        let scheduler = tokio::select! {
+            scheduler = self.init_scheduler(scheduler_uri) => scheduler?,
+            _ = shutdown.recv() => {
+                debug!("Received the signal to exit while connecting to the scheduler");
+                return Ok(());
+            }
+        };
+        self.status.raise(ConnectionStatus::SchedulerConnected);
+        let res = tokio::select! {
             run_forever_inner_res = self.run_forever_inner(scheduler) => {
                 error!("Inner signer loop exited unexpectedly: {run_forever_inner_res:?}");
+                run_forever_inner_res
             },
-            _ = shutdown.recv() => debug!("Received the signal to exit the signer loop")
+            _ = shutdown.recv() => {
+                debug!("Received the signal to exit the signer loop");
+                Ok(())
+            }
+            _ = telemetry::report_forever(self.telemetry.as_ref()) => Ok(()),
         };
 
         info!("Exiting the signer loop");
-        Ok(())
+        self.status.set(ConnectionStatus::Disconnected);
+        res
     }
 
     // TODO See comment on `sign_device_key`.
@@ -839,6 +1487,15 @@ impl Signer {
         Ok(sig.0.as_vec()[2..67].to_vec())
     }
 
+    /// The public key of the `index`-th swap, to hand to the swap
+    /// provider, see [`crate::swaps`]. The key is independent of the
+    /// node's keys, and only used by the signer to sign the claim or
+    /// refund of the swap. Use a new index for every swap.
+    pub fn swap_pubkey(&self, index: u32) -> Result<PublicKey, anyhow::Error> {
+        let key = derivation::swap_key(&self.secret, index)?;
+        Ok(PublicKey::from_secret_key(&Secp256k1::signing_only(), &key))
+    }
+
     /// Create a Node stub from this instance of the signer, configured to
     /// talk to the corresponding node.
     pub async fn node<Creds>(&self, creds: Creds) -> Result<Client, anyhow::Error>
@@ -889,28 +1546,35 @@ impl Signer {
     ///
     /// `create_rune("wjEjvKoFJToMLBv4QVbJpSbMoGFlnYVxs8yy40PIBgs9MC1nbDAmcHVia2V5PTAwMDAwMA", vec![vec!["method^list", "method^get"]])`
     ///
+    /// Values containing `\`, `|` or `&` have to be escaped with
+    /// [`runes::escape`], e.g., `format!("description={}", runes::escape("a|b"))`.
+    /// Unescaped separators are rejected rather than splitting the
+    /// alternative or restriction.
+    ///
     pub fn create_rune(
         &self,
         rune: Option<&str>,
         restrictions: Vec<Vec<&str>>,
     ) -> Result<String, anyhow::Error> {
+        let res: Vec<Restriction> = restrictions
+            .into_iter()
+            .map(|alts| {
+                let alts = alts
+                    .into_iter()
+                    .map(runes::parse_alternative)
+                    .collect::<Result<Vec<_>, _>>()?;
+                Restriction::new(alts)
+            })
+            .collect::<Result<Vec<Restriction>, RuneError>>()?;
+
         if let Some(rune) = rune {
             // We got a rune, add restrictions to it!
-            let mut rune: Rune = Rune::from_base64(rune)?;
-            restrictions.into_iter().for_each(|alts| {
-                let joined = alts.join("|");
-                _ = rune.add_restriction(joined.as_str())
-            });
+            let mut rune: Rune = runes::decode_rune(rune)?;
+            for r in res {
+                rune.add_restriction(r)?;
+            }
             return Ok(rune.to_base64());
         } else {
-            let res: Vec<Restriction> = restrictions
-                .into_iter()
-                .map(|alts| {
-                    let joined = alts.join("|");
-                    Restriction::try_from(joined.as_str())
-                })
-                .collect::<Result<Vec<Restriction>, RuneError>>()?;
-
             // New rune, we need a unique id.
             // FIXME: Add a counter that persists in SSS.
             let unique_id = 0;
@@ -936,6 +1600,14 @@ impl Signer {
     }
 }
 
+fn invoice_expired(invoice: &Invoice) -> bool {
+    let expiry = invoice.duration_since_epoch() + invoice.expiry_duration();
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|now| now > expiry)
+        .unwrap_or(true)
+}
+
 /// Look through the context requests and update the state
 /// accordingly. This is useful to modify allowlists and invoice lists
 /// extracted from the authenticated requests.
@@ -948,8 +1620,7 @@ fn update_state_from_context(
 
     requests
         .iter()
-        .for_each(|r| update_state_from_request(r, &node).unwrap());
-    Ok(())
+        .try_for_each(|r| update_state_from_request(r, &node))
 }
 
 fn update_state_from_request(
@@ -962,13 +1633,15 @@ fn update_state_from_request(
         model::Request::SendPay(model::cln::SendpayRequest {
             bolt11: Some(inv), ..
         }) => {
-            let invoice = Invoice::from_str(inv).unwrap();
+            let invoice = Invoice::from_str(inv)
+                .map_err(|e| Error::Other(anyhow!("invalid invoice in sendpay: {:?}", e)))?;
             log::debug!(
                 "Adding invoice {:?} as side-effect of this sendpay {:?}",
                 invoice,
                 request
             );
-            node.add_invoice(invoice).unwrap();
+            node.add_invoice(invoice)
+                .map_err(|e| Error::Other(anyhow!("adding invoice: {:?}", e)))?;
         }
         _ => {}
     }
@@ -988,8 +1661,10 @@ fn decode_request(r: crate::pb::PendingRequest) -> Result<model::Request, anyhow
     // for technical details.
     //
     // Notice that we assume that the compression flag is off.
-    assert_eq!(r.request[0], 0u8);
-    let payload = &r.request[5..];
+    let payload = match r.request.as_slice() {
+        [0, _, _, _, _, payload @ ..] => payload,
+        _ => return Err(anyhow!("request is not an uncompressed grpc message")),
+    };
 
     crate::signer::model::cln::decode_request(&r.uri, payload)
         .or_else(|_| crate::signer::model::greenlight::decode_request(&r.uri, payload))
@@ -1156,7 +1831,18 @@ mod tests {
             .create_rune(Some(rune), vec![vec!["method^get"]])
             .unwrap();
         let rs = Rune::from_base64(&new_rune).unwrap().to_string();
-        assert!(rs.contains("0-gl0&pubkey=000000&method^get"))
+        assert!(rs.contains("0-gl0&pubkey=000000&method^get"));
+
+        // Unescaped separators are rejected, rather than dropped.
+        assert!(signer
+            .create_rune(Some(rune), vec![vec!["description=a|b"]])
+            .is_err());
+        let escaped = format!("description={}", runes::escape("a|b"));
+        let new_rune = signer
+            .create_rune(Some(rune), vec![vec![&escaped]])
+            .unwrap();
+        let rs = runes::decode_rune(&new_rune).unwrap().to_string();
+        assert!(rs.ends_with("&description=a\\|b"));
     }
 
     #[test]
@@ -1273,4 +1959,102 @@ mod tests {
             })
             .is_err());
     }
+
+    #[test]
+    fn test_update_policy() {
+        let creds = credentials::Nobody::default();
+        let signer = Signer::new(vec![0u8; 32], Network::Bitcoin, creds).unwrap();
+
+        let pubkey = signer.node_id();
+        let pubkey_rest = format!("pubkey={}", hex::encode(&pubkey));
+        let rune = signer.create_rune(None, vec![vec![&pubkey_rest]]).unwrap();
+        let request = crate::pb::PendingRequest {
+            request: vec![],
+            uri: "/cln.Node/Pay".to_string(),
+            signature: vec![],
+            pubkey,
+            timestamp: 0,
+            rune: general_purpose::URL_SAFE.decode(&rune).unwrap(),
+        };
+        assert!(signer.verify_rune(request.clone()).is_ok());
+
+        let authcode = Rune::from_base64(&rune).unwrap().authcode();
+        let policy = SignerPolicy {
+            max_invoices: 5,
+            rune_blacklist: vec![hex::encode(authcode)],
+            ..Default::default()
+        };
+        signer.update_policy(policy.clone()).unwrap();
+        assert_eq!(signer.signer_policy(), policy);
+        assert!(signer.verify_rune(request).is_err());
+
+        let entries = signer.audit_log().entries();
+        assert_eq!(
+            entries.last().unwrap().event,
+            AuditEvent::PolicyChanged {
+                previous: Box::default(),
+                current: Box::new(policy),
+            }
+        );
+
+        // The allowlist of the policy replaces that of the previous
+        // policy, addresses added by the node stay.
+        let node_id = PublicKey::from_slice(&signer.node_id()).unwrap();
+        let persister = &signer.services.persister;
+        let allowlist = |addrs: &[&str]| addrs.iter().map(|a| a.to_string()).collect();
+        persister
+            .update_node_allowlist(&node_id, allowlist(&["close_to"]))
+            .unwrap();
+        for addrs in [vec!["cold1", "cold2"], vec!["cold2"]] {
+            let policy = SignerPolicy {
+                allowlist: allowlist(&addrs),
+                ..Default::default()
+            };
+            signer.update_policy(policy).unwrap();
+        }
+        assert_eq!(
+            persister.get_node_allowlist(&node_id).unwrap(),
+            allowlist(&["close_to", "cold2"])
+        );
+    }
+
+    #[test]
+    fn test_rotate_rune() {
+        let creds = credentials::Nobody::default();
+        let signer = Signer::new(vec![0u8; 32], Network::Bitcoin, creds).unwrap();
+
+        let pubkey = signer.node_id();
+        let pubkey_rest = format!("pubkey={}", hex::encode(&pubkey));
+        let rune = signer
+            .create_rune(None, vec![vec![&pubkey_rest], vec!["method^list"]])
+            .unwrap();
+        let request = |rune: &str, uri: &str| crate::pb::PendingRequest {
+            request: vec![],
+            uri: uri.to_string(),
+            signature: vec![],
+            pubkey: pubkey.clone(),
+            timestamp: 0,
+            rune: general_purpose::URL_SAFE.decode(rune).unwrap(),
+        };
+
+        let rotated = signer.rotate_rune(&rune).unwrap();
+        assert!(signer
+            .verify_rune(request(&rune, "/cln.Node/ListFunds"))
+            .is_err());
+        assert!(signer
+            .verify_rune(request(&rotated, "/cln.Node/ListFunds"))
+            .is_ok());
+        assert!(signer
+            .verify_rune(request(&rotated, "/cln.Node/Pay"))
+            .is_err());
+
+        // Only the successful use of the rotated rune is counted.
+        let usage = signer.audit_log().rune_usage();
+        assert_eq!(usage.len(), 1);
+        assert_eq!((usage[0].method.as_str(), usage[0].count), ("listfunds", 1));
+
+        let other = Signer::new(vec![1u8; 32], Network::Bitcoin, credentials::Nobody::default())
+            .unwrap();
+        assert!(other.rotate_rune(&rotated).is_err());
+    }
 }
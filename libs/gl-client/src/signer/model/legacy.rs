@@ -0,0 +1,273 @@
+// Migration from the deprecated `greenlight.Node` methods to their
+// `cln.Node` equivalents.
+
+use super::{cln, Request};
+use crate::pb;
+use anyhow::{anyhow, Result};
+use std::convert::TryInto;
+
+impl Request {
+    /// Map a deprecated `Gl*` request to the request of the equivalent
+    /// `cln.Node` method, leaving all other requests untouched. Fails
+    /// if the legacy request uses an option `cln.Node` does not
+    /// have. `GlListFunds` drops `minconf`, which has no equivalent.
+    pub fn into_cln(self) -> Result<Request> {
+        Ok(match self {
+            Request::GlGetinfo(_) => Request::Getinfo(cln::GetinfoRequest {}),
+            Request::GlStop(_) => Request::Stop(cln::StopRequest {}),
+            Request::GlListPeers(r) => Request::ListPeers(cln::ListpeersRequest {
+                id: match r.node_id.as_str() {
+                    "" => None,
+                    id => Some(hex::decode(id)?),
+                },
+                level: None,
+            }),
+            Request::GlDisconnect(r) => Request::Disconnect(cln::DisconnectRequest {
+                id: hex::decode(&r.node_id)?,
+                force: Some(r.force),
+            }),
+            Request::GlNewAddr(r) => {
+                if r.address_type != pb::BtcAddressType::Bech32 as i32 {
+                    return Err(anyhow!("address type {} has no equivalent", r.address_type));
+                }
+                Request::NewAddr(cln::NewaddrRequest {
+                    addresstype: Some(cln::newaddr_request::NewaddrAddresstype::Bech32 as i32),
+                })
+            }
+            Request::GlListFunds(_) => Request::ListFunds(cln::ListfundsRequest::default()),
+            Request::GlWithdraw(r) => Request::Withdraw(cln::WithdrawRequest {
+                destination: r.destination,
+                satoshi: r.amount.map(amount_or_all).transpose()?,
+                feerate: r.feerate.map(feerate).transpose()?,
+                minconf: r.minconf.map(|c| c.blocks),
+                utxos: r
+                    .utxos
+                    .into_iter()
+                    .map(|o| cln::Outpoint {
+                        txid: o.txid,
+                        outnum: o.outnum,
+                    })
+                    .collect(),
+            }),
+            Request::GlFundChannel(r) => Request::FundChannel(cln::FundchannelRequest {
+                id: r.node_id,
+                amount: Some(amount_or_all(
+                    r.amount.ok_or_else(|| anyhow!("missing amount"))?,
+                )?),
+                feerate: r.feerate.map(feerate).transpose()?,
+                announce: Some(r.announce),
+                minconf: r.minconf.map(|c| c.blocks),
+                close_to: non_empty(r.close_to),
+                ..Default::default()
+            }),
+            Request::GlCloseChannel(r) => Request::Close(cln::CloseRequest {
+                id: hex::encode(r.node_id),
+                unilateraltimeout: r.unilateraltimeout.map(|t| t.seconds),
+                destination: r.destination.map(|d| d.address),
+                ..Default::default()
+            }),
+            Request::GlCreateInvoice(r) => Request::Invoice(cln::InvoiceRequest {
+                amount_msat: Some(amount_or_any(r.amount)?),
+                description: r.description,
+                label: r.label,
+                preimage: Some(r.preimage).filter(|p| !p.is_empty()),
+                ..Default::default()
+            }),
+            Request::GlPay(r) => Request::Pay(cln::PayRequest {
+                bolt11: r.bolt11,
+                amount_msat: r.amount.map(amount).transpose()?.flatten(),
+                maxfeepercent: Some(r.maxfeepercent).filter(|p| *p != 0.0),
+                retry_for: Some(r.timeout).filter(|t| *t != 0),
+                maxfee: r.maxfee.map(amount).transpose()?.flatten(),
+                ..Default::default()
+            }),
+            Request::GlKeysend(r) => Request::KeySend(cln::KeysendRequest {
+                destination: r.node_id,
+                amount_msat: r.amount.map(amount).transpose()?.flatten(),
+                label: non_empty(r.label),
+                routehints: Some(cln::RoutehintList {
+                    hints: r
+                        .routehints
+                        .into_iter()
+                        .map(|h| cln::Routehint {
+                            hops: h
+                                .hops
+                                .into_iter()
+                                .map(|h| cln::RouteHop {
+                                    id: h.node_id,
+                                    short_channel_id: h.short_channel_id,
+                                    feebase: Some(cln::Amount { msat: h.fee_base }),
+                                    feeprop: h.fee_prop,
+                                    expirydelta: h.cltv_expiry_delta,
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                })
+                .filter(|l| !l.hints.is_empty()),
+                extratlvs: Some(cln::TlvStream {
+                    entries: r
+                        .extratlvs
+                        .into_iter()
+                        .map(|t| cln::TlvEntry {
+                            r#type: t.r#type,
+                            value: t.value,
+                        })
+                        .collect(),
+                })
+                .filter(|s| !s.entries.is_empty()),
+                ..Default::default()
+            }),
+            Request::GlListPayments(r) => {
+                use pb::payment_identifier::Id;
+                let mut req = cln::ListpaysRequest::default();
+                match r.identifier.and_then(|i| i.id) {
+                    Some(Id::Bolt11(b)) => req.bolt11 = Some(b),
+                    Some(Id::PaymentHash(h)) => req.payment_hash = Some(h),
+                    None => {}
+                }
+                Request::ListPays(req)
+            }
+            Request::GlListInvoices(r) => {
+                use pb::invoice_identifier::Id;
+                let mut req = cln::ListinvoicesRequest::default();
+                match r.identifier.and_then(|i| i.id) {
+                    Some(Id::Label(l)) => req.label = Some(l),
+                    Some(Id::Invstring(i)) => req.invstring = Some(i),
+                    Some(Id::PaymentHash(h)) => req.payment_hash = Some(h),
+                    None => {}
+                }
+                Request::ListInvoices(req)
+            }
+            Request::GlConnectPeer(r) => {
+                let (host, port) = match r.addr.rsplit_once(':') {
+                    Some((host, port)) => (Some(host.to_string()), Some(port.parse()?)),
+                    None => (non_empty(r.addr), None),
+                };
+                Request::Connect(cln::ConnectRequest {
+                    id: r.node_id,
+                    host,
+                    port,
+                })
+            }
+            r => r,
+        })
+    }
+}
+
+fn non_empty(s: String) -> Option<String> {
+    Some(s).filter(|s| !s.is_empty())
+}
+
+/// The amount in millisatoshi, or `None` for `all` and `any`.
+fn msat(a: pb::Amount) -> Result<Option<u64>> {
+    use pb::amount::Unit;
+    Ok(match a.unit {
+        Some(Unit::Millisatoshi(m)) => Some(m),
+        Some(Unit::Satoshi(s)) => Some(s * 1_000),
+        Some(Unit::Bitcoin(b)) => Some(b * 100_000_000_000),
+        Some(Unit::All(_)) | Some(Unit::Any(_)) => None,
+        None => return Err(anyhow!("amount without a unit")),
+    })
+}
+
+fn amount(a: pb::Amount) -> Result<Option<cln::Amount>> {
+    if let Some(pb::amount::Unit::All(_)) = a.unit {
+        return Err(anyhow!("amount `all` is not allowed here"));
+    }
+    Ok(msat(a)?.map(|msat| cln::Amount { msat }))
+}
+
+fn amount_or_all(a: pb::Amount) -> Result<cln::AmountOrAll> {
+    use cln::amount_or_all::Value;
+    if let Some(pb::amount::Unit::Any(_)) = a.unit {
+        return Err(anyhow!("amount `any` is not allowed here"));
+    }
+    Ok(cln::AmountOrAll {
+        value: Some(match msat(a)? {
+            Some(msat) => Value::Amount(cln::Amount { msat }),
+            None => Value::All(true),
+        }),
+    })
+}
+
+fn amount_or_any(a: Option<pb::Amount>) -> Result<cln::AmountOrAny> {
+    use cln::amount_or_any::Value;
+    if let Some(pb::amount::Unit::All(_)) = a.as_ref().and_then(|a| a.unit.as_ref()) {
+        return Err(anyhow!("amount `all` is not allowed here"));
+    }
+    let msat = match a {
+        Some(a) => msat(a)?,
+        None => None,
+    };
+    Ok(cln::AmountOrAny {
+        value: Some(match msat {
+            Some(msat) => Value::Amount(cln::Amount { msat }),
+            None => Value::Any(true),
+        }),
+    })
+}
+
+fn feerate(f: pb::Feerate) -> Result<cln::Feerate> {
+    use cln::feerate::Style;
+    use pb::feerate::Value;
+    let style = match f.value {
+        Some(Value::Preset(p)) => match pb::FeeratePreset::from_i32(p) {
+            Some(pb::FeeratePreset::Normal) => Style::Normal(true),
+            Some(pb::FeeratePreset::Slow) => Style::Slow(true),
+            Some(pb::FeeratePreset::Urgent) => Style::Urgent(true),
+            None => return Err(anyhow!("unknown feerate preset {}", p)),
+        },
+        Some(Value::Perkw(v)) => Style::Perkw(v.try_into()?),
+        Some(Value::Perkb(v)) => Style::Perkb(v.try_into()?),
+        None => return Err(anyhow!("feerate without a value")),
+    };
+    Ok(cln::Feerate { style: Some(style) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_cln() {
+        let pay = Request::GlPay(pb::PayRequest {
+            bolt11: "lnbc1".to_string(),
+            amount: Some(pb::Amount {
+                unit: Some(pb::amount::Unit::Satoshi(2)),
+            }),
+            ..Default::default()
+        });
+        match pay.into_cln().unwrap() {
+            Request::Pay(r) => {
+                assert_eq!(r.bolt11, "lnbc1");
+                assert_eq!(r.amount_msat, Some(cln::Amount { msat: 2000 }));
+                assert_eq!(r.retry_for, None);
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+
+        let connect = Request::GlConnectPeer(pb::ConnectRequest {
+            node_id: "02aa".to_string(),
+            addr: "127.0.0.1:9735".to_string(),
+        });
+        match connect.into_cln().unwrap() {
+            Request::Connect(r) => {
+                assert_eq!(r.host.as_deref(), Some("127.0.0.1"));
+                assert_eq!(r.port, Some(9735));
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+
+        let withdraw = Request::GlWithdraw(pb::WithdrawRequest {
+            amount: Some(pb::Amount {
+                unit: Some(pb::amount::Unit::Any(true)),
+            }),
+            ..Default::default()
+        });
+        assert!(withdraw.into_cln().is_err());
+
+        let getinfo = Request::Getinfo(cln::GetinfoRequest {});
+        assert_eq!(getinfo.method(), getinfo.into_cln().unwrap().method());
+    }
+}
@@ -4,26 +4,60 @@
 
 pub mod cln;
 pub mod greenlight;
+#[cfg(feature = "legacy-proto")]
+mod legacy;
 
-/// Variants prefixed with `Gl` are deprecated and will eventually be removed.
+/// Variants prefixed with `Gl` are deprecated and will eventually be
+/// removed. Except for `GlConfig` they are only available with the
+/// `legacy-proto` feature, see [`Request::into_cln`] to migrate them.
+///
+/// With the `model-serde` feature the request serializes as
+/// `{"method": <variant>, "params": <request>}`, using the same
+/// representation as the generated message types.
 #[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "model-serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "method", content = "params")
+)]
 pub enum Request {
+    #[cfg(feature = "legacy-proto")]
     GlGetinfo(greenlight::GetInfoRequest),
+    #[cfg(feature = "legacy-proto")]
     GlStop(greenlight::StopRequest),
+    #[cfg(feature = "legacy-proto")]
     GlListPeers(greenlight::ListPeersRequest),
+    #[cfg(feature = "legacy-proto")]
     GlDisconnect(greenlight::DisconnectRequest),
+    #[cfg(feature = "legacy-proto")]
     GlNewAddr(greenlight::NewAddrRequest),
+    #[cfg(feature = "legacy-proto")]
     GlListFunds(greenlight::ListFundsRequest),
+    #[cfg(feature = "legacy-proto")]
     GlWithdraw(greenlight::WithdrawRequest),
+    #[cfg(feature = "legacy-proto")]
     GlFundChannel(greenlight::FundChannelRequest),
+    #[cfg(feature = "legacy-proto")]
     GlCloseChannel(greenlight::CloseChannelRequest),
+    #[cfg(feature = "legacy-proto")]
     GlCreateInvoice(greenlight::InvoiceRequest),
+    #[cfg(feature = "legacy-proto")]
     GlPay(greenlight::PayRequest),
+    #[cfg(feature = "legacy-proto")]
     GlKeysend(greenlight::KeysendRequest),
+    #[cfg(feature = "legacy-proto")]
     GlListPayments(greenlight::ListPaymentsRequest),
+    #[cfg(feature = "legacy-proto")]
     GlListInvoices(greenlight::ListInvoicesRequest),
+    #[cfg(feature = "legacy-proto")]
     GlConnectPeer(greenlight::ConnectRequest),
     GlConfig(greenlight::GlConfig),
+    FundChannelStart(greenlight::FundChannelStartRequest),
+    FundChannelComplete(greenlight::FundChannelCompleteRequest),
+    FundChannelCancel(greenlight::FundChannelCancelRequest),
+    MultiFundChannel(greenlight::MultiFundChannelRequest),
+    EmergencyRecover(greenlight::EmergencyRecoverRequest),
+    RecoverChannel(greenlight::RecoverChannelRequest),
     Getinfo(cln::GetinfoRequest),
     ListPeers(cln::ListpeersRequest),
     ListFunds(cln::ListfundsRequest),
@@ -75,4 +109,29 @@ pub enum Request {
     Stop(cln::StopRequest),
     ListClosedChannels(cln::ListclosedchannelsRequest),
     StaticBackup(cln::StaticbackupRequest),
+    Wait(cln::WaitRequest),
+    ListPeerChannels(cln::ListpeerchannelsRequest),
+    DecodePay(cln::DecodepayRequest),
+    Decode(cln::DecodeRequest),
+    PreApproveKeysend(cln::PreapprovekeysendRequest),
+    SignInvoice(cln::SigninvoiceRequest),
+}
+
+#[cfg(all(test, feature = "model-serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serde_roundtrip() {
+        let req = Request::Pay(cln::PayRequest {
+            bolt11: "lnbc1".to_string(),
+            ..Default::default()
+        });
+        let json = serde_json::to_value(&req).unwrap();
+        assert_eq!(json["method"], "Pay");
+        assert_eq!(json["params"]["bolt11"], "lnbc1");
+
+        let back: Request = serde_json::from_value(json).unwrap();
+        assert_eq!(back.digest(), req.digest());
+    }
 }
@@ -0,0 +1,234 @@
+//! Enforce the limits of the invoice pool, see [`crate::invoice_pool`].
+//!
+//! The pool only adds invoices while the outstanding ones, as listed
+//! by the node, stay within the limits. The signer does not trust the
+//! node's list: before signing a pooled invoice it checks the limits
+//! against the pooled invoices it signed itself. It keeps those in the
+//! signer state, which the node stores across reconnects, and counts
+//! them as outstanding until they expire, or until VLS saw their
+//! HTLCs fulfilled.
+use super::{model, Signer, SignerPolicy};
+use crate::bitcoin::bech32::{u5, FromBase32};
+use crate::bitcoin::hashes::Hash;
+use crate::invoice_pool::LABEL_PREFIX;
+use crate::lightning::ln::PaymentHash;
+use crate::lightning_invoice::{RawBolt11Invoice, RawDataPart, RawHrp, DEFAULT_EXPIRY_TIME};
+use anyhow::{anyhow, ensure, Result};
+use lightning_signer::node::Node;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+use vls_protocol::msgs::Message;
+
+/// The key of the pooled invoices in the signer state.
+const STATE_KEY: &str = "invoicepools";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct PooledInvoice {
+    payment_hash: [u8; 32],
+    amount_msat: u64,
+    /// Seconds since the UNIX epoch.
+    expires_at: u64,
+}
+
+impl Signer {
+    /// If `msg` signs a pooled invoice, i.e., one created by a call in
+    /// `ctxrequests` with a pool label, check it against the offline
+    /// limits of `policy`. Once signed, record the returned invoice
+    /// with [`Signer::record_pooled_invoice`].
+    pub(super) fn check_pooled_invoice(
+        &self,
+        policy: &SignerPolicy,
+        msg: &Message,
+        ctxrequests: &[model::Request],
+        node: &Node,
+    ) -> Result<Option<PooledInvoice>> {
+        let (limits, m) = match (policy.offline_limits, msg) {
+            (Some(limits), Message::SignInvoice(m)) => (limits, m),
+            _ => return Ok(None),
+        };
+        let pooled = ctxrequests.iter().any(|r| match r {
+            model::Request::Invoice(i) => i.label.starts_with(LABEL_PREFIX),
+            _ => false,
+        });
+        if !pooled {
+            return Ok(None);
+        }
+
+        let invoice = pooled_invoice(&m.hrp.0, &m.u5bytes.0)?;
+        ensure!(
+            invoice.amount_msat <= limits.max_invoice_msat,
+            "pooled invoice of {}msat exceeds the limit of {}msat",
+            invoice.amount_msat,
+            limits.max_invoice_msat
+        );
+        let outstanding: u64 = self
+            .pooled_invoices(node, now())
+            .iter()
+            .filter(|p| p.payment_hash != invoice.payment_hash)
+            .map(|p| p.amount_msat)
+            .sum();
+        ensure!(
+            outstanding + invoice.amount_msat <= limits.max_total_msat,
+            "pooled invoices of {}msat outstanding, another {}msat exceeds the limit of {}msat",
+            outstanding,
+            invoice.amount_msat,
+            limits.max_total_msat
+        );
+        Ok(Some(invoice))
+    }
+
+    /// Remember the signed pooled `invoice`, and forget those that
+    /// are no longer outstanding.
+    pub(super) fn record_pooled_invoice(&self, invoice: PooledInvoice, node: &Node) {
+        let mut invoices = self.pooled_invoices(node, now());
+        invoices.retain(|p| p.payment_hash != invoice.payment_hash);
+        invoices.push(invoice);
+        let value = serde_json::to_value(invoices).expect("serializing pooled invoices");
+        self.state.lock().unwrap().put_entry(STATE_KEY, value);
+    }
+
+    /// The pooled invoices that are neither expired at `now` nor paid.
+    fn pooled_invoices(&self, node: &Node, now: u64) -> Vec<PooledInvoice> {
+        let invoices: Vec<PooledInvoice> = self
+            .state
+            .lock()
+            .unwrap()
+            .get_entry(STATE_KEY)
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+            .unwrap_or_default();
+        let state = node.get_state();
+        invoices
+            .into_iter()
+            .filter(|p| p.expires_at > now)
+            .filter(|p| {
+                state
+                    .payments
+                    .get(&PaymentHash(p.payment_hash))
+                    .map_or(true, |payment| !payment.is_fulfilled())
+            })
+            .collect()
+    }
+}
+
+/// Decode the invoice of a `SignInvoice` request.
+fn pooled_invoice(hrp: &[u8], data: &[u8]) -> Result<PooledInvoice> {
+    let hrp: RawHrp = std::str::from_utf8(hrp)?
+        .parse()
+        .map_err(|e| anyhow!("invalid invoice hrp: {:?}", e))?;
+    let data = data
+        .iter()
+        .map(|b| u5::try_from_u8(*b))
+        .collect::<Result<Vec<u5>, _>>()
+        .map_err(|e| anyhow!("invoice is not base32: {:?}", e))?;
+    let data =
+        RawDataPart::from_base32(&data).map_err(|e| anyhow!("invalid invoice data: {:?}", e))?;
+    let invoice = RawBolt11Invoice { hrp, data };
+
+    let amount_msat = invoice
+        .amount_pico_btc()
+        .map(|pico| pico / 10)
+        .filter(|msat| *msat > 0)
+        .ok_or_else(|| anyhow!("pooled invoices must have an amount"))?;
+    let payment_hash = invoice
+        .payment_hash()
+        .ok_or_else(|| anyhow!("invoice has no payment hash"))?
+        .0
+        .into_inner();
+    let expiry = invoice
+        .expiry_time()
+        .map_or(DEFAULT_EXPIRY_TIME, |e| e.as_seconds());
+    Ok(PooledInvoice {
+        payment_hash,
+        amount_msat,
+        expires_at: invoice.data.timestamp.as_unix_timestamp() + expiry,
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::bech32::ToBase32;
+    use crate::bitcoin::hashes::sha256;
+    use crate::bitcoin::Network;
+    use crate::credentials::Nobody;
+    use crate::invoice_pool::OfflineLimits;
+    use crate::lightning::ln::PaymentSecret;
+    use crate::lightning_invoice::{Currency, InvoiceBuilder};
+    use crate::pb::cln::InvoiceRequest;
+    use vls_protocol::msgs::SignInvoice;
+    use vls_protocol::serde_bolt::Octets;
+    use vls_protocol_signer::handler::Handler;
+
+    fn sign_invoice(amount_msat: u64, hash: u8) -> Message {
+        let raw = InvoiceBuilder::new(Currency::Bitcoin)
+            .description("pooled".to_string())
+            .payment_hash(sha256::Hash::hash(&[hash]))
+            .payment_secret(PaymentSecret([1; 32]))
+            .amount_milli_satoshis(amount_msat)
+            .current_timestamp()
+            .min_final_cltv_expiry_delta(144)
+            .build_raw()
+            .unwrap();
+        Message::SignInvoice(SignInvoice {
+            u5bytes: Octets(raw.data.to_base32().iter().map(|u| u.to_u8()).collect()),
+            hrp: Octets(raw.hrp.to_string().into_bytes()),
+        })
+    }
+
+    #[test]
+    fn test_pooled_invoice_limits() {
+        let signer = Signer::new(vec![1; 32], Network::Bitcoin, Nobody::default()).unwrap();
+        let handler = signer.handler().unwrap();
+        let node = handler.node();
+        let policy = SignerPolicy {
+            offline_limits: Some(OfflineLimits {
+                max_invoice_msat: 1000,
+                max_total_msat: 2500,
+            }),
+            ..Default::default()
+        };
+        let pooled = vec![model::Request::Invoice(InvoiceRequest {
+            label: format!("{}1", LABEL_PREFIX),
+            ..Default::default()
+        })];
+
+        // Invoices that are not pooled are not limited.
+        let big = sign_invoice(5000, 0);
+        assert!(signer
+            .check_pooled_invoice(&policy, &big, &[], node)
+            .unwrap()
+            .is_none());
+        assert!(signer
+            .check_pooled_invoice(&policy, &big, &pooled, node)
+            .is_err());
+
+        for i in 1..=2 {
+            let msg = sign_invoice(1000, i);
+            let invoice = signer
+                .check_pooled_invoice(&policy, &msg, &pooled, node)
+                .unwrap()
+                .unwrap();
+            signer.record_pooled_invoice(invoice, node);
+        }
+        // The aggregate limit survives a reconnect, since it is part
+        // of the signer state.
+        assert!(signer.state.lock().unwrap().get_entry(STATE_KEY).is_some());
+        assert!(signer
+            .check_pooled_invoice(&policy, &sign_invoice(1000, 3), &pooled, node)
+            .is_err());
+        assert!(signer
+            .check_pooled_invoice(&policy, &sign_invoice(500, 3), &pooled, node)
+            .unwrap()
+            .is_some());
+
+        // Expired invoices are no longer outstanding.
+        assert!(signer.pooled_invoices(node, u64::MAX).is_empty());
+    }
+}
@@ -0,0 +1,98 @@
+//! Proofs that the user controls a node.
+//!
+//! LSPs and other services ask users to sign a challenge with the node
+//! key, in the format of `lightningd`'s `signmessage`: the recovery id
+//! plus 31, followed by the compact signature, encoded as zbase32.
+//! Since the signer holds the node key, the proof can be created
+//! without the node being scheduled.
+use super::attestation::recover_signer;
+use super::Signer;
+use crate::bitcoin::secp256k1::PublicKey;
+use anyhow::{anyhow, Result};
+
+const ZBASE32_ALPHABET: &[u8; 32] = b"ybndrfg8ejkmcpqxot1uwisza345h769";
+
+impl Signer {
+    /// Sign `challenge` with the node key, returning the zbase32
+    /// encoded signature as `lightningd`'s `signmessage` does.
+    pub fn sign_ownership_proof(&self, challenge: &[u8]) -> Result<String> {
+        let (sig, recovery_id) = self.sign_message(challenge.to_vec())?;
+        let mut proof = vec![recovery_id + 31];
+        proof.extend(sig);
+        Ok(zbase32_encode(&proof))
+    }
+}
+
+/// Check that `proof` is a signature of `challenge` by the node
+/// `node_id`, as returned by [`Signer::sign_ownership_proof`].
+pub fn verify_ownership_proof(node_id: &[u8], challenge: &[u8], proof: &str) -> Result<()> {
+    let proof = zbase32_decode(proof)?;
+    if proof.len() != 65 || !(31..35).contains(&proof[0]) {
+        return Err(anyhow!("malformed ownership proof"));
+    }
+    let mut sig = proof[1..].to_vec();
+    sig.push(proof[0] - 31);
+    if recover_signer(challenge, &sig)? != PublicKey::from_slice(node_id)? {
+        return Err(anyhow!("ownership proof is not signed by the node"));
+    }
+    Ok(())
+}
+
+fn zbase32_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for b in data {
+        buffer = (buffer << 8) | *b as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ZBASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ZBASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+fn zbase32_decode(data: &str) -> Result<Vec<u8>> {
+    let mut out = vec![];
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in data.bytes() {
+        let v = ZBASE32_ALPHABET
+            .iter()
+            .position(|a| *a == c)
+            .ok_or_else(|| anyhow!("invalid zbase32 character {:?}", c as char))?;
+        buffer = (buffer << 5) | v as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::Network;
+    use crate::credentials;
+
+    #[test]
+    fn test_zbase32() {
+        assert_eq!(zbase32_encode(&[0]), "yy");
+        assert_eq!(zbase32_encode(&[0xff, 0xff]), "999o");
+        let data: Vec<u8> = (0..65).collect();
+        assert_eq!(zbase32_decode(&zbase32_encode(&data)).unwrap(), data);
+    }
+
+    #[test]
+    fn test_ownership_proof() {
+        let signer =
+            Signer::new(vec![0u8; 32], Network::Bitcoin, credentials::Nobody::new()).unwrap();
+        let proof = signer.sign_ownership_proof(b"lsp challenge").unwrap();
+        verify_ownership_proof(&signer.node_id(), b"lsp challenge", &proof).unwrap();
+        assert!(verify_ownership_proof(&signer.node_id(), b"other", &proof).is_err());
+    }
+}
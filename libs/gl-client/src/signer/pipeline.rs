@@ -0,0 +1,103 @@
+//! Order the signature requests that can be resolved concurrently.
+//!
+//! `lightningd` talks to the signer over one connection per hsmd
+//! client, e.g., one per channel daemon, and each client waits for
+//! the response before sending its next request. Requests from
+//! different clients therefore do not depend on each other, and can
+//! be claimed, signed and answered concurrently, while the requests
+//! of a single client are handled in the order they arrived. This
+//! matters most when the signer reconnects and the node has a backlog
+//! of requests queued up.
+use crate::pb::{HsmRequest, HsmRequestContext};
+use std::collections::{HashMap, VecDeque};
+
+/// The hsmd client a request originates from, `None` for the main
+/// daemon.
+pub(crate) type Lane = Option<(Vec<u8>, u64)>;
+
+pub(crate) fn lane(req: &HsmRequest) -> Lane {
+    match &req.context {
+        Some(HsmRequestContext { dbid: 0, .. }) | None => None,
+        Some(c) => Some((c.node_id.clone(), c.dbid)),
+    }
+}
+
+/// Requests waiting for the previous request of their lane to
+/// complete.
+#[derive(Default)]
+pub(crate) struct Lanes {
+    /// Lanes with a request in flight, and the ones queued behind it.
+    busy: HashMap<Lane, VecDeque<HsmRequest>>,
+}
+
+impl Lanes {
+    /// Add a request, returning it if it can be handled right away.
+    pub(crate) fn push(&mut self, req: HsmRequest) -> Option<HsmRequest> {
+        match self.busy.get_mut(&lane(&req)) {
+            Some(queue) => {
+                queue.push_back(req);
+                None
+            }
+            None => {
+                self.busy.insert(lane(&req), VecDeque::new());
+                Some(req)
+            }
+        }
+    }
+
+    /// Mark the request in flight on `lane` as completed, returning
+    /// the next request of the lane, if any.
+    pub(crate) fn complete(&mut self, lane: &Lane) -> Option<HsmRequest> {
+        let next = self.busy.get_mut(lane)?.pop_front();
+        if next.is_none() {
+            self.busy.remove(lane);
+        }
+        next
+    }
+
+    /// The number of requests waiting for their lane.
+    pub(crate) fn queued(&self) -> usize {
+        self.busy.values().map(|q| q.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(request_id: u32, dbid: u64) -> HsmRequest {
+        HsmRequest {
+            request_id,
+            context: Some(HsmRequestContext {
+                node_id: vec![2; 33],
+                dbid,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lanes() {
+        let mut lanes = Lanes::default();
+        assert_eq!(lanes.push(request(1, 1)).map(|r| r.request_id), Some(1));
+        // Different client, can run concurrently.
+        assert_eq!(lanes.push(request(2, 2)).map(|r| r.request_id), Some(2));
+        // Same client as the first one, has to wait.
+        assert!(lanes.push(request(3, 1)).is_none());
+        assert!(lanes.push(request(4, 1)).is_none());
+        // The main daemon is its own lane, with or without a context.
+        assert!(lanes.push(request(5, 0)).is_some());
+        assert!(lanes.push(HsmRequest::default()).is_none());
+        assert_eq!(lanes.queued(), 3);
+
+        let first = lane(&request(1, 1));
+        assert_eq!(lanes.complete(&first).map(|r| r.request_id), Some(3));
+        assert_eq!(lanes.complete(&first).map(|r| r.request_id), Some(4));
+        assert!(lanes.complete(&first).is_none());
+        // The lane is free again.
+        assert!(lanes.push(request(6, 1)).is_some());
+        assert_eq!(lanes.complete(&lane(&request(2, 2))), None);
+        assert_eq!(lanes.queued(), 1);
+    }
+}
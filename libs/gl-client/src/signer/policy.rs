@@ -0,0 +1,120 @@
+//! Runtime-adjustable signer policies.
+//!
+//! The VLS validator policy is fixed when the validator factory is
+//! created. Long-running signers should not have to drop the request
+//! stream to change a limit, so the signer hands VLS a factory that
+//! delegates to a replaceable inner factory, and swaps that out when
+//! the policy changes.
+use crate::invoice_pool::OfflineLimits;
+use gl_signer_core::PolicyLimits;
+use lightning_signer::bitcoin::secp256k1::PublicKey;
+use lightning_signer::bitcoin::Network;
+use lightning_signer::channel::ChannelId;
+use lightning_signer::policy::simple_validator::{SimplePolicy, SimpleValidatorFactory};
+use lightning_signer::policy::validator::{Validator, ValidatorFactory};
+use lightning_signer::policy::Policy;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+
+/// The parts of the signer policy that may be changed at runtime.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignerPolicy {
+    /// Maximum fee we accept to pay when routing a payment.
+    pub max_routing_fee_msat: u64,
+    /// Maximum number of invoices tracked by the signer.
+    pub max_invoices: usize,
+    /// Maximum value of a single HTLC, `None` for the VLS default.
+    pub max_htlc_value_sat: Option<u64>,
+    /// Maximum channel size, `None` for the VLS default.
+    pub max_channel_size_sat: Option<u64>,
+    /// Addresses the node may send funds to in addition to the
+    /// wallet, e.g., a cold wallet.
+    pub allowlist: Vec<String>,
+    /// Hex-encoded authcodes of runes that are no longer accepted.
+    /// All runes share the same unique id, so the authcode is what
+    /// identifies them.
+    pub rune_blacklist: Vec<String>,
+    /// The limits of pooled invoices, checked before signing one, see
+    /// [`crate::invoice_pool`]. `None` to not limit them.
+    #[serde(default)]
+    pub offline_limits: Option<OfflineLimits>,
+}
+
+impl Default for SignerPolicy {
+    fn default() -> Self {
+        SignerPolicy {
+            // Relaxed max_routing_fee since we no longer have the
+            // presplitter which was causing the HTLCs to be smaller.
+            max_routing_fee_msat: 1_000_000,
+            // Increase the invoices limit. Results in a larger state,
+            // but bumping into this is rather annoying.
+            max_invoices: 10_000,
+            max_htlc_value_sat: None,
+            max_channel_size_sat: None,
+            allowlist: vec![],
+            rune_blacklist: vec![],
+            offline_limits: None,
+        }
+    }
+}
+
+impl SignerPolicy {
+    /// The policy the VLS validator checks every request against.
+    pub(crate) fn vls_policy(&self, network: Network) -> SimplePolicy {
+        gl_signer_core::make_policy(
+            network,
+            &PolicyLimits {
+                max_routing_fee_msat: self.max_routing_fee_msat,
+                max_invoices: self.max_invoices,
+                max_htlc_value_sat: self.max_htlc_value_sat,
+                max_channel_size_sat: self.max_channel_size_sat,
+            },
+        )
+    }
+
+    pub fn is_blacklisted(&self, authcode: &[u8]) -> bool {
+        let authcode = hex::encode(authcode);
+        self.rune_blacklist
+            .iter()
+            .any(|a| a.eq_ignore_ascii_case(&authcode))
+    }
+}
+
+/// A [`ValidatorFactory`] whose inner factory can be replaced while
+/// the signer is running. Validators are created per request, so the
+/// new policy applies from the next request on.
+pub(crate) struct ReloadableValidatorFactory {
+    inner: RwLock<Arc<SimpleValidatorFactory>>,
+}
+
+impl ReloadableValidatorFactory {
+    pub(crate) fn new(inner: SimpleValidatorFactory) -> Self {
+        ReloadableValidatorFactory {
+            inner: RwLock::new(Arc::new(inner)),
+        }
+    }
+
+    pub(crate) fn replace(&self, inner: SimpleValidatorFactory) {
+        *self.inner.write().unwrap() = Arc::new(inner);
+    }
+
+    fn current(&self) -> Arc<SimpleValidatorFactory> {
+        self.inner.read().unwrap().clone()
+    }
+}
+
+impl ValidatorFactory for ReloadableValidatorFactory {
+    fn make_validator(
+        &self,
+        network: Network,
+        node_id: PublicKey,
+        channel_id: Option<ChannelId>,
+    ) -> Arc<dyn Validator> {
+        self.current().make_validator(network, node_id, channel_id)
+    }
+
+    fn policy(&self, network: Network) -> Box<dyn Policy> {
+        self.current().policy(network)
+    }
+}
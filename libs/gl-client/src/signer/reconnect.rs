@@ -0,0 +1,148 @@
+//! React to the network of the host going away and coming back.
+//!
+//! While the host is offline, the signer keeps failing to reach the
+//! scheduler, and the [`supervisor`](super::supervisor) waits longer
+//! and longer before each attempt. The host app usually learns about
+//! connectivity changes first, and tells the signer with
+//! [`Signer::network_changed`], which cuts the current wait short and
+//! retries right away. [`Signer::reconnect`] also drops a connection
+//! that still seems to work, e.g., because it goes over the old
+//! network.
+//!
+//! The signer also tracks since when it failed to reach the scheduler
+//! or the node. Once that is longer than the threshold, it publishes
+//! [`Event::SignerOffline`], and [`Event::SignerReconnected`] once
+//! it is attached again.
+//!
+//! [`Signer::network_changed`]: super::Signer::network_changed
+//! [`Signer::reconnect`]: super::Signer::reconnect
+use crate::events::Event;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// How long the signer may be detached before it reports being
+/// offline by default.
+pub const DEFAULT_OFFLINE_THRESHOLD: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Default)]
+struct Detached {
+    since: Option<Instant>,
+    reported: bool,
+}
+
+#[derive(Clone)]
+pub(crate) struct Reconnect {
+    network: Arc<Notify>,
+    reattach: Arc<Notify>,
+    threshold: Duration,
+    detached: Arc<Mutex<Detached>>,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Reconnect {
+            network: Arc::new(Notify::new()),
+            reattach: Arc::new(Notify::new()),
+            threshold: DEFAULT_OFFLINE_THRESHOLD,
+            detached: Arc::default(),
+        }
+    }
+}
+
+impl Reconnect {
+    pub(crate) fn set_threshold(&mut self, threshold: Duration) {
+        self.threshold = threshold;
+    }
+
+    /// Wake all waits of [`Reconnect::backoff`].
+    pub(crate) fn network_changed(&self) {
+        self.network.notify_waiters();
+    }
+
+    /// Wake all waits of [`Reconnect::backoff`] and
+    /// [`Reconnect::requested`].
+    pub(crate) fn reconnect(&self) {
+        self.reattach.notify_waiters();
+        self.network.notify_waiters();
+    }
+
+    /// Resolves once [`Reconnect::reconnect`] is called.
+    pub(crate) async fn requested(&self) {
+        self.reattach.notified().await
+    }
+
+    /// Wait for `backoff`, or until the network changes. Returns
+    /// whether the network changed.
+    pub(crate) async fn backoff(&self, backoff: Duration) -> bool {
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => false,
+            _ = self.network.notified() => true,
+        }
+    }
+
+    /// Record that the signer failed to reach the scheduler or the
+    /// node, returning the event to publish if it is now offline.
+    pub(crate) fn failed(&self) -> Option<Event> {
+        let mut detached = self.detached.lock().unwrap();
+        let since = *detached.since.get_or_insert_with(Instant::now);
+        if detached.reported || since.elapsed() < self.threshold {
+            return None;
+        }
+        detached.reported = true;
+        Some(Event::SignerOffline {
+            offline_secs: since.elapsed().as_secs(),
+        })
+    }
+
+    /// Record that the signer reached the scheduler or the node,
+    /// returning the event to publish if it was offline.
+    pub(crate) fn connected(&self) -> Option<Event> {
+        let detached = std::mem::take(&mut *self.detached.lock().unwrap());
+        match detached {
+            Detached {
+                since: Some(since),
+                reported: true,
+            } => Some(Event::SignerReconnected {
+                offline_secs: since.elapsed().as_secs(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_events() {
+        let mut reconnect = Reconnect::default();
+        reconnect.set_threshold(Duration::ZERO);
+        assert_eq!(reconnect.connected(), None);
+        assert_eq!(
+            reconnect.failed(),
+            Some(Event::SignerOffline { offline_secs: 0 })
+        );
+        // Reported only once per outage.
+        assert_eq!(reconnect.failed(), None);
+        assert_eq!(
+            reconnect.connected(),
+            Some(Event::SignerReconnected { offline_secs: 0 })
+        );
+
+        reconnect.set_threshold(DEFAULT_OFFLINE_THRESHOLD);
+        assert_eq!(reconnect.failed(), None);
+        assert_eq!(reconnect.connected(), None);
+    }
+
+    #[tokio::test]
+    async fn test_network_change_cuts_backoff_short() {
+        let reconnect = Reconnect::default();
+        let waiting = reconnect.backoff(Duration::from_secs(3600));
+        tokio::pin!(waiting);
+        assert!(futures::poll!(&mut waiting).is_pending());
+        reconnect.network_changed();
+        assert!(waiting.await);
+    }
+}
@@ -17,6 +17,10 @@ pub struct Reporter {}
 
 impl Reporter {
     pub async fn report(r: pb::scheduler::SignerRejection) {
+        // Tests and fuzzers reject requests by the thousands.
+        if cfg!(any(test, fuzzing)) {
+            return;
+        }
         log::warn!("Delivering report {:?}", r);
         let tls = crate::tls::TlsConfig::new();
         let uri = crate::utils::scheduler_uri();
@@ -1,117 +1,119 @@
 //! Resolver utilities to match incoming requests against the request
-//! context and find a justifications.
+//! context and find a justifications. The matching itself lives in
+//! [`gl_signer_core`], so it can run without the transport.
 
+use crate::amount::Msat;
+use crate::bitcoin::consensus::encode::deserialize;
+use crate::bitcoin::psbt::PartiallySignedTransaction;
+use crate::bitcoin::secp256k1::PublicKey;
 use crate::signer::{model::Request, Error};
+use gl_signer_core::ContextRequest;
 use vls_protocol::msgs::Message;
 pub struct Resolver {}
 
 impl Resolver {
-    /// Attempt to find a resolution for a given request. We default
-    /// to failing, and allowlist individual matches between pending
-    /// context requests and the signer request being resolved. Where
-    /// possible we also verify the contents of the request against
-    /// the contents of the context request. TODOs in here may
-    /// indicate ways to strengthen the verification.
-    pub fn try_resolve(req: &Message, reqctx: &Vec<Request>) -> Result<(), Error> {
-        log::trace!("Resolving {:?}", req);
-        // Some requests do not need a justification. For example we
-        // reconnect automatically, so there may not even be a context
-        // request pending which would skip the entire stack below, so
-        // we do an early pass:
-        let accept = match req {
-            // Commands that simply have no context to check against
-            Message::GetHeartbeat(_) => true,
-            Message::Ecdh(_) => true,
-            Message::Ping(_) => true,
-            Message::Pong(_) => true,
-            Message::SignChannelAnnouncement(_) => true,
-            Message::SignChannelUpdate(_) => true,
-            Message::SignNodeAnnouncement(_) => true,
-            Message::CheckPubKey(_) => true,
-            // Duplicate verification with VLS, we defer to VLS
-            Message::GetChannelBasepoints(_) => true,
-            Message::ValidateCommitmentTx(_) => true,
-            Message::SignWithdrawal(_) => true,
-            Message::SetupChannel(_) => true,
-            Message::GetPerCommitmentPoint(_) => true,
-            Message::ValidateRevocation(_) => true,
-            Message::NewChannel(_) => true,
-            Message::SignCommitmentTx(_) => true,
-            Message::SignGossipMessage(_) => true,
-            Message::SignMutualCloseTx(_) => true,
-            Message::SignMutualCloseTx2(_) => true,
-            Message::SignRemoteCommitmentTx(_) => true,
-            Message::SignRemoteCommitmentTx2(_) => true,
-            Message::SignRemoteHtlcTx(_) => true,
-            // Resolution of an existing HTLC, we should never not try
-            // to grab funds if we can.
-            Message::SignPenaltyToUs(_) => true,
-            Message::SignAnyPenaltyToUs(_) => true,
-            Message::SignAnyDelayedPaymentToUs(_) => true,
-            Message::SignAnyLocalHtlcTx(_) => true,
-            Message::SignAnyRemoteHtlcToUs(_) => true,
-	    Message::LockOutpoint(_) => true,
-	    Message::CheckOutpoint(_) => true,
-	    Message::SignAnyChannelAnnouncement(_) => true,
-	    Message::RevokeCommitmentTx(_) => true,
-	    Message::ForgetChannel(_) => true,
-            // Default to rejecting, punting the decision to the next
-            // step.
-            _ => false,
-        };
-
-        // If we found a resolution, then there is no point in trying
-        // to match up further.
-        if accept {
-            log::trace!(
-                "Request {:?} resolved with no context request required",
-                req
-            );
-            return Ok(());
-        }
-
-        for cr in reqctx {
-            let accept = match (req, cr) {
-                (Message::SignMessage(m1), Request::SignMessage(m2)) => {
-                    m1.message.0.clone() == m2.message.as_bytes()
-                }
-                (Message::NewChannel(m1), Request::FundChannel(m2)) => {
-                    // Different node_id? Reject!
-                    m1.node_id.0 == m2.id.as_slice()
-                    // TODO: Add `close_to` to allowlist for the close
-                    // later on
-                }
-                (Message::NewChannel(m1), Request::GlFundChannel(m2)) => {
-                    // Different node_id? Reject!
-                    m1.node_id.0 == m2.node_id.as_slice()
-                }
-                (Message::SignInvoice(_l), Request::GlCreateInvoice(_r)) => true,
-                (Message::SignInvoice(_l), Request::Invoice(_r)) => {
-                    // TODO: This could be strengthened by parsing the
-                    // invoice from `l.u5bytes` and verify the
-                    // description, amount and (maybe) payment_hash
-                    true
-                }
-                (Message::PreapproveInvoice(l), Request::Pay(r)) => {
-                    l.invstring.0 == r.bolt11.as_bytes()
-                }
-                (Message::PreapproveInvoice(l), Request::PreApproveInvoice(r)) => {
-                    // Manually calling preapproveinvoice should
-                    // always be allowed. The bolt11 string have to
-                    // match.
-                    l.invstring.0 == r.bolt11().as_bytes()
-                }
-                (_, _) => false,
-            };
+    /// Attempt to find a resolution for a given request, see
+    /// [`gl_signer_core::try_resolve`].
+    pub fn try_resolve(
+        req: &Message,
+        reqctx: &[Request],
+        funding_pubkey: Option<&PublicKey>,
+    ) -> Result<(), Error> {
+        let ctx: Vec<ContextRequest> = reqctx.iter().map(context).collect();
+        gl_signer_core::try_resolve(req, &ctx, funding_pubkey).map_err(|e| match e {
+            gl_signer_core::Error::Unresolved(ser) => Error::Resolver(ser, reqctx.to_vec()),
+            e => Error::Other(anyhow::anyhow!(e)),
+        })
+    }
+}
 
-            // Did we find a resolution? If yes we can stop here.
-            if accept {
-                log::trace!("Request {:?} approved with context request {:?}", req, cr);
-                return Ok(());
+/// Reduce a decoded context request to the parts the signer checks.
+pub(crate) fn context(r: &Request) -> ContextRequest {
+    match r {
+        Request::SignMessage(r) => ContextRequest::SignMessage {
+            message: r.message.clone(),
+        },
+        Request::FundChannel(r) => ContextRequest::FundChannel {
+            node_id: r.id.clone(),
+        },
+        #[cfg(feature = "legacy-proto")]
+        Request::GlFundChannel(r) => ContextRequest::FundChannel {
+            node_id: r.node_id.clone(),
+        },
+        Request::FundChannelStart(r) => ContextRequest::FundChannel {
+            node_id: r.id.clone(),
+        },
+        Request::MultiFundChannel(r) => ContextRequest::MultiFundChannel {
+            // Destinations are given as `id@host:port`, or just `id`.
+            node_ids: r
+                .destinations
+                .iter()
+                .filter_map(|d| hex::decode(d.id.split('@').next()?).ok())
+                .collect(),
+        },
+        Request::FundChannelComplete(r) => {
+            // An unparseable PSBT leaves no outputs, and the channel
+            // setup is rejected.
+            let funding = funding_outputs(&r.psbt).unwrap_or_default();
+            ContextRequest::FundChannelComplete {
+                node_id: r.id.clone(),
+                funding_txid: funding.txid,
+                outputs: funding.outputs,
             }
         }
-
-        let ser = req.inner().as_vec();
-        Err(Error::Resolver(ser, reqctx.to_vec()))
+        #[cfg(feature = "legacy-proto")]
+        Request::GlCreateInvoice(r) => ContextRequest::CreateInvoice {
+            preimage: Some(r.preimage.clone()).filter(|p| !p.is_empty()),
+        },
+        Request::Invoice(r) => ContextRequest::CreateInvoice {
+            preimage: r.preimage.clone(),
+        },
+        Request::CreateInvoice(r) => ContextRequest::CreateInvoice {
+            preimage: Some(r.preimage.clone()).filter(|p| !p.is_empty()),
+        },
+        Request::SignInvoice(_) => ContextRequest::CreateInvoice { preimage: None },
+        Request::Pay(r) => ContextRequest::Pay {
+            bolt11: r.bolt11.clone(),
+        },
+        #[cfg(feature = "legacy-proto")]
+        Request::GlPay(r) => ContextRequest::GlPay {
+            bolt11: r.bolt11.clone(),
+        },
+        Request::PreApproveInvoice(r) => ContextRequest::PreapproveInvoice {
+            bolt11: r.bolt11().to_string(),
+        },
+        Request::PreApproveKeysend(r) => ContextRequest::PreapproveKeysend {
+            destination: r.destination().to_vec(),
+            payment_hash: r.payment_hash().to_vec(),
+            amount_msat: Msat::from(&r.amount_msat).to_msat(),
+        },
+        _ => ContextRequest::Other,
     }
 }
+
+/// A funding transaction, as the context of a channel setup needs it.
+#[derive(Default)]
+struct FundingOutputs {
+    txid: Vec<u8>,
+    /// The value and `scriptPubKey` of each output.
+    outputs: Vec<(u64, Vec<u8>)>,
+}
+
+/// The txid and the outputs of the transaction in a base64 encoded
+/// PSBT.
+fn funding_outputs(psbt: &str) -> Option<FundingOutputs> {
+    use base64::Engine;
+    let raw = base64::engine::general_purpose::STANDARD
+        .decode(psbt)
+        .ok()?;
+    let psbt: PartiallySignedTransaction = deserialize(&raw).ok()?;
+    let tx = psbt.unsigned_tx;
+    Some(FundingOutputs {
+        txid: tx.txid()[..].to_vec(),
+        outputs: tx
+            .output
+            .iter()
+            .map(|o| (o.value, o.script_pubkey.to_bytes()))
+            .collect(),
+    })
+}
@@ -0,0 +1,187 @@
+//! Sources for the signer's seed.
+//!
+//! Applications should not have to keep the seed around as a plain
+//! byte array just to hand it to [`Signer::new`]. A [`SeedProvider`]
+//! fetches the seed from wherever it is stored when the signer is
+//! created, e.g., the secure enclave of a phone or a PKCS#11 token,
+//! so the only copy in memory is the one the signer needs to derive
+//! its keys.
+use super::Signer;
+use crate::credentials::TlsConfigProvider;
+use crate::secret::SecretBytes;
+use lightning_signer::bitcoin::Network;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SeedError {
+    #[error("seed must be at least 32 bytes, got {0}")]
+    Invalid(usize),
+
+    #[error("seed is unavailable: {0}")]
+    Unavailable(String),
+
+    #[cfg(feature = "pkcs11")]
+    #[error("PKCS#11 error: {0}")]
+    Pkcs11(#[from] cryptoki::error::Error),
+}
+
+/// Provides the seed of the signer when it is created.
+pub trait SeedProvider: Send + Sync {
+    /// Return the seed. Only the first 32 bytes are used.
+    fn seed(&self) -> Result<SecretBytes, SeedError>;
+}
+
+/// A seed that is already in memory.
+pub struct RawSeed(SecretBytes);
+
+impl RawSeed {
+    pub fn new<S: Into<SecretBytes>>(seed: S) -> Self {
+        RawSeed(seed.into())
+    }
+}
+
+impl SeedProvider for RawSeed {
+    fn seed(&self) -> Result<SecretBytes, SeedError> {
+        Ok(self.0.clone())
+    }
+}
+
+/// A seed retrieved through a callback, for platforms where the seed
+/// is kept by the OS, e.g., in the iOS Secure Enclave or the Android
+/// Keystore, and the bindings unlock it on demand.
+pub struct CallbackSeedProvider {
+    callback: Box<dyn Fn() -> Result<Vec<u8>, String> + Send + Sync>,
+}
+
+impl CallbackSeedProvider {
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn() -> Result<Vec<u8>, String> + Send + Sync + 'static,
+    {
+        CallbackSeedProvider {
+            callback: Box::new(callback),
+        }
+    }
+}
+
+impl SeedProvider for CallbackSeedProvider {
+    fn seed(&self) -> Result<SecretBytes, SeedError> {
+        (self.callback)()
+            .map(SecretBytes::from)
+            .map_err(SeedError::Unavailable)
+    }
+}
+
+/// A seed stored as a data object on a PKCS#11 token, which includes
+/// TPMs through `tpm2-pkcs11`.
+#[cfg(feature = "pkcs11")]
+pub struct Pkcs11SeedProvider {
+    module: std::path::PathBuf,
+    pin: String,
+    label: String,
+}
+
+#[cfg(feature = "pkcs11")]
+impl Pkcs11SeedProvider {
+    /// Read the data object labelled `label` from the first token
+    /// of the PKCS#11 `module`, logging in with `pin`.
+    pub fn new<P: Into<std::path::PathBuf>>(module: P, pin: &str, label: &str) -> Self {
+        Pkcs11SeedProvider {
+            module: module.into(),
+            pin: pin.to_string(),
+            label: label.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "pkcs11")]
+impl SeedProvider for Pkcs11SeedProvider {
+    fn seed(&self) -> Result<SecretBytes, SeedError> {
+        use cryptoki::context::{CInitializeArgs, Pkcs11};
+        use cryptoki::object::{Attribute, AttributeType, ObjectClass};
+        use cryptoki::session::UserType;
+        use cryptoki::types::AuthPin;
+
+        let pkcs11 = Pkcs11::new(&self.module)?;
+        pkcs11.initialize(CInitializeArgs::OsThreads)?;
+        let slot = *pkcs11
+            .get_slots_with_token()?
+            .first()
+            .ok_or_else(|| SeedError::Unavailable("no PKCS#11 token found".to_string()))?;
+
+        let session = pkcs11.open_ro_session(slot)?;
+        session.login(UserType::User, Some(&AuthPin::new(self.pin.clone())))?;
+        let object = *session
+            .find_objects(&[
+                Attribute::Class(ObjectClass::DATA),
+                Attribute::Label(self.label.as_bytes().to_vec()),
+            ])?
+            .first()
+            .ok_or_else(|| SeedError::Unavailable(format!("no object labelled {}", self.label)))?;
+
+        let seed = session
+            .get_attributes(object, &[AttributeType::Value])?
+            .into_iter()
+            .find_map(|a| match a {
+                Attribute::Value(v) => Some(v),
+                _ => None,
+            })
+            .ok_or_else(|| SeedError::Unavailable("seed object has no value".to_string()))?;
+        session.logout()?;
+        Ok(seed.into())
+    }
+}
+
+impl Signer {
+    /// Create a signer with the seed from `provider`.
+    pub fn with_seed_provider<T>(
+        provider: &dyn SeedProvider,
+        network: Network,
+        creds: T,
+    ) -> Result<Signer, anyhow::Error>
+    where
+        T: TlsConfigProvider + 'static,
+    {
+        let seed = provider.seed()?;
+        if seed.len() < 32 {
+            return Err(SeedError::Invalid(seed.len()).into());
+        }
+        Signer::new(seed, network, creds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials;
+
+    #[test]
+    fn test_seed_providers() {
+        let raw = Signer::with_seed_provider(
+            &RawSeed::new(vec![1; 32]),
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .unwrap();
+        let callback = Signer::with_seed_provider(
+            &CallbackSeedProvider::new(|| Ok(vec![1; 32])),
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .unwrap();
+        assert_eq!(raw.node_id(), callback.node_id());
+
+        assert!(Signer::with_seed_provider(
+            &RawSeed::new(vec![1; 16]),
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .is_err());
+        assert!(Signer::with_seed_provider(
+            &CallbackSeedProvider::new(|| Err("locked".to_string())),
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,315 @@
+//! Self-test of the signer.
+//!
+//! Before attaching to a live node, integrators may want to make sure
+//! that the seed material they load is intact, and that the
+//! cryptographic primitives work on the platform they run on. The
+//! self-test first runs each check on a signer for the all-zero
+//! seed, whose keys are known, and then on a scratch signer for the
+//! actual seed. The scratch signer keeps its own state, so the checks
+//! leave no traces in the state of the node.
+use super::attestation::recover_signer;
+use super::Signer;
+use crate::bitcoin::hashes::{sha256, Hash};
+use crate::bitcoin::psbt::PartiallySignedTransaction;
+use crate::bitcoin::secp256k1::ecdsa::{RecoverableSignature, RecoveryId, Signature};
+use crate::bitcoin::secp256k1::{Message, PublicKey, Secp256k1};
+use crate::bitcoin::util::sighash::SighashCache;
+use crate::bitcoin::{
+    EcdsaSighashType, OutPoint, PackedLockTime, Script, Sequence, Transaction, TxIn, TxOut, Txid,
+    Witness,
+};
+use crate::credentials::Nobody;
+use crate::derivation;
+use crate::lightning::ln::PaymentSecret;
+use crate::lightning_invoice::{Currency, InvoiceBuilder};
+use crate::secret::SecretBytes;
+use anyhow::{anyhow, Result};
+use bech32::ToBase32;
+use lightning_signer::wallet::Wallet;
+use serde::Serialize;
+use std::sync::Arc;
+use vls_protocol::model::Utxo;
+use vls_protocol::msgs::{self, Message as HsmMessage, SerBolt, SignInvoice, SignWithdrawal};
+use vls_protocol::psbt::StreamedPSBT;
+use vls_protocol::serde_bolt::{Array, Octets, WithSize};
+use vls_protocol_signer::approver::PositiveApprover;
+use vls_protocol_signer::handler::Handler;
+
+/// The node id for the all-zero seed.
+const REFERENCE_NODE_ID: &str =
+    "02058e8b6c2ad363ec59aa136429256d745164c2bdc87f98f0a68690ec2c5c9b0b";
+
+/// The key of the first wallet address for the all-zero seed.
+const REFERENCE_WALLET_KEY: &str =
+    "039d376035664f9305a3d6af279fce1f699b4e787fa260feac2ad4e30865b8a346";
+
+const TEST_MESSAGE: &[u8] = b"greenlight signer self-test";
+
+/// The outcome of one check of the self-test.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CheckResult {
+    pub passed: bool,
+    /// Why the check failed, `None` if it passed.
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SelfTestReport {
+    /// The node key is derived from the seed as expected.
+    pub key_derivation: CheckResult,
+    /// Messages are signed with the node key.
+    pub message_signing: CheckResult,
+    /// Invoices are signed with the node key.
+    pub invoice_signing: CheckResult,
+    /// Onchain wallet inputs are signed with the wallet keys.
+    pub psbt_signing: CheckResult,
+}
+
+impl SelfTestReport {
+    /// Whether all checks passed.
+    pub fn passed(&self) -> bool {
+        [
+            &self.key_derivation,
+            &self.message_signing,
+            &self.invoice_signing,
+            &self.psbt_signing,
+        ]
+        .iter()
+        .all(|c| c.passed)
+    }
+}
+
+/// The keys a signer under test must have.
+struct Expected {
+    node_id: PublicKey,
+    /// The key of the first wallet address, if known.
+    wallet_key: Option<PublicKey>,
+}
+
+type Target = (&'static str, Result<(Signer, Expected), String>);
+type Check = fn(&Signer, &Expected) -> Result<()>;
+
+impl Signer {
+    /// Check that the signer derives the expected keys, and signs
+    /// messages, invoices and PSBTs correctly. Meant to be called
+    /// before attaching to the node.
+    pub fn self_test(&self) -> SelfTestReport {
+        let targets: [Target; 2] = [
+            ("reference", self.reference_target()),
+            ("seed", self.own_target()),
+        ];
+        SelfTestReport {
+            key_derivation: run(&targets, check_key_derivation),
+            message_signing: run(&targets, check_message_signing),
+            invoice_signing: run(&targets, check_invoice_signing),
+            psbt_signing: run(&targets, check_psbt_signing),
+        }
+    }
+
+    fn reference_target(&self) -> Result<(Signer, Expected), String> {
+        let expected = Expected {
+            node_id: parse_key(REFERENCE_NODE_ID)?,
+            wallet_key: Some(parse_key(REFERENCE_WALLET_KEY)?),
+        };
+        let signer =
+            Signer::new(vec![0u8; 32], self.network, Nobody::new()).map_err(|e| e.to_string())?;
+        Ok((signer, expected))
+    }
+
+    fn own_target(&self) -> Result<(Signer, Expected), String> {
+        let expected = Expected {
+            node_id: PublicKey::from_slice(&self.id).map_err(|e| e.to_string())?,
+            wallet_key: None,
+        };
+        let signer = Signer::new(
+            SecretBytes::from(&self.secret[..]),
+            self.network,
+            Nobody::new(),
+        )
+        .map_err(|e| e.to_string())?;
+        Ok((signer, expected))
+    }
+}
+
+fn parse_key(hex: &str) -> Result<PublicKey, String> {
+    hex::decode(hex)
+        .map_err(|e| e.to_string())
+        .and_then(|k| PublicKey::from_slice(&k).map_err(|e| e.to_string()))
+}
+
+/// Run `check` on all `targets`, stopping at the first failure.
+fn run(targets: &[Target], check: Check) -> CheckResult {
+    for (name, target) in targets {
+        let res = match target {
+            Ok((signer, expected)) => check(signer, expected).map_err(|e| e.to_string()),
+            Err(e) => Err(format!("creating signer: {}", e)),
+        };
+        if let Err(e) = res {
+            return CheckResult {
+                passed: false,
+                error: Some(format!("{}: {}", name, e)),
+            };
+        }
+    }
+    CheckResult {
+        passed: true,
+        error: None,
+    }
+}
+
+fn check_key_derivation(signer: &Signer, expected: &Expected) -> Result<()> {
+    let derived = derivation::node_id(&signer.secret)?;
+    if derived != expected.node_id {
+        return Err(anyhow!("derived node id {}", derived));
+    }
+    if signer.id != expected.node_id.serialize() {
+        return Err(anyhow!(
+            "signer reports node id {}",
+            hex::encode(&signer.id)
+        ));
+    }
+    Ok(())
+}
+
+fn check_message_signing(signer: &Signer, expected: &Expected) -> Result<()> {
+    let (mut sig, recovery_id) = signer.sign_message(TEST_MESSAGE.to_vec())?;
+    sig.push(recovery_id);
+    if recover_signer(TEST_MESSAGE, &sig)? != expected.node_id {
+        return Err(anyhow!("message is not signed by the node key"));
+    }
+    Ok(())
+}
+
+fn check_invoice_signing(signer: &Signer, expected: &Expected) -> Result<()> {
+    let raw = InvoiceBuilder::new(Currency::from(signer.network))
+        .description("self-test".to_string())
+        .payment_hash(sha256::Hash::hash(TEST_MESSAGE))
+        .payment_secret(PaymentSecret([1; 32]))
+        .amount_milli_satoshis(1_000)
+        .current_timestamp()
+        .min_final_cltv_expiry_delta(144)
+        .build_raw()
+        .map_err(|e| anyhow!("building invoice: {:?}", e))?;
+    let req = SignInvoice {
+        u5bytes: Octets(raw.data.to_base32().iter().map(|u| u.to_u8()).collect()),
+        hrp: Octets(raw.hrp.to_string().into_bytes()),
+    };
+    let sig = signer.sign_invoice(req.as_vec())?;
+    let sig =
+        RecoverableSignature::from_compact(&sig[..64], RecoveryId::from_i32(sig[64] as i32)?)?;
+    let key = Secp256k1::verification_only()
+        .recover_ecdsa(&Message::from_slice(&raw.signable_hash())?, &sig)?;
+    if key != expected.node_id {
+        return Err(anyhow!("invoice is not signed by the node key"));
+    }
+    Ok(())
+}
+
+/// Sign a PSBT spending an output of the first wallet address back
+/// to the same address, and verify the witness.
+fn check_psbt_signing(signer: &Signer, expected: &Expected) -> Result<()> {
+    let handler = signer.handler_with_approver(Arc::new(PositiveApprover()))?;
+    let script = handler
+        .node()
+        .get_native_address(&[0])
+        .map_err(|e| anyhow!("deriving wallet address: {:?}", e))?
+        .script_pubkey();
+
+    let prev = TxOut {
+        value: 100_000,
+        script_pubkey: script.clone(),
+    };
+    let outpoint = OutPoint {
+        txid: Txid::from_inner([1; 32]),
+        vout: 0,
+    };
+    let tx = Transaction {
+        version: 2,
+        lock_time: PackedLockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: outpoint,
+            script_sig: Script::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::default(),
+        }],
+        output: vec![TxOut {
+            value: 99_500,
+            script_pubkey: script.clone(),
+        }],
+    };
+    let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx.clone())?;
+    psbt.inputs[0].witness_utxo = Some(prev.clone());
+
+    let req = SignWithdrawal {
+        utxos: Array(vec![Utxo {
+            txid: outpoint.txid,
+            outnum: outpoint.vout,
+            amount: prev.value,
+            keyindex: 0,
+            is_p2sh: false,
+            script: Octets(script.to_bytes()),
+            close_info: None,
+            is_in_coinbase: false,
+        }]),
+        psbt: WithSize(StreamedPSBT::new(psbt)),
+    };
+    let reply = handler
+        .handle(HsmMessage::SignWithdrawal(req))
+        .map_err(|e| anyhow!("signing PSBT: {:?}", e))?;
+    let psbt = match msgs::from_vec(reply.0.as_vec())? {
+        HsmMessage::SignWithdrawalReply(r) => r.psbt.0,
+        m => return Err(anyhow!("unexpected reply {:?}", m)),
+    };
+
+    let witness = psbt.inputs[0]
+        .final_script_witness
+        .as_ref()
+        .ok_or_else(|| anyhow!("input was not signed"))?
+        .to_vec();
+    let (sig, key) = match witness.as_slice() {
+        [sig, key] => (sig, key),
+        _ => return Err(anyhow!("unexpected witness {:?}", witness)),
+    };
+    let key = crate::bitcoin::PublicKey::from_slice(key)?;
+    let wpkh = key
+        .wpubkey_hash()
+        .ok_or_else(|| anyhow!("wallet key is not compressed"))?;
+    if Script::new_v0_p2wpkh(&wpkh) != script {
+        return Err(anyhow!("witness key does not match the wallet address"));
+    }
+    if let Some(wallet_key) = expected.wallet_key {
+        if key.inner != wallet_key {
+            return Err(anyhow!("derived wallet key {}", key));
+        }
+    }
+
+    let sighash = SighashCache::new(&tx).segwit_signature_hash(
+        0,
+        &Script::new_p2pkh(&key.pubkey_hash()),
+        prev.value,
+        EcdsaSighashType::All,
+    )?;
+    let der = match sig.split_last() {
+        Some((&flag, der)) if flag == EcdsaSighashType::All as u8 => der,
+        _ => return Err(anyhow!("unexpected sighash flag")),
+    };
+    Secp256k1::verification_only().verify_ecdsa(
+        &Message::from_slice(&sighash[..])?,
+        &Signature::from_der(der)?,
+        &key.inner,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::Network;
+
+    #[test]
+    fn test_self_test() {
+        let signer = Signer::new(vec![7u8; 32], Network::Bitcoin, Nobody::new()).unwrap();
+        let report = signer.self_test();
+        assert!(report.passed(), "{:?}", report);
+    }
+}
@@ -0,0 +1,333 @@
+//! Supervise the tasks of the signer loop.
+//!
+//! The signer loop consists of three tasks: the connection to the
+//! scheduler, waiting for the node to be scheduled, the stream of
+//! requests from the node, and the resolvers that verify, sign and
+//! answer the requests, several of them concurrently, see
+//! [`pipeline`](super::pipeline). All of them run within the signer
+//! loop, and the [`Supervisor`] decides what happens when one of them
+//! fails:
+//!
+//!  - a failed connection or stream is restarted after a backoff,
+//!    which doubles with every consecutive failure, up to a cap,
+//!  - a panicking resolver drops its request, and the others keep
+//!    signing. The node retries the request once the signer
+//!    reconnects.
+//!
+//! Once a task fails more often than its [`RestartPolicy`] allows, the
+//! supervisor escalates: resolvers take down the stream, so the
+//! signer reconnects with fresh state, and the connection ends the
+//! signer loop. [`Signer::supervisor_status`] reports the state of
+//! each task, e.g., for a health endpoint, and
+//! [`Signer::subscribe_supervisor`] whenever it changes.
+//!
+//! [`Signer::supervisor_status`]: super::Signer::supervisor_status
+//! [`Signer::subscribe_supervisor`]: super::Signer::subscribe_supervisor
+use serde::Serialize;
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum Task {
+    /// Waiting for the node to be scheduled, and connecting to it.
+    Connection,
+    /// Reading the requests from the node.
+    Stream,
+    /// Verifying, signing and answering the requests.
+    Resolver,
+}
+
+const TASKS: [Task; 3] = [Task::Connection, Task::Stream, Task::Resolver];
+
+/// How often a task may fail before the supervisor escalates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct RestartPolicy {
+    /// The failures tolerated within `window`, `None` for no limit.
+    pub max_restarts: Option<u32>,
+    pub window: Duration,
+    /// How long to wait before restarting the task after its first
+    /// failure. Doubles with every consecutive failure.
+    pub backoff: Duration,
+    /// The longest wait before restarting the task.
+    pub max_backoff: Duration,
+}
+
+impl RestartPolicy {
+    /// The default policy of `task`. Connections and streams are
+    /// restarted indefinitely, since the node is stopped and moved
+    /// around as a matter of course, waiting at most a minute in
+    /// between. Resolvers may panic 5 times a minute.
+    pub fn default_for(task: Task) -> Self {
+        match task {
+            Task::Connection | Task::Stream => RestartPolicy {
+                max_restarts: None,
+                window: Duration::from_secs(60),
+                backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(60),
+            },
+            Task::Resolver => RestartPolicy {
+                max_restarts: Some(5),
+                window: Duration::from_secs(60),
+                backoff: Duration::ZERO,
+                max_backoff: Duration::ZERO,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum TaskState {
+    /// The task is not running, e.g., no node is scheduled.
+    Idle,
+    Running,
+    /// The task failed and is restarted after the backoff.
+    Restarting,
+    /// The task failed too often, and the supervisor escalated.
+    Failed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TaskStatus {
+    pub task: Task,
+    pub state: TaskState,
+    /// The failures since the signer started.
+    pub failures: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SupervisorStatus {
+    pub tasks: Vec<TaskStatus>,
+}
+
+impl SupervisorStatus {
+    /// Whether no task gave up.
+    pub fn is_healthy(&self) -> bool {
+        self.tasks.iter().all(|t| t.state != TaskState::Failed)
+    }
+}
+
+/// What to do after a task failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Decision {
+    Restart(Duration),
+    Escalate,
+}
+
+struct Entry {
+    policy: RestartPolicy,
+    state: TaskState,
+    failures: u64,
+    last_error: Option<String>,
+    /// The failures since the task last recovered.
+    consecutive: u32,
+    /// The failures within the window of the policy.
+    recent: VecDeque<Instant>,
+}
+
+/// The state of the supervised tasks, shared by the clones of the
+/// signer.
+#[derive(Clone)]
+pub(crate) struct Supervisor {
+    tasks: Arc<Mutex<HashMap<Task, Entry>>>,
+    /// The status, published whenever it changes.
+    status: Arc<watch::Sender<SupervisorStatus>>,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        let tasks = TASKS
+            .iter()
+            .map(|&task| {
+                let entry = Entry {
+                    policy: RestartPolicy::default_for(task),
+                    state: TaskState::Idle,
+                    failures: 0,
+                    last_error: None,
+                    consecutive: 0,
+                    recent: VecDeque::new(),
+                };
+                (task, entry)
+            })
+            .collect();
+        let (status, _) = watch::channel(snapshot(&tasks));
+        Supervisor {
+            tasks: Arc::new(Mutex::new(tasks)),
+            status: Arc::new(status),
+        }
+    }
+}
+
+impl Supervisor {
+    pub(crate) fn set_policy(&self, task: Task, policy: RestartPolicy) {
+        self.with(task, |e| e.policy = policy);
+    }
+
+    pub(crate) fn set_state(&self, task: Task, state: TaskState) {
+        self.with(task, |e| e.state = state);
+    }
+
+    /// Record that `task` works again, so the next failure is
+    /// restarted after the initial backoff.
+    pub(crate) fn recovered(&self, task: Task) {
+        self.with(task, |e| e.consecutive = 0);
+    }
+
+    /// Record that `task` failed with `error`, and decide whether to
+    /// restart it.
+    pub(crate) fn failed(&self, task: Task, error: String) -> Decision {
+        self.with(task, |e| {
+            let now = Instant::now();
+            e.failures += 1;
+            e.consecutive = e.consecutive.saturating_add(1);
+            e.last_error = Some(error);
+            e.recent.push_back(now);
+            while let Some(&first) = e.recent.front() {
+                if now.duration_since(first) < e.policy.window {
+                    break;
+                }
+                e.recent.pop_front();
+            }
+            match e.policy.max_restarts {
+                Some(max) if e.recent.len() > max as usize => {
+                    e.state = TaskState::Failed;
+                    e.recent.clear();
+                    Decision::Escalate
+                }
+                _ => {
+                    e.state = TaskState::Restarting;
+                    let factor = 2u32.saturating_pow(e.consecutive - 1);
+                    let backoff = e.policy.backoff.saturating_mul(factor);
+                    Decision::Restart(backoff.min(e.policy.max_backoff))
+                }
+            }
+        })
+    }
+
+    pub(crate) fn status(&self) -> SupervisorStatus {
+        self.status.borrow().clone()
+    }
+
+    /// Receive the status whenever it changes.
+    pub(crate) fn subscribe(&self) -> watch::Receiver<SupervisorStatus> {
+        self.status.subscribe()
+    }
+
+    fn with<T>(&self, task: Task, f: impl FnOnce(&mut Entry) -> T) -> T {
+        let mut tasks = self.tasks.lock().unwrap();
+        let res = f(tasks.get_mut(&task).unwrap());
+        let status = snapshot(&tasks);
+        self.status.send_if_modified(|s| {
+            let modified = *s != status;
+            *s = status;
+            modified
+        });
+        res
+    }
+}
+
+fn snapshot(tasks: &HashMap<Task, Entry>) -> SupervisorStatus {
+    SupervisorStatus {
+        tasks: TASKS
+            .iter()
+            .map(|task| {
+                let e = &tasks[task];
+                TaskStatus {
+                    task: *task,
+                    state: e.state,
+                    failures: e.failures,
+                    last_error: e.last_error.clone(),
+                }
+            })
+            .collect(),
+    }
+}
+
+/// The message a task panicked with.
+pub(crate) fn panic_message(panic: &(dyn Any + Send)) -> String {
+    match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(s), _) => s.to_string(),
+        (_, Some(s)) => s.clone(),
+        _ => "panicked".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supervisor() {
+        let supervisor = Supervisor::default();
+        supervisor.set_policy(
+            Task::Resolver,
+            RestartPolicy {
+                max_restarts: Some(1),
+                ..RestartPolicy::default_for(Task::Resolver)
+            },
+        );
+        assert_eq!(
+            supervisor.failed(Task::Resolver, "boom".to_string()),
+            Decision::Restart(Duration::ZERO)
+        );
+        assert!(supervisor.status().is_healthy());
+        assert_eq!(
+            supervisor.failed(Task::Resolver, "boom".to_string()),
+            Decision::Escalate
+        );
+
+        let status = supervisor.status();
+        assert!(!status.is_healthy());
+        let resolver = &status.tasks[2];
+        assert_eq!(resolver.state, TaskState::Failed);
+        assert_eq!(resolver.failures, 2);
+        assert_eq!(resolver.last_error.as_deref(), Some("boom"));
+
+        // Connections are restarted no matter how often they fail.
+        for _ in 0..100 {
+            assert!(matches!(
+                supervisor.failed(Task::Connection, "lost".to_string()),
+                Decision::Restart(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let supervisor = Supervisor::default();
+        let mut rx = supervisor.subscribe();
+        supervisor.set_state(Task::Stream, TaskState::Idle);
+        assert!(!rx.has_changed().unwrap());
+
+        supervisor.failed(Task::Stream, "lost".to_string());
+        assert!(rx.has_changed().unwrap());
+        let status = rx.borrow_and_update().clone();
+        assert_eq!(status.tasks[1].state, TaskState::Restarting);
+        assert_eq!(status, supervisor.status());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let supervisor = Supervisor::default();
+        let backoffs: Vec<_> = (0..8)
+            .map(|_| supervisor.failed(Task::Connection, "offline".to_string()))
+            .collect();
+        let secs = |s| Decision::Restart(Duration::from_secs(s));
+        assert_eq!(backoffs, [1, 2, 4, 8, 16, 32, 60, 60].map(secs).to_vec());
+
+        supervisor.recovered(Task::Connection);
+        assert_eq!(
+            supervisor.failed(Task::Connection, "offline".to_string()),
+            secs(1)
+        );
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let panic = std::panic::catch_unwind(|| panic!("resolver {}", 1)).unwrap_err();
+        assert_eq!(panic_message(&*panic), "resolver 1");
+    }
+}
@@ -0,0 +1,217 @@
+//! Sign the claim and refund transactions of swaps.
+//!
+//! The keys of the swap HTLCs, see [`crate::swaps`], are derived from
+//! the seed like the node's keys, and never leave the signer. Like
+//! withdrawals from the node's wallet, claims and refunds may only
+//! pay to the wallet or to an address on the allowlist, and their fee
+//! is capped.
+use super::Signer;
+use crate::bitcoin::secp256k1::{Message, PublicKey, Secp256k1};
+use crate::bitcoin::util::sighash::SighashCache;
+use crate::bitcoin::{EcdsaSig, EcdsaSighashType, Script, Transaction, Witness};
+use crate::derivation;
+use crate::swaps::{LockupOutput, SwapKind, SwapScript};
+use anyhow::{anyhow, ensure, Result};
+use lightning_signer::wallet::Wallet;
+use vls_protocol_signer::handler::Handler;
+
+/// The highest feerate claims and refunds may pay.
+pub const MAX_SWAP_FEERATE_SAT_PER_VB: u64 = 500;
+
+impl Signer {
+    /// Check that claims and refunds may pay to `destination`, the
+    /// address of the node's wallet at `wallet_path`, or, if
+    /// `wallet_path` is empty, an address on the allowlist.
+    pub fn check_swap_destination(&self, destination: &Script, wallet_path: &[u32]) -> Result<()> {
+        let handler = self.handler()?;
+        ensure!(
+            handler.node().allowlist_contains(destination, wallet_path),
+            "swap pays to {}, which is neither in the wallet nor on the allowlist",
+            destination
+        );
+        Ok(())
+    }
+
+    /// Sign `tx`, a claim or refund built with
+    /// [`crate::swaps::build_claim_tx`] or
+    /// [`crate::swaps::build_refund_tx`], with the key of the
+    /// `index`-th swap.
+    ///
+    /// `tx` must only spend `lockup`, and only pay to destinations
+    /// accepted by [`Signer::check_swap_destination`].
+    pub fn sign_swap_spend(
+        &self,
+        index: u32,
+        script: &SwapScript,
+        lockup: &LockupOutput,
+        tx: &mut Transaction,
+        wallet_path: &[u32],
+    ) -> Result<()> {
+        let key = derivation::swap_key(&self.secret, index)?;
+        let secp = Secp256k1::signing_only();
+        // We claim reverse swaps, and refund submarine swaps.
+        let ours = match script.kind {
+            SwapKind::Reverse => script.claim_pubkey,
+            SwapKind::Submarine => script.refund_pubkey,
+        };
+        ensure!(
+            PublicKey::from_secret_key(&secp, &key) == ours,
+            "swap {} does not use the key of index {}",
+            hex::encode(script.payment_hash),
+            index
+        );
+
+        let witness_script = script.witness_script();
+        let witness = tx.input.first().map(|i| i.witness.to_vec());
+        ensure!(
+            tx.input.len() == 1 && tx.input[0].previous_output == lockup.outpoint,
+            "transaction spends more than the swap output"
+        );
+        ensure!(
+            witness.as_ref().and_then(|w| w.last()) == Some(&witness_script.to_bytes()),
+            "transaction does not spend with the swap script"
+        );
+
+        for output in tx.output.iter() {
+            self.check_swap_destination(&output.script_pubkey, wallet_path)?;
+        }
+
+        let paid: u64 = tx.output.iter().map(|o| o.value).sum();
+        let fee = lockup
+            .value_sat
+            .checked_sub(paid)
+            .ok_or_else(|| anyhow!("transaction pays more than the swap output"))?;
+        let max_fee = (tx.weight() as u64).div_ceil(4) * MAX_SWAP_FEERATE_SAT_PER_VB;
+        ensure!(
+            fee <= max_fee,
+            "fee of {} sat exceeds the maximum of {} sat",
+            fee,
+            max_fee
+        );
+
+        let sighash = SighashCache::new(&*tx).segwit_signature_hash(
+            0,
+            &witness_script,
+            lockup.value_sat,
+            EcdsaSighashType::All,
+        )?;
+        let msg = Message::from_slice(&sighash[..])?;
+        let sig = EcdsaSig::sighash_all(secp.sign_ecdsa_low_r(&msg, &key));
+
+        let mut witness = witness.unwrap_or_default();
+        witness[0] = sig.to_vec();
+        tx.input[0].witness = Witness::from_vec(witness);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::hashes::{sha256, Hash};
+    use crate::bitcoin::secp256k1::{ecdsa::Signature, SecretKey};
+    use crate::bitcoin::util::address::Address;
+    use crate::bitcoin::{Network, PackedLockTime, TxOut};
+    use crate::credentials::Nobody;
+    use crate::signer::SignerPolicy;
+    use crate::swaps::{build_claim_tx, build_refund_tx};
+
+    fn script(kind: SwapKind, ours: PublicKey) -> SwapScript {
+        let secp = Secp256k1::new();
+        let theirs = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[2; 32]).unwrap());
+        let (claim_pubkey, refund_pubkey) = match kind {
+            SwapKind::Reverse => (ours, theirs),
+            SwapKind::Submarine => (theirs, ours),
+        };
+        SwapScript {
+            kind,
+            payment_hash: sha256::Hash::hash(&[3; 32]).into_inner(),
+            claim_pubkey,
+            refund_pubkey,
+            timeout_height: 800_000,
+        }
+    }
+
+    fn lockup(script: &SwapScript) -> LockupOutput {
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: 100_000,
+                script_pubkey: script.address(Network::Bitcoin).script_pubkey(),
+            }],
+        };
+        LockupOutput::find(&tx, script).unwrap()
+    }
+
+    fn verify(signer: &Signer, script: &SwapScript, lockup: &LockupOutput, tx: &Transaction) {
+        let witness = tx.input[0].witness.to_vec();
+        let sig = &witness[0];
+        assert_eq!(*sig.last().unwrap(), EcdsaSighashType::All as u8);
+        let sighash = SighashCache::new(tx)
+            .segwit_signature_hash(
+                0,
+                &script.witness_script(),
+                lockup.value_sat,
+                EcdsaSighashType::All,
+            )
+            .unwrap();
+        let msg = Message::from_slice(&sighash[..]).unwrap();
+        let sig = Signature::from_der(&sig[..sig.len() - 1]).unwrap();
+        Secp256k1::verification_only()
+            .verify_ecdsa(&msg, &sig, &signer.swap_pubkey(0).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_sign_swap_spend() {
+        let signer = Signer::new(vec![1; 32], Network::Bitcoin, Nobody::default()).unwrap();
+        let ours = signer.swap_pubkey(0).unwrap();
+        let destination =
+            Address::p2wpkh(&crate::bitcoin::PublicKey::new(ours), Network::Bitcoin).unwrap();
+
+        let s = script(SwapKind::Reverse, ours);
+        let output = lockup(&s);
+        let mut claim =
+            build_claim_tx(&s, &output, &[3; 32], destination.script_pubkey(), 2).unwrap();
+        // The destination is neither in the wallet nor on the allowlist.
+        assert!(signer
+            .sign_swap_spend(0, &s, &output, &mut claim, &[])
+            .is_err());
+
+        signer
+            .update_policy(SignerPolicy {
+                allowlist: vec![destination.to_string()],
+                ..Default::default()
+            })
+            .unwrap();
+        // Only the key of the swap signs.
+        assert!(signer
+            .sign_swap_spend(1, &s, &output, &mut claim, &[])
+            .is_err());
+        signer
+            .sign_swap_spend(0, &s, &output, &mut claim, &[])
+            .unwrap();
+        verify(&signer, &s, &output, &claim);
+
+        let s = script(SwapKind::Submarine, ours);
+        let output = lockup(&s);
+        let mut refund = build_refund_tx(&s, &output, destination.script_pubkey(), 2).unwrap();
+        signer
+            .sign_swap_spend(0, &s, &output, &mut refund, &[])
+            .unwrap();
+        verify(&signer, &s, &output, &refund);
+
+        let mut greedy = build_refund_tx(
+            &s,
+            &output,
+            destination.script_pubkey(),
+            MAX_SWAP_FEERATE_SAT_PER_VB + 1,
+        )
+        .unwrap();
+        assert!(signer
+            .sign_swap_spend(0, &s, &output, &mut greedy, &[])
+            .is_err());
+    }
+}
@@ -0,0 +1,280 @@
+//! Opt-in telemetry of the requests the signer rejected.
+//!
+//! A policy that is too strict for how a wallet is used shows up as
+//! failed payments on user devices, where the [audit
+//! log](super::AuditLog) is out of reach. With telemetry enabled, see
+//! [`Signer::with_telemetry`](super::Signer::with_telemetry), the
+//! signer counts its rejections by reason, and periodically `POST`s
+//! the counts as a [`TelemetryReport`] to an endpoint chosen by the
+//! application.
+//!
+//! Reports are anonymized: they do not name the node, and reasons
+//! only keep the names of the failed checks, e.g.,
+//! `validate_counterparty_htlc_sweep: validate_sweep`, dropping the
+//! amounts, scripts and ids that follow them.
+use super::Signer;
+use log::{debug, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How often the counts are reported if not configured.
+pub const DEFAULT_TELEMETRY_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectionKind {
+    /// The request violated a policy of the validator.
+    Policy,
+    /// The request did not match any authorized call.
+    Resolver,
+    /// The application denied signing at the signing gate.
+    Gate,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RejectionCount {
+    pub kind: RejectionKind,
+    pub reason: String,
+    pub count: u64,
+}
+
+/// The payload sent to the telemetry endpoint.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TelemetryReport {
+    pub signer_version: String,
+    pub network: String,
+    /// The rejections since the last report, ordered by kind and
+    /// reason.
+    pub rejections: Vec<RejectionCount>,
+}
+
+/// Where and how often to report, see
+/// [`Signer::with_telemetry`](super::Signer::with_telemetry).
+#[derive(Clone, Debug)]
+pub struct TelemetryConfig {
+    pub endpoint: String,
+    pub interval: Duration,
+    /// The client the reports are sent with, see
+    /// [`Config::http_client`](crate::config::Config::http_client)
+    /// for one that honors the configured proxy and timeouts.
+    pub http: reqwest::Client,
+}
+
+impl TelemetryConfig {
+    pub fn new(endpoint: &str) -> Self {
+        TelemetryConfig {
+            endpoint: endpoint.to_string(),
+            interval: DEFAULT_TELEMETRY_INTERVAL,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+}
+
+impl Signer {
+    /// Count the rejected requests, and report the counts to
+    /// `config.endpoint`. Telemetry is off unless enabled here.
+    pub fn with_telemetry(mut self, config: TelemetryConfig) -> Self {
+        self.telemetry = Some(Telemetry::new(config, self.network.to_string()));
+        self
+    }
+
+    pub(super) fn record_rejection(&self, kind: RejectionKind, reason: &str) {
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.record(kind, reason);
+        }
+    }
+}
+
+/// The rejection counts of a signer. Clones share the same counts.
+#[derive(Clone, Debug)]
+pub(crate) struct Telemetry {
+    config: TelemetryConfig,
+    network: String,
+    counts: Arc<Mutex<BTreeMap<(RejectionKind, String), u64>>>,
+}
+
+impl Telemetry {
+    pub(crate) fn new(config: TelemetryConfig, network: String) -> Self {
+        Telemetry {
+            config,
+            network,
+            counts: Arc::default(),
+        }
+    }
+
+    pub(crate) fn record(&self, kind: RejectionKind, reason: &str) {
+        let reason = match kind {
+            RejectionKind::Policy => anonymize(reason),
+            // Resolver errors print the request, but there is only
+            // one way to fail.
+            RejectionKind::Resolver => String::new(),
+            // The URI of the denied call, e.g., `/cln.Node/Pay`.
+            RejectionKind::Gate => reason.to_string(),
+        };
+        *self
+            .counts
+            .lock()
+            .unwrap()
+            .entry((kind, reason))
+            .or_insert(0) += 1;
+    }
+
+    /// The report of the rejections since the last one, resetting
+    /// the counts, or `None` if there were none.
+    fn take(&self) -> Option<TelemetryReport> {
+        let counts = std::mem::take(&mut *self.counts.lock().unwrap());
+        if counts.is_empty() {
+            return None;
+        }
+        Some(TelemetryReport {
+            signer_version: super::VERSION.to_string(),
+            network: self.network.clone(),
+            rejections: counts
+                .into_iter()
+                .map(|((kind, reason), count)| RejectionCount {
+                    kind,
+                    reason,
+                    count,
+                })
+                .collect(),
+        })
+    }
+
+    /// Add the counts of a report that could not be delivered back,
+    /// so they are sent with the next one.
+    fn restore(&self, report: TelemetryReport) {
+        let mut counts = self.counts.lock().unwrap();
+        for r in report.rejections {
+            *counts.entry((r.kind, r.reason)).or_insert(0) += r.count;
+        }
+    }
+
+    async fn send(&self) {
+        let report = match self.take() {
+            Some(report) => report,
+            None => return,
+        };
+        let res = self
+            .config
+            .http
+            .post(&self.config.endpoint)
+            .json(&report)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        match res {
+            Ok(_) => debug!("Delivered signer telemetry to {}", self.config.endpoint),
+            Err(e) => {
+                warn!(
+                    "Could not deliver signer telemetry to {}: {}",
+                    self.config.endpoint, e
+                );
+                self.restore(report);
+            }
+        }
+    }
+}
+
+/// Report the counts every interval. Never returns, and does nothing
+/// if telemetry is not enabled.
+pub(crate) async fn report_forever(telemetry: Option<&Telemetry>) {
+    let telemetry = match telemetry {
+        Some(t) => t,
+        None => return std::future::pending().await,
+    };
+    loop {
+        tokio::time::sleep(telemetry.config.interval).await;
+        telemetry.send().await;
+    }
+}
+
+/// Keep the names of the failed checks of a policy error, e.g.,
+/// `policy failure: validate_sweep: fee underflow: 2000 - 3000`
+/// becomes `validate_sweep`.
+fn anonymize(reason: &str) -> String {
+    let reason = reason
+        .trim_start_matches("temporary ")
+        .trim_start_matches("policy failure: ");
+    let checks: Vec<&str> = reason.split(": ").take_while(|s| is_check(s)).collect();
+    if checks.is_empty() {
+        "unknown".to_string()
+    } else {
+        checks.join(": ")
+    }
+}
+
+/// Whether `s` is the name of a check, e.g., `validate_sweep`. Hex
+/// strings such as ids or hashes never contain an underscore.
+fn is_check(s: &str) -> bool {
+    s.contains('_')
+        && s.starts_with(|c: char| c.is_ascii_lowercase())
+        && s.chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anonymize() {
+        assert_eq!(
+            anonymize("policy failure: validate_counterparty_htlc_sweep: validate_sweep: fee underflow: 2000 - 3000"),
+            "validate_counterparty_htlc_sweep: validate_sweep"
+        );
+        assert_eq!(
+            anonymize("temporary policy failure: validate_invoice: 02abcd"),
+            "validate_invoice"
+        );
+        assert_eq!(anonymize("policy failure: Invoice 7f00 exceeds"), "unknown");
+    }
+
+    #[test]
+    fn test_take() {
+        let telemetry =
+            Telemetry::new(TelemetryConfig::new("http://localhost:1"), "regtest".into());
+        assert_eq!(telemetry.take(), None);
+
+        telemetry.record(
+            RejectionKind::Policy,
+            "policy failure: validate_sweep: fee 1",
+        );
+        telemetry.clone().record(
+            RejectionKind::Policy,
+            "policy failure: validate_sweep: fee 2",
+        );
+        telemetry.record(RejectionKind::Resolver, "no approval for 0a1b");
+        let report = telemetry.take().unwrap();
+        assert_eq!(
+            report.rejections,
+            vec![
+                RejectionCount {
+                    kind: RejectionKind::Policy,
+                    reason: "validate_sweep".to_string(),
+                    count: 2,
+                },
+                RejectionCount {
+                    kind: RejectionKind::Resolver,
+                    reason: String::new(),
+                    count: 1,
+                },
+            ]
+        );
+        assert_eq!(telemetry.take(), None);
+
+        telemetry.restore(report);
+        assert_eq!(telemetry.take().unwrap().rejections.len(), 2);
+    }
+}
@@ -0,0 +1,68 @@
+//! WebSocket transport for the signer.
+//!
+//! Some environments block HTTP/2, and with it gRPC, on egress. For
+//! those the signer can attach to the node through a WebSocket
+//! endpoint instead. The framing is minimal: the endpoint sends every
+//! `HsmRequest` as a protobuf-encoded binary message, and the signer
+//! replies with the protobuf-encoded `HsmResponse`. The connection is
+//! authenticated with the same mTLS identity used for gRPC, and the
+//! requests go through the same verification as in
+//! [`Signer::run_once`].
+use super::{Error, Signer};
+use crate::pb::HsmRequest;
+use crate::tls::client_config;
+use anyhow::anyhow;
+use futures::{SinkExt, StreamExt};
+use log::{debug, trace, warn};
+use prost::Message as _;
+use std::sync::Arc;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::Connector;
+
+impl Signer {
+    /// Connect to the WebSocket endpoint of the node at `url`
+    /// (`wss://...`) and process requests from it until the
+    /// connection is closed.
+    pub async fn run_once_ws(&self, url: &str) -> Result<(), Error> {
+        debug!("Connecting to node at {} via websocket", url);
+        let connector =
+            Connector::Rustls(Arc::new(client_config(&self.tls).map_err(Error::Other)?));
+        let (mut socket, _) =
+            tokio_tungstenite::connect_async_tls_with_config(url, None, true, Some(connector))
+                .await
+                .map_err(|e| Error::WebSocket(Box::new(e)))?;
+
+        debug!("Starting to stream signer requests");
+        while let Some(msg) = socket.next().await {
+            let raw = match msg.map_err(|e| Error::WebSocket(Box::new(e)))? {
+                Message::Binary(raw) => raw,
+                Message::Close(_) => break,
+                // Pings are answered by tungstenite itself.
+                Message::Ping(_) | Message::Pong(_) => continue,
+                m => {
+                    warn!("Ignoring unexpected websocket message {:?}", m);
+                    continue;
+                }
+            };
+
+            let req = HsmRequest::decode(raw.as_slice())
+                .map_err(|e| Error::Other(anyhow!("decoding request: {}", e)))?;
+            let hex_req = hex::encode(&req.raw);
+            trace!("Received request {}", hex_req);
+
+            match self.process_request(req).await {
+                Ok(response) => {
+                    trace!("Sending response {}", hex::encode(&response.raw));
+                    socket
+                        .send(Message::Binary(response.encode_to_vec()))
+                        .await
+                        .map_err(|e| Error::WebSocket(Box::new(e)))?;
+                }
+                Err(e) => warn!("Ignoring error {} for request {}", e, hex_req),
+            }
+        }
+
+        warn!("Signer websocket closed, the node shouldn't do this.");
+        Ok(())
+    }
+}
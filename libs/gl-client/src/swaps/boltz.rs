@@ -0,0 +1,222 @@
+use super::SwapError;
+use log::debug;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, Duration};
+
+const PAIR_ID: &str = "BTC/BTC";
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CreateSwapRequest<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    pair_id: &'a str,
+    order_side: &'a str,
+    invoice: &'a str,
+    refund_public_key: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CreateReverseSwapRequest<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    pair_id: &'a str,
+    order_side: &'a str,
+    invoice_amount: u64,
+    preimage_hash: String,
+    claim_public_key: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SubmarineSwapResponse {
+    pub id: String,
+    pub address: String,
+    pub redeem_script: String,
+    pub expected_amount: u64,
+    pub timeout_block_height: u32,
+    #[serde(default)]
+    pub accept_zero_conf: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReverseSwapResponse {
+    pub id: String,
+    pub invoice: String,
+    pub lockup_address: String,
+    pub redeem_script: String,
+    pub onchain_amount: u64,
+    pub timeout_block_height: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SwapTransaction {
+    pub id: String,
+    pub hex: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SwapStatus {
+    pub status: String,
+    pub transaction: Option<SwapTransaction>,
+}
+
+impl SwapStatus {
+    /// Whether the lockup transaction was seen, either in the mempool
+    /// or confirmed.
+    pub fn is_locked_up(&self) -> bool {
+        self.status == "transaction.mempool" || self.status == "transaction.confirmed"
+    }
+
+    /// Whether the swap failed and will not make progress anymore.
+    pub fn is_failed(&self) -> bool {
+        matches!(
+            self.status.as_str(),
+            "swap.expired"
+                | "invoice.expired"
+                | "invoice.failedToPay"
+                | "transaction.failed"
+                | "transaction.lockupFailed"
+                | "transaction.refunded"
+        )
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BroadcastResponse {
+    transaction_id: String,
+}
+
+/// A client for the HTTP API of a Boltz-compatible swap provider.
+pub struct BoltzClient {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl BoltzClient {
+    pub fn new(base_url: &str) -> BoltzClient {
+        BoltzClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Send the requests through `client`, see
+    /// [`Config::http_client`](crate::config::Config::http_client)
+    /// for one that honors the configured proxy and timeouts.
+    pub fn with_http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    async fn post<I: Serialize, O: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &I,
+    ) -> Result<O, SwapError> {
+        let url = format!("{}/{}", self.base_url, path);
+        debug!("Calling swap provider at {}", url);
+        let response = self.client.post(&url).json(body).send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let reason = match response.json::<ErrorResponse>().await {
+                Ok(e) => e.error,
+                Err(_) => status.to_string(),
+            };
+            return Err(SwapError::Provider(reason));
+        }
+        Ok(response.json::<O>().await?)
+    }
+
+    /// Create a swap in which we pay onchain, and the provider pays
+    /// `invoice`.
+    pub async fn create_submarine_swap(
+        &self,
+        invoice: &str,
+        refund_pubkey: &[u8],
+    ) -> Result<SubmarineSwapResponse, SwapError> {
+        self.post(
+            "createswap",
+            &CreateSwapRequest {
+                kind: "submarine",
+                pair_id: PAIR_ID,
+                order_side: "sell",
+                invoice,
+                refund_public_key: hex::encode(refund_pubkey),
+            },
+        )
+        .await
+    }
+
+    /// Create a swap in which we pay an invoice for `amount_sat`, and
+    /// the provider locks the funds onchain.
+    pub async fn create_reverse_swap(
+        &self,
+        amount_sat: u64,
+        payment_hash: &[u8; 32],
+        claim_pubkey: &[u8],
+    ) -> Result<ReverseSwapResponse, SwapError> {
+        self.post(
+            "createswap",
+            &CreateReverseSwapRequest {
+                kind: "reversesubmarine",
+                pair_id: PAIR_ID,
+                order_side: "buy",
+                invoice_amount: amount_sat,
+                preimage_hash: hex::encode(payment_hash),
+                claim_public_key: hex::encode(claim_pubkey),
+            },
+        )
+        .await
+    }
+
+    pub async fn swap_status(&self, id: &str) -> Result<SwapStatus, SwapError> {
+        self.post("swapstatus", &serde_json::json!({ "id": id }))
+            .await
+    }
+
+    /// Poll the status of the swap until the lockup transaction was
+    /// seen, and return it.
+    pub async fn wait_for_lockup(
+        &self,
+        id: &str,
+        interval: Duration,
+    ) -> Result<SwapTransaction, SwapError> {
+        loop {
+            let status = self.swap_status(id).await?;
+            if status.is_failed() {
+                return Err(SwapError::Provider(format!(
+                    "swap failed: {}",
+                    status.status
+                )));
+            }
+            if status.is_locked_up() {
+                if let Some(tx) = status.transaction {
+                    return Ok(tx);
+                }
+            }
+            sleep(interval).await;
+        }
+    }
+
+    /// Broadcast a raw transaction through the provider, returning
+    /// its txid.
+    pub async fn broadcast(&self, tx_hex: &str) -> Result<String, SwapError> {
+        let res: BroadcastResponse = self
+            .post(
+                "broadcasttransaction",
+                &serde_json::json!({ "currency": "BTC", "transactionHex": tx_hex }),
+            )
+            .await?;
+        Ok(res.transaction_id)
+    }
+}
@@ -0,0 +1,418 @@
+//! Submarine swaps against Boltz-compatible providers.
+//!
+//! Swaps move funds between the onchain wallet and Lightning without
+//! opening or closing channels:
+//!
+//!  - [`submarine_swap`]: we lock funds onchain, and the provider
+//!    pays an invoice of our node.
+//!  - [`reverse_swap`]: our node pays an invoice of the provider, and
+//!    we claim the funds the provider locked onchain.
+//!
+//! The onchain HTLCs are claimed or refunded with keys that never
+//! leave the signer, see [`Signer::sign_swap_spend`], so the node
+//! never holds the swap keys.
+//!
+//! The provider decides the amounts of a swap. Both swaps check them
+//! against the requested amount and the most the swap may cost before
+//! any funds move.
+mod boltz;
+mod script;
+mod tx;
+
+pub use boltz::{BoltzClient, ReverseSwapResponse, SubmarineSwapResponse, SwapStatus};
+pub use script::{SwapKind, SwapScript};
+pub use tx::{build_claim_tx, build_refund_tx, LockupOutput};
+
+use crate::bitcoin::consensus::encode::{deserialize, serialize_hex};
+use crate::bitcoin::hashes::{sha256, Hash};
+use crate::bitcoin::{Network, Script, Transaction};
+use crate::lightning_invoice::Bolt11Invoice;
+use crate::node::ClnClient;
+use crate::pb::cln::{
+    amount_or_all, amount_or_any, Amount, AmountOrAll, AmountOrAny, InvoiceRequest, PayRequest,
+    WithdrawRequest,
+};
+use crate::signer::Signer;
+use std::convert::TryInto;
+use std::str::FromStr;
+use thiserror::Error;
+use tokio::time::Duration;
+
+/// How often to poll the provider for the swap status.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Error, Debug)]
+pub enum SwapError {
+    #[error("swap provider returned an error: {0}")]
+    Provider(String),
+
+    #[error("could not reach the swap provider: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("swap script mismatch: {0}")]
+    ScriptMismatch(String),
+
+    #[error("invalid swap transaction: {0}")]
+    Transaction(String),
+
+    #[error("swap costs {fee_sat} sat, more than the maximum of {max_fee_sat} sat")]
+    FeeTooHigh { fee_sat: u64, max_fee_sat: u64 },
+
+    #[error("signer refused the swap: {0}")]
+    Signer(anyhow::Error),
+
+    #[error("node returned an error: {0}")]
+    Node(#[from] Box<tonic::Status>),
+}
+
+/// A submarine swap for which we locked the funds onchain.
+///
+/// Keep it around until the swap completes: if the provider does not
+/// claim the funds, `script`, `key_index` and the lockup transaction
+/// are needed to refund them with [`build_refund_tx`] and
+/// [`Signer::sign_swap_spend`] after the timeout.
+#[derive(Clone, Debug)]
+pub struct SubmarineSwap {
+    pub id: String,
+    pub script: SwapScript,
+    pub key_index: u32,
+    pub address: String,
+    pub amount_sat: u64,
+    /// The lockup transaction paying to `address`.
+    pub lockup_tx: Vec<u8>,
+}
+
+/// Swap `amount_msat` of onchain funds to Lightning, refundable with
+/// the `key_index`-th swap key of `signer`.
+///
+/// Creates an invoice on the node, has the provider verify a swap
+/// for it, and pays the expected amount to the lockup address from
+/// the node's onchain wallet, unless it exceeds the invoice by more
+/// than `max_fee_sat`.
+pub async fn submarine_swap(
+    boltz: &BoltzClient,
+    node: &mut ClnClient,
+    signer: &Signer,
+    key_index: u32,
+    amount_msat: u64,
+    max_fee_sat: u64,
+    network: Network,
+) -> Result<SubmarineSwap, SwapError> {
+    let refund_pubkey = signer.swap_pubkey(key_index).map_err(SwapError::Signer)?;
+    let label: [u8; 16] = rand::random();
+    let invoice = node
+        .invoice(InvoiceRequest {
+            amount_msat: Some(AmountOrAny {
+                value: Some(amount_or_any::Value::Amount(Amount { msat: amount_msat })),
+            }),
+            description: "Submarine swap".to_string(),
+            label: format!("swap-{}", hex::encode(label)),
+            ..Default::default()
+        })
+        .await
+        .map_err(Box::new)?
+        .into_inner();
+
+    let swap = boltz
+        .create_submarine_swap(&invoice.bolt11, &refund_pubkey.serialize())
+        .await?;
+    let redeem_script = decode_hex(&swap.redeem_script)?;
+    let script = SwapScript {
+        kind: SwapKind::Submarine,
+        payment_hash: invoice
+            .payment_hash
+            .as_slice()
+            .try_into()
+            .map_err(|_| SwapError::Transaction("invalid payment hash".to_string()))?,
+        claim_pubkey: SwapScript::counterparty_pubkey(SwapKind::Submarine, &redeem_script)?,
+        refund_pubkey,
+        timeout_height: swap.timeout_block_height,
+    };
+    script.verify(&redeem_script, &swap.address, network)?;
+    check_submarine_fee(swap.expected_amount, amount_msat, max_fee_sat)?;
+
+    let res = node
+        .withdraw(WithdrawRequest {
+            destination: swap.address.clone(),
+            satoshi: Some(AmountOrAll {
+                value: Some(amount_or_all::Value::Amount(Amount {
+                    msat: swap.expected_amount * 1000,
+                })),
+            }),
+            ..Default::default()
+        })
+        .await
+        .map_err(Box::new)?
+        .into_inner();
+
+    Ok(SubmarineSwap {
+        id: swap.id,
+        script,
+        key_index,
+        address: swap.address,
+        amount_sat: swap.expected_amount,
+        lockup_tx: res.tx,
+    })
+}
+
+/// The parameters of a [`reverse_swap`].
+#[derive(Clone, Debug)]
+pub struct ReverseSwapRequest {
+    /// The amount of the invoice to pay.
+    pub amount_sat: u64,
+    /// The most the swap may cost: the provider's fee, including the
+    /// onchain fees it charges, plus the routing fees of the payment.
+    pub max_fee_sat: u64,
+    /// Where the claimed funds go.
+    pub destination: Script,
+    /// The path of `destination` in the node's wallet, or empty if it
+    /// is on the allowlist, see [`Signer::sign_swap_spend`].
+    pub wallet_path: Vec<u32>,
+    /// The feerate of the claim transaction.
+    pub feerate_sat_per_vb: u64,
+}
+
+/// Swap `req.amount_sat` from Lightning to `req.destination`
+/// onchain, claiming with the `key_index`-th swap key of `signer`.
+///
+/// The invoice from the provider is only settled once we claim the
+/// onchain funds, hence it is paid in the background while we wait
+/// for the lockup transaction. Returns the broadcast claim
+/// transaction.
+pub async fn reverse_swap(
+    boltz: &BoltzClient,
+    node: &mut ClnClient,
+    signer: &Signer,
+    key_index: u32,
+    req: &ReverseSwapRequest,
+    network: Network,
+) -> Result<Transaction, SwapError> {
+    // Once the invoice is paid we need to be able to claim.
+    signer
+        .check_swap_destination(&req.destination, &req.wallet_path)
+        .map_err(SwapError::Signer)?;
+    let claim_pubkey = signer.swap_pubkey(key_index).map_err(SwapError::Signer)?;
+    let preimage: [u8; 32] = rand::random();
+    let payment_hash = sha256::Hash::hash(&preimage).into_inner();
+
+    let swap = boltz
+        .create_reverse_swap(req.amount_sat, &payment_hash, &claim_pubkey.serialize())
+        .await?;
+    let redeem_script = decode_hex(&swap.redeem_script)?;
+    let script = SwapScript {
+        kind: SwapKind::Reverse,
+        payment_hash,
+        claim_pubkey,
+        refund_pubkey: SwapScript::counterparty_pubkey(SwapKind::Reverse, &redeem_script)?,
+        timeout_height: swap.timeout_block_height,
+    };
+    script.verify(&redeem_script, &swap.lockup_address, network)?;
+
+    let invoice = Bolt11Invoice::from_str(&swap.invoice)
+        .map_err(|e| SwapError::Provider(format!("invalid invoice: {}", e)))?;
+    if invoice.payment_hash()[..] != payment_hash[..] {
+        return Err(SwapError::Provider(
+            "invoice does not commit to our payment hash".to_string(),
+        ));
+    }
+    let routing_budget_msat =
+        routing_budget(req, invoice.amount_milli_satoshis(), swap.onchain_amount)?;
+
+    let mut payer = node.clone();
+    let bolt11 = swap.invoice.clone();
+    let payment = crate::runtime::spawn(async move {
+        payer
+            .pay(PayRequest {
+                bolt11,
+                maxfee: Some(Amount {
+                    msat: routing_budget_msat,
+                }),
+                ..Default::default()
+            })
+            .await
+    });
+
+    let lockup = boltz.wait_for_lockup(&swap.id, POLL_INTERVAL).await?;
+    let lockup_tx: Transaction =
+        deserialize(&decode_hex(lockup.hex.as_deref().unwrap_or_default())?)
+            .map_err(|e| SwapError::Transaction(e.to_string()))?;
+    let output = LockupOutput::find(&lockup_tx, &script)?;
+    if output.value_sat < swap.onchain_amount {
+        return Err(SwapError::Transaction(format!(
+            "lockup of {} sat is less than the promised {} sat",
+            output.value_sat, swap.onchain_amount
+        )));
+    }
+
+    let mut claim = build_claim_tx(
+        &script,
+        &output,
+        &preimage,
+        req.destination.clone(),
+        req.feerate_sat_per_vb,
+    )?;
+    signer
+        .sign_swap_spend(key_index, &script, &output, &mut claim, &req.wallet_path)
+        .map_err(SwapError::Signer)?;
+    boltz.broadcast(&serialize_hex(&claim)).await?;
+
+    payment
+        .await
+        .map_err(|e| SwapError::Provider(format!("payment task failed: {}", e)))?
+        .map_err(Box::new)?;
+    Ok(claim)
+}
+
+/// Check that the `expected_sat` of a submarine swap for an invoice of
+/// `amount_msat` does not cost more than `max_fee_sat`.
+fn check_submarine_fee(
+    expected_sat: u64,
+    amount_msat: u64,
+    max_fee_sat: u64,
+) -> Result<(), SwapError> {
+    let fee_sat = expected_sat.saturating_sub(amount_msat / 1000);
+    if fee_sat > max_fee_sat {
+        return Err(SwapError::FeeTooHigh {
+            fee_sat,
+            max_fee_sat,
+        });
+    }
+    Ok(())
+}
+
+/// The routing fees left for paying the invoice of `invoice_msat` of a
+/// reverse swap locking up `onchain_sat`, once the provider's fee is
+/// taken out of `req.max_fee_sat`.
+fn routing_budget(
+    req: &ReverseSwapRequest,
+    invoice_msat: Option<u64>,
+    onchain_sat: u64,
+) -> Result<u64, SwapError> {
+    if invoice_msat != Some(req.amount_sat * 1000) {
+        return Err(SwapError::Provider(format!(
+            "invoice is for {:?} msat instead of {} sat",
+            invoice_msat, req.amount_sat
+        )));
+    }
+    let fee_sat = req.amount_sat.saturating_sub(onchain_sat);
+    if fee_sat > req.max_fee_sat {
+        return Err(SwapError::FeeTooHigh {
+            fee_sat,
+            max_fee_sat: req.max_fee_sat,
+        });
+    }
+    Ok((req.max_fee_sat - fee_sat) * 1000)
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, SwapError> {
+    hex::decode(s).map_err(|e| SwapError::Provider(format!("invalid hex: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use crate::bitcoin::{OutPoint, PackedLockTime, TxOut, Txid};
+
+    fn keys() -> (PublicKey, PublicKey) {
+        let secp = Secp256k1::new();
+        (
+            PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap()),
+            PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[2; 32]).unwrap()),
+        )
+    }
+
+    pub(crate) fn script(kind: SwapKind) -> SwapScript {
+        let (claim, refund) = keys();
+        SwapScript {
+            kind,
+            payment_hash: sha256::Hash::hash(&[3; 32]).into_inner(),
+            claim_pubkey: claim,
+            refund_pubkey: refund,
+            timeout_height: 800_000,
+        }
+    }
+
+    #[test]
+    fn test_verify_script() {
+        for kind in [SwapKind::Submarine, SwapKind::Reverse] {
+            let s = script(kind);
+            let redeem = s.witness_script().to_bytes();
+            let address = s.address(Network::Bitcoin).to_string();
+            assert!(address.starts_with("bc1q"));
+            assert!(s.verify(&redeem, &address, Network::Bitcoin).is_ok());
+            assert!(s.verify(&redeem, &address, Network::Testnet).is_err());
+
+            let counterparty = SwapScript::counterparty_pubkey(kind, &redeem).unwrap();
+            match kind {
+                SwapKind::Submarine => assert_eq!(counterparty, s.claim_pubkey),
+                SwapKind::Reverse => assert_eq!(counterparty, s.refund_pubkey),
+            }
+
+            let mut other = s.clone();
+            other.timeout_height += 1;
+            assert!(other.verify(&redeem, &address, Network::Bitcoin).is_err());
+        }
+    }
+
+    #[test]
+    fn test_claim_and_refund() {
+        let s = script(SwapKind::Reverse);
+        let lockup_tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: 100_000,
+                script_pubkey: s.address(Network::Bitcoin).script_pubkey(),
+            }],
+        };
+        let lockup = LockupOutput::find(&lockup_tx, &s).unwrap();
+        assert_eq!(lockup.value_sat, 100_000);
+
+        let claim = build_claim_tx(&s, &lockup, &[3; 32], Script::new(), 2).unwrap();
+        let witness = claim.input[0].witness.to_vec();
+        assert_eq!(witness[1], vec![3; 32]);
+        assert_eq!(witness[2], s.witness_script().to_bytes());
+        assert!(claim.output[0].value < 100_000);
+        assert_eq!(claim.lock_time, PackedLockTime::ZERO);
+
+        let refund = build_refund_tx(&s, &lockup, Script::new(), 2).unwrap();
+        assert_eq!(refund.lock_time, PackedLockTime(800_000));
+        assert!(refund.input[0].witness.to_vec()[1].is_empty());
+
+        let dust = LockupOutput {
+            outpoint: OutPoint::new(Txid::all_zeros(), 0),
+            value_sat: 600,
+        };
+        assert!(build_refund_tx(&s, &dust, Script::new(), 2).is_err());
+    }
+
+    #[test]
+    fn test_swap_fees() {
+        assert!(check_submarine_fee(101_000, 100_000_000, 1_000).is_ok());
+        assert!(matches!(
+            check_submarine_fee(101_001, 100_000_000, 1_000),
+            Err(SwapError::FeeTooHigh { fee_sat: 1_001, .. })
+        ));
+
+        let req = ReverseSwapRequest {
+            amount_sat: 100_000,
+            max_fee_sat: 1_000,
+            destination: Script::new(),
+            wallet_path: vec![],
+            feerate_sat_per_vb: 2,
+        };
+        assert_eq!(
+            routing_budget(&req, Some(100_000_000), 99_400).unwrap(),
+            400_000
+        );
+        // The invoice must be for the requested amount.
+        assert!(routing_budget(&req, Some(100_000_001), 99_400).is_err());
+        assert!(routing_budget(&req, None, 99_400).is_err());
+        assert!(matches!(
+            routing_budget(&req, Some(100_000_000), 98_000),
+            Err(SwapError::FeeTooHigh { fee_sat: 2_000, .. })
+        ));
+    }
+}
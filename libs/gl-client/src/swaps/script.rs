@@ -0,0 +1,125 @@
+use super::SwapError;
+use crate::bitcoin::blockdata::opcodes::all::*;
+use crate::bitcoin::blockdata::script::{Builder, Instruction, Script};
+use crate::bitcoin::hashes::{ripemd160, Hash};
+use crate::bitcoin::secp256k1::PublicKey;
+use crate::bitcoin::{Address, Network};
+
+/// The direction of a swap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwapKind {
+    /// Onchain to Lightning: we lock the funds onchain, and the
+    /// provider claims them by paying our invoice.
+    Submarine,
+    /// Lightning to onchain: the provider locks the funds onchain,
+    /// and we claim them with the preimage of the invoice we paid.
+    Reverse,
+}
+
+/// The HTLC script the onchain funds of a swap are locked to.
+///
+/// The output can be claimed by `claim_pubkey` with the preimage of
+/// `payment_hash`, or refunded to `refund_pubkey` once
+/// `timeout_height` is reached.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SwapScript {
+    pub kind: SwapKind,
+    pub payment_hash: [u8; 32],
+    pub claim_pubkey: PublicKey,
+    pub refund_pubkey: PublicKey,
+    pub timeout_height: u32,
+}
+
+impl SwapScript {
+    /// Extract the provider's public key from the `redeem_script`
+    /// they sent, i.e., the refund key for reverse swaps, and the
+    /// claim key for submarine swaps.
+    ///
+    /// The script still needs to be checked with [`SwapScript::verify`].
+    pub fn counterparty_pubkey(
+        kind: SwapKind,
+        redeem_script: &[u8],
+    ) -> Result<PublicKey, SwapError> {
+        let script = Script::from(redeem_script.to_vec());
+        let keys: Vec<&[u8]> = script
+            .instructions()
+            .filter_map(|i| match i {
+                Ok(Instruction::PushBytes(b)) if b.len() == 33 => Some(b),
+                _ => None,
+            })
+            .collect();
+        let key = match kind {
+            SwapKind::Reverse => keys.last(),
+            SwapKind::Submarine => keys.first(),
+        };
+        key.and_then(|k| PublicKey::from_slice(k).ok())
+            .ok_or_else(|| SwapError::ScriptMismatch("no public key in redeem script".to_string()))
+    }
+
+    /// The witness script, matching the one used by Boltz.
+    pub fn witness_script(&self) -> Script {
+        let hash = ripemd160::Hash::hash(&self.payment_hash);
+        match self.kind {
+            SwapKind::Submarine => Builder::new()
+                .push_opcode(OP_HASH160)
+                .push_slice(&hash[..])
+                .push_opcode(OP_EQUAL)
+                .push_opcode(OP_IF)
+                .push_slice(&self.claim_pubkey.serialize())
+                .push_opcode(OP_ELSE)
+                .push_int(self.timeout_height as i64)
+                .push_opcode(OP_CLTV)
+                .push_opcode(OP_DROP)
+                .push_slice(&self.refund_pubkey.serialize())
+                .push_opcode(OP_ENDIF)
+                .push_opcode(OP_CHECKSIG)
+                .into_script(),
+            SwapKind::Reverse => Builder::new()
+                .push_opcode(OP_SIZE)
+                .push_int(32)
+                .push_opcode(OP_EQUAL)
+                .push_opcode(OP_IF)
+                .push_opcode(OP_HASH160)
+                .push_slice(&hash[..])
+                .push_opcode(OP_EQUALVERIFY)
+                .push_slice(&self.claim_pubkey.serialize())
+                .push_opcode(OP_ELSE)
+                .push_opcode(OP_DROP)
+                .push_int(self.timeout_height as i64)
+                .push_opcode(OP_CLTV)
+                .push_opcode(OP_DROP)
+                .push_slice(&self.refund_pubkey.serialize())
+                .push_opcode(OP_ENDIF)
+                .push_opcode(OP_CHECKSIG)
+                .into_script(),
+        }
+    }
+
+    /// The P2WSH address the funds are locked to.
+    pub fn address(&self, network: Network) -> Address {
+        Address::p2wsh(&self.witness_script(), network)
+    }
+
+    /// Check that the script and lockup address the provider sent
+    /// match what we expect, so we never send funds to, or wait for,
+    /// an output we can not claim or refund.
+    pub fn verify(
+        &self,
+        redeem_script: &[u8],
+        address: &str,
+        network: Network,
+    ) -> Result<(), SwapError> {
+        if self.witness_script().as_bytes() != redeem_script {
+            return Err(SwapError::ScriptMismatch(
+                "redeem script does not match".to_string(),
+            ));
+        }
+        if self.address(network).to_string() != address {
+            return Err(SwapError::ScriptMismatch(format!(
+                "lockup address {} does not match",
+                address
+            )));
+        }
+        Ok(())
+    }
+}
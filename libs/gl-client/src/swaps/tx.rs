@@ -0,0 +1,122 @@
+use super::script::SwapScript;
+use super::SwapError;
+use crate::bitcoin::{
+    OutPoint, PackedLockTime, Script, Sequence, Transaction, TxIn, TxOut, Witness,
+};
+
+/// Outputs below this value are not relayed.
+const DUST_LIMIT_SAT: u64 = 546;
+
+/// The signer signs with low-R, so signatures are at most 72 bytes
+/// including the sighash flag. Used to estimate the weight before
+/// signing.
+const MAX_SIGNATURE_LEN: usize = 72;
+
+/// The swap output we want to spend.
+#[derive(Clone, Debug)]
+pub struct LockupOutput {
+    pub outpoint: OutPoint,
+    pub value_sat: u64,
+}
+
+impl LockupOutput {
+    /// Find the output paying to `script` in the lockup transaction.
+    pub fn find(tx: &Transaction, script: &SwapScript) -> Result<LockupOutput, SwapError> {
+        let spk = Script::new_v0_p2wsh(&script.witness_script().wscript_hash());
+        tx.output
+            .iter()
+            .enumerate()
+            .find(|(_, o)| o.script_pubkey == spk)
+            .map(|(vout, o)| LockupOutput {
+                outpoint: OutPoint {
+                    txid: tx.txid(),
+                    vout: vout as u32,
+                },
+                value_sat: o.value,
+            })
+            .ok_or_else(|| {
+                SwapError::Transaction("lockup transaction does not pay to the swap".to_string())
+            })
+    }
+}
+
+/// Build a transaction claiming the swap output with the `preimage`,
+/// sending the funds to `destination`. It still needs to be signed
+/// with [`Signer::sign_swap_spend`](crate::signer::Signer::sign_swap_spend).
+pub fn build_claim_tx(
+    script: &SwapScript,
+    lockup: &LockupOutput,
+    preimage: &[u8; 32],
+    destination: Script,
+    feerate_sat_per_vb: u64,
+) -> Result<Transaction, SwapError> {
+    build_spend(
+        script,
+        lockup,
+        destination,
+        feerate_sat_per_vb,
+        preimage.to_vec(),
+        PackedLockTime::ZERO,
+    )
+}
+
+/// Build a transaction refunding the swap output to `destination`
+/// once the timeout is reached. It still needs to be signed with
+/// [`Signer::sign_swap_spend`](crate::signer::Signer::sign_swap_spend).
+pub fn build_refund_tx(
+    script: &SwapScript,
+    lockup: &LockupOutput,
+    destination: Script,
+    feerate_sat_per_vb: u64,
+) -> Result<Transaction, SwapError> {
+    build_spend(
+        script,
+        lockup,
+        destination,
+        feerate_sat_per_vb,
+        vec![],
+        PackedLockTime(script.timeout_height),
+    )
+}
+
+fn build_spend(
+    script: &SwapScript,
+    lockup: &LockupOutput,
+    destination: Script,
+    feerate_sat_per_vb: u64,
+    preimage: Vec<u8>,
+    lock_time: PackedLockTime,
+) -> Result<Transaction, SwapError> {
+    let witness_script = script.witness_script();
+    let mut tx = Transaction {
+        version: 2,
+        lock_time,
+        input: vec![TxIn {
+            previous_output: lockup.outpoint,
+            script_sig: Script::new(),
+            // Non-final, so the locktime is enforced for refunds.
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            // The signature is a placeholder until signed.
+            witness: Witness::from_vec(vec![
+                vec![0; MAX_SIGNATURE_LEN],
+                preimage,
+                witness_script.to_bytes(),
+            ]),
+        }],
+        output: vec![TxOut {
+            value: 0,
+            script_pubkey: destination,
+        }],
+    };
+
+    let fee = (tx.weight() as u64).div_ceil(4) * feerate_sat_per_vb;
+    let value = lockup.value_sat.saturating_sub(fee);
+    if value < DUST_LIMIT_SAT {
+        return Err(SwapError::Transaction(format!(
+            "output of {} sat after paying {} sat fees is dust",
+            value, fee
+        )));
+    }
+    tx.output[0].value = value;
+    Ok(tx)
+}
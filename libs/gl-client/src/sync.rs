@@ -0,0 +1,429 @@
+//! Refresh a local cache of the node's records with the changes only.
+//!
+//! Wallets usually keep a copy of the node's invoices, payments,
+//! forwards and channels, to show them without waiting for the node.
+//! Listing everything again whenever the app returns from the
+//! background gets slower the more records the node has. `lightningd`
+//! numbers the records of these subsystems with a `created` and an
+//! `updated` index, see [`crate::wait`], and the list methods can
+//! start at a given index. A [`Cursor`] holds the indexes the client
+//! has seen so far, and [`fetch`] returns what changed since:
+//!
+//! ```no_run
+//! # use gl_client::node::ClnClient;
+//! # use gl_client::sync::{self, Cursor};
+//! # async fn example(node: ClnClient, cursor: Cursor) -> anyhow::Result<()> {
+//! let delta = sync::fetch(&node, cursor).await?;
+//! for invoice in &delta.invoices {
+//!     // Insert or replace the invoice in the cache.
+//! }
+//! // Persist `delta.cursor` together with the cache.
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `listpeerchannels` has no indexes, so channels are always returned
+//! in full. Nodes have few of them, compared to the other records.
+//! Deleted records are not reported, see [`crate::housekeeping`].
+//!
+//! Mobile apps get a few seconds of background execution at a time,
+//! e.g., from `BGAppRefreshTask` on iOS or `WorkManager` on Android.
+//! [`run_once`] makes the most of them: it connects to the node,
+//! answers the signer requests the node has queued, and fetches the
+//! delta, all within a time budget. The returned [`Summary`] lists
+//! what could not be done in the background.
+use crate::channels::{list_channels, Channel};
+#[cfg(feature = "signer")]
+use crate::credentials::{NodeIdProvider, RuneProvider, TlsConfigProvider};
+use crate::node::ClnClient;
+use crate::pb::cln::listforwards_request::ListforwardsIndex;
+use crate::pb::cln::listinvoices_request::ListinvoicesIndex;
+use crate::pb::cln::listsendpays_request::ListsendpaysIndex;
+#[cfg(feature = "signer")]
+use crate::pb::cln::listsendpays_request::ListsendpaysStatus;
+use crate::pb::cln::{
+    ListforwardsForwards, ListforwardsRequest, ListinvoicesInvoices, ListinvoicesRequest,
+    ListsendpaysPayments, ListsendpaysRequest,
+};
+#[cfg(feature = "signer")]
+use crate::{scheduler::Scheduler, signer::Signer};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+#[cfg(feature = "signer")]
+use std::collections::BTreeSet;
+#[cfg(feature = "signer")]
+use std::time::Duration;
+#[cfg(feature = "signer")]
+use tokio::time::{sleep, timeout_at, Instant};
+#[cfg(feature = "signer")]
+use tonic::transport::Uri;
+
+/// How long the signer has to be idle before the node is assumed to
+/// have no more requests for it.
+#[cfg(feature = "signer")]
+const SIGNER_QUIET: Duration = Duration::from_secs(2);
+
+/// The next `created` and `updated` index to fetch of a subsystem.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexCursor {
+    pub created: u64,
+    pub updated: u64,
+}
+
+/// Where the client's cache stands. The default cursor fetches all
+/// records.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+    pub invoices: IndexCursor,
+    pub payments: IndexCursor,
+    pub forwards: IndexCursor,
+}
+
+/// The records created or updated since a [`Cursor`].
+#[derive(Clone, Debug, Default)]
+pub struct Delta {
+    /// Each record appears once, in its latest state, ordered by
+    /// creation.
+    pub invoices: Vec<ListinvoicesInvoices>,
+    pub payments: Vec<ListsendpaysPayments>,
+    pub forwards: Vec<ListforwardsForwards>,
+    /// All channels of the node.
+    pub channels: Vec<Channel>,
+    /// The cursor to fetch the next delta from.
+    pub cursor: Cursor,
+}
+
+impl Delta {
+    /// Whether no invoice, payment or forward changed.
+    pub fn is_empty(&self) -> bool {
+        self.invoices.is_empty() && self.payments.is_empty() && self.forwards.is_empty()
+    }
+}
+
+/// Fetch the records changed since `cursor`. The list calls are
+/// issued concurrently.
+pub async fn fetch(node: &ClnClient, cursor: Cursor) -> Result<Delta> {
+    let (
+        invoices_created,
+        invoices_updated,
+        payments_created,
+        payments_updated,
+        forwards_created,
+        forwards_updated,
+        channels,
+    ) = futures::try_join!(
+        invoices(node, ListinvoicesIndex::Created, cursor.invoices.created),
+        invoices(node, ListinvoicesIndex::Updated, cursor.invoices.updated),
+        payments(node, ListsendpaysIndex::Created, cursor.payments.created),
+        payments(node, ListsendpaysIndex::Updated, cursor.payments.updated),
+        forwards(node, ListforwardsIndex::Created, cursor.forwards.created),
+        forwards(node, ListforwardsIndex::Updated, cursor.forwards.updated),
+        async { list_channels(&mut node.clone()).await },
+    )?;
+
+    let mut next = cursor;
+    Ok(Delta {
+        invoices: merge(
+            invoices_created,
+            invoices_updated,
+            &mut next.invoices,
+            |i| (i.created_index, i.updated_index),
+        ),
+        payments: merge(
+            payments_created,
+            payments_updated,
+            &mut next.payments,
+            |p| (p.created_index, p.updated_index),
+        ),
+        forwards: merge(
+            forwards_created,
+            forwards_updated,
+            &mut next.forwards,
+            |f| (f.created_index, f.updated_index),
+        ),
+        channels,
+        cursor: next,
+    })
+}
+
+async fn invoices(
+    node: &ClnClient,
+    index: ListinvoicesIndex,
+    start: u64,
+) -> Result<Vec<ListinvoicesInvoices>> {
+    let req = ListinvoicesRequest {
+        index: Some(index as i32),
+        start: Some(start),
+        ..Default::default()
+    };
+    Ok(node.clone().list_invoices(req).await?.into_inner().invoices)
+}
+
+async fn payments(
+    node: &ClnClient,
+    index: ListsendpaysIndex,
+    start: u64,
+) -> Result<Vec<ListsendpaysPayments>> {
+    let req = ListsendpaysRequest {
+        index: Some(index as i32),
+        start: Some(start),
+        ..Default::default()
+    };
+    Ok(node
+        .clone()
+        .list_send_pays(req)
+        .await?
+        .into_inner()
+        .payments)
+}
+
+async fn forwards(
+    node: &ClnClient,
+    index: ListforwardsIndex,
+    start: u64,
+) -> Result<Vec<ListforwardsForwards>> {
+    let req = ListforwardsRequest {
+        index: Some(index as i32),
+        start: Some(start),
+        ..Default::default()
+    };
+    Ok(node.clone().list_forwards(req).await?.into_inner().forwards)
+}
+
+/// A step of [`run_once`].
+#[cfg(feature = "signer")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Step {
+    /// Scheduling the node and connecting to it.
+    Connect,
+    /// Answering the signer requests of the node.
+    Sign,
+    /// Fetching the delta.
+    Refresh,
+}
+
+/// Something [`run_once`] left for the app to do in the foreground.
+#[cfg(feature = "signer")]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum Attention {
+    /// The budget ran out before the step completed.
+    OutOfTime(Step),
+    /// The step failed.
+    Failed { step: Step, error: String },
+    /// The number of outgoing payments that are still pending. They
+    /// need the signer to be online to complete.
+    PendingPayments(usize),
+}
+
+#[cfg(feature = "signer")]
+#[derive(Clone, Debug, Default)]
+pub struct Summary {
+    /// The delta, `None` if it could not be fetched. Persist its
+    /// cursor together with the cache.
+    pub delta: Option<Delta>,
+    pub attention: Vec<Attention>,
+}
+
+#[cfg(feature = "signer")]
+impl Summary {
+    /// Whether the app should bring the node up in the foreground,
+    /// or schedule another background run soon.
+    pub fn needs_foreground(&self) -> bool {
+        !self.attention.is_empty()
+    }
+}
+
+/// Connect to the node, answer its pending signer requests with
+/// `signer`, if given, and fetch the delta since `cursor`, stopping
+/// after `budget`. Steps that did not complete are reported in the
+/// [`Summary`], together with the payments still pending.
+#[cfg(feature = "signer")]
+pub async fn run_once<Creds>(
+    scheduler: &Scheduler<Creds>,
+    signer: Option<&Signer>,
+    cursor: Cursor,
+    budget: Duration,
+) -> Summary
+where
+    Creds: TlsConfigProvider + RuneProvider + NodeIdProvider + Clone,
+{
+    let deadline = Instant::now() + budget;
+    let mut summary = Summary::default();
+
+    let (node, uri) = match timeout_at(deadline, connect(scheduler)).await {
+        Ok(Ok(connected)) => connected,
+        Ok(Err(e)) => {
+            summary.attention.push(Attention::Failed {
+                step: Step::Connect,
+                error: e.to_string(),
+            });
+            return summary;
+        }
+        Err(_) => {
+            summary.attention.push(Attention::OutOfTime(Step::Connect));
+            return summary;
+        }
+    };
+
+    let signing = async {
+        match signer {
+            Some(signer) => drain(signer, uri).await,
+            None => Ok(()),
+        }
+    };
+    let refresh = async { futures::try_join!(fetch(&node, cursor), pending_payments(&node)) };
+    let (signed, refreshed) =
+        futures::join!(timeout_at(deadline, signing), timeout_at(deadline, refresh));
+
+    match signed {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => summary.attention.push(Attention::Failed {
+            step: Step::Sign,
+            error: e.to_string(),
+        }),
+        Err(_) => summary.attention.push(Attention::OutOfTime(Step::Sign)),
+    }
+    match refreshed {
+        Ok(Ok((delta, pending))) => {
+            if pending > 0 {
+                summary.attention.push(Attention::PendingPayments(pending));
+            }
+            summary.delta = Some(delta);
+        }
+        Ok(Err(e)) => summary.attention.push(Attention::Failed {
+            step: Step::Refresh,
+            error: e.to_string(),
+        }),
+        Err(_) => summary.attention.push(Attention::OutOfTime(Step::Refresh)),
+    }
+    summary
+}
+
+#[cfg(feature = "signer")]
+async fn connect<Creds>(scheduler: &Scheduler<Creds>) -> Result<(ClnClient, Uri)>
+where
+    Creds: TlsConfigProvider + RuneProvider + NodeIdProvider + Clone,
+{
+    let info = scheduler.schedule().await?;
+    let uri = Uri::from_maybe_shared(info.grpc_uri.clone())?;
+    let node = scheduler.node_builder()?.connect(info.grpc_uri).await?;
+    Ok((node, uri))
+}
+
+/// Answer the signer requests of the node at `uri`, until none came
+/// in for [`SIGNER_QUIET`].
+#[cfg(feature = "signer")]
+async fn drain(signer: &Signer, uri: Uri) -> Result<()> {
+    let quiet = async {
+        loop {
+            sleep(SIGNER_QUIET / 10).await;
+            if signer.health().idle_for() >= Some(SIGNER_QUIET) {
+                return;
+            }
+        }
+    };
+    tokio::select! {
+        res = signer.run_once(uri) => Ok(res?),
+        _ = quiet => Ok(()),
+    }
+}
+
+/// The number of outgoing payments with pending parts.
+#[cfg(feature = "signer")]
+async fn pending_payments(node: &ClnClient) -> Result<usize> {
+    let req = ListsendpaysRequest {
+        status: Some(ListsendpaysStatus::Pending as i32),
+        ..Default::default()
+    };
+    let payments = node
+        .clone()
+        .list_send_pays(req)
+        .await?
+        .into_inner()
+        .payments;
+    Ok(payments
+        .iter()
+        .map(|p| &p.payment_hash)
+        .collect::<BTreeSet<_>>()
+        .len())
+}
+
+/// Merge the records listed by `created` and `updated` index, keeping
+/// the latest state of each record, and advance `cursor` past them.
+/// `indexes` returns the `created` and `updated` index of a record.
+fn merge<T>(
+    created: Vec<T>,
+    updated: Vec<T>,
+    cursor: &mut IndexCursor,
+    indexes: fn(&T) -> (Option<u64>, Option<u64>),
+) -> Vec<T> {
+    let mut records: BTreeMap<u64, T> = BTreeMap::new();
+    // Nodes before v23.08 do not index their records.
+    let mut unindexed = vec![];
+    for record in created.into_iter().chain(updated) {
+        let (c, u) = indexes(&record);
+        if let Some(u) = u {
+            cursor.updated = cursor.updated.max(u + 1);
+        }
+        let c = match c {
+            Some(c) => c,
+            None => {
+                unindexed.push(record);
+                continue;
+            }
+        };
+        cursor.created = cursor.created.max(c + 1);
+        // The two lists are fetched concurrently, either may hold the
+        // newer state of a record.
+        match records.get(&c) {
+            Some(known) if indexes(known).1 > u => {}
+            _ => {
+                records.insert(c, record);
+            }
+        }
+    }
+    records.into_values().chain(unindexed).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoice(label: &str, created: u64, updated: Option<u64>) -> ListinvoicesInvoices {
+        ListinvoicesInvoices {
+            label: label.to_string(),
+            created_index: Some(created),
+            updated_index: updated,
+            ..Default::default()
+        }
+    }
+
+    fn indexes(i: &ListinvoicesInvoices) -> (Option<u64>, Option<u64>) {
+        (i.created_index, i.updated_index)
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut cursor = IndexCursor {
+            created: 3,
+            updated: 1,
+        };
+        let created = vec![invoice("new", 3, None), invoice("paid", 4, Some(1))];
+        let updated = vec![invoice("paid", 4, Some(1)), invoice("old", 1, Some(2))];
+        let merged = merge(created, updated, &mut cursor, indexes);
+        let labels: Vec<_> = merged.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, ["old", "new", "paid"]);
+        assert_eq!(
+            cursor,
+            IndexCursor {
+                created: 5,
+                updated: 3
+            }
+        );
+
+        // Nothing changed, the cursor stays put.
+        assert!(merge(vec![], vec![], &mut cursor, indexes).is_empty());
+        assert_eq!(cursor.created, 5);
+    }
+}
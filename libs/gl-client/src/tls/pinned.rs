@@ -0,0 +1,109 @@
+//! Node connections that only accept pinned certificates, see
+//! [`Pinning::Spki`](super::Pinning::Spki).
+//!
+//! `tonic` does not allow customizing the certificate verification,
+//! so the connector performs the TLS handshake itself. The endpoint
+//! using it must have the `http` scheme, otherwise `tonic` would wrap
+//! the connection in its own TLS once more.
+use super::{client_config, root_store, spki_hash, PinningError, TlsConfig};
+use anyhow::Result;
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, CertificateError, ServerName};
+use std::convert::TryFrom;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+use tonic::transport::Uri;
+
+/// Verifies the node certificate against the CA as usual, and then
+/// checks that its public key is pinned.
+struct PinnedVerifier {
+    inner: WebPkiVerifier,
+    pins: Vec<[u8; 32]>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+        let hash = spki_hash(&end_entity.0).map_err(invalid_certificate)?;
+        match self.pins.contains(&hash) {
+            true => Ok(verified),
+            false => Err(invalid_certificate(PinningError::SpkiMismatch(hash))),
+        }
+    }
+}
+
+/// Keeps the `PinningError` in the handshake error, so callers can
+/// tell pinning failures apart.
+fn invalid_certificate(e: PinningError) -> rustls::Error {
+    rustls::Error::InvalidCertificate(CertificateError::Other(Arc::new(e)))
+}
+
+#[derive(Clone)]
+pub(crate) struct PinnedConnector {
+    connector: TlsConnector,
+    domain: ServerName,
+}
+
+impl PinnedConnector {
+    /// Connect with the identity and CA of `tls`, expecting the node
+    /// certificate to be issued for `domain`, and to have one of the
+    /// `pins`.
+    pub(crate) fn new(tls: &TlsConfig, domain: &str, pins: Vec<[u8; 32]>) -> Result<Self> {
+        let mut config = client_config(tls)?;
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(PinnedVerifier {
+                inner: WebPkiVerifier::new(root_store(tls)?, None),
+                pins,
+            }));
+        config.alpn_protocols = vec![b"h2".to_vec()];
+
+        Ok(PinnedConnector {
+            connector: TlsConnector::from(Arc::new(config)),
+            domain: ServerName::try_from(domain)?,
+        })
+    }
+}
+
+impl tower::Service<Uri> for PinnedConnector {
+    type Response = TlsStream<TcpStream>;
+    type Error = std::io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connector = self.connector.clone();
+        let domain = self.domain.clone();
+        Box::pin(async move {
+            let host = uri.host().unwrap_or_default();
+            let port = uri.port_u16().unwrap_or(443);
+            let stream = TcpStream::connect((host, port)).await?;
+            stream.set_nodelay(true)?;
+            connector.connect(domain, stream).await
+        })
+    }
+}
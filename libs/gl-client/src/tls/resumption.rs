@@ -0,0 +1,375 @@
+//! Resume TLS sessions when reconnecting to the scheduler or a node.
+//!
+//! Every new connection normally performs a full TLS handshake,
+//! exchanging and verifying certificates. On mobile, where
+//! connections are dropped whenever the app goes to the background,
+//! these handshakes add up. With the `resumption` feature the
+//! scheduler and node clients share a cache of TLS sessions, so
+//! reconnecting to a host resumes the previous session with an
+//! abbreviated handshake instead.
+//!
+//! Sessions are cached per client identity, host and port: a session
+//! is only resumed by a client presenting the same certificate, so a
+//! `NOBODY` client cannot resume the session of a device, nor the
+//! client of one node the session of another, even though node
+//! connections all expect the host to be `localhost`.
+//!
+//! The cache lives in memory, its size and how long sessions are
+//! resumed can be set with [`configure`] at startup.
+//! [`handshake_stats`] counts the resumed and full handshakes, to
+//! check that resumption kicks in.
+//!
+//! `tonic` does not allow configuring the session store, so the
+//! connector performs the TLS handshake itself, like the one for
+//! pinned certificates. Node connections with pinned public keys do
+//! not resume sessions, so that the pins are checked every time.
+use super::{client_config, root_store, TlsConfig};
+use anyhow::{Context as _, Result};
+use rustls::client::{
+    ClientSessionStore, Resumption, ServerCertVerified, ServerCertVerifier,
+    Tls12ClientSessionValue, Tls13ClientSessionValue, WebPkiVerifier,
+};
+use rustls::{Certificate, ClientConfig, NamedGroup, ServerName};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+use tonic::transport::Uri;
+
+/// The number of hosts sessions are cached for by default, counting
+/// each client identity and port separately.
+pub const DEFAULT_CAPACITY: usize = 32;
+
+/// How long sessions are resumed by default. Servers may limit the
+/// lifetime of their tickets further.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Servers usually issue a couple of tickets per handshake, each can
+/// be used for one resumption.
+const TICKETS_PER_HOST: usize = 8;
+
+static CACHE: Mutex<Option<Arc<SessionCache>>> = Mutex::new(None);
+static FULL: AtomicU64 = AtomicU64::new(0);
+static RESUMED: AtomicU64 = AtomicU64::new(0);
+
+/// Cache sessions for up to `capacity` hosts, and resume them for up
+/// to `ttl`. A `capacity` of 0 disables resumption. Replaces the
+/// cache, forgetting the sessions in it, so call it at startup.
+pub fn configure(capacity: usize, ttl: Duration) {
+    *CACHE.lock().unwrap() = Some(Arc::new(SessionCache::new(capacity, ttl)));
+}
+
+fn cache() -> Arc<SessionCache> {
+    CACHE
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| Arc::new(SessionCache::new(DEFAULT_CAPACITY, DEFAULT_TTL)))
+        .clone()
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct HandshakeStats {
+    /// Handshakes that resumed a cached session.
+    pub resumed: u64,
+    /// Handshakes that exchanged and verified certificates.
+    pub full: u64,
+}
+
+/// The handshakes performed since the process started.
+pub fn handshake_stats() -> HandshakeStats {
+    HandshakeStats {
+        resumed: RESUMED.load(Ordering::Relaxed),
+        full: FULL.load(Ordering::Relaxed),
+    }
+}
+
+/// Sessions are only shared between connections from the same client
+/// identity to the same host and port.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Key {
+    /// The SHA256 hash of the client certificate.
+    identity: [u8; 32],
+    host: ServerName,
+    port: u16,
+}
+
+#[derive(Default)]
+struct Entry {
+    kx_hint: Option<NamedGroup>,
+    tls12: Option<(Instant, Tls12ClientSessionValue)>,
+    tls13: VecDeque<(Instant, Tls13ClientSessionValue)>,
+}
+
+struct SessionCache {
+    capacity: usize,
+    ttl: Duration,
+    /// The entries by host, the least recently used first.
+    hosts: Mutex<VecDeque<(Key, Entry)>>,
+}
+
+impl SessionCache {
+    fn new(capacity: usize, ttl: Duration) -> Self {
+        SessionCache {
+            capacity,
+            ttl,
+            hosts: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Update the entry of `host`, evicting the least recently used
+    /// host to make room for it.
+    fn update(&self, host: Key, f: impl FnOnce(&mut Entry)) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut hosts = self.hosts.lock().unwrap();
+        let mut entry = match hosts.iter().position(|(h, _)| *h == host) {
+            Some(i) => hosts.remove(i).unwrap().1,
+            None => Entry::default(),
+        };
+        f(&mut entry);
+        while hosts.len() >= self.capacity {
+            hosts.pop_front();
+        }
+        hosts.push_back((host, entry));
+    }
+
+    fn get<T>(&self, host: Key, f: impl FnOnce(&mut Entry) -> Option<T>) -> Option<T> {
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts
+            .iter_mut()
+            .find(|(h, _)| *h == host)
+            .and_then(|(_, e)| f(e))
+    }
+
+    fn fresh(&self, stored: &Instant) -> bool {
+        stored.elapsed() < self.ttl
+    }
+}
+
+/// The view of the cache for the connections of one client identity
+/// to one port, which is what `rustls` stores the sessions in.
+struct ScopedCache {
+    cache: Arc<SessionCache>,
+    identity: [u8; 32],
+    port: u16,
+}
+
+impl ScopedCache {
+    fn key(&self, server_name: &ServerName) -> Key {
+        Key {
+            identity: self.identity,
+            host: server_name.clone(),
+            port: self.port,
+        }
+    }
+}
+
+impl ClientSessionStore for ScopedCache {
+    fn set_kx_hint(&self, server_name: &ServerName, group: NamedGroup) {
+        self.cache
+            .update(self.key(server_name), |e| e.kx_hint = Some(group));
+    }
+
+    fn kx_hint(&self, server_name: &ServerName) -> Option<NamedGroup> {
+        self.cache.get(self.key(server_name), |e| e.kx_hint)
+    }
+
+    fn set_tls12_session(&self, server_name: &ServerName, value: Tls12ClientSessionValue) {
+        self.cache.update(self.key(server_name), |e| {
+            e.tls12 = Some((Instant::now(), value))
+        });
+    }
+
+    fn tls12_session(&self, server_name: &ServerName) -> Option<Tls12ClientSessionValue> {
+        self.cache.get(self.key(server_name), |e| match &e.tls12 {
+            Some((stored, value)) if self.cache.fresh(stored) => Some(value.clone()),
+            _ => None,
+        })
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName) {
+        self.cache.get(self.key(server_name), |e| e.tls12.take());
+    }
+
+    fn insert_tls13_ticket(&self, server_name: &ServerName, value: Tls13ClientSessionValue) {
+        self.cache.update(self.key(server_name), |e| {
+            if e.tls13.len() >= TICKETS_PER_HOST {
+                e.tls13.pop_front();
+            }
+            e.tls13.push_back((Instant::now(), value));
+        });
+    }
+
+    fn take_tls13_ticket(&self, server_name: &ServerName) -> Option<Tls13ClientSessionValue> {
+        self.cache.get(self.key(server_name), |e| {
+            e.tls13.retain(|(stored, _)| self.cache.fresh(stored));
+            e.tls13.pop_back().map(|(_, value)| value)
+        })
+    }
+}
+
+/// Verifies certificates as usual, and records that they were
+/// verified, which only happens in full handshakes.
+struct RecordingVerifier {
+    inner: Arc<WebPkiVerifier>,
+    full: Arc<AtomicBool>,
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.full.store(true, Ordering::SeqCst);
+        self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ResumingConnector {
+    config: Arc<ClientConfig>,
+    verifier: Arc<WebPkiVerifier>,
+    domain: ServerName,
+    cache: Arc<SessionCache>,
+    /// The SHA256 hash of the client certificate.
+    identity: [u8; 32],
+}
+
+impl ResumingConnector {
+    /// Connect with the identity and CA of `tls`, expecting the
+    /// certificate of the host to be issued for `domain`.
+    pub(crate) fn new(tls: &TlsConfig, domain: &str) -> Result<Self> {
+        let mut config = client_config(tls)?;
+        config.alpn_protocols = vec![b"h2".to_vec()];
+        let cert = tls
+            .x509_cert
+            .as_ref()
+            .context("missing client certificate")?
+            .encode_der()?;
+        let mut identity = [0u8; 32];
+        identity.copy_from_slice(ring::digest::digest(&ring::digest::SHA256, &cert).as_ref());
+
+        Ok(ResumingConnector {
+            config: Arc::new(config),
+            verifier: Arc::new(WebPkiVerifier::new(root_store(tls)?, None)),
+            domain: ServerName::try_from(domain)?,
+            cache: cache(),
+            identity,
+        })
+    }
+}
+
+impl tower::Service<Uri> for ResumingConnector {
+    type Response = TlsStream<TcpStream>;
+    type Error = std::io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let full = Arc::new(AtomicBool::new(false));
+        let port = uri.port_u16().unwrap_or(443);
+        let mut config = (*self.config).clone();
+        config.resumption = Resumption::store(Arc::new(ScopedCache {
+            cache: self.cache.clone(),
+            identity: self.identity,
+            port,
+        }));
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(RecordingVerifier {
+                inner: self.verifier.clone(),
+                full: full.clone(),
+            }));
+        let connector = TlsConnector::from(Arc::new(config));
+        let domain = self.domain.clone();
+        Box::pin(async move {
+            let host = uri.host().unwrap_or_default();
+            let stream = TcpStream::connect((host, port)).await?;
+            stream.set_nodelay(true)?;
+            let stream = connector.connect(domain, stream).await?;
+            match full.load(Ordering::SeqCst) {
+                true => FULL.fetch_add(1, Ordering::Relaxed),
+                false => RESUMED.fetch_add(1, Ordering::Relaxed),
+            };
+            Ok(stream)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(name: &str) -> ServerName {
+        ServerName::try_from(name).unwrap()
+    }
+
+    fn scoped(cache: &Arc<SessionCache>, identity: u8, port: u16) -> ScopedCache {
+        ScopedCache {
+            cache: cache.clone(),
+            identity: [identity; 32],
+            port,
+        }
+    }
+
+    #[test]
+    fn test_session_cache_eviction() {
+        let cache = Arc::new(SessionCache::new(2, DEFAULT_TTL));
+        let store = scoped(&cache, 1, 443);
+        store.set_kx_hint(&host("a.gl"), NamedGroup::X25519);
+        store.set_kx_hint(&host("b.gl"), NamedGroup::secp256r1);
+        // Using `a.gl` makes `b.gl` the least recently used host.
+        store.set_kx_hint(&host("a.gl"), NamedGroup::X25519);
+        store.set_kx_hint(&host("c.gl"), NamedGroup::X25519);
+
+        assert_eq!(store.kx_hint(&host("a.gl")), Some(NamedGroup::X25519));
+        assert_eq!(store.kx_hint(&host("b.gl")), None);
+        assert_eq!(store.kx_hint(&host("c.gl")), Some(NamedGroup::X25519));
+    }
+
+    #[test]
+    fn test_session_cache_disabled() {
+        let cache = Arc::new(SessionCache::new(0, DEFAULT_TTL));
+        let store = scoped(&cache, 1, 443);
+        store.set_kx_hint(&host("a.gl"), NamedGroup::X25519);
+        assert_eq!(store.kx_hint(&host("a.gl")), None);
+    }
+
+    #[test]
+    fn test_session_cache_scopes() {
+        let cache = Arc::new(SessionCache::new(DEFAULT_CAPACITY, DEFAULT_TTL));
+        scoped(&cache, 1, 443).set_kx_hint(&host("localhost"), NamedGroup::X25519);
+
+        // Other identities and ports do not see the entry.
+        assert_eq!(scoped(&cache, 2, 443).kx_hint(&host("localhost")), None);
+        assert_eq!(scoped(&cache, 1, 444).kx_hint(&host("localhost")), None);
+        assert_eq!(
+            scoped(&cache, 1, 443).kx_hint(&host("localhost")),
+            Some(NamedGroup::X25519)
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Choose the version of Core Lightning the node runs.
+//!
+//! By default the scheduler starts nodes with its default version,
+//! and upgrades them as the default moves on. Providers can stage a
+//! rollout by opting some nodes in to upgrades before they become the
+//! default, and hold nodes back by pinning them to a version, see
+//! [`Scheduler::pin_cln_version`](crate::scheduler::Scheduler::pin_cln_version).
+//!
+//! Pinning a version the scheduler does not offer fails with
+//! [`VersionNotOffered`], which can be found with `downcast_ref` on the
+//! returned error, alongside the original [`tonic::Status`].
+use crate::ratelimit::StatusExt;
+use tonic::{Code, Status};
+
+#[derive(thiserror::Error, Clone, Debug, PartialEq, Eq)]
+#[error("CLN version {requested} is not offered by the scheduler")]
+pub struct VersionNotOffered {
+    pub requested: String,
+}
+
+pub(crate) trait VersionStatusExt<T> {
+    /// Convert the error, attaching [`VersionNotOffered`] if the
+    /// scheduler does not offer `requested`.
+    fn or_not_offered(self, requested: &str) -> anyhow::Result<T>;
+}
+
+impl<T> VersionStatusExt<T> for Result<T, Status> {
+    fn or_not_offered(self, requested: &str) -> anyhow::Result<T> {
+        match self {
+            Err(status) if status.code() == Code::NotFound => {
+                let not_offered = VersionNotOffered {
+                    requested: requested.to_string(),
+                };
+                Err(anyhow::Error::new(status).context(not_offered))
+            }
+            r => r.or_rate_limited(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_not_offered() {
+        let r: Result<(), Status> = Err(Status::not_found("no such version"));
+        let err = r.or_not_offered("v0.1").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<VersionNotOffered>(),
+            Some(&VersionNotOffered {
+                requested: "v0.1".to_string()
+            })
+        );
+        assert!(err.downcast_ref::<Status>().is_some());
+
+        let r: Result<(), Status> = Err(Status::internal("boom"));
+        let err = r.or_not_offered("v0.1").unwrap_err();
+        assert!(err.downcast_ref::<VersionNotOffered>().is_none());
+    }
+}
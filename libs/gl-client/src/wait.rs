@@ -0,0 +1,120 @@
+//! Follow the changes to invoices, payments and forwards.
+//!
+//! `lightningd` numbers the changes to each of these subsystems with
+//! three indices: `created`, `updated` and `deleted`. The `wait` RPC
+//! returns once an index reaches a given value, which allows
+//! following all changes without polling, and without the gaps and
+//! special cases of `waitanyinvoice` and `waitsendpay`. A [`Waiter`]
+//! follows a single index:
+//!
+//! ```no_run
+//! # use gl_client::node::ClnClient;
+//! # use gl_client::wait::{Index, Subsystem, Waiter};
+//! # async fn example(node: ClnClient) -> anyhow::Result<()> {
+//! let mut waiter = Waiter::new(node, Subsystem::Invoices, Index::Updated, 1);
+//! loop {
+//!     let change = waiter.next().await?;
+//!     println!("invoice update #{}", change.value);
+//!     // Persist `waiter.next_value()` to resume from here later.
+//! }
+//! # }
+//! ```
+use crate::node::ClnClient;
+use crate::pb::cln::{WaitRequest, WaitResponse};
+use anyhow::{anyhow, Result};
+use futures::Stream;
+
+pub use crate::pb::cln::wait_request::{WaitIndexname as Index, WaitSubsystem as Subsystem};
+
+/// An index of a subsystem reached `value`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub subsystem: Subsystem,
+    pub index: Index,
+    pub value: u64,
+}
+
+/// Waits for the changes to one index of a subsystem, in order.
+pub struct Waiter {
+    node: ClnClient,
+    subsystem: Subsystem,
+    index: Index,
+    next: u64,
+}
+
+impl Waiter {
+    /// Follow `index` of `subsystem`, starting with the change
+    /// numbered `next`. Indices start at 1, and a waiter starting at 0
+    /// returns the current value of the index right away.
+    pub fn new(node: ClnClient, subsystem: Subsystem, index: Index, next: u64) -> Self {
+        Waiter {
+            node,
+            subsystem,
+            index,
+            next,
+        }
+    }
+
+    /// The value of the next change, to resume from after a restart.
+    pub fn next_value(&self) -> u64 {
+        self.next
+    }
+
+    /// Wait for the next change. The index may skip values, e.g.,
+    /// when several changes happen at once, so the change returned
+    /// may be past [`Waiter::next_value`].
+    pub async fn next(&mut self) -> Result<Change> {
+        let res = self
+            .node
+            .wait(WaitRequest {
+                subsystem: self.subsystem as i32,
+                indexname: self.index as i32,
+                nextvalue: self.next,
+            })
+            .await
+            .map_err(|e| anyhow!(e))?
+            .into_inner();
+        let value = index_value(&res, self.index)
+            .ok_or_else(|| anyhow!("wait response is missing the {:?} index", self.index))?;
+        self.next = value + 1;
+        Ok(Change {
+            subsystem: self.subsystem,
+            index: self.index,
+            value,
+        })
+    }
+
+    /// The changes as a stream, which ends after the first error.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Change>> {
+        futures::stream::unfold(Some(self), |waiter| async move {
+            let mut waiter = waiter?;
+            match waiter.next().await {
+                Ok(change) => Some((Ok(change), Some(waiter))),
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+}
+
+fn index_value(res: &WaitResponse, index: Index) -> Option<u64> {
+    match index {
+        Index::Created => res.created,
+        Index::Updated => res.updated,
+        Index::Deleted => res.deleted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_value() {
+        let res = WaitResponse {
+            updated: Some(7),
+            ..Default::default()
+        };
+        assert_eq!(index_value(&res, Index::Updated), Some(7));
+        assert_eq!(index_value(&res, Index::Created), None);
+    }
+}
@@ -0,0 +1,137 @@
+//! Compute when the node must be online to settle pending payments.
+//!
+//! Greenlight nodes only run while a client is connected, or an
+//! incoming payment wakes them. An HTLC we forwarded or received
+//! still has to be resolved before it times out, so the app registers
+//! wakeups with the scheduler ahead of these deadlines, see
+//! [`Scheduler::add_wakeup`].
+//!
+//! [`Scheduler::add_wakeup`]: crate::scheduler::Scheduler::add_wakeup
+use crate::pb::cln::{
+    listinvoices_invoices::ListinvoicesInvoicesStatus, ListinvoicesResponse,
+    ListpeerchannelsResponse,
+};
+use crate::pb::scheduler::WakeupReason;
+
+/// Assumed time between blocks, to estimate when a block height is
+/// reached.
+const BLOCK_INTERVAL_SECS: u64 = 600;
+
+/// Wake up this many blocks before an HTLC expires, leaving the node
+/// time to settle or fail it onchain.
+const HTLC_SAFETY_BLOCKS: u64 = 12;
+
+/// Wake up this long before an invoice expires.
+const INVOICE_SAFETY_SECS: u64 = 600;
+
+/// A wakeup to register with the scheduler.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WakeupTrigger {
+    /// Seconds since the UNIX epoch.
+    pub wake_at: u64,
+    pub reason: WakeupReason,
+    /// The hex-encoded payment hash, or the invoice label.
+    pub reference: String,
+}
+
+/// Wakeups ahead of the expiry of all pending HTLCs, given the
+/// current `blockheight` and time `now`.
+pub fn htlc_wakeups(
+    channels: &ListpeerchannelsResponse,
+    blockheight: u32,
+    now: u64,
+) -> Vec<WakeupTrigger> {
+    channels
+        .channels
+        .iter()
+        .flat_map(|c| c.htlcs.iter())
+        .filter_map(|h| {
+            let blocks = (h.expiry? as u64)
+                .saturating_sub(blockheight as u64)
+                .saturating_sub(HTLC_SAFETY_BLOCKS);
+            Some(WakeupTrigger {
+                wake_at: now + blocks * BLOCK_INTERVAL_SECS,
+                reason: WakeupReason::HtlcTimeout,
+                reference: hex::encode(h.payment_hash.as_ref()?),
+            })
+        })
+        .collect()
+}
+
+/// Wakeups ahead of the expiry of unpaid invoices.
+pub fn invoice_wakeups(invoices: &ListinvoicesResponse, now: u64) -> Vec<WakeupTrigger> {
+    invoices
+        .invoices
+        .iter()
+        .filter(|i| {
+            ListinvoicesInvoicesStatus::from_i32(i.status)
+                == Some(ListinvoicesInvoicesStatus::Unpaid)
+                && i.expires_at > now
+        })
+        .map(|i| WakeupTrigger {
+            wake_at: i.expires_at.saturating_sub(INVOICE_SAFETY_SECS).max(now),
+            reason: WakeupReason::InvoiceExpiry,
+            reference: i.label.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::cln::{
+        ListinvoicesInvoices, ListpeerchannelsChannels, ListpeerchannelsChannelsHtlcs,
+    };
+
+    #[test]
+    fn test_htlc_wakeups() {
+        let channels = ListpeerchannelsResponse {
+            channels: vec![ListpeerchannelsChannels {
+                htlcs: vec![
+                    ListpeerchannelsChannelsHtlcs {
+                        expiry: Some(1144),
+                        payment_hash: Some(vec![1; 32]),
+                        ..Default::default()
+                    },
+                    // Already past the safety margin: wake up now.
+                    ListpeerchannelsChannelsHtlcs {
+                        expiry: Some(1005),
+                        payment_hash: Some(vec![2; 32]),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }],
+        };
+        let wakeups = htlc_wakeups(&channels, 1000, 5000);
+        assert_eq!(wakeups.len(), 2);
+        assert_eq!(wakeups[0].wake_at, 5000 + 132 * BLOCK_INTERVAL_SECS);
+        assert_eq!(wakeups[0].reference, hex::encode([1; 32]));
+        assert_eq!(wakeups[1].wake_at, 5000);
+    }
+
+    #[test]
+    fn test_invoice_wakeups() {
+        let invoice = |label: &str, status, expires_at| ListinvoicesInvoices {
+            label: label.to_string(),
+            status: status as i32,
+            expires_at,
+            ..Default::default()
+        };
+        let invoices = ListinvoicesResponse {
+            invoices: vec![
+                invoice("open", ListinvoicesInvoicesStatus::Unpaid, 10_000),
+                invoice("paid", ListinvoicesInvoicesStatus::Paid, 10_000),
+                invoice("expired", ListinvoicesInvoicesStatus::Unpaid, 100),
+            ],
+        };
+        assert_eq!(
+            invoice_wakeups(&invoices, 1000),
+            vec![WakeupTrigger {
+                wake_at: 10_000 - INVOICE_SAFETY_SECS,
+                reason: WakeupReason::InvoiceExpiry,
+                reference: "open".to_string(),
+            }]
+        );
+    }
+}
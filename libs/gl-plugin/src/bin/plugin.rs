@@ -13,6 +13,7 @@ use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
+use tonic::codec::CompressionEncoding;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -70,16 +71,23 @@ async fn start_node_server(
         gl_plugin::node::WrappedNodeServer::new(node_server.clone())
             .await
             .context("creating cln_grpc::pb::node_server::NodeServer instance")?,
-    );
+    )
+    // Responses are only compressed for clients that accept it.
+    .send_compressed(CompressionEncoding::Gzip)
+    .accept_compressed(CompressionEncoding::Gzip);
     let router = tonic::transport::Server::builder()
         .tls_config(tls)?
         .layer(gl_plugin::node::SignatureContextLayer::new(
             node_server.ctx.clone(),
         ))
         .add_service(gl_plugin::node::RpcWaitService::new(cln_node, rpc_path))
-        .add_service(gl_plugin::pb::node_server::NodeServer::new(
-            gl_plugin::node::WrappedNodeServer::new(node_server).await?,
-        ));
+        .add_service(
+            gl_plugin::pb::node_server::NodeServer::new(
+                gl_plugin::node::WrappedNodeServer::new(node_server).await?,
+            )
+            .send_compressed(CompressionEncoding::Gzip)
+            .accept_compressed(CompressionEncoding::Gzip),
+        );
 
     tokio::spawn(async move {
         router
@@ -184,6 +184,21 @@ impl PluginNodeServer {
         drop(rpc);
         r
     }
+
+    /// Call an `askrene` layer command, whose result we ignore.
+    async fn askrene(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<Response<pb::Empty>, Status> {
+        let _: serde_json::Value = self
+            .get_rpc()
+            .await
+            .call(method, params)
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        Ok(Response::new(pb::Empty {}))
+    }
 }
 
 #[tonic::async_trait]
@@ -191,6 +206,7 @@ impl Node for PluginNodeServer {
     type StreamCustommsgStream = ReceiverStream<Result<pb::Custommsg, Status>>;
     type StreamHsmRequestsStream = ReceiverStream<Result<pb::HsmRequest, Status>>;
     type StreamLogStream = ReceiverStream<Result<pb::LogEntry, Status>>;
+    type StreamSignerRequiredStream = ReceiverStream<Result<pb::PendingSignature, Status>>;
 
     async fn stream_custommsg(
         &self,
@@ -282,6 +298,7 @@ impl Node for PluginNodeServer {
         let mut stream = self.stage.mystream().await;
         let signer_state = self.signer_state.clone();
         let ctx = self.ctx.clone();
+        let stage = self.stage.clone();
 
         tokio::spawn(async move {
             trace!("hsmd hsm_id={} request processor started", hsm_id);
@@ -355,7 +372,20 @@ impl Node for PluginNodeServer {
                     .collect();
 
                 req.request.signer_state = state.into();
-                req.request.requests = ctx.snapshot().await.into_iter().map(|r| r.into()).collect();
+                req.request.requests = vec![];
+                for r in ctx.snapshot().await {
+                    let r: pb::PendingRequest = r.into();
+                    // Signers reject requests they cannot decode, so
+                    // don't bother them with those.
+                    if stage.supports(&r.uri).await {
+                        req.request.requests.push(r);
+                    } else {
+                        warn!(
+                            "Not attaching {} to request, the signer does not support it",
+                            r.uri
+                        );
+                    }
+                }
 
                 let serialized_configure_request = SERIALIZED_CONFIGURE_REQUEST.lock().await;
 
@@ -438,6 +468,364 @@ impl Node for PluginNodeServer {
         Ok(Response::new(pb::Empty::default()))
     }
 
+    async fn claim_hsm_request(
+        &self,
+        request: Request<pb::HsmRequestClaim>,
+    ) -> Result<Response<pb::HsmRequestClaimResponse>, Status> {
+        let req = request.into_inner();
+        Ok(Response::new(
+            self.stage.claim(req.request_id, req.signer_id).await,
+        ))
+    }
+
+    async fn bkpr_list_account_events(
+        &self,
+        request: Request<pb::BkprListAccountEventsRequest>,
+    ) -> Result<Response<pb::BkprListAccountEventsResponse>, Status> {
+        let req = request.into_inner();
+        let params = match req.account.is_empty() {
+            true => json!({}),
+            false => json!({ "account": req.account }),
+        };
+        let res: crate::responses::BkprListAccountEvents = self
+            .get_rpc()
+            .await
+            .call("bkpr-listaccountevents", params)
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        res.try_into()
+            .map(Response::new)
+            .map_err(|e: anyhow::Error| Status::new(Code::Internal, e.to_string()))
+    }
+
+    async fn bkpr_list_balances(
+        &self,
+        _request: Request<pb::BkprListBalancesRequest>,
+    ) -> Result<Response<pb::BkprListBalancesResponse>, Status> {
+        let res: crate::responses::BkprListBalances = self
+            .get_rpc()
+            .await
+            .call("bkpr-listbalances", json!({}))
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        res.try_into()
+            .map(Response::new)
+            .map_err(|e: anyhow::Error| Status::new(Code::Internal, e.to_string()))
+    }
+
+    async fn list_configs(
+        &self,
+        request: Request<pb::ListConfigsRequest>,
+    ) -> Result<Response<pb::ListConfigsResponse>, Status> {
+        let req = request.into_inner();
+        let params = match req.config.is_empty() {
+            true => json!({}),
+            false => json!({ "config": req.config }),
+        };
+        let res: crate::responses::ListConfigs = self
+            .get_rpc()
+            .await
+            .call("listconfigs", params)
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        Ok(Response::new(res.into()))
+    }
+
+    async fn set_config(
+        &self,
+        request: Request<pb::SetConfigRequest>,
+    ) -> Result<Response<pb::SetConfigResponse>, Status> {
+        let req = request.into_inner();
+        let res: crate::responses::SetConfig = self
+            .get_rpc()
+            .await
+            .call("setconfig", json!({ "config": req.config, "val": req.val }))
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        Ok(Response::new(res.into()))
+    }
+
+    async fn autoclean_once(
+        &self,
+        request: Request<pb::AutocleanOnceRequest>,
+    ) -> Result<Response<pb::AutocleanOnceResponse>, Status> {
+        let req = request.into_inner();
+        let res: crate::responses::AutocleanOnce = self
+            .get_rpc()
+            .await
+            .call(
+                "autoclean-once",
+                json!({ "subsystem": req.subsystem, "age": req.age }),
+            )
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        Ok(Response::new(res.into()))
+    }
+
+    async fn autoclean_status(
+        &self,
+        request: Request<pb::AutocleanStatusRequest>,
+    ) -> Result<Response<pb::AutocleanStatusResponse>, Status> {
+        let req = request.into_inner();
+        let params = match req.subsystem.is_empty() {
+            true => json!({}),
+            false => json!({ "subsystem": req.subsystem }),
+        };
+        let res: crate::responses::AutocleanStatus = self
+            .get_rpc()
+            .await
+            .call("autoclean-status", params)
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        Ok(Response::new(res.into()))
+    }
+
+    async fn del_pay(
+        &self,
+        request: Request<pb::DelPayRequest>,
+    ) -> Result<Response<pb::DelPayResponse>, Status> {
+        let req = request.into_inner();
+        let res: crate::responses::DelPay = self
+            .get_rpc()
+            .await
+            .call(
+                "delpay",
+                json!({
+                    "payment_hash": hex::encode(&req.payment_hash),
+                    "status": req.status,
+                    "partid": req.partid,
+                    "groupid": req.groupid,
+                }),
+            )
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        Ok(Response::new(res.into()))
+    }
+
+    async fn del_forward(
+        &self,
+        request: Request<pb::DelForwardRequest>,
+    ) -> Result<Response<pb::DelForwardResponse>, Status> {
+        let req = request.into_inner();
+        let _: crate::responses::DelForward = self
+            .get_rpc()
+            .await
+            .call(
+                "delforward",
+                json!({
+                    "in_channel": req.in_channel,
+                    "in_htlc_id": req.in_htlc_id,
+                    "status": req.status,
+                }),
+            )
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        Ok(Response::new(pb::DelForwardResponse {}))
+    }
+
+    async fn fund_channel_start(
+        &self,
+        request: Request<pb::FundChannelStartRequest>,
+    ) -> Result<Response<pb::FundChannelStartResponse>, Status> {
+        let req = request.into_inner();
+        let res: crate::responses::FundChannelStart = self
+            .get_rpc()
+            .await
+            .call(
+                "fundchannel_start",
+                json!({
+                    "id": hex::encode(&req.id),
+                    "amount": req.amount_sat,
+                    "feerate": req.feerate,
+                    "announce": req.announce,
+                    "close_to": req.close_to,
+                    "mindepth": req.mindepth,
+                }),
+            )
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        Ok(Response::new(res.into()))
+    }
+
+    async fn fund_channel_complete(
+        &self,
+        request: Request<pb::FundChannelCompleteRequest>,
+    ) -> Result<Response<pb::FundChannelCompleteResponse>, Status> {
+        let req = request.into_inner();
+        let res: crate::responses::FundChannelComplete = self
+            .get_rpc()
+            .await
+            .call(
+                "fundchannel_complete",
+                json!({ "id": hex::encode(&req.id), "psbt": req.psbt }),
+            )
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        Ok(Response::new(res.into()))
+    }
+
+    async fn fund_channel_cancel(
+        &self,
+        request: Request<pb::FundChannelCancelRequest>,
+    ) -> Result<Response<pb::FundChannelCancelResponse>, Status> {
+        let req = request.into_inner();
+        let res: crate::responses::FundChannelCancel = self
+            .get_rpc()
+            .await
+            .call("fundchannel_cancel", json!({ "id": hex::encode(&req.id) }))
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        Ok(Response::new(pb::FundChannelCancelResponse {
+            cancelled: res.cancelled,
+        }))
+    }
+
+    async fn emergency_recover(
+        &self,
+        _: Request<pb::EmergencyRecoverRequest>,
+    ) -> Result<Response<pb::EmergencyRecoverResponse>, Status> {
+        let res: crate::responses::Recover = self
+            .get_rpc()
+            .await
+            .call("emergencyrecover", json!({}))
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        Ok(Response::new(pb::EmergencyRecoverResponse {
+            stubs: res.stubs(),
+        }))
+    }
+
+    async fn recover_channel(
+        &self,
+        request: Request<pb::RecoverChannelRequest>,
+    ) -> Result<Response<pb::RecoverChannelResponse>, Status> {
+        let req = request.into_inner();
+        let scb: Vec<String> = req.scb.iter().map(hex::encode).collect();
+        let res: crate::responses::Recover = self
+            .get_rpc()
+            .await
+            .call("recoverchannel", json!({ "scb": scb }))
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        Ok(Response::new(pb::RecoverChannelResponse {
+            stubs: res.stubs(),
+        }))
+    }
+
+    async fn get_routes(
+        &self,
+        request: Request<pb::GetRoutesRequest>,
+    ) -> Result<Response<pb::GetRoutesResponse>, Status> {
+        let req = request.into_inner();
+        let res: crate::responses::GetRoutes = self
+            .get_rpc()
+            .await
+            .call(
+                "getroutes",
+                json!({
+                    "source": hex::encode(&req.source),
+                    "destination": hex::encode(&req.destination),
+                    "amount_msat": req.amount_msat,
+                    "layers": req.layers,
+                    "maxfee_msat": req.maxfee_msat,
+                    "final_cltv": req.final_cltv,
+                }),
+            )
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        Ok(Response::new(res.into()))
+    }
+
+    async fn askrene_create_layer(
+        &self,
+        request: Request<pb::AskreneLayerRequest>,
+    ) -> Result<Response<pb::Empty>, Status> {
+        let req = request.into_inner();
+        self.askrene("askrene-create-layer", json!({ "layer": req.layer }))
+            .await
+    }
+
+    async fn askrene_remove_layer(
+        &self,
+        request: Request<pb::AskreneLayerRequest>,
+    ) -> Result<Response<pb::Empty>, Status> {
+        let req = request.into_inner();
+        self.askrene("askrene-remove-layer", json!({ "layer": req.layer }))
+            .await
+    }
+
+    async fn askrene_disable_node(
+        &self,
+        request: Request<pb::AskreneDisableNodeRequest>,
+    ) -> Result<Response<pb::Empty>, Status> {
+        let req = request.into_inner();
+        self.askrene(
+            "askrene-disable-node",
+            json!({ "layer": req.layer, "node": hex::encode(&req.node) }),
+        )
+        .await
+    }
+
+    async fn askrene_disable_channel(
+        &self,
+        request: Request<pb::AskreneDisableChannelRequest>,
+    ) -> Result<Response<pb::Empty>, Status> {
+        let req = request.into_inner();
+        self.askrene(
+            "askrene-update-channel",
+            json!({
+                "layer": req.layer,
+                "short_channel_id_dir": req.short_channel_id_dir,
+                "enabled": false,
+            }),
+        )
+        .await
+    }
+
+    async fn multi_fund_channel(
+        &self,
+        request: Request<pb::MultiFundChannelRequest>,
+    ) -> Result<Response<pb::MultiFundChannelResponse>, Status> {
+        let req = request.into_inner();
+        let destinations: Vec<_> = req
+            .destinations
+            .iter()
+            .map(|d| {
+                json!({
+                    "id": d.id,
+                    "amount": d.amount_sat,
+                    "announce": d.announce,
+                    "push_msat": d.push_msat,
+                    "close_to": d.close_to,
+                    "mindepth": d.mindepth,
+                })
+            })
+            .collect();
+        let res: crate::responses::MultiFundChannel = self
+            .get_rpc()
+            .await
+            .call(
+                "multifundchannel",
+                json!({
+                    "destinations": destinations,
+                    "feerate": req.feerate,
+                    "minconf": req.minconf,
+                    "minchannels": req.minchannels,
+                }),
+            )
+            .await
+            .map_err(|e| Status::new(Code::Unknown, e.to_string()))?;
+        Ok(Response::new(res.into()))
+    }
+
+    async fn advertise_signer_capabilities(
+        &self,
+        request: Request<pb::SignerCapabilities>,
+    ) -> Result<Response<pb::SignerCapabilitiesResponse>, Status> {
+        self.stage.set_capabilities(request.into_inner()).await;
+        Ok(Response::new(pb::SignerCapabilitiesResponse {}))
+    }
+
     type StreamIncomingStream = ReceiverStream<Result<pb::IncomingPayment, Status>>;
 
     async fn stream_incoming(
@@ -534,9 +922,49 @@ impl Node for PluginNodeServer {
             }
         }
     }
+
+    async fn get_signer_status(
+        &self,
+        _req: tonic::Request<pb::SignerStatusRequest>,
+    ) -> Result<Response<pb::SignerStatusResponse>, Status> {
+        let pending_calls = self
+            .ctx
+            .snapshot()
+            .await
+            .into_iter()
+            .map(|r| {
+                let r: pb::PendingRequest = r.into();
+                r.uri
+            })
+            .collect();
+
+        Ok(Response::new(pb::SignerStatusResponse {
+            connected_signers: self.stage.hsm_connections() as u32,
+            pending: self.stage.pending().await,
+            pending_calls,
+        }))
+    }
+
+    async fn stream_signer_required(
+        &self,
+        _req: tonic::Request<pb::StreamSignerRequiredRequest>,
+    ) -> Result<Response<Self::StreamSignerRequiredStream>, Status> {
+        let (tx, rx) = mpsc::channel(1);
+        let mut unattended = self.stage.subscribe_unattended();
+        tokio::spawn(async move {
+            while let Ok(p) = unattended.recv().await {
+                if tx.send(Ok(p)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
 }
 
 use cln_grpc::pb::node_server::NodeServer;
+use tonic::codec::CompressionEncoding;
 
 impl PluginNodeServer {
     pub async fn run(self) -> Result<()> {
@@ -546,7 +974,10 @@ impl PluginNodeServer {
             WrappedNodeServer::new(self.clone())
                 .await
                 .context("creating NodeServer instance")?,
-        );
+        )
+        // Responses are only compressed for clients that accept it.
+        .send_compressed(CompressionEncoding::Gzip)
+        .accept_compressed(CompressionEncoding::Gzip);
 
         let router = tonic::transport::Server::builder()
             .max_frame_size(4 * 1024 * 1024) // 4MB max request size
@@ -556,7 +987,11 @@ impl PluginNodeServer {
                 ctx: self.ctx.clone(),
             })
             .add_service(RpcWaitService::new(cln_node, self.rpc_path.clone()))
-            .add_service(crate::pb::node_server::NodeServer::new(self.clone()));
+            .add_service(
+                crate::pb::node_server::NodeServer::new(self.clone())
+                    .send_compressed(CompressionEncoding::Gzip)
+                    .accept_compressed(CompressionEncoding::Gzip),
+            );
 
         router
             .serve(addr)
@@ -639,8 +639,19 @@ impl WrappedNodeServer {
 }
 
 use crate::pb::{
-    node_server::Node as GlNode, Custommsg, Empty, HsmRequest, HsmResponse, IncomingPayment,
-    LogEntry, StreamCustommsgRequest, StreamIncomingFilter, StreamLogRequest,
+    node_server::Node as GlNode, AskreneDisableChannelRequest, AskreneDisableNodeRequest,
+    AskreneLayerRequest, AutocleanOnceRequest, AutocleanOnceResponse, AutocleanStatusRequest,
+    AutocleanStatusResponse, BkprListAccountEventsRequest, BkprListAccountEventsResponse,
+    BkprListBalancesRequest, BkprListBalancesResponse, Custommsg, DelForwardRequest,
+    DelForwardResponse, DelPayRequest, DelPayResponse, EmergencyRecoverRequest,
+    EmergencyRecoverResponse, Empty, FundChannelCancelRequest, FundChannelCancelResponse,
+    FundChannelCompleteRequest, FundChannelCompleteResponse, FundChannelStartRequest,
+    FundChannelStartResponse, GetRoutesRequest, GetRoutesResponse, HsmRequest, HsmRequestClaim,
+    HsmRequestClaimResponse, HsmResponse, IncomingPayment, ListConfigsRequest, ListConfigsResponse,
+    LogEntry, MultiFundChannelRequest, MultiFundChannelResponse, PendingSignature,
+    RecoverChannelRequest, RecoverChannelResponse, SetConfigRequest, SetConfigResponse,
+    SignerCapabilities, SignerCapabilitiesResponse, SignerStatusRequest, SignerStatusResponse,
+    StreamCustommsgRequest, StreamIncomingFilter, StreamLogRequest, StreamSignerRequiredRequest,
 };
 use tokio_stream::wrappers::ReceiverStream;
 
@@ -650,6 +661,7 @@ impl GlNode for WrappedNodeServer {
     type StreamHsmRequestsStream = ReceiverStream<Result<HsmRequest, Status>>;
     type StreamLogStream = ReceiverStream<Result<LogEntry, Status>>;
     type StreamIncomingStream = ReceiverStream<Result<IncomingPayment, Status>>;
+    type StreamSignerRequiredStream = ReceiverStream<Result<PendingSignature, Status>>;
 
     async fn stream_incoming(
         &self,
@@ -665,6 +677,153 @@ impl GlNode for WrappedNodeServer {
         self.node_server.respond_hsm_request(req).await
     }
 
+    async fn claim_hsm_request(
+        &self,
+        req: Request<HsmRequestClaim>,
+    ) -> Result<Response<HsmRequestClaimResponse>, Status> {
+        self.node_server.claim_hsm_request(req).await
+    }
+
+    async fn bkpr_list_account_events(
+        &self,
+        req: Request<BkprListAccountEventsRequest>,
+    ) -> Result<Response<BkprListAccountEventsResponse>, Status> {
+        self.node_server.bkpr_list_account_events(req).await
+    }
+
+    async fn bkpr_list_balances(
+        &self,
+        req: Request<BkprListBalancesRequest>,
+    ) -> Result<Response<BkprListBalancesResponse>, Status> {
+        self.node_server.bkpr_list_balances(req).await
+    }
+
+    async fn list_configs(
+        &self,
+        req: Request<ListConfigsRequest>,
+    ) -> Result<Response<ListConfigsResponse>, Status> {
+        self.node_server.list_configs(req).await
+    }
+
+    async fn set_config(
+        &self,
+        req: Request<SetConfigRequest>,
+    ) -> Result<Response<SetConfigResponse>, Status> {
+        self.node_server.set_config(req).await
+    }
+
+    async fn autoclean_once(
+        &self,
+        req: Request<AutocleanOnceRequest>,
+    ) -> Result<Response<AutocleanOnceResponse>, Status> {
+        self.node_server.autoclean_once(req).await
+    }
+
+    async fn autoclean_status(
+        &self,
+        req: Request<AutocleanStatusRequest>,
+    ) -> Result<Response<AutocleanStatusResponse>, Status> {
+        self.node_server.autoclean_status(req).await
+    }
+
+    async fn del_pay(
+        &self,
+        req: Request<DelPayRequest>,
+    ) -> Result<Response<DelPayResponse>, Status> {
+        self.node_server.del_pay(req).await
+    }
+
+    async fn del_forward(
+        &self,
+        req: Request<DelForwardRequest>,
+    ) -> Result<Response<DelForwardResponse>, Status> {
+        self.node_server.del_forward(req).await
+    }
+
+    async fn fund_channel_start(
+        &self,
+        req: Request<FundChannelStartRequest>,
+    ) -> Result<Response<FundChannelStartResponse>, Status> {
+        self.node_server.fund_channel_start(req).await
+    }
+
+    async fn fund_channel_complete(
+        &self,
+        req: Request<FundChannelCompleteRequest>,
+    ) -> Result<Response<FundChannelCompleteResponse>, Status> {
+        self.node_server.fund_channel_complete(req).await
+    }
+
+    async fn fund_channel_cancel(
+        &self,
+        req: Request<FundChannelCancelRequest>,
+    ) -> Result<Response<FundChannelCancelResponse>, Status> {
+        self.node_server.fund_channel_cancel(req).await
+    }
+
+    async fn emergency_recover(
+        &self,
+        req: Request<EmergencyRecoverRequest>,
+    ) -> Result<Response<EmergencyRecoverResponse>, Status> {
+        self.node_server.emergency_recover(req).await
+    }
+
+    async fn recover_channel(
+        &self,
+        req: Request<RecoverChannelRequest>,
+    ) -> Result<Response<RecoverChannelResponse>, Status> {
+        self.node_server.recover_channel(req).await
+    }
+
+    async fn get_routes(
+        &self,
+        req: Request<GetRoutesRequest>,
+    ) -> Result<Response<GetRoutesResponse>, Status> {
+        self.node_server.get_routes(req).await
+    }
+
+    async fn askrene_create_layer(
+        &self,
+        req: Request<AskreneLayerRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        self.node_server.askrene_create_layer(req).await
+    }
+
+    async fn askrene_remove_layer(
+        &self,
+        req: Request<AskreneLayerRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        self.node_server.askrene_remove_layer(req).await
+    }
+
+    async fn askrene_disable_node(
+        &self,
+        req: Request<AskreneDisableNodeRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        self.node_server.askrene_disable_node(req).await
+    }
+
+    async fn askrene_disable_channel(
+        &self,
+        req: Request<AskreneDisableChannelRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        self.node_server.askrene_disable_channel(req).await
+    }
+
+    async fn multi_fund_channel(
+        &self,
+        req: Request<MultiFundChannelRequest>,
+    ) -> Result<Response<MultiFundChannelResponse>, Status> {
+        self.node_server.multi_fund_channel(req).await
+    }
+
+    async fn advertise_signer_capabilities(
+        &self,
+        req: Request<SignerCapabilities>,
+    ) -> Result<Response<SignerCapabilitiesResponse>, Status> {
+        self.node_server.advertise_signer_capabilities(req).await
+    }
+
     async fn stream_hsm_requests(
         &self,
         req: Request<Empty>,
@@ -700,4 +859,18 @@ impl GlNode for WrappedNodeServer {
     ) -> Result<tonic::Response<crate::pb::Empty>, tonic::Status> {
         self.node_server.configure(request).await
     }
+
+    async fn get_signer_status(
+        &self,
+        req: Request<SignerStatusRequest>,
+    ) -> Result<Response<SignerStatusResponse>, Status> {
+        self.node_server.get_signer_status(req).await
+    }
+
+    async fn stream_signer_required(
+        &self,
+        req: Request<StreamSignerRequiredRequest>,
+    ) -> Result<Response<Self::StreamSignerRequiredStream>, Status> {
+        self.node_server.stream_signer_required(req).await
+    }
 }
@@ -163,6 +163,233 @@ impl From<responses::ListFunds> for ListFundsResponse {
     }
 }
 
+impl TryFrom<responses::BkprAccountEvent> for BkprAccountEvent {
+    type Error = anyhow::Error;
+    fn try_from(e: responses::BkprAccountEvent) -> Result<Self> {
+        Ok(BkprAccountEvent {
+            account: e.account,
+            item_type: e.item_type,
+            tag: e.tag,
+            credit_msat: e.credit_msat.0,
+            debit_msat: e.debit_msat.0,
+            currency: e.currency,
+            timestamp: e.timestamp,
+            outpoint: e.outpoint.unwrap_or_default(),
+            txid: e.txid.map(hex::decode).transpose()?.unwrap_or_default(),
+            blockheight: e.blockheight.unwrap_or_default(),
+            origin: e.origin.unwrap_or_default(),
+            payment_id: e.payment_id.map(hex::decode).transpose()?.unwrap_or_default(),
+            part_id: e.part_id.unwrap_or_default(),
+            fees_msat: e.fees_msat.map(|f| f.0).unwrap_or_default(),
+            description: e.description.unwrap_or_default(),
+            is_rebalance: e.is_rebalance.unwrap_or_default(),
+        })
+    }
+}
+
+impl TryFrom<responses::BkprListAccountEvents> for BkprListAccountEventsResponse {
+    type Error = anyhow::Error;
+    fn try_from(r: responses::BkprListAccountEvents) -> Result<Self> {
+        Ok(BkprListAccountEventsResponse {
+            events: r
+                .events
+                .into_iter()
+                .map(|e| e.try_into())
+                .collect::<Result<Vec<_>>>()?,
+        })
+    }
+}
+
+impl TryFrom<responses::BkprAccount> for BkprAccount {
+    type Error = anyhow::Error;
+    fn try_from(a: responses::BkprAccount) -> Result<Self> {
+        Ok(BkprAccount {
+            account: a.account,
+            peer_id: a.peer_id.map(hex::decode).transpose()?.unwrap_or_default(),
+            we_opened: a.we_opened.unwrap_or_default(),
+            account_closed: a.account_closed.unwrap_or_default(),
+            account_resolved: a.account_resolved.unwrap_or_default(),
+            resolved_at_block: a.resolved_at_block.unwrap_or_default(),
+            balances: a
+                .balances
+                .into_iter()
+                .map(|b| BkprBalance {
+                    balance_msat: b.balance_msat.0,
+                    coin_type: b.coin_type,
+                })
+                .collect(),
+        })
+    }
+}
+
+impl TryFrom<responses::BkprListBalances> for BkprListBalancesResponse {
+    type Error = anyhow::Error;
+    fn try_from(r: responses::BkprListBalances) -> Result<Self> {
+        Ok(BkprListBalancesResponse {
+            accounts: r
+                .accounts
+                .into_iter()
+                .map(|a| a.try_into())
+                .collect::<Result<Vec<_>>>()?,
+        })
+    }
+}
+
+fn config_option(name: String, o: responses::ListConfigsOption) -> ConfigOption {
+    ConfigOption {
+        values: o.values(),
+        source: o
+            .source
+            .clone()
+            .or_else(|| o.sources.as_ref().and_then(|s| s.first().cloned()))
+            .unwrap_or_default(),
+        dynamic: o.dynamic.unwrap_or_default(),
+        name,
+    }
+}
+
+impl From<responses::ListConfigs> for ListConfigsResponse {
+    fn from(r: responses::ListConfigs) -> Self {
+        ListConfigsResponse {
+            configs: r
+                .configs
+                .into_iter()
+                .map(|(name, o)| config_option(name, o))
+                .collect(),
+        }
+    }
+}
+
+impl From<responses::SetConfig> for SetConfigResponse {
+    fn from(r: responses::SetConfig) -> Self {
+        SetConfigResponse {
+            config: Some(config_option(r.config.config, r.config.option)),
+        }
+    }
+}
+
+impl From<responses::AutocleanOnce> for AutocleanOnceResponse {
+    fn from(r: responses::AutocleanOnce) -> Self {
+        let (cleaned, uncleaned) = r
+            .autoclean
+            .values()
+            .fold((0, 0), |(c, u), s| (c + s.cleaned, u + s.uncleaned));
+        AutocleanOnceResponse { cleaned, uncleaned }
+    }
+}
+
+impl From<responses::AutocleanStatus> for AutocleanStatusResponse {
+    fn from(r: responses::AutocleanStatus) -> Self {
+        AutocleanStatusResponse {
+            subsystems: r
+                .autoclean
+                .into_iter()
+                .map(|(subsystem, s)| AutocleanSubsystemStatus {
+                    subsystem,
+                    enabled: s.enabled,
+                    age: s.age.unwrap_or_default(),
+                    cleaned: s.cleaned,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<responses::DelPay> for DelPayResponse {
+    fn from(r: responses::DelPay) -> Self {
+        DelPayResponse {
+            payments: r
+                .payments
+                .into_iter()
+                .map(|p| DeletedPayment {
+                    payment_hash: hex::decode(p.payment_hash).unwrap_or_default(),
+                    status: p.status,
+                    partid: p.partid.unwrap_or_default(),
+                    groupid: p.groupid.unwrap_or_default(),
+                    amount_sent_msat: p.amount_sent_msat.0,
+                    created_at: p.created_at,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<responses::FundChannelStart> for FundChannelStartResponse {
+    fn from(r: responses::FundChannelStart) -> Self {
+        FundChannelStartResponse {
+            funding_address: r.funding_address,
+            scriptpubkey: hex::decode(r.scriptpubkey).unwrap_or_default(),
+            close_to: r.close_to.and_then(|c| hex::decode(c).ok()),
+            mindepth: r.mindepth.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<responses::FundChannelComplete> for FundChannelCompleteResponse {
+    fn from(r: responses::FundChannelComplete) -> Self {
+        FundChannelCompleteResponse {
+            channel_id: hex::decode(r.channel_id).unwrap_or_default(),
+            commitments_secured: r.commitments_secured,
+        }
+    }
+}
+
+impl From<responses::MultiFundChannel> for MultiFundChannelResponse {
+    fn from(r: responses::MultiFundChannel) -> Self {
+        MultiFundChannelResponse {
+            tx: hex::decode(r.tx).unwrap_or_default(),
+            txid: hex::decode(r.txid).unwrap_or_default(),
+            channel_ids: r
+                .channel_ids
+                .into_iter()
+                .map(|c| MultiFundChannelChannel {
+                    id: hex::decode(c.id).unwrap_or_default(),
+                    outnum: c.outnum,
+                    channel_id: hex::decode(c.channel_id).unwrap_or_default(),
+                    close_to: c.close_to.and_then(|c| hex::decode(c).ok()),
+                })
+                .collect(),
+            failed: r
+                .failed
+                .into_iter()
+                .map(|f| MultiFundChannelFailure {
+                    id: hex::decode(f.id).unwrap_or_default(),
+                    method: f.method,
+                    code: f.error.code,
+                    message: f.error.message,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<responses::GetRoutes> for GetRoutesResponse {
+    fn from(r: responses::GetRoutes) -> Self {
+        GetRoutesResponse {
+            probability_ppm: r.probability_ppm,
+            routes: r
+                .routes
+                .into_iter()
+                .map(|r| AskreneRoute {
+                    probability_ppm: r.probability_ppm,
+                    amount_msat: r.amount_msat.0,
+                    final_cltv: r.final_cltv,
+                    path: r
+                        .path
+                        .into_iter()
+                        .map(|h| AskreneRouteHop {
+                            short_channel_id_dir: h.short_channel_id_dir,
+                            next_node_id: hex::decode(h.next_node_id).unwrap_or_default(),
+                            amount_msat: h.amount_msat.0,
+                            delay: h.delay,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
 impl From<responses::Withdraw> for WithdrawResponse {
     fn from(r: responses::Withdraw) -> Self {
         WithdrawResponse {
@@ -2,6 +2,7 @@
 pub use clightningrpc::responses::*;
 
 use serde::{de, Deserialize, Deserializer};
+use std::collections::BTreeMap;
 use std::str::FromStr;
 
 /// A simple wrapper that generalizes bare amounts and amounts with
@@ -334,10 +335,366 @@ pub struct ListFunds {
     pub channels: Vec<ListFundsChannel>,
 }
 
+/// Sub-structure for 'bkpr-listaccountevents' items
+#[derive(Debug, Clone, Deserialize)]
+pub struct BkprAccountEvent {
+    pub account: String,
+    #[serde(rename = "type")]
+    pub item_type: String,
+    pub tag: String,
+    pub credit_msat: MSat,
+    pub debit_msat: MSat,
+    pub currency: String,
+    pub timestamp: u32,
+    pub outpoint: Option<String>,
+    pub txid: Option<String>,
+    pub blockheight: Option<u32>,
+    pub origin: Option<String>,
+    pub payment_id: Option<String>,
+    pub part_id: Option<u32>,
+    pub fees_msat: Option<MSat>,
+    pub description: Option<String>,
+    pub is_rebalance: Option<bool>,
+}
+
+/// 'bkpr-listaccountevents' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct BkprListAccountEvents {
+    pub events: Vec<BkprAccountEvent>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BkprBalance {
+    pub balance_msat: MSat,
+    pub coin_type: String,
+}
+
+/// Sub-structure for 'bkpr-listbalances' accounts
+#[derive(Debug, Clone, Deserialize)]
+pub struct BkprAccount {
+    pub account: String,
+    pub peer_id: Option<String>,
+    pub we_opened: Option<bool>,
+    pub account_closed: Option<bool>,
+    pub account_resolved: Option<bool>,
+    pub resolved_at_block: Option<u32>,
+    pub balances: Vec<BkprBalance>,
+}
+
+/// 'bkpr-listbalances' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct BkprListBalances {
+    pub accounts: Vec<BkprAccount>,
+}
+
+/// Sub-structure for 'listconfigs' options. Only one of the
+/// `value*` fields is set, depending on the type of the option.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListConfigsOption {
+    pub value_str: Option<String>,
+    pub value_int: Option<i64>,
+    pub value_msat: Option<MSat>,
+    pub value_bool: Option<bool>,
+    pub values_str: Option<Vec<String>>,
+    pub values_int: Option<Vec<i64>>,
+    pub values_bool: Option<Vec<bool>>,
+    /// Set for flags, which have no value.
+    pub set: Option<bool>,
+    pub source: Option<String>,
+    /// The sources of options given multiple times.
+    pub sources: Option<Vec<String>>,
+    pub dynamic: Option<bool>,
+}
+
+impl ListConfigsOption {
+    /// The values of the option, rendered as strings.
+    pub fn values(&self) -> Vec<String> {
+        let mut values: Vec<String> = vec![];
+        values.extend(self.value_str.clone());
+        values.extend(self.value_int.map(|v| v.to_string()));
+        values.extend(self.value_msat.map(|v| v.0.to_string()));
+        values.extend(self.value_bool.map(|v| v.to_string()));
+        values.extend(self.values_str.iter().flatten().cloned());
+        values.extend(self.values_int.iter().flatten().map(|v| v.to_string()));
+        values.extend(self.values_bool.iter().flatten().map(|v| v.to_string()));
+        values.extend(self.set.map(|v| v.to_string()));
+        values
+    }
+}
+
+/// 'listconfigs' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListConfigs {
+    pub configs: BTreeMap<String, ListConfigsOption>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SetConfigOption {
+    pub config: String,
+    #[serde(flatten)]
+    pub option: ListConfigsOption,
+}
+
+/// 'setconfig' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct SetConfig {
+    pub config: SetConfigOption,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AutocleanCount {
+    pub cleaned: u64,
+    pub uncleaned: u64,
+}
+
+/// 'autoclean-once' command, keyed by subsystem
+#[derive(Debug, Clone, Deserialize)]
+pub struct AutocleanOnce {
+    pub autoclean: BTreeMap<String, AutocleanCount>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AutocleanSubsystem {
+    pub enabled: bool,
+    pub age: Option<u64>,
+    pub cleaned: u64,
+}
+
+/// 'autoclean-status' command, keyed by subsystem
+#[derive(Debug, Clone, Deserialize)]
+pub struct AutocleanStatus {
+    pub autoclean: BTreeMap<String, AutocleanSubsystem>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeletedPayment {
+    pub payment_hash: String,
+    pub status: String,
+    pub partid: Option<u64>,
+    pub groupid: Option<u64>,
+    pub amount_sent_msat: MSat,
+    pub created_at: u64,
+}
+
+/// 'delpay' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct DelPay {
+    pub payments: Vec<DeletedPayment>,
+}
+
+/// 'delforward' command, returns an empty object
+#[derive(Debug, Clone, Deserialize)]
+pub struct DelForward {}
+
+/// 'fundchannel_start' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct FundChannelStart {
+    pub funding_address: String,
+    pub scriptpubkey: String,
+    pub close_to: Option<String>,
+    pub mindepth: Option<u32>,
+}
+
+/// 'fundchannel_complete' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct FundChannelComplete {
+    pub channel_id: String,
+    pub commitments_secured: bool,
+}
+
+/// 'fundchannel_cancel' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct FundChannelCancel {
+    pub cancelled: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MultiFundChannelChannel {
+    pub id: String,
+    pub outnum: u32,
+    pub channel_id: String,
+    pub close_to: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MultiFundChannelError {
+    pub code: i32,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MultiFundChannelFailure {
+    pub id: String,
+    pub method: String,
+    pub error: MultiFundChannelError,
+}
+
+/// 'multifundchannel' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct MultiFundChannel {
+    pub tx: String,
+    pub txid: String,
+    pub channel_ids: Vec<MultiFundChannelChannel>,
+    #[serde(default)]
+    pub failed: Vec<MultiFundChannelFailure>,
+}
+
+/// 'emergencyrecover' and 'recoverchannel' commands
+#[derive(Debug, Clone, Deserialize)]
+pub struct Recover {
+    pub stubs: Vec<String>,
+}
+
+impl Recover {
+    /// The ids of the recovered channels, skipping malformed ones.
+    pub fn stubs(&self) -> Vec<Vec<u8>> {
+        self.stubs
+            .iter()
+            .filter_map(|s| hex::decode(s).ok())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GetRoutesHop {
+    pub short_channel_id_dir: String,
+    pub next_node_id: String,
+    pub amount_msat: MSat,
+    pub delay: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GetRoutesRoute {
+    pub probability_ppm: u64,
+    pub amount_msat: MSat,
+    pub final_cltv: u32,
+    pub path: Vec<GetRoutesHop>,
+}
+
+/// 'getroutes' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct GetRoutes {
+    pub probability_ppm: u64,
+    pub routes: Vec<GetRoutesRoute>,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn test_bkpr_parsing() {
+        let events: BkprListAccountEvents = serde_json::from_str(
+            r#"{"events": [{"account": "wallet", "type": "chain", "tag": "deposit",
+                "credit_msat": 1000000, "debit_msat": 0, "currency": "bc",
+                "outpoint": "aa:0", "timestamp": 1700000000, "blockheight": 800000}]}"#,
+        )
+        .unwrap();
+        assert_eq!(events.events[0].item_type, "chain");
+        assert_eq!(events.events[0].credit_msat.0, 1_000_000);
+        assert!(events.events[0].payment_id.is_none());
+
+        let balances: BkprListBalances = serde_json::from_str(
+            r#"{"accounts": [{"account": "wallet", "balances": [
+                {"balance_msat": 1000000, "coin_type": "bc"}]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(balances.accounts[0].balances[0].balance_msat.0, 1_000_000);
+    }
+
+    #[test]
+    fn test_listconfigs_parsing() {
+        let configs: ListConfigs = serde_json::from_str(
+            r#"{"configs": {
+                "fee-base": {"value_int": 1000, "source": "default"},
+                "autoclean-cycle": {"value_int": 3600, "source": "default", "dynamic": true},
+                "addr": {"values_str": ["a", "b"], "sources": ["cmdline", "cmdline"]},
+                "developer": {"set": true, "source": "cmdline"},
+                "htlc-maximum-msat": {"value_msat": 18446744073709551615, "source": "default"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(configs.configs["fee-base"].values(), vec!["1000"]);
+        assert_eq!(configs.configs["autoclean-cycle"].dynamic, Some(true));
+        assert_eq!(configs.configs["addr"].values(), vec!["a", "b"]);
+        assert_eq!(configs.configs["developer"].values(), vec!["true"]);
+        assert_eq!(
+            configs.configs["htlc-maximum-msat"].values(),
+            vec![u64::MAX.to_string()]
+        );
+    }
+
+    #[test]
+    fn test_setconfig_parsing() {
+        let res: SetConfig = serde_json::from_str(
+            r#"{"config": {"config": "fee-base", "source": "/tmp/config:3",
+                "dynamic": true, "value_int": 1000}}"#,
+        )
+        .unwrap();
+        assert_eq!(res.config.config, "fee-base");
+        assert_eq!(res.config.option.values(), vec!["1000"]);
+    }
+
+    #[test]
+    fn test_autoclean_parsing() {
+        let once: AutocleanOnce = serde_json::from_str(
+            r#"{"autoclean": {"expiredinvoices": {"cleaned": 3, "uncleaned": 1}}}"#,
+        )
+        .unwrap();
+        assert_eq!(once.autoclean["expiredinvoices"].cleaned, 3);
+
+        let status: AutocleanStatus = serde_json::from_str(
+            r#"{"autoclean": {
+                "expiredinvoices": {"enabled": true, "age": 86400, "cleaned": 3},
+                "failedforwards": {"enabled": false, "cleaned": 0}}}"#,
+        )
+        .unwrap();
+        assert_eq!(status.autoclean["expiredinvoices"].age, Some(86400));
+        assert!(!status.autoclean["failedforwards"].enabled);
+    }
+
+    #[test]
+    fn test_delpay_parsing() {
+        let res: DelPay = serde_json::from_str(
+            r#"{"payments": [{
+                "created_index": 1, "id": 1, "groupid": 1, "partid": 2,
+                "payment_hash": "6e9d1ff6af0e6b6ab3e8b4b6d2f1c3f1a8b6f8e7d1c2b3a4f5e6d7c8b9a0f1e2",
+                "destination": "02aa", "amount_msat": 1000, "amount_sent_msat": 1001,
+                "created_at": 1700000000, "status": "failed"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(res.payments[0].amount_sent_msat.0, 1001);
+        assert_eq!(res.payments[0].partid, Some(2));
+
+        let _: DelForward = serde_json::from_str("{}").unwrap();
+    }
+
+    #[test]
+    fn test_multifundchannel_parsing() {
+        let res: MultiFundChannel = serde_json::from_str(
+            r#"{"tx": "0200", "txid": "aa", "channel_ids": [
+                {"id": "02bb", "outnum": 1, "channel_id": "cc", "channel_type": {}}],
+              "failed": [{"id": "03dd", "method": "connect",
+                "error": {"code": 401, "message": "Unable to connect"}}]}"#,
+        )
+        .unwrap();
+        assert_eq!(res.channel_ids[0].outnum, 1);
+        assert_eq!(res.failed[0].method, "connect");
+        assert_eq!(res.failed[0].error.code, 401);
+    }
+
+    #[test]
+    fn test_getroutes_parsing() {
+        let res: GetRoutes = serde_json::from_str(
+            r#"{"probability_ppm": 900000, "routes": [{
+                "probability_ppm": 900000, "amount_msat": 1000, "final_cltv": 18,
+                "path": [{"short_channel_id_dir": "1x2x3/1", "next_node_id": "02aa",
+                  "amount_msat": 1001, "delay": 24}]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(res.routes[0].path[0].amount_msat.0, 1001);
+        assert_eq!(res.routes[0].path[0].short_channel_id_dir, "1x2x3/1");
+    }
+
     #[test]
     fn test_msat_parsing() {
         #[derive(Deserialize)]
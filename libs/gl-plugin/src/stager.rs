@@ -15,8 +15,29 @@ use tokio::sync::{broadcast, mpsc, Mutex};
 #[derive(Debug)]
 pub struct Stage {
     requests: Mutex<collections::HashMap<u32, Request>>,
+    claims: Mutex<collections::HashMap<u32, Claim>>,
+    /// The state changes of the most recently answered requests, for
+    /// the signers that observed them, see [`Stage::claim`].
+    answered: Mutex<collections::VecDeque<(u32, Vec<pb::SignerStateEntry>)>>,
     notify: broadcast::Sender<Request>,
     hsm_connections: Arc<AtomicUsize>,
+    unattended: broadcast::Sender<pb::PendingSignature>,
+    /// The context requests the signers advertised they can verify,
+    /// or `None` if they did not advertise their capabilities.
+    capabilities: Mutex<Option<collections::HashSet<String>>>,
+}
+
+/// How long a signer may hold a claim on a request without
+/// responding, before another signer may take over.
+const CLAIM_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(10);
+
+/// How many answered requests are remembered for observing signers.
+const MAX_ANSWERED: usize = 256;
+
+#[derive(Debug)]
+struct Claim {
+    signer_id: Vec<u8>,
+    claimed_at: tokio::time::Instant,
 }
 
 #[derive(Clone, Debug)]
@@ -26,13 +47,32 @@ pub struct Request {
     pub start_time: tokio::time::Instant,
 }
 
+impl Request {
+    fn pending(&self) -> pb::PendingSignature {
+        let raw = &self.request.raw;
+        pb::PendingSignature {
+            request_id: self.request.request_id,
+            message_type: match raw.get(0..2) {
+                Some(t) => u16::from_be_bytes([t[0], t[1]]) as u32,
+                None => 0,
+            },
+            waiting_ms: self.start_time.elapsed().as_millis() as u64,
+        }
+    }
+}
+
 impl Stage {
     pub fn new() -> Self {
         let (notify, _) = broadcast::channel(1000);
+        let (unattended, _) = broadcast::channel(100);
         Stage {
             requests: Mutex::new(collections::HashMap::new()),
+            claims: Mutex::new(collections::HashMap::new()),
+            answered: Mutex::new(collections::VecDeque::new()),
             notify: notify,
             hsm_connections: Arc::new(AtomicUsize::new(0)),
+            unattended,
+            capabilities: Mutex::new(None),
         }
     }
 
@@ -54,6 +94,14 @@ impl Stage {
 
         requests.insert(r.request.request_id, r.clone());
 
+        if self.hsm_connections.load(Ordering::Relaxed) == 0 {
+            debug!(
+                "No signer attached for request_id={}, notifying listeners",
+                r.request.request_id
+            );
+            let _ = self.unattended.send(r.pending());
+        }
+
         if let Err(_) = self.notify.send(r) {
             warn!("Error notifying hsmd request stream, likely lost connection.");
         }
@@ -71,6 +119,84 @@ impl Stage {
         }
     }
 
+    /// Claim the request for `signer_id`, so that only one signer
+    /// handles it. The other signers claim again until the request
+    /// is answered, and then get the state changes of the response,
+    /// or the claim expires, and they take over. Requests that are
+    /// not staged, because they were sent to a single signer or were
+    /// answered long ago, are always granted, since their responses
+    /// are harmless.
+    pub async fn claim(&self, request_id: u32, signer_id: Vec<u8>) -> pb::HsmRequestClaimResponse {
+        let requests = self.requests.lock().await;
+        let mut claims = self.claims.lock().await;
+        claims.retain(|id, _| requests.contains_key(id));
+
+        if !requests.contains_key(&request_id) {
+            let answered = self.answered.lock().await;
+            return match answered.iter().find(|(id, _)| *id == request_id) {
+                Some((_, signer_state)) => pb::HsmRequestClaimResponse {
+                    granted: false,
+                    answered: true,
+                    signer_state: signer_state.clone(),
+                    ..Default::default()
+                },
+                None => pb::HsmRequestClaimResponse {
+                    granted: true,
+                    ..Default::default()
+                },
+            };
+        }
+
+        match claims.get(&request_id) {
+            Some(c) if c.signer_id != signer_id && c.claimed_at.elapsed() < CLAIM_TIMEOUT => {
+                debug!(
+                    "Request {} already claimed by signer {}",
+                    request_id,
+                    hex::encode(&c.signer_id)
+                );
+                pb::HsmRequestClaimResponse {
+                    granted: false,
+                    holder: c.signer_id.clone(),
+                    ..Default::default()
+                }
+            }
+            _ => {
+                claims.insert(
+                    request_id,
+                    Claim {
+                        signer_id,
+                        claimed_at: tokio::time::Instant::now(),
+                    },
+                );
+                pb::HsmRequestClaimResponse {
+                    granted: true,
+                    ..Default::default()
+                }
+            }
+        }
+    }
+
+    /// Record the capabilities advertised by a signer. If several
+    /// signers are attached the most recent advertisement wins.
+    pub async fn set_capabilities(&self, capabilities: pb::SignerCapabilities) {
+        debug!(
+            "Signer {} supports {} request types",
+            capabilities.version,
+            capabilities.request_uris.len()
+        );
+        *self.capabilities.lock().await = Some(capabilities.request_uris.into_iter().collect());
+    }
+
+    /// Whether the signers can verify context requests for `uri`.
+    /// Signers that did not advertise their capabilities are assumed
+    /// to support everything.
+    pub async fn supports(&self, uri: &str) -> bool {
+        match &*self.capabilities.lock().await {
+            Some(c) => c.contains(uri),
+            None => true,
+        }
+    }
+
     pub async fn respond(&self, response: pb::HsmResponse) -> Result<(), Error> {
         let mut requests = self.requests.lock().await;
         match requests.remove(&response.request_id) {
@@ -81,6 +207,12 @@ impl Stage {
                     req.start_time.elapsed().as_secs_f64(),
                     requests.len()
                 );
+                let mut answered = self.answered.lock().await;
+                if answered.len() == MAX_ANSWERED {
+                    answered.pop_front();
+                }
+                answered.push_back((response.request_id, response.signer_state.clone()));
+                drop(answered);
                 if let Err(e) = req.response.send(response).await {
                     Err(anyhow!("Error sending request to requester: {:?}", e))
                 } else {
@@ -97,6 +229,30 @@ impl Stage {
         }
     }
 
+    /// The requests that are waiting for a signer to respond.
+    pub async fn pending(&self) -> Vec<pb::PendingSignature> {
+        let mut pending: Vec<pb::PendingSignature> = self
+            .requests
+            .lock()
+            .await
+            .values()
+            .map(|r| r.pending())
+            .collect();
+        pending.sort_by_key(|p| p.request_id);
+        pending
+    }
+
+    /// The number of signers currently streaming requests.
+    pub fn hsm_connections(&self) -> usize {
+        self.hsm_connections.load(Ordering::Relaxed)
+    }
+
+    /// Subscribe to requests that are staged while no signer is
+    /// attached.
+    pub fn subscribe_unattended(&self) -> broadcast::Receiver<pb::PendingSignature> {
+        self.unattended.subscribe()
+    }
+
     pub async fn is_stuck(&self) -> bool {
         let sticky = self
             .requests
@@ -228,4 +384,88 @@ mod test {
         f1.await.unwrap();
         f2.await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_unattended_requests() {
+        let stage = Stage::new();
+        let mut unattended = stage.subscribe_unattended();
+
+        let _r1 = stage
+            .send(pb::HsmRequest {
+                request_id: 1,
+                context: None,
+                raw: vec![0, 5, 1, 2],
+                signer_state: vec![],
+                requests: vec![],
+            })
+            .await
+            .unwrap();
+        let p = unattended.recv().await.unwrap();
+        assert_eq!(p.request_id, 1);
+        assert_eq!(p.message_type, 5);
+
+        // Only the first signer gets the claim, unknown requests are
+        // always granted.
+        assert!(stage.claim(1, vec![1]).await.granted);
+        assert!(stage.claim(1, vec![1]).await.granted);
+        let denied = stage.claim(1, vec![2]).await;
+        assert!(!denied.granted);
+        assert_eq!(denied.holder, vec![1]);
+        assert!(stage.claim(42, vec![2]).await.granted);
+
+        // Once a signer is attached we no longer notify.
+        let _s = stage.mystream().await;
+        assert_eq!(stage.hsm_connections(), 1);
+        let _r2 = stage
+            .send(pb::HsmRequest {
+                request_id: 2,
+                context: None,
+                raw: vec![0, 23],
+                signer_state: vec![],
+                requests: vec![],
+            })
+            .await
+            .unwrap();
+        assert!(unattended.try_recv().is_err());
+
+        let pending = stage.pending().await;
+        assert_eq!(
+            pending.iter().map(|p| p.request_id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        // Observers get the state changes of the response.
+        let state = vec![pb::SignerStateEntry {
+            key: "k".to_string(),
+            value: vec![1],
+            version: 2,
+        }];
+        stage
+            .respond(pb::HsmResponse {
+                request_id: 1,
+                raw: vec![],
+                signer_state: state.clone(),
+            })
+            .await
+            .unwrap();
+        let answered = stage.claim(1, vec![2]).await;
+        assert!(!answered.granted);
+        assert!(answered.answered);
+        assert_eq!(answered.signer_state, state);
+    }
+
+    #[tokio::test]
+    async fn test_capabilities() {
+        let stage = Stage::new();
+        assert!(stage.supports("/cln.Node/Pay").await);
+
+        stage
+            .set_capabilities(pb::SignerCapabilities {
+                version: "v24.02".to_string(),
+                request_uris: vec!["/cln.Node/Pay".to_string()],
+            })
+            .await;
+        assert!(stage.supports("/cln.Node/Pay").await);
+        assert!(!stage.supports("/cln.Node/Xpay").await);
+    }
 }
@@ -0,0 +1,39 @@
+//! Derive the approvals for the VLS policy from the context requests.
+use crate::{ContextRequest, Error};
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::str::FromStr;
+use lightning_signer::invoice::Invoice;
+use lightning_signer::lightning::ln::PaymentHash;
+use vls_protocol_signer::approver::Approval;
+
+/// The approvals implied by the calls in `requests`, e.g., paying an
+/// invoice approves the payments of that invoice. Preapproving an
+/// invoice or keysend approves it ahead of the payment, so the
+/// policy is checked before the node adds the HTLC.
+pub fn approvals(requests: &[ContextRequest]) -> Result<Vec<Approval>, Error> {
+    requests
+        .iter()
+        .filter_map(|request| match request {
+            ContextRequest::GlPay { bolt11 } | ContextRequest::PreapproveInvoice { bolt11 } => {
+                Some(
+                    Invoice::from_str(bolt11)
+                        .map(Approval::Invoice)
+                        .map_err(|e| Error::Approval(e.to_string())),
+                )
+            }
+            ContextRequest::PreapproveKeysend {
+                payment_hash,
+                amount_msat,
+                ..
+            } => Some(
+                payment_hash
+                    .as_slice()
+                    .try_into()
+                    .map(|h| Approval::KeySend(PaymentHash(h), *amount_msat))
+                    .map_err(|_| Error::Approval("invalid keysend payment_hash".to_string())),
+            ),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,53 @@
+//! The calls that signature requests are checked against.
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A call the user authorized, reduced to the parts the signer
+/// checks. The transport decodes the calls attached to a signature
+/// request, and converts them into these.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContextRequest {
+    SignMessage {
+        message: String,
+    },
+    /// Opening a channel with the peer `node_id`.
+    FundChannel {
+        node_id: Vec<u8>,
+    },
+    /// Opening channels with each of `node_ids` in one transaction.
+    MultiFundChannel {
+        node_ids: Vec<Vec<u8>>,
+    },
+    /// Completing the opening of a channel with `node_id`, funded by
+    /// the transaction `funding_txid` built outside of the node.
+    /// `outputs` are the value (in satoshis) and script of each of
+    /// its outputs.
+    FundChannelComplete {
+        node_id: Vec<u8>,
+        funding_txid: Vec<u8>,
+        outputs: Vec<(u64, Vec<u8>)>,
+    },
+    /// Creating or signing an invoice. `preimage` is set if the
+    /// caller chose the preimage.
+    CreateInvoice {
+        preimage: Option<Vec<u8>>,
+    },
+    Pay {
+        bolt11: String,
+    },
+    /// The deprecated `greenlight.Node/Pay` call.
+    GlPay {
+        bolt11: String,
+    },
+    PreapproveInvoice {
+        bolt11: String,
+    },
+    /// Preapproving a keysend of `amount_msat` to `destination`.
+    PreapproveKeysend {
+        destination: Vec<u8>,
+        payment_hash: Vec<u8>,
+        amount_msat: u64,
+    },
+    /// A call that does not justify any signature request by itself.
+    Other,
+}
@@ -0,0 +1,60 @@
+//! The parts of the Greenlight signer that do not depend on how the
+//! signer talks to the node.
+//!
+//! Before signing, the signer checks that every signature request
+//! from the node is justified by a call the user authorized, derives
+//! the approvals the VLS policy needs from these calls, and builds
+//! that policy. This crate implements those checks without `tokio`,
+//! `tonic` or other transport dependencies, so they can run inside
+//! constrained environments, such as HSMs, secure elements, or
+//! embedded devices. Build it with `default-features = false` there,
+//! and enable the `no-std` feature of the VLS crates in the firmware,
+//! as for VLS itself. `gl-client` uses it for its signer.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+mod auth;
+mod context;
+mod policy;
+mod preimage;
+mod resolve;
+
+pub use auth::approvals;
+pub use context::ContextRequest;
+pub use policy::{make_policy, PolicyLimits, ENFORCED_POLICIES};
+pub use preimage::check_preimage;
+pub use resolve::try_resolve;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// No context request justifies the signature request, given in
+    /// its serialized form.
+    Unresolved(Vec<u8>),
+    /// A context request could not be turned into an approval.
+    Approval(String),
+    /// A caller-supplied preimage is unsafe to use.
+    WeakPreimage(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Unresolved(_) => {
+                write!(
+                    f,
+                    "the signature request does not match any authorized call"
+                )
+            }
+            Error::Approval(e) => write!(f, "could not approve context request: {}", e),
+            Error::WeakPreimage(e) => write!(f, "rejected preimage: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
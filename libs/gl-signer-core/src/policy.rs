@@ -0,0 +1,96 @@
+//! The VLS policy the signer validates the node's requests against.
+use alloc::vec;
+use lightning_signer::bitcoin::Network;
+use lightning_signer::policy::filter::{FilterRule, PolicyFilter};
+use lightning_signer::policy::simple_validator::{make_simple_policy, SimplePolicy};
+
+/// Policies protecting the channel state that must never be demoted
+/// to warnings: the balance of a channel must not regress without a
+/// matching payment, and HTLCs must stay within limits.
+pub const ENFORCED_POLICIES: &[&str] = &[
+    "policy-commitment-htlc-routing-balance",
+    "policy-commitment-htlc-count-limit",
+    "policy-commitment-htlc-inflight-limit",
+    "policy-commitment-htlc-cltv-range",
+    "policy-commitment-previous-revoked",
+    "policy-commitment-retry-same",
+];
+
+/// The limits of the policy that may be changed by the user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PolicyLimits {
+    /// Maximum fee we accept to pay when routing a payment.
+    pub max_routing_fee_msat: u64,
+    /// Maximum number of invoices tracked by the signer.
+    pub max_invoices: usize,
+    /// Maximum value of a single HTLC, `None` for the VLS default.
+    pub max_htlc_value_sat: Option<u64>,
+    /// Maximum channel size, `None` for the VLS default.
+    pub max_channel_size_sat: Option<u64>,
+}
+
+/// The VLS policy for `network` with `limits` applied.
+pub fn make_policy(network: Network, limits: &PolicyLimits) -> SimplePolicy {
+    let mut policy = make_simple_policy(network);
+
+    // Enforced policies come first, since the first matching rule
+    // applies.
+    policy.filter = PolicyFilter {
+        rules: ENFORCED_POLICIES
+            .iter()
+            .map(|t| FilterRule::new_error(*t))
+            .collect(),
+    };
+    policy.filter.merge(PolicyFilter {
+        // TODO: Remove once we have fully switched over to zero-fee anchors
+        rules: vec![
+            FilterRule::new_warn("policy-channel-safe-type-anchors"),
+            FilterRule::new_warn("policy-routing-balanced"),
+        ],
+    });
+
+    policy.filter.merge(PolicyFilter {
+        // TODO: Remove once we have implemented zero invoice support
+        rules: vec![
+            FilterRule::new_warn("policy-routing-balanced"),
+            FilterRule::new_warn("policy-htlc-fee-range"),
+        ],
+    });
+
+    policy.max_invoices = limits.max_invoices;
+    policy.max_routing_fee_msat = limits.max_routing_fee_msat;
+    if let Some(v) = limits.max_htlc_value_sat {
+        policy.max_htlc_value_sat = v;
+    }
+    if let Some(v) = limits.max_channel_size_sat {
+        policy.max_channel_size_sat = v;
+    }
+    policy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lightning_signer::policy::filter::FilterResult;
+
+    #[test]
+    fn test_enforced_policies() {
+        let policy = make_policy(
+            Network::Bitcoin,
+            &PolicyLimits {
+                max_routing_fee_msat: 1_000_000,
+                max_invoices: 10_000,
+                max_htlc_value_sat: Some(1_000),
+                max_channel_size_sat: None,
+            },
+        );
+        for tag in ENFORCED_POLICIES {
+            assert_eq!(policy.filter.filter(*tag), FilterResult::Error);
+        }
+        assert_eq!(
+            policy.filter.filter("policy-routing-balanced"),
+            FilterResult::Warn
+        );
+        assert_eq!(policy.max_htlc_value_sat, 1_000);
+    }
+}
@@ -0,0 +1,65 @@
+//! Sanity checks for preimages supplied by the caller.
+//!
+//! Invoices normally use a preimage the node derives from its own
+//! secret. Swap protocols and similar integrations need to supply
+//! the preimage themselves, and anyone who learns or guesses it can
+//! claim the payment. We can not verify that a preimage was generated
+//! randomly, but we can reject the obviously guessable ones, such as
+//! all-zero or repeating patterns produced by a forgotten test stub.
+use crate::Error;
+
+/// The length of a payment preimage.
+pub const PREIMAGE_LEN: usize = 32;
+
+/// The minimum number of distinct byte values in a preimage. A random
+/// 32 byte value has about 30 of them; fewer than this happens with
+/// negligible probability.
+const MIN_DISTINCT_BYTES: usize = 16;
+
+/// Check that `preimage` looks like a random 32 byte value.
+pub fn check_preimage(preimage: &[u8]) -> Result<(), Error> {
+    if preimage.len() != PREIMAGE_LEN {
+        return Err(Error::WeakPreimage("preimage must be 32 bytes"));
+    }
+    let mut seen = [false; 256];
+    for b in preimage {
+        seen[*b as usize] = true;
+    }
+    if seen.iter().filter(|s| **s).count() < MIN_DISTINCT_BYTES {
+        return Err(Error::WeakPreimage("preimage has too little entropy"));
+    }
+    // Counters and other arithmetic sequences have many distinct
+    // bytes, but a constant difference between them.
+    let step = preimage[1].wrapping_sub(preimage[0]);
+    if preimage.windows(2).all(|w| w[1].wrapping_sub(w[0]) == step) {
+        return Err(Error::WeakPreimage("preimage is a sequence"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_preimage() {
+        let random = [
+            0x5f, 0x9a, 0x13, 0xc7, 0x2e, 0x84, 0xd1, 0x6b, 0x07, 0xee, 0x39, 0xa2, 0x58, 0xf4,
+            0x1d, 0x90, 0x66, 0xbb, 0x2a, 0xc3, 0x71, 0x0f, 0xe8, 0x44, 0x9d, 0x36, 0xab, 0x52,
+            0xfa, 0x1c, 0x87, 0x60,
+        ];
+        assert!(check_preimage(&random).is_ok());
+        assert!(check_preimage(&random[..31]).is_err());
+        assert!(check_preimage(&[0; 32]).is_err());
+
+        let mut pattern = [0u8; 32];
+        for (i, b) in pattern.iter_mut().enumerate() {
+            *b = (i % 4) as u8;
+        }
+        assert!(check_preimage(&pattern).is_err());
+        for (i, b) in pattern.iter_mut().enumerate() {
+            *b = (i * 3) as u8;
+        }
+        assert!(check_preimage(&pattern).is_err());
+    }
+}
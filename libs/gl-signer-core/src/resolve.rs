@@ -0,0 +1,291 @@
+//! Match signature requests against the context requests to find a
+//! justification.
+use crate::{check_preimage, ContextRequest, Error};
+use lightning_signer::bitcoin::secp256k1::PublicKey;
+use lightning_signer::lightning::ln::chan_utils::make_funding_redeemscript;
+use vls_protocol::msgs::{Message, SetupChannel};
+
+/// Attempt to find a resolution for a given request. We default to
+/// failing, and allowlist individual matches between pending context
+/// requests and the signer request being resolved. Where possible we
+/// also verify the contents of the request against the contents of
+/// the context request. TODOs in here may indicate ways to strengthen
+/// the verification.
+///
+/// `funding_pubkey` is the local funding pubkey of the channel the
+/// request is for, if any. Without it, channels funded from outside
+/// the node can not be set up.
+pub fn try_resolve(
+    req: &Message,
+    reqctx: &[ContextRequest],
+    funding_pubkey: Option<&PublicKey>,
+) -> Result<(), Error> {
+    log::trace!("Resolving {:?}", req);
+    if let Message::SetupChannel(m) = req {
+        if !check_external_funding(m, reqctx, funding_pubkey) {
+            log::warn!("Channel funding does not match the PSBT passed to fundchannel_complete");
+            return Err(Error::Unresolved(req.inner().as_vec()));
+        }
+    }
+
+    // Some requests do not need a justification. For example we
+    // reconnect automatically, so there may not even be a context
+    // request pending which would skip the entire stack below, so we
+    // do an early pass:
+    let accept = match req {
+        // Commands that simply have no context to check against
+        Message::GetHeartbeat(_) => true,
+        Message::Ecdh(_) => true,
+        Message::Ping(_) => true,
+        Message::Pong(_) => true,
+        Message::SignChannelAnnouncement(_) => true,
+        Message::SignChannelUpdate(_) => true,
+        Message::SignNodeAnnouncement(_) => true,
+        Message::CheckPubKey(_) => true,
+        // Duplicate verification with VLS, we defer to VLS
+        Message::GetChannelBasepoints(_) => true,
+        Message::ValidateCommitmentTx(_) => true,
+        Message::SignWithdrawal(_) => true,
+        Message::SetupChannel(_) => true,
+        Message::GetPerCommitmentPoint(_) => true,
+        Message::ValidateRevocation(_) => true,
+        Message::NewChannel(_) => true,
+        Message::SignCommitmentTx(_) => true,
+        Message::SignGossipMessage(_) => true,
+        Message::SignMutualCloseTx(_) => true,
+        Message::SignMutualCloseTx2(_) => true,
+        Message::SignRemoteCommitmentTx(_) => true,
+        Message::SignRemoteCommitmentTx2(_) => true,
+        Message::SignRemoteHtlcTx(_) => true,
+        // Resolution of an existing HTLC, we should never not try to
+        // grab funds if we can.
+        Message::SignPenaltyToUs(_) => true,
+        Message::SignAnyPenaltyToUs(_) => true,
+        Message::SignAnyDelayedPaymentToUs(_) => true,
+        Message::SignAnyLocalHtlcTx(_) => true,
+        Message::SignAnyRemoteHtlcToUs(_) => true,
+        Message::LockOutpoint(_) => true,
+        Message::CheckOutpoint(_) => true,
+        Message::SignAnyChannelAnnouncement(_) => true,
+        Message::RevokeCommitmentTx(_) => true,
+        Message::ForgetChannel(_) => true,
+        // Default to rejecting, punting the decision to the next step.
+        _ => false,
+    };
+
+    // If we found a resolution, then there is no point in trying to
+    // match up further.
+    if accept {
+        log::trace!(
+            "Request {:?} resolved with no context request required",
+            req
+        );
+        return Ok(());
+    }
+
+    for cr in reqctx {
+        let accept = match (req, cr) {
+            (Message::SignMessage(m1), ContextRequest::SignMessage { message }) => {
+                m1.message.0 == message.as_bytes()
+            }
+            (Message::NewChannel(m1), ContextRequest::FundChannel { node_id }) => {
+                // Different node_id? Reject!
+                m1.node_id.0 == node_id.as_slice()
+                // TODO: Add `close_to` to allowlist for the close
+                // later on
+            }
+            (Message::NewChannel(m1), ContextRequest::MultiFundChannel { node_ids }) => {
+                node_ids.iter().any(|id| m1.node_id.0 == id.as_slice())
+            }
+            (Message::SignInvoice(_l), ContextRequest::CreateInvoice { preimage }) => {
+                // TODO: This could be strengthened by parsing the
+                // invoice from `l.u5bytes` and verify the description,
+                // amount and (maybe) payment_hash
+                match preimage.as_deref().map(check_preimage) {
+                    Some(Err(e)) => {
+                        log::warn!("Refusing to sign invoice: {}", e);
+                        false
+                    }
+                    _ => true,
+                }
+            }
+            (Message::PreapproveInvoice(l), ContextRequest::Pay { bolt11 }) => {
+                l.invstring.0 == bolt11.as_bytes()
+            }
+            (Message::PreapproveInvoice(l), ContextRequest::PreapproveInvoice { bolt11 }) => {
+                // Manually calling preapproveinvoice should always be
+                // allowed. The bolt11 string have to match.
+                l.invstring.0 == bolt11.as_bytes()
+            }
+            (
+                Message::PreapproveKeysend(l),
+                ContextRequest::PreapproveKeysend {
+                    destination,
+                    payment_hash,
+                    amount_msat,
+                },
+            ) => {
+                l.destination.0[..] == destination[..]
+                    && l.payment_hash.0[..] == payment_hash[..]
+                    && l.amount_msat == *amount_msat
+            }
+            (_, _) => false,
+        };
+
+        // Did we find a resolution? If yes we can stop here.
+        if accept {
+            log::trace!("Request {:?} approved with context request {:?}", req, cr);
+            return Ok(());
+        }
+    }
+
+    Err(Error::Unresolved(req.inner().as_vec()))
+}
+
+/// Channels funded from outside the node are set up from the PSBT
+/// passed to `fundchannel_complete`. Check that the channel is set up
+/// with an output of that PSBT carrying the channel's value, and
+/// paying to the 2-of-2 multisig of the `local` and remote funding
+/// pubkeys. Since the context requests of concurrent calls are
+/// attached as well, a pending regular `fundchannel` or
+/// `multifundchannel` lifts the check.
+fn check_external_funding(
+    m: &SetupChannel,
+    reqctx: &[ContextRequest],
+    local: Option<&PublicKey>,
+) -> bool {
+    let mut external = reqctx
+        .iter()
+        .filter_map(|cr| match cr {
+            ContextRequest::FundChannelComplete {
+                funding_txid,
+                outputs,
+                ..
+            } => Some((funding_txid, outputs)),
+            _ => None,
+        })
+        .peekable();
+    if external.peek().is_none()
+        || reqctx.iter().any(|cr| {
+            matches!(
+                cr,
+                ContextRequest::FundChannel { .. } | ContextRequest::MultiFundChannel { .. }
+            )
+        })
+    {
+        return true;
+    }
+
+    let expected = match (local, PublicKey::from_slice(&m.remote_funding_pubkey.0)) {
+        (Some(local), Ok(remote)) => make_funding_redeemscript(local, &remote).to_v0_p2wsh(),
+        _ => return false,
+    };
+    external.any(|(txid, outputs)| {
+        txid[..] == m.funding_txid[..]
+            && outputs
+                .get(m.funding_txout as usize)
+                .is_some_and(|(value, script)| {
+                    *value == m.channel_value && script[..] == expected[..]
+                })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use lightning_signer::bitcoin::hashes::Hash;
+    use lightning_signer::bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use lightning_signer::bitcoin::{Script, Txid};
+    use vls_protocol::model::{Basepoints, PubKey, Sha256};
+    use vls_protocol::msgs::{PreapproveInvoice, PreapproveKeysend};
+    use vls_protocol::serde_bolt::{Octets, WireString};
+
+    #[test]
+    fn test_preapprove_needs_matching_pay() {
+        let msg = Message::PreapproveInvoice(PreapproveInvoice {
+            invstring: WireString("lnbc1".as_bytes().to_vec()),
+        });
+        assert!(try_resolve(&msg, &[], None).is_err());
+        assert!(try_resolve(
+            &msg,
+            &[ContextRequest::Pay {
+                bolt11: "lnbc2".to_string()
+            }],
+            None
+        )
+        .is_err());
+        assert!(try_resolve(
+            &msg,
+            &[
+                ContextRequest::Other,
+                ContextRequest::Pay {
+                    bolt11: "lnbc1".to_string()
+                }
+            ],
+            None
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_preapprove_keysend() {
+        let msg = Message::PreapproveKeysend(PreapproveKeysend {
+            destination: PubKey([2; 33]),
+            payment_hash: Sha256([1; 32]),
+            amount_msat: 1000,
+        });
+        let ctx = |amount_msat| ContextRequest::PreapproveKeysend {
+            destination: vec![2; 33],
+            payment_hash: vec![1; 32],
+            amount_msat,
+        };
+        assert!(try_resolve(&msg, &[ctx(1001)], None).is_err());
+        assert!(try_resolve(&msg, &[ctx(1000)], None).is_ok());
+        assert_eq!(crate::approvals(&[ctx(1000)]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_external_funding() {
+        let secp = Secp256k1::new();
+        let key = |b| PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[b; 32]).unwrap());
+        let (local, remote) = (key(1), key(2));
+        let pk = || PubKey(remote.serialize());
+        let msg = Message::SetupChannel(SetupChannel {
+            is_outbound: true,
+            channel_value: 100_000,
+            push_value: 0,
+            funding_txid: Txid::from_inner([3; 32]),
+            funding_txout: 1,
+            to_self_delay: 144,
+            local_shutdown_script: Octets(vec![]),
+            local_shutdown_wallet_index: None,
+            remote_basepoints: Basepoints {
+                revocation: pk(),
+                payment: pk(),
+                htlc: pk(),
+                delayed_payment: pk(),
+            },
+            remote_funding_pubkey: pk(),
+            remote_to_self_delay: 144,
+            remote_shutdown_script: Octets(vec![]),
+            channel_type: Octets(vec![]),
+        });
+        let funding = |script: Script| ContextRequest::FundChannelComplete {
+            node_id: vec![2; 33],
+            funding_txid: vec![3; 32],
+            outputs: vec![(50_000, vec![]), (100_000, script.to_bytes())],
+        };
+
+        let expected = make_funding_redeemscript(&local, &remote).to_v0_p2wsh();
+        assert!(try_resolve(&msg, &[funding(expected.clone())], Some(&local)).is_ok());
+        // Any other P2WSH output, e.g., one without our key, is
+        // rejected.
+        let other = make_funding_redeemscript(&remote, &key(3)).to_v0_p2wsh();
+        assert!(try_resolve(&msg, &[funding(other)], Some(&local)).is_err());
+        // Without our key the funding can not be checked.
+        assert!(try_resolve(&msg, &[funding(expected)], None).is_err());
+        assert!(try_resolve(&msg, &[], None).is_ok());
+    }
+}
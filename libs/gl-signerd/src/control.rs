@@ -0,0 +1,166 @@
+//! The local control socket.
+//!
+//! Operators talk to a running daemon over a unix socket, sending one
+//! JSON request per line and receiving one JSON response per line:
+//!
+//! ```text
+//! {"method": "status"}
+//! {"method": "approve", "bolt11": "lnbc..."}
+//! {"method": "rotate_rune", "rune": "..."}
+//! ```
+//!
+//! Responses are either `{"result": ...}` or `{"error": "..."}`. Access
+//! is controlled by the permissions of the socket, which only its
+//! owner may use.
+use crate::keystore::Keystore;
+use anyhow::Result;
+use gl_client::signer::Signer;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Request {
+    Status,
+    /// Approve payments of an invoice, see [`Signer::approve_invoice`].
+    Approve {
+        bolt11: String,
+    },
+    /// Revoke a rune and issue a replacement, see
+    /// [`Signer::rotate_rune`].
+    RotateRune {
+        rune: String,
+    },
+}
+
+pub struct Control {
+    pub signer: Signer,
+    pub keystore: Keystore,
+}
+
+impl Control {
+    pub fn bind(path: &Path) -> Result<UnixListener> {
+        // A stale socket from a previous run would make binding fail.
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        Ok(listener)
+    }
+
+    pub async fn serve(self: Arc<Self>, listener: UnixListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let control = self.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = control.connection(stream).await {
+                            debug!("Control connection closed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => warn!("Error accepting control connection: {}", e),
+            }
+        }
+    }
+
+    async fn connection(&self, stream: UnixStream) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            let response = match serde_json::from_str(&line) {
+                Ok(request) => match self.handle(request) {
+                    Ok(result) => json!({ "result": result }),
+                    Err(e) => json!({ "error": format!("{:#}", e) }),
+                },
+                Err(e) => json!({ "error": format!("invalid request: {}", e) }),
+            };
+            writer
+                .write_all(format!("{}\n", response).as_bytes())
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub fn handle(&self, request: Request) -> Result<Value> {
+        debug!("Control request {:?}", request);
+        match request {
+            Request::Status => Ok(json!({
+                "node_id": hex::encode(self.signer.node_id()),
+                "status": self.signer.status().current(),
+                "version": self.signer.version(),
+                "policy": self.signer.signer_policy(),
+            })),
+            Request::Approve { bolt11 } => {
+                self.signer.approve_invoice(&bolt11)?;
+                Ok(json!({}))
+            }
+            Request::RotateRune { rune } => {
+                let rune = self.signer.rotate_rune(&rune)?;
+                self.keystore
+                    .store_revoked_runes(&self.signer.signer_policy().rune_blacklist)?;
+                Ok(json!({ "rune": rune }))
+            }
+        }
+    }
+}
+
+/// Send `request` to the daemon listening on `path`.
+pub async fn call(path: &Path, request: &Request) -> Result<Value> {
+    let stream = UnixStream::connect(path).await?;
+    let (reader, mut writer) = stream.into_split();
+    writer
+        .write_all(format!("{}\n", serde_json::to_string(request)?).as_bytes())
+        .await?;
+    let line = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await?
+        .unwrap_or_default();
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl_client::bitcoin::Network;
+    use gl_client::credentials::Nobody;
+
+    #[tokio::test]
+    async fn test_control() {
+        let dir = tempfile::tempdir().unwrap();
+        let signer = Signer::new(vec![0; 32], Network::Regtest, Nobody::new()).unwrap();
+        let pubkey = format!("pubkey={}", hex::encode(signer.node_id()));
+        let rune = signer.create_rune(None, vec![vec![&pubkey]]).unwrap();
+        let control = Arc::new(Control {
+            signer,
+            keystore: Keystore::open(dir.path()).unwrap(),
+        });
+        let path = dir.path().join("control.sock");
+        tokio::spawn(control.clone().serve(Control::bind(&path).unwrap()));
+
+        let status = call(&path, &Request::Status).await.unwrap();
+        assert_eq!(status["result"]["status"], "disconnected");
+
+        let res = call(
+            &path,
+            &Request::Approve {
+                bolt11: "lnbc".into(),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(res["error"].is_string());
+
+        let res = call(&path, &Request::RotateRune { rune }).await.unwrap();
+        assert!(res["result"]["rune"].is_string());
+        assert_eq!(control.keystore.revoked_runes().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,92 @@
+//! A minimal HTTP endpoint for health checks and metrics.
+//!
+//! `GET /healthz` returns the [`HealthReport`] as JSON, with status
+//! 200 if the signer is healthy and 503 otherwise. `GET /metrics`
+//! returns the same report as Prometheus gauges.
+use anyhow::Result;
+use gl_client::health::HealthReport;
+use gl_client::signer::Signer;
+use log::debug;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+pub async fn serve(listener: TcpListener, signer: Signer) {
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(s) => s,
+            Err(e) => {
+                debug!("Error accepting health connection: {}", e);
+                continue;
+            }
+        };
+        let report = signer.health_report();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, report).await {
+                debug!("Health connection failed: {}", e);
+            }
+        });
+    }
+}
+
+async fn respond(stream: TcpStream, report: HealthReport) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+    let (status, content_type, body) = route(&line, &report);
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    writer.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// The status line, content type and body for the request `line`.
+fn route(line: &str, report: &HealthReport) -> (&'static str, &'static str, String) {
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/healthz")) => (
+            if report.is_healthy() {
+                "200 OK"
+            } else {
+                "503 Service Unavailable"
+            },
+            "application/json",
+            serde_json::to_string(report).unwrap_or_default(),
+        ),
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            report.to_prometheus(),
+        ),
+        _ => ("404 Not Found", "text/plain", String::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl_client::connection::ConnectionStatus;
+    use gl_client::health::Health;
+
+    #[test]
+    fn test_route() {
+        let report = Health::new().report(ConnectionStatus::Disconnected);
+        let (status, _, body) = route("GET /healthz HTTP/1.1\r\n", &report);
+        assert_eq!(status, "503 Service Unavailable");
+        assert!(body.contains("\"status\":\"disconnected\""));
+
+        let report = Health::new().report(ConnectionStatus::SignerAttached);
+        assert_eq!(route("GET /healthz HTTP/1.1\r\n", &report).0, "200 OK");
+        assert!(route("GET /metrics HTTP/1.1\r\n", &report)
+            .2
+            .contains("gl_signer_healthy 1"));
+        assert_eq!(
+            route("POST /healthz HTTP/1.1\r\n", &report).0,
+            "404 Not Found"
+        );
+    }
+}
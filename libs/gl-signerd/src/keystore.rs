@@ -0,0 +1,114 @@
+//! The files the daemon keeps its secrets in.
+//!
+//! The keystore is a directory holding the `seed`, the device `creds`
+//! returned on registration, and the `revoked_runes`, one hex-encoded
+//! authcode per line, so revocations survive restarts.
+use anyhow::{anyhow, Context, Result};
+use gl_client::credentials::Device;
+use gl_client::secret::SecretBytes;
+use gl_client::signer::{SeedError, SeedProvider};
+use log::warn;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+pub struct Keystore {
+    dir: PathBuf,
+}
+
+impl Keystore {
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Keystore> {
+        let dir = dir.into();
+        let meta = std::fs::metadata(&dir)
+            .with_context(|| format!("opening keystore {}", dir.display()))?;
+        if !meta.is_dir() {
+            return Err(anyhow!("keystore {} is not a directory", dir.display()));
+        }
+        if meta.permissions().mode() & 0o077 != 0 {
+            warn!(
+                "Keystore {} is accessible by other users, restrict it to mode 0700",
+                dir.display()
+            );
+        }
+        Ok(Keystore { dir })
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    pub fn seed_path(&self) -> PathBuf {
+        self.path("seed")
+    }
+
+    pub fn creds_path(&self) -> PathBuf {
+        self.path("creds")
+    }
+
+    pub fn creds(&self) -> Result<Device> {
+        let path = self.creds_path();
+        let data = std::fs::read(&path)
+            .with_context(|| format!("reading credentials {}", path.display()))?;
+        Ok(Device::from_bytes(data))
+    }
+
+    /// The authcodes of the runes revoked through the daemon.
+    pub fn revoked_runes(&self) -> Result<Vec<String>> {
+        match std::fs::read_to_string(self.path("revoked_runes")) {
+            Ok(s) => Ok(s
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(str::to_string)
+                .collect()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn store_revoked_runes(&self, authcodes: &[String]) -> Result<()> {
+        let path = self.path("revoked_runes");
+        let tmp = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        for a in authcodes {
+            writeln!(file, "{}", a)?;
+        }
+        file.sync_all()?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+impl SeedProvider for Keystore {
+    fn seed(&self) -> Result<SecretBytes, SeedError> {
+        read_seed(&self.seed_path())
+    }
+}
+
+fn read_seed(path: &Path) -> Result<SecretBytes, SeedError> {
+    std::fs::read(path)
+        .map(SecretBytes::from)
+        .map_err(|e| SeedError::Unavailable(format!("{}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keystore() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = Keystore::open(dir.path()).unwrap();
+        assert!(keystore.seed().is_err());
+        assert!(keystore.creds().is_err());
+        assert!(keystore.revoked_runes().unwrap().is_empty());
+
+        std::fs::write(keystore.seed_path(), [1; 32]).unwrap();
+        assert_eq!(&keystore.seed().unwrap()[..], &[1; 32]);
+
+        keystore
+            .store_revoked_runes(&["aa".to_string(), "bb".to_string()])
+            .unwrap();
+        assert_eq!(keystore.revoked_runes().unwrap(), vec!["aa", "bb"]);
+    }
+}
@@ -0,0 +1,203 @@
+//! `gl-signerd`: an always-on signer for Greenlight nodes.
+//!
+//! Runs the signer for the node whose seed and credentials are in the
+//! keystore, see [`keystore`], applying the signer policy from the
+//! configuration, see [`gl_client::config`]. A running daemon is
+//! controlled through the socket described in [`control`], either
+//! directly or with `gl-signerd ctl`.
+//!
+//! When run by systemd with `Type=notify` the daemon reports when it
+//! is ready, and the connection status of the signer, see
+//! `contrib/gl-signerd.service`. With `--health-addr` it also serves
+//! health checks and metrics over HTTP, see [`health`].
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+use gl_client::config::Config;
+use gl_client::scheduler::Scheduler;
+use gl_client::signer::Signer;
+use log::{error, info};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+
+mod control;
+mod health;
+mod keystore;
+mod notify;
+
+use control::{Control, Request};
+use keystore::Keystore;
+
+/// How often to check that the scheduler is reachable.
+const PROBE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Parser, Debug)]
+#[command(name = "gl-signerd", version, about)]
+struct Cli {
+    /// The configuration file.
+    #[arg(long, env = "GL_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// The directory holding the seed and the credentials.
+    #[arg(
+        long,
+        env = "GL_SIGNERD_KEYSTORE",
+        default_value = "/var/lib/gl-signerd"
+    )]
+    keystore: PathBuf,
+
+    #[arg(
+        long,
+        env = "GL_SIGNERD_SOCKET",
+        default_value = "/run/gl-signerd/control.sock"
+    )]
+    control_socket: PathBuf,
+
+    /// Serve `/healthz` and `/metrics` on this address.
+    #[arg(long, env = "GL_SIGNERD_HEALTH_ADDR")]
+    health_addr: Option<SocketAddr>,
+
+    /// Run the daemon if no command is given.
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Talk to a running daemon.
+    #[command(subcommand)]
+    Ctl(CtlCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum CtlCommand {
+    Status,
+    /// Approve payments of an invoice the node has no matching call
+    /// for.
+    Approve {
+        bolt11: String,
+    },
+    /// Revoke a rune, and print its replacement.
+    RotateRune {
+        rune: String,
+    },
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let cli = Cli::parse();
+    let res = match cli.command {
+        None => run(&cli).await,
+        Some(Command::Ctl(ref c)) => ctl(&cli, c).await,
+    };
+    if let Err(e) = res {
+        eprintln!("error: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn ctl(cli: &Cli, command: &CtlCommand) -> Result<()> {
+    let request = match command {
+        CtlCommand::Status => Request::Status,
+        CtlCommand::Approve { bolt11 } => Request::Approve {
+            bolt11: bolt11.clone(),
+        },
+        CtlCommand::RotateRune { rune } => Request::RotateRune { rune: rune.clone() },
+    };
+    let response = control::call(&cli.control_socket, &request).await?;
+    if let Some(e) = response.get("error") {
+        return Err(anyhow!("{}", e.as_str().unwrap_or_default()));
+    }
+    println!("{}", serde_json::to_string_pretty(&response["result"])?);
+    Ok(())
+}
+
+async fn run(cli: &Cli) -> Result<()> {
+    let config = Config::load(cli.config.as_deref())?;
+    let keystore = Keystore::open(&cli.keystore)?;
+    let creds = keystore.creds()?;
+    let signer = Signer::with_seed_provider(&keystore, config.network, creds.clone())?;
+
+    let mut policy = config.signer_policy()?.unwrap_or_default();
+    for authcode in keystore.revoked_runes()? {
+        if !policy.rune_blacklist.contains(&authcode) {
+            policy.rune_blacklist.push(authcode);
+        }
+    }
+    signer.update_policy(policy)?;
+    info!("Starting signer for node {}", hex::encode(signer.node_id()));
+
+    let listener = Control::bind(&cli.control_socket)?;
+    let control = Arc::new(Control {
+        signer: signer.clone(),
+        keystore,
+    });
+    tokio::spawn(control.serve(listener));
+
+    if let Some(addr) = cli.health_addr {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        info!("Serving health checks on {}", addr);
+        tokio::spawn(health::serve(listener, signer.clone()));
+    }
+
+    let scheduler = Scheduler::with_config(&config, creds).await?;
+    let probe = signer.clone();
+    tokio::spawn(async move {
+        loop {
+            probe.health().probe_scheduler(&scheduler).await;
+            tokio::time::sleep(PROBE_INTERVAL).await;
+        }
+    });
+
+    let mut status = signer.status().subscribe();
+    tokio::spawn(async move {
+        loop {
+            let current = *status.borrow_and_update();
+            notify::status(current.as_str());
+            if status.changed().await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let (stop, stopped) = mpsc::channel(1);
+    let scheduler_uri = config.scheduler_uri();
+    let mut runner =
+        tokio::spawn(async move { signer.run_forever_with_uri(stopped, scheduler_uri).await });
+    notify::ready();
+
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let res = tokio::select! {
+        _ = sigterm.recv() => {
+            info!("Received SIGTERM, stopping");
+            None
+        }
+        _ = tokio::signal::ctrl_c() => {
+            info!("Received SIGINT, stopping");
+            None
+        }
+        res = &mut runner => {
+            // The signer gave up, exit so the service manager restarts
+            // us.
+            Some(res?.and_then(|()| Err(anyhow!("signer stopped unexpectedly"))))
+        }
+    };
+    notify::stopping();
+    let res = match res {
+        Some(res) => res,
+        None => {
+            let _ = stop.send(()).await;
+            runner.await?
+        }
+    };
+    let _ = std::fs::remove_file(&cli.control_socket);
+    if let Err(e) = res {
+        error!("Signer exited with an error: {}", e);
+        return Err(e);
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+//! Report readiness and status to systemd, see `sd_notify(3)`.
+//!
+//! Only does something when started by systemd with `Type=notify`,
+//! which sets `NOTIFY_SOCKET`.
+use log::{debug, warn};
+use std::os::unix::net::UnixDatagram;
+
+pub fn ready() {
+    notify("READY=1")
+}
+
+pub fn stopping() {
+    notify("STOPPING=1")
+}
+
+pub fn status(status: &str) {
+    notify(&format!("STATUS={}", status))
+}
+
+fn notify(state: &str) {
+    let path = match std::env::var_os("NOTIFY_SOCKET") {
+        Some(p) => p,
+        None => return,
+    };
+    debug!("Notifying systemd: {}", state);
+    if let Err(e) = send(&path.to_string_lossy(), state) {
+        warn!("Could not notify systemd: {}", e);
+    }
+}
+
+fn send(path: &str, state: &str) -> std::io::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    match path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        _ => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        send(path.to_str().unwrap(), "READY=1").unwrap();
+        let mut buf = [0; 16];
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+    }
+}